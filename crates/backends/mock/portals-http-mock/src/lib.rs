@@ -1,14 +1,42 @@
 //! Mock implementation of portals-http for testing.
 //!
 //! Provides a mock HTTP client that returns canned responses and records requests.
+//! It can also simulate a slow or unreliable network, on a mock clock so tests
+//! stay fast and deterministic. Bodies can be streamed with
+//! [`StreamingHttpClient`], in reads of a chosen size.
 
-use portals_http::{Error, HttpClient, Method, Request, Response};
+mod matcher;
+mod route;
+
+pub use route::UrlPattern;
+
+use matcher::{BodyMatcher, RequestMatcher};
+
+use portals_clocks_mock::MockMonotonicClock;
+use portals_http::{
+    Body, Error, Headers, HttpClient, Method, Request, Response, StreamingHttpClient,
+    StreamingRequest, StreamingResponse,
+};
+use portals_io::{InputStream, StreamError};
+use portals_random::InsecureRandom;
+use portals_random_mock::MockInsecureRandom;
 use std::collections::VecDeque;
+use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// A mock HTTP client for testing.
 ///
-/// Queues responses to return and records all requests made.
+/// Queues responses to return and records all requests made. Responses can
+/// also be routed by method and URL with [`when`](Self::when), so tests
+/// that make interleaved requests don't depend on their order. Routes can
+/// expect to be hit a number of times, checked by [`verify`](Self::verify).
+///
+/// Responses can be delayed, with [`set_latency`](Self::set_latency) or per
+/// response, and requests made to time out or fail at random. Delays don't
+/// sleep: they advance the client's [`MockMonotonicClock`] (see
+/// [`with_clock`](Self::with_clock)), so code that times requests against
+/// the same clock sees them take that long.
 #[derive(Debug, Clone, Default)]
 pub struct MockHttpClient {
     inner: Arc<Mutex<MockState>>,
@@ -16,15 +44,42 @@ pub struct MockHttpClient {
 
 #[derive(Debug, Default)]
 struct MockState {
+    routes: Vec<Route>,
     responses: VecDeque<MockResponse>,
     requests: Vec<Request>,
     default_response: Option<Response>,
+    clock: MockMonotonicClock,
+    latency: Duration,
+    timeout: Option<Duration>,
+    failures: Option<Failures>,
+    /// Largest read from a streamed response body.
+    chunk_size: Option<usize>,
 }
 
+/// A queued response.
 #[derive(Debug)]
-enum MockResponse {
-    Success(Response),
-    Error(ErrorKind),
+struct MockResponse {
+    reply: Reply,
+    /// Overrides the client's latency.
+    delay: Option<Duration>,
+}
+
+/// Failures injected by [`MockHttpClient::fail_randomly`].
+#[derive(Debug)]
+struct Failures {
+    /// Chance of each request failing, from 0 to 1.
+    rate: f64,
+    kind: ErrorKind,
+    rng: MockInsecureRandom,
+}
+
+impl Failures {
+    /// Decide whether the next request fails.
+    fn strike(&mut self) -> bool {
+        // The top 53 bits, as a fraction in [0, 1).
+        let draw = (self.rng.u64() >> 11) as f64 / (1u64 << 53) as f64;
+        draw < self.rate
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -35,29 +90,291 @@ enum ErrorKind {
     ProtocolError,
 }
 
+impl ErrorKind {
+    fn parse(error: &str) -> Self {
+        match error {
+            "invalid_url" => Self::InvalidUrl,
+            "connection_failed" => Self::ConnectionFailed,
+            "timeout" => Self::Timeout,
+            "protocol_error" => Self::ProtocolError,
+            _ => Self::ConnectionFailed,
+        }
+    }
+
+    fn to_error(self) -> Error {
+        match self {
+            Self::InvalidUrl => Error::InvalidUrl,
+            Self::ConnectionFailed => Error::ConnectionFailed,
+            Self::Timeout => Error::Timeout,
+            Self::ProtocolError => Error::ProtocolError,
+        }
+    }
+}
+
+/// A response registered with [`MockHttpClient::when`].
+#[derive(Debug)]
+struct Route {
+    matcher: RequestMatcher,
+    reply: Reply,
+    /// How many requests the route should answer, if set with `times`.
+    expected: Option<usize>,
+    hits: usize,
+    /// Overrides the client's latency.
+    delay: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Reply {
+    Response(Response),
+    Error(ErrorKind),
+    With(Arc<dyn Fn(&Request) -> Response + Send + Sync>),
+}
+
+impl fmt::Debug for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Response(response) => f.debug_tuple("Response").field(response).finish(),
+            Self::Error(kind) => f.debug_tuple("Error").field(kind).finish(),
+            Self::With(_) => f.write_str("With(..)"),
+        }
+    }
+}
+
+/// A route being set up by [`MockHttpClient::when`].
+///
+/// Narrow which requests it matches with [`header`](Self::header) and the
+/// body matchers, and set an expected call count with
+/// [`times`](Self::times). The route takes effect once given a reply.
+#[must_use = "a route does nothing until it is given a reply"]
+pub struct When<'a> {
+    client: &'a MockHttpClient,
+    matcher: RequestMatcher,
+    expected: Option<usize>,
+    delay: Option<Duration>,
+}
+
+impl When<'_> {
+    /// Only match requests with this header value. The name is compared
+    /// case-insensitively.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.matcher.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Only match requests with exactly this body. A request without a body
+    /// matches an empty one.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.matcher.body = Some(BodyMatcher::Exact(body.into()));
+        self
+    }
+
+    /// Only match requests whose body is the same JSON value as `json`,
+    /// regardless of whitespace and key order.
+    ///
+    /// # Panics
+    ///
+    /// If `json` is not valid JSON.
+    pub fn body_json(mut self, json: &str) -> Self {
+        self.matcher.body = Some(BodyMatcher::json(json));
+        self
+    }
+
+    /// Expect the route to answer exactly `n` requests; see
+    /// [`MockHttpClient::verify`].
+    pub fn times(mut self, n: usize) -> Self {
+        self.expected = Some(n);
+        self
+    }
+
+    /// Take `delay` to answer, instead of the client's latency.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Answer matching requests with `response`, however many arrive.
+    pub fn respond(self, response: Response) {
+        self.reply(Reply::Response(response));
+    }
+
+    /// Fail matching requests; `error` is as for
+    /// [`MockHttpClient::queue_error`].
+    pub fn respond_error(self, error: &str) {
+        self.reply(Reply::Error(ErrorKind::parse(error)));
+    }
+
+    /// Answer matching requests with whatever `f` builds from them.
+    pub fn respond_with(self, f: impl Fn(&Request) -> Response + Send + Sync + 'static) {
+        self.reply(Reply::With(Arc::new(f)));
+    }
+
+    fn reply(self, reply: Reply) {
+        let mut state = self.client.inner.lock().unwrap();
+        state.routes.push(Route {
+            matcher: self.matcher,
+            reply,
+            expected: self.expected,
+            hits: 0,
+            delay: self.delay,
+        });
+    }
+}
+
 impl MockHttpClient {
     /// Create a new mock HTTP client.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Advance `clock` by response delays, instead of a clock of the
+    /// client's own.
+    pub fn with_clock(self, clock: MockMonotonicClock) -> Self {
+        self.inner.lock().unwrap().clock = clock;
+        self
+    }
+
+    /// The clock advanced by response delays.
+    pub fn clock(&self) -> MockMonotonicClock {
+        self.inner.lock().unwrap().clock.clone()
+    }
+
+    /// Take `latency` to answer each request, unless a route or queued
+    /// response sets its own delay. Zero by default.
+    pub fn set_latency(&self, latency: Duration) {
+        let mut state = self.inner.lock().unwrap();
+        state.latency = latency;
+    }
+
+    /// Fail requests with [`Error::Timeout`] when their response would take
+    /// `timeout` or longer, advancing the clock by `timeout` only. The
+    /// response is used up either way.
+    pub fn set_timeout(&self, timeout: Duration) {
+        let mut state = self.inner.lock().unwrap();
+        state.timeout = Some(timeout);
+    }
+
+    /// Fail each request with chance `rate` (from 0 to 1), with `error` as
+    /// for [`queue_error`](Self::queue_error).
+    ///
+    /// Failures are drawn from a generator seeded with `seed`, so the same
+    /// seed fails the same requests in every run. A failed request is
+    /// still recorded, uses up its response, and counts toward its route's
+    /// [`times`](When::times).
+    pub fn fail_randomly(&self, rate: f64, error: &str, seed: u64) {
+        let mut state = self.inner.lock().unwrap();
+        state.failures = Some(Failures {
+            rate,
+            kind: ErrorKind::parse(error),
+            rng: MockInsecureRandom::new(seed),
+        });
+    }
+
+    /// Stop injecting failures and timeouts, and remove the latency.
+    pub fn reset_network(&self) {
+        let mut state = self.inner.lock().unwrap();
+        state.latency = Duration::ZERO;
+        state.timeout = None;
+        state.failures = None;
+    }
+
+    /// Stream response bodies from
+    /// [`send_streaming`](StreamingHttpClient::send_streaming) in reads of
+    /// at most `size` bytes, so tests can see a download arrive in pieces.
+    /// Unlimited by default.
+    pub fn set_chunk_size(&self, size: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.chunk_size = Some(size.max(1));
+    }
+
+    /// Route requests with `method` and a URL matching `pattern`.
+    ///
+    /// Routes are tried in the order they were added, and the first match
+    /// answers; only requests no route matches fall through to the queue
+    /// and then the default response. A `&str` pattern is a glob (see
+    /// [`UrlPattern`]):
+    ///
+    /// ```
+    /// # use portals_http::Method;
+    /// # use portals_http_mock::{MockHttpClient, ResponseBuilder, UrlPattern};
+    /// let client = MockHttpClient::new();
+    /// client
+    ///     .when(Method::Get, "/users/*")
+    ///     .respond(ResponseBuilder::ok().json(r#"{"id":1}"#).build());
+    /// client
+    ///     .when(Method::Delete, UrlPattern::regex(r"/users/\d+$").unwrap())
+    ///     .respond(ResponseBuilder::new(204).build());
+    /// ```
+    pub fn when(&self, method: Method, pattern: impl Into<UrlPattern>) -> When<'_> {
+        When {
+            client: self,
+            matcher: RequestMatcher::new(method, pattern.into()),
+            expected: None,
+            delay: None,
+        }
+    }
+
+    /// Describe each route whose expected call count (see [`When::times`])
+    /// does not match how many requests it answered.
+    pub fn unmet_expectations(&self) -> Vec<String> {
+        let state = self.inner.lock().unwrap();
+        state
+            .routes
+            .iter()
+            .filter_map(|route| {
+                let expected = route.expected?;
+                (route.hits != expected).then(|| {
+                    format!(
+                        "{}: expected {} {}, got {}",
+                        route.matcher,
+                        expected,
+                        if expected == 1 { "call" } else { "calls" },
+                        route.hits
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Assert that every route with an expected call count answered exactly
+    /// that many requests.
+    ///
+    /// # Panics
+    ///
+    /// Listing every unmet expectation, if there are any.
+    pub fn verify(&self) {
+        let unmet = self.unmet_expectations();
+        assert!(
+            unmet.is_empty(),
+            "unmet expectations:\n  {}",
+            unmet.join("\n  ")
+        );
+    }
+
+    /// Remove all routes.
+    pub fn clear_routes(&self) {
+        let mut state = self.inner.lock().unwrap();
+        state.routes.clear();
+    }
+
     /// Queue a response to be returned for the next request.
     pub fn queue_response(&self, response: Response) {
-        let mut state = self.inner.lock().unwrap();
-        state.responses.push_back(MockResponse::Success(response));
+        self.queue(Reply::Response(response), None);
+    }
+
+    /// Queue a response that takes `delay` to arrive, instead of the
+    /// client's latency.
+    pub fn queue_delayed_response(&self, response: Response, delay: Duration) {
+        self.queue(Reply::Response(response), Some(delay));
     }
 
     /// Queue an error to be returned for the next request.
     pub fn queue_error(&self, error: &str) {
-        let kind = match error {
-            "invalid_url" => ErrorKind::InvalidUrl,
-            "connection_failed" => ErrorKind::ConnectionFailed,
-            "timeout" => ErrorKind::Timeout,
-            "protocol_error" => ErrorKind::ProtocolError,
-            _ => ErrorKind::ConnectionFailed,
-        };
+        self.queue(Reply::Error(ErrorKind::parse(error)), None);
+    }
+
+    fn queue(&self, reply: Reply, delay: Option<Duration>) {
         let mut state = self.inner.lock().unwrap();
-        state.responses.push_back(MockResponse::Error(kind));
+        state.responses.push_back(MockResponse { reply, delay });
     }
 
     /// Set a default response to return when the queue is empty.
@@ -113,41 +430,159 @@ impl MockHttpClient {
             url
         );
     }
+
+    /// Assert that a request was made with the given method and URL and a
+    /// body that is the same JSON value as `json`, regardless of whitespace
+    /// and key order.
+    pub fn assert_requested_with_body_json(&self, method: Method, url: &str, json: &str) {
+        let body = BodyMatcher::json(json);
+        let state = self.inner.lock().unwrap();
+        assert!(
+            state.requests.iter().any(|r| r.method == method
+                && r.url == url
+                && body.matches(r.body.as_deref().unwrap_or_default())),
+            "expected {:?} request to {} with JSON body {} but none was made",
+            method,
+            url,
+            json
+        );
+    }
+
+    /// Assert that a request was made with the given method and URL and a
+    /// header with the given value. The name is compared case-insensitively.
+    pub fn assert_requested_with_header(&self, method: Method, url: &str, name: &str, value: &str) {
+        let state = self.inner.lock().unwrap();
+        assert!(
+            state.requests.iter().any(|r| r.method == method
+                && r.url == url
+                && r.headers.get_all(name).any(|v| v == value)),
+            "expected {:?} request to {} with {}: {} but none was made",
+            method,
+            url,
+            name,
+            value
+        );
+    }
+}
+
+impl MockState {
+    /// Find the reply to `request` and how long it takes to arrive.
+    fn reply_for(&mut self, request: &Request) -> (Reply, Option<Duration>) {
+        if let Some(route) = self
+            .routes
+            .iter_mut()
+            .find(|route| route.matcher.matches(request))
+        {
+            route.hits += 1;
+            return (route.reply.clone(), route.delay);
+        }
+        match self.responses.pop_front() {
+            Some(queued) => (queued.reply, queued.delay),
+            // Return a 200 OK with empty body as fallback
+            None => (
+                Reply::Response(self.default_response.clone().unwrap_or(Response {
+                    status: 200,
+                    headers: Default::default(),
+                    body: Vec::new(),
+                    redirects: Vec::new(),
+                })),
+                None,
+            ),
+        }
+    }
+
+    /// Wait out a response's delay on the clock, failing if it times out
+    /// or a failure is injected.
+    fn simulate_network(&mut self, delay: Option<Duration>) -> Result<(), Error> {
+        let delay = delay.unwrap_or(self.latency);
+        if let Some(timeout) = self.timeout
+            && delay >= timeout
+        {
+            self.clock.advance(timeout);
+            return Err(Error::Timeout);
+        }
+        self.clock.advance(delay);
+        if let Some(failures) = &mut self.failures
+            && failures.strike()
+        {
+            return Err(failures.kind.to_error());
+        }
+        Ok(())
+    }
 }
 
 impl HttpClient for MockHttpClient {
     async fn send(&self, request: Request) -> Result<Response, Error> {
         let mut state = self.inner.lock().unwrap();
-        state.requests.push(request);
-
-        match state.responses.pop_front() {
-            Some(MockResponse::Success(response)) => Ok(response),
-            Some(MockResponse::Error(kind)) => Err(match kind {
-                ErrorKind::InvalidUrl => Error::InvalidUrl,
-                ErrorKind::ConnectionFailed => Error::ConnectionFailed,
-                ErrorKind::Timeout => Error::Timeout,
-                ErrorKind::ProtocolError => Error::ProtocolError,
+        let (reply, delay) = state.reply_for(&request);
+        state.requests.push(request.clone());
+        state.simulate_network(delay)?;
+        // Release the lock so a `respond_with` closure may use the client.
+        drop(state);
+        match reply {
+            Reply::Response(response) => Ok(response),
+            Reply::Error(kind) => Err(kind.to_error()),
+            Reply::With(f) => Ok(f(&request)),
+        }
+    }
+}
+
+impl StreamingHttpClient for MockHttpClient {
+    type Body = MockBodyStream;
+
+    /// Read the request body into memory, then answer as for
+    /// [`send`](HttpClient::send) with the response body as a stream.
+    async fn send_streaming<S: InputStream>(
+        &self,
+        request: StreamingRequest<S>,
+    ) -> Result<StreamingResponse<MockBodyStream>, Error> {
+        let response = self.send(request.buffer().await?).await?;
+        let chunk_size = self.inner.lock().unwrap().chunk_size;
+        Ok(StreamingResponse {
+            status: response.status,
+            headers: response.headers,
+            body: Body::Stream(MockBodyStream {
+                data: response.body,
+                pos: 0,
+                chunk_size: chunk_size.unwrap_or(usize::MAX),
             }),
-            None => {
-                if let Some(ref default) = state.default_response {
-                    Ok(default.clone())
-                } else {
-                    // Return a 200 OK with empty body as fallback
-                    Ok(Response {
-                        status: 200,
-                        headers: Default::default(),
-                        body: Vec::new(),
-                    })
-                }
-            }
+            redirects: response.redirects,
+        })
+    }
+}
+
+/// A response body streamed from memory by
+/// [`MockHttpClient::send_streaming`](StreamingHttpClient::send_streaming).
+#[derive(Debug, Clone)]
+pub struct MockBodyStream {
+    data: Vec<u8>,
+    pos: usize,
+    chunk_size: usize,
+}
+
+impl InputStream for MockBodyStream {
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        let rest = &self.data[self.pos..];
+        if rest.is_empty() {
+            return Err(StreamError::Closed);
         }
+        let n = rest.len().min(buf.len()).min(self.chunk_size);
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+
+    fn blocking_read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        self.read_into(buf)
     }
+
+    async fn subscribe(&self) {}
 }
 
 /// Builder for creating Response objects easily.
 pub struct ResponseBuilder {
     status: u16,
-    headers: std::collections::HashMap<String, String>,
+    headers: Headers,
     body: Vec<u8>,
 }
 
@@ -191,16 +626,14 @@ impl ResponseBuilder {
     /// Set the body from a string.
     pub fn text(mut self, text: impl Into<String>) -> Self {
         self.body = text.into().into_bytes();
-        self.headers
-            .insert("content-type".to_string(), "text/plain".to_string());
+        self.headers.insert("content-type", "text/plain");
         self
     }
 
     /// Set the body from JSON (as a string).
     pub fn json(mut self, json: impl Into<String>) -> Self {
         self.body = json.into().into_bytes();
-        self.headers
-            .insert("content-type".to_string(), "application/json".to_string());
+        self.headers.insert("content-type", "application/json");
         self
     }
 
@@ -210,6 +643,7 @@ impl ResponseBuilder {
             status: self.status,
             headers: self.headers,
             body: self.body,
+            redirects: Vec::new(),
         }
     }
 }
@@ -217,13 +651,12 @@ impl ResponseBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
 
     fn make_request(method: Method, url: &str) -> Request {
         Request {
             method,
             url: url.to_string(),
-            headers: HashMap::new(),
+            headers: Headers::new(),
             body: None,
         }
     }
@@ -285,6 +718,198 @@ mod tests {
         assert_eq!(response.status, 404);
     }
 
+    #[tokio::test]
+    async fn routes_by_method_and_url() {
+        let client = MockHttpClient::new();
+        client
+            .when(Method::Get, "/users/*")
+            .respond(ResponseBuilder::ok().text("user").build());
+        client
+            .when(Method::Post, UrlPattern::exact("/users"))
+            .respond(ResponseBuilder::new(201).build());
+        client
+            .when(Method::Get, UrlPattern::regex(r"/slow$").unwrap())
+            .respond_error("timeout");
+        client
+            .when(Method::Get, "/echo/**")
+            .respond_with(|req| ResponseBuilder::ok().text(req.url.clone()).build());
+        client.queue_response(ResponseBuilder::not_found().build());
+
+        // Routed responses repeat and ignore the queue, whatever the order.
+        let post = make_request(Method::Post, "https://example.com/users");
+        assert_eq!(client.send(post).await.unwrap().status, 201);
+        for id in ["1", "2"] {
+            let get = make_request(Method::Get, &format!("https://example.com/users/{id}"));
+            assert_eq!(client.send(get).await.unwrap().body, b"user");
+        }
+        let slow = make_request(Method::Get, "https://example.com/slow");
+        assert!(matches!(client.send(slow).await, Err(Error::Timeout)));
+        let echo = make_request(Method::Get, "https://example.com/echo/a/b");
+        assert_eq!(
+            client.send(echo).await.unwrap().body,
+            b"https://example.com/echo/a/b"
+        );
+
+        // Unrouted requests fall through to the queue, then the default.
+        let other = make_request(Method::Delete, "https://example.com/users/1");
+        assert_eq!(client.send(other.clone()).await.unwrap().status, 404);
+        assert_eq!(client.send(other).await.unwrap().status, 200);
+        assert_eq!(client.request_count(), 7);
+
+        client.clear_routes();
+        let get = make_request(Method::Get, "https://example.com/users/1");
+        assert_eq!(client.send(get).await.unwrap().body, b"");
+    }
+
+    #[tokio::test]
+    async fn matches_headers_and_bodies() {
+        let client = MockHttpClient::new();
+        client
+            .when(Method::Post, "/users")
+            .header("Authorization", "Bearer token")
+            .body_json(r#"{"name": "alice", "admin": false}"#)
+            .times(1)
+            .respond(ResponseBuilder::new(201).build());
+        client
+            .when(Method::Post, "/users")
+            .body("raw")
+            .times(2)
+            .respond(ResponseBuilder::new(202).build());
+        client
+            .when(Method::Get, "/health")
+            .times(0)
+            .respond(ResponseBuilder::ok().build());
+
+        let mut request = make_request(Method::Post, "https://example.com/users");
+        request.headers.insert("authorization", "Bearer token");
+        request.body = Some(br#"{"admin":false,"name":"alice"}"#.to_vec());
+        assert_eq!(client.send(request.clone()).await.unwrap().status, 201);
+
+        request.body = Some(b"raw".to_vec());
+        assert_eq!(client.send(request.clone()).await.unwrap().status, 202);
+        request.headers = Headers::new();
+        request.body = Some(br#"{"admin":false,"name":"alice"}"#.to_vec());
+        assert_eq!(client.send(request).await.unwrap().status, 200);
+
+        client.assert_requested_with_body_json(
+            Method::Post,
+            "https://example.com/users",
+            r#"{ "name": "alice", "admin": false }"#,
+        );
+        client.assert_requested_with_header(
+            Method::Post,
+            "https://example.com/users",
+            "AUTHORIZATION",
+            "Bearer token",
+        );
+
+        assert_eq!(
+            client.unmet_expectations(),
+            ["Post /users with body \"raw\": expected 2 calls, got 1"]
+        );
+        let mut request = make_request(Method::Post, "https://example.com/users");
+        request.body = Some(b"raw".to_vec());
+        client.send(request).await.unwrap();
+        client.verify();
+    }
+
+    #[test]
+    #[should_panic(expected = "Get /health: expected 1 call, got 0")]
+    fn verify_reports_unmet_expectations() {
+        let client = MockHttpClient::new();
+        client
+            .when(Method::Get, "/health")
+            .times(1)
+            .respond(ResponseBuilder::ok().build());
+        client.verify();
+    }
+
+    #[tokio::test]
+    async fn simulates_latency_and_timeouts() {
+        use portals_clocks::MonotonicClock;
+
+        let clock = MockMonotonicClock::new();
+        let client = MockHttpClient::new().with_clock(clock.clone());
+        client.set_latency(Duration::from_millis(50));
+        client
+            .when(Method::Get, "/slow")
+            .delay(Duration::from_secs(10))
+            .respond(ResponseBuilder::ok().build());
+        client.queue_delayed_response(ResponseBuilder::ok().build(), Duration::from_millis(5));
+
+        let request = make_request(Method::Get, "https://example.com/fast");
+        client.send(request.clone()).await.unwrap();
+        assert_eq!(clock.now(), 5_000_000);
+        client.send(request.clone()).await.unwrap();
+        assert_eq!(clock.now(), 55_000_000);
+
+        let slow = make_request(Method::Get, "https://example.com/slow");
+        client.send(slow.clone()).await.unwrap();
+        assert_eq!(client.clock().now(), 10_055_000_000);
+
+        client.set_timeout(Duration::from_secs(1));
+        assert!(matches!(client.send(slow).await, Err(Error::Timeout)));
+        assert_eq!(clock.now(), 11_055_000_000);
+        client.send(request).await.unwrap();
+        assert_eq!(client.request_count(), 5);
+    }
+
+    #[tokio::test]
+    async fn fails_randomly_but_reproducibly() {
+        async fn outcomes(seed: u64) -> Vec<bool> {
+            let client = MockHttpClient::new();
+            client.fail_randomly(0.25, "connection_failed", seed);
+            let mut outcomes = Vec::new();
+            for _ in 0..1000 {
+                let request = make_request(Method::Get, "https://example.com");
+                match client.send(request).await {
+                    Ok(_) => outcomes.push(true),
+                    Err(Error::ConnectionFailed) => outcomes.push(false),
+                    Err(e) => panic!("unexpected error {:?}", e),
+                }
+            }
+            outcomes
+        }
+
+        let first = outcomes(7).await;
+        assert_eq!(first, outcomes(7).await);
+        assert_ne!(first, outcomes(8).await);
+        let failed = first.iter().filter(|ok| !**ok).count();
+        assert!((200..300).contains(&failed), "{} failures", failed);
+
+        let client = MockHttpClient::new();
+        client.fail_randomly(1.0, "timeout", 1);
+        let request = make_request(Method::Get, "https://example.com");
+        assert!(client.send(request.clone()).await.is_err());
+        client.reset_network();
+        assert!(client.send(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn streams_bodies() {
+        let client = MockHttpClient::new();
+        client.set_chunk_size(4);
+        client.queue_response(ResponseBuilder::ok().text("hello, world").build());
+
+        let mut request = StreamingRequest::from(make_request(Method::Put, "https://example.com"));
+        request.body = Some(Body::Stream(MockBodyStream {
+            data: b"upload".to_vec(),
+            pos: 0,
+            chunk_size: 2,
+        }));
+        let response = client.send_streaming(request).await.unwrap();
+        assert_eq!(client.requests()[0].body.as_deref(), Some(&b"upload"[..]));
+
+        let Body::Stream(mut stream) = response.body else {
+            panic!("expected a streamed body");
+        };
+        let mut chunks = Vec::new();
+        while let Ok(chunk) = stream.read(64) {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks, [&b"hell"[..], b"o, w", b"orld"]);
+    }
+
     #[tokio::test]
     async fn response_builder_works() {
         let response = ResponseBuilder::ok()
@@ -293,10 +918,10 @@ mod tests {
             .build();
 
         assert_eq!(response.status, 200);
-        assert_eq!(response.headers.get("x-custom"), Some(&"value".to_string()));
+        assert_eq!(response.headers.get("x-custom"), Some("value"));
         assert_eq!(
             response.headers.get("content-type"),
-            Some(&"application/json".to_string())
+            Some("application/json")
         );
     }
 }
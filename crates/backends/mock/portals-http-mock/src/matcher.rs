@@ -0,0 +1,85 @@
+//! Request matchers for routes and expectations.
+
+use crate::UrlPattern;
+use portals_http::{Method, Request};
+use std::fmt;
+
+/// What a route requires of a request.
+#[derive(Debug)]
+pub(crate) struct RequestMatcher {
+    pub(crate) method: Method,
+    pub(crate) pattern: UrlPattern,
+    /// Header names are compared case-insensitively, values exactly.
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Option<BodyMatcher>,
+}
+
+#[derive(Debug)]
+pub(crate) enum BodyMatcher {
+    Exact(Vec<u8>),
+    /// Equal as JSON, ignoring formatting and key order.
+    Json(serde_json::Value),
+}
+
+impl RequestMatcher {
+    pub(crate) fn new(method: Method, pattern: UrlPattern) -> Self {
+        Self {
+            method,
+            pattern,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub(crate) fn matches(&self, request: &Request) -> bool {
+        request.method == self.method
+            && self.pattern.matches(&request.url)
+            && self
+                .headers
+                .iter()
+                .all(|(name, value)| request.headers.get_all(name).any(|v| v == value))
+            && self
+                .body
+                .as_ref()
+                .is_none_or(|body| body.matches(request.body.as_deref().unwrap_or_default()))
+    }
+}
+
+impl BodyMatcher {
+    pub(crate) fn json(json: &str) -> Self {
+        match serde_json::from_str(json) {
+            Ok(value) => Self::Json(value),
+            Err(e) => panic!("invalid JSON in body matcher: {}", e),
+        }
+    }
+
+    pub(crate) fn matches(&self, body: &[u8]) -> bool {
+        match self {
+            Self::Exact(expected) => body == expected.as_slice(),
+            Self::Json(expected) => {
+                serde_json::from_slice::<serde_json::Value>(body).is_ok_and(|v| v == *expected)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RequestMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} ", self.method)?;
+        match &self.pattern {
+            UrlPattern::Exact(url) | UrlPattern::Glob(url) => write!(f, "{}", url)?,
+            UrlPattern::Regex(regex) => write!(f, "/{}/", regex)?,
+        }
+        for (name, value) in &self.headers {
+            write!(f, " with {}: {}", name, value)?;
+        }
+        match &self.body {
+            Some(BodyMatcher::Exact(body)) => {
+                write!(f, " with body {:?}", String::from_utf8_lossy(body))?
+            }
+            Some(BodyMatcher::Json(json)) => write!(f, " with JSON body {}", json)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
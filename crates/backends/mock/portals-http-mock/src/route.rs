@@ -0,0 +1,130 @@
+//! URL patterns for routed mock responses.
+
+use regex::Regex;
+
+/// Which URLs a route answers.
+///
+/// Exact and glob patterns that start with `/` are matched against the
+/// URL's path alone, without scheme, host, query, or fragment; other
+/// patterns are matched against the whole URL. Regexes always see the whole
+/// URL and match anywhere in it unless they anchor themselves.
+///
+/// A `&str` converts to a glob, so a string without `*` matches exactly.
+#[derive(Debug, Clone)]
+pub enum UrlPattern {
+    /// Matches this URL or path and nothing else.
+    Exact(String),
+    /// `*` matches any run of characters other than `/`; `**` matches any
+    /// run at all.
+    Glob(String),
+    /// Matches if the regex matches the URL.
+    Regex(Regex),
+}
+
+impl UrlPattern {
+    /// Match a URL or path exactly.
+    pub fn exact(pattern: impl Into<String>) -> Self {
+        Self::Exact(pattern.into())
+    }
+
+    /// Match a URL or path with `*` and `**` wildcards.
+    pub fn glob(pattern: impl Into<String>) -> Self {
+        Self::Glob(pattern.into())
+    }
+
+    /// Match the URL against a regular expression.
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self::Regex)
+    }
+
+    /// Whether `url` matches this pattern.
+    pub fn matches(&self, url: &str) -> bool {
+        match self {
+            Self::Exact(pattern) => subject(pattern, url) == pattern,
+            Self::Glob(pattern) => glob_match(pattern.as_bytes(), subject(pattern, url).as_bytes()),
+            Self::Regex(regex) => regex.is_match(url),
+        }
+    }
+}
+
+impl From<&str> for UrlPattern {
+    fn from(pattern: &str) -> Self {
+        Self::glob(pattern)
+    }
+}
+
+impl From<String> for UrlPattern {
+    fn from(pattern: String) -> Self {
+        Self::glob(pattern)
+    }
+}
+
+impl From<Regex> for UrlPattern {
+    fn from(regex: Regex) -> Self {
+        Self::Regex(regex)
+    }
+}
+
+/// The part of `url` that `pattern` is matched against.
+fn subject<'a>(pattern: &str, url: &'a str) -> &'a str {
+    if pattern.starts_with('/') {
+        path(url)
+    } else {
+        url
+    }
+}
+
+/// The path of `url`, without query or fragment; `/` if it has none.
+fn path(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    match rest.find(['/', '?', '#']) {
+        Some(start) if rest[start..].starts_with('/') => {
+            let path = &rest[start..];
+            &path[..path.find(['?', '#']).unwrap_or(path.len())]
+        }
+        _ => "/",
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => {
+            let segment = text.iter().position(|&b| b == b'/').unwrap_or(text.len());
+            (0..=segment).any(|i| glob_match(rest, &text[i..]))
+        }
+        [c, rest @ ..] => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_path() {
+        assert_eq!(path("https://example.com/users/1?x=/y#z"), "/users/1");
+        assert_eq!(path("https://example.com"), "/");
+        assert_eq!(path("https://example.com?next=/a"), "/");
+        assert_eq!(path("/users"), "/users");
+    }
+
+    #[test]
+    fn matches_patterns() {
+        let url = "https://example.com/users/42?full=1";
+
+        assert!(UrlPattern::exact("/users/42").matches(url));
+        assert!(!UrlPattern::exact("/users").matches(url));
+        assert!(UrlPattern::exact(url).matches(url));
+
+        assert!(UrlPattern::from("/users/*").matches(url));
+        assert!(!UrlPattern::from("/users/*").matches("https://example.com/users/42/posts"));
+        assert!(UrlPattern::from("/users/**").matches("https://example.com/users/42/posts"));
+        assert!(UrlPattern::from("https://*.com/**").matches(url));
+        assert!(!UrlPattern::from("/posts/*").matches(url));
+
+        assert!(UrlPattern::regex(r"/users/\d+").unwrap().matches(url));
+        assert!(!UrlPattern::regex(r"/users/\d+$").unwrap().matches(url));
+    }
+}
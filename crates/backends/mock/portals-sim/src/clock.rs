@@ -0,0 +1,163 @@
+//! Virtual time.
+
+use portals_clocks::MonotonicClock;
+use portals_clocks_mock::MockMonotonicClock;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// A monotonic clock whose timers fire when virtual time reaches them.
+///
+/// Unlike [`MockMonotonicClock`], whose timers complete immediately, a
+/// [`Sleep`] stays pending until the clock is advanced past its deadline,
+/// either by hand with [`advance`](Self::advance) or by
+/// [`Sim`](crate::Sim) once every task is blocked.
+#[derive(Debug, Clone, Default)]
+pub struct SimClock {
+    time: MockMonotonicClock,
+    timers: Rc<RefCell<Timers>>,
+}
+
+#[derive(Debug, Default)]
+struct Timers {
+    next_id: u64,
+    /// Wakers keyed by deadline, then registration order.
+    pending: BTreeMap<(u64, u64), Waker>,
+}
+
+impl SimClock {
+    /// Create a clock at time zero with no timers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A future that completes after `duration` of virtual time.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        self.sleep_until(self.now().saturating_add(duration.as_nanos() as u64))
+    }
+
+    /// A future that completes once virtual time reaches `instant`.
+    pub fn sleep_until(&self, instant: u64) -> Sleep {
+        Sleep {
+            clock: self.clone(),
+            deadline: instant,
+            key: None,
+        }
+    }
+
+    /// Advance time by `duration`, waking every timer that comes due.
+    pub fn advance(&self, duration: Duration) {
+        self.advance_to(self.now().saturating_add(duration.as_nanos() as u64));
+    }
+
+    /// The deadline of the earliest pending timer.
+    pub fn next_timer(&self) -> Option<u64> {
+        self.timers
+            .borrow()
+            .pending
+            .keys()
+            .next()
+            .map(|(deadline, _)| *deadline)
+    }
+
+    /// Move time forward to `instant` (never backwards) and wake every timer
+    /// due by then.
+    pub(crate) fn advance_to(&self, instant: u64) {
+        if instant > self.now() {
+            self.time.set(instant);
+        }
+        let now = self.now();
+        let due = {
+            let mut timers = self.timers.borrow_mut();
+            let later = timers.pending.split_off(&(now.saturating_add(1), 0));
+            std::mem::replace(&mut timers.pending, later)
+        };
+        for waker in due.into_values() {
+            waker.wake();
+        }
+    }
+
+    /// Jump to the earliest timer if it is due by `limit`, waking it and any
+    /// others with the same deadline. Returns whether a timer fired.
+    pub(crate) fn fire_next_until(&self, limit: u64) -> bool {
+        match self.next_timer() {
+            Some(deadline) if deadline <= limit => {
+                self.advance_to(deadline);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl MonotonicClock for SimClock {
+    fn now(&self) -> u64 {
+        self.time.now()
+    }
+
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn subscribe_duration(&self, duration: Duration) -> impl Future<Output = ()> {
+        self.sleep(duration)
+    }
+
+    fn subscribe_instant(&self, instant: u64) -> impl Future<Output = ()> {
+        self.sleep_until(instant)
+    }
+}
+
+/// A timer on a [`SimClock`].
+#[derive(Debug)]
+pub struct Sleep {
+    clock: SimClock,
+    deadline: u64,
+    key: Option<(u64, u64)>,
+}
+
+impl Sleep {
+    fn unregister(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.clock.timers.borrow_mut().pending.remove(&key);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.clock.now() >= self.deadline {
+            self.unregister();
+            return Poll::Ready(());
+        }
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                let mut timers = self.clock.timers.borrow_mut();
+                let key = (self.deadline, timers.next_id);
+                timers.next_id += 1;
+                drop(timers);
+                self.key = Some(key);
+                key
+            }
+        };
+        self.clock
+            .timers
+            .borrow_mut()
+            .pending
+            .insert(key, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
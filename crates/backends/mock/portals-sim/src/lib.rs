@@ -0,0 +1,499 @@
+//! Deterministic simulation harness.
+//!
+//! [`Sim`] runs async code written against portals capabilities on a single
+//! thread, with a virtual clock, seeded randomness, and in-memory sockets
+//! and messaging. Runnable tasks are polled in an order chosen by the seed,
+//! and when every task is blocked the clock jumps straight to the next
+//! timer. A run is a pure function of its seed: a failure found with one
+//! seed replays exactly, and hours of simulated time take milliseconds.
+//!
+//! ```ignore
+//! let sim = Sim::new(seed);
+//! let net = sim.network();
+//! let listener = net.bind("10.0.0.1:80".parse()?)?;
+//! sim.spawn(async move {
+//!     let (mut stream, _) = listener.accept().await.unwrap();
+//!     // ...
+//! });
+//! let reply = sim.block_on(async move {
+//!     let mut stream = net.connect("10.0.0.1:80".parse().unwrap()).await?;
+//!     // ...
+//! });
+//! ```
+
+mod clock;
+mod messaging;
+mod net;
+
+pub use clock::{SimClock, Sleep};
+pub use messaging::{SimChannel, SimReceiver, SimSender, SimSubscriber, SimTopic};
+pub use net::{SimListener, SimNetwork, SimStream};
+
+use portals_clocks::MonotonicClock;
+use portals_random::InsecureRandom;
+use portals_random_mock::{MockInsecureRandom, MockSecureRandom};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+/// Task ID of the future passed to [`Sim::block_on`].
+const MAIN: usize = usize::MAX;
+
+type LocalTask = Pin<Box<dyn Future<Output = ()>>>;
+
+/// A deterministic simulation.
+///
+/// Spawn tasks with [`spawn`](Self::spawn), then drive them with
+/// [`run`](Self::run), [`run_for`](Self::run_for), or
+/// [`block_on`](Self::block_on).
+pub struct Sim {
+    executor: Rc<Executor>,
+    network: SimNetwork,
+}
+
+struct Executor {
+    clock: SimClock,
+    rng: RefCell<MockInsecureRandom>,
+    /// Spawned tasks by ID. `None` while a task is being polled and once it
+    /// has finished.
+    tasks: RefCell<Vec<Option<LocalTask>>>,
+    /// IDs of tasks that have been woken.
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.lock().unwrap().insert(self.id);
+    }
+}
+
+impl Executor {
+    fn waker(&self, id: usize) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: self.ready.clone(),
+        }))
+    }
+
+    fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let state = Rc::new(RefCell::new(JoinState {
+            output: None,
+            finished: false,
+            waker: None,
+        }));
+        let task_state = state.clone();
+        let task = async move {
+            let output = future.await;
+            let mut state = task_state.borrow_mut();
+            state.output = Some(output);
+            state.finished = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        };
+        let mut tasks = self.tasks.borrow_mut();
+        let id = tasks.len();
+        tasks.push(Some(Box::pin(task)));
+        self.ready.lock().unwrap().insert(id);
+        JoinHandle { state }
+    }
+
+    /// Remove and return a seeded-random choice among the woken tasks.
+    fn next_ready(&self) -> Option<usize> {
+        let mut ready = self.ready.lock().unwrap();
+        if ready.is_empty() {
+            return None;
+        }
+        let index = self.rng.borrow_mut().u64() % ready.len() as u64;
+        let id = *ready.iter().nth(index as usize)?;
+        ready.remove(&id);
+        Some(id)
+    }
+
+    fn poll_task(&self, id: usize) {
+        let Some(mut task) = self.tasks.borrow_mut().get_mut(id).and_then(Option::take) else {
+            return;
+        };
+        let waker = self.waker(id);
+        if task
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+        {
+            self.tasks.borrow_mut()[id] = Some(task);
+        }
+    }
+}
+
+impl Sim {
+    /// Create a simulation at time zero whose scheduling and randomness
+    /// derive from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            executor: Rc::new(Executor {
+                clock: SimClock::new(),
+                rng: RefCell::new(MockInsecureRandom::new(seed)),
+                tasks: RefCell::new(Vec::new()),
+                ready: Arc::new(Mutex::new(BTreeSet::new())),
+            }),
+            network: SimNetwork::new(),
+        }
+    }
+
+    /// The simulation's clock.
+    pub fn clock(&self) -> SimClock {
+        self.executor.clock.clone()
+    }
+
+    /// The simulation's network.
+    pub fn network(&self) -> SimNetwork {
+        self.network.clone()
+    }
+
+    /// A new insecure random source, seeded from the simulation's seed.
+    pub fn insecure_random(&self) -> MockInsecureRandom {
+        MockInsecureRandom::new(self.executor.rng.borrow_mut().u64())
+    }
+
+    /// A new secure random source, seeded from the simulation's seed.
+    pub fn secure_random(&self) -> MockSecureRandom {
+        MockSecureRandom::new(self.executor.rng.borrow_mut().u64())
+    }
+
+    /// A channel factory whose receive timeouts use the simulation's clock.
+    pub fn channel(&self) -> SimChannel {
+        SimChannel::new(self.clock())
+    }
+
+    /// A new topic whose receive timeouts use the simulation's clock.
+    pub fn topic(&self) -> SimTopic {
+        SimTopic::new(self.clock())
+    }
+
+    /// A handle for spawning tasks from inside other tasks.
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            executor: Rc::downgrade(&self.executor),
+        }
+    }
+
+    /// Spawn a task. It first runs when the simulation is next driven.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.executor.spawn(future)
+    }
+
+    /// Run until every task is finished or blocked with no timer left to
+    /// wake it.
+    ///
+    /// Never returns if a task keeps setting timers, such as a periodic
+    /// job; use [`run_for`](Self::run_for) for those.
+    pub fn run(&self) {
+        self.run_until(u64::MAX);
+    }
+
+    /// Run for `duration` of virtual time, leaving the clock exactly
+    /// `duration` later.
+    pub fn run_for(&self, duration: Duration) {
+        let deadline = self
+            .executor
+            .clock
+            .now()
+            .saturating_add(duration.as_nanos() as u64);
+        self.run_until(deadline);
+        self.executor.clock.advance_to(deadline);
+        self.run_until(self.executor.clock.now());
+    }
+
+    fn run_until(&self, limit: u64) {
+        let executor = &self.executor;
+        loop {
+            match executor.next_ready() {
+                Some(id) => executor.poll_task(id),
+                None if executor.clock.fire_next_until(limit) => {}
+                None => return,
+            }
+        }
+    }
+
+    /// Drive the simulation until `future` completes, and return its output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `future` can never complete: it is blocked, no task is
+    /// runnable, and no timer is pending.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let executor = &self.executor;
+        let mut future = pin!(future);
+        let waker = executor.waker(MAIN);
+        executor.ready.lock().unwrap().insert(MAIN);
+        loop {
+            match executor.next_ready() {
+                Some(MAIN) => {
+                    if let Poll::Ready(output) =
+                        future.as_mut().poll(&mut Context::from_waker(&waker))
+                    {
+                        return output;
+                    }
+                }
+                Some(id) => executor.poll_task(id),
+                None if executor.clock.fire_next_until(u64::MAX) => {}
+                None => panic!("simulation deadlocked: future blocked with nothing left to run"),
+            }
+        }
+    }
+}
+
+/// Spawns tasks onto a [`Sim`] from inside the simulation.
+#[derive(Clone)]
+pub struct Spawner {
+    executor: Weak<Executor>,
+}
+
+impl Spawner {
+    /// Spawn a task, or return `None` if the simulation has been dropped.
+    pub fn spawn<F>(&self, future: F) -> Option<JoinHandle<F::Output>>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        Some(self.executor.upgrade()?.spawn(future))
+    }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+/// Awaits the output of a spawned task. Dropping it detaches the task.
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Whether the task has finished.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().finished
+    }
+
+    /// Take the task's output if it has finished.
+    pub fn try_take(&self) -> Option<T> {
+        self.state.borrow_mut().output.take()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Yield to the simulation's scheduler once, letting other runnable tasks
+/// go first.
+pub async fn yield_now() {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_messaging::{Channel, Message, Receiver, Sender, Topic};
+    use portals_sockets::{Error, TcpConnect, TcpListener, TcpStream};
+    use std::net::SocketAddr;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn timers_advance_virtual_time() {
+        let sim = Sim::new(1);
+        let clock = sim.clock();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for (name, secs) in [("slow", 10), ("fast", 5)] {
+            let clock = clock.clone();
+            let log = log.clone();
+            sim.spawn(async move {
+                clock.subscribe_duration(Duration::from_secs(secs)).await;
+                log.borrow_mut().push((name, clock.now()));
+            });
+        }
+        sim.run();
+        assert_eq!(
+            *log.borrow(),
+            [("fast", 5_000_000_000), ("slow", 10_000_000_000)]
+        );
+        assert_eq!(clock.now(), 10_000_000_000);
+    }
+
+    #[test]
+    fn run_for_stops_at_deadline() {
+        let sim = Sim::new(1);
+        let clock = sim.clock();
+        let ticks = Rc::new(RefCell::new(0));
+        let (task_clock, task_ticks) = (clock.clone(), ticks.clone());
+        sim.spawn(async move {
+            loop {
+                task_clock.subscribe_duration(Duration::from_secs(1)).await;
+                *task_ticks.borrow_mut() += 1;
+            }
+        });
+        sim.run_for(Duration::from_millis(3500));
+        assert_eq!(*ticks.borrow(), 3);
+        assert_eq!(clock.now(), 3_500_000_000);
+    }
+
+    fn interleaving(seed: u64) -> Vec<usize> {
+        let sim = Sim::new(seed);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for id in 0..4 {
+            let log = log.clone();
+            sim.spawn(async move {
+                for _ in 0..4 {
+                    log.borrow_mut().push(id);
+                    yield_now().await;
+                }
+            });
+        }
+        sim.run();
+        Rc::try_unwrap(log).unwrap().into_inner()
+    }
+
+    #[test]
+    fn scheduling_is_determined_by_seed() {
+        assert_eq!(interleaving(7), interleaving(7));
+        assert!((0..10).any(|seed| interleaving(seed) != interleaving(7)));
+    }
+
+    #[test]
+    fn join_handles_and_spawner() {
+        let sim = Sim::new(3);
+        let spawner = sim.spawner();
+        let result = sim.block_on(async move {
+            let inner = spawner.spawn(async { 20 }).unwrap();
+            inner.await + 22
+        });
+        assert_eq!(result, 42);
+    }
+
+    #[test]
+    fn tcp_echo() {
+        let sim = Sim::new(5);
+        let net = sim.network();
+        let listener = net.bind(addr("10.0.0.1:7")).unwrap();
+        assert!(matches!(
+            net.bind(addr("10.0.0.1:7")),
+            Err(Error::AddressInUse)
+        ));
+        sim.spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 64];
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                stream.write(&buf[..n]).await.unwrap();
+            }
+        });
+
+        let reply = sim.block_on(async move {
+            assert!(matches!(
+                net.connect(addr("10.0.0.2:7")).await,
+                Err(Error::ConnectionRefused)
+            ));
+            let mut stream = net.connect(addr("10.0.0.1:7")).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), addr("10.0.0.1:7"));
+            stream.write(b"ping").await.unwrap();
+            let mut buf = [0; 4];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.shutdown().unwrap();
+            buf[..n].to_vec()
+        });
+        assert_eq!(reply, b"ping");
+        sim.run();
+    }
+
+    #[test]
+    fn messaging_with_virtual_timeouts() {
+        let sim = Sim::new(9);
+        let clock = sim.clock();
+        let (sender, receiver) = sim.channel().create();
+        let topic = sim.topic();
+
+        sim.block_on(async move {
+            let subscriber = topic.subscribe().await.unwrap();
+            let result = receiver.receive_timeout(Duration::from_secs(30)).await;
+            assert!(matches!(result, Err(portals_messaging::Error::Timeout)));
+            assert_eq!(clock.now(), 30_000_000_000);
+
+            sender.send(Message::new("hi")).await.unwrap();
+            assert_eq!(receiver.receive().await.unwrap().data, b"hi");
+
+            topic.publish(Message::new("news")).await.unwrap();
+            assert_eq!(subscriber.receive().await.unwrap().data, b"news");
+
+            drop(sender);
+            assert!(matches!(
+                receiver.receive().await,
+                Err(portals_messaging::Error::Closed)
+            ));
+        });
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let a = Sim::new(11).insecure_random().u64();
+        let b = Sim::new(11).insecure_random().u64();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    #[should_panic(expected = "deadlocked")]
+    fn detects_deadlock() {
+        let sim = Sim::new(1);
+        let (_sender, receiver) = sim.channel().create();
+        let _ = sim.block_on(async move { receiver.receive().await });
+    }
+}
@@ -0,0 +1,227 @@
+//! In-memory channels and topics.
+
+use crate::SimClock;
+use portals_messaging::{Channel, Error, Message, Receiver, Sender, Subscriber, Topic};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct Queue {
+    messages: VecDeque<Message>,
+    waker: Option<Waker>,
+    senders: usize,
+    receiver_dropped: bool,
+}
+
+impl Queue {
+    fn push(&mut self, message: Message) {
+        self.messages.push_back(message);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Result<Message, Error>> {
+        match self.try_receive() {
+            Ok(Some(message)) => Poll::Ready(Ok(message)),
+            Ok(None) => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<Message>, Error> {
+        match self.messages.pop_front() {
+            Some(message) => Ok(Some(message)),
+            None if self.senders == 0 => Err(Error::Closed),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Point-to-point channels whose receive timeouts use a [`SimClock`].
+#[derive(Debug, Clone)]
+pub struct SimChannel {
+    clock: SimClock,
+}
+
+impl SimChannel {
+    /// Create a channel factory on `clock`.
+    pub fn new(clock: SimClock) -> Self {
+        Self { clock }
+    }
+}
+
+impl Channel for SimChannel {
+    type Sender = SimSender;
+    type Receiver = SimReceiver;
+
+    fn create(&self) -> (SimSender, SimReceiver) {
+        let queue = Rc::new(RefCell::new(Queue {
+            senders: 1,
+            ..Queue::default()
+        }));
+        let sender = SimSender {
+            queue: queue.clone(),
+        };
+        let receiver = SimReceiver {
+            queue,
+            clock: self.clock.clone(),
+        };
+        (sender, receiver)
+    }
+}
+
+/// The sending half of a [`SimChannel`]. Once every clone is dropped, the
+/// receiver sees [`Error::Closed`] after draining the queue.
+#[derive(Debug)]
+pub struct SimSender {
+    queue: Rc<RefCell<Queue>>,
+}
+
+impl Clone for SimSender {
+    fn clone(&self) -> Self {
+        self.queue.borrow_mut().senders += 1;
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Drop for SimSender {
+    fn drop(&mut self) {
+        let mut queue = self.queue.borrow_mut();
+        queue.senders -= 1;
+        if queue.senders == 0
+            && let Some(waker) = queue.waker.take()
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl Sender for SimSender {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        let mut queue = self.queue.borrow_mut();
+        if queue.receiver_dropped {
+            return Err(Error::Closed);
+        }
+        queue.push(message);
+        Ok(())
+    }
+}
+
+/// The receiving half of a [`SimChannel`].
+#[derive(Debug)]
+pub struct SimReceiver {
+    queue: Rc<RefCell<Queue>>,
+    clock: SimClock,
+}
+
+impl Drop for SimReceiver {
+    fn drop(&mut self) {
+        self.queue.borrow_mut().receiver_dropped = true;
+    }
+}
+
+impl Receiver for SimReceiver {
+    async fn receive(&self) -> Result<Message, Error> {
+        poll_fn(|cx| self.queue.borrow_mut().poll_receive(cx)).await
+    }
+
+    async fn receive_timeout(&self, timeout: Duration) -> Result<Message, Error> {
+        let mut sleep = self.clock.sleep(timeout);
+        poll_fn(|cx| {
+            if let Poll::Ready(result) = self.queue.borrow_mut().poll_receive(cx) {
+                return Poll::Ready(result);
+            }
+            match Pin::new(&mut sleep).poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(Error::Timeout)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    async fn try_receive(&self) -> Result<Option<Message>, Error> {
+        self.queue.borrow_mut().try_receive()
+    }
+}
+
+/// A publish/subscribe topic. Each subscriber receives every message
+/// published after it subscribed.
+#[derive(Debug, Clone)]
+pub struct SimTopic {
+    clock: SimClock,
+    subscribers: Rc<RefCell<Vec<Weak<RefCell<Queue>>>>>,
+}
+
+impl SimTopic {
+    /// Create a topic on `clock`.
+    pub fn new(clock: SimClock) -> Self {
+        Self {
+            clock,
+            subscribers: Rc::default(),
+        }
+    }
+}
+
+impl Topic for SimTopic {
+    type Subscriber = SimSubscriber;
+
+    async fn publish(&self, message: Message) -> Result<(), Error> {
+        let mut subscribers = self.subscribers.borrow_mut();
+        subscribers.retain(|queue| match queue.upgrade() {
+            Some(queue) => {
+                queue.borrow_mut().push(message.clone());
+                true
+            }
+            None => false,
+        });
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<SimSubscriber, Error> {
+        // The topic counts as the queue's one sender, so it never closes.
+        let queue = Rc::new(RefCell::new(Queue {
+            senders: 1,
+            ..Queue::default()
+        }));
+        self.subscribers.borrow_mut().push(Rc::downgrade(&queue));
+        Ok(SimSubscriber(SimReceiver {
+            queue,
+            clock: self.clock.clone(),
+        }))
+    }
+}
+
+/// A subscription to a [`SimTopic`].
+#[derive(Debug)]
+pub struct SimSubscriber(SimReceiver);
+
+impl Receiver for SimSubscriber {
+    fn receive(&self) -> impl Future<Output = Result<Message, Error>> {
+        self.0.receive()
+    }
+
+    fn receive_timeout(&self, timeout: Duration) -> impl Future<Output = Result<Message, Error>> {
+        self.0.receive_timeout(timeout)
+    }
+
+    fn try_receive(&self) -> impl Future<Output = Result<Option<Message>, Error>> {
+        self.0.try_receive()
+    }
+}
+
+impl Subscriber for SimSubscriber {
+    async fn unsubscribe(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! In-memory TCP.
+
+use portals_sockets::{Error, TcpConnect, TcpListener, TcpStream};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::future::poll_fn;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+/// First port handed out for port 0 binds and outgoing connections.
+const EPHEMERAL_START: u16 = 49152;
+
+/// An in-memory network of TCP listeners and streams.
+///
+/// Bind listeners with [`bind`](Self::bind) and connect to them through the
+/// [`TcpConnect`] impl. Data is delivered instantly and in order; a
+/// connection to an address with no listener is refused.
+#[derive(Debug, Clone, Default)]
+pub struct SimNetwork {
+    inner: Rc<RefCell<Network>>,
+}
+
+#[derive(Debug, Default)]
+struct Network {
+    listeners: HashMap<SocketAddr, Rc<RefCell<Backlog>>>,
+    next_port: u16,
+}
+
+impl Network {
+    fn ephemeral_port(&mut self) -> u16 {
+        if self.next_port < EPHEMERAL_START {
+            self.next_port = EPHEMERAL_START;
+        }
+        let port = self.next_port;
+        self.next_port = self.next_port.checked_add(1).unwrap_or(EPHEMERAL_START);
+        port
+    }
+}
+
+#[derive(Debug, Default)]
+struct Backlog {
+    pending: VecDeque<(SimStream, SocketAddr)>,
+    waker: Option<Waker>,
+}
+
+impl SimNetwork {
+    /// Create an empty network.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listen on `addr`. Port 0 picks an unused port.
+    pub fn bind(&self, addr: SocketAddr) -> Result<SimListener, Error> {
+        let mut network = self.inner.borrow_mut();
+        let mut addr = addr;
+        if addr.port() == 0 {
+            addr.set_port(network.ephemeral_port());
+        }
+        if network.listeners.contains_key(&addr) {
+            return Err(Error::AddressInUse);
+        }
+        let backlog = Rc::new(RefCell::new(Backlog::default()));
+        network.listeners.insert(addr, backlog.clone());
+        Ok(SimListener {
+            network: self.clone(),
+            addr,
+            backlog,
+        })
+    }
+}
+
+impl TcpConnect for SimNetwork {
+    type Stream = SimStream;
+
+    async fn connect(&self, addr: SocketAddr) -> Result<SimStream, Error> {
+        let mut network = self.inner.borrow_mut();
+        let backlog = network
+            .listeners
+            .get(&addr)
+            .cloned()
+            .ok_or(Error::ConnectionRefused)?;
+        let local = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), network.ephemeral_port());
+        drop(network);
+
+        let (client, server) = SimStream::pair(local, addr);
+        let mut backlog = backlog.borrow_mut();
+        backlog.pending.push_back((server, local));
+        if let Some(waker) = backlog.waker.take() {
+            waker.wake();
+        }
+        Ok(client)
+    }
+}
+
+/// A listener on a [`SimNetwork`]. Dropping it frees the address.
+#[derive(Debug)]
+pub struct SimListener {
+    network: SimNetwork,
+    addr: SocketAddr,
+    backlog: Rc<RefCell<Backlog>>,
+}
+
+impl TcpListener for SimListener {
+    type Stream = SimStream;
+
+    async fn accept(&self) -> Result<(SimStream, SocketAddr), Error> {
+        poll_fn(|cx| {
+            let mut backlog = self.backlog.borrow_mut();
+            match backlog.pending.pop_front() {
+                Some(conn) => Poll::Ready(Ok(conn)),
+                None => {
+                    backlog.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.addr)
+    }
+}
+
+impl Drop for SimListener {
+    fn drop(&mut self) {
+        self.network.inner.borrow_mut().listeners.remove(&self.addr);
+    }
+}
+
+/// One direction of a connection.
+#[derive(Debug, Default)]
+struct Pipe {
+    data: VecDeque<u8>,
+    waker: Option<Waker>,
+    /// The writing end shut down or was dropped.
+    closed: bool,
+    /// The reading end was dropped.
+    abandoned: bool,
+}
+
+/// A connected stream on a [`SimNetwork`].
+///
+/// Dropping a stream closes both directions: the peer reads end-of-stream
+/// and its writes fail with [`Error::ConnectionReset`].
+#[derive(Debug)]
+pub struct SimStream {
+    incoming: Rc<RefCell<Pipe>>,
+    outgoing: Rc<RefCell<Pipe>>,
+    local: SocketAddr,
+    peer: SocketAddr,
+}
+
+impl SimStream {
+    fn pair(a: SocketAddr, b: SocketAddr) -> (Self, Self) {
+        let ab = Rc::new(RefCell::new(Pipe::default()));
+        let ba = Rc::new(RefCell::new(Pipe::default()));
+        let first = Self {
+            incoming: ba.clone(),
+            outgoing: ab.clone(),
+            local: a,
+            peer: b,
+        };
+        let second = Self {
+            incoming: ab,
+            outgoing: ba,
+            local: b,
+            peer: a,
+        };
+        (first, second)
+    }
+
+    fn close_outgoing(&self) {
+        let mut pipe = self.outgoing.borrow_mut();
+        pipe.closed = true;
+        if let Some(waker) = pipe.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl TcpStream for SimStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        poll_fn(|cx| {
+            let mut pipe = self.incoming.borrow_mut();
+            if buf.is_empty() || pipe.closed && pipe.data.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            if pipe.data.is_empty() {
+                pipe.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let n = buf.len().min(pipe.data.len());
+            for (slot, byte) in buf.iter_mut().zip(pipe.data.drain(..n)) {
+                *slot = byte;
+            }
+            Poll::Ready(Ok(n))
+        })
+        .await
+    }
+
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let mut pipe = self.outgoing.borrow_mut();
+        if pipe.abandoned {
+            return Err(Error::ConnectionReset);
+        }
+        if pipe.closed {
+            return Err(Error::NotConnected);
+        }
+        pipe.data.extend(buf);
+        if let Some(waker) = pipe.waker.take() {
+            waker.wake();
+        }
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        self.close_outgoing();
+        Ok(())
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.local)
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.peer)
+    }
+}
+
+impl Drop for SimStream {
+    fn drop(&mut self) {
+        self.close_outgoing();
+        self.incoming.borrow_mut().abandoned = true;
+    }
+}
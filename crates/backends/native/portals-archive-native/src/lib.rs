@@ -0,0 +1,412 @@
+//! Native implementation of portals-archive.
+//!
+//! Provides [`TarReader`]/[`TarWriter`] for ustar archives (with GNU and
+//! PAX extensions), on the `tar` crate, and [`ZipReader`]/[`ZipWriter`] for
+//! zip archives with stored or deflated entries, data descriptors, and
+//! zip64, using `flate2` and `crc32fast`. [`append_tar`] and [`append_zip`]
+//! open an existing archive in a directory to add entries to it.
+
+mod tar;
+mod zip;
+
+pub use tar::{TarReader, TarWriter, append_tar};
+pub use zip::{ZipReader, ZipWriter, append_zip};
+
+use portals_archive::ArchiveError;
+use portals_io::{InputStream, StreamError};
+
+/// Fill `buf` from `stream`, returning fewer bytes only at end of stream.
+fn read_full<S: InputStream>(stream: &mut S, buf: &mut [u8]) -> Result<usize, ArchiveError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match stream.blocking_read_into(&mut buf[filled..]) {
+            Ok(0) | Err(StreamError::Closed) => break,
+            Ok(n) => filled += n,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+/// Fill `buf` from `stream`, treating end of stream as truncation.
+fn read_exact<S: InputStream>(stream: &mut S, buf: &mut [u8]) -> Result<(), ArchiveError> {
+    if read_full(stream, buf)? < buf.len() {
+        return Err(ArchiveError::Corrupt(
+            "unexpected end of archive".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Read and discard `n` bytes.
+fn skip<S: InputStream>(stream: &mut S, mut n: u64) -> Result<(), ArchiveError> {
+    let mut buf = [0u8; 8192];
+    while n > 0 {
+        let len = n.min(buf.len() as u64) as usize;
+        read_exact(stream, &mut buf[..len])?;
+        n -= len as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_archive::{ArchiveReader, ArchiveWriter, Entry, EntryKind, safe_path};
+    use portals_filesystem_native::NativeDir;
+    use portals_io_native::{ReaderStream, WriterStream};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    type Input = ReaderStream<Cursor<Vec<u8>>>;
+
+    fn input(bytes: Vec<u8>) -> Input {
+        ReaderStream::new(Cursor::new(bytes))
+    }
+
+    fn write_sample<W: ArchiveWriter>(writer: &mut W) {
+        writer.append_dir("docs").unwrap();
+        writer.append_file("docs/readme.txt", b"hello").unwrap();
+        let mut data = input(b"streamed".to_vec());
+        writer
+            .append_stream(
+                &Entry::file("data.bin", 8).modified(1_700_000_000),
+                &mut data,
+            )
+            .unwrap();
+        writer.append_file("empty", b"").unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn read_all<R: ArchiveReader>(reader: &mut R) -> Vec<(Entry, Vec<u8>)> {
+        let mut out = Vec::new();
+        while let Some(entry) = reader.next_entry().unwrap() {
+            let data = reader.read_to_end().unwrap();
+            out.push((entry, data));
+        }
+        out
+    }
+
+    fn check_sample(entries: &[(Entry, Vec<u8>)]) {
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|(e, data)| (e.path.as_str(), e.kind, data.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("docs", EntryKind::Directory, &b""[..]),
+                ("docs/readme.txt", EntryKind::File, b"hello"),
+                ("data.bin", EntryKind::File, b"streamed"),
+                ("empty", EntryKind::File, b""),
+            ]
+        );
+        assert_eq!(entries[2].0.modified, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        let mut writer = TarWriter::new(WriterStream::new(Vec::new()));
+        write_sample(&mut writer);
+        let bytes = writer.into_inner().unwrap().into_inner();
+        assert_eq!(bytes.len() % 512, 0);
+
+        let entries = read_all(&mut TarReader::new(input(bytes)));
+        check_sample(&entries);
+        assert_eq!(entries[1].0.mode, 0o644);
+    }
+
+    #[test]
+    fn tar_long_names_and_symlinks() {
+        let long = format!("{}/file.txt", "nested/".repeat(40));
+        let mut writer = TarWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file(&long, b"deep").unwrap();
+        writer
+            .append_data(&Entry::symlink("link", "docs/readme.txt"), &[])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = TarReader::new(input(writer.into_inner().unwrap().into_inner()));
+        let entries = read_all(&mut reader);
+        assert_eq!(entries[0].0.path, long);
+        assert_eq!(entries[0].1, b"deep");
+        assert_eq!(entries[1].0.kind, EntryKind::Symlink);
+        assert_eq!(entries[1].0.link_target.as_deref(), Some("docs/readme.txt"));
+    }
+
+    #[test]
+    fn tar_skips_unread_data_and_detects_corruption() {
+        let mut writer = TarWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("a", &[1; 1000]).unwrap();
+        writer.append_file("b", b"second").unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().unwrap().into_inner();
+
+        let mut reader = TarReader::new(input(bytes.clone()));
+        reader.next_entry().unwrap();
+        let second = reader.next_entry().unwrap().unwrap();
+        assert_eq!(second.path, "b");
+        assert_eq!(reader.read_to_end().unwrap(), b"second");
+
+        let mut corrupt = bytes;
+        corrupt[10] ^= 0xff;
+        let mut reader = TarReader::new(input(corrupt));
+        assert!(matches!(reader.next_entry(), Err(ArchiveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        write_sample(&mut writer);
+        let bytes = writer.into_inner().into_inner();
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+
+        let entries = read_all(&mut ZipReader::new(input(bytes)));
+        check_sample(&entries);
+    }
+
+    #[test]
+    fn zip_deflates_compressible_entries() {
+        let data = b"all work and no play ".repeat(1000);
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("dull.txt", &data).unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().into_inner();
+        // Method field of the first local header.
+        assert_eq!(&bytes[8..10], &8u16.to_le_bytes());
+        assert!(bytes.len() < data.len() / 10);
+
+        let entries = read_all(&mut ZipReader::new(input(bytes.clone())));
+        assert_eq!(entries[0].0.size, data.len() as u64);
+        assert_eq!(entries[0].1, data);
+
+        // Data inflating past the recorded size is rejected.
+        let mut lying = bytes;
+        lying[22..26].copy_from_slice(&1000u32.to_le_bytes());
+        let mut reader = ZipReader::new(input(lying));
+        reader.next_entry().unwrap();
+        assert!(matches!(
+            reader.read_to_end(),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn zip_detects_bad_checksum() {
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("a.txt", b"payload").unwrap();
+        writer.finish().unwrap();
+        let mut bytes = writer.into_inner().into_inner();
+        // Flip a byte of the file data, just after the 30-byte header and
+        // 5-byte name.
+        bytes[35] ^= 0xff;
+
+        let mut reader = ZipReader::new(input(bytes));
+        reader.next_entry().unwrap();
+        assert!(matches!(
+            reader.read_to_end(),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn zip_streams_entries_with_data_descriptors() {
+        let data = b"all work and no play ".repeat(1000);
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        for name in ["first", "second"] {
+            let mut stream = input(data.clone());
+            writer
+                .append_stream(&Entry::file(name, data.len() as u64), &mut stream)
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().into_inner();
+        // Flags field of the first local header.
+        assert_eq!(&bytes[6..8], &(1u16 << 3 | 1 << 11).to_le_bytes());
+
+        // An unread entry is inflated past to find its end.
+        let mut reader = ZipReader::new(input(bytes.clone()));
+        assert_eq!(reader.next_entry().unwrap().unwrap().size, 0);
+        let second = reader.next_entry().unwrap().unwrap();
+        assert_eq!(second.path, "second");
+        assert_eq!(reader.read_to_end().unwrap(), data);
+        assert!(reader.next_entry().unwrap().is_none());
+
+        // The descriptor is checked against the data.
+        let mut lying = bytes;
+        let descriptor = lying.windows(4).position(|w| w == b"PK\x07\x08").unwrap();
+        lying[descriptor + 4] ^= 0xff;
+        let mut reader = ZipReader::new(input(lying));
+        reader.next_entry().unwrap();
+        assert!(matches!(
+            reader.read_to_end(),
+            Err(ArchiveError::Corrupt(_))
+        ));
+
+        // Data shorter than the entry's size is an error.
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        let result = writer.append_stream(&Entry::file("short", 10), &mut input(b"abc".to_vec()));
+        assert!(matches!(result, Err(ArchiveError::Corrupt(_))));
+    }
+
+    fn temp_dir(name: &str) -> (std::path::PathBuf, NativeDir) {
+        let temp_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let dir = NativeDir::new(&temp_dir);
+        (temp_dir, dir)
+    }
+
+    #[test]
+    fn tar_appends_to_existing_archive() {
+        let (temp_dir, dir) = temp_dir("portals-archive-test-append-tar");
+        let path = Path::new("archive.tar");
+        let mut writer = TarWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("a.txt", b"first").unwrap();
+        writer.finish().unwrap();
+        std::fs::write(
+            temp_dir.join(path),
+            writer.into_inner().unwrap().into_inner(),
+        )
+        .unwrap();
+
+        for contents in [&b"second"[..], b"third"] {
+            let mut writer = append_tar(&dir, path).unwrap();
+            writer.append_file("b.txt", contents).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let bytes = std::fs::read(temp_dir.join(path)).unwrap();
+        let entries = read_all(&mut TarReader::new(input(bytes)));
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|(e, data)| (e.path.as_str(), data.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("a.txt", &b"first"[..]),
+                ("b.txt", b"second"),
+                ("b.txt", b"third")
+            ]
+        );
+    }
+
+    #[test]
+    fn zip_appends_to_existing_archive() {
+        let (temp_dir, dir) = temp_dir("portals-archive-test-append-zip");
+        let path = Path::new("archive.zip");
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("a.txt", b"first").unwrap();
+        writer.finish().unwrap();
+        std::fs::write(temp_dir.join(path), writer.into_inner().into_inner()).unwrap();
+
+        let mut writer = append_zip(&dir, path).unwrap();
+        writer.append_file("b.txt", b"second").unwrap();
+        writer.finish().unwrap();
+
+        let bytes = std::fs::read(temp_dir.join(path)).unwrap();
+        // The central directory lists both entries.
+        assert_eq!(
+            &bytes[bytes.len() - 12..bytes.len() - 10],
+            &2u16.to_le_bytes()
+        );
+        let entries = read_all(&mut ZipReader::new(input(bytes)));
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|(e, data)| (e.path.as_str(), data.as_slice()))
+            .collect();
+        assert_eq!(summary, [("a.txt", &b"first"[..]), ("b.txt", b"second")]);
+    }
+
+    #[test]
+    fn zip_writes_zip64_end_records_for_many_entries() {
+        let (temp_dir, dir) = temp_dir("portals-archive-test-zip64");
+        let path = Path::new("archive.zip");
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        for i in 0..u16::MAX {
+            writer.append_file(&i.to_string(), b"").unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().into_inner();
+        // The count in the end record is maxed out, pointing to zip64.
+        assert_eq!(&bytes[bytes.len() - 12..bytes.len() - 10], &[0xff, 0xff]);
+        std::fs::write(temp_dir.join(path), bytes).unwrap();
+
+        // Appending finds the real count through the zip64 record.
+        let mut writer = append_zip(&dir, path).unwrap();
+        writer.append_file("last", b"end").unwrap();
+        writer.finish().unwrap();
+
+        let bytes = std::fs::read(temp_dir.join(path)).unwrap();
+        let record = bytes.windows(4).rposition(|w| w == b"PK\x06\x06").unwrap();
+        assert_eq!(
+            &bytes[record + 32..record + 40],
+            &(u16::MAX as u64 + 1).to_le_bytes()
+        );
+        let mut reader = ZipReader::new(input(bytes));
+        let mut count = 0;
+        while let Some(entry) = reader.next_entry().unwrap() {
+            count += 1;
+            if entry.path == "last" {
+                assert_eq!(reader.read_to_end().unwrap(), b"end");
+            }
+        }
+        assert_eq!(count, u16::MAX as usize + 1);
+    }
+
+    #[test]
+    fn rejects_unsafe_paths() {
+        for path in [
+            "/etc/passwd",
+            "../up",
+            "a/../../up",
+            "C:\\windows",
+            "\\share",
+            "",
+            "./",
+        ] {
+            assert!(
+                matches!(safe_path(path), Err(ArchiveError::UnsafePath(_))),
+                "{:?}",
+                path
+            );
+        }
+        assert_eq!(safe_path("./a//b/").unwrap(), Path::new("a/b"));
+    }
+
+    #[test]
+    fn extracts_into_directory() {
+        let temp_dir = std::env::temp_dir().join("portals-archive-test-1");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let dir = NativeDir::new(&temp_dir);
+
+        let mut writer = TarWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("a/b/c.txt", b"nested").unwrap();
+        writer.append_file("a/top.txt", b"top").unwrap();
+        writer
+            .append_data(&Entry::symlink("escape", "/etc"), &[])
+            .unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().unwrap().into_inner();
+
+        let files = TarReader::new(input(bytes)).extract_to(&dir).unwrap();
+        assert_eq!(files, 2);
+        assert_eq!(
+            std::fs::read(temp_dir.join("a/b/c.txt")).unwrap(),
+            b"nested"
+        );
+        assert_eq!(std::fs::read(temp_dir.join("a/top.txt")).unwrap(), b"top");
+        assert!(!temp_dir.join("escape").exists());
+
+        // An entry escaping the directory stops extraction.
+        let mut writer = ZipWriter::new(WriterStream::new(Vec::new()));
+        writer.append_file("../evil.txt", b"x").unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().into_inner();
+        let result = ZipReader::new(input(bytes)).extract_to(&dir);
+        assert!(matches!(result, Err(ArchiveError::UnsafePath(_))));
+        assert!(!temp_dir.join("../evil.txt").exists());
+    }
+}
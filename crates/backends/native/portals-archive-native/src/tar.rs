@@ -0,0 +1,457 @@
+//! tar archives, on the `tar` crate's header and builder.
+
+use crate::{read_exact, read_full, skip};
+use portals_archive::{ArchiveError, ArchiveReader, ArchiveWriter, Entry, EntryKind, safe_path};
+use portals_filesystem::Directory;
+use portals_io::{InputStream, OutputStream, StreamError};
+use std::io;
+use std::path::Path;
+use tar::{EntryType, Header, PaxExtensions};
+
+const BLOCK: u64 = 512;
+
+fn padding(size: u64) -> u64 {
+    (BLOCK - size % BLOCK) % BLOCK
+}
+
+fn corrupt(msg: &str) -> ArchiveError {
+    ArchiveError::Corrupt(msg.to_string())
+}
+
+fn invalid_field(e: io::Error) -> ArchiveError {
+    ArchiveError::Corrupt(e.to_string())
+}
+
+/// A NUL-terminated string, as GNU long names are stored.
+fn nul_terminated(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// The header checksum: the sum of its bytes, with the checksum field
+/// itself counted as spaces.
+fn checksum(block: &[u8; BLOCK as usize]) -> u32 {
+    block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u32)
+        .sum()
+}
+
+/// Values from extension headers that override the next entry's header.
+#[derive(Default)]
+struct Overrides {
+    path: Option<String>,
+    link: Option<String>,
+    size: Option<u64>,
+    modified: Option<u64>,
+}
+
+impl Overrides {
+    /// Apply PAX extended header records.
+    fn apply_pax(&mut self, data: &[u8]) -> Result<(), ArchiveError> {
+        for extension in PaxExtensions::new(data) {
+            let extension = extension.map_err(invalid_field)?;
+            let value = String::from_utf8_lossy(extension.value_bytes());
+            match extension.key_bytes() {
+                b"path" => self.path = Some(value.into_owned()),
+                b"linkpath" => self.link = Some(value.into_owned()),
+                b"size" => self.size = value.parse().ok(),
+                // Fractional seconds are dropped.
+                b"mtime" => self.modified = value.split('.').next().and_then(|s| s.parse().ok()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a tar archive from an [`InputStream`].
+///
+/// Headers are decoded with the `tar` crate, so ustar, GNU, and old-style
+/// headers are understood, including GNU base-256 numbers. GNU long names
+/// (`L`/`K`) and PAX extended headers override paths, link targets, sizes,
+/// and modification times.
+pub struct TarReader<S> {
+    stream: S,
+    /// Unread data in the current entry.
+    remaining: u64,
+    /// Padding after the current entry's data.
+    padding: u64,
+    /// Bytes read from the stream so far.
+    offset: u64,
+    /// Where the entries end: the offset of the end-of-archive marker, once
+    /// it has been reached.
+    end: Option<u64>,
+}
+
+impl<S: InputStream> TarReader<S> {
+    /// Read entries from `stream`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            remaining: 0,
+            padding: 0,
+            offset: 0,
+            end: None,
+        }
+    }
+
+    /// Consume the reader, returning the stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// The next header block, or `None` at the end-of-archive marker or end
+    /// of stream.
+    fn read_header(&mut self) -> Result<Option<[u8; BLOCK as usize]>, ArchiveError> {
+        let start = self.offset;
+        let mut block = [0u8; BLOCK as usize];
+        let n = read_full(&mut self.stream, &mut block)?;
+        self.offset += n as u64;
+        match n as u64 {
+            0 => {}
+            BLOCK if block.iter().all(|&b| b == 0) => {}
+            BLOCK => {
+                let stored = Header::from_byte_slice(&block)
+                    .cksum()
+                    .map_err(invalid_field)?;
+                if stored != checksum(&block) {
+                    return Err(corrupt("header checksum mismatch"));
+                }
+                return Ok(Some(block));
+            }
+            _ => return Err(corrupt("truncated header")),
+        }
+        self.end = Some(start);
+        Ok(None)
+    }
+
+    /// Read an extension header's data.
+    fn read_extension(&mut self, size: u64) -> Result<Vec<u8>, ArchiveError> {
+        if size > 1 << 20 {
+            return Err(corrupt("extension header too large"));
+        }
+        let mut data = vec![0u8; size as usize];
+        read_exact(&mut self.stream, &mut data)?;
+        self.skip(padding(size))?;
+        self.offset += size;
+        Ok(data)
+    }
+
+    fn skip(&mut self, n: u64) -> Result<(), ArchiveError> {
+        skip(&mut self.stream, n)?;
+        self.offset += n;
+        Ok(())
+    }
+}
+
+impl<S: InputStream> ArchiveReader for TarReader<S> {
+    fn next_entry(&mut self) -> Result<Option<Entry>, ArchiveError> {
+        if self.end.is_some() {
+            return Ok(None);
+        }
+        self.skip(self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+
+        let mut overrides = Overrides::default();
+        loop {
+            let Some(block) = self.read_header()? else {
+                return Ok(None);
+            };
+            let header = Header::from_byte_slice(&block);
+            let size = header.entry_size().map_err(invalid_field)?;
+            let entry_type = header.entry_type();
+            match entry_type {
+                EntryType::GNULongName => {
+                    overrides.path = Some(nul_terminated(&self.read_extension(size)?));
+                    continue;
+                }
+                EntryType::GNULongLink => {
+                    overrides.link = Some(nul_terminated(&self.read_extension(size)?));
+                    continue;
+                }
+                EntryType::XHeader => {
+                    let data = self.read_extension(size)?;
+                    overrides.apply_pax(&data)?;
+                    continue;
+                }
+                EntryType::XGlobalHeader => {
+                    self.skip(size + padding(size))?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let path = overrides
+                .path
+                .take()
+                .unwrap_or_else(|| String::from_utf8_lossy(&header.path_bytes()).into_owned());
+            let kind = match entry_type {
+                EntryType::Directory => EntryKind::Directory,
+                EntryType::Regular | EntryType::Continuous if path.ends_with('/') => {
+                    EntryKind::Directory
+                }
+                EntryType::Regular | EntryType::Continuous => EntryKind::File,
+                EntryType::Symlink => EntryKind::Symlink,
+                _ => EntryKind::Other,
+            };
+            let link_target = match entry_type {
+                EntryType::Link | EntryType::Symlink => {
+                    Some(overrides.link.take().unwrap_or_else(|| {
+                        header
+                            .link_name_bytes()
+                            .map(|name| String::from_utf8_lossy(&name).into_owned())
+                            .unwrap_or_default()
+                    }))
+                }
+                _ => None,
+            };
+            let size = overrides.size.unwrap_or(size);
+            let modified = match overrides.modified {
+                Some(mtime) => mtime,
+                None => header.mtime().map_err(invalid_field)?,
+            };
+
+            self.remaining = size;
+            self.padding = padding(size);
+            return Ok(Some(Entry {
+                path: path.trim_end_matches('/').to_string(),
+                kind,
+                size,
+                mode: header.mode().map_err(invalid_field)?,
+                modified: Some(modified),
+                link_target,
+            }));
+        }
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, ArchiveError> {
+        let len = (buf.len() as u64).min(self.remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let n = read_full(&mut self.stream, &mut buf[..len])?;
+        if n == 0 {
+            return Err(corrupt("unexpected end of archive"));
+        }
+        self.remaining -= n as u64;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// Writes a tar archive to an [`OutputStream`], with the `tar` crate's
+/// [`Builder`](tar::Builder).
+///
+/// Headers are GNU: paths and link targets that don't fit in the header
+/// are written as GNU long names, and sizes and times too large for octal
+/// in base-256. Entry paths are checked with
+/// [`safe_path`](portals_archive::safe_path), so an archive written here
+/// can't escape the directory it is extracted into.
+pub struct TarWriter<S: OutputStream> {
+    builder: tar::Builder<StreamWriter<S>>,
+    finished: bool,
+}
+
+impl<S: OutputStream> TarWriter<S> {
+    /// Write an archive to `stream`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            builder: tar::Builder::new(StreamWriter {
+                stream,
+                error: None,
+            }),
+            finished: false,
+        }
+    }
+
+    /// Consume the writer, returning the stream.
+    ///
+    /// Writes the end-of-archive marker first if
+    /// [`finish`](ArchiveWriter::finish) wasn't called.
+    pub fn into_inner(self) -> Result<S, ArchiveError> {
+        self.builder
+            .into_inner()
+            .map(|writer| writer.stream)
+            .map_err(|e| ArchiveError::Other(e.to_string()))
+    }
+
+    /// The error for a failed builder call: the stream's own error if it
+    /// failed, otherwise `e`.
+    fn error(&mut self, e: io::Error) -> ArchiveError {
+        match self.builder.get_mut().error.take() {
+            Some(e) => e.into(),
+            None => ArchiveError::Other(e.to_string()),
+        }
+    }
+
+    fn append_entry(
+        &mut self,
+        entry: &Entry,
+        size: u64,
+        data: &mut dyn io::Read,
+    ) -> Result<(), ArchiveError> {
+        if self.finished {
+            return Err(ArchiveError::Other("archive already finished".to_string()));
+        }
+        safe_path(&entry.path)?;
+
+        let mut header = Header::new_gnu();
+        header.set_mode(entry.mode & 0o7777);
+        header.set_mtime(entry.modified.unwrap_or(0));
+        header.set_size(size);
+        let result = match entry.kind {
+            EntryKind::File | EntryKind::Other => {
+                header.set_entry_type(EntryType::Regular);
+                self.builder.append_data(&mut header, &entry.path, data)
+            }
+            EntryKind::Directory => {
+                header.set_entry_type(EntryType::Directory);
+                let path = format!("{}/", entry.path.trim_end_matches('/'));
+                self.builder.append_data(&mut header, path, io::empty())
+            }
+            EntryKind::Symlink => {
+                header.set_entry_type(EntryType::Symlink);
+                let target = entry.link_target.as_deref().unwrap_or("");
+                self.builder.append_link(&mut header, &entry.path, target)
+            }
+        };
+        result.map_err(|e| self.error(e))
+    }
+}
+
+impl<S: OutputStream> ArchiveWriter for TarWriter<S> {
+    fn append_data(&mut self, entry: &Entry, mut data: &[u8]) -> Result<(), ArchiveError> {
+        let size = match entry.kind {
+            EntryKind::File | EntryKind::Other => data.len() as u64,
+            _ => 0,
+        };
+        self.append_entry(entry, size, &mut data)
+    }
+
+    fn append_stream<R: InputStream>(
+        &mut self,
+        entry: &Entry,
+        data: &mut R,
+    ) -> Result<(), ArchiveError> {
+        let size = match entry.kind {
+            EntryKind::File | EntryKind::Other => entry.size,
+            _ => 0,
+        };
+        let mut reader = StreamReader {
+            stream: data,
+            remaining: size,
+            error: None,
+        };
+        self.append_entry(entry, size, &mut reader)
+            .map_err(|e| reader.error.take().unwrap_or(e))
+    }
+
+    fn finish(&mut self) -> Result<(), ArchiveError> {
+        self.finished = true;
+        self.builder.finish().map_err(|e| self.error(e))?;
+        self.builder.get_mut().stream.blocking_flush()?;
+        Ok(())
+    }
+}
+
+/// Open the tar archive at `path` in `dir` to append entries to it.
+///
+/// The archive is read to find where its entries end, cut off there to
+/// drop its end-of-archive marker, and reopened for appending, so the
+/// entries already in it are kept without being copied. Call
+/// [`finish`](ArchiveWriter::finish) to write a new marker.
+pub fn append_tar<D: Directory>(
+    dir: &D,
+    path: &Path,
+) -> Result<TarWriter<impl OutputStream>, ArchiveError> {
+    let mut reader = TarReader::new(dir.open_read(path)?);
+    while reader.next_entry()?.is_some() {}
+    let end = reader.end.unwrap_or(reader.offset);
+    dir.truncate(path, end)?;
+    Ok(TarWriter::new(dir.open_append(path)?))
+}
+
+/// An [`OutputStream`] as an [`io::Write`], keeping the stream's error so
+/// it can be reported as is.
+struct StreamWriter<S> {
+    stream: S,
+    error: Option<StreamError>,
+}
+
+impl<S: OutputStream> io::Write for StreamWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.stream.blocking_write(buf) {
+            Ok(()) => Ok(buf.len()),
+            Err(e) => {
+                let message = e.to_string();
+                self.error = Some(e);
+                Err(io::Error::other(message))
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Exactly `remaining` bytes of an [`InputStream`] as an [`io::Read`],
+/// failing if the stream ends early.
+struct StreamReader<'a, R> {
+    stream: &'a mut R,
+    remaining: u64,
+    error: Option<ArchiveError>,
+}
+
+impl<R: InputStream> io::Read for StreamReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = (buf.len() as u64).min(self.remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let result = match read_full(self.stream, &mut buf[..len]) {
+            Ok(0) => Err(corrupt("entry data shorter than its size")),
+            result => result,
+        };
+        match result {
+            Ok(n) => {
+                self.remaining -= n as u64;
+                Ok(n)
+            }
+            Err(e) => {
+                let message = e.to_string();
+                self.error = Some(e);
+                Err(io::Error::other(message))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_matches_builder() {
+        let mut header = Header::new_gnu();
+        header.set_path("a/b.txt").unwrap();
+        header.set_size(5);
+        header.set_cksum();
+        let block: &[u8; BLOCK as usize] = header.as_bytes();
+        assert_eq!(header.cksum().unwrap(), checksum(block));
+    }
+
+    #[test]
+    fn reads_pax_overrides() {
+        let mut overrides = Overrides::default();
+        overrides
+            .apply_pax(b"18 path=long/name\n22 mtime=1700000000.5\n")
+            .unwrap();
+        assert_eq!(overrides.path.as_deref(), Some("long/name"));
+        assert_eq!(overrides.modified, Some(1_700_000_000));
+        assert!(Overrides::default().apply_pax(b"99 path=x\n").is_err());
+    }
+}
@@ -0,0 +1,970 @@
+//! Zip archives with stored and deflated entries.
+
+use crate::{read_exact, read_full};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use portals_archive::{ArchiveError, ArchiveReader, ArchiveWriter, Entry, EntryKind};
+use portals_filesystem::Directory;
+use portals_io::{InputStream, OutputStream, Seek, SeekFrom, StreamError};
+use std::io::Write;
+use std::path::Path;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const ZIP64_END_LOCATOR: u32 = 0x0706_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// Header ID of the zip64 extended information extra field.
+const ZIP64_EXTRA: u16 = 0x0001;
+/// The value a 16- or 32-bit field holds when the real one is in a zip64
+/// record.
+const MAX_U16: u64 = u16::MAX as u64;
+const MAX_U32: u64 = u32::MAX as u64;
+
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+/// "Version made by": Unix, so external attributes carry a mode.
+const VERSION_MADE_BY: u16 = 3 << 8 | VERSION_ZIP64;
+
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Size of the buffers data is read, inflated, and deflated through.
+const BUFFER_SIZE: usize = 8192;
+
+fn corrupt(msg: &str) -> ArchiveError {
+    ArchiveError::Corrupt(msg.to_string())
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+/// The archive being read, with input read ahead of the current position
+/// kept for the next read.
+///
+/// Deflated data with a trailing data descriptor has no recorded length,
+/// so it is inflated straight from this buffer and whatever follows the end
+/// of the deflate stream stays here for the descriptor and the next header.
+struct Input<S> {
+    stream: S,
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+}
+
+impl<S: InputStream> Input<S> {
+    /// Buffered input, reading more from the stream if there is none.
+    /// Empty at the end of the stream.
+    fn fill(&mut self) -> Result<&[u8], ArchiveError> {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = match self.stream.blocking_read_into(&mut self.buf) {
+                Ok(n) => n,
+                Err(StreamError::Closed) => 0,
+                Err(e) => return Err(e.into()),
+            };
+        }
+        Ok(&self.buf[self.start..self.end])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.start += n;
+    }
+
+    /// Fill `out`, treating end of stream as truncation.
+    fn read_exact(&mut self, out: &mut [u8]) -> Result<(), ArchiveError> {
+        let mut filled = 0;
+        while filled < out.len() {
+            let available = self.fill()?;
+            if available.is_empty() {
+                return Err(corrupt("unexpected end of archive"));
+            }
+            let n = available.len().min(out.len() - filled);
+            out[filled..filled + n].copy_from_slice(&available[..n]);
+            self.consume(n);
+            filled += n;
+        }
+        Ok(())
+    }
+
+    /// Read and discard `n` bytes.
+    fn skip(&mut self, mut n: u64) -> Result<(), ArchiveError> {
+        while n > 0 {
+            let available = self.fill()?.len();
+            if available == 0 {
+                return Err(corrupt("unexpected end of archive"));
+            }
+            let len = (available as u64).min(n) as usize;
+            self.consume(len);
+            n -= len as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a zip archive from an [`InputStream`], front to back.
+///
+/// Entries are found from their local headers, so the archive can be read
+/// as it arrives without seeking to the central directory. Local headers
+/// don't record permissions or symlinks, so entries read this way are files
+/// or directories (names ending in `/`) with default modes.
+///
+/// Stored and deflated entries can be read, including zip64 entries.
+/// Deflated entries may have their sizes and checksum in a data descriptor
+/// after the data, as streaming writers produce; their [`Entry::size`] is
+/// `0`, since it isn't known until the data has been read. Data of entries
+/// compressed with other methods fails with [`ArchiveError::Unsupported`],
+/// though they can still be skipped when their header records their size.
+/// Other entries with data descriptors have no recorded end and fail with
+/// [`ArchiveError::Unsupported`] too. Data inflating past the size recorded
+/// for it is rejected as corrupt.
+pub struct ZipReader<S> {
+    input: Input<S>,
+    current: Option<Current>,
+    done: bool,
+}
+
+struct Current {
+    method: u16,
+    /// Unread compressed data, or `None` if its length is only in the data
+    /// descriptor after it.
+    remaining: Option<u64>,
+    /// Uncompressed size, or `None` if it is only in the data descriptor.
+    size: Option<u64>,
+    /// Whether the local header has a zip64 extra field, so the sizes in
+    /// the data descriptor are 8 bytes each.
+    zip64: bool,
+    expected_crc: u32,
+    crc: crc32fast::Hasher,
+    decompress: Option<Decompress>,
+    /// The data has been read to its end and verified.
+    done: bool,
+}
+
+impl Current {
+    /// Check the data read against the header, or against the data
+    /// descriptor that follows it, once it is complete.
+    fn finish<S: InputStream>(&mut self, input: &mut Input<S>) -> Result<(), ArchiveError> {
+        if self.remaining.is_none() {
+            let (compressed, size) = self
+                .decompress
+                .as_ref()
+                .map_or((0, 0), |d| (d.total_in(), d.total_out()));
+            let zip64 = self.zip64 || compressed >= MAX_U32 || size >= MAX_U32;
+            let mut signature = [0u8; 4];
+            input.read_exact(&mut signature)?;
+            let mut fields = [0u8; 20];
+            let fields = &mut fields[..if zip64 { 20 } else { 12 }];
+            if u32::from_le_bytes(signature) == DATA_DESCRIPTOR {
+                input.read_exact(fields)?;
+            } else {
+                // The signature is optional; without it, those bytes were
+                // the CRC.
+                fields[..4].copy_from_slice(&signature);
+                input.read_exact(&mut fields[4..])?;
+            }
+            self.expected_crc = u32_at(fields, 0);
+            let recorded = if zip64 {
+                (u64_at(fields, 4), u64_at(fields, 12))
+            } else {
+                (u32_at(fields, 4) as u64, u32_at(fields, 8) as u64)
+            };
+            if recorded != (compressed, size) {
+                return Err(corrupt("entry sizes differ from its data descriptor"));
+            }
+        }
+        if self.crc.clone().finalize() != self.expected_crc {
+            return Err(corrupt("CRC mismatch"));
+        }
+        self.done = true;
+        Ok(())
+    }
+}
+
+impl<S: InputStream> ZipReader<S> {
+    /// Read entries from `stream`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            input: Input {
+                stream,
+                buf: vec![0u8; BUFFER_SIZE].into_boxed_slice(),
+                start: 0,
+                end: 0,
+            },
+            current: None,
+            done: false,
+        }
+    }
+
+    /// Consume the reader, returning the stream. Input the reader has read
+    /// ahead is dropped.
+    pub fn into_inner(self) -> S {
+        self.input.stream
+    }
+
+    /// Move past the rest of the current entry's data.
+    fn skip_current(&mut self, mut current: Current) -> Result<(), ArchiveError> {
+        if current.done {
+            return Ok(());
+        }
+        match current.remaining {
+            Some(remaining) => self.input.skip(remaining),
+            // The end of the data is only found by inflating it.
+            None => {
+                let mut scratch = vec![0u8; BUFFER_SIZE];
+                while !current.done {
+                    inflate(&mut self.input, &mut current, &mut scratch)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<S: InputStream> ArchiveReader for ZipReader<S> {
+    fn next_entry(&mut self) -> Result<Option<Entry>, ArchiveError> {
+        if self.done {
+            return Ok(None);
+        }
+        if let Some(current) = self.current.take() {
+            self.skip_current(current)?;
+        }
+
+        let mut signature = [0u8; 4];
+        self.input.read_exact(&mut signature)?;
+        match u32::from_le_bytes(signature) {
+            LOCAL_HEADER => {}
+            CENTRAL_HEADER | ZIP64_END_OF_CENTRAL_DIRECTORY | END_OF_CENTRAL_DIRECTORY => {
+                self.done = true;
+                return Ok(None);
+            }
+            _ => return Err(corrupt("invalid local header signature")),
+        }
+
+        let mut header = [0u8; 26];
+        self.input.read_exact(&mut header)?;
+        let flags = u16_at(&header, 2);
+        let method = u16_at(&header, 4);
+        let modified = from_dos_datetime(u16_at(&header, 6), u16_at(&header, 8));
+        let expected_crc = u32_at(&header, 10);
+        let mut compressed_size = u32_at(&header, 14) as u64;
+        let mut size = u32_at(&header, 18) as u64;
+        let name_len = u16_at(&header, 22) as usize;
+        let extra_len = u16_at(&header, 24) as usize;
+
+        let descriptor = flags & FLAG_DATA_DESCRIPTOR != 0;
+        if descriptor && method != METHOD_DEFLATED {
+            return Err(ArchiveError::Unsupported(format!(
+                "data descriptors on entries with compression method {}",
+                method
+            )));
+        }
+
+        let mut name = vec![0u8; name_len];
+        self.input.read_exact(&mut name)?;
+        let mut extra = vec![0u8; extra_len];
+        self.input.read_exact(&mut extra)?;
+        let zip64 = match extra_field(&extra, ZIP64_EXTRA) {
+            Some(field) => {
+                // Only the sizes the header has no room for are present,
+                // uncompressed first.
+                let mut at = 0;
+                for value in [&mut size, &mut compressed_size] {
+                    if *value == MAX_U32 {
+                        if field.len() < at + 8 {
+                            return Err(corrupt("truncated zip64 extra field"));
+                        }
+                        *value = u64_at(field, at);
+                        at += 8;
+                    }
+                }
+                true
+            }
+            None => false,
+        };
+        let path = String::from_utf8_lossy(&name).into_owned();
+        let kind = if path.ends_with('/') {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        };
+
+        self.current = Some(Current {
+            method,
+            remaining: (!descriptor).then_some(compressed_size),
+            size: (!descriptor).then_some(size),
+            zip64,
+            expected_crc,
+            crc: crc32fast::Hasher::new(),
+            decompress: (method == METHOD_DEFLATED).then(|| Decompress::new(false)),
+            done: false,
+        });
+        Ok(Some(Entry {
+            path: path.trim_end_matches('/').to_string(),
+            kind,
+            size: if descriptor { 0 } else { size },
+            mode: if kind == EntryKind::Directory {
+                0o755
+            } else {
+                0o644
+            },
+            modified,
+            link_target: None,
+        }))
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, ArchiveError> {
+        let Some(current) = &mut self.current else {
+            return Ok(0);
+        };
+        match current.method {
+            METHOD_STORED => {}
+            METHOD_DEFLATED => return inflate(&mut self.input, current, buf),
+            method => {
+                return Err(ArchiveError::Unsupported(format!(
+                    "compression method {}",
+                    method
+                )));
+            }
+        }
+        if current.done {
+            return Ok(0);
+        }
+
+        // Stored entries always record their size in the header.
+        let remaining = current.remaining.unwrap_or(0);
+        let len = (buf.len() as u64).min(remaining) as usize;
+        self.input.read_exact(&mut buf[..len])?;
+        current.crc.update(&buf[..len]);
+        current.remaining = Some(remaining - len as u64);
+        if remaining == len as u64 {
+            current.finish(&mut self.input)?;
+        }
+        Ok(len)
+    }
+}
+
+/// Read the next piece of a deflated entry's data into `buf`.
+fn inflate<S: InputStream>(
+    input: &mut Input<S>,
+    current: &mut Current,
+    buf: &mut [u8],
+) -> Result<usize, ArchiveError> {
+    let Some(decompress) = &mut current.decompress else {
+        return Ok(0);
+    };
+    if current.done || buf.is_empty() {
+        return Ok(0);
+    }
+    loop {
+        let available = input.fill()?;
+        let available = match current.remaining {
+            Some(remaining) => &available[..(available.len() as u64).min(remaining) as usize],
+            None => available,
+        };
+        let (before_in, before_out) = (decompress.total_in(), decompress.total_out());
+        let status = decompress
+            .decompress(available, buf, FlushDecompress::None)
+            .map_err(|e| ArchiveError::Corrupt(format!("invalid deflate data: {}", e)))?;
+        let exhausted = available.is_empty();
+        let consumed = decompress.total_in() - before_in;
+        input.consume(consumed as usize);
+        if let Some(remaining) = &mut current.remaining {
+            *remaining -= consumed;
+        }
+        let produced = (decompress.total_out() - before_out) as usize;
+        if current
+            .size
+            .is_some_and(|size| decompress.total_out() > size)
+        {
+            return Err(corrupt("entry larger than its recorded size"));
+        }
+        current.crc.update(&buf[..produced]);
+
+        if status == Status::StreamEnd {
+            if current
+                .size
+                .is_some_and(|size| decompress.total_out() != size)
+            {
+                return Err(corrupt("entry smaller than its recorded size"));
+            }
+            if let Some(remaining) = &mut current.remaining {
+                input.skip(std::mem::take(remaining))?;
+            }
+            current.finish(input)?;
+            return Ok(produced);
+        }
+        if produced > 0 {
+            return Ok(produced);
+        }
+        if exhausted {
+            return Err(corrupt("truncated deflate data"));
+        }
+    }
+}
+
+/// The data of the extra field with header ID `id`, if present.
+fn extra_field(mut extra: &[u8], id: u16) -> Option<&[u8]> {
+    while extra.len() >= 4 {
+        let len = u16_at(extra, 2) as usize;
+        let data = extra.get(4..4 + len)?;
+        if u16_at(extra, 0) == id {
+            return Some(data);
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+/// Writes a zip archive to an [`OutputStream`].
+///
+/// [`append_data`](ArchiveWriter::append_data) deflates entries in memory,
+/// storing them instead when that doesn't make them smaller.
+/// [`append_stream`](ArchiveWriter::append_stream) deflates file data as it
+/// is read, without buffering the entry, and writes its sizes and checksum
+/// in a data descriptor after it. Zip64 records are written where sizes,
+/// offsets, or the number of entries don't fit the original format.
+pub struct ZipWriter<S> {
+    stream: S,
+    offset: u64,
+    central: Vec<u8>,
+    entries: u64,
+    finished: bool,
+}
+
+/// The fields of an entry's local and central headers.
+struct Header<'a> {
+    name: &'a str,
+    flags: u16,
+    method: u16,
+    modified: u64,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+}
+
+impl Header<'_> {
+    /// The local header. With `zip64`, the sizes go in a zip64 extra field,
+    /// as they must when they may not fit in 32 bits.
+    fn local(&self, zip64: bool) -> Vec<u8> {
+        let (sizes, extra) = if zip64 {
+            (
+                [MAX_U32, MAX_U32],
+                zip64_extra(&[self.size, self.compressed]),
+            )
+        } else {
+            ([self.compressed, self.size], Vec::new())
+        };
+        let mut out = Vec::with_capacity(30 + self.name.len() + extra.len());
+        out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        self.common(&mut out, sizes, &extra);
+        out.extend_from_slice(self.name.as_bytes());
+        out.extend_from_slice(&extra);
+        out
+    }
+
+    /// The central directory header, for an entry with external attributes
+    /// `external` whose local header is at `offset`.
+    fn central(&self, external: u32, offset: u64) -> Vec<u8> {
+        // Only the fields that don't fit go in the zip64 extra field.
+        let mut values = Vec::new();
+        let mut fit = |value: u64| {
+            if value >= MAX_U32 {
+                values.push(value);
+                MAX_U32
+            } else {
+                value
+            }
+        };
+        let size = fit(self.size);
+        let compressed = fit(self.compressed);
+        let offset = fit(offset);
+        let extra = if values.is_empty() {
+            Vec::new()
+        } else {
+            zip64_extra(&values)
+        };
+
+        let mut out = Vec::with_capacity(46 + self.name.len() + extra.len());
+        out.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+        self.common(&mut out, [compressed, size], &extra);
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        out.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        out.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        out.extend_from_slice(&external.to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        out.extend_from_slice(self.name.as_bytes());
+        out.extend_from_slice(&extra);
+        out
+    }
+
+    /// Fields shared by the local and central headers, from "version
+    /// needed" through the extra field length.
+    fn common(&self, out: &mut Vec<u8>, [compressed, size]: [u64; 2], extra: &[u8]) {
+        let (time, date) = to_dos_datetime(self.modified);
+        let version = if extra.is_empty() {
+            VERSION
+        } else {
+            VERSION_ZIP64
+        };
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&self.method.to_le_bytes());
+        out.extend_from_slice(&time.to_le_bytes());
+        out.extend_from_slice(&date.to_le_bytes());
+        out.extend_from_slice(&self.crc.to_le_bytes());
+        out.extend_from_slice(&(compressed as u32).to_le_bytes());
+        out.extend_from_slice(&(size as u32).to_le_bytes());
+        out.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    }
+}
+
+/// A zip64 extended information extra field holding `values`.
+fn zip64_extra(values: &[u64]) -> Vec<u8> {
+    let mut extra = Vec::with_capacity(4 + 8 * values.len());
+    extra.extend_from_slice(&ZIP64_EXTRA.to_le_bytes());
+    extra.extend_from_slice(&(8 * values.len() as u16).to_le_bytes());
+    for value in values {
+        extra.extend_from_slice(&value.to_le_bytes());
+    }
+    extra
+}
+
+/// External attributes for `entry`: its Unix file type and mode.
+fn external_attributes(entry: &Entry) -> u32 {
+    let file_type = match entry.kind {
+        EntryKind::Directory => S_IFDIR,
+        EntryKind::Symlink => S_IFLNK,
+        EntryKind::File | EntryKind::Other => S_IFREG,
+    };
+    (file_type | (entry.mode & 0o7777)) << 16
+}
+
+/// An upper bound on the deflated size of `size` bytes, after zlib's
+/// `deflateBound`.
+fn deflate_bound(size: u64) -> u64 {
+    size.saturating_add((size >> 12) + (size >> 14) + (size >> 25) + 19)
+}
+
+impl<S: OutputStream> ZipWriter<S> {
+    /// Write an archive to `stream`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            offset: 0,
+            central: Vec::new(),
+            entries: 0,
+            finished: false,
+        }
+    }
+
+    /// Consume the writer, returning the stream. Call
+    /// [`finish`](ArchiveWriter::finish) first.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ArchiveError> {
+        self.stream.blocking_write(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// The name to record for `entry`, with directories ending in `/`.
+    fn name(&self, entry: &Entry) -> Result<String, ArchiveError> {
+        if self.finished {
+            return Err(ArchiveError::Other("archive already finished".to_string()));
+        }
+        let mut name = entry.path.clone();
+        if entry.kind == EntryKind::Directory && !name.ends_with('/') {
+            name.push('/');
+        }
+        if name.len() > u16::MAX as usize {
+            return Err(ArchiveError::Other("entry path too long".to_string()));
+        }
+        Ok(name)
+    }
+}
+
+impl<S: OutputStream> ArchiveWriter for ZipWriter<S> {
+    fn append_data(&mut self, entry: &Entry, data: &[u8]) -> Result<(), ArchiveError> {
+        let name = self.name(entry)?;
+        let data = match entry.kind {
+            EntryKind::Directory => &[][..],
+            EntryKind::Symlink => entry.link_target.as_deref().unwrap_or("").as_bytes(),
+            EntryKind::File | EntryKind::Other => data,
+        };
+
+        let crc = crc32fast::hash(data);
+        let (method, stored) = match deflate(data)? {
+            Some(compressed) => (METHOD_DEFLATED, compressed),
+            None => (METHOD_STORED, data.to_vec()),
+        };
+        let header = Header {
+            name: &name,
+            flags: FLAG_UTF8,
+            method,
+            modified: entry.modified.unwrap_or(0),
+            crc,
+            compressed: stored.len() as u64,
+            size: data.len() as u64,
+        };
+        let offset = self.offset;
+        let zip64 = header.compressed >= MAX_U32 || header.size >= MAX_U32;
+        self.write(&header.local(zip64))?;
+        self.write(&stored)?;
+
+        self.central
+            .extend_from_slice(&header.central(external_attributes(entry), offset));
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn append_stream<R: InputStream>(
+        &mut self,
+        entry: &Entry,
+        data: &mut R,
+    ) -> Result<(), ArchiveError> {
+        if !matches!(entry.kind, EntryKind::File | EntryKind::Other) {
+            return self.append_data(entry, &[]);
+        }
+        let name = self.name(entry)?;
+        let mut header = Header {
+            name: &name,
+            flags: FLAG_UTF8 | FLAG_DATA_DESCRIPTOR,
+            method: METHOD_DEFLATED,
+            modified: entry.modified.unwrap_or(0),
+            crc: 0,
+            compressed: 0,
+            size: 0,
+        };
+        // The compressed size isn't known yet, so zip64 is used whenever it
+        // could need to be.
+        let zip64 = deflate_bound(entry.size) >= MAX_U32;
+        let offset = self.offset;
+        self.write(&header.local(zip64))?;
+
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut crc = crc32fast::Hasher::new();
+        let mut input = vec![0u8; BUFFER_SIZE];
+        let mut output = vec![0u8; BUFFER_SIZE];
+        let mut remaining = entry.size;
+        loop {
+            let len = (input.len() as u64).min(remaining) as usize;
+            if read_full(data, &mut input[..len])? < len {
+                return Err(corrupt("entry data shorter than its size"));
+            }
+            crc.update(&input[..len]);
+            remaining -= len as u64;
+            let last = remaining == 0;
+            let flush = if last {
+                FlushCompress::Finish
+            } else {
+                FlushCompress::None
+            };
+
+            let mut consumed = 0;
+            loop {
+                let (before_in, before_out) = (compress.total_in(), compress.total_out());
+                let status = compress
+                    .compress(&input[consumed..len], &mut output, flush)
+                    .map_err(|e| ArchiveError::Other(format!("deflate failed: {}", e)))?;
+                consumed += (compress.total_in() - before_in) as usize;
+                let produced = (compress.total_out() - before_out) as usize;
+                self.write(&output[..produced])?;
+                if if last {
+                    status == Status::StreamEnd
+                } else {
+                    consumed == len
+                } {
+                    break;
+                }
+            }
+            if last {
+                break;
+            }
+        }
+
+        header.crc = crc.finalize();
+        header.compressed = compress.total_out();
+        header.size = compress.total_in();
+        let mut descriptor = Vec::with_capacity(24);
+        descriptor.extend_from_slice(&DATA_DESCRIPTOR.to_le_bytes());
+        descriptor.extend_from_slice(&header.crc.to_le_bytes());
+        if zip64 {
+            descriptor.extend_from_slice(&header.compressed.to_le_bytes());
+            descriptor.extend_from_slice(&header.size.to_le_bytes());
+        } else {
+            descriptor.extend_from_slice(&(header.compressed as u32).to_le_bytes());
+            descriptor.extend_from_slice(&(header.size as u32).to_le_bytes());
+        }
+        self.write(&descriptor)?;
+
+        self.central
+            .extend_from_slice(&header.central(external_attributes(entry), offset));
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ArchiveError> {
+        if !self.finished {
+            let central_offset = self.offset;
+            let central = std::mem::take(&mut self.central);
+            self.write(&central)?;
+            let central_len = central.len() as u64;
+
+            if self.entries >= MAX_U16 || central_len >= MAX_U32 || central_offset >= MAX_U32 {
+                let record_offset = self.offset;
+                let mut record = Vec::with_capacity(76);
+                record.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+                record.extend_from_slice(&44u64.to_le_bytes()); // size of the rest
+                record.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+                record.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+                record.extend_from_slice(&0u32.to_le_bytes()); // this disk
+                record.extend_from_slice(&0u32.to_le_bytes()); // central directory disk
+                record.extend_from_slice(&self.entries.to_le_bytes());
+                record.extend_from_slice(&self.entries.to_le_bytes());
+                record.extend_from_slice(&central_len.to_le_bytes());
+                record.extend_from_slice(&central_offset.to_le_bytes());
+                record.extend_from_slice(&ZIP64_END_LOCATOR.to_le_bytes());
+                record.extend_from_slice(&0u32.to_le_bytes()); // record disk
+                record.extend_from_slice(&record_offset.to_le_bytes());
+                record.extend_from_slice(&1u32.to_le_bytes()); // total disks
+                self.write(&record)?;
+            }
+
+            let entries = self.entries.min(MAX_U16) as u16;
+            let mut end = Vec::with_capacity(22);
+            end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+            end.extend_from_slice(&0u16.to_le_bytes()); // this disk
+            end.extend_from_slice(&0u16.to_le_bytes()); // central directory disk
+            end.extend_from_slice(&entries.to_le_bytes());
+            end.extend_from_slice(&entries.to_le_bytes());
+            end.extend_from_slice(&(central_len.min(MAX_U32) as u32).to_le_bytes());
+            end.extend_from_slice(&(central_offset.min(MAX_U32) as u32).to_le_bytes());
+            end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            self.write(&end)?;
+            self.finished = true;
+        }
+        self.stream.blocking_flush()?;
+        Ok(())
+    }
+}
+
+/// Open the zip archive at `path` in `dir` to append entries to it.
+///
+/// The central directory is read from the end of the archive, the archive
+/// is cut off where it starts, and the file is reopened for appending, so
+/// the entries already in it are kept without being copied. Call
+/// [`finish`](ArchiveWriter::finish) to write the central directory back
+/// with the new entries added. An archive comment is not kept.
+pub fn append_zip<D: Directory>(
+    dir: &D,
+    path: &Path,
+) -> Result<ZipWriter<impl OutputStream>, ArchiveError> {
+    let mut file = dir.open_read(path)?;
+    let len = file.stream_len()?;
+    // The end of central directory record is 22 bytes, followed by a
+    // comment of up to 65535.
+    let tail_start = len.saturating_sub(22 + MAX_U16);
+    file.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = vec![0u8; (len - tail_start) as usize];
+    read_exact(&mut file, &mut tail)?;
+    let end = (0..=tail.len().saturating_sub(22))
+        .rev()
+        .find(|&at| {
+            tail.len() >= at + 22
+                && u32_at(&tail, at) == END_OF_CENTRAL_DIRECTORY
+                && at + 22 + u16_at(&tail, at + 20) as usize == tail.len()
+        })
+        .ok_or_else(|| corrupt("no end of central directory record"))?;
+    let record = &tail[end..];
+    let mut entries = u16_at(record, 10) as u64;
+    let mut central_len = u32_at(record, 12) as u64;
+    let mut central_offset = u32_at(record, 16) as u64;
+
+    // Fields at their maximum may be in a zip64 record, found through the
+    // locator just before this one.
+    let end_offset = tail_start + end as u64;
+    if (entries == MAX_U16 || central_len == MAX_U32 || central_offset == MAX_U32)
+        && end_offset >= 20
+    {
+        file.seek(SeekFrom::Start(end_offset - 20))?;
+        let mut locator = [0u8; 20];
+        read_exact(&mut file, &mut locator)?;
+        if u32_at(&locator, 0) == ZIP64_END_LOCATOR {
+            file.seek(SeekFrom::Start(u64_at(&locator, 8)))?;
+            let mut record = [0u8; 56];
+            read_exact(&mut file, &mut record)?;
+            if u32_at(&record, 0) != ZIP64_END_OF_CENTRAL_DIRECTORY {
+                return Err(corrupt("invalid zip64 end of central directory record"));
+            }
+            entries = u64_at(&record, 32);
+            central_len = u64_at(&record, 40);
+            central_offset = u64_at(&record, 48);
+        }
+    }
+    if central_offset.saturating_add(central_len) > end_offset {
+        return Err(corrupt("central directory outside the archive"));
+    }
+
+    file.seek(SeekFrom::Start(central_offset))?;
+    let mut central = vec![0u8; central_len as usize];
+    read_exact(&mut file, &mut central)?;
+    drop(file);
+
+    dir.truncate(path, central_offset)?;
+    let mut writer = ZipWriter::new(dir.open_append(path)?);
+    writer.offset = central_offset;
+    writer.central = central;
+    writer.entries = entries;
+    Ok(writer)
+}
+
+/// `data` deflated, or `None` if that doesn't make it smaller.
+fn deflate(data: &[u8]) -> Result<Option<Vec<u8>>, ArchiveError> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .and_then(|()| encoder.finish())
+        .map(|compressed| (compressed.len() < data.len()).then_some(compressed))
+        .map_err(|e| ArchiveError::Other(format!("deflate failed: {}", e)))
+}
+
+/// MS-DOS time and date for seconds since the Unix epoch (UTC), clamped to
+/// the representable range 1980-2107. Seconds are rounded down to even.
+fn to_dos_datetime(secs: u64) -> (u16, u16) {
+    const MIN: u64 = 315_532_800; // 1980-01-01
+    let secs = secs.max(MIN);
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Civil from days, after Howard Hinnant's algorithm.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    if year > 2107 {
+        return (0xbf7d, 0xff9f); // 2107-12-31 23:59:58
+    }
+
+    let time = ((rem / 3600) << 11) | ((rem / 60 % 60) << 5) | ((rem % 60) / 2);
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+/// Seconds since the Unix epoch for an MS-DOS time and date, or `None` if
+/// the date is invalid.
+fn from_dos_datetime(time: u16, date: u16) -> Option<u64> {
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xf) as i64;
+    let day = (date & 0x1f) as i64;
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+
+    // Days from civil, after Howard Hinnant's algorithm.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let hour = (time >> 11) as i64;
+    let minute = ((time >> 5) & 0x3f) as i64;
+    let second = ((time & 0x1f) * 2) as i64;
+    Some((days * 86400 + hour * 3600 + minute * 60 + second) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_zip64_local_headers() {
+        let header = Header {
+            name: "big",
+            flags: FLAG_UTF8,
+            method: METHOD_STORED,
+            modified: 0,
+            crc: crc32fast::hash(b"hi"),
+            compressed: 2,
+            size: 2,
+        };
+        let mut bytes = header.local(true);
+        // The header's own size fields defer to the extra field.
+        assert_eq!(u32_at(&bytes, 18), u32::MAX);
+        bytes.extend_from_slice(b"hi");
+        bytes.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+
+        let mut reader = ZipReader::new(portals_io_native::ReaderStream::new(
+            std::io::Cursor::new(bytes),
+        ));
+        assert_eq!(reader.next_entry().unwrap().unwrap().size, 2);
+        assert_eq!(reader.read_to_end().unwrap(), b"hi");
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn central_headers_move_large_fields_to_zip64() {
+        let header = Header {
+            name: "big",
+            flags: FLAG_UTF8,
+            method: METHOD_DEFLATED,
+            modified: 0,
+            crc: 0,
+            compressed: 100,
+            size: 5 << 30,
+        };
+        let bytes = header.central(0, 6 << 30);
+        assert_eq!(u32_at(&bytes, 20), 100);
+        assert_eq!(u32_at(&bytes, 24), u32::MAX);
+        assert_eq!(u32_at(&bytes, 42), u32::MAX);
+        // Uncompressed size and offset, in that order.
+        let extra = extra_field(&bytes[46 + 3..], ZIP64_EXTRA).unwrap();
+        assert_eq!(extra.len(), 16);
+        assert_eq!(u64_at(extra, 0), 5 << 30);
+        assert_eq!(u64_at(extra, 8), 6 << 30);
+    }
+
+    #[test]
+    fn dos_datetime_round_trip() {
+        // 2023-11-14 22:13:20 UTC
+        let secs = 1_700_000_000;
+        let (time, date) = to_dos_datetime(secs);
+        assert_eq!(from_dos_datetime(time, date), Some(secs));
+
+        // Odd seconds round down; dates before 1980 clamp.
+        let (time, date) = to_dos_datetime(secs + 1);
+        assert_eq!(from_dos_datetime(time, date), Some(secs));
+        let (time, date) = to_dos_datetime(0);
+        assert_eq!(from_dos_datetime(time, date), Some(315_532_800));
+    }
+}
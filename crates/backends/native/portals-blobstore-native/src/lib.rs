@@ -3,9 +3,13 @@
 //! Provides `MemoryBlobStore` for creating and managing containers,
 //! and `MemoryContainer` which implements the `Container` trait.
 
-use portals_blobstore::{Container, Error, ObjectMeta};
+use portals_blobstore::{Container, Error, IntegrityError, ObjectMeta};
+use portals_clocks::MonotonicClock;
+use portals_crypto::Hash;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 /// In-memory blob storage.
 ///
@@ -14,13 +18,34 @@ use std::sync::{Arc, RwLock};
 #[derive(Debug, Default)]
 pub struct MemoryBlobStore {
     containers: RwLock<HashMap<String, Arc<MemoryContainer>>>,
+    /// Deadlines of temporary containers, in monotonic clock nanoseconds.
+    expiry: RwLock<HashMap<String, u64>>,
+    next_temp: AtomicU64,
+    checksum: Option<Digest>,
 }
 
+/// Computes an object checksum.
+type Digest = fn(&[u8]) -> Vec<u8>;
+
 impl MemoryBlobStore {
     /// Create a new empty blob store.
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new empty blob store that checksums objects with `H`.
+    ///
+    /// Containers compute the digest on `put`, report it in
+    /// [`ObjectMeta::checksum`], and verify it on `get`, failing with
+    /// [`Error::Integrity`] on a mismatch.
+    ///
+    /// ```ignore
+    /// let store = MemoryBlobStore::with_checksums::<Sha256>();
+    /// ```
+    pub fn with_checksums<H: Hash>() -> Self {
         Self {
-            containers: RwLock::new(HashMap::new()),
+            checksum: Some(H::hash),
+            ..Self::default()
         }
     }
 
@@ -33,10 +58,89 @@ impl MemoryBlobStore {
         if containers.contains_key(name) {
             return Err(Error::ContainerExists(name.to_string()));
         }
-        containers.insert(name.to_string(), Arc::new(MemoryContainer::new()));
+        containers.insert(
+            name.to_string(),
+            Arc::new(MemoryContainer::new(self.checksum)),
+        );
         Ok(())
     }
 
+    /// Create a temporary container that expires after `ttl`, returning
+    /// its generated name.
+    ///
+    /// Expired containers are deleted by the next cleanup pass, see
+    /// [`remove_expired`](Self::remove_expired) and
+    /// [`collect_expired`](Self::collect_expired); until then they remain
+    /// usable. Pass the same clock to all three.
+    ///
+    /// ```ignore
+    /// let scratch = store.create_temp_container(Duration::from_secs(3600), &clock)?;
+    /// let container = store.open_container(&scratch)?;
+    /// ```
+    pub fn create_temp_container(
+        &self,
+        ttl: Duration,
+        clock: &impl MonotonicClock,
+    ) -> Result<String, Error> {
+        let deadline = clock
+            .now()
+            .saturating_add(ttl.as_nanos().try_into().unwrap_or(u64::MAX));
+        let mut containers = self
+            .containers
+            .write()
+            .map_err(|e| Error::Store(e.to_string()))?;
+        let name = loop {
+            let name = format!("tmp-{}", self.next_temp.fetch_add(1, Ordering::Relaxed));
+            if !containers.contains_key(&name) {
+                break name;
+            }
+        };
+        let mut expiry = self
+            .expiry
+            .write()
+            .map_err(|e| Error::Store(e.to_string()))?;
+        containers.insert(name.clone(), Arc::new(MemoryContainer::new(self.checksum)));
+        expiry.insert(name.clone(), deadline);
+        Ok(name)
+    }
+
+    /// Delete temporary containers whose time is up, returning their names.
+    pub fn remove_expired(&self, clock: &impl MonotonicClock) -> Result<Vec<String>, Error> {
+        let now = clock.now();
+        let mut containers = self
+            .containers
+            .write()
+            .map_err(|e| Error::Store(e.to_string()))?;
+        let mut expiry = self
+            .expiry
+            .write()
+            .map_err(|e| Error::Store(e.to_string()))?;
+        let expired: Vec<String> = expiry
+            .iter()
+            .filter(|&(_, &deadline)| deadline <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &expired {
+            expiry.remove(name);
+            containers.remove(name);
+        }
+        Ok(expired)
+    }
+
+    /// Run [`remove_expired`](Self::remove_expired) every `interval`.
+    ///
+    /// Returns only if a pass fails; drop the future to stop.
+    pub async fn collect_expired(
+        &self,
+        clock: &impl MonotonicClock,
+        interval: Duration,
+    ) -> Result<(), Error> {
+        loop {
+            clock.subscribe_duration(interval).await;
+            self.remove_expired(clock)?;
+        }
+    }
+
     /// Delete a container.
     pub fn delete_container(&self, name: &str) -> Result<(), Error> {
         let mut containers = self
@@ -46,6 +150,10 @@ impl MemoryBlobStore {
         containers
             .remove(name)
             .ok_or_else(|| Error::ContainerNotFound(name.to_string()))?;
+        self.expiry
+            .write()
+            .map_err(|e| Error::Store(e.to_string()))?
+            .remove(name);
         Ok(())
     }
 
@@ -59,6 +167,7 @@ impl MemoryBlobStore {
             .get(name)
             .map(|c| MemoryContainer {
                 objects: c.objects.clone(),
+                checksum: c.checksum,
             })
             .ok_or_else(|| Error::ContainerNotFound(name.to_string()))
     }
@@ -87,19 +196,50 @@ impl MemoryBlobStore {
 struct StoredObject {
     data: Vec<u8>,
     created_at: u64,
+    checksum: Option<Vec<u8>>,
+}
+
+impl StoredObject {
+    fn meta(&self, name: &str) -> ObjectMeta {
+        ObjectMeta {
+            name: name.to_string(),
+            size: self.data.len() as u64,
+            created_at: Some(self.created_at),
+            checksum: self.checksum.clone(),
+        }
+    }
 }
 
 /// In-memory container.
 #[derive(Debug, Default)]
 pub struct MemoryContainer {
     objects: Arc<RwLock<HashMap<String, StoredObject>>>,
+    checksum: Option<Digest>,
 }
 
 impl MemoryContainer {
-    fn new() -> Self {
+    fn new(checksum: Option<Digest>) -> Self {
         Self {
             objects: Arc::new(RwLock::new(HashMap::new())),
+            checksum,
+        }
+    }
+
+    /// Check `obj` against its recorded checksum.
+    fn verify(&self, name: &str, obj: &StoredObject) -> Result<(), Error> {
+        let (Some(digest), Some(expected)) = (self.checksum, &obj.checksum) else {
+            return Ok(());
+        };
+        let actual = digest(&obj.data);
+        if actual != *expected {
+            return Err(IntegrityError {
+                name: name.to_string(),
+                expected: expected.clone(),
+                actual,
+            }
+            .into());
         }
+        Ok(())
     }
 
     fn now() -> u64 {
@@ -116,10 +256,11 @@ impl Container for MemoryContainer {
             .objects
             .read()
             .map_err(|e| Error::Store(e.to_string()))?;
-        objects
+        let obj = objects
             .get(name)
-            .map(|o| o.data.clone())
-            .ok_or_else(|| Error::ObjectNotFound(name.to_string()))
+            .ok_or_else(|| Error::ObjectNotFound(name.to_string()))?;
+        self.verify(name, obj)?;
+        Ok(obj.data.clone())
     }
 
     async fn put(&self, name: &str, data: &[u8]) -> Result<(), Error> {
@@ -132,6 +273,7 @@ impl Container for MemoryContainer {
             StoredObject {
                 data: data.to_vec(),
                 created_at: Self::now(),
+                checksum: self.checksum.map(|digest| digest(data)),
             },
         );
         Ok(())
@@ -161,14 +303,7 @@ impl Container for MemoryContainer {
             .objects
             .read()
             .map_err(|e| Error::Store(e.to_string()))?;
-        Ok(objects
-            .iter()
-            .map(|(name, obj)| ObjectMeta {
-                name: name.clone(),
-                size: obj.data.len() as u64,
-                created_at: Some(obj.created_at),
-            })
-            .collect())
+        Ok(objects.iter().map(|(name, obj)| obj.meta(name)).collect())
     }
 
     async fn metadata(&self, name: &str) -> Result<ObjectMeta, Error> {
@@ -178,11 +313,7 @@ impl Container for MemoryContainer {
             .map_err(|e| Error::Store(e.to_string()))?;
         objects
             .get(name)
-            .map(|obj| ObjectMeta {
-                name: name.to_string(),
-                size: obj.data.len() as u64,
-                created_at: Some(obj.created_at),
-            })
+            .map(|obj| obj.meta(name))
             .ok_or_else(|| Error::ObjectNotFound(name.to_string()))
     }
 
@@ -195,11 +326,13 @@ impl Container for MemoryContainer {
             .get(src)
             .ok_or_else(|| Error::ObjectNotFound(src.to_string()))?
             .clone();
+        self.verify(src, &src_obj)?;
         objects.insert(
             dst.to_string(),
             StoredObject {
                 data: src_obj.data,
                 created_at: Self::now(),
+                checksum: src_obj.checksum,
             },
         );
         Ok(())
@@ -209,6 +342,8 @@ impl Container for MemoryContainer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use portals_clocks_mock::MockMonotonicClock;
+    use portals_crypto_native::Sha256;
 
     #[tokio::test]
     async fn container_lifecycle() {
@@ -256,4 +391,85 @@ mod tests {
         container.copy("a.txt", "c.txt").await.unwrap();
         assert_eq!(container.get("c.txt").await.unwrap(), b"aaa");
     }
+
+    #[tokio::test]
+    async fn temp_containers_expire() {
+        let store = MemoryBlobStore::new();
+        let clock = MockMonotonicClock::new();
+        store.create_container("tmp-0").unwrap();
+
+        let short = store
+            .create_temp_container(Duration::from_secs(60), &clock)
+            .unwrap();
+        let long = store
+            .create_temp_container(Duration::from_secs(3600), &clock)
+            .unwrap();
+        assert_eq!(short, "tmp-1");
+        store
+            .open_container(&short)
+            .unwrap()
+            .put("scratch", b"data")
+            .await
+            .unwrap();
+
+        clock.advance(Duration::from_secs(59));
+        assert!(store.remove_expired(&clock).unwrap().is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(store.remove_expired(&clock).unwrap(), [short.as_str()]);
+        assert!(!store.container_exists(&short).unwrap());
+        assert!(store.container_exists(&long).unwrap());
+        assert!(store.container_exists("tmp-0").unwrap());
+
+        store.delete_container(&long).unwrap();
+        clock.advance(Duration::from_secs(3600));
+        assert!(store.remove_expired(&clock).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn checksums_detect_corruption() {
+        let store = MemoryBlobStore::with_checksums::<Sha256>();
+        store.create_container("bucket").unwrap();
+        let container = store.open_container("bucket").unwrap();
+
+        container.put("a.txt", b"hello").await.unwrap();
+        let meta = container.metadata("a.txt").await.unwrap();
+        assert_eq!(meta.checksum, Some(Sha256::hash(b"hello")));
+        assert_eq!(container.get("a.txt").await.unwrap(), b"hello");
+
+        container
+            .objects
+            .write()
+            .unwrap()
+            .get_mut("a.txt")
+            .unwrap()
+            .data[0] = b'j';
+        let Err(Error::Integrity(e)) = container.get("a.txt").await else {
+            panic!("corruption not detected");
+        };
+        assert_eq!(e.name, "a.txt");
+        assert_eq!(e.expected, Sha256::hash(b"hello"));
+        assert_eq!(e.actual, Sha256::hash(b"jello"));
+        assert!(matches!(
+            container.copy("a.txt", "b.txt").await,
+            Err(Error::Integrity(_))
+        ));
+
+        let plain = MemoryBlobStore::new();
+        plain.create_container("bucket").unwrap();
+        let container = plain.open_container("bucket").unwrap();
+        container.put("a.txt", b"hello").await.unwrap();
+        assert_eq!(container.metadata("a.txt").await.unwrap().checksum, None);
+    }
+
+    #[tokio::test]
+    async fn conformance() {
+        let store = MemoryBlobStore::new();
+        let next = std::cell::Cell::new(0);
+        portals_conformance::blobstore::run(|| {
+            let name = format!("conformance-{}", next.replace(next.get() + 1));
+            store.create_container(&name).unwrap();
+            store.open_container(&name).unwrap()
+        })
+        .await;
+    }
 }
@@ -0,0 +1,93 @@
+//! MemoryCache get/set benchmarks.
+//!
+//! Run with `cargo bench -p portals-cache-native`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use portals_cache::Cache;
+use portals_cache_native::MemoryCache;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn single_threaded(c: &mut Criterion) {
+    let cache = MemoryCache::new();
+    for i in 0..10_000 {
+        cache.set(&format!("key-{}", i), vec![0u8; 64]);
+    }
+
+    let mut i = 0u32;
+    c.bench_function("set (64 B)", |b| {
+        b.iter(|| {
+            i = (i + 1) % 10_000;
+            cache.set(&format!("key-{}", i), vec![0u8; 64]);
+        })
+    });
+
+    c.bench_function("get hit (64 B)", |b| {
+        b.iter(|| {
+            i = (i + 1) % 10_000;
+            cache.get(&format!("key-{}", i))
+        })
+    });
+
+    c.bench_function("get miss", |b| b.iter(|| cache.get(black_box("missing"))));
+
+    cache.set("large", vec![0u8; 1 << 20]);
+    c.bench_function("get hit (1 MiB)", |b| {
+        b.iter(|| cache.get(black_box("large")))
+    });
+
+    c.bench_function("get_shared hit (1 MiB)", |b| {
+        b.iter(|| cache.get_shared(black_box("large")))
+    });
+}
+
+/// Mixed load: each thread does 80% gets and 20% sets over a shared keyspace.
+fn concurrent(c: &mut Criterion) {
+    const THREADS: usize = 8;
+
+    for shards in [1, 16] {
+        let cache = Arc::new(MemoryCache::builder().shards(shards).build());
+        for i in 0..10_000 {
+            cache.set(&format!("key-{}", i), vec![0u8; 64]);
+        }
+
+        let name = format!("mixed {} threads shards={}", THREADS, shards);
+        c.bench_function(&name, |b| {
+            b.iter_custom(|iters| mixed_load(&cache, THREADS, iters))
+        });
+    }
+}
+
+/// Run `ops` operations spread over `threads` threads and return the
+/// wall-clock time taken.
+fn mixed_load(cache: &Arc<MemoryCache>, threads: usize, ops: u64) -> Duration {
+    let per_thread = ops.div_ceil(threads as u64);
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let cache = Arc::clone(cache);
+            std::thread::spawn(move || {
+                let mut key = String::new();
+                for i in 0..per_thread {
+                    let n = (i as usize * 7919 + t * 104_729) % 10_000;
+                    key.clear();
+                    key.push_str("key-");
+                    key.push_str(&n.to_string());
+                    if i % 5 == 0 {
+                        cache.set(&key, vec![0u8; 64]);
+                    } else {
+                        black_box(cache.get_shared(&key));
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+criterion_group!(benches, single_threaded, concurrent);
+criterion_main!(benches);
@@ -0,0 +1,482 @@
+//! Persistent cache backed by a directory capability.
+
+use portals_cache::{Cache, CacheStats, CacheWithStats};
+use portals_clocks::WallClock;
+use portals_filesystem::{Directory, FileType, InputStream, OutputStream, StreamError};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// File header magic.
+const MAGIC: &[u8; 4] = b"PCC1";
+/// Magic + expiry (u64) + key length (u32).
+const HEADER_LEN: usize = 16;
+const ENTRY_EXT: &str = "entry";
+const TEMP_EXT: &str = "tmp";
+
+/// A cache that writes every entry through to files in a directory.
+///
+/// An in-memory index of keys, sizes, and expiry times is rebuilt from the
+/// directory on [`DiskCache::open`], so entries survive restarts. When the
+/// total size of stored values exceeds the cap, least recently used entries
+/// are evicted.
+///
+/// TTLs are stored as absolute deadlines read from the [`WallClock`], so an
+/// entry set with a one-hour TTL expires an hour later even across restarts.
+///
+/// Each entry is one file named by a stable hash of its key; the key is
+/// stored in the file and checked on read. I/O failures are treated as
+/// misses (or dropped writes), matching the infallible [`Cache`] trait.
+pub struct DiskCache<D, C> {
+    dir: D,
+    clock: C,
+    max_bytes: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    index: HashMap<String, IndexEntry>,
+    total_bytes: u64,
+    /// Counter ordering uses, for LRU eviction.
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+struct IndexEntry {
+    file: PathBuf,
+    size: u64,
+    /// Milliseconds since the Unix epoch, if the entry expires.
+    expires_at: Option<u64>,
+    last_used: u64,
+}
+
+impl<D: Directory, C: WallClock> DiskCache<D, C> {
+    /// Open a cache in `dir`, loading any entries already there.
+    ///
+    /// `max_bytes` caps the total size of stored values, and `clock` decides
+    /// when entries expire. Expired, partial, or unreadable files found while
+    /// loading are removed.
+    pub fn open(dir: D, max_bytes: u64, clock: C) -> Result<Self, portals_filesystem::Error> {
+        let cache = Self {
+            dir,
+            clock,
+            max_bytes,
+            state: Mutex::new(State::default()),
+        };
+        cache.load()?;
+        Ok(cache)
+    }
+
+    /// Get the underlying directory capability.
+    pub fn dir(&self) -> &D {
+        &self.dir
+    }
+
+    /// Get the clock used for expiry.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Total size of stored values in bytes.
+    pub fn size_bytes(&self) -> u64 {
+        self.state.lock().unwrap().total_bytes
+    }
+
+    /// Current time in milliseconds since the Unix epoch.
+    fn now_millis(&self) -> u64 {
+        let (secs, nanos) = self.clock.now();
+        secs.saturating_mul(1000)
+            .saturating_add(u64::from(nanos / 1_000_000))
+    }
+
+    fn load(&self) -> Result<(), portals_filesystem::Error> {
+        let now = self.now_millis();
+        let mut state = self.state.lock().unwrap();
+        let mut loaded = Vec::new();
+
+        for entry in self.dir.read_dir(Path::new("."))? {
+            let entry = entry?;
+            if entry.file_type != FileType::Regular {
+                continue;
+            }
+            let file = PathBuf::from(&entry.name);
+            match file.extension().and_then(|e| e.to_str()) {
+                Some(ENTRY_EXT) => {}
+                Some(TEMP_EXT) => {
+                    let _ = self.dir.remove_file(&file);
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let header = self.read_header(&file);
+            let size = self.dir.metadata(&file).map(|m| m.size);
+            match (header, size) {
+                (Some((key, expires_at)), Ok(size)) if !is_expired(expires_at, now) => {
+                    let value_size = size.saturating_sub((HEADER_LEN + key.len()) as u64);
+                    loaded.push((key, file, value_size, expires_at));
+                }
+                _ => {
+                    let _ = self.dir.remove_file(&file);
+                }
+            }
+        }
+
+        for (key, file, size, expires_at) in loaded {
+            state.tick += 1;
+            let last_used = state.tick;
+            state.total_bytes += size;
+            state.index.insert(
+                key,
+                IndexEntry {
+                    file,
+                    size,
+                    expires_at,
+                    last_used,
+                },
+            );
+        }
+        self.evict(&mut state);
+        Ok(())
+    }
+
+    fn read_header(&self, file: &Path) -> Option<(String, Option<u64>)> {
+        let mut stream = self.dir.open_read(file).ok()?;
+        let header = read_exact(&mut stream, HEADER_LEN)?;
+        if &header[..4] != MAGIC {
+            return None;
+        }
+        let expires_at = u64::from_le_bytes(header[4..12].try_into().ok()?);
+        let key_len = u32::from_le_bytes(header[12..16].try_into().ok()?) as usize;
+        let key = String::from_utf8(read_exact(&mut stream, key_len)?).ok()?;
+        Some((key, (expires_at != 0).then_some(expires_at)))
+    }
+
+    fn read_value(&self, key: &str, file: &Path) -> Option<Vec<u8>> {
+        let mut stream = self.dir.open_read(file).ok()?;
+        let header = read_exact(&mut stream, HEADER_LEN + key.len())?;
+        if &header[..4] != MAGIC || &header[HEADER_LEN..] != key.as_bytes() {
+            return None;
+        }
+        read_to_end(&mut stream)
+    }
+
+    fn write_file(&self, key: &str, value: &[u8], expires_at: Option<u64>) -> Option<PathBuf> {
+        let name = format!("{:016x}", fnv1a(key.as_bytes()));
+        let file = PathBuf::from(format!("{}.{}", name, ENTRY_EXT));
+        let temp = PathBuf::from(format!("{}.{}", name, TEMP_EXT));
+
+        let mut header = Vec::with_capacity(HEADER_LEN + key.len());
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&expires_at.unwrap_or(0).to_le_bytes());
+        header.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        header.extend_from_slice(key.as_bytes());
+
+        let written = (|| {
+            let mut stream = self.dir.open_write(&temp).ok()?;
+            stream.blocking_write(&header).ok()?;
+            stream.blocking_write(value).ok()?;
+            stream.blocking_flush().ok()
+        })();
+        if written.is_none() {
+            let _ = self.dir.remove_file(&temp);
+            return None;
+        }
+        self.dir.rename(&temp, &file).ok()?;
+        Some(file)
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        let expires_at = ttl.map(|ttl| self.now_millis().saturating_add(ttl.as_millis() as u64));
+        let size = value.len() as u64;
+        if size > self.max_bytes {
+            self.delete(key);
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        // A different key hashing to the same file is displaced.
+        let Some(file) = self.write_file(key, &value, expires_at) else {
+            return;
+        };
+        let displaced: Vec<String> = state
+            .index
+            .iter()
+            .filter(|(k, e)| e.file == file && k.as_str() != key)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for k in displaced {
+            if let Some(old) = state.index.remove(&k) {
+                state.total_bytes -= old.size;
+            }
+        }
+
+        state.tick += 1;
+        let last_used = state.tick;
+        let old = state.index.insert(
+            key.to_string(),
+            IndexEntry {
+                file,
+                size,
+                expires_at,
+                last_used,
+            },
+        );
+        if let Some(old) = old {
+            state.total_bytes -= old.size;
+        }
+        state.total_bytes += size;
+        self.evict(&mut state);
+    }
+
+    /// Evict least recently used entries until under the size cap.
+    fn evict(&self, state: &mut State) {
+        while state.total_bytes > self.max_bytes {
+            let Some(key) = state
+                .index
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            self.remove(state, &key);
+        }
+    }
+
+    fn remove(&self, state: &mut State, key: &str) -> bool {
+        match state.index.remove(key) {
+            Some(entry) => {
+                state.total_bytes -= entry.size;
+                let _ = self.dir.remove_file(&entry.file);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<D: Directory, C: WallClock> Cache for DiskCache<D, C> {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let now = self.now_millis();
+
+        let file = match state.index.get(key) {
+            Some(entry) if is_expired(entry.expires_at, now) => {
+                self.remove(&mut state, key);
+                None
+            }
+            Some(entry) => Some(entry.file.clone()),
+            None => None,
+        };
+        let value = file.and_then(|file| self.read_value(key, &file));
+
+        match value {
+            Some(value) => {
+                state.tick += 1;
+                let tick = state.tick;
+                if let Some(entry) = state.index.get_mut(key) {
+                    entry.last_used = tick;
+                }
+                state.hits += 1;
+                Some(value)
+            }
+            None => {
+                // Drop index entries whose file has gone missing or is corrupt.
+                self.remove(&mut state, key);
+                state.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>) {
+        self.insert(key, value, None);
+    }
+
+    fn set_with_ttl(&self, key: &str, value: Vec<u8>, ttl: Duration) {
+        self.insert(key, value, Some(ttl));
+    }
+
+    fn delete(&self, key: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.remove(&mut state, key)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.index.get(key) {
+            Some(entry) if is_expired(entry.expires_at, self.now_millis()) => {
+                self.remove(&mut state, key);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        for (_, entry) in state.index.drain() {
+            let _ = self.dir.remove_file(&entry.file);
+        }
+        state.total_bytes = 0;
+    }
+}
+
+impl<D: Directory, C: WallClock> CacheWithStats for DiskCache<D, C> {
+    fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries: state.index.len(),
+            size_bytes: state.total_bytes as usize,
+        }
+    }
+
+    fn reset_stats(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.hits = 0;
+        state.misses = 0;
+    }
+}
+
+fn is_expired(expires_at: Option<u64>, now: u64) -> bool {
+    expires_at.is_some_and(|at| now > at)
+}
+
+/// FNV-1a, used for file names because it is stable across builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn read_exact(stream: &mut impl InputStream, len: usize) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    let mut filled = 0;
+    while filled < len {
+        match stream.blocking_read_into(&mut buf[filled..]) {
+            Ok(n) => filled += n,
+            Err(_) => return None,
+        }
+    }
+    Some(buf)
+}
+
+fn read_to_end(stream: &mut impl InputStream) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        match stream.blocking_read_into(&mut buf) {
+            Ok(n) => out.extend_from_slice(&buf[..n]),
+            Err(StreamError::Closed) => return Some(out),
+            Err(_) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_clocks_mock::MockWallClock;
+    use portals_filesystem_native::NativeDir;
+    use std::fs;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("portals-disk-cache-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn set_get_delete() {
+        let path = temp_dir("basic");
+        let cache =
+            DiskCache::open(NativeDir::new(&path), 1024, MockWallClock::new(NOW, 0)).unwrap();
+
+        cache.set("key", b"value".to_vec());
+        assert_eq!(cache.get("key"), Some(b"value".to_vec()));
+        assert!(cache.exists("key"));
+
+        assert!(cache.delete("key"));
+        assert_eq!(cache.get("key"), None);
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn expires_entries() {
+        let path = temp_dir("ttl");
+        let clock = MockWallClock::new(NOW, 0);
+        let cache = DiskCache::open(NativeDir::new(&path), 1024, clock.clone()).unwrap();
+
+        cache.set_with_ttl("key", b"value".to_vec(), Duration::from_secs(60));
+        clock.advance(Duration::from_secs(60));
+        assert!(cache.exists("key"));
+        clock.advance(Duration::from_millis(1));
+        assert!(!cache.exists("key"));
+        assert_eq!(cache.get("key"), None);
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn survives_reopen() {
+        let path = temp_dir("reopen");
+        let clock = MockWallClock::new(NOW, 0);
+        {
+            let cache = DiskCache::open(NativeDir::new(&path), 1024, clock.clone()).unwrap();
+            cache.set("persistent", b"hello".to_vec());
+            cache.set_with_ttl("short", b"gone".to_vec(), Duration::from_secs(1));
+            cache.set_with_ttl("long", b"kept".to_vec(), Duration::from_secs(3600));
+        }
+        clock.advance(Duration::from_secs(10));
+
+        let cache = DiskCache::open(NativeDir::new(&path), 1024, clock).unwrap();
+        assert_eq!(cache.get("persistent"), Some(b"hello".to_vec()));
+        assert_eq!(cache.get("long"), Some(b"kept".to_vec()));
+        assert_eq!(cache.get("short"), None);
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.size_bytes(), 9);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let path = temp_dir("evict");
+        let cache = DiskCache::open(NativeDir::new(&path), 10, MockWallClock::new(NOW, 0)).unwrap();
+
+        cache.set("a", vec![0; 4]);
+        cache.set("b", vec![0; 4]);
+        cache.get("a");
+        cache.set("c", vec![0; 4]);
+
+        assert!(cache.exists("a"));
+        assert!(!cache.exists("b"));
+        assert!(cache.exists("c"));
+        assert_eq!(cache.size_bytes(), 8);
+
+        cache.set("huge", vec![0; 11]);
+        assert!(!cache.exists("huge"));
+
+        cache.clear();
+        assert_eq!(cache.size_bytes(), 0);
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -1,21 +1,38 @@
-//! Native in-memory cache implementation.
+//! Native cache implementations.
+//!
+//! [`MemoryCache`] keeps entries in memory; [`DiskCache`] writes them through
+//! to a directory capability so they survive restarts.
 
+mod disk;
+
+pub use disk::DiskCache;
 use portals_cache::{Cache, CacheEntry, CacheStats, CacheWithStats};
+use portals_clocks::MonotonicClock;
+use portals_clocks_native::StdMonotonicClock;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::RwLock;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 /// Thread-safe in-memory cache.
-pub struct MemoryCache {
-    entries: RwLock<HashMap<String, Entry>>,
-    start_time: Instant,
+///
+/// Values are stored as `Arc<[u8]>`, so [`MemoryCache::get_shared`] hands out
+/// a reference-counted view without copying the bytes.
+///
+/// Entries are split across one or more shards, each behind its own lock;
+/// see [`MemoryCacheBuilder::shards`]. TTLs are measured with a
+/// [`MonotonicClock`]; see [`MemoryCacheBuilder::clock`].
+pub struct MemoryCache<C = StdMonotonicClock> {
+    shards: Box<[RwLock<HashMap<String, Entry>>]>,
+    hasher: RandomState,
+    clock: C,
     hits: AtomicU64,
     misses: AtomicU64,
 }
 
 struct Entry {
-    value: Vec<u8>,
+    value: Arc<[u8]>,
     created_at: Duration,
     ttl: Option<Duration>,
 }
@@ -31,7 +48,7 @@ impl Entry {
 
     fn to_cache_entry(&self) -> CacheEntry {
         CacheEntry {
-            value: self.value.clone(),
+            value: self.value.to_vec(),
             created_at: self.created_at,
             ttl: self.ttl,
         }
@@ -39,36 +56,76 @@ impl Entry {
 }
 
 impl MemoryCache {
-    /// Create a new empty cache.
+    /// Create a new empty cache with a single shard.
     pub fn new() -> Self {
-        Self {
-            entries: RwLock::new(HashMap::new()),
-            start_time: Instant::now(),
-            hits: AtomicU64::new(0),
-            misses: AtomicU64::new(0),
+        Self::builder().build()
+    }
+
+    /// Configure a new cache.
+    pub fn builder() -> MemoryCacheBuilder {
+        MemoryCacheBuilder::default()
+    }
+}
+
+impl<C: MonotonicClock> MemoryCache<C> {
+    /// Number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard holding `key`.
+    fn shard(&self, key: &str) -> &RwLock<HashMap<String, Entry>> {
+        if self.shards.len() == 1 {
+            return &self.shards[0];
         }
+        let hash = self.hasher.hash_one(key);
+        &self.shards[(hash % self.shards.len() as u64) as usize]
     }
 
-    /// Get the current time since cache creation.
+    /// Get the current time on the cache's clock.
     fn now(&self) -> Duration {
-        self.start_time.elapsed()
+        Duration::from_nanos(self.clock.now())
     }
 
     /// Get entry with metadata.
     pub fn get_entry(&self, key: &str) -> Option<CacheEntry> {
+        self.lookup(key, Entry::to_cache_entry)
+    }
+
+    /// Get a value without copying it.
+    ///
+    /// Returns a shared reference to the stored bytes; cheap even for large
+    /// values.
+    pub fn get_shared(&self, key: &str) -> Option<Arc<[u8]>> {
+        self.lookup(key, |entry| Arc::clone(&entry.value))
+    }
+
+    /// Store a value that is already shared, without copying it.
+    pub fn set_shared(&self, key: &str, value: Arc<[u8]>) {
+        self.insert(key, value, None);
+    }
+
+    /// Store a shared value with a time-to-live.
+    pub fn set_shared_with_ttl(&self, key: &str, value: Arc<[u8]>, ttl: Duration) {
+        self.insert(key, value, Some(ttl));
+    }
+
+    /// Look up a live entry, counting the hit or miss.
+    fn lookup<T>(&self, key: &str, f: impl FnOnce(&Entry) -> T) -> Option<T> {
         let now = self.now();
-        let entries = self.entries.read().unwrap();
+        let shard = self.shard(key);
+        let entries = shard.read().unwrap();
 
         if let Some(entry) = entries.get(key) {
             if entry.is_expired(now) {
                 drop(entries);
                 // Remove expired entry
-                self.entries.write().unwrap().remove(key);
+                shard.write().unwrap().remove(key);
                 self.misses.fetch_add(1, Ordering::Relaxed);
                 None
             } else {
                 self.hits.fetch_add(1, Ordering::Relaxed);
-                Some(entry.to_cache_entry())
+                Some(f(entry))
             }
         } else {
             self.misses.fetch_add(1, Ordering::Relaxed);
@@ -76,11 +133,26 @@ impl MemoryCache {
         }
     }
 
+    fn insert(&self, key: &str, value: Arc<[u8]>, ttl: Option<Duration>) {
+        let now = self.now();
+        let mut entries = self.shard(key).write().unwrap();
+        entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                created_at: now,
+                ttl,
+            },
+        );
+    }
+
     /// Remove expired entries.
     pub fn cleanup(&self) {
         let now = self.now();
-        let mut entries = self.entries.write().unwrap();
-        entries.retain(|_, entry| !entry.is_expired(now));
+        for shard in &self.shards {
+            let mut entries = shard.write().unwrap();
+            entries.retain(|_, entry| !entry.is_expired(now));
+        }
     }
 }
 
@@ -90,49 +162,81 @@ impl Default for MemoryCache {
     }
 }
 
-impl Cache for MemoryCache {
+/// Builder for [`MemoryCache`].
+#[derive(Debug, Clone)]
+pub struct MemoryCacheBuilder<C = StdMonotonicClock> {
+    shards: usize,
+    clock: C,
+}
+
+impl Default for MemoryCacheBuilder {
+    fn default() -> Self {
+        Self {
+            shards: 1,
+            clock: StdMonotonicClock::new(),
+        }
+    }
+}
+
+impl<C: MonotonicClock> MemoryCacheBuilder<C> {
+    /// Split entries across `n` independently locked shards (minimum 1).
+    ///
+    /// A single shard serializes all writers behind one lock. Under
+    /// concurrent mixed read/write load, a shard count around the number of
+    /// threads touching the cache removes most contention.
+    pub fn shards(mut self, n: usize) -> Self {
+        self.shards = n.max(1);
+        self
+    }
+
+    /// Measure TTLs with `clock` instead of the system monotonic clock.
+    pub fn clock<T: MonotonicClock>(self, clock: T) -> MemoryCacheBuilder<T> {
+        MemoryCacheBuilder {
+            shards: self.shards,
+            clock,
+        }
+    }
+
+    /// Build the cache.
+    pub fn build(self) -> MemoryCache<C> {
+        MemoryCache {
+            shards: (0..self.shards)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            hasher: RandomState::new(),
+            clock: self.clock,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<C: MonotonicClock> Cache for MemoryCache<C> {
     fn get(&self, key: &str) -> Option<Vec<u8>> {
-        self.get_entry(key).map(|e| e.value)
+        self.lookup(key, |entry| entry.value.to_vec())
     }
 
     fn set(&self, key: &str, value: Vec<u8>) {
-        let now = self.now();
-        let mut entries = self.entries.write().unwrap();
-        entries.insert(
-            key.to_string(),
-            Entry {
-                value,
-                created_at: now,
-                ttl: None,
-            },
-        );
+        self.insert(key, value.into(), None);
     }
 
     fn set_with_ttl(&self, key: &str, value: Vec<u8>, ttl: Duration) {
-        let now = self.now();
-        let mut entries = self.entries.write().unwrap();
-        entries.insert(
-            key.to_string(),
-            Entry {
-                value,
-                created_at: now,
-                ttl: Some(ttl),
-            },
-        );
+        self.insert(key, value.into(), Some(ttl));
     }
 
     fn delete(&self, key: &str) -> bool {
-        self.entries.write().unwrap().remove(key).is_some()
+        self.shard(key).write().unwrap().remove(key).is_some()
     }
 
     fn exists(&self, key: &str) -> bool {
         let now = self.now();
-        let entries = self.entries.read().unwrap();
+        let shard = self.shard(key);
+        let entries = shard.read().unwrap();
 
         if let Some(entry) = entries.get(key) {
             if entry.is_expired(now) {
                 drop(entries);
-                self.entries.write().unwrap().remove(key);
+                shard.write().unwrap().remove(key);
                 false
             } else {
                 true
@@ -143,19 +247,26 @@ impl Cache for MemoryCache {
     }
 
     fn clear(&self) {
-        self.entries.write().unwrap().clear();
+        for shard in &self.shards {
+            shard.write().unwrap().clear();
+        }
     }
 }
 
-impl CacheWithStats for MemoryCache {
+impl<C: MonotonicClock> CacheWithStats for MemoryCache<C> {
     fn stats(&self) -> CacheStats {
-        let entries = self.entries.read().unwrap();
-        let size_bytes: usize = entries.values().map(|e| e.value.len()).sum();
+        let mut entries = 0;
+        let mut size_bytes = 0;
+        for shard in &self.shards {
+            let shard = shard.read().unwrap();
+            entries += shard.len();
+            size_bytes += shard.values().map(|e| e.value.len()).sum::<usize>();
+        }
 
         CacheStats {
             hits: self.hits.load(Ordering::Relaxed),
             misses: self.misses.load(Ordering::Relaxed),
-            entries: entries.len(),
+            entries,
             size_bytes,
         }
     }
@@ -169,6 +280,7 @@ impl CacheWithStats for MemoryCache {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use portals_clocks_mock::MockMonotonicClock;
     use std::thread;
 
     #[test]
@@ -213,14 +325,14 @@ mod tests {
 
     #[test]
     fn ttl_expiration() {
-        let cache = MemoryCache::new();
+        let clock = MockMonotonicClock::new();
+        let cache = MemoryCache::builder().clock(clock.clone()).build();
         cache.set_with_ttl("key", b"value".to_vec(), Duration::from_millis(50));
 
-        // Should exist immediately
+        // Should exist until the TTL has passed
+        clock.advance(Duration::from_millis(50));
         assert!(cache.exists("key"));
-
-        // Wait for expiration
-        thread::sleep(Duration::from_millis(100));
+        clock.advance(Duration::from_millis(1));
 
         // Should be gone
         assert!(!cache.exists("key"));
@@ -270,11 +382,12 @@ mod tests {
 
     #[test]
     fn cleanup() {
-        let cache = MemoryCache::new();
+        let clock = MockMonotonicClock::new();
+        let cache = MemoryCache::builder().clock(clock.clone()).build();
         cache.set_with_ttl("a", b"1".to_vec(), Duration::from_millis(10));
         cache.set("b", b"2".to_vec());
 
-        thread::sleep(Duration::from_millis(50));
+        clock.advance(Duration::from_millis(50));
         cache.cleanup();
 
         let stats = cache.stats();
@@ -306,4 +419,51 @@ mod tests {
         let stats = cache.stats();
         assert_eq!(stats.entries, 10);
     }
+
+    #[test]
+    fn shared_values() {
+        let cache = MemoryCache::new();
+        cache.set("key", b"value".to_vec());
+
+        let a = cache.get_shared("key").unwrap();
+        let b = cache.get_shared("key").unwrap();
+        assert_eq!(&*a, b"value");
+        assert!(Arc::ptr_eq(&a, &b));
+
+        let shared: Arc<[u8]> = Arc::from(&b"shared"[..]);
+        cache.set_shared("other", Arc::clone(&shared));
+        assert!(Arc::ptr_eq(&cache.get_shared("other").unwrap(), &shared));
+        assert_eq!(cache.get("other"), Some(b"shared".to_vec()));
+
+        assert!(cache.get_shared("missing").is_none());
+        assert_eq!(cache.stats().hits, 4);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn sharded_cache() {
+        let cache = MemoryCache::builder().shards(8).build();
+        assert_eq!(cache.shard_count(), 8);
+
+        for i in 0..100 {
+            cache.set(&format!("key-{}", i), vec![i as u8]);
+        }
+        for i in 0..100 {
+            assert_eq!(cache.get(&format!("key-{}", i)), Some(vec![i as u8]));
+        }
+        assert_eq!(cache.stats().entries, 100);
+        assert_eq!(cache.stats().size_bytes, 100);
+
+        assert!(cache.delete("key-5"));
+        assert!(!cache.exists("key-5"));
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(MemoryCache::builder().shards(0).build().shard_count(), 1);
+    }
+
+    #[test]
+    fn conformance() {
+        portals_conformance::cache::run(MemoryCache::new);
+    }
 }
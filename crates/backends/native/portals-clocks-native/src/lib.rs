@@ -1,5 +1,9 @@
 //! Native implementation of portals-clocks.
 
+mod sntp;
+
+pub use sntp::{AdjustedClock, NTP_POOL, NTP_PORT, SntpClient, SntpError, SntpSample};
+
 use portals_clocks::{MonotonicClock, WallClock};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
@@ -0,0 +1,502 @@
+//! SNTP client (RFC 4330) for checking the wall clock against time servers.
+
+use portals_clocks::{MonotonicClock, WallClock};
+use portals_sockets::{Resolver, UdpSocket};
+use std::future::poll_fn;
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::task::Poll;
+use std::time::Duration;
+
+/// The public NTP pool.
+pub const NTP_POOL: &str = "pool.ntp.org";
+
+/// The NTP port.
+pub const NTP_PORT: u16 = 123;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const NTP_UNIX_OFFSET: i128 = 2_208_988_800;
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// Size of an NTP packet without extensions.
+const PACKET_LEN: usize = 48;
+
+/// Most pool addresses queried by [`SntpClient::query_pool`].
+const MAX_POOL_SERVERS: usize = 4;
+
+/// SNTP errors.
+#[derive(Debug)]
+pub enum SntpError {
+    /// No reply arrived before the timeout.
+    Timeout,
+    /// The reply was malformed, didn't answer our request, or came from
+    /// an unsynchronized server.
+    InvalidResponse(&'static str),
+    /// The server asked us to go away, with the given kiss code (such as
+    /// `RATE` or `DENY`).
+    KissOfDeath(String),
+    /// The pool name resolved to no addresses.
+    NoServers,
+    Socket(portals_sockets::Error),
+}
+
+impl std::fmt::Display for SntpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timed out waiting for time server"),
+            Self::InvalidResponse(reason) => write!(f, "invalid SNTP response: {}", reason),
+            Self::KissOfDeath(code) => write!(f, "time server refused service: {}", code),
+            Self::NoServers => write!(f, "no time servers found"),
+            Self::Socket(e) => write!(f, "socket error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SntpError {}
+
+impl From<portals_sockets::Error> for SntpError {
+    fn from(e: portals_sockets::Error) -> Self {
+        Self::Socket(e)
+    }
+}
+
+/// The outcome of one exchange with a time server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SntpSample {
+    pub server: SocketAddr,
+    /// Nanoseconds to add to the local wall clock to match the server.
+    pub offset_nanos: i64,
+    /// Time spent on the network, excluding the server's processing.
+    pub round_trip: Duration,
+    /// The server's distance from a reference clock; 1 is a primary server.
+    pub stratum: u8,
+}
+
+/// Queries time servers with SNTP.
+///
+/// Timestamps come from `clock`, the wall clock being checked; `timer`
+/// bounds how long each query waits for a reply (two seconds by default).
+///
+/// ```ignore
+/// let mut socket = NativeUdpSocket::bind("0.0.0.0:0".parse()?)?;
+/// let client = SntpClient::new(SystemClock, StdMonotonicClock::new());
+/// let sample = client.query_pool(&mut socket, &NativeResolver, NTP_POOL).await?;
+/// let clock = AdjustedClock::new(SystemClock).with_offset(sample.offset_nanos);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SntpClient<C, M> {
+    clock: C,
+    timer: M,
+    timeout: Duration,
+}
+
+impl<C: WallClock, M: MonotonicClock> SntpClient<C, M> {
+    /// Create a client reading `clock` and timing out with `timer`.
+    pub fn new(clock: C, timer: M) -> Self {
+        Self {
+            clock,
+            timer,
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Wait up to `timeout` for each reply.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Query one server.
+    ///
+    /// Datagrams from other addresses, or that don't answer this request,
+    /// are ignored while waiting.
+    pub async fn query(
+        &self,
+        socket: &mut impl UdpSocket,
+        server: SocketAddr,
+    ) -> Result<SntpSample, SntpError> {
+        let mut request = [0u8; PACKET_LEN];
+        // Leap indicator 0, version 4, mode 3 (client).
+        request[0] = (4 << 3) | 3;
+        let sent = self.now();
+        let transmit = to_ntp(sent);
+        request[40..48].copy_from_slice(&transmit.to_be_bytes());
+        socket.send_to(&request, server).await?;
+
+        let mut timeout = pin!(self.timer.subscribe_duration(self.timeout));
+        let mut buf = [0u8; 1024];
+        loop {
+            let received = {
+                let mut recv = pin!(socket.recv_from(&mut buf));
+                poll_fn(|cx| match recv.as_mut().poll(cx) {
+                    Poll::Ready(result) => Poll::Ready(Some(result)),
+                    Poll::Pending => timeout.as_mut().poll(cx).map(|()| None),
+                })
+                .await
+            };
+            let Some(received) = received else {
+                return Err(SntpError::Timeout);
+            };
+            let (n, from) = received?;
+            let arrived = self.now();
+            if from != server || n < PACKET_LEN || buf[24..32] != transmit.to_be_bytes() {
+                continue;
+            }
+            return parse_reply(&buf[..n], server, sent, arrived);
+        }
+    }
+
+    /// Resolve `pool` and query up to four of its addresses, returning the
+    /// sample with the shortest round trip.
+    ///
+    /// Fails with the last error if no server answers.
+    pub async fn query_pool(
+        &self,
+        socket: &mut impl UdpSocket,
+        resolver: &impl Resolver,
+        pool: &str,
+    ) -> Result<SntpSample, SntpError> {
+        let addrs = resolver.resolve(pool).await?;
+        let mut best: Option<SntpSample> = None;
+        let mut last_error = SntpError::NoServers;
+        for addr in addrs.into_iter().take(MAX_POOL_SERVERS) {
+            match self.query(socket, SocketAddr::new(addr, NTP_PORT)).await {
+                Ok(sample) if best.is_none_or(|b| sample.round_trip < b.round_trip) => {
+                    best = Some(sample);
+                }
+                Ok(_) => {}
+                Err(e) => last_error = e,
+            }
+        }
+        best.ok_or(last_error)
+    }
+
+    /// The wall clock in nanoseconds since the Unix epoch.
+    fn now(&self) -> i128 {
+        let (secs, nanos) = self.clock.now();
+        secs as i128 * NANOS_PER_SEC + nanos as i128
+    }
+}
+
+/// Check a server's reply and compute the sample from its timestamps and
+/// ours (`sent` and `arrived`, in Unix nanoseconds).
+fn parse_reply(
+    reply: &[u8],
+    server: SocketAddr,
+    sent: i128,
+    arrived: i128,
+) -> Result<SntpSample, SntpError> {
+    let leap = reply[0] >> 6;
+    let version = (reply[0] >> 3) & 0b111;
+    let mode = reply[0] & 0b111;
+    let stratum = reply[1];
+    if mode != 4 {
+        return Err(SntpError::InvalidResponse("not a server reply"));
+    }
+    if !(1..=4).contains(&version) {
+        return Err(SntpError::InvalidResponse("unsupported version"));
+    }
+    if stratum == 0 {
+        let code = String::from_utf8_lossy(&reply[12..16]).into_owned();
+        return Err(SntpError::KissOfDeath(code));
+    }
+    if leap == 3 || stratum > 15 {
+        return Err(SntpError::InvalidResponse("server is not synchronized"));
+    }
+    let timestamp = |at: usize| u64::from_be_bytes(reply[at..at + 8].try_into().unwrap());
+    let (received, transmitted) = (timestamp(32), timestamp(40));
+    if transmitted == 0 {
+        return Err(SntpError::InvalidResponse("missing transmit timestamp"));
+    }
+    let (received, transmitted) = (from_ntp(received), from_ntp(transmitted));
+
+    let offset = ((received - sent) + (transmitted - arrived)) / 2;
+    let round_trip = (arrived - sent) - (transmitted - received);
+    Ok(SntpSample {
+        server,
+        offset_nanos: offset.clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+        round_trip: Duration::from_nanos(round_trip.clamp(0, u64::MAX as i128) as u64),
+        stratum,
+    })
+}
+
+/// Unix nanoseconds to an NTP timestamp: 32 bits of seconds since 1900
+/// and 32 bits of fraction.
+fn to_ntp(nanos: i128) -> u64 {
+    let secs = nanos.div_euclid(NANOS_PER_SEC) + NTP_UNIX_OFFSET;
+    let fraction = (nanos.rem_euclid(NANOS_PER_SEC) << 32) / NANOS_PER_SEC;
+    ((secs as u64) << 32) | fraction as u64
+}
+
+/// An NTP timestamp to Unix nanoseconds.
+///
+/// Timestamps wrap in 2036; those with the top bit clear are read as
+/// belonging to the era after, per RFC 4330 section 3.
+fn from_ntp(timestamp: u64) -> i128 {
+    let mut secs = (timestamp >> 32) as i128;
+    if secs & 0x8000_0000 == 0 {
+        secs += 1 << 32;
+    }
+    let fraction = (timestamp & 0xffff_ffff) as i128;
+    (secs - NTP_UNIX_OFFSET) * NANOS_PER_SEC + ((fraction * NANOS_PER_SEC) >> 32)
+}
+
+/// A wall clock corrected by an offset, such as one measured by
+/// [`SntpClient`].
+///
+/// Clones share the offset, so a task that periodically resyncs can
+/// update it for every holder.
+#[derive(Debug, Clone)]
+pub struct AdjustedClock<C> {
+    inner: C,
+    offset_nanos: Arc<AtomicI64>,
+}
+
+impl<C: WallClock> AdjustedClock<C> {
+    /// Wrap `inner` with no offset.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            offset_nanos: Arc::new(AtomicI64::new(0)),
+        }
+    }
+
+    /// Start with an offset of `nanos`.
+    pub fn with_offset(self, nanos: i64) -> Self {
+        self.set_offset(nanos);
+        self
+    }
+
+    /// Change the offset, for this clock and its clones.
+    pub fn set_offset(&self, nanos: i64) {
+        self.offset_nanos.store(nanos, Ordering::Relaxed);
+    }
+
+    /// The offset added to the inner clock, in nanoseconds.
+    pub fn offset(&self) -> i64 {
+        self.offset_nanos.load(Ordering::Relaxed)
+    }
+}
+
+impl<C: WallClock> WallClock for AdjustedClock<C> {
+    /// The inner clock's time plus the offset, never before the epoch.
+    fn now(&self) -> (u64, u32) {
+        let (secs, nanos) = self.inner.now();
+        let adjusted =
+            (secs as i128 * NANOS_PER_SEC + nanos as i128 + self.offset() as i128).max(0);
+        (
+            (adjusted / NANOS_PER_SEC) as u64,
+            (adjusted % NANOS_PER_SEC) as u32,
+        )
+    }
+
+    fn resolution(&self) -> (u64, u32) {
+        self.inner.resolution()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_sockets::Error;
+    use std::cell::Cell;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    /// A wall clock that reads `now` and then advances by `step`.
+    struct SteppingClock {
+        now: Cell<i128>,
+        step: i128,
+    }
+
+    impl WallClock for SteppingClock {
+        fn now(&self) -> (u64, u32) {
+            let now = self.now.get();
+            self.now.set(now + self.step);
+            ((now / NANOS_PER_SEC) as u64, (now % NANOS_PER_SEC) as u32)
+        }
+
+        fn resolution(&self) -> (u64, u32) {
+            (0, 1)
+        }
+    }
+
+    /// A timer that never fires, or fires at once.
+    struct Timer(bool);
+
+    impl MonotonicClock for Timer {
+        fn now(&self) -> u64 {
+            0
+        }
+
+        fn resolution(&self) -> u64 {
+            1
+        }
+
+        async fn subscribe_duration(&self, _duration: Duration) {
+            if !self.0 {
+                std::future::pending::<()>().await;
+            }
+        }
+
+        async fn subscribe_instant(&self, _instant: u64) {}
+    }
+
+    /// A socket whose peer answers each request like a server whose clock
+    /// is `offset` ahead and takes `processing` to reply.
+    struct FakeServer {
+        offset: i128,
+        processing: i128,
+        stratum: u8,
+        /// Datagrams to deliver before the real reply.
+        noise: Vec<(Vec<u8>, SocketAddr)>,
+        reply: Option<(Vec<u8>, SocketAddr)>,
+    }
+
+    impl FakeServer {
+        fn new(offset: i128) -> Self {
+            Self {
+                offset,
+                processing: 1_000_000,
+                stratum: 2,
+                noise: Vec::new(),
+                reply: None,
+            }
+        }
+    }
+
+    impl UdpSocket for FakeServer {
+        async fn send_to(&self, buf: &[u8], _addr: SocketAddr) -> Result<usize, Error> {
+            Ok(buf.len())
+        }
+
+        async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+            let (packet, from) = if self.noise.is_empty() {
+                match self.reply.take() {
+                    Some(reply) => reply,
+                    None => std::future::pending().await,
+                }
+            } else {
+                self.noise.remove(0)
+            };
+            buf[..packet.len()].copy_from_slice(&packet);
+            Ok((packet.len(), from))
+        }
+
+        fn local_addr(&self) -> Result<SocketAddr, Error> {
+            Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        }
+    }
+
+    const SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 123);
+
+    /// Run a query against `server`, with its reply prepared in advance
+    /// from the request the client is about to send. The client's clock starts at `start` and ticks 10ms per
+    /// reading.
+    async fn exchange(server: &mut FakeServer, start: i128) -> Result<SntpSample, SntpError> {
+        let clock = SteppingClock {
+            now: Cell::new(start),
+            step: 10_000_000,
+        };
+        let client = SntpClient::new(clock, Timer(false));
+        // The request's transmit timestamp is the clock's first reading.
+        let transmit = to_ntp(start);
+        let server_received = start + 5_000_000 + server.offset;
+        let mut reply = vec![0u8; PACKET_LEN];
+        reply[0] = (4 << 3) | 4;
+        reply[1] = server.stratum;
+        reply[12..16].copy_from_slice(b"RATE");
+        reply[24..32].copy_from_slice(&transmit.to_be_bytes());
+        reply[32..40].copy_from_slice(&to_ntp(server_received).to_be_bytes());
+        reply[40..48].copy_from_slice(&to_ntp(server_received + server.processing).to_be_bytes());
+        server.reply = Some((reply, SERVER));
+        client.query(server, SERVER).await
+    }
+
+    const START: i128 = 1_700_000_000 * NANOS_PER_SEC;
+
+    #[test]
+    fn ntp_timestamps_roundtrip() {
+        for nanos in [0, START, START + 123_456_789, 2_085_978_496 * NANOS_PER_SEC] {
+            let back = from_ntp(to_ntp(nanos));
+            assert!((back - nanos).abs() <= 1, "{} became {}", nanos, back);
+        }
+    }
+
+    #[tokio::test]
+    async fn computes_offset_and_round_trip() {
+        // Server 3s ahead. The client reads its clock at START (sent) and
+        // START + 10ms (arrived); the request takes 5ms to arrive and the
+        // server 1ms to answer, so the reply's 4ms trip skews the offset
+        // by half the 1ms asymmetry.
+        let mut server = FakeServer::new(3 * NANOS_PER_SEC);
+        let sample = exchange(&mut server, START).await.unwrap();
+        assert_eq!(sample.server, SERVER);
+        assert_eq!(sample.stratum, 2);
+        assert_eq!(sample.round_trip, Duration::from_millis(9));
+        let expected = 3 * NANOS_PER_SEC as i64 + 500_000;
+        assert!((sample.offset_nanos - expected).abs() < 1_000);
+
+        let mut behind = FakeServer::new(-NANOS_PER_SEC);
+        let sample = exchange(&mut behind, START).await.unwrap();
+        assert!((sample.offset_nanos + NANOS_PER_SEC as i64 - 500_000).abs() < 1_000);
+    }
+
+    #[tokio::test]
+    async fn ignores_stray_datagrams() {
+        let mut server = FakeServer::new(0);
+        let elsewhere = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 9)), 123);
+        server.noise.push((vec![0x24; PACKET_LEN], elsewhere));
+        server.noise.push((vec![0x24; PACKET_LEN], SERVER));
+        server.noise.push((vec![0x24; 4], SERVER));
+        assert!(exchange(&mut server, START).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_replies() {
+        let mut server = FakeServer::new(0);
+        server.stratum = 0;
+        match exchange(&mut server, START).await {
+            Err(SntpError::KissOfDeath(code)) => assert_eq!(code, "RATE"),
+            other => panic!("expected a kiss of death, got {:?}", other),
+        }
+
+        let mut server = FakeServer::new(0);
+        server.stratum = 16;
+        assert!(matches!(
+            exchange(&mut server, START).await,
+            Err(SntpError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let clock = SteppingClock {
+            now: Cell::new(START),
+            step: 1,
+        };
+        let client = SntpClient::new(clock, Timer(true));
+        let mut server = FakeServer::new(0);
+        assert!(matches!(
+            client.query(&mut server, SERVER).await,
+            Err(SntpError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn adjusted_clock_applies_offset() {
+        let inner = SteppingClock {
+            now: Cell::new(10 * NANOS_PER_SEC + 500_000_000),
+            step: 0,
+        };
+        let clock = AdjustedClock::new(inner).with_offset(-750_000_000);
+        assert_eq!(clock.now(), (9, 750_000_000));
+
+        clock.set_offset(-20 * NANOS_PER_SEC as i64);
+        assert_eq!(clock.now(), (0, 0));
+        clock.set_offset(1);
+        assert_eq!(clock.now(), (10, 500_000_001));
+    }
+}
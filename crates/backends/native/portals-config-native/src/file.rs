@@ -0,0 +1,190 @@
+//! Configuration files, with per-profile overlays.
+
+use portals_config::{Config, Error, Profile};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Configuration read from a file of `key = value` lines.
+///
+/// Blank lines and lines starting with `#` or `;` are ignored, values may
+/// be wrapped in double quotes, and a `[section]` line prefixes the keys
+/// after it with `section.`:
+///
+/// ```text
+/// # app.conf
+/// log_level = info
+///
+/// [database]
+/// url = "postgres://localhost/app"
+/// ```
+///
+/// [`with_profile`](Self::with_profile) merges in an overlay for a
+/// deployment profile, read from the file with the profile's name before
+/// the extension (`app.prod.conf` for `app.conf`).
+#[derive(Debug, Clone)]
+pub struct FileConfig {
+    path: PathBuf,
+    values: HashMap<String, String>,
+    profile: Option<Profile>,
+}
+
+impl FileConfig {
+    /// Read the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        Ok(Self {
+            path: path.to_path_buf(),
+            values: read(path)?,
+            profile: None,
+        })
+    }
+
+    /// Override values with those in the overlay for `profile`, which must
+    /// exist even if it sets nothing, so a misspelled profile fails rather
+    /// than quietly running with the base configuration.
+    ///
+    /// The overlay is found by the name given, and [`Config::profile`]
+    /// then reports it as parsed by [`Profile::parse`].
+    pub fn with_profile(mut self, profile: &str) -> Result<Self, Error> {
+        let overlay = read(&self.overlay_path(profile))?;
+        self.values.extend(overlay);
+        self.profile = Some(Profile::parse(profile));
+        Ok(self)
+    }
+
+    /// Where the overlay for `profile` is read from.
+    pub fn overlay_path(&self, profile: &str) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, profile, ext.to_string_lossy()),
+            None => format!("{}.{}", stem, profile),
+        };
+        self.path.with_file_name(name)
+    }
+}
+
+impl Config for FileConfig {
+    fn get(&self, key: &str) -> Result<String, Error> {
+        self.values
+            .get(key)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(key.to_string()))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+
+    fn profile(&self) -> Option<Profile> {
+        self.profile.clone()
+    }
+}
+
+fn read(path: &Path) -> Result<HashMap<String, String>, Error> {
+    let text = std::fs::read_to_string(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::NotFound(path.display().to_string()),
+        _ => Error::Other(format!("{}: {}", path.display(), e)),
+    })?;
+    parse(&text).map_err(|line| {
+        Error::InvalidValue(format!(
+            "{}:{}: expected `key = value`",
+            path.display(),
+            line
+        ))
+    })
+}
+
+/// Parse `key = value` lines, failing with the number of the first line
+/// that isn't one.
+fn parse(text: &str) -> Result<HashMap<String, String>, usize> {
+    let mut values = HashMap::new();
+    let mut section = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(i + 1);
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(i + 1);
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        let key = match section.as_str() {
+            "" => key.to_string(),
+            section => format!("{}.{}", section, key),
+        };
+        values.insert(key, value.to_string());
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test's files.
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("portals-config-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_sections_and_quotes() {
+        let values = parse(
+            "# comment\nname = app\n\n[database]\nurl = \"postgres://db\"\n; another\n[ ]\nport=80\n",
+        )
+        .unwrap();
+        assert_eq!(values["name"], "app");
+        assert_eq!(values["database.url"], "postgres://db");
+        assert_eq!(values["port"], "80");
+        assert_eq!(parse("a = 1\njunk\n"), Err(2));
+        assert_eq!(parse(" = 1"), Err(1));
+    }
+
+    #[test]
+    fn merges_profile_overlay() {
+        let dir = dir("profile");
+        let base = dir.join("app.conf");
+        std::fs::write(
+            &base,
+            "log_level = info\n[database]\nurl = local\npool = 5\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("app.prod.conf"),
+            "log_level = warn\n[database]\nurl = \"postgres://prod\"\n",
+        )
+        .unwrap();
+
+        let config = FileConfig::load(&base).unwrap();
+        assert_eq!(config.get("database.url").unwrap(), "local");
+        assert_eq!(config.profile(), None);
+
+        let config = FileConfig::load(&base)
+            .unwrap()
+            .with_profile("prod")
+            .unwrap();
+        assert_eq!(config.get("log_level").unwrap(), "warn");
+        assert_eq!(config.get("database.url").unwrap(), "postgres://prod");
+        assert_eq!(config.get("database.pool").unwrap(), "5");
+        assert!(config.profile().unwrap().is_production());
+
+        let missing = FileConfig::load(&base).unwrap().with_profile("prdo");
+        assert!(matches!(missing, Err(Error::NotFound(path)) if path.ends_with("app.prdo.conf")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+//! Configuration combined from several sources.
+
+use portals_config::{Config, Error, Profile};
+
+/// Configuration looked up in a stack of sources.
+///
+/// Layers added later take precedence, so a typical stack adds defaults
+/// first and overrides last:
+///
+/// ```ignore
+/// let config = LayeredConfig::new()
+///     .with_layer(FileConfig::load("app.conf")?.with_profile(&profile)?)
+///     .with_layer(EnvConfig::with_prefix("APP"));
+/// if config.profile().is_some_and(|p| p.is_production()) {
+///     // ...
+/// }
+/// ```
+///
+/// The active profile is the one set with
+/// [`with_profile`](Self::with_profile), or else the one reported by the
+/// topmost layer that has one.
+#[derive(Default)]
+pub struct LayeredConfig {
+    layers: Vec<Box<dyn Config + Send + Sync>>,
+    profile: Option<Profile>,
+}
+
+impl LayeredConfig {
+    /// Create a config with no layers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a layer over the existing ones.
+    pub fn with_layer(mut self, layer: impl Config + Send + Sync + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Report `profile` as active, whatever the layers say.
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+}
+
+impl std::fmt::Debug for LayeredConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LayeredConfig")
+            .field("layers", &self.layers.len())
+            .field("profile", &self.profile)
+            .finish()
+    }
+}
+
+impl Config for LayeredConfig {
+    fn get(&self, key: &str) -> Result<String, Error> {
+        for layer in self.layers.iter().rev() {
+            match layer.get(key) {
+                Err(Error::NotFound(_)) => continue,
+                result => return result,
+            }
+        }
+        Err(Error::NotFound(key.to_string()))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.layers.iter().flat_map(|layer| layer.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    fn profile(&self) -> Option<Profile> {
+        self.profile
+            .clone()
+            .or_else(|| self.layers.iter().rev().find_map(|layer| layer.profile()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryConfig;
+
+    struct Profiled(Profile);
+
+    impl Config for Profiled {
+        fn get(&self, key: &str) -> Result<String, Error> {
+            Err(Error::NotFound(key.to_string()))
+        }
+
+        fn keys(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn profile(&self) -> Option<Profile> {
+            Some(self.0.clone())
+        }
+    }
+
+    fn memory(pairs: &[(&str, &str)]) -> MemoryConfig {
+        MemoryConfig::from_pairs(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let config = LayeredConfig::new()
+            .with_layer(memory(&[("a", "base"), ("b", "base")]))
+            .with_layer(memory(&[("b", "override"), ("c", "new")]));
+        assert_eq!(config.get("a").unwrap(), "base");
+        assert_eq!(config.get("b").unwrap(), "override");
+        assert_eq!(config.keys(), ["a", "b", "c"]);
+        assert!(matches!(config.get("d"), Err(Error::NotFound(_))));
+        assert_eq!(config.profile(), None);
+    }
+
+    #[test]
+    fn reports_active_profile() {
+        let config = LayeredConfig::new()
+            .with_layer(Profiled(Profile::Staging))
+            .with_layer(memory(&[]));
+        assert_eq!(config.profile(), Some(Profile::Staging));
+
+        let config = config.with_profile(Profile::parse("PROD"));
+        assert!(config.profile().unwrap().is_production());
+        assert_eq!(Profile::parse("qa"), Profile::Custom("qa".to_string()));
+    }
+}
@@ -1,4 +1,11 @@
-//! Native configuration implementation using environment variables.
+//! Native configuration implementation using environment variables, files,
+//! and layers of other sources.
+
+mod file;
+mod layered;
+
+pub use file::FileConfig;
+pub use layered::LayeredConfig;
 
 use portals_config::{Config, ConfigMut, Error};
 use std::collections::HashMap;
@@ -128,6 +135,25 @@ mod tests {
         assert_eq!(config.get("key").unwrap(), "value");
     }
 
+    #[test]
+    fn memory_config_units() {
+        let mut config = MemoryConfig::new();
+        config.set("timeout", "1m30s").unwrap();
+        config.set("max_body", "2MiB").unwrap();
+        config.set("bad", "fast").unwrap();
+        assert_eq!(
+            config.get_duration("timeout").unwrap(),
+            std::time::Duration::from_secs(90)
+        );
+        assert_eq!(config.get_bytes("max_body").unwrap(), 2 * 1024 * 1024);
+        let err = config.get_duration("bad").unwrap_err().to_string();
+        assert!(err.starts_with("invalid value: bad: \"fast\" is not a duration"));
+        assert!(matches!(
+            config.get_bytes("missing"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
     #[test]
     fn memory_config_remove() {
         let mut config = MemoryConfig::new();
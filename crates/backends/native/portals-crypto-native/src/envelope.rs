@@ -0,0 +1,333 @@
+//! Envelope encryption.
+//!
+//! Each payload is encrypted under a fresh random data key, and the data key
+//! is wrapped (encrypted) under a long-lived master key. Only the small
+//! wrapped key depends on the master key, and the blob records which master
+//! key that was.
+
+use crate::{Aes256Gcm, ChaCha20Poly1305};
+use portals_crypto::{Cipher, CryptoError};
+use rand::RngCore;
+use rand::rngs::OsRng;
+
+const MAGIC: &[u8; 4] = b"PENV";
+const VERSION: u8 = 1;
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+
+/// AEAD used for both the data key and the payload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvelopeAlgorithm {
+    /// AES-256-GCM.
+    #[default]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305.
+    ChaCha20Poly1305,
+}
+
+impl EnvelopeAlgorithm {
+    fn id(self) -> u8 {
+        match self {
+            Self::Aes256Gcm => 1,
+            Self::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::Aes256Gcm),
+            2 => Some(Self::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    fn encrypt(
+        self,
+        key: &[u8],
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        match self {
+            Self::Aes256Gcm => Aes256Gcm::encrypt(key, nonce, plaintext, aad),
+            Self::ChaCha20Poly1305 => ChaCha20Poly1305::encrypt(key, nonce, plaintext, aad),
+        }
+    }
+
+    fn decrypt(
+        self,
+        key: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        match self {
+            Self::Aes256Gcm => Aes256Gcm::decrypt(key, nonce, ciphertext, aad),
+            Self::ChaCha20Poly1305 => ChaCha20Poly1305::decrypt(key, nonce, ciphertext, aad),
+        }
+    }
+}
+
+/// Seals and opens self-describing envelope blobs under a master key.
+///
+/// Blob layout (lengths big-endian):
+///
+/// ```text
+/// "PENV" | version u8 | algorithm u8 | key id len u16 | key id
+///        | wrapped key len u16 | wrap nonce (12) | wrapped data key
+///        | payload nonce (12) | payload ciphertext
+/// ```
+///
+/// The header (everything before the wrapped key) is authenticated with the
+/// wrapped key; the payload is authenticated with the caller's `aad`.
+///
+/// ```ignore
+/// let envelope = Envelope::new(&master_key)?.key_id("2024-06");
+/// let blob = envelope.seal(b"secret", b"blob/42")?;
+/// assert_eq!(Envelope::key_id_of(&blob)?, "2024-06");
+/// assert_eq!(envelope.open(&blob, b"blob/42")?, b"secret");
+/// ```
+#[derive(Clone)]
+pub struct Envelope {
+    master_key: Vec<u8>,
+    key_id: String,
+    algorithm: EnvelopeAlgorithm,
+}
+
+impl Envelope {
+    /// Create an envelope for a 32-byte master key, using AES-256-GCM.
+    pub fn new(master_key: &[u8]) -> Result<Self, CryptoError> {
+        if master_key.len() != KEY_SIZE {
+            return Err(CryptoError::InvalidKeySize);
+        }
+        Ok(Self {
+            master_key: master_key.to_vec(),
+            key_id: String::new(),
+            algorithm: EnvelopeAlgorithm::default(),
+        })
+    }
+
+    /// Identifier of the master key, recorded in sealed blobs so the right
+    /// key can be found when opening them (at most 65535 bytes).
+    pub fn key_id(mut self, id: impl Into<String>) -> Self {
+        self.key_id = id.into();
+        self
+    }
+
+    /// Algorithm for newly sealed blobs. Opening uses the blob's algorithm.
+    pub fn algorithm(mut self, algorithm: EnvelopeAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Encrypt `plaintext` under a fresh data key.
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let key_id_len: u16 = self
+            .key_id
+            .len()
+            .try_into()
+            .map_err(|_| CryptoError::Other("envelope key id too long".to_string()))?;
+
+        let mut data_key = [0u8; KEY_SIZE];
+        let mut wrap_nonce = [0u8; NONCE_SIZE];
+        let mut payload_nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut data_key);
+        OsRng.fill_bytes(&mut wrap_nonce);
+        OsRng.fill_bytes(&mut payload_nonce);
+
+        let mut blob = Vec::new();
+        blob.extend_from_slice(MAGIC);
+        blob.push(VERSION);
+        blob.push(self.algorithm.id());
+        blob.extend_from_slice(&key_id_len.to_be_bytes());
+        blob.extend_from_slice(self.key_id.as_bytes());
+
+        let wrapped = self
+            .algorithm
+            .encrypt(&self.master_key, &wrap_nonce, &data_key, &blob)?;
+        let payload = self
+            .algorithm
+            .encrypt(&data_key, &payload_nonce, plaintext, aad);
+        data_key.fill(0);
+        let payload = payload?;
+
+        blob.extend_from_slice(&(wrapped.len() as u16).to_be_bytes());
+        blob.extend_from_slice(&wrap_nonce);
+        blob.extend_from_slice(&wrapped);
+        blob.extend_from_slice(&payload_nonce);
+        blob.extend_from_slice(&payload);
+        Ok(blob)
+    }
+
+    /// Decrypt a blob produced by [`seal`](Self::seal) with the same master
+    /// key and `aad`.
+    pub fn open(&self, blob: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let parsed = Parsed::parse(blob)?;
+        let mut data_key = parsed.algorithm.decrypt(
+            &self.master_key,
+            parsed.wrap_nonce,
+            parsed.wrapped_key,
+            parsed.header,
+        )?;
+        let plaintext =
+            parsed
+                .algorithm
+                .decrypt(&data_key, parsed.payload_nonce, parsed.payload, aad);
+        data_key.fill(0);
+        plaintext
+    }
+
+    /// The master key identifier recorded in a blob.
+    pub fn key_id_of(blob: &[u8]) -> Result<&str, CryptoError> {
+        Ok(Parsed::parse(blob)?.key_id)
+    }
+}
+
+/// A blob split into its parts.
+struct Parsed<'a> {
+    algorithm: EnvelopeAlgorithm,
+    header: &'a [u8],
+    key_id: &'a str,
+    wrap_nonce: &'a [u8],
+    wrapped_key: &'a [u8],
+    payload_nonce: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> Parsed<'a> {
+    fn parse(blob: &'a [u8]) -> Result<Self, CryptoError> {
+        let malformed = || CryptoError::Other("malformed envelope".to_string());
+        let mut reader = Reader { blob, pos: 0 };
+
+        if reader.take(4).ok_or_else(malformed)? != MAGIC {
+            return Err(malformed());
+        }
+        let version = reader.u8().ok_or_else(malformed)?;
+        if version != VERSION {
+            return Err(CryptoError::Other(format!(
+                "unsupported envelope version {}",
+                version
+            )));
+        }
+        let id = reader.u8().ok_or_else(malformed)?;
+        let algorithm = EnvelopeAlgorithm::from_id(id)
+            .ok_or_else(|| CryptoError::Other(format!("unsupported envelope algorithm {}", id)))?;
+        let key_id_len = reader.u16().ok_or_else(malformed)?;
+        let key_id = reader.take(key_id_len as usize).ok_or_else(malformed)?;
+        let key_id = std::str::from_utf8(key_id).map_err(|_| malformed())?;
+        let header = &blob[..reader.pos];
+
+        let wrapped_len = reader.u16().ok_or_else(malformed)?;
+        let wrap_nonce = reader.take(NONCE_SIZE).ok_or_else(malformed)?;
+        let wrapped_key = reader.take(wrapped_len as usize).ok_or_else(malformed)?;
+        let payload_nonce = reader.take(NONCE_SIZE).ok_or_else(malformed)?;
+        let payload = &blob[reader.pos..];
+
+        Ok(Self {
+            algorithm,
+            header,
+            key_id,
+            wrap_nonce,
+            wrapped_key,
+            payload_nonce,
+            payload,
+        })
+    }
+}
+
+struct Reader<'a> {
+    blob: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.blob.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: [u8; 32] = [7; 32];
+
+    #[test]
+    fn seal_and_open() {
+        for algorithm in [
+            EnvelopeAlgorithm::Aes256Gcm,
+            EnvelopeAlgorithm::ChaCha20Poly1305,
+        ] {
+            let envelope = Envelope::new(&MASTER)
+                .unwrap()
+                .key_id("master-1")
+                .algorithm(algorithm);
+            let blob = envelope.seal(b"hello world", b"blob/1").unwrap();
+            assert_eq!(Envelope::key_id_of(&blob).unwrap(), "master-1");
+            assert_eq!(envelope.open(&blob, b"blob/1").unwrap(), b"hello world");
+        }
+    }
+
+    #[test]
+    fn fresh_data_key_per_blob() {
+        let envelope = Envelope::new(&MASTER).unwrap();
+        let a = envelope.seal(b"same", &[]).unwrap();
+        let b = envelope.seal(b"same", &[]).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn rejects_wrong_key_or_aad() {
+        let envelope = Envelope::new(&MASTER).unwrap();
+        let blob = envelope.seal(b"payload", b"aad").unwrap();
+
+        let other = Envelope::new(&[8; 32]).unwrap();
+        assert!(matches!(
+            other.open(&blob, b"aad"),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+        assert!(matches!(
+            envelope.open(&blob, b"other"),
+            Err(CryptoError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn header_is_authenticated() {
+        let envelope = Envelope::new(&MASTER).unwrap().key_id("a");
+        let mut blob = envelope.seal(b"payload", &[]).unwrap();
+        // Swap the recorded key id.
+        blob[8] = b'b';
+        assert_eq!(Envelope::key_id_of(&blob).unwrap(), "b");
+        assert!(envelope.open(&blob, &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let envelope = Envelope::new(&MASTER).unwrap();
+        let blob = envelope.seal(b"payload", &[]).unwrap();
+
+        assert!(envelope.open(&blob[..20], &[]).is_err());
+        assert!(envelope.open(b"nope", &[]).is_err());
+        let mut bad = blob.clone();
+        bad[5] = 9;
+        assert!(matches!(
+            envelope.open(&bad, &[]),
+            Err(CryptoError::Other(msg)) if msg.contains("algorithm")
+        ));
+        assert!(matches!(
+            Envelope::new(&[0; 16]),
+            Err(CryptoError::InvalidKeySize)
+        ));
+    }
+}
@@ -0,0 +1,575 @@
+//! JSON Web Key Sets (RFC 7517) and verification of tokens signed with them.
+//!
+//! Only asymmetric signature keys are kept: Ed25519 (`OKP`) for `EdDSA`, RSA
+//! for `RS*`/`PS*`, and P-256/P-384 (`EC`) for `ES256`/`ES384`. Symmetric
+//! (`oct`) keys are skipped along with other key types: a secret published
+//! in a remote set would let anyone who can fetch it mint tokens. Signatures
+//! are verified by `jsonwebtoken`.
+
+use crate::Ed25519;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::errors::ErrorKind as JwtErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use portals_clocks::MonotonicClock;
+use portals_crypto::{CryptoError, Signature};
+use portals_http::{Headers, HttpClient, Method, Request};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Key material of a [`Jwk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwkKey {
+    /// Ed25519 public key (`kty: OKP`, `crv: Ed25519`), for `EdDSA`.
+    Ed25519(Vec<u8>),
+    /// RSA public key (`kty: RSA`), for `RS256`-`RS512` and `PS256`-`PS512`.
+    Rsa {
+        /// Modulus, big-endian.
+        n: Vec<u8>,
+        /// Public exponent, big-endian.
+        e: Vec<u8>,
+    },
+    /// P-256 public key (`kty: EC`, `crv: P-256`), for `ES256`.
+    P256 {
+        /// Affine x coordinate.
+        x: Vec<u8>,
+        /// Affine y coordinate.
+        y: Vec<u8>,
+    },
+    /// P-384 public key (`kty: EC`, `crv: P-384`), for `ES384`.
+    P384 {
+        /// Affine x coordinate.
+        x: Vec<u8>,
+        /// Affine y coordinate.
+        y: Vec<u8>,
+    },
+}
+
+impl JwkKey {
+    fn supports(&self, alg: Algorithm) -> bool {
+        use Algorithm::*;
+        matches!(
+            (self, alg),
+            (Self::Ed25519(_), EdDSA)
+                | (
+                    Self::Rsa { .. },
+                    RS256 | RS384 | RS512 | PS256 | PS384 | PS512
+                )
+                | (Self::P256 { .. }, ES256)
+                | (Self::P384 { .. }, ES384)
+        )
+    }
+
+    fn decoding_key(&self) -> Result<DecodingKey, CryptoError> {
+        let key = match self {
+            Self::Ed25519(x) => DecodingKey::from_ed_components(&URL_SAFE_NO_PAD.encode(x)),
+            Self::Rsa { n, e } => Ok(DecodingKey::from_rsa_raw_components(n, e)),
+            Self::P256 { x, y } | Self::P384 { x, y } => DecodingKey::from_ec_components(
+                &URL_SAFE_NO_PAD.encode(x),
+                &URL_SAFE_NO_PAD.encode(y),
+            ),
+        };
+        key.map_err(|_| CryptoError::InvalidEncoding)
+    }
+}
+
+/// A single JSON Web Key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jwk {
+    /// Key ID (`kid`).
+    pub kid: Option<String>,
+    /// Algorithm the key is restricted to (`alg`), if any.
+    pub alg: Option<String>,
+    /// Key material.
+    pub key: JwkKey,
+}
+
+impl Jwk {
+    /// Parse one key. Returns `Ok(None)` for keys of an unsupported type or
+    /// not meant for signatures.
+    fn from_json(value: &Value) -> Result<Option<Self>, CryptoError> {
+        let field = |name: &str| value.get(name).and_then(Value::as_str);
+        let bytes = |name: &str| decode(field(name).ok_or(CryptoError::InvalidEncoding)?);
+        if field("use").is_some_and(|usage| usage != "sig") {
+            return Ok(None);
+        }
+        let key = match (field("kty"), field("crv")) {
+            (Some("OKP"), Some("Ed25519")) => {
+                let x = bytes("x")?;
+                if x.len() != Ed25519::PUBLIC_KEY_SIZE {
+                    return Err(CryptoError::InvalidKeySize);
+                }
+                JwkKey::Ed25519(x)
+            }
+            (Some("RSA"), _) => JwkKey::Rsa {
+                n: bytes("n")?,
+                e: bytes("e")?,
+            },
+            (Some("EC"), Some(crv @ ("P-256" | "P-384"))) => {
+                let (x, y) = (bytes("x")?, bytes("y")?);
+                let size = if crv == "P-256" { 32 } else { 48 };
+                if x.len() != size || y.len() != size {
+                    return Err(CryptoError::InvalidKeySize);
+                }
+                if crv == "P-256" {
+                    JwkKey::P256 { x, y }
+                } else {
+                    JwkKey::P384 { x, y }
+                }
+            }
+            (Some(_), _) => return Ok(None),
+            (None, _) => return Err(CryptoError::InvalidEncoding),
+        };
+        Ok(Some(Self {
+            kid: field("kid").map(str::to_string),
+            alg: field("alg").map(str::to_string),
+            key,
+        }))
+    }
+
+    /// Whether this key may verify a token signed with `alg`.
+    pub fn accepts(&self, alg: &str) -> bool {
+        alg.parse().is_ok_and(|alg| self.accepts_alg(alg))
+    }
+
+    fn accepts_alg(&self, alg: Algorithm) -> bool {
+        self.alg
+            .as_deref()
+            .is_none_or(|a| a.parse::<Algorithm>().is_ok_and(|a| a == alg))
+            && self.key.supports(alg)
+    }
+}
+
+/// A parsed JSON Web Key Set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    /// Parse a `{"keys": [...]}` document.
+    pub fn parse(json: &[u8]) -> Result<Self, CryptoError> {
+        let document: Value =
+            serde_json::from_slice(json).map_err(|_| CryptoError::InvalidEncoding)?;
+        let entries = document
+            .get("keys")
+            .and_then(Value::as_array)
+            .ok_or(CryptoError::InvalidEncoding)?;
+        let mut keys = Vec::new();
+        for entry in entries {
+            keys.extend(Jwk::from_json(entry)?);
+        }
+        Ok(Self { keys })
+    }
+
+    /// The supported keys in the set.
+    pub fn keys(&self) -> &[Jwk] {
+        &self.keys
+    }
+
+    /// Find the key with the given `kid`.
+    pub fn find(&self, kid: &str) -> Option<&Jwk> {
+        self.keys.iter().find(|k| k.kid.as_deref() == Some(kid))
+    }
+
+    /// Verify a compact JWS (`header.payload.signature`) and deserialize its
+    /// claims.
+    ///
+    /// The key is selected by the header's `kid`; without one, every key
+    /// accepting the header's `alg` is tried. No claims are checked, not
+    /// even `exp`: `jsonwebtoken` would read the system clock for that, so
+    /// expiry is left to the caller and its [`WallClock`].
+    ///
+    /// [`WallClock`]: portals_clocks::WallClock
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, CryptoError> {
+        let header = jsonwebtoken::decode_header(token).map_err(jwt_error)?;
+        let candidates: Vec<&Jwk> = match &header.kid {
+            Some(kid) => self.find(kid).into_iter().collect(),
+            None => self.keys.iter().collect(),
+        };
+        let mut validation = Validation::new(header.alg);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+        for jwk in candidates {
+            if !jwk.accepts_alg(header.alg) {
+                continue;
+            }
+            let key = jwk.key.decoding_key()?;
+            match jsonwebtoken::decode(token, &key, &validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) if matches!(e.kind(), JwtErrorKind::InvalidSignature) => continue,
+                Err(e) => return Err(jwt_error(e)),
+            }
+        }
+        Err(CryptoError::InvalidSignature)
+    }
+}
+
+fn jwt_error(error: jsonwebtoken::errors::Error) -> CryptoError {
+    match error.kind() {
+        JwtErrorKind::InvalidSignature => CryptoError::InvalidSignature,
+        JwtErrorKind::InvalidToken
+        | JwtErrorKind::Base64(_)
+        | JwtErrorKind::Json(_)
+        | JwtErrorKind::Utf8(_) => CryptoError::InvalidEncoding,
+        _ => CryptoError::Other(error.to_string()),
+    }
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, CryptoError> {
+    URL_SAFE_NO_PAD
+        .decode(text)
+        .map_err(|_| CryptoError::InvalidEncoding)
+}
+
+/// Fetches a JSON Web Key Set over HTTP and keeps it cached.
+///
+/// The set is refetched once it is older than the refresh interval, and
+/// early when a token names a `kid` the cached set lacks, so keys rotated
+/// in by the issuer are picked up. Early refetches are rate-limited by the
+/// minimum refresh interval. If a refetch fails, the stale set keeps being
+/// used.
+///
+/// ```ignore
+/// let jwks = JwksClient::new(http, clock, "https://issuer.example/.well-known/jwks.json")
+///     .refresh_interval(Duration::from_secs(600));
+/// let claims: serde_json::Value = jwks.verify(token).await?;
+/// ```
+pub struct JwksClient<C, M> {
+    client: C,
+    clock: M,
+    url: String,
+    refresh_interval: Duration,
+    min_refresh_interval: Duration,
+    cache: Mutex<Option<Cached>>,
+}
+
+struct Cached {
+    set: Arc<JwkSet>,
+    fetched_at: u64,
+}
+
+impl<C: HttpClient, M: MonotonicClock> JwksClient<C, M> {
+    /// Create a client for the key set at `url`, with a one-hour refresh
+    /// interval and a 30-second minimum between fetches.
+    pub fn new(client: C, clock: M, url: impl Into<String>) -> Self {
+        Self {
+            client,
+            clock,
+            url: url.into(),
+            refresh_interval: Duration::from_secs(3600),
+            min_refresh_interval: Duration::from_secs(30),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// How long a fetched set is used before it is refetched.
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Minimum time between fetches triggered by an unknown `kid`.
+    pub fn min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    /// The key set, fetched if not cached or older than the refresh
+    /// interval.
+    pub async fn key_set(&self) -> Result<Arc<JwkSet>, CryptoError> {
+        match self.cached() {
+            Some((set, age)) if age < self.refresh_interval => Ok(set),
+            Some((set, _)) => Ok(self.refresh().await.unwrap_or(set)),
+            None => self.refresh().await,
+        }
+    }
+
+    /// The key with the given `kid`, refetching the set early if it is
+    /// missing.
+    pub async fn key(&self, kid: &str) -> Result<Jwk, CryptoError> {
+        let set = self.key_set_with(Some(kid)).await?;
+        set.find(kid)
+            .cloned()
+            .ok_or_else(|| CryptoError::Other(format!("no JWK with kid {}", kid)))
+    }
+
+    /// Verify a compact JWS against the key set and deserialize its claims.
+    ///
+    /// See [`JwkSet::verify`].
+    pub async fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, CryptoError> {
+        let kid = jsonwebtoken::decode_header(token).map_err(jwt_error)?.kid;
+        self.key_set_with(kid.as_deref()).await?.verify(token)
+    }
+
+    /// Fetch the key set now and replace the cached one.
+    pub async fn refresh(&self) -> Result<Arc<JwkSet>, CryptoError> {
+        let request = Request {
+            method: Method::Get,
+            url: self.url.clone(),
+            headers: Headers::from([("accept", "application/json")]),
+            body: None,
+        };
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| CryptoError::Other(format!("JWKS fetch failed: {}", e)))?;
+        if response.status != 200 {
+            return Err(CryptoError::Other(format!(
+                "JWKS fetch failed with status {}",
+                response.status
+            )));
+        }
+
+        let set = Arc::new(JwkSet::parse(&response.body)?);
+        *self.lock() = Some(Cached {
+            set: set.clone(),
+            fetched_at: self.clock.now(),
+        });
+        Ok(set)
+    }
+
+    async fn key_set_with(&self, kid: Option<&str>) -> Result<Arc<JwkSet>, CryptoError> {
+        let set = self.key_set().await?;
+        let Some(kid) = kid else {
+            return Ok(set);
+        };
+        if set.find(kid).is_some() {
+            return Ok(set);
+        }
+        match self.cached() {
+            Some((_, age)) if age < self.min_refresh_interval => Ok(set),
+            _ => Ok(self.refresh().await.unwrap_or(set)),
+        }
+    }
+
+    /// The cached set and its age.
+    fn cached(&self) -> Option<(Arc<JwkSet>, Duration)> {
+        let cache = self.lock();
+        let cached = cache.as_ref()?;
+        let age = Duration::from_nanos(self.clock.now().saturating_sub(cached.fetched_at));
+        Some((cached.set.clone(), age))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Cached>> {
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_clocks_mock::MockMonotonicClock;
+    use portals_http_mock::{MockHttpClient, ResponseBuilder};
+
+    fn b64(data: &[u8]) -> String {
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    fn ed25519_token(secret_key: &[u8], kid: &str, payload: &str) -> String {
+        let header = b64(format!(r#"{{"alg":"EdDSA","kid":"{}"}}"#, kid).as_bytes());
+        let input = format!("{}.{}", header, b64(payload.as_bytes()));
+        let signature = Ed25519::sign(secret_key, input.as_bytes()).unwrap();
+        format!("{}.{}", input, b64(&signature))
+    }
+
+    fn ed25519_set(keys: &[(&str, &[u8])]) -> String {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|(kid, public_key)| {
+                format!(
+                    r#"{{"kty":"OKP","crv":"Ed25519","kid":"{}","x":"{}"}}"#,
+                    kid,
+                    b64(public_key)
+                )
+            })
+            .collect();
+        format!(r#"{{"keys":[{}]}}"#, keys.join(","))
+    }
+
+    #[test]
+    fn parses_supported_keys() {
+        let json = br#"{"keys":[
+            {"kty":"RSA","kid":"rsa","alg":"RS256","n":"AQAB","e":"AQAB"},
+            {"kty":"oct","kid":"hmac","alg":"HS256","k":"c2VjcmV0"},
+            {"kty":"EC","crv":"secp256k1","kid":"k1","x":"AA","y":"AA"},
+            {"kty":"OKP","crv":"Ed25519","kid":"enc","use":"enc","x":"AAAA"},
+            {"kty":"OKP","crv":"Ed25519","kid":"ed","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}
+        ]}"#;
+        let set = JwkSet::parse(json).unwrap();
+        assert_eq!(set.keys().len(), 2);
+        assert!(set.find("hmac").is_none());
+        assert!(set.find("k1").is_none());
+        let rsa = set.find("rsa").unwrap();
+        assert!(matches!(rsa.key, JwkKey::Rsa { .. }));
+        assert!(rsa.accepts("RS256"));
+        assert!(!rsa.accepts("PS256"));
+        assert!(!rsa.accepts("HS256"));
+        assert!(matches!(set.find("ed").unwrap().key, JwkKey::Ed25519(_)));
+        assert!(set.find("ed").unwrap().accepts("EdDSA"));
+
+        assert!(JwkSet::parse(b"{}").is_err());
+        assert!(JwkSet::parse(br#"{"keys":[{"kty":"OKP","crv":"Ed25519","x":"AA"}]}"#).is_err());
+        assert!(
+            JwkSet::parse(br#"{"keys":[{"kty":"EC","crv":"P-256","x":"AA","y":"AA"}]}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn verifies_tokens() {
+        let (public_key, secret_key) = Ed25519::generate_keypair();
+        let set = JwkSet::parse(ed25519_set(&[("a", &public_key[..])]).as_bytes()).unwrap();
+
+        let token = ed25519_token(&secret_key, "a", r#"{"sub":"alice"}"#);
+        let claims: Value = set.verify(&token).unwrap();
+        assert_eq!(claims["sub"], "alice");
+
+        // Unknown kid, tampered payload, malformed token.
+        let other = ed25519_token(&secret_key, "b", "{}");
+        assert!(matches!(
+            set.verify::<Value>(&other),
+            Err(CryptoError::InvalidSignature)
+        ));
+        let parts: Vec<&str> = token.split('.').collect();
+        let tampered = format!("{}.{}.{}", parts[0], b64(b"{}"), parts[2]);
+        assert!(matches!(
+            set.verify::<Value>(&tampered),
+            Err(CryptoError::InvalidSignature)
+        ));
+        assert!(set.verify::<Value>("not-a-token").is_err());
+
+        // "none" and HMAC are never accepted.
+        let unsigned = format!("{}.{}.", b64(br#"{"alg":"none"}"#), b64(b"{}"));
+        assert!(set.verify::<Value>(&unsigned).is_err());
+        let hmac = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(Algorithm::HS256),
+            &serde_json::json!({}),
+            &jsonwebtoken::EncodingKey::from_secret(&public_key),
+        )
+        .unwrap();
+        assert!(set.verify::<Value>(&hmac).is_err());
+    }
+
+    #[test]
+    fn verifies_es256_tokens() {
+        use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        // Uncompressed SEC1 point: 0x04 || x || y.
+        let point = pair.public_key().as_ref();
+        let set = JwkSet::parse(
+            format!(
+                r#"{{"keys":[{{"kty":"EC","crv":"P-256","x":"{}","y":"{}"}}]}}"#,
+                b64(&point[1..33]),
+                b64(&point[33..])
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let key = jsonwebtoken::EncodingKey::from_ec_der(pkcs8.as_ref());
+        let sign = |alg| {
+            jsonwebtoken::encode(
+                &jsonwebtoken::Header::new(alg),
+                &serde_json::json!({"sub": "bob"}),
+                &key,
+            )
+        };
+        let claims: Value = set.verify(&sign(Algorithm::ES256).unwrap()).unwrap();
+        assert_eq!(claims["sub"], "bob");
+
+        // Signed by another key.
+        let (_, secret_key) = Ed25519::generate_keypair();
+        let token = ed25519_token(&secret_key, "a", "{}");
+        assert!(set.verify::<Value>(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn caches_and_refreshes() {
+        let (public_key, secret_key) = Ed25519::generate_keypair();
+        let http = MockHttpClient::new();
+        let clock = MockMonotonicClock::new();
+        http.set_default_response(
+            ResponseBuilder::ok()
+                .json(ed25519_set(&[("a", &public_key[..])]))
+                .build(),
+        );
+        let jwks = JwksClient::new(http.clone(), clock.clone(), "https://issuer/jwks")
+            .refresh_interval(Duration::from_secs(600));
+
+        let token = ed25519_token(&secret_key, "a", "{}");
+        jwks.verify::<Value>(&token).await.unwrap();
+        jwks.verify::<Value>(&token).await.unwrap();
+        assert_eq!(http.request_count(), 1);
+        http.assert_requested("https://issuer/jwks");
+
+        clock.advance(Duration::from_secs(600));
+        jwks.key("a").await.unwrap();
+        assert_eq!(http.request_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn picks_up_rotated_keys() {
+        let (old_public, _) = Ed25519::generate_keypair();
+        let (new_public, new_secret) = Ed25519::generate_keypair();
+        let http = MockHttpClient::new();
+        let clock = MockMonotonicClock::new();
+        http.queue_response(
+            ResponseBuilder::ok()
+                .json(ed25519_set(&[("old", &old_public[..])]))
+                .build(),
+        );
+        let jwks = JwksClient::new(http.clone(), clock.clone(), "https://issuer/jwks");
+        jwks.key_set().await.unwrap();
+
+        // Within the minimum interval an unknown kid does not refetch.
+        let token = ed25519_token(&new_secret, "new", "{}");
+        assert!(jwks.verify::<Value>(&token).await.is_err());
+        assert_eq!(http.request_count(), 1);
+
+        clock.advance(Duration::from_secs(30));
+        http.queue_response(
+            ResponseBuilder::ok()
+                .json(ed25519_set(&[
+                    ("old", &old_public[..]),
+                    ("new", &new_public[..]),
+                ]))
+                .build(),
+        );
+        jwks.verify::<Value>(&token).await.unwrap();
+        assert_eq!(http.request_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn keeps_stale_set_when_refresh_fails() {
+        let (public_key, secret_key) = Ed25519::generate_keypair();
+        let http = MockHttpClient::new();
+        let clock = MockMonotonicClock::new();
+        http.queue_response(
+            ResponseBuilder::ok()
+                .json(ed25519_set(&[("a", &public_key[..])]))
+                .build(),
+        );
+        let jwks = JwksClient::new(http.clone(), clock.clone(), "https://issuer/jwks");
+        jwks.key_set().await.unwrap();
+
+        clock.advance(Duration::from_secs(3600));
+        http.queue_response(ResponseBuilder::server_error().build());
+        let token = ed25519_token(&secret_key, "a", "{}");
+        jwks.verify::<Value>(&token).await.unwrap();
+        assert_eq!(http.request_count(), 2);
+
+        let fresh = JwksClient::new(http.clone(), clock, "https://issuer/jwks");
+        http.queue_error("connection_failed");
+        assert!(fresh.key_set().await.is_err());
+    }
+}
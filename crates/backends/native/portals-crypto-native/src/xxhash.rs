@@ -0,0 +1,119 @@
+//! xxHash: XXH64 and XXH3-128.
+
+use portals_crypto::FastHash;
+use std::hash::Hasher;
+
+/// xxHash64: a fast 64-bit non-cryptographic hash.
+///
+/// Also implements [`Hasher`], so it can key a `HashMap` through
+/// `BuildHasherDefault<XxHash64>`.
+#[derive(Clone)]
+pub struct XxHash64(xxhash_rust::xxh64::Xxh64);
+
+impl FastHash for XxHash64 {
+    type Output = u64;
+
+    fn with_seed(seed: u64) -> Self {
+        Self(xxhash_rust::xxh64::Xxh64::new(seed))
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn digest(&self) -> u64 {
+        self.0.digest()
+    }
+}
+
+impl Default for XxHash64 {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+impl Hasher for XxHash64 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.digest()
+    }
+}
+
+/// XXH3-128: a fast 128-bit non-cryptographic hash, for checksums where
+/// 64 bits make accidental collisions too likely.
+#[derive(Clone)]
+pub struct Xxh3_128(xxhash_rust::xxh3::Xxh3);
+
+impl FastHash for Xxh3_128 {
+    type Output = u128;
+
+    fn with_seed(seed: u64) -> Self {
+        Self(xxhash_rust::xxh3::Xxh3::with_seed(seed))
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn digest(&self) -> u128 {
+        self.0.digest128()
+    }
+}
+
+impl Default for Xxh3_128 {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::hash::BuildHasherDefault;
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(XxHash64::hash(b""), 0xef46db3751d8e999);
+        assert_eq!(XxHash64::hash(b"a"), 0xd24ec4f1a98c6e5b);
+        assert_eq!(XxHash64::hash(b"abc"), 0x44bc2cf5ad770999);
+        assert_eq!(Xxh3_128::hash(b""), 0x99aa06d3014798d86001c324468d497f);
+    }
+
+    #[test]
+    fn seed_changes_hash() {
+        let mut seeded = XxHash64::with_seed(1);
+        seeded.update(b"abc");
+        assert_ne!(seeded.digest(), XxHash64::hash(b"abc"));
+
+        let mut seeded = Xxh3_128::with_seed(1);
+        seeded.update(b"abc");
+        assert_ne!(seeded.digest(), Xxh3_128::hash(b"abc"));
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        for step in [1, 3, 31, 32, 33, 100] {
+            let mut hasher = XxHash64::with_seed(0);
+            let mut wide = Xxh3_128::with_seed(0);
+            for piece in data.chunks(step) {
+                hasher.update(piece);
+                wide.update(piece);
+            }
+            assert_eq!(hasher.digest(), XxHash64::hash(&data), "step {}", step);
+            assert_eq!(wide.digest(), Xxh3_128::hash(&data), "step {}", step);
+        }
+    }
+
+    #[test]
+    fn keys_hash_map() {
+        let mut map: HashMap<&str, u32, BuildHasherDefault<XxHash64>> = HashMap::default();
+        map.insert("one", 1);
+        map.insert("two", 2);
+        assert_eq!(map["two"], 2);
+    }
+}
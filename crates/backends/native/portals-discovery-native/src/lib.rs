@@ -0,0 +1,272 @@
+//! mDNS implementation of portals-discovery.
+//!
+//! Announces and finds DNS-SD services with multicast DNS (RFC 6762 and
+//! RFC 6763) over IPv4 on the local link.
+
+mod records;
+
+use hickory_proto::op::{Message, MessageType};
+use hickory_proto::rr::RecordType;
+use portals_discovery::{Announcer, Browser, Error, Service};
+use records::{ServiceRecords, State, TTL, instance_name, query, type_name};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+/// The mDNS multicast group.
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// The mDNS port.
+pub const MDNS_PORT: u16 = 5353;
+
+/// How long browsing and resolving listen for answers by default.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(1);
+
+/// Largest mDNS message accepted.
+const MAX_MESSAGE: usize = 9000;
+
+/// Service discovery over multicast DNS.
+///
+/// Creating one joins the mDNS group and spawns a tokio task that answers
+/// queries for the services it announces and remembers the services other
+/// hosts announce. Browsing and resolving send a query and then listen
+/// for a short window (one second by default) before reporting what they
+/// found. Dropping it withdraws its announcements and stops the task.
+///
+/// The socket is bound with address reuse, so it can share the mDNS port
+/// with the system's responder and with other instances in the process.
+pub struct MdnsDiscovery {
+    shared: Arc<Shared>,
+    responder: JoinHandle<()>,
+    window: Duration,
+}
+
+struct Shared {
+    socket: UdpSocket,
+    group: SocketAddr,
+    /// Address announced for services that list none of their own.
+    host_addr: IpAddr,
+    state: Mutex<State>,
+}
+
+impl MdnsDiscovery {
+    /// Join the mDNS group on the standard port.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new() -> Result<Self, Error> {
+        Self::on_port(MDNS_PORT)
+    }
+
+    /// Join the mDNS group on a non-standard port, keeping discovery
+    /// private to peers using the same one.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn on_port(port: u16) -> Result<Self, Error> {
+        let group = SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, port));
+        let shared = Arc::new(Shared {
+            socket: bind(port)?,
+            group,
+            host_addr: host_addr(group),
+            state: Mutex::new(State::default()),
+        });
+        let responder = tokio::spawn(respond(Arc::clone(&shared)));
+        Ok(Self {
+            shared,
+            responder,
+            window: DEFAULT_WINDOW,
+        })
+    }
+
+    /// Listen for answers to browse and resolve queries for `window`.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Send a query and give answers `window` to arrive.
+    async fn ask(&self, message: Message) -> Result<(), Error> {
+        self.shared.send(&message).await?;
+        tokio::time::sleep(self.window).await;
+        Ok(())
+    }
+}
+
+impl Drop for MdnsDiscovery {
+    fn drop(&mut self) {
+        self.responder.abort();
+        let announced = std::mem::take(&mut self.shared.state.lock().unwrap().announced);
+        for service in announced {
+            if let Ok(records) = ServiceRecords::new(&service, self.shared.host_addr, 0)
+                && let Ok(bytes) = records.announcement().to_vec()
+            {
+                let _ = self.shared.socket.try_send_to(&bytes, self.shared.group);
+            }
+        }
+    }
+}
+
+impl Shared {
+    async fn send(&self, message: &Message) -> Result<(), Error> {
+        let bytes = message.to_vec().map_err(|e| Error::Other(e.to_string()))?;
+        self.socket.send_to(&bytes, self.group).await?;
+        Ok(())
+    }
+}
+
+/// Answer queries and learn from responses until aborted.
+async fn respond(shared: Arc<Shared>) {
+    let mut buf = vec![0; MAX_MESSAGE];
+    loop {
+        let Ok((n, _)) = shared.socket.recv_from(&mut buf).await else {
+            continue;
+        };
+        let Ok(message) = Message::from_vec(&buf[..n]) else {
+            continue;
+        };
+        match message.message_type() {
+            MessageType::Query => {
+                let answer = shared
+                    .state
+                    .lock()
+                    .unwrap()
+                    .answer(&message, shared.host_addr);
+                if let Some(answer) = answer {
+                    let _ = shared.send(&answer).await;
+                }
+            }
+            MessageType::Response => shared.state.lock().unwrap().learn(&message),
+        }
+    }
+}
+
+fn bind(port: u16) -> Result<UdpSocket, Error> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// The address of the interface multicast traffic leaves from, or
+/// loopback if there is none.
+fn host_addr(group: SocketAddr) -> IpAddr {
+    std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect(group)?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .ok()
+        .filter(|ip| !ip.is_unspecified())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+impl Announcer for MdnsDiscovery {
+    async fn announce(&self, service: &Service) -> Result<(), Error> {
+        let records = ServiceRecords::new(service, self.shared.host_addr, TTL)?;
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.announced.retain(|s| {
+                s.instance != service.instance || s.service_type != service.service_type
+            });
+            state.announced.push(service.clone());
+        }
+        self.shared.send(&records.announcement()).await
+    }
+
+    async fn withdraw(&self, instance: &str, service_type: &str) -> Result<(), Error> {
+        let service = {
+            let mut state = self.shared.state.lock().unwrap();
+            let position = state
+                .announced
+                .iter()
+                .position(|s| s.instance == instance && s.service_type == service_type);
+            match position {
+                Some(i) => state.announced.remove(i),
+                None => return Ok(()),
+            }
+        };
+        let records = ServiceRecords::new(&service, self.shared.host_addr, 0)?;
+        self.shared.send(&records.announcement()).await
+    }
+}
+
+impl Browser for MdnsDiscovery {
+    async fn browse(&self, service_type: &str) -> Result<Vec<Service>, Error> {
+        self.ask(query(type_name(service_type)?, RecordType::PTR))
+            .await?;
+        Ok(self.shared.state.lock().unwrap().browse(service_type))
+    }
+
+    async fn resolve(&self, instance: &str, service_type: &str) -> Result<Vec<SocketAddr>, Error> {
+        let name = instance_name(instance, service_type)?;
+        if let Some(service) = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .find(instance, service_type)
+        {
+            return Ok(service.socket_addrs());
+        }
+        self.ask(query(name, RecordType::SRV)).await?;
+        let state = self.shared.state.lock().unwrap();
+        state
+            .find(instance, service_type)
+            .map(|service| service.socket_addrs())
+            .ok_or(Error::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A port for a private mDNS group, free when asked for.
+    fn free_port() -> u16 {
+        let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        socket.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires multicast
+    async fn discovers_over_multicast() {
+        let port = free_port();
+        let window = Duration::from_millis(200);
+        let announcer = MdnsDiscovery::on_port(port).unwrap();
+        let browser = MdnsDiscovery::on_port(port).unwrap().with_window(window);
+
+        let service = Service::new("Test API", "_pith-test._tcp", 8080)
+            .with_addr(IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .with_txt("version", "2");
+        announcer.announce(&service).await.unwrap();
+
+        assert_eq!(browser.browse("_pith-test._tcp").await.unwrap(), [service]);
+        assert_eq!(
+            browser
+                .resolve("Test API", "_pith-test._tcp")
+                .await
+                .unwrap(),
+            ["127.0.0.1:8080".parse().unwrap()]
+        );
+
+        announcer
+            .withdraw("Test API", "_pith-test._tcp")
+            .await
+            .unwrap();
+        tokio::time::sleep(window).await;
+        assert!(browser.browse("_pith-test._tcp").await.unwrap().is_empty());
+        assert!(matches!(
+            browser.resolve("Test API", "_pith-test._tcp").await,
+            Err(Error::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,377 @@
+//! DNS-SD records: building them for announced services, answering
+//! queries with them, and learning services from responses.
+
+use hickory_proto::op::{Message, MessageType, Query};
+use hickory_proto::rr::rdata::{A, AAAA, PTR, SRV, TXT};
+use hickory_proto::rr::{Name, RData, Record, RecordType};
+use portals_discovery::{Error, Service};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+/// TTL of announced records, as RFC 6762 recommends for records naming
+/// hosts.
+pub(crate) const TTL: u32 = 120;
+
+/// `<service type>.local.`, the name browsed for instances of a type.
+pub(crate) fn type_name(service_type: &str) -> Result<Name, Error> {
+    let labels: Vec<&str> = service_type.split('.').collect();
+    let valid = labels.len() == 2
+        && labels[0].starts_with('_')
+        && labels[0].len() > 1
+        && matches!(labels[1], "_tcp" | "_udp");
+    if !valid {
+        return Err(Error::InvalidName(service_type.to_string()));
+    }
+    Name::from_ascii(format!("{}.local.", service_type))
+        .map_err(|_| Error::InvalidName(service_type.to_string()))
+}
+
+/// `<instance>.<service type>.local.`; the instance label may hold any
+/// UTF-8, spaces included.
+pub(crate) fn instance_name(instance: &str, service_type: &str) -> Result<Name, Error> {
+    if instance.is_empty() {
+        return Err(Error::InvalidName(instance.to_string()));
+    }
+    type_name(service_type)?
+        .prepend_label(instance.as_bytes())
+        .map_err(|_| Error::InvalidName(instance.to_string()))
+}
+
+/// `<instance>.local.`, with everything but letters and digits replaced by
+/// `-`, as the host the service's SRV record points to.
+fn host_name(instance: &str) -> Result<Name, Error> {
+    let label: String = instance
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    Name::from_ascii(format!("{}.local.", label.trim_matches('-')))
+        .map_err(|_| Error::InvalidName(instance.to_string()))
+}
+
+/// The records announcing `service`, split into those answering a browse
+/// and those to add alongside.
+pub(crate) struct ServiceRecords {
+    pub(crate) ptr: Record,
+    pub(crate) srv: Record,
+    pub(crate) txt: Record,
+    pub(crate) addrs: Vec<Record>,
+}
+
+impl ServiceRecords {
+    /// Build the records for `service`, reachable at `host_addr` unless it
+    /// lists addresses of its own.
+    pub(crate) fn new(service: &Service, host_addr: IpAddr, ttl: u32) -> Result<Self, Error> {
+        let instance = instance_name(&service.instance, &service.service_type)?;
+        let host = host_name(&service.instance)?;
+        let mut txt: Vec<String> = service
+            .txt
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        if txt.is_empty() {
+            // An empty TXT record still holds one empty string.
+            txt.push(String::new());
+        }
+        let addrs = if service.addrs.is_empty() {
+            std::slice::from_ref(&host_addr)
+        } else {
+            &service.addrs
+        };
+        Ok(Self {
+            ptr: Record::from_rdata(
+                type_name(&service.service_type)?,
+                ttl,
+                RData::PTR(PTR(instance.clone())),
+            ),
+            srv: Record::from_rdata(
+                instance.clone(),
+                ttl,
+                RData::SRV(SRV::new(0, 0, service.port, host.clone())),
+            ),
+            txt: Record::from_rdata(instance, ttl, RData::TXT(TXT::new(txt))),
+            addrs: addrs
+                .iter()
+                .map(|addr| {
+                    let rdata = match *addr {
+                        IpAddr::V4(v4) => RData::A(A(v4)),
+                        IpAddr::V6(v6) => RData::AAAA(AAAA(v6)),
+                    };
+                    Record::from_rdata(host.clone(), ttl, rdata)
+                })
+                .collect(),
+        })
+    }
+
+    /// An unsolicited response announcing the service, or withdrawing it
+    /// if the records were built with a TTL of zero.
+    pub(crate) fn announcement(self) -> Message {
+        let mut message = response();
+        message.add_answer(self.ptr);
+        message.add_answer(self.srv);
+        message.add_answer(self.txt);
+        message.add_answers(self.addrs);
+        message
+    }
+}
+
+fn response() -> Message {
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Response)
+        .set_authoritative(true);
+    message
+}
+
+/// A query for `name`.
+pub(crate) fn query(name: Name, record_type: RecordType) -> Message {
+    let mut message = Message::new();
+    message.add_query(Query::query(name, record_type));
+    message
+}
+
+/// A service instance learned from responses.
+#[derive(Debug)]
+struct Seen {
+    port: u16,
+    target: Name,
+    txt: BTreeMap<String, String>,
+}
+
+/// Services announced here and learned from the network.
+#[derive(Debug, Default)]
+pub(crate) struct State {
+    pub(crate) announced: Vec<Service>,
+    /// Keyed by instance and service type.
+    seen: HashMap<(String, String), Seen>,
+    hosts: HashMap<Name, Vec<IpAddr>>,
+}
+
+impl State {
+    /// The response to `query`, if it asks about anything announced here.
+    pub(crate) fn answer(&self, query: &Message, host_addr: IpAddr) -> Option<Message> {
+        let mut message = response();
+        for service in &self.announced {
+            let Ok(records) = ServiceRecords::new(service, host_addr, TTL) else {
+                continue;
+            };
+            for q in query.queries() {
+                let wants =
+                    |t: RecordType| q.query_type() == t || q.query_type() == RecordType::ANY;
+                if wants(RecordType::PTR) && q.name() == records.ptr.name() {
+                    message.add_answer(records.ptr.clone());
+                    message.add_additional(records.srv.clone());
+                    message.add_additional(records.txt.clone());
+                    message.add_additionals(records.addrs.clone());
+                } else if q.name() == records.srv.name() {
+                    if wants(RecordType::SRV) {
+                        message.add_answer(records.srv.clone());
+                        message.add_additionals(records.addrs.clone());
+                    }
+                    if wants(RecordType::TXT) {
+                        message.add_answer(records.txt.clone());
+                    }
+                } else if records.addrs.first().is_some_and(|a| q.name() == a.name()) {
+                    message.add_answers(
+                        records
+                            .addrs
+                            .iter()
+                            .filter(|a| wants(a.record_type()))
+                            .cloned(),
+                    );
+                }
+            }
+        }
+        (!message.answers().is_empty()).then_some(message)
+    }
+
+    /// Take in the records of a response. Records with a TTL of zero
+    /// withdraw what they name.
+    pub(crate) fn learn(&mut self, response: &Message) {
+        let records: Vec<&Record> = response
+            .answers()
+            .iter()
+            .chain(response.additionals())
+            .collect();
+
+        for record in &records {
+            let addr = match record.data() {
+                RData::A(a) => IpAddr::V4(a.0),
+                RData::AAAA(aaaa) => IpAddr::V6(aaaa.0),
+                _ => continue,
+            };
+            let addrs = self.hosts.entry(record.name().clone()).or_default();
+            if record.ttl() == 0 {
+                addrs.retain(|a| *a != addr);
+            } else if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+
+        // SRV records create entries, so take them before the TXT and PTR
+        // records that refer to them.
+        for record in &records {
+            let RData::SRV(srv) = record.data() else {
+                continue;
+            };
+            let Some(key) = split_instance_name(record.name()) else {
+                continue;
+            };
+            if record.ttl() == 0 {
+                self.seen.remove(&key);
+                continue;
+            }
+            let seen = self.seen.entry(key).or_insert_with(|| Seen {
+                port: 0,
+                target: srv.target().clone(),
+                txt: BTreeMap::new(),
+            });
+            seen.port = srv.port();
+            seen.target = srv.target().clone();
+        }
+        for record in &records {
+            match record.data() {
+                RData::TXT(txt) => {
+                    let Some(seen) =
+                        split_instance_name(record.name()).and_then(|key| self.seen.get_mut(&key))
+                    else {
+                        continue;
+                    };
+                    seen.txt = txt
+                        .iter()
+                        .filter_map(|entry| {
+                            let entry = String::from_utf8_lossy(entry);
+                            let (key, value) = entry.split_once('=').unwrap_or((&entry, ""));
+                            (!key.is_empty()).then(|| (key.to_string(), value.to_string()))
+                        })
+                        .collect();
+                }
+                RData::PTR(ptr) if record.ttl() == 0 => {
+                    if let Some(key) = split_instance_name(&ptr.0) {
+                        self.seen.remove(&key);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Every instance of `service_type` announced here or learned, with
+    /// the ones announced here taking precedence.
+    pub(crate) fn browse(&self, service_type: &str) -> Vec<Service> {
+        let mut found: Vec<Service> = self
+            .announced
+            .iter()
+            .filter(|s| s.service_type.eq_ignore_ascii_case(service_type))
+            .cloned()
+            .collect();
+        for ((instance, ty), seen) in &self.seen {
+            let duplicate = found.iter().any(|s| s.instance == *instance);
+            if duplicate || !ty.eq_ignore_ascii_case(service_type) {
+                continue;
+            }
+            found.push(Service {
+                instance: instance.clone(),
+                service_type: ty.clone(),
+                port: seen.port,
+                addrs: self.hosts.get(&seen.target).cloned().unwrap_or_default(),
+                txt: seen.txt.clone(),
+            });
+        }
+        found.sort_by(|a, b| a.instance.cmp(&b.instance));
+        found
+    }
+
+    /// One instance, if it is known and has addresses.
+    pub(crate) fn find(&self, instance: &str, service_type: &str) -> Option<Service> {
+        self.browse(service_type)
+            .into_iter()
+            .find(|s| s.instance == instance && !s.addrs.is_empty())
+    }
+}
+
+/// Split `<instance>.<_service>.<_proto>.local.` into instance and service
+/// type.
+fn split_instance_name(name: &Name) -> Option<(String, String)> {
+    let labels: Vec<&[u8]> = name.iter().collect();
+    let [instance, service, proto, local] = labels[..] else {
+        return None;
+    };
+    if !local.eq_ignore_ascii_case(b"local") {
+        return None;
+    }
+    let service_type = format!(
+        "{}.{}",
+        String::from_utf8_lossy(service),
+        String::from_utf8_lossy(proto)
+    );
+    Some((String::from_utf8_lossy(instance).into_owned(), service_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn service() -> Service {
+        Service::new("Office printer", "_ipp._tcp", 631).with_txt("rp", "printers/office")
+    }
+
+    /// Encode and decode, as if sent over the network.
+    fn wire(message: Message) -> Message {
+        Message::from_vec(&message.to_vec().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn validates_names() {
+        assert!(type_name("_http._tcp").is_ok());
+        assert!(type_name("http._tcp").is_err());
+        assert!(type_name("_http._sctp").is_err());
+        assert!(type_name("_http").is_err());
+        assert!(instance_name("", "_http._tcp").is_err());
+        assert_eq!(
+            split_instance_name(&instance_name("My service", "_http._tcp").unwrap()),
+            Some(("My service".to_string(), "_http._tcp".to_string()))
+        );
+    }
+
+    #[test]
+    fn answers_browse_and_resolve_queries() {
+        let mut state = State::default();
+        state.announced.push(service());
+
+        let browse = wire(query(type_name("_ipp._tcp").unwrap(), RecordType::PTR));
+        let answer = state.answer(&browse, HOST).unwrap();
+        assert_eq!(answer.answers().len(), 1);
+        assert_eq!(answer.additionals().len(), 3);
+
+        let name = instance_name("Office printer", "_ipp._tcp").unwrap();
+        let answer = state
+            .answer(&wire(query(name, RecordType::SRV)), HOST)
+            .unwrap();
+        assert_eq!(answer.answers()[0].record_type(), RecordType::SRV);
+
+        let other = wire(query(type_name("_http._tcp").unwrap(), RecordType::PTR));
+        assert!(state.answer(&other, HOST).is_none());
+    }
+
+    #[test]
+    fn learns_and_forgets_services() {
+        let mut announcer = State::default();
+        announcer.announced.push(service());
+        let browse = wire(query(type_name("_ipp._tcp").unwrap(), RecordType::PTR));
+        let answer = wire(announcer.answer(&browse, HOST).unwrap());
+
+        let mut browser = State::default();
+        browser.learn(&answer);
+        let found = browser.browse("_ipp._tcp");
+        assert_eq!(found, [service().with_addr(HOST)]);
+        assert!(browser.find("Office printer", "_ipp._tcp").is_some());
+        assert!(browser.browse("_http._tcp").is_empty());
+
+        let goodbye = ServiceRecords::new(&service(), HOST, 0).unwrap();
+        browser.learn(&wire(goodbye.announcement()));
+        assert!(browser.browse("_ipp._tcp").is_empty());
+    }
+}
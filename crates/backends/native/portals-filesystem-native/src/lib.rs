@@ -2,7 +2,8 @@
 
 use portals_filesystem::{DirEntry, Directory, Error, FileType, Metadata};
 use portals_io_native::{ReaderStream, WriterStream};
-use std::fs::{self, File, OpenOptions};
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{Seek, Write};
 use std::path::{Path, PathBuf};
 
 /// A capability to access a native directory.
@@ -48,7 +49,6 @@ impl Directory for NativeDir {
     fn open_append(&self, path: &Path) -> Result<impl portals_filesystem::OutputStream, Error> {
         let full_path = self.resolve(path);
         let file = OpenOptions::new()
-            .write(true)
             .create(true)
             .append(true)
             .open(&full_path)?;
@@ -137,6 +137,46 @@ impl Directory for NativeDir {
         fs::rename(&full_from, &full_to)?;
         Ok(())
     }
+
+    fn set_times(&self, path: &Path, accessed: Option<u64>, modified: Option<u64>) -> Result<(), Error> {
+        let full_path = self.resolve(path);
+        let to_time = |secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let mut times = FileTimes::new();
+        if let Some(secs) = accessed {
+            times = times.set_accessed(to_time(secs));
+        }
+        if let Some(secs) = modified {
+            times = times.set_modified(to_time(secs));
+        }
+        File::open(&full_path)?.set_times(times)?;
+        Ok(())
+    }
+
+    fn truncate(&self, path: &Path, len: u64) -> Result<(), Error> {
+        let full_path = self.resolve(path);
+        OpenOptions::new().write(true).open(&full_path)?.set_len(len)?;
+        Ok(())
+    }
+
+    fn allocate(&self, path: &Path, len: u64) -> Result<(), Error> {
+        let full_path = self.resolve(path);
+        let mut file = OpenOptions::new().write(true).open(&full_path)?;
+        let current = file.metadata()?.len();
+        if current >= len {
+            return Ok(());
+        }
+        // `set_len` alone would leave a sparse hole, so write the zeros out.
+        static ZEROS: [u8; 64 * 1024] = [0; 64 * 1024];
+        file.seek(std::io::SeekFrom::Start(current))?;
+        let mut remaining = len - current;
+        while remaining > 0 {
+            let n = remaining.min(ZEROS.len() as u64) as usize;
+            file.write_all(&ZEROS[..n])?;
+            remaining -= n as u64;
+        }
+        file.sync_data()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -246,4 +286,83 @@ mod tests {
         // Cleanup
         fs::remove_dir_all(&temp_dir).unwrap();
     }
+
+    #[test]
+    fn hash_file_works() {
+        use portals_crypto_native::Sha256;
+        use portals_filesystem::Hash;
+
+        let temp_dir = std::env::temp_dir().join("portals-fs-test-5");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let dir = NativeDir::new(&temp_dir);
+
+        // Larger than one read chunk
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        fs::write(temp_dir.join("data.bin"), &data).unwrap();
+        fs::write(temp_dir.join("empty.bin"), b"").unwrap();
+
+        let digest = dir.hash_file::<Sha256>(Path::new("data.bin")).unwrap();
+        assert_eq!(digest, Sha256::hash(&data));
+        let digest = dir.hash_file::<Sha256>(Path::new("empty.bin")).unwrap();
+        assert_eq!(digest, Sha256::hash(b""));
+        assert!(dir.hash_file::<Sha256>(Path::new("missing.bin")).is_err());
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn conformance() {
+        let root = std::env::temp_dir().join("portals-fs-test-conformance");
+        let _ = fs::remove_dir_all(&root);
+        let next = std::cell::Cell::new(0);
+        portals_conformance::filesystem::run(|| {
+            let dir = root.join(next.replace(next.get() + 1).to_string());
+            fs::create_dir_all(&dir).unwrap();
+            NativeDir::new(&dir)
+        });
+
+        // Cleanup
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn times_truncate_allocate() {
+        let temp_dir = std::env::temp_dir().join("portals-fs-test-6");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        let dir = NativeDir::new(&temp_dir);
+        let path = Path::new("file.txt");
+        fs::write(temp_dir.join(path), b"hello world").unwrap();
+
+        dir.set_times(path, Some(1_000_000_000), Some(1_500_000_000)).unwrap();
+        let meta = dir.metadata(path).unwrap();
+        assert_eq!(meta.accessed, Some(1_000_000_000));
+        assert_eq!(meta.modified, Some(1_500_000_000));
+        dir.set_times(path, None, Some(1_600_000_000)).unwrap();
+        let meta = dir.metadata(path).unwrap();
+        assert_eq!(meta.accessed, Some(1_000_000_000));
+        assert_eq!(meta.modified, Some(1_600_000_000));
+
+        dir.truncate(path, 5).unwrap();
+        assert_eq!(fs::read(temp_dir.join(path)).unwrap(), b"hello");
+        dir.truncate(path, 7).unwrap();
+        assert_eq!(fs::read(temp_dir.join(path)).unwrap(), b"hello\0\0");
+
+        dir.allocate(path, 200_000).unwrap();
+        let data = fs::read(temp_dir.join(path)).unwrap();
+        assert_eq!(data.len(), 200_000);
+        assert_eq!(&data[..5], b"hello");
+        assert!(data[5..].iter().all(|&b| b == 0));
+        dir.allocate(path, 10).unwrap();
+        assert_eq!(dir.metadata(path).unwrap().size, 200_000);
+
+        assert!(matches!(dir.truncate(Path::new("missing"), 0), Err(Error::Io(_))));
+
+        // Cleanup
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
 }
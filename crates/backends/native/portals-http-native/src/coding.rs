@@ -0,0 +1,123 @@
+//! `gzip` and `deflate` content codings for [`Compression`], using flate2.
+//!
+//! [`Compression`]: portals_http1::Compression
+
+use flate2::read::{DeflateDecoder, GzEncoder, MultiGzDecoder, ZlibDecoder, ZlibEncoder};
+use portals_http1::{Codec, Error};
+use std::io::Read;
+
+/// The `gzip` coding.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Gzip;
+
+impl Codec for Gzip {
+    fn name(&self) -> &str {
+        "gzip"
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        encode(GzEncoder::new(data, flate2::Compression::default()))
+    }
+
+    fn decode(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+        decode(MultiGzDecoder::new(data), limit)
+    }
+}
+
+/// The `deflate` coding: zlib-wrapped DEFLATE.
+///
+/// Decoding also accepts raw DEFLATE data, which some servers send instead.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Deflate;
+
+impl Codec for Deflate {
+    fn name(&self) -> &str {
+        "deflate"
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        encode(ZlibEncoder::new(data, flate2::Compression::default()))
+    }
+
+    fn decode(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+        match decode(ZlibDecoder::new(data), limit) {
+            Err(Error::InvalidEncoding) => decode(DeflateDecoder::new(data), limit),
+            result => result,
+        }
+    }
+}
+
+fn encode(mut encoder: impl Read) -> Vec<u8> {
+    let mut out = Vec::new();
+    // Reading from a slice can't fail.
+    encoder.read_to_end(&mut out).expect("read from slice");
+    out
+}
+
+/// Read `decoder` to the end, stopping once the output passes `limit`.
+fn decode(decoder: impl Read, limit: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|_| Error::InvalidEncoding)?;
+    if out.len() > limit {
+        return Err(Error::BodyTooLarge);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_http1::{Compression, Headers, Method, Request, Response};
+    use std::io::Write;
+
+    #[test]
+    fn round_trips() {
+        let body = b"compressible ".repeat(100);
+        for codec in [&Gzip as &dyn Codec, &Deflate] {
+            let encoded = codec.encode(&body);
+            assert!(encoded.len() < body.len() / 4, "{}", codec.name());
+            assert_eq!(codec.decode(&encoded, body.len()).unwrap(), body);
+            assert!(matches!(
+                codec.decode(b"not compressed", 1000),
+                Err(Error::InvalidEncoding)
+            ));
+        }
+
+        // Raw DEFLATE is accepted as `deflate`.
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+        raw.write_all(b"raw").unwrap();
+        assert_eq!(Deflate.decode(&raw.finish().unwrap(), 3).unwrap(), b"raw");
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        let bomb = Gzip.encode(&vec![0; 1 << 20]);
+        assert!(bomb.len() < 2048);
+        assert!(matches!(Gzip.decode(&bomb, 4096), Err(Error::BodyTooLarge)));
+
+        let compression = Compression::new().codec(Gzip).max_decoded_size(4096);
+        let mut response = Response::new(200)
+            .header("content-encoding", "gzip")
+            .body(bomb);
+        assert!(matches!(
+            compression.decode_response(&mut response),
+            Err(Error::BodyTooLarge)
+        ));
+
+        let mut request = Request {
+            method: Method::Post,
+            path: "/".to_string(),
+            headers: Headers::from([("content-encoding", "deflate")]),
+            body: Deflate.encode(&[0; 4096]),
+        };
+        Compression::new()
+            .codec(Deflate)
+            .max_decoded_size(4096)
+            .decode_request(&mut request)
+            .unwrap();
+        assert_eq!(request.body.len(), 4096);
+    }
+}
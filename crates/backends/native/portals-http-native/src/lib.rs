@@ -1,8 +1,25 @@
-//! Native implementation of portals-http using reqwest.
+//! Native implementation of portals-http using reqwest, with an HTTP/1.1
+//! server on tokio and `gzip`/`deflate` codecs for portals-http1.
 
-use portals_http::{Error, HttpClient, Method, Request, Response};
+mod coding;
+mod server;
+
+pub use coding::{Deflate, Gzip};
+pub use portals_observe::REQUEST_ID;
+pub use server::NativeHttpServer;
+
+use futures_util::stream::{BoxStream, StreamExt};
+use portals_http::{
+    Body, ChunkSource, ChunkStream, Error, HttpClient, Method, Request, Response,
+    StreamingHttpClient, StreamingRequest, StreamingResponse,
+};
+use portals_io::InputStream;
+use std::task::{Context, Poll};
 
 /// HTTP client using reqwest.
+///
+/// Implements [`StreamingHttpClient`] too, streaming response bodies as
+/// reqwest receives them.
 #[derive(Debug, Clone)]
 pub struct ReqwestClient {
     inner: reqwest::Client,
@@ -26,8 +43,9 @@ impl ReqwestClient {
     }
 }
 
-impl HttpClient for ReqwestClient {
-    async fn send(&self, request: Request) -> Result<Response, Error> {
+impl ReqwestClient {
+    /// Send `request` and return reqwest's response once its head arrives.
+    async fn execute(&self, request: Request) -> Result<reqwest::Response, Error> {
         let method = match request.method {
             Method::Get => reqwest::Method::GET,
             Method::Head => reqwest::Method::HEAD,
@@ -48,35 +66,226 @@ impl HttpClient for ReqwestClient {
             req = req.body(body);
         }
 
-        let resp = req.send().await.map_err(|e| {
-            if e.is_connect() {
-                Error::ConnectionFailed
-            } else if e.is_timeout() {
-                Error::Timeout
-            } else {
-                Error::ProtocolError
-            }
-        })?;
+        req.send().await.map_err(from_reqwest)
+    }
+}
 
+impl HttpClient for ReqwestClient {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        let resp = self.execute(request).await?;
         let status = resp.status().as_u16();
-        let headers = resp
-            .headers()
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
-        let body = resp.bytes().await.map_err(|_| Error::ProtocolError)?.to_vec();
+        let headers = response_headers(&resp);
+        let body = resp
+            .bytes()
+            .await
+            .map_err(|_| Error::ProtocolError)?
+            .to_vec();
 
         Ok(Response {
             status,
             headers,
             body,
+            redirects: Vec::new(),
+        })
+    }
+}
+
+impl StreamingHttpClient for ReqwestClient {
+    type Body = ChunkStream<ReqwestBody>;
+
+    /// Read the request body into memory, then send it and stream the
+    /// response body as reqwest receives it.
+    async fn send_streaming<S: InputStream>(
+        &self,
+        request: StreamingRequest<S>,
+    ) -> Result<StreamingResponse<Self::Body>, Error> {
+        let resp = self.execute(request.buffer().await?).await?;
+        let status = resp.status().as_u16();
+        let headers = response_headers(&resp);
+        let chunks = resp
+            .bytes_stream()
+            .map(|chunk| chunk.map(|bytes| bytes.to_vec()).map_err(from_reqwest))
+            .boxed();
+
+        Ok(StreamingResponse {
+            status,
+            headers,
+            body: Body::Stream(ChunkStream::new(ReqwestBody(chunks))),
+            redirects: Vec::new(),
         })
     }
 }
 
+/// A response body streamed from reqwest, read through [`ChunkStream`].
+pub struct ReqwestBody(BoxStream<'static, Result<Vec<u8>, Error>>);
+
+impl std::fmt::Debug for ReqwestBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReqwestBody").finish_non_exhaustive()
+    }
+}
+
+impl ChunkSource for ReqwestBody {
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>, Error>>> {
+        self.0.poll_next_unpin(cx)
+    }
+}
+
+fn response_headers(resp: &reqwest::Response) -> portals_http::Headers {
+    resp.headers()
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+        .collect()
+}
+
+fn from_reqwest(e: reqwest::Error) -> Error {
+    if e.is_connect() {
+        Error::ConnectionFailed
+    } else if e.is_timeout() {
+        Error::Timeout
+    } else {
+        Error::ProtocolError
+    }
+}
+
+/// Client wrapper that tags outgoing requests with a request ID.
+///
+/// Requests that already carry the header keep it, so an ID received by a
+/// server can be propagated to downstream calls by setting it explicitly;
+/// otherwise a fresh ID is generated.
+///
+/// ```ignore
+/// let ids = SnowflakeGenerator::new(1)?;
+/// let client = RequestIdClient::new(ReqwestClient::new(), move || {
+///     ids.next_id().unwrap().to_string()
+/// });
+/// ```
+pub struct RequestIdClient<C, G> {
+    inner: C,
+    generate: G,
+    header: String,
+}
+
+impl<C, G> RequestIdClient<C, G>
+where
+    C: HttpClient,
+    G: Fn() -> String,
+{
+    /// Wrap a client with an ID generator.
+    pub fn new(inner: C, generate: G) -> Self {
+        Self {
+            inner,
+            generate,
+            header: REQUEST_ID.to_string(),
+        }
+    }
+
+    /// Use a different header name.
+    pub fn header(mut self, name: impl Into<String>) -> Self {
+        self.header = name.into();
+        self
+    }
+
+    /// Get the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C, G> HttpClient for RequestIdClient<C, G>
+where
+    C: HttpClient,
+    G: Fn() -> String,
+{
+    async fn send(&self, mut request: Request) -> Result<Response, Error> {
+        if !request.headers.contains_key(&self.header) {
+            request
+                .headers
+                .insert(self.header.clone(), (self.generate)());
+        }
+        self.inner.send(request).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use portals_http_mock::MockHttpClient;
+
+    #[tokio::test]
+    async fn request_id_client_sets_header() {
+        let mock = MockHttpClient::new();
+        let client = RequestIdClient::new(mock.clone(), || "generated".to_string());
+
+        let mut request = Request {
+            method: Method::Get,
+            url: "http://example.com/".to_string(),
+            headers: Default::default(),
+            body: None,
+        };
+        client.send(request.clone()).await.unwrap();
+
+        request
+            .headers
+            .insert(REQUEST_ID.to_string(), "upstream".to_string());
+        client.send(request).await.unwrap();
+
+        let sent = mock.requests();
+        assert_eq!(sent[0].headers.get(REQUEST_ID), Some("generated"));
+        assert_eq!(sent[1].headers.get(REQUEST_ID), Some("upstream"));
+    }
+
+    #[tokio::test]
+    async fn streams_response_bodies() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (first_read, mut wait_for_read) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            let head = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n";
+            stream.write_all(head).await.unwrap();
+            stream.write_all(b"6\r\nfirst \r\n").await.unwrap();
+            // Hold the rest back until the client has read the first chunk.
+            wait_for_read.recv().await;
+            stream.write_all(b"6\r\nsecond\r\n0\r\n\r\n").await.unwrap();
+        });
+
+        let request = Request {
+            method: Method::Get,
+            url: format!("http://{}/", addr),
+            headers: Default::default(),
+            body: None,
+        };
+        let response = ReqwestClient::new()
+            .send_streaming(StreamingRequest::<portals_http::EmptyStream>::from(request))
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        let Body::Stream(mut body) = response.body else {
+            panic!("expected a streamed body");
+        };
+
+        let mut chunks = Vec::new();
+        let mut buf = [0; 64];
+        loop {
+            match body.read_into(&mut buf) {
+                Ok(0) => body.subscribe().await,
+                Ok(n) => {
+                    chunks.push(buf[..n].to_vec());
+                    let _ = first_read.try_send(());
+                }
+                Err(portals_io::StreamError::Closed) => break,
+                Err(e) => panic!("read failed: {}", e),
+            }
+        }
+        assert_eq!(chunks, [b"first ".to_vec(), b"second".to_vec()]);
+    }
 
     // Note: These tests require network access
     // In a real test suite, you'd use a mock server
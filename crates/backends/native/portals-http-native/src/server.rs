@@ -0,0 +1,387 @@
+//! HTTP/1.1 server on a tokio listener.
+
+use portals_http::{Error, HttpHandler, HttpServer};
+use portals_http1::Http1Connection;
+use portals_signals::Shutdown;
+use portals_sockets::{TcpListener, TcpStream};
+use portals_sockets_native::NativeTcpListener;
+use std::future::{Future, poll_fn};
+use std::net::SocketAddr;
+use std::pin::{Pin, pin};
+use std::task::Poll;
+use std::time::Duration;
+
+/// A connection being served, polled by the accept loop.
+type Connection<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// How long to stop accepting after an accept error that isn't about one
+/// connection, such as running out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// HTTP/1.1 server answering requests on an already-bound listener.
+///
+/// Connections are served concurrently on the task that calls
+/// [`serve`](HttpServer::serve), so handlers need not be `Send` or
+/// `'static`. Each connection is kept alive for as many requests as the
+/// client makes, and driven by [`Http1Connection::serve_http`]: malformed
+/// requests are answered with a 4xx status, and requests with methods
+/// [`Method`](portals_http::Method) can't express or with a
+/// `transfer-encoding` with `501 Not Implemented`.
+///
+/// Failing to accept a connection doesn't stop the server. A connection
+/// that was reset before it was accepted is skipped; other errors, such as
+/// running out of file descriptors, pause accepting for a moment while the
+/// connections already accepted carry on.
+#[derive(Debug)]
+pub struct NativeHttpServer {
+    listener: NativeTcpListener,
+}
+
+impl NativeHttpServer {
+    /// Serve on `listener`.
+    pub fn new(listener: NativeTcpListener) -> Self {
+        Self { listener }
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr().map_err(socket_error)
+    }
+
+    /// Like [`serve`](HttpServer::serve), but stops when `shutdown` is
+    /// triggered.
+    ///
+    /// Once triggered, no new connections are accepted and idle keep-alive
+    /// connections are closed. Requests already being answered get up to
+    /// `grace` to finish; the function then returns `Ok(true)` if they all
+    /// finished, or `Ok(false)` if some were cut off at the deadline.
+    pub async fn serve_with_shutdown<H: HttpHandler>(
+        &self,
+        handler: &H,
+        shutdown: &Shutdown,
+        grace: Duration,
+    ) -> Result<bool, Error> {
+        let mut connections = Vec::new();
+        accept(&self.listener, handler, Some(shutdown), &mut connections).await;
+        let drained = poll_fn(|cx| {
+            connections.retain_mut(|connection| connection.as_mut().poll(cx).is_pending());
+            if connections.is_empty() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        });
+        Ok(tokio::time::timeout(grace, drained).await.is_ok())
+    }
+}
+
+/// Accept connections on `listener` into `connections` until `shutdown` is
+/// triggered, polling the ones already accepted meanwhile.
+async fn accept<'a, L: TcpListener, H: HttpHandler>(
+    listener: &'a L,
+    handler: &'a H,
+    shutdown: Option<&'a Shutdown>,
+    connections: &mut Vec<Connection<'a>>,
+) {
+    let mut stopped = pin!(async {
+        match shutdown {
+            Some(shutdown) => shutdown.wait().await,
+            None => std::future::pending().await,
+        }
+    });
+    loop {
+        let Some(accepted) = alongside(listener.accept(), stopped.as_mut(), connections).await
+        else {
+            return;
+        };
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) if is_connection_error(&e) => continue,
+            Err(_) => {
+                let pause = tokio::time::sleep(ACCEPT_BACKOFF);
+                if alongside(pause, stopped.as_mut(), connections)
+                    .await
+                    .is_none()
+                {
+                    return;
+                }
+                continue;
+            }
+        };
+        let guard = match shutdown.map(Shutdown::track) {
+            Some(None) => return,
+            Some(guard) => guard,
+            None => None,
+        };
+        connections.push(Box::pin(async move {
+            let _guard = guard;
+            serve_connection(stream, handler, shutdown).await;
+        }));
+    }
+}
+
+/// Run `future` while polling `connections`, or return `None` once
+/// `stopped` is ready.
+async fn alongside<T>(
+    future: impl Future<Output = T>,
+    mut stopped: Pin<&mut impl Future<Output = ()>>,
+    connections: &mut Vec<Connection<'_>>,
+) -> Option<T> {
+    let mut future = pin!(future);
+    poll_fn(|cx| {
+        connections.retain_mut(|connection| connection.as_mut().poll(cx).is_pending());
+        if stopped.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        future.as_mut().poll(cx).map(Some)
+    })
+    .await
+}
+
+/// Whether an accept error is about the one connection being accepted,
+/// rather than the listener or the process.
+fn is_connection_error(error: &portals_sockets::Error) -> bool {
+    use std::io::ErrorKind;
+    match error {
+        portals_sockets::Error::ConnectionAborted
+        | portals_sockets::Error::ConnectionReset
+        | portals_sockets::Error::ConnectionRefused => true,
+        portals_sockets::Error::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::Interrupted
+        ),
+        _ => false,
+    }
+}
+
+impl HttpServer for NativeHttpServer {
+    async fn serve<H: HttpHandler>(&self, handler: &H) -> Result<(), Error> {
+        accept(&self.listener, handler, None, &mut Vec::new()).await;
+        Ok(())
+    }
+}
+
+/// Answer requests on one connection until either side closes it, or until
+/// `shutdown` is triggered while the connection is idle.
+async fn serve_connection<S: TcpStream, H: HttpHandler>(
+    stream: S,
+    handler: &H,
+    shutdown: Option<&Shutdown>,
+) {
+    let mut conn = Http1Connection::server(stream);
+    let stop = async {
+        match shutdown {
+            Some(shutdown) => shutdown.wait().await,
+            None => std::future::pending().await,
+        }
+    };
+    // I/O errors just end the connection.
+    let _ = conn.serve_http(handler, stop).await;
+    let _ = conn.get_mut().shutdown();
+}
+
+fn socket_error(e: portals_sockets::Error) -> Error {
+    match e {
+        portals_sockets::Error::Io(e) => Error::Io(e),
+        e => Error::Other(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_http::{Request, Response};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Answers with the method, URL, and body it was sent, and an `x-seen`
+    /// header for each `x-a` header.
+    struct Echo;
+
+    impl HttpHandler for Echo {
+        async fn handle(&self, request: Request) -> Response {
+            let body = format!(
+                "{:?} {} {}",
+                request.method,
+                request.url,
+                String::from_utf8_lossy(&request.body.unwrap_or_default())
+            );
+            Response {
+                status: 200,
+                headers: request
+                    .headers
+                    .get_all("x-a")
+                    .map(|v| ("x-seen", v))
+                    .collect(),
+                body: body.into_bytes(),
+                redirects: Vec::new(),
+            }
+        }
+    }
+
+    async fn exchange(addr: SocketAddr, raw: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn serves_requests() {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let server = NativeHttpServer::new(listener);
+        let addr = server.local_addr().unwrap();
+
+        let client = async {
+            let response = exchange(
+                addr,
+                "POST /items?x=1 HTTP/1.1\r\nhost: a\r\nX-A: 1\r\nx-a: 2\r\n\
+                 content-length: 3\r\nconnection: close\r\n\r\nabc",
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+            assert!(
+                response.contains("x-seen: 1\r\nx-seen: 2\r\n"),
+                "{}",
+                response
+            );
+            assert!(
+                response.ends_with("\r\n\r\nPost /items?x=1 abc"),
+                "{}",
+                response
+            );
+
+            let response = exchange(
+                addr,
+                "TRACE / HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n",
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 501 "), "{}", response);
+
+            let response = exchange(addr, "nonsense\r\n\r\n").await;
+            assert!(response.starts_with("HTTP/1.1 400 "), "{}", response);
+
+            // Either would let a smuggled request follow the body.
+            let response = exchange(
+                addr,
+                "POST / HTTP/1.1\r\nhost: a\r\ntransfer-encoding: chunked\r\n\r\n\
+                 0\r\n\r\nGET /smuggled HTTP/1.1\r\nhost: a\r\n\r\n",
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 501 "), "{}", response);
+            assert!(!response.contains("/smuggled"), "{}", response);
+            let response = exchange(
+                addr,
+                "POST / HTTP/1.1\r\nhost: a\r\ncontent-length: 0\r\ncontent-length: 28\r\n\r\n\
+                 GET /smuggled HTTP/1.1\r\n\r\n",
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 400 "), "{}", response);
+            assert!(!response.contains("/smuggled"), "{}", response);
+        };
+        tokio::select! {
+            result = server.serve(&Echo) => panic!("server stopped: {:?}", result),
+            () = client => {}
+        }
+    }
+
+    /// A listener whose accepts fail with `errors`, in order, after the
+    /// first one succeeds.
+    struct Flaky {
+        inner: NativeTcpListener,
+        accepted: std::cell::Cell<bool>,
+        errors: std::cell::RefCell<Vec<portals_sockets::Error>>,
+    }
+
+    impl TcpListener for Flaky {
+        type Stream = <NativeTcpListener as TcpListener>::Stream;
+
+        async fn accept(&self) -> Result<(Self::Stream, SocketAddr), portals_sockets::Error> {
+            if self.accepted.replace(true) && !self.errors.borrow().is_empty() {
+                return Err(self.errors.borrow_mut().remove(0));
+            }
+            self.inner.accept().await
+        }
+
+        fn local_addr(&self) -> Result<SocketAddr, portals_sockets::Error> {
+            self.inner.local_addr()
+        }
+    }
+
+    #[tokio::test]
+    async fn accept_errors_do_not_stop_the_server() {
+        let listener = Flaky {
+            inner: NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap(),
+            accepted: Default::default(),
+            errors: std::cell::RefCell::new(vec![
+                // Too many open files.
+                std::io::Error::from_raw_os_error(24).into(),
+                portals_sockets::Error::ConnectionAborted,
+            ]),
+        };
+        let addr = listener.local_addr().unwrap();
+
+        let client = async {
+            // Accepted before the errors, and kept while they happen.
+            let mut open = tokio::net::TcpStream::connect(addr).await.unwrap();
+            for path in ["/a", "/b"] {
+                let request = format!("GET {} HTTP/1.1\r\nhost: a\r\n\r\n", path);
+                open.write_all(request.as_bytes()).await.unwrap();
+                let body = format!("Get {} ", path);
+                let mut response = Vec::new();
+                while !response.ends_with(body.as_bytes()) {
+                    let mut buf = [0; 256];
+                    let n = open.read(&mut buf).await.unwrap();
+                    assert_ne!(n, 0, "{}", String::from_utf8_lossy(&response));
+                    response.extend_from_slice(&buf[..n]);
+                }
+                assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+                tokio::time::sleep(ACCEPT_BACKOFF / 2).await;
+            }
+
+            let response = exchange(
+                addr,
+                "GET /c HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n",
+            )
+            .await;
+            assert!(response.ends_with("Get /c "), "{}", response);
+        };
+        let mut connections = Vec::new();
+        tokio::select! {
+            () = accept(&listener, &Echo, None, &mut connections) => panic!("server stopped"),
+            () = client => {}
+        }
+        assert!(listener.errors.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_accepting_and_drains() {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let server = NativeHttpServer::new(listener);
+        let addr = server.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+
+        let client = async {
+            // An idle keep-alive connection must not hold up shutdown.
+            let mut idle = tokio::net::TcpStream::connect(addr).await.unwrap();
+            idle.write_all(b"GET /a HTTP/1.1\r\nhost: a\r\nx-a: 1\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0; 17];
+            idle.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"HTTP/1.1 200 OK\r\n");
+
+            shutdown.trigger();
+            let mut rest = Vec::new();
+            idle.read_to_end(&mut rest).await.unwrap();
+        };
+        let (served, ()) = tokio::join!(
+            server.serve_with_shutdown(&Echo, &shutdown, Duration::from_secs(5)),
+            client
+        );
+        assert!(served.unwrap());
+    }
+}
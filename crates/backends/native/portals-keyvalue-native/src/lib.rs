@@ -141,4 +141,9 @@ mod tests {
         assert_eq!(store.increment("counter", 5).await.unwrap(), 6);
         assert_eq!(store.increment("counter", -2).await.unwrap(), 4);
     }
+
+    #[tokio::test]
+    async fn conformance() {
+        portals_conformance::keyvalue::run_atomic(MemoryStore::new).await;
+    }
 }
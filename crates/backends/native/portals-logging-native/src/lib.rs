@@ -109,9 +109,88 @@ impl Logger for StderrLogger {
     }
 }
 
+/// A logger that adds context fields to every record.
+///
+/// Useful for correlation: wrap a logger with the current request ID and
+/// pass it down, and every record logged through it carries the ID.
+///
+/// ```ignore
+/// let logger = ContextLogger::new(&base).with_field("request_id", id);
+/// logger.info("http", "handling request");
+/// ```
+#[derive(Debug)]
+pub struct ContextLogger<L> {
+    inner: L,
+    fields: Vec<(String, String)>,
+}
+
+impl<L: Logger> ContextLogger<L> {
+    /// Wrap a logger.
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a context field.
+    pub fn with_field(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.push((key.into(), value.into()));
+        self
+    }
+}
+
+impl<L: Logger> Logger for ContextLogger<L> {
+    fn log(&self, record: &Record) {
+        if self.fields.is_empty() {
+            return self.inner.log(record);
+        }
+        let mut record = record.clone();
+        record.fields.splice(0..0, self.fields.iter().cloned());
+        self.inner.log(&record);
+    }
+
+    fn enabled(&self, level: Level) -> bool {
+        self.inner.enabled(level)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CapturingLogger {
+        records: Mutex<Vec<Record>>,
+    }
+
+    impl Logger for CapturingLogger {
+        fn log(&self, record: &Record) {
+            self.records.lock().unwrap().push(record.clone());
+        }
+
+        fn enabled(&self, _level: Level) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn context_logger_adds_fields() {
+        let base = CapturingLogger::default();
+        let logger = ContextLogger::new(&base).with_field("request_id", "abc");
+
+        logger.log(&Record::new(Level::Info, "test", "message").field("key", "value"));
+
+        let records = base.records.lock().unwrap();
+        assert_eq!(
+            records[0].fields,
+            vec![
+                ("request_id".to_string(), "abc".to_string()),
+                ("key".to_string(), "value".to_string()),
+            ]
+        );
+    }
 
     #[test]
     fn stderr_logger_works() {
@@ -0,0 +1,237 @@
+//! Admonition (callout) blocks.
+//!
+//! pulldown-cmark parses GitHub alerts (`> [!NOTE]`) natively. Fenced
+//! `:::note` blocks are rewritten into alerts before parsing, so both
+//! syntaxes reach the renderer as `BlockQuote(Some(kind))`.
+
+use portals_markdown::AdmonitionKind;
+use pulldown_cmark::{BlockQuoteKind, CowStr, Event, Tag, TagEnd};
+
+/// Source rewritten by [`expand_fences`].
+#[derive(Debug, Clone)]
+pub(crate) struct Expanded {
+    pub(crate) text: String,
+    /// For each line of `text`: its start, the length of the `> ` prefix
+    /// added to it, and the start and length of the source line it came
+    /// from.
+    lines: Vec<(usize, usize, usize, usize)>,
+}
+
+impl Expanded {
+    /// Map a byte offset in the rewritten text back to the source.
+    pub(crate) fn source_offset(&self, offset: usize) -> usize {
+        let i = self
+            .lines
+            .partition_point(|&(start, ..)| start <= offset)
+            .saturating_sub(1);
+        let (start, prefix, source_start, source_len) = self.lines[i];
+        source_start + offset.saturating_sub(start + prefix).min(source_len)
+    }
+}
+
+/// Rewrite `:::kind` ... `:::` fences into GitHub alert blockquotes.
+///
+/// Returns `None` if the source has no such fences. Fences inside code
+/// blocks and fences naming an unknown kind are left alone.
+pub(crate) fn expand_fences(source: &str) -> Option<Expanded> {
+    if !source.contains(":::") {
+        return None;
+    }
+
+    let mut out = String::with_capacity(source.len() + 64);
+    let mut lines = Vec::new();
+    let mut depth = 0;
+    let mut code_fence: Option<(char, usize)> = None;
+    let mut changed = false;
+    let mut source_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        let line_start = source_start;
+        source_start += line.len();
+        // Rewritten fence lines map onto the fence with no prefix, so
+        // offsets within them stay within the fence.
+        let mut push = |out: &mut String, depth: usize, text: &str, copied: bool| {
+            let prefix = if copied { prefixed_len(depth, text) } else { 0 };
+            lines.push((out.len(), prefix, line_start, line.len()));
+            push_prefixed(out, depth, text);
+        };
+        let content = line.trim_end_matches(['\n', '\r']);
+        let trimmed = content.trim_start_matches(' ');
+        let indent = content.len() - trimmed.len();
+
+        if indent < 4
+            && code_fence.is_none()
+            && let Some(kind) = trimmed.strip_prefix(":::")
+        {
+            let kind = kind.trim();
+            if kind.is_empty() && depth > 0 {
+                // Close, with a blank line so following text is not a
+                // lazy continuation of the quote.
+                depth -= 1;
+                push(&mut out, depth, "", false);
+                changed = true;
+                continue;
+            }
+            if let Some(kind) = AdmonitionKind::from_name(kind) {
+                push(&mut out, depth, "", false);
+                depth += 1;
+                let marker = format!("[!{}]", kind.as_str().to_ascii_uppercase());
+                push(&mut out, depth, &marker, false);
+                changed = true;
+                continue;
+            }
+        }
+
+        code_fence = match code_fence {
+            None => opening_fence(trimmed).filter(|_| indent < 4),
+            Some((c, len)) if indent < 4 && closes_fence(trimmed, c, len) => None,
+            open => open,
+        };
+        push(&mut out, depth, content, true);
+    }
+
+    changed.then_some(Expanded { text: out, lines })
+}
+
+/// Length of the prefix [`push_prefixed`] adds.
+fn prefixed_len(depth: usize, line: &str) -> usize {
+    if line.is_empty() { depth } else { depth * 2 }
+}
+
+fn push_prefixed(out: &mut String, depth: usize, line: &str) {
+    for _ in 0..depth {
+        out.push('>');
+        if !line.is_empty() {
+            out.push(' ');
+        }
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|&ch| ch == c).count();
+    (len >= 3).then_some((c, len))
+}
+
+fn closes_fence(line: &str, c: char, len: usize) -> bool {
+    let run = line.chars().take_while(|&ch| ch == c).count();
+    run >= len && line[run..].trim().is_empty()
+}
+
+/// Map a pulldown-cmark alert kind.
+pub(crate) fn kind(kind: BlockQuoteKind) -> AdmonitionKind {
+    match kind {
+        BlockQuoteKind::Note => AdmonitionKind::Note,
+        BlockQuoteKind::Tip => AdmonitionKind::Tip,
+        BlockQuoteKind::Important => AdmonitionKind::Important,
+        BlockQuoteKind::Warning => AdmonitionKind::Warning,
+        BlockQuoteKind::Caution => AdmonitionKind::Caution,
+    }
+}
+
+/// Render alert blockquotes as classed divs.
+pub(crate) fn html_event(event: Event<'_>) -> Event<'_> {
+    match event {
+        Event::Start(Tag::BlockQuote(Some(k))) => {
+            let kind = kind(k).as_str();
+            let mut title = kind.to_string();
+            title[..1].make_ascii_uppercase();
+            Event::Html(CowStr::from(format!(
+                "<div class=\"admonition {}\">\n<p class=\"admonition-title\">{}</p>\n",
+                kind, title
+            )))
+        }
+        Event::End(TagEnd::BlockQuote(Some(_))) => Event::Html(CowStr::Borrowed("</div>\n")),
+        event => event,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Markdown;
+    use portals_markdown::{
+        Admonition, MarkdownDocument, MarkdownOptions, MarkdownParser, MarkdownRenderer,
+    };
+
+    fn options() -> MarkdownOptions {
+        MarkdownOptions {
+            admonitions: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn expands_fences() {
+        let source = "before\n:::warning\nBe *careful*.\n\n:::\nafter\n";
+        assert_eq!(
+            expand_fences(source).unwrap().text,
+            "before\n\n> [!WARNING]\n> Be *careful*.\n>\n\nafter\n"
+        );
+        assert!(expand_fences("no fences").is_none());
+        assert!(expand_fences(":::unknown\ntext\n:::\n").is_none());
+    }
+
+    #[test]
+    fn ignores_fences_in_code() {
+        let source = "```\n:::note\n```\n";
+        assert!(expand_fences(source).is_none());
+    }
+
+    #[test]
+    fn nested_fences() {
+        let source = ":::note\nouter\n:::tip\ninner\n:::\n:::\n";
+        let expanded = expand_fences(source).unwrap().text;
+        assert_eq!(
+            expanded,
+            "\n> [!NOTE]\n> outer\n>\n> > [!TIP]\n> > inner\n>\n\n"
+        );
+    }
+
+    #[test]
+    fn renders_classed_divs() {
+        let md = Markdown::new();
+        for source in ["> [!NOTE]\n> Read this.\n", ":::note\nRead this.\n:::\n"] {
+            let html = md.render_with_options(source, &options());
+            assert!(html.contains("<div class=\"admonition note\">"), "{}", html);
+            assert!(html.contains("<p class=\"admonition-title\">Note</p>"));
+            assert!(html.contains("<p>Read this.</p>"));
+            assert!(html.trim_end().ends_with("</div>"));
+        }
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let md = Markdown::new();
+        let html = md.render(":::note\ntext\n:::\n");
+        assert!(html.contains(":::note"));
+        assert!(md.parse("> [!NOTE]\n> x\n").admonitions().is_empty());
+    }
+
+    #[test]
+    fn extracts_admonitions() {
+        let doc = Markdown::new().parse_with_options(
+            "> [!CAUTION]\n> Hot.\n\n:::tip\nUse `x`.\n:::\n",
+            &options(),
+        );
+        assert_eq!(
+            doc.admonitions(),
+            vec![
+                Admonition {
+                    kind: AdmonitionKind::Caution,
+                    text: "Hot.".to_string()
+                },
+                Admonition {
+                    kind: AdmonitionKind::Tip,
+                    text: "Use x.".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            doc.source(),
+            "> [!CAUTION]\n> Hot.\n\n:::tip\nUse `x`.\n:::\n"
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! Syntax tree construction.
+//!
+//! [`build`] folds pulldown-cmark's offset events into a [`Node`] tree, and
+//! [`events`] flattens a tree back into events so rendering and re-emission
+//! work on transformed trees too.
+
+use portals_markdown::{AdmonitionKind, Alignment, Node, NodeKind};
+use pulldown_cmark::{
+    BlockQuoteKind, CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, Tag, TagEnd,
+};
+use std::ops::Range;
+
+use crate::{admonition, heading_level_to_u8};
+
+/// Build a tree from `events`, mapping their offsets into the source with
+/// `offset`.
+pub(crate) fn build<'a>(
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+    source_len: usize,
+    offset: impl Fn(usize) -> usize,
+) -> Node {
+    let mut stack = vec![Node::new(NodeKind::Document, 0..source_len)];
+    // Whether each open tag pushed a node; definition lists and metadata
+    // blocks are never enabled, but would be flattened into their parent.
+    let mut opened = Vec::new();
+
+    for (event, range) in events {
+        let range = offset(range.start)..offset(range.end);
+        let kind = match event {
+            Event::Start(tag) => {
+                let kind = tag_kind(tag);
+                opened.push(kind.is_some());
+                if let Some(kind) = kind {
+                    stack.push(Node::new(kind, range));
+                }
+                continue;
+            }
+            Event::End(_) => {
+                if opened.pop() == Some(true) {
+                    let node = stack.pop().expect("unbalanced events");
+                    top(&mut stack).children.push(node);
+                }
+                continue;
+            }
+            Event::TaskListMarker(checked) => {
+                let item = stack
+                    .iter_mut()
+                    .rev()
+                    .find_map(|node| match &mut node.kind {
+                        NodeKind::Item { checked } => Some(checked),
+                        _ => None,
+                    });
+                if let Some(item) = item {
+                    *item = Some(checked);
+                }
+                continue;
+            }
+            Event::Text(text) => NodeKind::Text(text.to_string()),
+            Event::Code(code) => NodeKind::Code(code.to_string()),
+            Event::InlineMath(tex) => NodeKind::Math {
+                display: false,
+                tex: tex.to_string(),
+            },
+            Event::DisplayMath(tex) => NodeKind::Math {
+                display: true,
+                tex: tex.to_string(),
+            },
+            Event::Html(html) | Event::InlineHtml(html) => NodeKind::Html(html.to_string()),
+            Event::FootnoteReference(label) => NodeKind::FootnoteReference(label.to_string()),
+            Event::SoftBreak => NodeKind::SoftBreak,
+            Event::HardBreak => NodeKind::HardBreak,
+            Event::Rule => NodeKind::ThematicBreak,
+        };
+        top(&mut stack).children.push(Node::new(kind, range));
+    }
+
+    stack.pop().expect("root node")
+}
+
+fn top(stack: &mut [Node]) -> &mut Node {
+    stack.last_mut().expect("root node")
+}
+
+fn tag_kind(tag: Tag<'_>) -> Option<NodeKind> {
+    Some(match tag {
+        Tag::Paragraph => NodeKind::Paragraph,
+        Tag::Heading { level, id, .. } => NodeKind::Heading {
+            level: heading_level_to_u8(level),
+            id: id.map(|id| id.to_string()),
+        },
+        Tag::BlockQuote(None) => NodeKind::BlockQuote,
+        Tag::BlockQuote(Some(kind)) => NodeKind::Admonition(admonition::kind(kind)),
+        Tag::CodeBlock(CodeBlockKind::Fenced(info)) => NodeKind::CodeBlock {
+            lang: (!info.is_empty()).then(|| info.to_string()),
+        },
+        Tag::CodeBlock(CodeBlockKind::Indented) => NodeKind::CodeBlock { lang: None },
+        Tag::HtmlBlock => NodeKind::HtmlBlock,
+        Tag::List(start) => NodeKind::List { start },
+        Tag::Item => NodeKind::Item { checked: None },
+        Tag::FootnoteDefinition(label) => NodeKind::FootnoteDefinition {
+            label: label.to_string(),
+        },
+        Tag::Table(alignments) => NodeKind::Table {
+            alignments: alignments.into_iter().map(alignment).collect(),
+        },
+        Tag::TableHead => NodeKind::TableHead,
+        Tag::TableRow => NodeKind::TableRow,
+        Tag::TableCell => NodeKind::TableCell,
+        Tag::Emphasis => NodeKind::Emphasis,
+        Tag::Strong => NodeKind::Strong,
+        Tag::Strikethrough => NodeKind::Strikethrough,
+        Tag::Link {
+            dest_url, title, ..
+        } => NodeKind::Link {
+            url: dest_url.to_string(),
+            title: title.to_string(),
+        },
+        Tag::Image {
+            dest_url, title, ..
+        } => NodeKind::Image {
+            url: dest_url.to_string(),
+            title: title.to_string(),
+        },
+        Tag::DefinitionList
+        | Tag::DefinitionListTitle
+        | Tag::DefinitionListDefinition
+        | Tag::MetadataBlock(_) => return None,
+    })
+}
+
+fn alignment(alignment: pulldown_cmark::Alignment) -> Alignment {
+    match alignment {
+        pulldown_cmark::Alignment::None => Alignment::None,
+        pulldown_cmark::Alignment::Left => Alignment::Left,
+        pulldown_cmark::Alignment::Center => Alignment::Center,
+        pulldown_cmark::Alignment::Right => Alignment::Right,
+    }
+}
+
+/// Flatten `node` back into pulldown-cmark events.
+///
+/// A [`NodeKind::Document`] node contributes only its children, so any
+/// subtree can be rendered on its own.
+pub(crate) fn events(node: &Node) -> Vec<Event<'_>> {
+    let mut out = Vec::new();
+    push_events(node, &mut out);
+    out
+}
+
+fn push_events<'a>(node: &'a Node, out: &mut Vec<Event<'a>>) {
+    let leaf = match &node.kind {
+        NodeKind::Text(text) => Some(Event::Text(borrowed(text))),
+        NodeKind::Code(code) => Some(Event::Code(borrowed(code))),
+        NodeKind::Html(html) => Some(Event::Html(borrowed(html))),
+        NodeKind::Math { display, tex } => Some(if *display {
+            Event::DisplayMath(borrowed(tex))
+        } else {
+            Event::InlineMath(borrowed(tex))
+        }),
+        NodeKind::FootnoteReference(label) => Some(Event::FootnoteReference(borrowed(label))),
+        NodeKind::SoftBreak => Some(Event::SoftBreak),
+        NodeKind::HardBreak => Some(Event::HardBreak),
+        NodeKind::ThematicBreak => Some(Event::Rule),
+        _ => None,
+    };
+    if let Some(event) = leaf {
+        out.push(event);
+        return;
+    }
+
+    let tag = match &node.kind {
+        NodeKind::Document => None,
+        NodeKind::Paragraph => Some(Tag::Paragraph),
+        NodeKind::Heading { level, id } => Some(Tag::Heading {
+            level: heading_level(*level),
+            id: id.as_deref().map(borrowed),
+            classes: Vec::new(),
+            attrs: Vec::new(),
+        }),
+        NodeKind::BlockQuote => Some(Tag::BlockQuote(None)),
+        NodeKind::Admonition(kind) => Some(Tag::BlockQuote(Some(blockquote_kind(*kind)))),
+        NodeKind::CodeBlock { lang } => Some(Tag::CodeBlock(CodeBlockKind::Fenced(borrowed(
+            lang.as_deref().unwrap_or(""),
+        )))),
+        NodeKind::HtmlBlock => Some(Tag::HtmlBlock),
+        NodeKind::List { start } => Some(Tag::List(*start)),
+        NodeKind::Item { .. } => Some(Tag::Item),
+        NodeKind::FootnoteDefinition { label } => Some(Tag::FootnoteDefinition(borrowed(label))),
+        NodeKind::Table { alignments } => Some(Tag::Table(
+            alignments.iter().map(|&a| pulldown_alignment(a)).collect(),
+        )),
+        NodeKind::TableHead => Some(Tag::TableHead),
+        NodeKind::TableRow => Some(Tag::TableRow),
+        NodeKind::TableCell => Some(Tag::TableCell),
+        NodeKind::Emphasis => Some(Tag::Emphasis),
+        NodeKind::Strong => Some(Tag::Strong),
+        NodeKind::Strikethrough => Some(Tag::Strikethrough),
+        NodeKind::Link { url, title } => Some(Tag::Link {
+            link_type: LinkType::Inline,
+            dest_url: borrowed(url),
+            title: borrowed(title),
+            id: CowStr::Borrowed(""),
+        }),
+        NodeKind::Image { url, title } => Some(Tag::Image {
+            link_type: LinkType::Inline,
+            dest_url: borrowed(url),
+            title: borrowed(title),
+            id: CowStr::Borrowed(""),
+        }),
+        NodeKind::Text(_)
+        | NodeKind::Code(_)
+        | NodeKind::Html(_)
+        | NodeKind::Math { .. }
+        | NodeKind::FootnoteReference(_)
+        | NodeKind::SoftBreak
+        | NodeKind::HardBreak
+        | NodeKind::ThematicBreak => unreachable!("leaf handled above"),
+    };
+
+    let end = tag.as_ref().map(Tag::to_end);
+    out.extend(tag.map(Event::Start));
+
+    // A task marker goes inside the first paragraph of a loose item, as
+    // pulldown-cmark emits it.
+    let marker = match node.kind {
+        NodeKind::Item {
+            checked: Some(checked),
+        } => Some(Event::TaskListMarker(checked)),
+        _ => None,
+    };
+    let mut children = node.children.iter();
+    if let Some(marker) = marker {
+        match node.children.first() {
+            Some(first) if first.kind == NodeKind::Paragraph => {
+                children.next();
+                out.push(Event::Start(Tag::Paragraph));
+                out.push(marker);
+                for child in &first.children {
+                    push_events(child, out);
+                }
+                out.push(Event::End(TagEnd::Paragraph));
+            }
+            _ => out.push(marker),
+        }
+    }
+    for child in children {
+        push_events(child, out);
+    }
+
+    out.extend(end.map(Event::End));
+}
+
+fn borrowed(s: &str) -> CowStr<'_> {
+    CowStr::Borrowed(s)
+}
+
+fn heading_level(level: u8) -> HeadingLevel {
+    match level {
+        0 | 1 => HeadingLevel::H1,
+        2 => HeadingLevel::H2,
+        3 => HeadingLevel::H3,
+        4 => HeadingLevel::H4,
+        5 => HeadingLevel::H5,
+        _ => HeadingLevel::H6,
+    }
+}
+
+fn blockquote_kind(kind: AdmonitionKind) -> BlockQuoteKind {
+    match kind {
+        AdmonitionKind::Note => BlockQuoteKind::Note,
+        AdmonitionKind::Tip => BlockQuoteKind::Tip,
+        AdmonitionKind::Important => BlockQuoteKind::Important,
+        AdmonitionKind::Warning => BlockQuoteKind::Warning,
+        AdmonitionKind::Caution => BlockQuoteKind::Caution,
+    }
+}
+
+fn pulldown_alignment(alignment: Alignment) -> pulldown_cmark::Alignment {
+    match alignment {
+        Alignment::None => pulldown_cmark::Alignment::None,
+        Alignment::Left => pulldown_cmark::Alignment::Left,
+        Alignment::Center => pulldown_cmark::Alignment::Center,
+        Alignment::Right => pulldown_cmark::Alignment::Right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Markdown;
+    use portals_markdown::{
+        AdmonitionKind, MarkdownDocument, MarkdownFormatOptions, MarkdownOptions, MarkdownParser,
+        Node, NodeKind, Visitor, Walk,
+    };
+
+    fn kinds(node: &Node) -> Vec<&NodeKind> {
+        node.children.iter().map(|child| &child.kind).collect()
+    }
+
+    #[test]
+    fn builds_tree_with_source_ranges() {
+        let source = "# Title\n\nSome *em* and **strong**.\n\n- [x] done\n- todo\n";
+        let doc = Markdown::new().parse_with_options(source, &MarkdownOptions::gfm());
+        let root = doc.ast();
+        assert_eq!(root.kind, NodeKind::Document);
+        assert_eq!(root.range, 0..source.len());
+        assert_eq!(
+            kinds(&root),
+            [
+                &NodeKind::Heading { level: 1, id: None },
+                &NodeKind::Paragraph,
+                &NodeKind::List { start: None },
+            ]
+        );
+
+        let paragraph = &root.children[1];
+        let em = &paragraph.children[1];
+        assert_eq!(em.kind, NodeKind::Emphasis);
+        assert_eq!(&source[em.range.clone()], "*em*");
+        assert_eq!(em.text(), "em");
+        assert_eq!(&source[paragraph.children[3].range.clone()], "**strong**");
+
+        let items = &root.children[2].children;
+        assert_eq!(
+            items[0].kind,
+            NodeKind::Item {
+                checked: Some(true)
+            }
+        );
+        assert_eq!(items[1].kind, NodeKind::Item { checked: None });
+        assert_eq!(items[1].text(), "todo");
+    }
+
+    #[test]
+    fn builds_tables() {
+        let source = "| A | B |\n|:--|--:|\n| 1 | 2 |\n";
+        let root = Markdown::new()
+            .parse_with_options(source, &MarkdownOptions::gfm())
+            .ast();
+        let table = &root.children[0];
+        assert_eq!(kinds(table), [&NodeKind::TableHead, &NodeKind::TableRow]);
+        let row = &table.children[1];
+        assert_eq!(row.children.len(), 2);
+        assert_eq!(row.children[1].text(), "2");
+        assert_eq!(&source[row.range.clone()], "| 1 | 2 |\n");
+    }
+
+    #[test]
+    fn maps_ranges_through_fence_rewriting() {
+        let source = "intro\n\n:::tip\nUse *x*.\n:::\n";
+        let options = MarkdownOptions {
+            admonitions: true,
+            ..Default::default()
+        };
+        let root = Markdown::new().parse_with_options(source, &options).ast();
+        let tip = &root.children[1];
+        assert_eq!(tip.kind, NodeKind::Admonition(AdmonitionKind::Tip));
+        let em = &tip.children[0].children[1];
+        assert_eq!(em.kind, NodeKind::Emphasis);
+        assert_eq!(&source[em.range.clone()], "*x*");
+    }
+
+    #[test]
+    fn walks_and_visits() {
+        let root = Markdown::new()
+            .parse("A [link](https://a.example) and [another](https://b.example).")
+            .ast();
+
+        let mut urls = Vec::new();
+        root.walk(|node| {
+            if let NodeKind::Link { url, .. } = &node.kind {
+                urls.push(url.clone());
+                return Walk::Stop;
+            }
+            Walk::Continue
+        });
+        assert_eq!(urls, ["https://a.example"]);
+
+        struct Depth {
+            current: usize,
+            max: usize,
+            left: usize,
+        }
+        impl Visitor for Depth {
+            fn enter(&mut self, node: &Node) -> Walk {
+                self.current += 1;
+                self.max = self.max.max(self.current);
+                if matches!(node.kind, NodeKind::Link { .. }) {
+                    self.current -= 1;
+                    return Walk::SkipChildren;
+                }
+                Walk::Continue
+            }
+            fn leave(&mut self, _node: &Node) {
+                self.current -= 1;
+                self.left += 1;
+            }
+        }
+        let mut depth = Depth {
+            current: 0,
+            max: 0,
+            left: 0,
+        };
+        root.accept(&mut depth);
+        // Document > Paragraph > Link, with the links' text skipped.
+        assert_eq!(depth.max, 3);
+        assert_eq!(depth.current, 0);
+        assert_eq!(depth.left, 5);
+    }
+
+    #[test]
+    fn renders_transformed_tree() {
+        let md = Markdown::new();
+        let doc = md.parse("# Hi\n\nSee [docs](old/page.md).\n");
+        let mut root = doc.ast();
+        root.walk_mut(|node| {
+            match &mut node.kind {
+                NodeKind::Link { url, .. } => *url = url.replace("old/", "new/"),
+                NodeKind::Heading { level, .. } => *level = 2,
+                _ => {}
+            }
+            Walk::Continue
+        });
+
+        let options = MarkdownFormatOptions::default();
+        assert_eq!(
+            md.format_ast(&root, &options),
+            "## Hi\n\nSee [docs](new/page.md).\n"
+        );
+        let html = md.render_ast(&root, &MarkdownOptions::default());
+        assert!(html.contains("<h2>Hi</h2>"), "{}", html);
+        assert!(html.contains("<a href=\"new/page.md\">docs</a>"));
+    }
+
+    #[test]
+    fn unchanged_tree_formats_like_document() {
+        let source = "# T\n\n1. one\n2. two\n\n- [ ] loose\n\n- [x] task\n\n> quote\n\n```rust\nfn f() {}\n```\n\n| a | b |\n|---|:-:|\n| `c` | ~~d~~ |\n";
+        let md = Markdown::new();
+        let doc = md.parse_with_options(source, &MarkdownOptions::gfm());
+        let options = MarkdownFormatOptions::default();
+        assert_eq!(md.format_ast(&doc.ast(), &options), doc.to_markdown());
+        assert_eq!(
+            md.render_ast(&doc.ast(), &MarkdownOptions::gfm()),
+            doc.to_html()
+        );
+    }
+}
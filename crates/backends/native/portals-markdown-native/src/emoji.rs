@@ -0,0 +1,114 @@
+//! Emoji shortcodes.
+//!
+//! `:name:` in text is replaced with the Unicode emoji for GitHub's
+//! shortcode `name`, as listed by the `emojis` crate; unknown shortcodes
+//! are left as written.
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+/// Replace shortcodes in text events, leaving code blocks and code spans
+/// untouched. Expects adjacent text events to have been merged.
+pub(crate) fn html_event<'a>(event: Event<'a>, in_code_block: &mut bool) -> Event<'a> {
+    match event {
+        Event::Start(Tag::CodeBlock(_)) => {
+            *in_code_block = true;
+            event
+        }
+        Event::End(TagEnd::CodeBlock) => {
+            *in_code_block = false;
+            event
+        }
+        Event::Text(text) if !*in_code_block => match replace_shortcodes(&text) {
+            Some(replaced) => Event::Text(CowStr::from(replaced)),
+            None => Event::Text(text),
+        },
+        event => event,
+    }
+}
+
+/// The emoji for a shortcode name (without colons).
+fn lookup(name: &str) -> Option<&'static str> {
+    emojis::get_by_shortcode(name).map(emojis::Emoji::as_str)
+}
+
+/// `text` with known shortcodes replaced, or `None` if it has none.
+fn replace_shortcodes(text: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    let mut replaced = false;
+    while let Some(open) = rest.find(':') {
+        let after = &rest[open + 1..];
+        let Some(close) = after.find(|c: char| !is_name_char(c)) else {
+            break;
+        };
+        let name = &after[..close];
+        if after[close..].starts_with(':')
+            && let Some(emoji) = lookup(name)
+        {
+            out.push_str(&rest[..open]);
+            out.push_str(emoji);
+            rest = &after[close + 1..];
+            replaced = true;
+        } else {
+            // The closing colon (if any) may open the next shortcode.
+            out.push_str(&rest[..open + 1 + close]);
+            rest = &after[close..];
+        }
+    }
+    if !replaced {
+        return None;
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '+' | '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Markdown;
+    use portals_markdown::{MarkdownOptions, MarkdownRenderer};
+
+    fn options() -> MarkdownOptions {
+        MarkdownOptions {
+            emoji: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replaces_known_shortcodes() {
+        assert_eq!(
+            replace_shortcodes("ship it :rocket::tada: :+1:").as_deref(),
+            Some("ship it \u{1f680}\u{1f389} \u{1f44d}")
+        );
+        assert_eq!(
+            replace_shortcodes("at 10:30: :nope: :smile:").as_deref(),
+            Some("at 10:30: :nope: \u{1f604}")
+        );
+        assert_eq!(replace_shortcodes("12:00 :unknown: :"), None);
+        assert_eq!(
+            replace_shortcodes(":t-rex: :axe: :jp:").as_deref(),
+            Some("\u{1f996} \u{1fa93} \u{1f1ef}\u{1f1f5}")
+        );
+    }
+
+    #[test]
+    fn renders_outside_code() {
+        let html = Markdown::new().render_with_options(
+            "**Done** :white_check_mark: `:fire:`\n\n```\n:fire:\n```\n",
+            &options(),
+        );
+        assert!(html.contains("<strong>Done</strong> \u{2705} <code>:fire:</code>"));
+        assert!(html.contains("<pre><code>:fire:\n</code></pre>"));
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let html = Markdown::new().render("nice :smile:");
+        assert_eq!(html, "<p>nice :smile:</p>\n");
+    }
+}
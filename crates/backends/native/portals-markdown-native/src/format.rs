@@ -0,0 +1,866 @@
+//! Markdown re-emission.
+//!
+//! Walks pulldown-cmark events and writes them back out as normalized
+//! CommonMark. Block containers are built bottom-up: each open container is
+//! a frame collecting its finished children, and closing it prefixes the
+//! children's lines (`> `, list markers, indentation) before handing the
+//! result to its parent.
+
+use portals_markdown::{HeadingStyle, ListMarker, MarkdownFormatOptions};
+use pulldown_cmark::{Alignment, BlockQuoteKind, CodeBlockKind, Event, LinkType, Tag, TagEnd};
+
+use crate::heading_level_to_u8;
+
+/// Re-emit `events` as Markdown.
+///
+/// `escape_tildes` should be set when the events were parsed with
+/// strikethrough enabled, so literal `~` does not turn into strikethrough.
+pub(crate) fn to_markdown<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    options: &MarkdownFormatOptions,
+    escape_tildes: bool,
+) -> String {
+    let mut emitter = Emitter {
+        options,
+        escape_tildes,
+        stack: vec![Frame::new(Kind::Root)],
+        links: Vec::new(),
+        raw: 0,
+    };
+    for event in events {
+        emitter.event(event);
+    }
+
+    let root = emitter.stack.pop().expect("root frame");
+    let mut out = root.blocks.join("\n\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+enum Kind {
+    Root,
+    Paragraph,
+    Heading {
+        level: u8,
+        attrs: String,
+    },
+    BlockQuote {
+        alert: Option<&'static str>,
+    },
+    CodeBlock {
+        info: String,
+    },
+    HtmlBlock,
+    List {
+        marker: Marker,
+        loose: bool,
+    },
+    Item {
+        loose: bool,
+    },
+    FootnoteDefinition {
+        label: String,
+    },
+    Table {
+        alignments: Vec<Alignment>,
+        rows: Vec<Vec<String>>,
+    },
+    TableRow {
+        cells: Vec<String>,
+    },
+    TableCell,
+    Other,
+}
+
+#[derive(Clone, Copy)]
+enum Marker {
+    Bullet(char),
+    Ordered { next: u64, delimiter: char },
+}
+
+struct Frame {
+    kind: Kind,
+    /// Finished child blocks.
+    blocks: Vec<String>,
+    /// Pending inline content.
+    text: String,
+    /// Whether the last child block was a list, which decides the marker of
+    /// an immediately following list so the two don't merge.
+    last_was_list: bool,
+}
+
+impl Frame {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            blocks: Vec::new(),
+            text: String::new(),
+            last_was_list: false,
+        }
+    }
+}
+
+struct Emitter<'o> {
+    options: &'o MarkdownFormatOptions,
+    escape_tildes: bool,
+    stack: Vec<Frame>,
+    /// Open links and images: (autolink, destination, title).
+    links: Vec<(bool, String, String)>,
+    /// Depth of constructs whose text is written verbatim (autolinks).
+    raw: usize,
+}
+
+impl Emitter<'_> {
+    fn top(&mut self) -> &mut Frame {
+        self.stack.last_mut().expect("root frame")
+    }
+
+    fn pop(&mut self) -> Frame {
+        self.stack.pop().expect("unbalanced events")
+    }
+
+    fn push_text(&mut self, s: &str) {
+        self.top().text.push_str(s);
+    }
+
+    /// Add a finished block to the current container.
+    fn push_block(&mut self, block: String, is_list: bool) {
+        let width = self.wrap_width();
+        let top = self.top();
+        // Tight list items hold their text inline; it becomes a block of its
+        // own once a nested block follows it.
+        if !top.text.is_empty() {
+            let text = std::mem::take(&mut top.text);
+            top.blocks.push(paragraph(&text, width));
+        }
+        top.blocks.push(block);
+        top.last_was_list = is_list;
+    }
+
+    /// Wrap width for text at the current nesting depth.
+    fn wrap_width(&self) -> Option<usize> {
+        let width = self.options.wrap_width?;
+        let mut indent = 0;
+        for (i, frame) in self.stack.iter().enumerate() {
+            indent += match &frame.kind {
+                Kind::BlockQuote { .. } => 2,
+                Kind::FootnoteDefinition { .. } => 4,
+                Kind::Item { .. } => match self.stack.get(i.wrapping_sub(1)).map(|f| &f.kind) {
+                    Some(Kind::List { marker, .. }) => marker.text().len() + 1,
+                    _ => 2,
+                },
+                _ => 0,
+            };
+        }
+        Some(width.saturating_sub(indent).max(1))
+    }
+
+    fn in_table_cell(&self) -> bool {
+        matches!(self.stack.last().map(|f| &f.kind), Some(Kind::TableCell))
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                let verbatim = self.raw > 0
+                    || matches!(
+                        self.stack.last().map(|f| &f.kind),
+                        Some(Kind::CodeBlock { .. } | Kind::HtmlBlock)
+                    );
+                if verbatim {
+                    self.push_text(&text);
+                } else {
+                    let mut escaped = String::with_capacity(text.len());
+                    escape(
+                        &text,
+                        &mut escaped,
+                        self.in_table_cell(),
+                        self.escape_tildes,
+                    );
+                    self.push_text(&escaped);
+                }
+            }
+            Event::Code(code) => {
+                let span = code_span(&code);
+                self.push_text(&span);
+            }
+            Event::InlineMath(math) => self.push_text(&format!("${}$", math)),
+            Event::DisplayMath(math) => self.push_text(&format!("$${}$$", math)),
+            Event::Html(html) | Event::InlineHtml(html) => self.push_text(&html),
+            Event::FootnoteReference(label) => self.push_text(&format!("[^{}]", label)),
+            Event::SoftBreak => {
+                let soft = if self.options.wrap_width.is_some() {
+                    " "
+                } else {
+                    "\n"
+                };
+                self.push_text(soft);
+            }
+            Event::HardBreak => self.push_text("\\\n"),
+            Event::Rule => self.push_block("---".to_string(), false),
+            Event::TaskListMarker(checked) => {
+                self.push_text(if checked { "[x] " } else { "[ ] " });
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        let kind = match tag {
+            Tag::Paragraph => {
+                if let Kind::Item { loose } = &mut self.top().kind {
+                    *loose = true;
+                }
+                Kind::Paragraph
+            }
+            Tag::Heading {
+                level,
+                id,
+                classes,
+                attrs,
+            } => {
+                let mut parts = Vec::new();
+                if let Some(id) = id {
+                    parts.push(format!("#{}", id));
+                }
+                parts.extend(classes.iter().map(|c| format!(".{}", c)));
+                parts.extend(attrs.iter().map(|(k, v)| match v {
+                    Some(v) => format!("{}={}", k, v),
+                    None => k.to_string(),
+                }));
+                let attrs = if parts.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {{{}}}", parts.join(" "))
+                };
+                Kind::Heading {
+                    level: heading_level_to_u8(level),
+                    attrs,
+                }
+            }
+            Tag::BlockQuote(kind) => Kind::BlockQuote {
+                alert: kind.map(alert_marker),
+            },
+            Tag::CodeBlock(CodeBlockKind::Fenced(info)) => Kind::CodeBlock {
+                info: info.to_string(),
+            },
+            Tag::CodeBlock(CodeBlockKind::Indented) => Kind::CodeBlock {
+                info: String::new(),
+            },
+            Tag::HtmlBlock => Kind::HtmlBlock,
+            Tag::List(start) => {
+                let follows_list = self.top().last_was_list;
+                let marker = match start {
+                    Some(start) => Marker::Ordered {
+                        next: start,
+                        delimiter: if follows_list { ')' } else { '.' },
+                    },
+                    None => {
+                        let preferred = self.options.list_marker;
+                        let marker = if follows_list {
+                            match preferred {
+                                ListMarker::Dash => ListMarker::Star,
+                                _ => ListMarker::Dash,
+                            }
+                        } else {
+                            preferred
+                        };
+                        Marker::Bullet(marker.as_char())
+                    }
+                };
+                Kind::List {
+                    marker,
+                    loose: false,
+                }
+            }
+            Tag::Item => Kind::Item { loose: false },
+            Tag::FootnoteDefinition(label) => Kind::FootnoteDefinition {
+                label: label.to_string(),
+            },
+            Tag::Table(alignments) => Kind::Table {
+                alignments,
+                rows: Vec::new(),
+            },
+            Tag::TableHead | Tag::TableRow => Kind::TableRow { cells: Vec::new() },
+            Tag::TableCell => Kind::TableCell,
+            Tag::Emphasis => return self.push_text("*"),
+            Tag::Strong => return self.push_text("**"),
+            Tag::Strikethrough => return self.push_text("~~"),
+            Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                ..
+            } => {
+                let autolink = matches!(link_type, LinkType::Autolink | LinkType::Email);
+                if autolink {
+                    self.raw += 1;
+                    self.push_text("<");
+                } else {
+                    self.push_text("[");
+                }
+                self.links
+                    .push((autolink, dest_url.to_string(), title.to_string()));
+                return;
+            }
+            Tag::Image {
+                dest_url, title, ..
+            } => {
+                self.push_text("![");
+                self.links
+                    .push((false, dest_url.to_string(), title.to_string()));
+                return;
+            }
+            Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition
+            | Tag::MetadataBlock(_) => Kind::Other,
+        };
+        self.stack.push(Frame::new(kind));
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Emphasis => return self.push_text("*"),
+            TagEnd::Strong => return self.push_text("**"),
+            TagEnd::Strikethrough => return self.push_text("~~"),
+            TagEnd::Link | TagEnd::Image => {
+                let (autolink, dest, title) = self.links.pop().expect("unbalanced link");
+                if autolink {
+                    self.raw -= 1;
+                    self.push_text(">");
+                } else {
+                    let target = link_target(&dest, &title);
+                    self.push_text(&format!("]({})", target));
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let width = self.wrap_width();
+        let frame = self.pop();
+        match frame.kind {
+            Kind::Paragraph => self.push_block(paragraph(&frame.text, width), false),
+            Kind::Heading { level, attrs } => {
+                let text = heading_text(&frame.text);
+                let block = match self.options.heading_style {
+                    HeadingStyle::Setext if level <= 2 && attrs.is_empty() && !text.is_empty() => {
+                        let underline = if level == 1 { "=" } else { "-" };
+                        let len = text.chars().count().max(3);
+                        let text = escape_line_start(&text);
+                        format!("{}\n{}", text, underline.repeat(len))
+                    }
+                    _ => {
+                        let hashes = "#".repeat(level as usize);
+                        if text.is_empty() {
+                            format!("{}{}", hashes, attrs)
+                        } else {
+                            format!("{} {}{}", hashes, text, attrs)
+                        }
+                    }
+                };
+                self.push_block(block, false);
+            }
+            Kind::BlockQuote { alert } => {
+                let mut content = container_content(frame.blocks, &frame.text, width, "\n\n");
+                if let Some(marker) = alert {
+                    content = format!("{}\n{}", marker, content);
+                }
+                self.push_block(prefix_lines(&content, "> ", "> "), false);
+            }
+            Kind::CodeBlock { info } => {
+                let code = frame.text.strip_suffix('\n').unwrap_or(&frame.text);
+                let fence_char = if info.contains('`') { '~' } else { '`' };
+                let longest = longest_run(code, fence_char);
+                let fence = fence_char.to_string().repeat(longest.max(2) + 1);
+                let block = if code.is_empty() && frame.text.is_empty() {
+                    format!("{}{}\n{}", fence, info, fence)
+                } else {
+                    format!("{}{}\n{}\n{}", fence, info, code, fence)
+                };
+                self.push_block(block, false);
+            }
+            Kind::HtmlBlock => {
+                let html = frame.text.trim_end_matches('\n').to_string();
+                self.push_block(html, false);
+            }
+            Kind::List { loose, .. } => {
+                let separator = if loose { "\n\n" } else { "\n" };
+                self.push_block(frame.blocks.join(separator), true);
+            }
+            Kind::Item { loose } => {
+                let separator = if loose { "\n\n" } else { "\n" };
+                let content = container_content(frame.blocks, &frame.text, width, separator);
+                let Some(Kind::List {
+                    marker,
+                    loose: list_loose,
+                }) = self.stack.last_mut().map(|f| &mut f.kind)
+                else {
+                    return;
+                };
+                *list_loose |= loose;
+                let text = marker.text();
+                marker.advance();
+
+                let indent = " ".repeat(text.len() + 1);
+                let block = if content.is_empty() {
+                    text
+                } else {
+                    prefix_lines(&content, &format!("{} ", text), &indent)
+                };
+                self.top().blocks.push(block);
+            }
+            Kind::FootnoteDefinition { label } => {
+                let content = container_content(frame.blocks, &frame.text, width, "\n\n");
+                let first = format!("[^{}]: ", label);
+                self.push_block(prefix_lines(&content, &first, "    "), false);
+            }
+            Kind::TableCell => {
+                let cell = frame.text.replace('\n', " ").trim().to_string();
+                if let Kind::TableRow { cells } = &mut self.top().kind {
+                    cells.push(cell);
+                }
+            }
+            Kind::TableRow { cells } => {
+                if let Kind::Table { rows, .. } = &mut self.top().kind {
+                    rows.push(cells);
+                }
+            }
+            Kind::Table { alignments, rows } => {
+                self.push_block(table(&alignments, &rows), false);
+            }
+            Kind::Root | Kind::Other => {
+                let content = container_content(frame.blocks, &frame.text, width, "\n\n");
+                if !content.is_empty() {
+                    self.push_block(content, false);
+                }
+            }
+        }
+    }
+}
+
+impl Marker {
+    fn text(&self) -> String {
+        match self {
+            Self::Bullet(c) => c.to_string(),
+            Self::Ordered { next, delimiter } => format!("{}{}", next, delimiter),
+        }
+    }
+
+    fn advance(&mut self) {
+        if let Self::Ordered { next, .. } = self {
+            *next += 1;
+        }
+    }
+}
+
+/// The `[!KIND]` line opening a GitHub alert.
+fn alert_marker(kind: BlockQuoteKind) -> &'static str {
+    match kind {
+        BlockQuoteKind::Note => "[!NOTE]",
+        BlockQuoteKind::Tip => "[!TIP]",
+        BlockQuoteKind::Important => "[!IMPORTANT]",
+        BlockQuoteKind::Warning => "[!WARNING]",
+        BlockQuoteKind::Caution => "[!CAUTION]",
+    }
+}
+
+/// Join a container's children, including trailing inline text.
+fn container_content(
+    mut blocks: Vec<String>,
+    text: &str,
+    width: Option<usize>,
+    separator: &str,
+) -> String {
+    if !text.is_empty() {
+        blocks.push(paragraph(text, width));
+    }
+    blocks.join(separator)
+}
+
+/// Prefix the first line with `first` and the rest with `rest`.
+///
+/// Blank lines get the prefix with trailing whitespace trimmed.
+fn prefix_lines(content: &str, first: &str, rest: &str) -> String {
+    let mut out = String::with_capacity(content.len() + 8);
+    for (i, line) in content.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let prefix = if i == 0 { first } else { rest };
+        if line.is_empty() {
+            out.push_str(prefix.trim_end());
+        } else {
+            out.push_str(prefix);
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+/// Finish a paragraph: rewrap if requested, and escape line starts that
+/// would otherwise begin a block.
+fn paragraph(text: &str, width: Option<usize>) -> String {
+    let text = text.trim_matches(' ');
+    let wrapped = match width {
+        Some(width) => wrap(text, width),
+        None => text.to_string(),
+    };
+    wrapped
+        .split('\n')
+        .map(|line| escape_line_start(line.trim_start_matches(' ')))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Heading content on one line, protecting a trailing `#` run from being
+/// read as a closing sequence.
+fn heading_text(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    let text = text.trim();
+    let body = text.trim_end_matches('#');
+    if body.len() < text.len() && (body.is_empty() || body.ends_with(' ')) {
+        format!("{}\\{}", body, &text[body.len()..])
+    } else {
+        text.to_string()
+    }
+}
+
+/// Greedily wrap each line of `text` to `width` columns.
+///
+/// Never breaks before a word that would read as a block marker at the start
+/// of a line.
+fn wrap(text: &str, width: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut column = 0;
+        for (j, word) in line.split(' ').enumerate() {
+            let len = word.chars().count();
+            if j > 0 {
+                if column > 0 && column + 1 + len > width && !starts_block(word) {
+                    out.push('\n');
+                    column = 0;
+                } else {
+                    out.push(' ');
+                    column += 1;
+                }
+            }
+            out.push_str(word);
+            column += len;
+        }
+    }
+    out
+}
+
+/// Whether `line` would start a block construct rather than continue a
+/// paragraph.
+fn starts_block(line: &str) -> bool {
+    escape_line_start(line).len() != line.len()
+}
+
+/// Escape a leading character that would start a block construct.
+fn escape_line_start(line: &str) -> String {
+    let bytes = line.as_bytes();
+    let Some(&first) = bytes.first() else {
+        return String::new();
+    };
+    let followed_by_space = |i: usize| bytes.get(i).is_none_or(|&b| b == b' ');
+
+    let escape_at = match first {
+        b'>' => Some(0),
+        b'#' => {
+            let hashes = bytes.iter().take_while(|&&b| b == b'#').count();
+            (hashes <= 6 && followed_by_space(hashes)).then_some(0)
+        }
+        b'-' | b'+' | b'*' if followed_by_space(1) => Some(0),
+        b'-' | b'=' if bytes.iter().all(|&b| b == first || b == b' ') => Some(0),
+        b'~' if line.starts_with("~~~") => Some(0),
+        b'0'..=b'9' => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+            let delimited = matches!(bytes.get(digits), Some(b'.' | b')'));
+            (digits <= 9 && delimited && followed_by_space(digits + 1)).then_some(digits)
+        }
+        _ => None,
+    };
+
+    match escape_at {
+        Some(i) => format!("{}\\{}", &line[..i], &line[i..]),
+        None => line.to_string(),
+    }
+}
+
+/// Backslash-escape characters that Markdown would interpret.
+fn escape(text: &str, out: &mut String, in_table: bool, escape_tildes: bool) {
+    let chars: Vec<char> = text.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let needs_escape = match c {
+            '\\' | '`' | '*' | '[' | ']' | '<' => true,
+            // Intraword underscores can't open or close emphasis.
+            '_' => {
+                let before = i.checked_sub(1).and_then(|i| chars.get(i));
+                let after = chars.get(i + 1);
+                !(before.is_some_and(|c| c.is_alphanumeric())
+                    && after.is_some_and(|c| c.is_alphanumeric()))
+            }
+            '&' => starts_entity(&chars[i + 1..]),
+            '|' => in_table,
+            '~' => escape_tildes,
+            _ => false,
+        };
+        if needs_escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// Whether the characters after `&` form an entity reference.
+fn starts_entity(rest: &[char]) -> bool {
+    let name = rest
+        .iter()
+        .take_while(|c| c.is_ascii_alphanumeric() || **c == '#')
+        .count();
+    name > 0 && rest.get(name) == Some(&';')
+}
+
+/// An inline code span that round-trips `code`.
+fn code_span(code: &str) -> String {
+    let fence = "`".repeat(longest_run(code, '`') + 1);
+    let pad = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+    if pad {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+/// Length of the longest run of `c` in `s`.
+fn longest_run(s: &str, c: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for ch in s.chars() {
+        if ch == c {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+/// Destination and optional title of an inline link.
+fn link_target(dest: &str, title: &str) -> String {
+    let needs_brackets = dest.is_empty()
+        || dest.contains([' ', '(', ')', '<', '>'])
+        || dest.contains(char::is_control);
+    let dest = if needs_brackets {
+        format!("<{}>", dest.replace('<', "\\<").replace('>', "\\>"))
+    } else {
+        dest.to_string()
+    };
+    if title.is_empty() {
+        dest
+    } else {
+        format!("{} \"{}\"", dest, title.replace('"', "\\\""))
+    }
+}
+
+/// A pipe table with columns padded to equal width.
+fn table(alignments: &[Alignment], rows: &[Vec<String>]) -> String {
+    let columns = alignments.len();
+    let mut widths = vec![3; columns];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: &[String]| {
+        let mut line = String::from("|");
+        for (i, width) in widths.iter().enumerate() {
+            let cell = cells.get(i).map(String::as_str).unwrap_or("");
+            let pad = width - cell.chars().count();
+            line.push(' ');
+            line.push_str(cell);
+            line.push_str(&" ".repeat(pad));
+            line.push_str(" |");
+        }
+        line
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    let mut rows = rows.iter();
+    lines.push(format_row(rows.next().map(Vec::as_slice).unwrap_or(&[])));
+
+    let mut delimiter = String::from("|");
+    for (alignment, &width) in alignments.iter().zip(&widths) {
+        let cell = match alignment {
+            Alignment::None => "-".repeat(width),
+            Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+            Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+            Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+        };
+        delimiter.push(' ');
+        delimiter.push_str(&cell);
+        delimiter.push_str(" |");
+    }
+    lines.push(delimiter);
+
+    lines.extend(rows.map(|row| format_row(row)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Markdown;
+    use portals_markdown::{
+        HeadingStyle, ListMarker, MarkdownDocument, MarkdownFormatOptions, MarkdownFormatter,
+        MarkdownOptions, MarkdownParser, MarkdownRenderer,
+    };
+
+    fn format(source: &str) -> String {
+        Markdown::new().parse(source).to_markdown()
+    }
+
+    fn format_with(source: &str, options: &MarkdownFormatOptions) -> String {
+        Markdown::new()
+            .parse(source)
+            .to_markdown_with_options(options)
+    }
+
+    /// Formatting must not change what the document renders to.
+    fn assert_stable(source: &str, options: &MarkdownOptions) {
+        let md = Markdown::new();
+        let formatted = md.parse_with_options(source, options).to_markdown();
+        assert_eq!(
+            md.render_with_options(source, options),
+            md.render_with_options(&formatted, options),
+            "formatted:\n{}",
+            formatted
+        );
+        let again = md.parse_with_options(&formatted, options).to_markdown();
+        assert_eq!(formatted, again, "not idempotent");
+    }
+
+    #[test]
+    fn normalizes_blocks() {
+        let source = "Title\n=====\n\n* one\n* two\n\ntext\n\n    code\n\n***\n";
+        assert_eq!(
+            format(source),
+            "# Title\n\n- one\n- two\n\ntext\n\n```\ncode\n```\n\n---\n"
+        );
+    }
+
+    #[test]
+    fn inline_markup() {
+        assert_eq!(
+            format("_em_ __strong__ `code` [link](http://x.io \"T\") <http://a.b>"),
+            "*em* **strong** `code` [link](http://x.io \"T\") <http://a.b>\n"
+        );
+    }
+
+    #[test]
+    fn list_markers_and_setext() {
+        let options = MarkdownFormatOptions {
+            list_marker: ListMarker::Star,
+            heading_style: HeadingStyle::Setext,
+            wrap_width: None,
+        };
+        assert_eq!(
+            format_with("# A\n\n### B\n\n- x\n  - y\n", &options),
+            "A\n===\n\n### B\n\n* x\n  * y\n"
+        );
+    }
+
+    #[test]
+    fn wraps_paragraphs() {
+        let options = MarkdownFormatOptions {
+            wrap_width: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_with("the quick brown fox\njumps over the lazy dog\n", &options),
+            "the quick brown fox\njumps over the lazy\ndog\n"
+        );
+        assert_eq!(
+            format_with("> one two three four five six\n", &options),
+            "> one two three four\n> five six\n"
+        );
+        // Never wrap so that a line starts with a list marker.
+        assert_eq!(
+            format_with("aaaaaaaaaaaaaaaaa - b\n", &options),
+            "aaaaaaaaaaaaaaaaa -\nb\n"
+        );
+    }
+
+    #[test]
+    fn escapes_literal_markup() {
+        assert_eq!(
+            format("\\*not em\\* snake_case\n"),
+            "\\*not em\\* snake_case\n"
+        );
+        assert_eq!(format("\\# not a heading\n"), "\\# not a heading\n");
+        assert_eq!(format("1\\. not a list\n"), "1\\. not a list\n");
+    }
+
+    #[test]
+    fn round_trips() {
+        let documents = [
+            "# Heading #\n\nSome *emphasis*, **strong** and `a `` b`.\n",
+            "1. one\n2. two\n\n   para in two\n3. three\n",
+            "- a\n\n* b\n",
+            "> quote\n>\n> - list\n>   - nested\n",
+            "Line one  \nline two\\\nthree\n",
+            "```rust\nfn main() {}\n```\n\n~~~\n```\n~~~\n",
+            "<div>\nhtml\n</div>\n\ntext &amp; &copy; AT&T\n",
+            "![alt *text*](/img.png) and [ref][r]\n\n[r]: /target \"Title\"\n",
+        ];
+        for doc in documents {
+            assert_stable(doc, &MarkdownOptions::default());
+        }
+
+        let gfm = [
+            "| a | b |\n|:--|--:|\n| 1 | x \\| y |\n",
+            "- [ ] todo\n- [x] done\n",
+            "- [ ] loose\n\n- [x] task\n",
+            "~~gone~~ and ~literal\\~\n",
+        ];
+        for doc in gfm {
+            assert_stable(doc, &MarkdownOptions::gfm());
+        }
+
+        assert_stable(":::warning\nCareful.\n:::\n", &MarkdownOptions::full());
+        assert_stable(
+            "Text[^1].\n\n[^1]: Note\n\n    more.\n",
+            &MarkdownOptions::full(),
+        );
+    }
+
+    #[test]
+    fn formatter_reads_gfm() {
+        let md = Markdown::new();
+        let options = MarkdownFormatOptions {
+            list_marker: ListMarker::Plus,
+            heading_style: HeadingStyle::Atx,
+            wrap_width: Some(30),
+        };
+        let source = "Title\n-----\n\n* [x] done\n* ~~dropped~~\n\n|a|b|\n|-|-|\n|1|2|\n\nSee[^n].\n\n[^n]: A note that is long enough to wrap.\n";
+        let formatted = md.format_with_options(source, &options);
+        assert_eq!(
+            formatted,
+            "## Title\n\n+ [x] done\n+ ~~dropped~~\n\n| a   | b   |\n| --- | --- |\n| 1   | 2   |\n\nSee[^n].\n\n[^n]: A note that is long enough\n    to wrap.\n"
+        );
+        assert_eq!(md.format_with_options(&formatted, &options), formatted);
+        assert_eq!(md.format("*a*\n"), "*a*\n");
+    }
+}
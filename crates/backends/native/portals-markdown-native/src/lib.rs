@@ -1,7 +1,26 @@
 //! Native Markdown implementation using pulldown-cmark.
 
-use portals_markdown::{MarkdownDocument, MarkdownOptions, MarkdownParser, MarkdownRenderer};
-use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+mod admonition;
+mod ast;
+mod emoji;
+mod format;
+mod linkcheck;
+mod math;
+mod sanitize;
+mod slug;
+mod stream;
+
+pub use linkcheck::{LinkChecker, LinkReport, LinkStatus};
+
+use portals_io::{OutputStream, StreamError};
+use portals_markdown::{
+    Admonition, HtmlPolicy, MarkdownDocument, MarkdownFormatOptions, MarkdownFormatter,
+    MarkdownOptions, MarkdownParser, MarkdownRenderer, Node, TocEntry, UrlKind, UrlRewriter,
+};
+use pulldown_cmark::{
+    Event, HeadingLevel, Options, Parser, Tag, TagEnd, TextMergeStream, TextMergeWithOffset, html,
+};
+use std::fmt;
 
 /// Markdown renderer using pulldown-cmark.
 #[derive(Debug, Default, Clone, Copy)]
@@ -33,9 +52,141 @@ impl Markdown {
         if options.footnotes {
             opts.insert(Options::ENABLE_FOOTNOTES);
         }
+        if options.admonitions {
+            opts.insert(Options::ENABLE_GFM);
+        }
+        if options.math {
+            opts.insert(Options::ENABLE_MATH);
+        }
 
         opts
     }
+
+    /// The source rewritten for extensions implemented by rewriting, if
+    /// any apply.
+    fn prepare(markdown: &str, options: &MarkdownOptions) -> Option<admonition::Expanded> {
+        if options.admonitions {
+            admonition::expand_fences(markdown)
+        } else {
+            None
+        }
+    }
+
+    /// Render a syntax tree to HTML.
+    ///
+    /// `options` selects the HTML for extension nodes such as admonitions
+    /// and math; the tree itself is rendered as given.
+    pub fn render_ast(&self, node: &Node, options: &MarkdownOptions) -> String {
+        let mut html_output = String::new();
+        Self::push_html(&mut html_output, ast::events(node).into_iter(), options);
+        html_output
+    }
+
+    /// Re-emit a syntax tree as normalized CommonMark.
+    ///
+    /// Links are written inline; reference definitions and autolink syntax
+    /// are not kept in the tree.
+    pub fn format_ast(&self, node: &Node, options: &MarkdownFormatOptions) -> String {
+        format::to_markdown(ast::events(node).into_iter(), options, true)
+    }
+
+    /// Render to a stream, writing HTML as events are consumed instead of
+    /// building the whole document in memory.
+    pub fn render_to(
+        &self,
+        markdown: &str,
+        out: &mut impl OutputStream,
+    ) -> Result<(), StreamError> {
+        self.render_to_with_options(markdown, &MarkdownOptions::default(), out)
+    }
+
+    /// Render to a stream with options. See [`render_to`](Self::render_to).
+    ///
+    /// The stream is flushed once the document is written. With
+    /// `heading_ids` set, events are buffered so that repeated headings get
+    /// the same slugs as [`render`](MarkdownRenderer::render), and with an
+    /// [`HtmlPolicy::Allowlist`] the HTML is buffered to be sanitized as a
+    /// whole.
+    pub fn render_to_with_options(
+        &self,
+        markdown: &str,
+        options: &MarkdownOptions,
+        out: &mut impl OutputStream,
+    ) -> Result<(), StreamError> {
+        let opts = Self::options_to_pulldown(options);
+        let prepared = Self::prepare(markdown, options);
+        let source = prepared.as_ref().map_or(markdown, |p| &p.text);
+        let parser = Parser::new_ext(source, opts);
+        let mut writer = stream::StreamWriter::new(out);
+        let result = Self::write_html(&mut writer, parser, options);
+        writer.finish(result)
+    }
+
+    fn push_html<'a>(
+        out: &mut String,
+        events: impl Iterator<Item = Event<'a>>,
+        options: &MarkdownOptions,
+    ) {
+        // Writing to a String cannot fail.
+        let _ = Self::write_html(out, events, options);
+    }
+
+    fn write_html<'a>(
+        mut out: impl fmt::Write,
+        events: impl Iterator<Item = Event<'a>>,
+        options: &MarkdownOptions,
+    ) -> fmt::Result {
+        match &options.html {
+            // The allowlist is applied to the whole document at once.
+            HtmlPolicy::Allowlist(allowlist) => {
+                let mut rendered = String::new();
+                Self::write_events(&mut rendered, events, options)?;
+                out.write_str(&sanitize::clean(allowlist, &rendered))
+            }
+            _ => Self::write_events(out, events, options),
+        }
+    }
+
+    fn write_events<'a>(
+        out: impl fmt::Write,
+        events: impl Iterator<Item = Event<'a>>,
+        options: &MarkdownOptions,
+    ) -> fmt::Result {
+        let admonitions = options.admonitions;
+        let math = options.math;
+        let emoji = options.emoji;
+        let mut in_code_block = false;
+        let mut sanitizer = sanitize::Sanitizer::new(&options.html);
+        let rewriter = options.rewrite_urls.clone();
+        let events: Box<dyn Iterator<Item = Event<'a>>> = if options.heading_ids {
+            let mut events: Vec<_> = events.collect();
+            slug::assign_ids(&mut events);
+            Box::new(events.into_iter())
+        } else {
+            Box::new(events)
+        };
+        html::write_html_fmt(
+            out,
+            TextMergeStream::new(events).map(move |mut event| {
+                // Rewrite URLs, then sanitize before the extensions run, so
+                // rewritten URLs are checked and extension HTML is kept.
+                if let Some(rewriter) = &rewriter {
+                    event = rewrite_url(event, rewriter);
+                }
+                event = sanitizer.event(event);
+                if admonitions {
+                    event = admonition::html_event(event);
+                }
+                if math {
+                    event = math::html_event(event);
+                }
+                if emoji {
+                    event = emoji::html_event(event, &mut in_code_block);
+                }
+                event
+            }),
+        )
+    }
 }
 
 impl MarkdownRenderer for Markdown {
@@ -45,9 +196,11 @@ impl MarkdownRenderer for Markdown {
 
     fn render_with_options(&self, markdown: &str, options: &MarkdownOptions) -> String {
         let opts = Self::options_to_pulldown(options);
-        let parser = Parser::new_ext(markdown, opts);
+        let prepared = Self::prepare(markdown, options);
+        let source = prepared.as_ref().map_or(markdown, |p| &p.text);
+        let parser = Parser::new_ext(source, opts);
         let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
+        Self::push_html(&mut html_output, parser, options);
         html_output
     }
 }
@@ -62,38 +215,61 @@ impl MarkdownParser for Markdown {
     fn parse_with_options(&self, markdown: &str, options: &MarkdownOptions) -> Self::Document {
         Document {
             source: markdown.to_string(),
+            prepared: Self::prepare(markdown, options),
             options: options.clone(),
         }
     }
 }
 
+/// Input is read as GitHub Flavored Markdown with footnotes, so tables,
+/// task lists, and strikethrough survive formatting. Admonition fences
+/// and math are left as plain text.
+impl MarkdownFormatter for Markdown {
+    fn format_with_options(&self, markdown: &str, options: &MarkdownFormatOptions) -> String {
+        let parse_options = MarkdownOptions {
+            footnotes: true,
+            ..MarkdownOptions::gfm()
+        };
+        self.parse_with_options(markdown, &parse_options)
+            .to_markdown_with_options(options)
+    }
+}
+
 /// A parsed Markdown document.
 #[derive(Debug, Clone)]
 pub struct Document {
     source: String,
+    /// Rewritten source, if any extension needed it.
+    prepared: Option<admonition::Expanded>,
     options: MarkdownOptions,
 }
 
+impl Document {
+    fn parser(&self) -> Parser<'_> {
+        let text = self.prepared.as_ref().map_or(&self.source, |p| &p.text);
+        Parser::new_ext(text, Markdown::options_to_pulldown(&self.options))
+    }
+}
+
 impl MarkdownDocument for Document {
     fn source(&self) -> &str {
         &self.source
     }
 
     fn to_html(&self) -> String {
-        let opts = Markdown::options_to_pulldown(&self.options);
-        let parser = Parser::new_ext(&self.source, opts);
         let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
+        Markdown::push_html(&mut html_output, self.parser(), &self.options);
         html_output
     }
 
     fn to_text(&self) -> String {
-        let opts = Markdown::options_to_pulldown(&self.options);
-        let parser = Parser::new_ext(&self.source, opts);
+        let parser = self.parser();
         let mut text = String::new();
 
         for event in parser {
-            if let Event::Text(t) | Event::Code(t) = event {
+            if let Event::Text(t) | Event::Code(t) | Event::InlineMath(t) | Event::DisplayMath(t) =
+                event
+            {
                 text.push_str(&t);
             }
         }
@@ -102,8 +278,7 @@ impl MarkdownDocument for Document {
     }
 
     fn headings(&self) -> Vec<(u8, String)> {
-        let opts = Markdown::options_to_pulldown(&self.options);
-        let parser = Parser::new_ext(&self.source, opts);
+        let parser = self.parser();
         let mut headings = Vec::new();
         let mut current_level: Option<u8> = None;
         let mut current_text = String::new();
@@ -129,9 +304,14 @@ impl MarkdownDocument for Document {
         headings
     }
 
+    fn toc(&self) -> Vec<TocEntry> {
+        let mut events: Vec<_> = self.parser().collect();
+        slug::assign_ids(&mut events);
+        slug::toc(events.into_iter())
+    }
+
     fn links(&self) -> Vec<(String, String)> {
-        let opts = Markdown::options_to_pulldown(&self.options);
-        let parser = Parser::new_ext(&self.source, opts);
+        let parser = self.parser();
         let mut links = Vec::new();
         let mut current_url: Option<String> = None;
         let mut current_text = String::new();
@@ -157,9 +337,73 @@ impl MarkdownDocument for Document {
         links
     }
 
+    fn images(&self) -> Vec<(String, String)> {
+        let parser = self.parser();
+        let mut images = Vec::new();
+        let mut current_url: Option<String> = None;
+        let mut current_alt = String::new();
+
+        for event in parser {
+            match event {
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    current_url = Some(dest_url.to_string());
+                    current_alt.clear();
+                }
+                Event::End(TagEnd::Image) => {
+                    if let Some(url) = current_url.take() {
+                        images.push((std::mem::take(&mut current_alt), url));
+                    }
+                }
+                Event::Text(t) | Event::Code(t) if current_url.is_some() => {
+                    current_alt.push_str(&t);
+                }
+                _ => {}
+            }
+        }
+
+        images
+    }
+
+    fn tasks(&self) -> Vec<(bool, String)> {
+        let parser = self.parser();
+        let mut tasks: Vec<(bool, String)> = Vec::new();
+        // For each open list item, the index of its task, if it is one.
+        let mut items: Vec<Option<usize>> = Vec::new();
+
+        for event in parser {
+            match event {
+                Event::Start(Tag::Item) => items.push(None),
+                Event::End(TagEnd::Item) => {
+                    if let Some(Some(index)) = items.pop() {
+                        let text = &mut tasks[index].1;
+                        *text = text.trim().to_string();
+                    }
+                }
+                Event::TaskListMarker(checked) => {
+                    if let Some(item) = items.last_mut() {
+                        *item = Some(tasks.len());
+                        tasks.push((checked, String::new()));
+                    }
+                }
+                Event::Text(t) | Event::Code(t) => {
+                    if let Some(Some(index)) = items.last() {
+                        tasks[*index].1.push_str(&t);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some(Some(index)) = items.last() {
+                        tasks[*index].1.push(' ');
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        tasks
+    }
+
     fn code_blocks(&self) -> Vec<(Option<String>, String)> {
-        let opts = Markdown::options_to_pulldown(&self.options);
-        let parser = Parser::new_ext(&self.source, opts);
+        let parser = self.parser();
         let mut blocks = Vec::new();
         let mut current_lang: Option<Option<String>> = None;
         let mut current_code = String::new();
@@ -170,11 +414,7 @@ impl MarkdownDocument for Document {
                     let lang = match kind {
                         pulldown_cmark::CodeBlockKind::Fenced(lang) => {
                             let lang = lang.to_string();
-                            if lang.is_empty() {
-                                None
-                            } else {
-                                Some(lang)
-                            }
+                            if lang.is_empty() { None } else { Some(lang) }
                         }
                         pulldown_cmark::CodeBlockKind::Indented => None,
                     };
@@ -195,6 +435,108 @@ impl MarkdownDocument for Document {
 
         blocks
     }
+
+    fn admonitions(&self) -> Vec<Admonition> {
+        let mut admonitions = Vec::new();
+        // Open admonitions as (index, quote depth), innermost last.
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut depth = 0;
+
+        for event in self.parser() {
+            match event {
+                Event::Start(Tag::BlockQuote(kind)) => {
+                    depth += 1;
+                    if let Some(kind) = kind {
+                        open.push((admonitions.len(), depth));
+                        admonitions.push(Admonition {
+                            kind: admonition::kind(kind),
+                            text: String::new(),
+                        });
+                    }
+                }
+                Event::End(TagEnd::BlockQuote(_)) => {
+                    if open.last().is_some_and(|&(_, d)| d == depth) {
+                        open.pop();
+                    }
+                    depth -= 1;
+                }
+                Event::Text(t) | Event::Code(t) => {
+                    if let Some(&(i, _)) = open.last() {
+                        admonitions[i].text.push_str(&t);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        admonitions
+    }
+
+    fn ast(&self) -> Node {
+        let events = TextMergeWithOffset::new(self.parser().into_offset_iter());
+        let len = self.source.len();
+        match &self.prepared {
+            Some(prepared) => ast::build(events, len, |offset| prepared.source_offset(offset)),
+            None => ast::build(events, len, |offset| offset),
+        }
+    }
+
+    fn to_markdown_with_options(&self, options: &MarkdownFormatOptions) -> String {
+        let opts = Markdown::options_to_pulldown(&self.options);
+        format::to_markdown(
+            self.parser(),
+            options,
+            opts.contains(Options::ENABLE_STRIKETHROUGH),
+        )
+    }
+}
+
+/// Escape text for inclusion in HTML.
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Apply `rewriter` to a link or image start event.
+fn rewrite_url<'a>(event: Event<'a>, rewriter: &UrlRewriter) -> Event<'a> {
+    match event {
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            dest_url: rewriter
+                .rewrite(UrlKind::Link, &dest_url)
+                .map_or(dest_url, Into::into),
+            link_type,
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            dest_url: rewriter
+                .rewrite(UrlKind::Image, &dest_url)
+                .map_or(dest_url, Into::into),
+            link_type,
+            title,
+            id,
+        }),
+        event => event,
+    }
 }
 
 fn heading_level_to_u8(level: HeadingLevel) -> u8 {
@@ -301,8 +643,43 @@ mod tests {
         let doc = md.parse("[Google](https://google.com) and [Rust](https://rust-lang.org)");
         let links = doc.links();
         assert_eq!(links.len(), 2);
-        assert_eq!(links[0], ("Google".to_string(), "https://google.com".to_string()));
-        assert_eq!(links[1], ("Rust".to_string(), "https://rust-lang.org".to_string()));
+        assert_eq!(
+            links[0],
+            ("Google".to_string(), "https://google.com".to_string())
+        );
+        assert_eq!(
+            links[1],
+            ("Rust".to_string(), "https://rust-lang.org".to_string())
+        );
+    }
+
+    #[test]
+    fn document_images() {
+        let md = Markdown::new();
+        let doc = md.parse("![A *logo*](logo.png) and [![badge](ci.svg)](https://ci.test)");
+        assert_eq!(
+            doc.images(),
+            vec![
+                ("A logo".to_string(), "logo.png".to_string()),
+                ("badge".to_string(), "ci.svg".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn document_tasks() {
+        let md = Markdown::new();
+        let source = "- [x] Ship `v1`\n- [ ] Write\n  docs\n  - [X] Nested\n- Not a task\n";
+        assert!(md.parse(source).tasks().is_empty());
+        let doc = md.parse_with_options(source, &MarkdownOptions::gfm());
+        assert_eq!(
+            doc.tasks(),
+            vec![
+                (true, "Ship v1".to_string()),
+                (false, "Write docs".to_string()),
+                (true, "Nested".to_string()),
+            ]
+        );
     }
 
     #[test]
@@ -332,4 +709,43 @@ mod tests {
         assert!(full.smart_punctuation);
         assert!(full.footnotes);
     }
+
+    #[test]
+    fn rewrite_urls() {
+        let options = MarkdownOptions {
+            rewrite_urls: Some(UrlRewriter::new(|kind, url| match kind {
+                UrlKind::Image => Some(format!("https://cdn.test/{}", url)),
+                UrlKind::Link if url.starts_with('/') => Some(format!("https://site.test{}", url)),
+                UrlKind::Link => None,
+            })),
+            html: portals_markdown::HtmlPolicy::Strip,
+            ..MarkdownOptions::default()
+        };
+        let source = "[a](/docs) [b](https://x.test) ![c](logo.png) [d](javascript:alert(1))";
+        let md = Markdown::new();
+        let html = md.render_with_options(source, &options);
+        assert_eq!(html, md.parse_with_options(source, &options).to_html());
+        assert!(html.contains("<a href=\"https://site.test/docs\">a</a>"));
+        assert!(html.contains("<a href=\"https://x.test\">b</a>"));
+        assert!(html.contains("<img src=\"https://cdn.test/logo.png\""));
+        assert!(html.contains("<a href=\"#\">d</a>"));
+    }
+
+    #[test]
+    fn render_to_stream() {
+        use portals_io_native::WriterStream;
+
+        let md = Markdown::new();
+        let source = "# Title\n\nSome *text* and `code`.\n\n".repeat(1000);
+        let options = MarkdownOptions {
+            heading_ids: true,
+            ..MarkdownOptions::gfm()
+        };
+        let mut out = WriterStream::new(Vec::new());
+        md.render_to_with_options(&source, &options, &mut out)
+            .unwrap();
+        let html = String::from_utf8(out.into_inner()).unwrap();
+        assert_eq!(html, md.render_with_options(&source, &options));
+        assert!(html.contains("<h1 id=\"title-999\">"));
+    }
 }
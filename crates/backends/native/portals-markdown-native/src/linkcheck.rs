@@ -0,0 +1,341 @@
+//! Link checking for Markdown documents.
+//!
+//! External links are checked over an [`HttpClient`]; intra-document
+//! anchors (`#section`) are checked against the document's heading IDs.
+
+use futures_util::stream::{self, StreamExt};
+use portals_clocks::MonotonicClock;
+use portals_http::{Headers, HttpClient, Method, PithError, Request};
+use portals_markdown::MarkdownDocument;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Outcome of checking one link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// The target exists.
+    Ok,
+    /// The server answered with an error status.
+    Broken(u16),
+    /// The request failed after all retries.
+    Failed(String),
+    /// An intra-document anchor that matches no heading.
+    MissingAnchor,
+    /// Not checked (relative paths, `mailto:`, and other schemes).
+    Skipped,
+}
+
+/// A checked link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkReport {
+    /// Link text.
+    pub text: String,
+    /// Link destination as written.
+    pub url: String,
+    /// Check outcome.
+    pub status: LinkStatus,
+}
+
+impl LinkReport {
+    /// Whether the link is broken (skipped links are not).
+    pub fn is_broken(&self) -> bool {
+        !matches!(self.status, LinkStatus::Ok | LinkStatus::Skipped)
+    }
+}
+
+/// Validates the links in a [`MarkdownDocument`].
+///
+/// Each distinct URL is requested once, with `HEAD` falling back to `GET`
+/// when the server rejects `HEAD`. Requests run up to `concurrency` at a
+/// time, are spaced at least `host_interval` apart per host, and are retried
+/// on retryable errors, `429`, and `5xx` with exponential backoff.
+///
+/// ```ignore
+/// let checker = LinkChecker::new(client, clock).concurrency(4).retries(2);
+/// for report in checker.check(&doc).await {
+///     if report.is_broken() {
+///         println!("{}: {:?}", report.url, report.status);
+///     }
+/// }
+/// ```
+pub struct LinkChecker<C, K> {
+    client: C,
+    clock: K,
+    concurrency: usize,
+    host_interval: Duration,
+    retries: u32,
+    backoff: Duration,
+    /// Earliest time (monotonic nanos) the next request to each host may go.
+    next_slot: Mutex<HashMap<String, u64>>,
+}
+
+impl<C: HttpClient, K: MonotonicClock> LinkChecker<C, K> {
+    /// Create a checker with 8 concurrent requests, no per-host spacing, and
+    /// 2 retries starting at 500ms backoff.
+    pub fn new(client: C, clock: K) -> Self {
+        Self {
+            client,
+            clock,
+            concurrency: 8,
+            host_interval: Duration::ZERO,
+            retries: 2,
+            backoff: Duration::from_millis(500),
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Maximum number of requests in flight (minimum 1).
+    pub fn concurrency(mut self, n: usize) -> Self {
+        self.concurrency = n.max(1);
+        self
+    }
+
+    /// Minimum spacing between requests to the same host.
+    pub fn host_interval(mut self, interval: Duration) -> Self {
+        self.host_interval = interval;
+        self
+    }
+
+    /// Number of retries after the first attempt.
+    pub fn retries(mut self, n: u32) -> Self {
+        self.retries = n;
+        self
+    }
+
+    /// Delay before the first retry; doubled for each further retry.
+    pub fn backoff(mut self, delay: Duration) -> Self {
+        self.backoff = delay;
+        self
+    }
+
+    /// Check every link in `doc`, returning reports in document order.
+    pub async fn check<D: MarkdownDocument>(&self, doc: &D) -> Vec<LinkReport> {
+        let links = doc.links();
+        let anchors = crate::slug::ids(&doc.toc());
+
+        let mut external: Vec<&str> = Vec::new();
+        let mut seen = HashSet::new();
+        for (_, url) in &links {
+            if is_external(url) && seen.insert(url.as_str()) {
+                external.push(url);
+            }
+        }
+
+        let results: HashMap<&str, LinkStatus> = stream::iter(external)
+            .map(|url| async move { (url, self.check_url(url).await) })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        links
+            .iter()
+            .map(|(text, url)| {
+                let status = if let Some(anchor) = url.strip_prefix('#') {
+                    if anchors.contains(anchor) {
+                        LinkStatus::Ok
+                    } else {
+                        LinkStatus::MissingAnchor
+                    }
+                } else {
+                    results
+                        .get(url.as_str())
+                        .cloned()
+                        .unwrap_or(LinkStatus::Skipped)
+                };
+                LinkReport {
+                    text: text.clone(),
+                    url: url.clone(),
+                    status,
+                }
+            })
+            .collect()
+    }
+
+    /// Check one external URL, retrying transient failures.
+    async fn check_url(&self, url: &str) -> LinkStatus {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let (status, retryable) = self.probe(url).await;
+            if !retryable || attempt >= self.retries {
+                return status;
+            }
+            attempt += 1;
+            self.clock.subscribe_duration(delay).await;
+            delay = delay.saturating_mul(2);
+        }
+    }
+
+    /// A single attempt: the status and whether it is worth retrying.
+    async fn probe(&self, url: &str) -> (LinkStatus, bool) {
+        let mut method = Method::Head;
+        loop {
+            self.throttle(host(url)).await;
+            let request = Request {
+                method,
+                url: url.to_string(),
+                headers: Headers::new(),
+                body: None,
+            };
+            return match self.client.send(request).await {
+                Ok(response) if method == Method::Head && matches!(response.status, 405 | 501) => {
+                    method = Method::Get;
+                    continue;
+                }
+                Ok(response) if response.status < 400 => (LinkStatus::Ok, false),
+                Ok(response) => (
+                    LinkStatus::Broken(response.status),
+                    response.status == 429 || response.status >= 500,
+                ),
+                Err(e) => (LinkStatus::Failed(e.to_string()), e.is_retryable()),
+            };
+        }
+    }
+
+    /// Wait for this host's next request slot.
+    async fn throttle(&self, host: &str) {
+        if self.host_interval.is_zero() {
+            return;
+        }
+        let now = self.clock.now();
+        let slot = {
+            let mut slots = self
+                .next_slot
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let slot = slots.get(host).copied().unwrap_or(0).max(now);
+            slots.insert(
+                host.to_string(),
+                slot.saturating_add(self.host_interval.as_nanos() as u64),
+            );
+            slot
+        };
+        if slot > now {
+            self.clock.subscribe_instant(slot).await;
+        }
+    }
+}
+
+fn is_external(url: &str) -> bool {
+    let lower = url.get(..8).unwrap_or(url).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// The `host[:port]` part of an absolute URL.
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..end];
+    authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Markdown;
+    use portals_clocks_mock::MockMonotonicClock;
+    use portals_http_mock::{MockHttpClient, ResponseBuilder};
+    use portals_markdown::MarkdownParser;
+
+    fn checker(client: MockHttpClient) -> LinkChecker<MockHttpClient, MockMonotonicClock> {
+        LinkChecker::new(client, MockMonotonicClock::new()).concurrency(1)
+    }
+
+    #[tokio::test]
+    async fn checks_anchors() {
+        let doc = Markdown::new().parse(
+            "# Getting Started\n\n## Setup\n\n## Setup\n\n\
+             [a](#getting-started) [b](#setup-1) [c](#missing) [d](./other.md)",
+        );
+        let client = MockHttpClient::new();
+        let reports = checker(client.clone()).check(&doc).await;
+
+        let statuses: Vec<_> = reports.iter().map(|r| r.status.clone()).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                LinkStatus::Ok,
+                LinkStatus::Ok,
+                LinkStatus::MissingAnchor,
+                LinkStatus::Skipped
+            ]
+        );
+        assert_eq!(client.request_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn checks_external_links_once() {
+        let doc = Markdown::new().parse(
+            "[ok](https://a.example/) [gone](https://b.example/x) [again](https://a.example/)",
+        );
+        let client = MockHttpClient::new();
+        client.queue_response(ResponseBuilder::ok().build());
+        client.queue_response(ResponseBuilder::not_found().build());
+
+        let reports = checker(client.clone()).check(&doc).await;
+        assert_eq!(reports[0].status, LinkStatus::Ok);
+        assert_eq!(reports[1].status, LinkStatus::Broken(404));
+        assert_eq!(reports[2].status, LinkStatus::Ok);
+        assert!(reports[1].is_broken());
+        assert_eq!(client.request_count(), 2);
+        client.assert_requested_with(Method::Head, "https://a.example/");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_get() {
+        let doc = Markdown::new().parse("[x](https://a.example/)");
+        let client = MockHttpClient::new();
+        client.queue_response(ResponseBuilder::new(405).build());
+        client.queue_response(ResponseBuilder::ok().build());
+
+        let reports = checker(client.clone()).check(&doc).await;
+        assert_eq!(reports[0].status, LinkStatus::Ok);
+        client.assert_requested_with(Method::Get, "https://a.example/");
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let doc = Markdown::new().parse("[x](https://a.example/)");
+        let client = MockHttpClient::new();
+        client.queue_error("timeout");
+        client.queue_response(ResponseBuilder::server_error().build());
+        client.queue_response(ResponseBuilder::ok().build());
+
+        let reports = checker(client.clone()).retries(2).check(&doc).await;
+        assert_eq!(reports[0].status, LinkStatus::Ok);
+        assert_eq!(client.request_count(), 3);
+
+        client.clear_requests();
+        client.queue_error("timeout");
+        let reports = checker(client.clone()).retries(0).check(&doc).await;
+        assert!(matches!(reports[0].status, LinkStatus::Failed(_)));
+        assert_eq!(client.request_count(), 1);
+    }
+
+    #[test]
+    fn extracts_host() {
+        assert_eq!(host("https://example.com/path"), "example.com");
+        assert_eq!(host("http://user@example.com:8080?q"), "example.com:8080");
+    }
+
+    #[test]
+    fn spaces_requests_per_host() {
+        let clock = MockMonotonicClock::new();
+        let checker = LinkChecker::new(MockHttpClient::new(), clock.clone())
+            .host_interval(Duration::from_secs(1));
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            checker.throttle("a").await;
+            checker.throttle("a").await;
+            checker.throttle("b").await;
+        });
+        let slots = checker.next_slot.lock().unwrap();
+        assert_eq!(slots["a"], 2_000_000_000);
+        assert_eq!(slots["b"], 1_000_000_000);
+    }
+}
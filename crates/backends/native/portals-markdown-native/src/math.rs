@@ -0,0 +1,67 @@
+//! Math (LaTeX) spans.
+//!
+//! pulldown-cmark recognizes `$...$` and `$$...$$`; the TeX source is passed
+//! through, HTML-escaped, for a client-side renderer such as KaTeX or
+//! MathJax to typeset.
+
+use pulldown_cmark::{CowStr, Event};
+
+/// Render math as `<span class="math">` (inline) or `<div class="math">`
+/// (display).
+pub(crate) fn html_event(event: Event<'_>) -> Event<'_> {
+    match event {
+        Event::InlineMath(tex) => Event::InlineHtml(CowStr::from(format!(
+            "<span class=\"math\">{}</span>",
+            crate::escape_html(&tex)
+        ))),
+        Event::DisplayMath(tex) => Event::InlineHtml(CowStr::from(format!(
+            "<div class=\"math\">{}</div>",
+            crate::escape_html(&tex)
+        ))),
+        event => event,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Markdown;
+    use portals_markdown::{MarkdownDocument, MarkdownOptions, MarkdownParser, MarkdownRenderer};
+
+    fn options() -> MarkdownOptions {
+        MarkdownOptions {
+            math: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_inline_and_display() {
+        let md = Markdown::new();
+        let html =
+            md.render_with_options("Euler: $e^{i\\pi} < 0$\n\n$$\\sum_i x_i$$\n", &options());
+        assert!(
+            html.contains("<span class=\"math\">e^{i\\pi} &lt; 0</span>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<div class=\"math\">\\sum_i x_i</div>"),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let html = Markdown::new().render("costs $5 and $6");
+        assert!(!html.contains("class=\"math\""));
+        assert!(html.contains("$5 and $6"));
+    }
+
+    #[test]
+    fn math_in_text_and_markdown() {
+        let doc = Markdown::new().parse_with_options("Area $\\pi r^2$.\n", &options());
+        assert_eq!(doc.to_text(), "Area \\pi r^2.");
+        assert_eq!(doc.to_markdown(), "Area $\\pi r^2$.\n");
+    }
+}
@@ -0,0 +1,330 @@
+//! Raw HTML sanitization.
+//!
+//! [`HtmlPolicy::Allowlist`] is applied by `ammonia` to the whole rendered
+//! document, since raw HTML arrives in pieces (a tag per inline event, a
+//! line per block event) that only make sense together. The HTML rendered
+//! from Markdown is swapped for placeholders while it runs and put back
+//! afterwards, so the allowlist only applies to raw HTML.
+
+use portals_markdown::{HtmlAllowlist, HtmlPolicy};
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/// URL schemes links and URL attributes may use.
+const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+/// Opens and closes raw HTML in the rendered document. Rendered Markdown
+/// never has `<` before a private use character, and the character is
+/// removed from raw HTML.
+const RAW_START: &str = "<\u{E000}";
+const RAW_END: &str = "\u{E000}>";
+
+/// Opens and closes the placeholder for a piece of rendered Markdown.
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+pub(crate) struct Sanitizer<'p> {
+    policy: &'p HtmlPolicy,
+}
+
+impl<'p> Sanitizer<'p> {
+    pub(crate) fn new(policy: &'p HtmlPolicy) -> Self {
+        Self { policy }
+    }
+
+    pub(crate) fn event<'a>(&mut self, event: Event<'a>) -> Event<'a> {
+        match (self.policy, event) {
+            (HtmlPolicy::Allow, event) => event,
+            (HtmlPolicy::Strip, Event::Html(_) | Event::InlineHtml(_)) => {
+                Event::Html(CowStr::Borrowed(""))
+            }
+            (HtmlPolicy::Escape, Event::Html(html) | Event::InlineHtml(html)) => Event::Text(html),
+            (HtmlPolicy::Allowlist(_), Event::Html(html) | Event::InlineHtml(html)) => {
+                let html = html.replace([PLACEHOLDER_START, PLACEHOLDER_END], "\u{FFFD}");
+                // A trailing newline stays outside, where the HTML writer
+                // looks for it before starting a new block.
+                let (html, newline) = match html.strip_suffix('\n') {
+                    Some(html) => (html, "\n"),
+                    None => (html.as_str(), ""),
+                };
+                Event::Html(CowStr::from(format!(
+                    "{}{}{}{}",
+                    RAW_START, html, RAW_END, newline
+                )))
+            }
+            (
+                _,
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }),
+            ) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: safe_destination(dest_url),
+                title,
+                id,
+            }),
+            (
+                _,
+                Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }),
+            ) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: safe_destination(dest_url),
+                title,
+                id,
+            }),
+            (_, event) => event,
+        }
+    }
+}
+
+/// Apply `allowlist` to the raw HTML in `rendered`, the output of events
+/// passed through a [`Sanitizer`] for it.
+pub(crate) fn clean(allowlist: &HtmlAllowlist, rendered: &str) -> String {
+    let mut input = String::with_capacity(rendered.len());
+    let mut fragments = Vec::new();
+    let mut rest = rendered;
+    while !rest.is_empty() {
+        let (markdown, raw, after) = match rest.find(RAW_START) {
+            Some(start) => {
+                let raw = &rest[start + RAW_START.len()..];
+                let end = raw.find(RAW_END).unwrap_or(raw.len());
+                let after = raw.get(end + RAW_END.len()..).unwrap_or("");
+                (&rest[..start], &raw[..end], after)
+            }
+            None => (rest, "", ""),
+        };
+        if markdown.trim_ascii().is_empty() {
+            // Whitespace, like the newline between the lines of an HTML
+            // block, may be inside a tag split across them.
+            input.push_str(markdown);
+        } else {
+            let _ = write!(
+                input,
+                "{}{}{}",
+                PLACEHOLDER_START,
+                fragments.len(),
+                PLACEHOLDER_END
+            );
+            fragments.push(markdown);
+        }
+        input.push_str(raw);
+        rest = after;
+    }
+
+    let cleaned = builder(allowlist).clean(&input).to_string();
+    let mut out = String::with_capacity(rendered.len());
+    let mut rest = cleaned.as_str();
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        out.push_str(&rest[..start]);
+        let placeholder = &rest[start + PLACEHOLDER_START.len_utf8()..];
+        let end = placeholder
+            .find(PLACEHOLDER_END)
+            .unwrap_or(placeholder.len());
+        if let Some(fragment) = placeholder[..end]
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| fragments.get(i))
+        {
+            out.push_str(fragment);
+        }
+        rest = placeholder
+            .get(end + PLACEHOLDER_END.len_utf8()..)
+            .unwrap_or("");
+    }
+    out.push_str(rest);
+    out
+}
+
+/// An `ammonia` configuration allowing what `allowlist` does.
+fn builder(allowlist: &HtmlAllowlist) -> ammonia::Builder<'_> {
+    let tags: HashSet<&str> = allowlist.tags().map(|(tag, _)| tag).collect();
+    let tag_attributes: HashMap<&str, HashSet<&str>> = allowlist
+        .tags()
+        .map(|(tag, attributes)| (tag, attributes.filter(|a| !a.starts_with("on")).collect()))
+        .collect();
+    let attributes: HashSet<&str> = allowlist
+        .attributes()
+        .filter(|a| !a.starts_with("on"))
+        .collect();
+
+    let mut builder = ammonia::Builder::empty();
+    builder
+        .rm_clean_content_tags(&tags)
+        .tags(tags)
+        .tag_attributes(tag_attributes)
+        .generic_attributes(attributes)
+        .url_schemes(SAFE_SCHEMES.iter().copied().collect())
+        .link_rel(None)
+        .attribute_filter(filter_attribute);
+    builder
+}
+
+/// Drop attribute values `ammonia` doesn't check itself.
+fn filter_attribute<'u>(_element: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    // A placeholder only ends up in an attribute if raw HTML left a tag
+    // open, and the Markdown it stands for doesn't belong there.
+    if value.contains(PLACEHOLDER_START) {
+        return None;
+    }
+    if attribute == "srcset"
+        && !value
+            .split(',')
+            .all(|candidate| is_safe_url(candidate.split_whitespace().next().unwrap_or("")))
+    {
+        return None;
+    }
+    Some(Cow::Borrowed(value))
+}
+
+/// Whether `url` is relative or uses a safe scheme.
+///
+/// Browsers ignore whitespace and control characters in a scheme, so
+/// `java\tscript:` is caught too.
+fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url.as_bytes()[i] == b':' => SAFE_SCHEMES
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(&url[..i])),
+        _ => true,
+    }
+}
+
+fn safe_destination(url: CowStr<'_>) -> CowStr<'_> {
+    if is_safe_url(&url) {
+        url
+    } else {
+        CowStr::Borrowed("#")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Markdown;
+    use portals_markdown::{MarkdownOptions, MarkdownRenderer};
+
+    fn render(source: &str, html: HtmlPolicy) -> String {
+        let options = MarkdownOptions {
+            html,
+            ..MarkdownOptions::gfm()
+        };
+        Markdown::new().render_with_options(source, &options)
+    }
+
+    #[test]
+    fn allow_passes_html_through() {
+        let html = render(
+            "<script>x()</script>\n\nhi <b onclick=\"y()\">b</b>",
+            HtmlPolicy::Allow,
+        );
+        assert!(html.contains("<script>x()</script>"));
+        assert!(html.contains("<b onclick=\"y()\">"));
+    }
+
+    #[test]
+    fn strip_and_escape() {
+        let source = "<div>\nblock\n</div>\n\nInline <em>tag</em> here.\n";
+        assert_eq!(
+            render(source, HtmlPolicy::Strip),
+            "<p>Inline tag here.</p>\n"
+        );
+        assert_eq!(
+            render(source, HtmlPolicy::Escape),
+            "&lt;div&gt;\nblock\n&lt;/div&gt;\n<p>Inline &lt;em&gt;tag&lt;/em&gt; here.</p>\n"
+        );
+    }
+
+    #[test]
+    fn allowlist_filters_tags_and_attributes() {
+        let policy = HtmlPolicy::Allowlist(HtmlAllowlist::basic());
+        let html = render(
+            "<div class=\"x\" onmouseover=\"steal()\">\n<script>alert(1)</script>\n<!-- note -->\n</div>\n\nA <a href='/ok' target=_blank title=\"T &amp; U\">link</a><br/> and <iframe src=x></iframe>.\n",
+            policy,
+        );
+        assert_eq!(
+            html,
+            "<div>\n\n\n</div>\n<p>A <a href=\"/ok\" title=\"T &amp; U\">link</a><br> and .</p>\n"
+        );
+    }
+
+    #[test]
+    fn allowlist_rejects_unsafe_urls() {
+        let policy = HtmlPolicy::Allowlist(
+            HtmlAllowlist::new()
+                .tag("a", &["href"])
+                .tag("img", &["src", "srcset"]),
+        );
+        for (source, expected) in [
+            ("<a href=\"javascript:alert(1)\">x</a>", "<a>x</a>"),
+            (
+                "<a href=\"java&#x09;script&colon;alert(1)\">x</a>",
+                "<a>x</a>",
+            ),
+            ("<a href=\"&#106;avascript:x\">x</a>", "<a>x</a>"),
+            (
+                "<a href=\"https://ok.example/?a=1&amp;b=2\">x</a>",
+                "<a href=\"https://ok.example/?a=1&amp;b=2\">x</a>",
+            ),
+            ("x <img srcset=\"a.png 1x, javascript:x 2x\">", "x <img>"),
+        ] {
+            assert_eq!(
+                render(source, policy.clone()),
+                format!("<p>{}</p>\n", expected),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn allowlist_joins_tags_split_across_lines() {
+        let policy = HtmlPolicy::Allowlist(HtmlAllowlist::basic());
+        let html = render(
+            "<div>\n<a\n  href=\"/x\" onclick=\"y()\">x</a>\n</div>\n",
+            policy,
+        );
+        assert_eq!(html, "<div>\n<a href=\"/x\">x</a>\n</div>\n");
+    }
+
+    #[test]
+    fn allowlist_applies_to_html_around_markdown() {
+        let policy = HtmlPolicy::Allowlist(HtmlAllowlist::basic());
+        let html = render(
+            "<details onclick=\"x()\">\n<summary>More</summary>\n\n*hidden* <u>text</u>\n\n</details>\n",
+            policy.clone(),
+        );
+        assert_eq!(
+            html,
+            "<details>\n<summary>More</summary>\n<p><em>hidden</em> text</p>\n</details>\n"
+        );
+
+        // Placeholder characters in the source are not placeholders.
+        let html = render("a \u{E000}0\u{E001} <b>b</b>", policy);
+        assert_eq!(html, "<p>a \u{E000}0\u{E001} <b>b</b></p>\n");
+    }
+
+    #[test]
+    fn sanitizing_rewrites_unsafe_markdown_links() {
+        let source = "[a](javascript:alert(1)) [b](https://ok.example) ![c](JAVASCRIPT:x)";
+        let html = render(source, HtmlPolicy::Escape);
+        assert_eq!(
+            html,
+            "<p><a href=\"#\">a</a> <a href=\"https://ok.example\">b</a> <img src=\"#\" alt=\"c\" /></p>\n"
+        );
+        assert!(render(source, HtmlPolicy::Allow).contains("javascript:alert(1)"));
+    }
+}
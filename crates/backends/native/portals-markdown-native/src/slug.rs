@@ -0,0 +1,197 @@
+//! Heading anchor IDs.
+
+use crate::heading_level_to_u8;
+use portals_markdown::TocEntry;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::collections::{HashMap, HashSet};
+
+/// Anchor slug for heading text, GitHub style: lowercase, punctuation
+/// dropped, spaces turned into `-`.
+fn slug(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Hands out unique slugs, suffixing repeats `-1`, `-2`, ...
+#[derive(Debug, Default)]
+struct Slugger {
+    counts: HashMap<String, usize>,
+    used: HashSet<String>,
+}
+
+impl Slugger {
+    fn unique(&mut self, text: &str) -> String {
+        let base = slug(text);
+        let count = self.counts.entry(base.clone()).or_insert(0);
+        loop {
+            let candidate = if *count == 0 {
+                base.clone()
+            } else {
+                format!("{}-{}", base, count)
+            };
+            *count += 1;
+            if self.used.insert(candidate.clone()) {
+                return candidate;
+            }
+        }
+    }
+}
+
+/// Give every heading an ID. Explicit `{#id}` attributes are kept, and
+/// generated slugs avoid them.
+pub(crate) fn assign_ids(events: &mut [Event<'_>]) {
+    let mut slugger = Slugger::default();
+    for event in events.iter() {
+        if let Event::Start(Tag::Heading { id: Some(id), .. }) = event {
+            slugger.used.insert(id.to_string());
+        }
+    }
+
+    let mut heading: Option<(usize, String)> = None;
+    for i in 0..events.len() {
+        match &events[i] {
+            Event::Start(Tag::Heading { id: None, .. }) => heading = Some((i, String::new())),
+            Event::Text(t) | Event::Code(t) => {
+                if let Some((_, text)) = &mut heading {
+                    text.push_str(t);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((start, text)) = heading.take()
+                    && let Event::Start(Tag::Heading { id, .. }) = &mut events[start]
+                {
+                    *id = Some(slugger.unique(&text).into());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Build a table of contents from events whose headings all have IDs.
+pub(crate) fn toc<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<TocEntry> {
+    let mut flat: Vec<TocEntry> = Vec::new();
+    let mut current: Option<TocEntry> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                current = Some(TocEntry {
+                    level: heading_level_to_u8(level),
+                    text: String::new(),
+                    id: id.map(|id| id.to_string()).unwrap_or_default(),
+                    children: Vec::new(),
+                });
+            }
+            Event::Text(t) | Event::Code(t) => {
+                if let Some(entry) = &mut current {
+                    entry.text.push_str(&t);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => flat.extend(current.take()),
+            _ => {}
+        }
+    }
+    nest(flat)
+}
+
+/// Nest each entry under the closest preceding entry of a lower level.
+fn nest(flat: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut roots: Vec<TocEntry> = Vec::new();
+    // Open ancestors of the next entry, outermost first.
+    let mut stack: Vec<TocEntry> = Vec::new();
+    for entry in flat {
+        while stack.last().is_some_and(|open| open.level >= entry.level) {
+            close(&mut stack, &mut roots);
+        }
+        stack.push(entry);
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+    roots
+}
+
+fn close(stack: &mut Vec<TocEntry>, roots: &mut Vec<TocEntry>) {
+    let Some(entry) = stack.pop() else { return };
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(entry),
+        None => roots.push(entry),
+    }
+}
+
+/// Every ID in a table of contents.
+pub(crate) fn ids(toc: &[TocEntry]) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    let mut pending: Vec<&TocEntry> = toc.iter().collect();
+    while let Some(entry) = pending.pop() {
+        ids.insert(entry.id.clone());
+        pending.extend(&entry.children);
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Markdown;
+    use portals_markdown::{MarkdownDocument, MarkdownOptions, MarkdownParser, TocEntry};
+
+    fn entry(level: u8, text: &str, id: &str, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry {
+            level,
+            text: text.into(),
+            id: id.into(),
+            children,
+        }
+    }
+
+    #[test]
+    fn nests_headings() {
+        let source =
+            "# Guide\n\n## Install\n\n### From `source`\n\n## Usage\n\n#### Deep\n\n# Appendix\n";
+        let doc = Markdown::new().parse(source);
+        assert_eq!(
+            doc.toc(),
+            [
+                entry(
+                    1,
+                    "Guide",
+                    "guide",
+                    vec![
+                        entry(
+                            2,
+                            "Install",
+                            "install",
+                            vec![entry(3, "From source", "from-source", vec![])]
+                        ),
+                        entry(2, "Usage", "usage", vec![entry(4, "Deep", "deep", vec![])]),
+                    ]
+                ),
+                entry(1, "Appendix", "appendix", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_match_html() {
+        let source = "# Intro {#intro-1}\n\n# Intro\n\n# Intro\n\n## What's new?\n";
+        let doc = Markdown::new().parse_with_options(source, &MarkdownOptions::gfm());
+        let toc = doc.toc();
+        let ids: Vec<&str> = toc.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["intro-1", "intro", "intro-2"]);
+        assert_eq!(toc[2].children[0].id, "whats-new");
+
+        let html = doc.to_html();
+        assert!(html.contains("<h1 id=\"intro-1\">Intro</h1>"), "{}", html);
+        assert!(html.contains("<h1 id=\"intro\">Intro</h1>"), "{}", html);
+        assert!(html.contains("<h1 id=\"intro-2\">Intro</h1>"), "{}", html);
+        assert!(html.contains("<h2 id=\"whats-new\">"), "{}", html);
+    }
+}
@@ -0,0 +1,57 @@
+//! Incremental HTML output to an [`OutputStream`].
+
+use portals_io::{OutputStream, StreamError};
+use std::fmt;
+
+const BUFFER_SIZE: usize = 8192;
+
+/// Buffers rendered HTML and writes it to a stream in chunks.
+///
+/// `fmt::Write` cannot carry the stream's error, so the first one is kept
+/// and returned from [`finish`](Self::finish).
+pub(crate) struct StreamWriter<'s, S> {
+    stream: &'s mut S,
+    buf: Vec<u8>,
+    error: Option<StreamError>,
+}
+
+impl<'s, S: OutputStream> StreamWriter<'s, S> {
+    pub(crate) fn new(stream: &'s mut S) -> Self {
+        Self {
+            stream,
+            buf: Vec::with_capacity(BUFFER_SIZE),
+            error: None,
+        }
+    }
+
+    fn write_buf(&mut self) -> Result<(), StreamError> {
+        if !self.buf.is_empty() {
+            self.stream.blocking_write(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    /// Write what is buffered and flush the stream.
+    pub(crate) fn finish(mut self, result: fmt::Result) -> Result<(), StreamError> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        result.map_err(|_| StreamError::Other("formatting failed".to_string()))?;
+        self.write_buf()?;
+        self.stream.blocking_flush()
+    }
+}
+
+impl<S: OutputStream> fmt::Write for StreamWriter<'_, S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.buf.extend_from_slice(s.as_bytes());
+        if self.buf.len() >= BUFFER_SIZE
+            && let Err(error) = self.write_buf()
+        {
+            self.error = Some(error);
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
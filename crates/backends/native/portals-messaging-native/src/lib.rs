@@ -3,36 +3,86 @@
 //! Provides `MemoryMessaging` for creating channels and topics,
 //! with implementations of the `Channel`, `Topic`, and related traits.
 
+mod trace;
+
+pub use portals_observe::REQUEST_ID;
+pub use trace::{TracingReceiver, TracingSender, TracingTopic, set_trace_context, trace_context};
+
 use portals_messaging::{Channel, Error, Message, Receiver, Sender, Subscriber, Topic};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use portals_observe::{Gauge, Metrics};
+use portals_signals::Shutdown;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::time::Duration;
 use tokio::sync::{broadcast, mpsc};
 
 /// A tokio mpsc sender.
 pub struct MpscSender {
     tx: mpsc::Sender<Message>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl MpscSender {
+    /// Number of messages sent but not yet received.
+    pub fn queue_depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
 }
 
 impl Sender for MpscSender {
     async fn send(&self, message: Message) -> Result<(), Error> {
-        self.tx.send(message).await.map_err(|_| Error::Closed)
+        // Count once there is room and before sending, so a send cancelled
+        // while waiting isn't counted and the receiver never decrements
+        // first.
+        let permit = self.tx.reserve().await.map_err(|_| Error::Closed)?;
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        permit.send(message);
+        Ok(())
+    }
+
+    /// Reserves room for as many messages as the buffer holds at a time,
+    /// so each chunk is queued together with no other sender's messages
+    /// interleaved.
+    async fn send_batch(&self, messages: &[Message]) -> Result<(), Error> {
+        for chunk in messages.chunks(self.tx.max_capacity()) {
+            let permits = self
+                .tx
+                .reserve_many(chunk.len())
+                .await
+                .map_err(|_| Error::Closed)?;
+            self.depth.fetch_add(chunk.len(), Ordering::Relaxed);
+            for (permit, message) in permits.zip(chunk) {
+                permit.send(message.clone());
+            }
+        }
+        Ok(())
     }
 }
 
 /// A tokio mpsc receiver.
 pub struct MpscReceiver {
     rx: tokio::sync::Mutex<mpsc::Receiver<Message>>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl MpscReceiver {
+    /// Number of messages sent but not yet received.
+    pub fn queue_depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    fn taken(&self, count: usize) {
+        self.depth.fetch_sub(count, Ordering::Relaxed);
+    }
 }
 
 impl Receiver for MpscReceiver {
     async fn receive(&self) -> Result<Message, Error> {
-        self.rx
-            .lock()
-            .await
-            .recv()
-            .await
-            .ok_or(Error::Closed)
+        let msg = self.rx.lock().await.recv().await.ok_or(Error::Closed)?;
+        self.taken(1);
+        Ok(msg)
     }
 
     async fn receive_timeout(&self, timeout: Duration) -> Result<Message, Error> {
@@ -43,28 +93,53 @@ impl Receiver for MpscReceiver {
 
     async fn try_receive(&self) -> Result<Option<Message>, Error> {
         match self.rx.lock().await.try_recv() {
-            Ok(msg) => Ok(Some(msg)),
+            Ok(msg) => {
+                self.taken(1);
+                Ok(Some(msg))
+            }
             Err(mpsc::error::TryRecvError::Empty) => Ok(None),
             Err(mpsc::error::TryRecvError::Disconnected) => Err(Error::Closed),
         }
     }
+
+    async fn receive_batch(&self, max: usize, max_wait: Duration) -> Result<Vec<Message>, Error> {
+        let mut batch = Vec::new();
+        if max == 0 {
+            return Ok(batch);
+        }
+        let mut rx = self.rx.lock().await;
+        match tokio::time::timeout(max_wait, rx.recv_many(&mut batch, max)).await {
+            Ok(0) => Err(Error::Closed),
+            _ => {
+                self.taken(batch.len());
+                Ok(batch)
+            }
+        }
+    }
 }
 
+/// Queue depths of the live pairs created by a named channel.
+type DepthRegistry = Arc<Mutex<Vec<Weak<AtomicUsize>>>>;
+
 /// An mpsc channel factory.
 #[derive(Debug, Default)]
 pub struct MpscChannel {
     buffer_size: usize,
+    registry: Option<DepthRegistry>,
 }
 
 impl MpscChannel {
     /// Create with default buffer size (32).
     pub fn new() -> Self {
-        Self { buffer_size: 32 }
+        Self::with_buffer_size(32)
     }
 
     /// Create with custom buffer size.
     pub fn with_buffer_size(size: usize) -> Self {
-        Self { buffer_size: size }
+        Self {
+            buffer_size: size,
+            registry: None,
+        }
     }
 }
 
@@ -74,10 +149,20 @@ impl Channel for MpscChannel {
 
     fn create(&self) -> (Self::Sender, Self::Receiver) {
         let (tx, rx) = mpsc::channel(self.buffer_size);
+        let depth = Arc::new(AtomicUsize::new(0));
+        if let Some(registry) = &self.registry {
+            let mut depths = registry.lock().unwrap();
+            depths.retain(|d| d.strong_count() > 0);
+            depths.push(Arc::downgrade(&depth));
+        }
         (
-            MpscSender { tx },
+            MpscSender {
+                tx,
+                depth: depth.clone(),
+            },
             MpscReceiver {
                 rx: tokio::sync::Mutex::new(rx),
+                depth,
             },
         )
     }
@@ -86,6 +171,13 @@ impl Channel for MpscChannel {
 /// A broadcast topic subscriber.
 pub struct BroadcastSubscriber {
     rx: tokio::sync::Mutex<broadcast::Receiver<Message>>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl BroadcastSubscriber {
+    fn lagged(&self, skipped: u64) {
+        self.lagged.fetch_add(skipped, Ordering::Relaxed);
+    }
 }
 
 impl Receiver for BroadcastSubscriber {
@@ -93,7 +185,7 @@ impl Receiver for BroadcastSubscriber {
         loop {
             match self.rx.lock().await.recv().await {
                 Ok(msg) => return Ok(msg),
-                Err(broadcast::error::RecvError::Lagged(_)) => continue, // Skip lagged messages
+                Err(broadcast::error::RecvError::Lagged(n)) => self.lagged(n), // Skip lagged messages
                 Err(broadcast::error::RecvError::Closed) => return Err(Error::Closed),
             }
         }
@@ -109,10 +201,43 @@ impl Receiver for BroadcastSubscriber {
         match self.rx.lock().await.try_recv() {
             Ok(msg) => Ok(Some(msg)),
             Err(broadcast::error::TryRecvError::Empty) => Ok(None),
-            Err(broadcast::error::TryRecvError::Lagged(_)) => Ok(None), // Treat lagged as empty
+            Err(broadcast::error::TryRecvError::Lagged(n)) => {
+                // Treat lagged as empty
+                self.lagged(n);
+                Ok(None)
+            }
             Err(broadcast::error::TryRecvError::Closed) => Err(Error::Closed),
         }
     }
+
+    async fn receive_batch(&self, max: usize, max_wait: Duration) -> Result<Vec<Message>, Error> {
+        let mut batch = Vec::new();
+        if max == 0 {
+            return Ok(batch);
+        }
+        let mut rx = self.rx.lock().await;
+        let first = async {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => return Ok(msg),
+                    Err(broadcast::error::RecvError::Lagged(n)) => self.lagged(n),
+                    Err(broadcast::error::RecvError::Closed) => return Err(Error::Closed),
+                }
+            }
+        };
+        match tokio::time::timeout(max_wait, first).await {
+            Ok(msg) => batch.push(msg?),
+            Err(_) => return Ok(batch),
+        }
+        while batch.len() < max {
+            match rx.try_recv() {
+                Ok(msg) => batch.push(msg),
+                Err(broadcast::error::TryRecvError::Lagged(n)) => self.lagged(n),
+                Err(_) => break,
+            }
+        }
+        Ok(batch)
+    }
 }
 
 impl Subscriber for BroadcastSubscriber {
@@ -125,12 +250,25 @@ impl Subscriber for BroadcastSubscriber {
 /// A broadcast topic.
 pub struct BroadcastTopic {
     tx: broadcast::Sender<Message>,
+    lagged: Arc<AtomicU64>,
 }
 
 impl BroadcastTopic {
     fn new(capacity: usize) -> Self {
         let (tx, _) = broadcast::channel(capacity);
-        Self { tx }
+        Self {
+            tx,
+            lagged: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Snapshot the topic's subscriber count, backlog, and lag.
+    pub fn stats(&self) -> TopicStats {
+        TopicStats {
+            subscribers: self.tx.receiver_count(),
+            queued: self.tx.len(),
+            lagged: self.lagged.load(Ordering::Relaxed),
+        }
     }
 }
 
@@ -143,9 +281,17 @@ impl Topic for BroadcastTopic {
         Ok(())
     }
 
+    async fn publish_batch(&self, messages: &[Message]) -> Result<(), Error> {
+        for message in messages {
+            let _ = self.tx.send(message.clone());
+        }
+        Ok(())
+    }
+
     async fn subscribe(&self) -> Result<Self::Subscriber, Error> {
         Ok(BroadcastSubscriber {
             rx: tokio::sync::Mutex::new(self.tx.subscribe()),
+            lagged: self.lagged.clone(),
         })
     }
 }
@@ -154,6 +300,13 @@ impl Topic for BroadcastTopic {
 #[derive(Clone)]
 pub struct SharedTopic(Arc<BroadcastTopic>);
 
+impl SharedTopic {
+    /// Snapshot the topic's subscriber count, backlog, and lag.
+    pub fn stats(&self) -> TopicStats {
+        self.0.stats()
+    }
+}
+
 impl Topic for SharedTopic {
     type Subscriber = BroadcastSubscriber;
 
@@ -161,11 +314,47 @@ impl Topic for SharedTopic {
         self.0.publish(message).await
     }
 
+    async fn publish_batch(&self, messages: &[Message]) -> Result<(), Error> {
+        self.0.publish_batch(messages).await
+    }
+
     async fn subscribe(&self) -> Result<Self::Subscriber, Error> {
         self.0.subscribe().await
     }
 }
 
+/// A snapshot of a topic's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopicStats {
+    /// Live subscribers.
+    pub subscribers: usize,
+    /// Messages retained for subscribers that have not received them yet.
+    pub queued: usize,
+    /// Messages skipped by subscribers that fell more than the topic's
+    /// capacity behind, in total.
+    pub lagged: u64,
+}
+
+/// A snapshot of a named channel's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Live sender/receiver pairs created from the channel.
+    pub pairs: usize,
+    /// Messages sent but not yet received, across all pairs.
+    pub queued: usize,
+    /// Buffer size of each pair.
+    pub capacity: usize,
+}
+
+/// A snapshot of every topic and named channel in a [`MemoryMessaging`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessagingStats {
+    /// Topics by name.
+    pub topics: BTreeMap<String, TopicStats>,
+    /// Named channels by name.
+    pub channels: BTreeMap<String, ChannelStats>,
+}
+
 /// In-memory messaging system.
 ///
 /// This struct manages channels and topics. Topic/channel construction is
@@ -173,6 +362,7 @@ impl Topic for SharedTopic {
 #[derive(Default)]
 pub struct MemoryMessaging {
     topics: RwLock<HashMap<String, Arc<BroadcastTopic>>>,
+    channels: RwLock<HashMap<String, DepthRegistry>>,
     channel_buffer: usize,
     topic_capacity: usize,
 }
@@ -180,17 +370,14 @@ pub struct MemoryMessaging {
 impl MemoryMessaging {
     /// Create a new messaging system with default settings.
     pub fn new() -> Self {
-        Self {
-            topics: RwLock::new(HashMap::new()),
-            channel_buffer: 32,
-            topic_capacity: 64,
-        }
+        Self::with_config(32, 64)
     }
 
     /// Create with custom buffer sizes.
     pub fn with_config(channel_buffer: usize, topic_capacity: usize) -> Self {
         Self {
             topics: RwLock::new(HashMap::new()),
+            channels: RwLock::new(HashMap::new()),
             channel_buffer,
             topic_capacity,
         }
@@ -201,6 +388,103 @@ impl MemoryMessaging {
         MpscChannel::with_buffer_size(self.channel_buffer)
     }
 
+    /// Create a channel whose pairs are reported under `name` in
+    /// [`stats`](Self::stats).
+    ///
+    /// Channels opened with the same name are reported together.
+    pub fn named_channel(&self, name: &str) -> Result<MpscChannel, Error> {
+        let mut channels = self.channels.write().map_err(|e| Error::Other(e.to_string()))?;
+        let registry = channels.entry(name.to_string()).or_default().clone();
+        Ok(MpscChannel {
+            buffer_size: self.channel_buffer,
+            registry: Some(registry),
+        })
+    }
+
+    /// Snapshot every topic and named channel.
+    pub fn stats(&self) -> Result<MessagingStats, Error> {
+        let topics = self.topics.read().map_err(|e| Error::Other(e.to_string()))?;
+        let channels = self.channels.read().map_err(|e| Error::Other(e.to_string()))?;
+        Ok(MessagingStats {
+            topics: topics
+                .iter()
+                .map(|(name, topic)| (name.clone(), topic.stats()))
+                .collect(),
+            channels: channels
+                .iter()
+                .map(|(name, registry)| {
+                    let depths: Vec<_> = registry
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .filter_map(Weak::upgrade)
+                        .collect();
+                    let stats = ChannelStats {
+                        pairs: depths.len(),
+                        queued: depths.iter().map(|d| d.load(Ordering::Relaxed)).sum(),
+                        capacity: self.channel_buffer,
+                    };
+                    (name.clone(), stats)
+                })
+                .collect(),
+        })
+    }
+
+    /// Publish a [`stats`](Self::stats) snapshot as gauges.
+    ///
+    /// Call periodically, e.g. from a scheduler job. [`Metrics`] has no
+    /// labels, so names include the topic or channel:
+    ///
+    /// | Metric | Value |
+    /// |--------|-------|
+    /// | `messaging.topic.{name}.subscribers` | live subscribers |
+    /// | `messaging.topic.{name}.queued` | retained messages |
+    /// | `messaging.topic.{name}.lagged` | messages skipped by lagging subscribers, in total |
+    /// | `messaging.channel.{name}.pairs` | live sender/receiver pairs |
+    /// | `messaging.channel.{name}.queued` | messages awaiting receipt |
+    /// | `messaging.channel.{name}.utilization` | `queued` over total buffer capacity |
+    pub fn record_metrics<M: Metrics>(&self, metrics: &M) -> Result<(), Error> {
+        let stats = self.stats()?;
+        let record = |prefix: String, values: [(&str, &str, f64); 3]| {
+            for (metric, description, value) in values {
+                metrics
+                    .gauge(&format!("{}.{}", prefix, metric), description)
+                    .set(value);
+            }
+        };
+        for (name, topic) in &stats.topics {
+            record(
+                format!("messaging.topic.{}", name),
+                [
+                    ("subscribers", "Live subscribers", topic.subscribers as f64),
+                    ("queued", "Retained messages", topic.queued as f64),
+                    (
+                        "lagged",
+                        "Messages skipped by lagging subscribers",
+                        topic.lagged as f64,
+                    ),
+                ],
+            );
+        }
+        for (name, channel) in &stats.channels {
+            let capacity = channel.pairs * channel.capacity;
+            let utilization = if capacity == 0 {
+                0.0
+            } else {
+                channel.queued as f64 / capacity as f64
+            };
+            record(
+                format!("messaging.channel.{}", name),
+                [
+                    ("pairs", "Live sender/receiver pairs", channel.pairs as f64),
+                    ("queued", "Messages awaiting receipt", channel.queued as f64),
+                    ("utilization", "Fraction of buffer in use", utilization),
+                ],
+            );
+        }
+        Ok(())
+    }
+
     /// Open or create a topic by name.
     pub fn open_topic(&self, name: &str) -> Result<SharedTopic, Error> {
         // Try read first
@@ -220,10 +504,322 @@ impl MemoryMessaging {
     }
 }
 
+/// Get the request ID attached to a message, if any.
+pub fn request_id(message: &Message) -> Option<&str> {
+    message.metadata.get(REQUEST_ID)
+}
+
+/// Sender wrapper that tags outgoing messages with a request ID.
+///
+/// Messages that already carry an ID keep it, so a consumer handling one
+/// message can forward its ID to the messages it produces; otherwise a fresh
+/// ID is generated.
+pub struct RequestIdSender<S, G> {
+    inner: S,
+    generate: G,
+}
+
+impl<S, G> RequestIdSender<S, G>
+where
+    S: Sender,
+    G: Fn() -> String,
+{
+    /// Wrap a sender with an ID generator.
+    pub fn new(inner: S, generate: G) -> Self {
+        Self { inner, generate }
+    }
+}
+
+impl<S, G> Sender for RequestIdSender<S, G>
+where
+    S: Sender,
+    G: Fn() -> String,
+{
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        let message = if request_id(&message).is_some() {
+            message
+        } else {
+            message.with_metadata(REQUEST_ID, (self.generate)())
+        };
+        self.inner.send(message).await
+    }
+}
+
+/// Receive a message unless shutdown is triggered first.
+///
+/// Returns `Ok(None)` once `shutdown` has been triggered, without taking a
+/// message off the receiver.
+pub async fn receive_until<R: Receiver>(
+    receiver: &R,
+    shutdown: &Shutdown,
+) -> Result<Option<Message>, Error> {
+    if shutdown.is_triggered() {
+        return Ok(None);
+    }
+    tokio::select! {
+        biased;
+        _ = shutdown.wait() => Ok(None),
+        message = receiver.receive() => message.map(Some),
+    }
+}
+
+/// Process messages from `receiver` until shutdown is triggered.
+///
+/// Each receive and the handling of the message it returns count as
+/// in-flight work on the token, so [`Shutdown::drain`]/[`Shutdown::drained`]
+/// wait for the current message, and a message taken just as shutdown
+/// begins is still handled rather than dropped. No further messages are
+/// taken once shutdown begins. Returns `Ok(())` on shutdown, or the receive
+/// error (e.g. `Closed`).
+pub async fn consume<R, F, Fut>(
+    receiver: &R,
+    shutdown: &Shutdown,
+    mut handler: F,
+) -> Result<(), Error>
+where
+    R: Receiver,
+    F: FnMut(Message) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    while let Some(_guard) = shutdown.track() {
+        let Some(message) = receive_until(receiver, shutdown).await? else {
+            break;
+        };
+        handler(message).await;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn consume_stops_on_shutdown() {
+        let channel = MpscChannel::new();
+        let (tx, rx) = channel.create();
+        let shutdown = Shutdown::new();
+
+        tx.send(Message::new(b"one".to_vec())).await.unwrap();
+        tx.send(Message::new(b"two".to_vec())).await.unwrap();
+
+        let token = shutdown.clone();
+        let consumer = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            consume(&rx, &token, |msg| {
+                seen.push(msg.data);
+                async {}
+            })
+            .await
+            .unwrap();
+            seen
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        shutdown.trigger();
+        let seen = tokio::time::timeout(Duration::from_secs(5), consumer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(seen, vec![b"one".to_vec(), b"two".to_vec()]);
+    }
+
+    /// Triggers shutdown as each message is received.
+    struct TriggeringReceiver<R> {
+        inner: R,
+        shutdown: Shutdown,
+    }
+
+    impl<R: Receiver> Receiver for TriggeringReceiver<R> {
+        async fn receive(&self) -> Result<Message, Error> {
+            let message = self.inner.receive().await;
+            self.shutdown.trigger();
+            message
+        }
+
+        async fn receive_timeout(&self, timeout: Duration) -> Result<Message, Error> {
+            let message = self.inner.receive_timeout(timeout).await;
+            self.shutdown.trigger();
+            message
+        }
+
+        async fn try_receive(&self) -> Result<Option<Message>, Error> {
+            let message = self.inner.try_receive().await;
+            self.shutdown.trigger();
+            message
+        }
+    }
+
+    #[tokio::test]
+    async fn consume_handles_message_in_flight_at_shutdown() {
+        let (tx, rx) = MpscChannel::new().create();
+        let shutdown = Shutdown::new();
+        let rx = TriggeringReceiver {
+            inner: rx,
+            shutdown: shutdown.clone(),
+        };
+        tx.send(Message::new(b"one".to_vec())).await.unwrap();
+        tx.send(Message::new(b"two".to_vec())).await.unwrap();
+
+        let mut seen = Vec::new();
+        consume(&rx, &shutdown, |msg| {
+            seen.push(msg.data);
+            async {}
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(seen, vec![b"one".to_vec()]);
+        assert_eq!(shutdown.in_flight(), 0);
+        let left = rx.inner.try_receive().await.unwrap().unwrap();
+        assert_eq!(left.data, b"two");
+    }
+
+    #[tokio::test]
+    async fn request_id_sender_tags_messages() {
+        let channel = MpscChannel::new();
+        let (tx, rx) = channel.create();
+        let tx = RequestIdSender::new(tx, || "generated".to_string());
+
+        tx.send(Message::new(b"a".to_vec())).await.unwrap();
+        tx.send(Message::new(b"b".to_vec()).with_metadata(REQUEST_ID, "upstream"))
+            .await
+            .unwrap();
+
+        let first = rx.receive().await.unwrap();
+        assert_eq!(request_id(&first), Some("generated"));
+        let second = rx.receive().await.unwrap();
+        assert_eq!(request_id(&second), Some("upstream"));
+        assert_eq!(second.metadata.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stats_and_metrics() {
+        use portals_observe_native::{NoopCounter, NoopHistogram};
+
+        #[derive(Default)]
+        struct Recorded(Arc<Mutex<HashMap<String, f64>>>);
+        struct RecordedGauge(Arc<Mutex<HashMap<String, f64>>>, String);
+        impl Gauge for RecordedGauge {
+            fn set(&self, value: f64) {
+                self.0.lock().unwrap().insert(self.1.clone(), value);
+            }
+            fn add(&self, delta: f64) {
+                *self.0.lock().unwrap().entry(self.1.clone()).or_default() += delta;
+            }
+        }
+        impl Metrics for Recorded {
+            type Counter = NoopCounter;
+            type Gauge = RecordedGauge;
+            type Histogram = NoopHistogram;
+            fn counter(&self, _: &str, _: &str) -> NoopCounter {
+                NoopCounter
+            }
+            fn gauge(&self, name: &str, _: &str) -> RecordedGauge {
+                RecordedGauge(self.0.clone(), name.to_string())
+            }
+            fn histogram(&self, _: &str, _: &str) -> NoopHistogram {
+                NoopHistogram
+            }
+        }
+
+        let messaging = MemoryMessaging::with_config(4, 2);
+        let (tx, rx) = messaging.named_channel("jobs").unwrap().create();
+        tx.send_batch(&[Message::new("a"), Message::new("b"), Message::new("c")])
+            .await
+            .unwrap();
+        rx.receive().await.unwrap();
+        assert_eq!(tx.queue_depth(), 2);
+        let jobs = messaging.stats().unwrap().channels["jobs"];
+        assert_eq!(
+            jobs,
+            ChannelStats {
+                pairs: 1,
+                queued: 2,
+                capacity: 4
+            }
+        );
+
+        let topic = messaging.open_topic("events").unwrap();
+        let slow = topic.subscribe().await.unwrap();
+        let _idle = topic.subscribe().await.unwrap();
+        for data in ["1", "2", "3"] {
+            topic.publish(Message::new(data)).await.unwrap();
+        }
+        assert_eq!(slow.receive().await.unwrap().data, b"2");
+        let events = topic.stats();
+        assert_eq!(events.subscribers, 2);
+        assert_eq!(events.queued, 2);
+        assert_eq!(events.lagged, 1);
+
+        let metrics = Recorded::default();
+        messaging.record_metrics(&metrics).unwrap();
+        let recorded = metrics.0.lock().unwrap();
+        assert_eq!(recorded["messaging.channel.jobs.queued"], 2.0);
+        assert_eq!(recorded["messaging.channel.jobs.utilization"], 0.5);
+        assert_eq!(recorded["messaging.topic.events.subscribers"], 2.0);
+        assert_eq!(recorded["messaging.topic.events.lagged"], 1.0);
+        drop(recorded);
+
+        drop((tx, rx));
+        assert_eq!(messaging.stats().unwrap().channels["jobs"].pairs, 0);
+    }
+
+    #[tokio::test]
+    async fn cancelled_send_is_not_counted() {
+        let channel = MpscChannel::with_buffer_size(1);
+        let (tx, rx) = channel.create();
+        tx.send(Message::new("a")).await.unwrap();
+
+        // The buffer is full, so this send waits until it times out.
+        let blocked = tokio::time::timeout(Duration::from_millis(10), tx.send(Message::new("b")));
+        assert!(blocked.await.is_err());
+        assert_eq!(tx.queue_depth(), 1);
+
+        assert_eq!(rx.receive().await.unwrap().data, b"a");
+        assert_eq!(rx.queue_depth(), 0);
+        assert!(rx.try_receive().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn batches() {
+        let channel = MpscChannel::with_buffer_size(4);
+        let (tx, rx) = channel.create();
+        let messages: Vec<_> = (0..6u8).map(|i| Message::new(vec![i])).collect();
+
+        let sending = tokio::spawn(async move { tx.send_batch(&messages).await });
+        let mut received = rx.receive_batch(10, Duration::from_secs(5)).await.unwrap();
+        assert!(!received.is_empty() && received.len() <= 4);
+        while received.len() < 6 {
+            received.extend(rx.receive_batch(10, Duration::from_secs(5)).await.unwrap());
+        }
+        sending.await.unwrap().unwrap();
+        let data: Vec<_> = received.into_iter().map(|m| m.data[0]).collect();
+        assert_eq!(data, [0, 1, 2, 3, 4, 5]);
+        assert!(matches!(
+            rx.receive_batch(10, Duration::from_millis(10)).await,
+            Err(Error::Closed)
+        ));
+
+        let messaging = MemoryMessaging::new();
+        let topic = messaging.open_topic("events").unwrap();
+        let sub = topic.subscribe().await.unwrap();
+        assert!(
+            sub.receive_batch(10, Duration::from_millis(10))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        topic
+            .publish_batch(&[Message::new("a"), Message::new("b"), Message::new("c")])
+            .await
+            .unwrap();
+        let batch = sub.receive_batch(2, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(batch.len(), 2);
+        let batch = sub.receive_batch(2, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(batch[0].data, b"c");
+    }
+
     #[tokio::test]
     async fn channel_send_receive() {
         let channel = MpscChannel::new();
@@ -271,7 +867,7 @@ mod tests {
             .with_metadata("trace-id", "abc123");
 
         assert_eq!(msg.metadata.len(), 2);
-        assert_eq!(msg.metadata[0], ("content-type".to_string(), "application/json".to_string()));
+        assert_eq!(msg.metadata.get("content-type"), Some("application/json"));
     }
 
     #[tokio::test]
@@ -282,4 +878,17 @@ mod tests {
         let result = rx.receive_timeout(Duration::from_millis(10)).await;
         assert!(matches!(result, Err(Error::Timeout)));
     }
+
+    #[tokio::test]
+    async fn conformance() {
+        portals_conformance::messaging::run_channel(&MpscChannel::new()).await;
+
+        let messaging = MemoryMessaging::new();
+        let next = std::cell::Cell::new(0);
+        portals_conformance::messaging::run_topic(|| {
+            let name = format!("conformance-{}", next.replace(next.get() + 1));
+            messaging.open_topic(&name).unwrap()
+        })
+        .await;
+    }
 }
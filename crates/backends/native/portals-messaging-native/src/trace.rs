@@ -0,0 +1,307 @@
+//! Trace context propagation through message metadata.
+//!
+//! Producers inject the context of a `send` span into each message's
+//! `traceparent` metadata; consumers extract it and open a `process` span
+//! as its remote child, so one trace follows a message across the queue.
+//! Spans for messages tagged with a request ID (see
+//! [`RequestIdSender`](crate::RequestIdSender)) record it as an attribute.
+//!
+//! ```ignore
+//! let tx = TracingSender::new(tx, tracer.clone(), "orders");
+//! tx.send(Message::new(order)).await?;
+//!
+//! let rx = TracingReceiver::new(rx, tracer, "orders");
+//! let (message, span) = rx.receive().await?;
+//! handle(message).await;
+//! span.end();
+//! ```
+
+use crate::request_id;
+use portals_messaging::{Error, Message, Receiver, Sender, Topic};
+use portals_observe::{REQUEST_ID_ATTRIBUTE, Span, SpanContext, SpanKind, TRACEPARENT, Tracer};
+use std::time::Duration;
+
+/// Get the trace context attached to a message, if any.
+///
+/// Malformed `traceparent` values are ignored.
+pub fn trace_context(message: &Message) -> Option<SpanContext> {
+    message
+        .metadata
+        .get(TRACEPARENT)
+        .and_then(SpanContext::from_traceparent)
+}
+
+/// Attach `context` to a message, replacing any context it carried.
+pub fn set_trace_context(message: &mut Message, context: &SpanContext) {
+    message
+        .metadata
+        .insert(TRACEPARENT, context.to_traceparent());
+}
+
+/// Start a span named `{operation} {destination}`, continuing the trace the
+/// message carries and recording its request ID.
+fn start_span<T: Tracer>(
+    tracer: &T,
+    kind: SpanKind,
+    operation: &str,
+    destination: &str,
+    message: &Message,
+) -> T::Span {
+    let mut builder = tracer
+        .span_builder(&format!("{} {}", operation, destination))
+        .with_kind(kind)
+        .with_attribute("messaging.operation", operation)
+        .with_attribute("messaging.destination", destination);
+    if let Some(parent) = trace_context(message) {
+        builder = builder.with_remote_parent(parent);
+    }
+    if let Some(id) = request_id(message) {
+        builder = builder.with_attribute(REQUEST_ID_ATTRIBUTE, id);
+    }
+    builder.start()
+}
+
+/// Wrap an outgoing message in a `send`/`publish` span and inject its
+/// context.
+async fn traced_send<T, F>(
+    tracer: &T,
+    operation: &str,
+    destination: &str,
+    mut message: Message,
+    send: impl FnOnce(Message) -> F,
+) -> Result<(), Error>
+where
+    T: Tracer,
+    F: Future<Output = Result<(), Error>>,
+{
+    let span = start_span(tracer, SpanKind::Producer, operation, destination, &message);
+    if let Some(context) = span.context() {
+        set_trace_context(&mut message, &context);
+    }
+    let result = send(message).await;
+    if let Err(e) = &result {
+        span.set_attribute("error", &e.to_string());
+    }
+    span.end();
+    result
+}
+
+/// Sender wrapper that traces each send and propagates the trace context.
+///
+/// A message that already carries a context, e.g. one forwarded by a
+/// consumer, continues that trace; otherwise the send span starts a new
+/// one. The message leaves with the send span's context, so consumers
+/// become its children.
+pub struct TracingSender<S, T> {
+    inner: S,
+    tracer: T,
+    destination: String,
+}
+
+impl<S: Sender, T: Tracer> TracingSender<S, T> {
+    /// Wrap a sender. `destination` names the channel in span names and
+    /// attributes.
+    pub fn new(inner: S, tracer: T, destination: impl Into<String>) -> Self {
+        Self {
+            inner,
+            tracer,
+            destination: destination.into(),
+        }
+    }
+}
+
+impl<S: Sender, T: Tracer> Sender for TracingSender<S, T> {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        traced_send(&self.tracer, "send", &self.destination, message, |m| {
+            self.inner.send(m)
+        })
+        .await
+    }
+}
+
+/// Topic wrapper that traces each publish and propagates the trace context.
+///
+/// Publishing works like [`TracingSender`]. Subscribers are returned
+/// unwrapped; wrap them in [`TracingReceiver`] to trace processing.
+pub struct TracingTopic<P, T> {
+    inner: P,
+    tracer: T,
+    destination: String,
+}
+
+impl<P: Topic, T: Tracer> TracingTopic<P, T> {
+    /// Wrap a topic. `destination` names the topic in span names and
+    /// attributes.
+    pub fn new(inner: P, tracer: T, destination: impl Into<String>) -> Self {
+        Self {
+            inner,
+            tracer,
+            destination: destination.into(),
+        }
+    }
+}
+
+impl<P: Topic, T: Tracer> Topic for TracingTopic<P, T> {
+    type Subscriber = P::Subscriber;
+
+    async fn publish(&self, message: Message) -> Result<(), Error> {
+        traced_send(&self.tracer, "publish", &self.destination, message, |m| {
+            self.inner.publish(m)
+        })
+        .await
+    }
+
+    async fn subscribe(&self) -> Result<Self::Subscriber, Error> {
+        self.inner.subscribe().await
+    }
+}
+
+/// Receiver wrapper that opens a `process` span for each message.
+///
+/// The span is a child of the context the message carries, or a new root
+/// if it carries none. It is returned with the message for the handler to
+/// annotate, and ends when the handler calls [`Span::end`].
+pub struct TracingReceiver<R, T> {
+    inner: R,
+    tracer: T,
+    destination: String,
+}
+
+impl<R: Receiver, T: Tracer> TracingReceiver<R, T> {
+    /// Wrap a receiver or subscriber. `destination` names the channel or
+    /// topic in span names and attributes.
+    pub fn new(inner: R, tracer: T, destination: impl Into<String>) -> Self {
+        Self {
+            inner,
+            tracer,
+            destination: destination.into(),
+        }
+    }
+
+    /// The wrapped receiver.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn process(&self, message: Message) -> (Message, T::Span) {
+        let span = start_span(
+            &self.tracer,
+            SpanKind::Consumer,
+            "process",
+            &self.destination,
+            &message,
+        );
+        (message, span)
+    }
+
+    /// Receive a message, waiting indefinitely.
+    pub async fn receive(&self) -> Result<(Message, T::Span), Error> {
+        let message = self.inner.receive().await?;
+        Ok(self.process(message))
+    }
+
+    /// Receive a message with timeout.
+    pub async fn receive_timeout(&self, timeout: Duration) -> Result<(Message, T::Span), Error> {
+        let message = self.inner.receive_timeout(timeout).await?;
+        Ok(self.process(message))
+    }
+
+    /// Try to receive a message without blocking.
+    pub async fn try_receive(&self) -> Result<Option<(Message, T::Span)>, Error> {
+        let message = self.inner.try_receive().await?;
+        Ok(message.map(|message| self.process(message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryMessaging, MpscChannel, REQUEST_ID, RequestIdSender};
+    use portals_messaging::Channel;
+    use portals_observe_native::MemoryTracer;
+
+    #[tokio::test]
+    async fn propagates_through_channel() {
+        let tracer = MemoryTracer::new();
+        let (tx, rx) = MpscChannel::new().create();
+        let tx = TracingSender::new(tx, tracer.clone(), "orders");
+        let rx = TracingReceiver::new(rx, tracer.clone(), "orders");
+
+        tx.send(Message::new("a")).await.unwrap();
+        let (message, span) = rx.receive().await.unwrap();
+        assert_eq!(message.data, b"a");
+        span.end();
+
+        let spans = tracer.spans();
+        assert_eq!(spans[0].name, "send orders");
+        assert_eq!(spans[0].kind, SpanKind::Producer);
+        assert_eq!(spans[0].parent, None);
+        assert!(spans[0].ended);
+        assert_eq!(trace_context(&message), Some(spans[0].context));
+        assert_eq!(spans[1].name, "process orders");
+        assert_eq!(spans[1].kind, SpanKind::Consumer);
+        assert_eq!(spans[1].parent, Some(spans[0].context));
+        assert_eq!(spans[1].context.trace_id, spans[0].context.trace_id);
+        assert!(spans[1].ended);
+
+        // Forwarding a consumed message continues its trace and replaces
+        // the context rather than adding a second one.
+        tx.send(message).await.unwrap();
+        let (forwarded, _) = rx.receive().await.unwrap();
+        let spans = tracer.spans();
+        assert_eq!(spans[2].parent, Some(spans[0].context));
+        assert_eq!(trace_context(&forwarded), Some(spans[2].context));
+        assert_eq!(forwarded.metadata.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn publish_and_untraced_messages() {
+        let tracer = MemoryTracer::new();
+        let messaging = MemoryMessaging::new();
+        let topic = TracingTopic::new(
+            messaging.open_topic("events").unwrap(),
+            tracer.clone(),
+            "events",
+        );
+        let subscriber =
+            TracingReceiver::new(topic.subscribe().await.unwrap(), tracer.clone(), "events");
+
+        topic.publish(Message::new("e")).await.unwrap();
+        let (_, span) = subscriber.try_receive().await.unwrap().unwrap();
+        span.end();
+        let spans = tracer.spans();
+        assert_eq!(spans[0].name, "publish events");
+        assert_eq!(spans[1].parent, Some(spans[0].context));
+
+        // A message from an untraced producer starts a new trace.
+        messaging
+            .open_topic("events")
+            .unwrap()
+            .publish(Message::new("plain").with_metadata(TRACEPARENT, "garbage"))
+            .await
+            .unwrap();
+        let (_, span) = subscriber.receive().await.unwrap();
+        assert_eq!(tracer.spans()[2].parent, None);
+        span.end();
+    }
+
+    #[tokio::test]
+    async fn records_request_id() {
+        let tracer = MemoryTracer::new();
+        let (tx, rx) = MpscChannel::new().create();
+        let tx = RequestIdSender::new(TracingSender::new(tx, tracer.clone(), "orders"), || {
+            "req-1".to_string()
+        });
+        let rx = TracingReceiver::new(rx, tracer.clone(), "orders");
+
+        tx.send(Message::new("a")).await.unwrap();
+        let (message, span) = rx.receive().await.unwrap();
+        span.end();
+        assert_eq!(message.metadata.get(REQUEST_ID), Some("req-1"));
+
+        let attribute = (REQUEST_ID_ATTRIBUTE.to_string(), "req-1".to_string());
+        for span in tracer.spans() {
+            assert!(span.attributes.contains(&attribute), "{}", span.name);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! JSON lines export to a directory capability.
+
+use crate::{MemoryMetrics, MemoryTracer, MetricValue, SpanData};
+use portals_filesystem::{Directory, Error, OutputStream};
+use portals_observe::SpanKind;
+use portals_signals::Shutdown;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Appends the spans of a [`MemoryTracer`] and snapshots of a
+/// [`MemoryMetrics`] to a file as JSON lines, for environments with no
+/// collector to send them to.
+///
+/// Each [`export`](Self::export) writes every span that has ended since
+/// the last export, then the current value of every metric. Spans still
+/// open are written once they end. Every record has a `type` (`span`,
+/// `counter`, `gauge`, or `histogram`) and a `time` in milliseconds since
+/// the Unix epoch:
+///
+/// ```text
+/// {"type":"span","time":1700000000000,"name":"GET /","trace_id":"…","span_id":"…","parent_span_id":null,"kind":"server","start":1699999999990,"end":1700000000000,"attributes":{"http.status":"200"},"events":[]}
+/// {"type":"counter","time":1700000000000,"name":"requests","description":"Total requests","value":12}
+/// {"type":"histogram","time":1700000000000,"name":"latency","description":"Latency","count":12,"sum":0.9,"min":0.01,"max":0.3}
+/// ```
+///
+/// Run [`run`](Self::run) on a thread of its own to export periodically:
+///
+/// ```ignore
+/// let exporter = FileExporter::new(dir, "telemetry.jsonl")
+///     .tracer(tracer.clone())
+///     .metrics(metrics.clone());
+/// std::thread::spawn(move || exporter.run(Duration::from_secs(10), &shutdown));
+/// ```
+pub struct FileExporter<D> {
+    dir: D,
+    path: PathBuf,
+    tracer: Option<MemoryTracer>,
+    metrics: Option<MemoryMetrics>,
+    exported: Mutex<Exported>,
+}
+
+/// Which spans have been written, by index in the tracer.
+#[derive(Default)]
+struct Exported {
+    /// Every span before this index has been written.
+    next: usize,
+    /// Spans at or after `next` that have been written, having ended
+    /// before some earlier span.
+    later: BTreeSet<usize>,
+}
+
+impl<D: Directory> FileExporter<D> {
+    /// Export to the file at `path` in `dir`, creating it if needed.
+    ///
+    /// Nothing is exported until a tracer or metrics provider is added.
+    pub fn new(dir: D, path: impl Into<PathBuf>) -> Self {
+        Self {
+            dir,
+            path: path.into(),
+            tracer: None,
+            metrics: None,
+            exported: Mutex::new(Exported::default()),
+        }
+    }
+
+    /// Export the spans `tracer` records.
+    pub fn tracer(mut self, tracer: MemoryTracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Export snapshots of the metrics `metrics` records.
+    pub fn metrics(mut self, metrics: MemoryMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Append a record for each span ended since the last export and for
+    /// each metric, returning how many were written.
+    ///
+    /// The records are appended in one write. If it fails, the spans are
+    /// written by the next export instead.
+    pub fn export(&self) -> Result<usize, Error> {
+        let time = millis(SystemTime::now());
+        let mut exported = self.exported.lock().unwrap();
+        let mut out = String::new();
+        let mut written = Vec::new();
+
+        let spans = self.tracer.as_ref().map(MemoryTracer::spans);
+        for (index, span) in spans.iter().flatten().enumerate().skip(exported.next) {
+            if span.ended && !exported.later.contains(&index) {
+                write_span(&mut out, time, span);
+                written.push(index);
+            }
+        }
+        let metrics = self.metrics.as_ref().map(MemoryMetrics::snapshot);
+        for metric in metrics.iter().flatten() {
+            out.push_str(r#"{"type":"#);
+            out.push_str(match metric.value {
+                MetricValue::Counter(_) => r#""counter""#,
+                MetricValue::Gauge(_) => r#""gauge""#,
+                MetricValue::Histogram(_) => r#""histogram""#,
+            });
+            let _ = write!(out, r#","time":{},"name":"#, time);
+            write_string(&mut out, &metric.name);
+            out.push_str(r#","description":"#);
+            write_string(&mut out, &metric.description);
+            match &metric.value {
+                MetricValue::Counter(value) => {
+                    let _ = write!(out, r#","value":{}"#, value);
+                }
+                MetricValue::Gauge(value) => {
+                    out.push_str(r#","value":"#);
+                    write_number(&mut out, *value);
+                }
+                MetricValue::Histogram(values) => {
+                    let _ = write!(out, r#","count":{},"sum":"#, values.len());
+                    write_number(&mut out, values.iter().sum());
+                    out.push_str(r#","min":"#);
+                    write_number(&mut out, values.iter().copied().fold(f64::NAN, f64::min));
+                    out.push_str(r#","max":"#);
+                    write_number(&mut out, values.iter().copied().fold(f64::NAN, f64::max));
+                }
+            }
+            out.push_str("}\n");
+        }
+
+        let records = written.len() + metrics.map_or(0, |m| m.len());
+        if records == 0 {
+            return Ok(0);
+        }
+        let mut stream = self.dir.open_append(&self.path)?;
+        stream
+            .blocking_write(out.as_bytes())
+            .map_err(stream_error)?;
+        stream.flush().map_err(stream_error)?;
+
+        let Exported { next, later } = &mut *exported;
+        later.extend(written);
+        while later.remove(next) {
+            *next += 1;
+        }
+        Ok(records)
+    }
+
+    /// Export every `interval` until `shutdown` is triggered, then export
+    /// once more so nothing recorded before shutdown is lost.
+    ///
+    /// Returns the first export error.
+    pub fn run(&self, interval: Duration, shutdown: &Shutdown) -> Result<(), Error> {
+        while !shutdown.wait_timeout(interval) {
+            self.export()?;
+        }
+        self.export().map(|_| ())
+    }
+}
+
+fn write_span(out: &mut String, time: u64, span: &SpanData) {
+    let _ = write!(out, r#"{{"type":"span","time":{},"name":"#, time);
+    write_string(out, &span.name);
+    out.push_str(r#","trace_id":""#);
+    write_hex(out, &span.context.trace_id);
+    out.push_str(r#"","span_id":""#);
+    write_hex(out, &span.context.span_id);
+    out.push_str(r#"","parent_span_id":"#);
+    match &span.parent {
+        Some(parent) => {
+            out.push('"');
+            write_hex(out, &parent.span_id);
+            out.push('"');
+        }
+        None => out.push_str("null"),
+    }
+    let kind = match span.kind {
+        SpanKind::Internal => "internal",
+        SpanKind::Server => "server",
+        SpanKind::Client => "client",
+        SpanKind::Producer => "producer",
+        SpanKind::Consumer => "consumer",
+    };
+    let _ = write!(
+        out,
+        r#","kind":"{}","start":{},"end":{}"#,
+        kind,
+        millis(span.start_time),
+        span.end_time.map_or(0, millis)
+    );
+    // A key set more than once keeps its last value.
+    out.push_str(r#","attributes":{"#);
+    let mut first = true;
+    for (i, (key, value)) in span.attributes.iter().enumerate() {
+        if span.attributes[i + 1..].iter().any(|(k, _)| k == key) {
+            continue;
+        }
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        write_string(out, key);
+        out.push(':');
+        write_string(out, value);
+    }
+    out.push_str(r#"},"events":["#);
+    for (i, event) in span.events.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, event);
+    }
+    out.push_str("]}\n");
+}
+
+/// Write `s` as a JSON string literal.
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Write `n` as a JSON number, or `null` if it has no JSON form.
+fn write_number(out: &mut String, n: f64) {
+    if n.is_finite() {
+        let _ = write!(out, "{}", n);
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn write_hex(out: &mut String, bytes: &[u8]) {
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn stream_error(e: portals_filesystem::StreamError) -> Error {
+    Error::Other(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_filesystem::InputStream;
+    use portals_filesystem_native::NativeDir;
+    use portals_observe::{Counter, Gauge, Histogram, Metrics, Span, Tracer};
+    use std::path::Path;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("portals-observe-export-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_lines(dir: &NativeDir) -> Vec<String> {
+        let mut stream = dir.open_read(Path::new("out.jsonl")).unwrap();
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        while let Ok(n) = stream.blocking_read_into(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(data)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn exports_spans_once_and_metric_snapshots() {
+        let path = temp_dir("spans");
+        let tracer = MemoryTracer::new();
+        let metrics = MemoryMetrics::new();
+        let exporter = FileExporter::new(NativeDir::new(&path), "out.jsonl")
+            .tracer(tracer.clone())
+            .metrics(metrics.clone());
+        assert_eq!(exporter.export().unwrap(), 0);
+
+        let outer = tracer
+            .span_builder("outer")
+            .with_kind(SpanKind::Server)
+            .start();
+        let inner = tracer.start_span_with_parent("in\"ner", &outer);
+        inner.set_attribute("key", "first");
+        inner.set_attribute("key", "line\nbreak");
+        inner.add_event("done");
+        inner.end();
+        metrics.counter("requests", "Total requests").add(3);
+        metrics.gauge("ratio", "Ratio").set(f64::NAN);
+        metrics.histogram("latency", "Latency").record(0.5);
+        metrics.histogram("latency", "Latency").record(1.5);
+        assert_eq!(exporter.export().unwrap(), 4);
+
+        // The inner span is not exported twice; the outer one is exported
+        // once it ends.
+        outer.end();
+        assert_eq!(exporter.export().unwrap(), 4);
+        assert_eq!(exporter.export().unwrap(), 3);
+
+        let lines = read_lines(&NativeDir::new(&path));
+        assert_eq!(lines.len(), 11);
+        assert!(lines[0].starts_with(r#"{"type":"span","time":"#));
+        assert!(lines[0].contains(r#""name":"in\"ner","trace_id":"00000000000000000000000000000001","span_id":"0000000000000002","parent_span_id":"0000000000000001","kind":"internal""#));
+        assert!(lines[0].ends_with(r#""attributes":{"key":"line\nbreak"},"events":["done"]}"#));
+        assert!(lines[1].starts_with(r#"{"type":"counter""#));
+        assert!(
+            lines[1].ends_with(r#""name":"requests","description":"Total requests","value":3}"#)
+        );
+        assert!(lines[2].ends_with(r#""value":null}"#));
+        assert!(lines[3].ends_with(r#""count":2,"sum":2,"min":0.5,"max":1.5}"#));
+        assert!(lines[4].contains(r#""name":"outer""#));
+        assert!(lines[4].contains(r#""parent_span_id":null,"kind":"server""#));
+        assert!(
+            lines[8..]
+                .iter()
+                .all(|line| !line.contains(r#""type":"span""#))
+        );
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn run_exports_until_shutdown() {
+        let path = temp_dir("run");
+        let tracer = MemoryTracer::new();
+        let exporter = FileExporter::new(NativeDir::new(&path), "out.jsonl").tracer(tracer.clone());
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+        tracer.start_span("last").end();
+        exporter.run(Duration::from_secs(60), &shutdown).unwrap();
+
+        let lines = read_lines(&NativeDir::new(&path));
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains(r#""name":"last""#));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
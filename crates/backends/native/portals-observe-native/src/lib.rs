@@ -1,11 +1,20 @@
 //! Native observability implementation.
 //!
 //! Provides no-op implementations for when telemetry is not needed,
-//! plus simple in-memory implementations for testing.
+//! plus simple in-memory implementations for testing, which
+//! [`FileExporter`] can write out as JSON lines.
 
-use portals_observe::{Counter, Gauge, Histogram, Metrics, Span, Tracer};
+mod export;
+
+pub use export::FileExporter;
+
+use portals_observe::{
+    Counter, Gauge, Histogram, Metrics, Parent, Span, SpanBuilder, SpanContext, SpanKind, Tracer,
+};
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
 
 /// A no-op span that does nothing.
 #[derive(Debug, Default)]
@@ -54,6 +63,8 @@ pub struct NoopGauge;
 
 impl Gauge for NoopGauge {
     fn set(&self, _value: f64) {}
+
+    fn add(&self, _delta: f64) {}
 }
 
 /// A no-op histogram.
@@ -93,6 +104,147 @@ impl Metrics for NoopMetrics {
     }
 }
 
+/// A span recorded by [`MemoryTracer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanData {
+    /// Span name.
+    pub name: String,
+    /// IDs of this span.
+    pub context: SpanContext,
+    /// Context of the parent span, local or remote.
+    pub parent: Option<SpanContext>,
+    /// Span kind; [`SpanKind::Internal`] unless started from a builder.
+    pub kind: SpanKind,
+    /// Attributes in the order they were set.
+    pub attributes: Vec<(String, String)>,
+    /// Event names in the order they were added.
+    pub events: Vec<String>,
+    /// Whether [`Span::end`] has been called.
+    pub ended: bool,
+    /// When the span started.
+    pub start_time: SystemTime,
+    /// When the span ended, if it has.
+    pub end_time: Option<SystemTime>,
+}
+
+#[derive(Debug, Default)]
+struct TraceStore {
+    spans: Mutex<Vec<SpanData>>,
+    next_id: AtomicU64,
+}
+
+/// An in-memory tracer for testing.
+///
+/// Records every span it starts. IDs are sequential rather than random, so
+/// they are predictable in tests. Clones share the recorded spans.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTracer {
+    store: Arc<TraceStore>,
+}
+
+impl MemoryTracer {
+    /// Create a new in-memory tracer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All spans started so far, in start order.
+    pub fn spans(&self) -> Vec<SpanData> {
+        self.store.spans.lock().unwrap().clone()
+    }
+
+    fn start(
+        &self,
+        name: &str,
+        parent: Option<SpanContext>,
+        kind: SpanKind,
+        attributes: Vec<(String, String)>,
+    ) -> MemorySpan {
+        let id = self.store.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let context = SpanContext {
+            trace_id: parent.map_or((id as u128).to_be_bytes(), |p| p.trace_id),
+            span_id: id.to_be_bytes(),
+            sampled: parent.is_none_or(|p| p.sampled),
+        };
+        let mut spans = self.store.spans.lock().unwrap();
+        spans.push(SpanData {
+            name: name.to_string(),
+            context,
+            parent,
+            kind,
+            attributes,
+            events: Vec::new(),
+            ended: false,
+            start_time: SystemTime::now(),
+            end_time: None,
+        });
+        MemorySpan {
+            store: self.store.clone(),
+            index: spans.len() - 1,
+            context,
+        }
+    }
+}
+
+impl Tracer for MemoryTracer {
+    type Span = MemorySpan;
+
+    fn start_span(&self, name: &str) -> Self::Span {
+        self.start(name, None, SpanKind::Internal, Vec::new())
+    }
+
+    fn start_span_with_parent(&self, name: &str, parent: &Self::Span) -> Self::Span {
+        self.start(name, Some(parent.context), SpanKind::Internal, Vec::new())
+    }
+
+    fn start_span_with_remote_parent(&self, name: &str, parent: &SpanContext) -> Self::Span {
+        self.start(name, Some(*parent), SpanKind::Internal, Vec::new())
+    }
+
+    fn start_span_from(&self, builder: SpanBuilder<'_, Self>) -> Self::Span {
+        let parent = builder.parent.map(|parent| match parent {
+            Parent::Local(span) => span.context,
+            Parent::Remote(context) => context,
+        });
+        self.start(&builder.name, parent, builder.kind, builder.attributes)
+    }
+}
+
+/// A span started by [`MemoryTracer`].
+#[derive(Debug)]
+pub struct MemorySpan {
+    store: Arc<TraceStore>,
+    index: usize,
+    context: SpanContext,
+}
+
+impl MemorySpan {
+    fn update(&self, f: impl FnOnce(&mut SpanData)) {
+        f(&mut self.store.spans.lock().unwrap()[self.index]);
+    }
+}
+
+impl Span for MemorySpan {
+    fn set_attribute(&self, key: &str, value: &str) {
+        self.update(|span| span.attributes.push((key.to_string(), value.to_string())));
+    }
+
+    fn add_event(&self, name: &str) {
+        self.update(|span| span.events.push(name.to_string()));
+    }
+
+    fn context(&self) -> Option<SpanContext> {
+        Some(self.context)
+    }
+
+    fn end(self) {
+        self.update(|span| {
+            span.ended = true;
+            span.end_time = Some(SystemTime::now());
+        });
+    }
+}
+
 /// An in-memory counter for testing.
 #[derive(Debug, Default)]
 pub struct MemoryCounter {
@@ -129,6 +281,10 @@ impl Gauge for MemoryGauge {
     fn set(&self, value: f64) {
         *self.value.write().unwrap() = value;
     }
+
+    fn add(&self, delta: f64) {
+        *self.value.write().unwrap() += delta;
+    }
 }
 
 /// An in-memory histogram for testing.
@@ -187,6 +343,10 @@ impl Gauge for SharedGauge {
     fn set(&self, value: f64) {
         self.0.set(value);
     }
+
+    fn add(&self, delta: f64) {
+        self.0.add(delta);
+    }
 }
 
 /// Shared histogram wrapper.
@@ -211,38 +371,113 @@ impl Histogram for SharedHistogram {
     }
 }
 
-/// In-memory metrics provider for testing.
+/// The current value of a metric recorded by [`MemoryMetrics`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    Counter(u64),
+    Gauge(f64),
+    /// Every value recorded, in order.
+    Histogram(Vec<f64>),
+}
+
+/// A snapshot of a metric recorded by [`MemoryMetrics`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricData {
+    /// Metric name.
+    pub name: String,
+    /// Description given when the metric was first created.
+    pub description: String,
+    /// Value when the snapshot was taken.
+    pub value: MetricValue,
+}
+
 #[derive(Debug, Default)]
-pub struct MemoryMetrics;
+struct MetricStore {
+    counters: BTreeMap<String, (String, SharedCounter)>,
+    gauges: BTreeMap<String, (String, SharedGauge)>,
+    histograms: BTreeMap<String, (String, SharedHistogram)>,
+}
+
+/// In-memory metrics provider for testing.
+///
+/// Metrics are registered by name: asking for a metric again returns the
+/// same one, keeping its first description. Clones share the registry.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryMetrics {
+    store: Arc<Mutex<MetricStore>>,
+}
 
 impl MemoryMetrics {
     /// Create a new in-memory metrics provider.
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Snapshot every metric created so far: counters, then gauges, then
+    /// histograms, each sorted by name.
+    pub fn snapshot(&self) -> Vec<MetricData> {
+        let store = self.store.lock().unwrap();
+        let data = |name: &String, description: &String, value| MetricData {
+            name: name.clone(),
+            description: description.clone(),
+            value,
+        };
+        let counters = store
+            .counters
+            .iter()
+            .map(|(name, (desc, m))| data(name, desc, MetricValue::Counter(m.value())));
+        let gauges = store
+            .gauges
+            .iter()
+            .map(|(name, (desc, m))| data(name, desc, MetricValue::Gauge(m.value())));
+        let histograms = store
+            .histograms
+            .iter()
+            .map(|(name, (desc, m))| data(name, desc, MetricValue::Histogram(m.values())));
+        counters.chain(gauges).chain(histograms).collect()
     }
 }
 
+/// Get the metric registered under `name`, registering a new one if there
+/// is none.
+fn register<M: Clone + Default>(
+    metrics: &mut BTreeMap<String, (String, M)>,
+    name: &str,
+    description: &str,
+) -> M {
+    metrics
+        .entry(name.to_string())
+        .or_insert_with(|| (description.to_string(), M::default()))
+        .1
+        .clone()
+}
+
 impl Metrics for MemoryMetrics {
     type Counter = SharedCounter;
     type Gauge = SharedGauge;
     type Histogram = SharedHistogram;
 
-    fn counter(&self, _name: &str, _description: &str) -> Self::Counter {
-        SharedCounter(Arc::new(MemoryCounter::default()))
+    fn counter(&self, name: &str, description: &str) -> Self::Counter {
+        register(&mut self.store.lock().unwrap().counters, name, description)
     }
 
-    fn gauge(&self, _name: &str, _description: &str) -> Self::Gauge {
-        SharedGauge(Arc::new(MemoryGauge::default()))
+    fn gauge(&self, name: &str, description: &str) -> Self::Gauge {
+        register(&mut self.store.lock().unwrap().gauges, name, description)
     }
 
-    fn histogram(&self, _name: &str, _description: &str) -> Self::Histogram {
-        SharedHistogram(Arc::new(MemoryHistogram::default()))
+    fn histogram(&self, name: &str, description: &str) -> Self::Histogram {
+        register(
+            &mut self.store.lock().unwrap().histograms,
+            name,
+            description,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use portals_observe::SpanGuard;
 
     #[test]
     fn noop_tracer() {
@@ -253,6 +488,129 @@ mod tests {
         span.end();
     }
 
+    #[test]
+    fn memory_tracer() {
+        let tracer = MemoryTracer::new();
+        let root = tracer.start_span("root");
+        let child = tracer.start_span_with_parent("child", &root);
+        child.set_attribute("key", "value");
+        child.add_event("event");
+        child.end();
+        let remote = SpanContext::from_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )
+        .unwrap();
+        let continued = tracer.start_span_with_remote_parent("continued", &remote);
+        assert_eq!(continued.context().unwrap().trace_id, remote.trace_id);
+        assert!(!continued.context().unwrap().sampled);
+
+        let spans = tracer.spans();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].parent, None);
+        assert!(!spans[0].ended);
+        assert_eq!(spans[1].parent, Some(spans[0].context));
+        assert_eq!(spans[1].context.trace_id, spans[0].context.trace_id);
+        assert_ne!(spans[1].context.span_id, spans[0].context.span_id);
+        assert_eq!(
+            spans[1].attributes,
+            [("key".to_string(), "value".to_string())]
+        );
+        assert_eq!(spans[1].events, ["event"]);
+        assert!(spans[1].ended);
+        assert_eq!(spans[2].parent, Some(remote));
+    }
+
+    #[test]
+    fn span_builder() {
+        let tracer = MemoryTracer::new();
+        let root = tracer
+            .span_builder("GET /users")
+            .with_kind(SpanKind::Server)
+            .with_attributes([("http.method", "GET"), ("http.route", "/users")])
+            .start();
+        let child = tracer
+            .span_builder("SELECT users")
+            .with_kind(SpanKind::Client)
+            .with_attribute("db.system", "postgresql")
+            .with_parent(&root)
+            .start();
+        child.set_attribute("db.rows", "3");
+
+        let spans = tracer.spans();
+        assert_eq!(spans[0].kind, SpanKind::Server);
+        assert_eq!(spans[0].parent, None);
+        assert_eq!(
+            spans[0].attributes,
+            [
+                ("http.method".to_string(), "GET".to_string()),
+                ("http.route".to_string(), "/users".to_string()),
+            ]
+        );
+        assert_eq!(spans[1].kind, SpanKind::Client);
+        assert_eq!(spans[1].parent, Some(spans[0].context));
+        assert_eq!(spans[1].attributes.len(), 2);
+
+        // The default implementation still applies parents and attributes.
+        let span = NoopTracer::new()
+            .span_builder("noop")
+            .with_kind(SpanKind::Producer)
+            .with_attribute("key", "value")
+            .start();
+        span.end();
+    }
+
+    #[test]
+    fn in_span_ends_and_records_errors() {
+        let tracer = MemoryTracer::new();
+        let ok: Result<u32, String> = tracer.in_span("ok", |span| {
+            span.add_event("working");
+            Ok(1)
+        });
+        assert_eq!(ok, Ok(1));
+        let failed: Result<(), String> = tracer.in_span("failed", |_| Err("boom".to_string()));
+        assert!(failed.is_err());
+        tracer.in_span("unit", |_| ());
+
+        let panicked = std::panic::catch_unwind(|| {
+            let span = SpanGuard::new(tracer.start_span("panicked"));
+            span.set_attribute("key", "value");
+            panic!("oops");
+        });
+        assert!(panicked.is_err());
+
+        let spans = tracer.spans();
+        assert!(spans.iter().all(|span| span.ended));
+        assert_eq!(spans[0].events, ["working"]);
+        assert!(spans[0].attributes.is_empty());
+        assert_eq!(
+            spans[1].attributes,
+            [("error".to_string(), "boom".to_string())]
+        );
+        assert!(spans[2].attributes.is_empty());
+        assert_eq!(
+            spans[3].attributes[1],
+            ("error".to_string(), "panicked".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn in_span_async_ends_and_records_errors() {
+        let tracer = MemoryTracer::new();
+        let result: Result<(), std::io::Error> = tracer
+            .in_span_async("read", async { Err(std::io::ErrorKind::NotFound.into()) })
+            .await;
+        assert!(result.is_err());
+
+        // A cancelled future still ends its span.
+        let pending = tracer.in_span_async("cancelled", std::future::pending::<()>());
+        drop(pending);
+
+        let spans = tracer.spans();
+        assert!(spans[0].ended);
+        assert_eq!(spans[0].attributes[0].0, "error");
+        assert!(spans[1].ended);
+    }
+
     #[test]
     fn noop_metrics() {
         let metrics = NoopMetrics::new();
@@ -260,10 +618,29 @@ mod tests {
         counter.add(1);
         let gauge = metrics.gauge("temp", "Temperature");
         gauge.set(42.0);
+        gauge.add(1.0);
+        gauge.sub(2.0);
         let histogram = metrics.histogram("latency", "Request latency");
         histogram.record(0.5);
     }
 
+    #[test]
+    fn memory_metrics_registry() {
+        let metrics = MemoryMetrics::new();
+        metrics.counter("requests", "Total requests").add(2);
+        metrics.counter("requests", "Ignored").add(3);
+        metrics.gauge("queue", "Queue depth").set(4.0);
+        metrics.histogram("latency", "Latency").record(0.5);
+
+        let snapshot = metrics.clone().snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot[0].name, "requests");
+        assert_eq!(snapshot[0].description, "Total requests");
+        assert_eq!(snapshot[0].value, MetricValue::Counter(5));
+        assert_eq!(snapshot[1].value, MetricValue::Gauge(4.0));
+        assert_eq!(snapshot[2].value, MetricValue::Histogram(vec![0.5]));
+    }
+
     #[test]
     fn memory_counter() {
         let counter = MemoryCounter::default();
@@ -280,6 +657,30 @@ mod tests {
         assert_eq!(gauge.value(), 10.0);
         gauge.set(20.0);
         assert_eq!(gauge.value(), 20.0);
+        gauge.add(2.5);
+        gauge.sub(5.0);
+        assert_eq!(gauge.value(), 17.5);
+    }
+
+    #[test]
+    fn concurrent_gauge_adjustments() {
+        let gauge = SharedGauge::default();
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let gauge = gauge.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        gauge.add(1.0);
+                        gauge.sub(1.0);
+                        gauge.add(1.0);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(gauge.value(), 8000.0);
     }
 
     #[test]
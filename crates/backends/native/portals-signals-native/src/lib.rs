@@ -0,0 +1,130 @@
+//! Native implementation of portals-signals using tokio.
+
+use portals_signals::{Error, Signal, Signals};
+
+/// Listens for process termination signals.
+///
+/// On Unix this covers SIGINT, SIGTERM, and SIGHUP; elsewhere only Ctrl-C.
+/// Must be created inside a tokio runtime.
+pub struct NativeSignals {
+    #[cfg(unix)]
+    streams: tokio::sync::Mutex<UnixStreams>,
+}
+
+#[cfg(unix)]
+struct UnixStreams {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl NativeSignals {
+    /// Register signal handlers.
+    #[cfg(unix)]
+    pub fn new() -> Result<Self, Error> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let register = |kind| signal(kind).map_err(|e| Error::Other(e.to_string()));
+        Ok(Self {
+            streams: tokio::sync::Mutex::new(UnixStreams {
+                interrupt: register(SignalKind::interrupt())?,
+                terminate: register(SignalKind::terminate())?,
+                hangup: register(SignalKind::hangup())?,
+            }),
+        })
+    }
+
+    /// Register signal handlers.
+    #[cfg(not(unix))]
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {})
+    }
+}
+
+impl Signals for NativeSignals {
+    #[cfg(unix)]
+    async fn recv(&self) -> Result<Signal, Error> {
+        let mut streams = self.streams.lock().await;
+        let streams = &mut *streams;
+        let received = tokio::select! {
+            s = streams.interrupt.recv() => s.map(|_| Signal::Interrupt),
+            s = streams.terminate.recv() => s.map(|_| Signal::Terminate),
+            s = streams.hangup.recv() => s.map(|_| Signal::Hangup),
+        };
+        received.ok_or_else(|| Error::Other("signal stream closed".to_string()))
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&self) -> Result<Signal, Error> {
+        tokio::signal::ctrl_c()
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?;
+        Ok(Signal::Interrupt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_signals::Shutdown;
+    use std::time::Duration;
+
+    #[test]
+    fn track_refuses_after_trigger() {
+        let shutdown = Shutdown::new();
+        let guard = shutdown.track().unwrap();
+        assert_eq!(shutdown.in_flight(), 1);
+
+        shutdown.trigger();
+        assert!(shutdown.is_triggered());
+        assert!(shutdown.track().is_none());
+
+        drop(guard);
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[test]
+    fn drain_waits_for_work() {
+        let shutdown = Shutdown::new();
+        let guard = shutdown.track().unwrap();
+        shutdown.trigger();
+
+        assert!(!shutdown.drain(Duration::from_millis(10)));
+
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(guard);
+        });
+        assert!(shutdown.drain(Duration::from_secs(5)));
+        handle.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn async_wait_and_drained() {
+        let shutdown = Shutdown::new();
+        let guard = shutdown.track().unwrap();
+
+        let token = shutdown.clone();
+        let waiter = tokio::spawn(async move {
+            token.wait().await;
+            token.drained().await;
+        });
+
+        tokio::task::yield_now().await;
+        shutdown.trigger();
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn native_signals_register() {
+        assert!(NativeSignals::new().is_ok());
+    }
+}
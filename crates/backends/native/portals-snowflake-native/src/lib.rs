@@ -153,7 +153,10 @@ mod tests {
     #[test]
     fn invalid_machine_id() {
         let result = SnowflakeGenerator::twitter(1024);
-        assert!(matches!(result, Err(SnowflakeError::InvalidMachineId(1024))));
+        assert!(matches!(
+            result,
+            Err(SnowflakeError::InvalidMachineId(1024))
+        ));
     }
 
     #[test]
@@ -189,6 +192,18 @@ mod tests {
         assert_eq!(format!("{}", id), "123456789");
     }
 
+    #[test]
+    fn uuid_v7_ids() {
+        let generator = SnowflakeGenerator::new(7, DISCORD_EPOCH).unwrap();
+        let first = generator.next_uuid_v7().unwrap();
+        let second = generator.next_uuid_v7().unwrap();
+        assert!(first < second);
+
+        let id = SnowflakeId::from_uuid_v7(second, DISCORD_EPOCH).unwrap();
+        assert_eq!(id.machine_id(), 7);
+        assert_eq!(generator.extract_timestamp(id), (second >> 80) as u64);
+    }
+
     #[test]
     fn conversions() {
         let id = SnowflakeId::from_u64(12345);
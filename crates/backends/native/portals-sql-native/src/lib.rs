@@ -1,13 +1,26 @@
 //! Native implementation of portals-sql using libsql.
 
 use portals_sql::{Connection, Error, Row, Value};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 /// A SQLite connection backed by libsql.
 ///
 /// Create connections using [`LibsqlConnection::open`].
+///
+/// With [`auto_reconnect`](Self::auto_reconnect), a statement that fails
+/// with [`Error::ConnectionFailed`] re-opens the connection. Queries and
+/// `BEGIN` outside a transaction are then retried once. Other statements
+/// are not, since the failure may have come after the database applied
+/// them; the error is returned and the caller decides whether running the
+/// statement again is safe. Inside a transaction, the transaction is lost
+/// with the old connection, so nothing is retried.
 pub struct LibsqlConnection {
-    _db: libsql::Database,
-    conn: libsql::Connection,
+    db: libsql::Database,
+    conn: Mutex<libsql::Connection>,
+    auto_reconnect: bool,
+    in_transaction: AtomicBool,
+    reconnects: AtomicU64,
 }
 
 impl LibsqlConnection {
@@ -18,67 +31,136 @@ impl LibsqlConnection {
         let db = libsql::Builder::new_local(path)
             .build()
             .await
-            .map_err(|e| Error::Other(e.to_string()))?;
-        let conn = db.connect().map_err(|e| Error::Other(e.to_string()))?;
-        Ok(LibsqlConnection { _db: db, conn })
+            .map_err(map_error)?;
+        let conn = db.connect().map_err(map_error)?;
+        Ok(LibsqlConnection {
+            db,
+            conn: Mutex::new(conn),
+            auto_reconnect: false,
+            in_transaction: AtomicBool::new(false),
+            reconnects: AtomicU64::new(0),
+        })
+    }
+
+    /// Re-open the connection transparently when it fails (default: false).
+    pub fn auto_reconnect(mut self, enabled: bool) -> Self {
+        self.auto_reconnect = enabled;
+        self
+    }
+
+    /// Check that the connection is alive by running a trivial query.
+    pub async fn ping(&self) -> Result<(), Error> {
+        self.query("SELECT 1", &[]).await.map(|_| ())
+    }
+
+    /// Replace the connection with a new one from the same database.
+    ///
+    /// Any open transaction is lost.
+    pub fn reconnect(&self) -> Result<(), Error> {
+        let conn = self.db.connect().map_err(map_error)?;
+        *self.conn.lock().unwrap() = conn;
+        self.in_transaction.store(false, Ordering::SeqCst);
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// How many times the connection has been re-opened.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    fn conn(&self) -> libsql::Connection {
+        self.conn.lock().unwrap().clone()
+    }
+
+    /// Run `op` on the current connection, re-opening it if it fails with
+    /// [`Error::ConnectionFailed`] and auto-reconnect is on. The op is then
+    /// retried once if it is `idempotent` and no transaction was open.
+    async fn with_retry<T, F, Fut>(&self, idempotent: bool, op: F) -> Result<T, Error>
+    where
+        F: Fn(libsql::Connection) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        match op(self.conn()).await {
+            Err(Error::ConnectionFailed(msg)) if self.auto_reconnect => {
+                let in_transaction = self.in_transaction.load(Ordering::SeqCst);
+                self.reconnect()?;
+                if in_transaction || !idempotent {
+                    return Err(Error::ConnectionFailed(msg));
+                }
+                op(self.conn()).await
+            }
+            result => result,
+        }
     }
 }
 
 impl Connection for LibsqlConnection {
     async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>, Error> {
         let params: Vec<libsql::Value> = params.iter().map(to_libsql_value).collect();
-        let mut rows = self
-            .conn
-            .query(sql, params)
-            .await
-            .map_err(map_error)?;
+        // Queries are retried on the assumption that they only read. A
+        // query that writes (`INSERT ... RETURNING`) should run inside a
+        // transaction, where nothing is retried.
+        self.with_retry(true, |conn| {
+            let params = params.clone();
+            async move {
+                let mut rows = conn.query(sql, params).await.map_err(map_error)?;
 
-        let mut result = Vec::new();
-        let columns: Vec<String> = (0..rows.column_count())
-            .map(|i| rows.column_name(i).unwrap_or("").to_string())
-            .collect();
+                let mut result = Vec::new();
+                let columns: Vec<String> = (0..rows.column_count())
+                    .map(|i| rows.column_name(i).unwrap_or("").to_string())
+                    .collect();
 
-        while let Some(row) = rows.next().await.map_err(map_error)? {
-            let values: Vec<Value> = (0..columns.len())
-                .map(|i| from_libsql_value(row.get_value(i as i32).unwrap_or(libsql::Value::Null)))
-                .collect();
-            result.push(Row::new(columns.clone(), values));
-        }
+                while let Some(row) = rows.next().await.map_err(map_error)? {
+                    let values: Vec<Value> = (0..columns.len())
+                        .map(|i| {
+                            from_libsql_value(row.get_value(i as i32).unwrap_or(libsql::Value::Null))
+                        })
+                        .collect();
+                    result.push(Row::new(columns.clone(), values));
+                }
 
-        Ok(result)
+                Ok(result)
+            }
+        })
+        .await
     }
 
     async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64, Error> {
         let params: Vec<libsql::Value> = params.iter().map(to_libsql_value).collect();
-        let rows_affected = self
-            .conn
-            .execute(sql, params)
-            .await
-            .map_err(map_error)?;
-        Ok(rows_affected)
+        self.with_retry(false, |conn| {
+            let params = params.clone();
+            async move { conn.execute(sql, params).await.map_err(map_error) }
+        })
+        .await
     }
 
     async fn begin(&self) -> Result<(), Error> {
-        self.conn
-            .execute("BEGIN", ())
-            .await
-            .map_err(map_error)?;
+        // A transaction begun on the old connection died with it, so
+        // beginning again on the new one is safe.
+        self.with_retry(true, |conn| async move {
+            conn.execute("BEGIN", ()).await.map_err(map_error)
+        })
+        .await?;
+        self.in_transaction.store(true, Ordering::SeqCst);
         Ok(())
     }
 
     async fn commit(&self) -> Result<(), Error> {
-        self.conn
+        self.conn()
             .execute("COMMIT", ())
             .await
             .map_err(map_error)?;
+        self.in_transaction.store(false, Ordering::SeqCst);
         Ok(())
     }
 
     async fn rollback(&self) -> Result<(), Error> {
-        self.conn
+        self.conn()
             .execute("ROLLBACK", ())
             .await
             .map_err(map_error)?;
+        self.in_transaction.store(false, Ordering::SeqCst);
         Ok(())
     }
 }
@@ -103,9 +185,24 @@ fn from_libsql_value(v: libsql::Value) -> Value {
     }
 }
 
+/// SQLite primary result codes meaning the database can no longer be
+/// reached through this connection.
+const SQLITE_IOERR: i32 = 10;
+const SQLITE_CANTOPEN: i32 = 14;
+const SQLITE_NOTADB: i32 = 26;
+
 fn map_error(e: libsql::Error) -> Error {
     let msg = e.to_string();
-    if msg.contains("UNIQUE") || msg.contains("constraint") {
+    let dead = match &e {
+        libsql::Error::ConnectionFailed(_) | libsql::Error::Hrana(_) => true,
+        libsql::Error::SqliteFailure(code, _) => {
+            matches!(code & 0xff, SQLITE_IOERR | SQLITE_CANTOPEN | SQLITE_NOTADB)
+        }
+        _ => false,
+    };
+    if dead {
+        Error::ConnectionFailed(msg)
+    } else if msg.contains("UNIQUE") || msg.contains("constraint") {
         Error::ConstraintViolation(msg)
     } else if msg.contains("syntax") || msg.contains("parse") {
         Error::SyntaxError(msg)
@@ -169,4 +266,38 @@ mod tests {
         let rows = conn.query("SELECT * FROM t", &[]).await.unwrap();
         assert_eq!(rows.len(), 0);
     }
+
+    #[tokio::test]
+    async fn ping_and_reconnect() {
+        let path = std::env::temp_dir().join(format!("portals-sql-native-{}.db", std::process::id()));
+        let conn = LibsqlConnection::open(path.to_str().unwrap())
+            .await
+            .unwrap()
+            .auto_reconnect(true);
+        conn.ping().await.unwrap();
+        conn.execute("CREATE TABLE t (x INTEGER)", &[]).await.unwrap();
+        conn.execute("INSERT INTO t VALUES (1)", &[]).await.unwrap();
+
+        conn.begin().await.unwrap();
+        conn.reconnect().unwrap();
+        assert_eq!(conn.reconnects(), 1);
+
+        // The open transaction went away with the old connection.
+        conn.begin().await.unwrap();
+        conn.rollback().await.unwrap();
+        let rows = conn.query("SELECT * FROM t", &[]).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        conn.ping().await.unwrap();
+
+        drop(conn);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn conformance() {
+        portals_conformance::sql::run(async || {
+            LibsqlConnection::open(":memory:").await.unwrap()
+        })
+        .await;
+    }
 }
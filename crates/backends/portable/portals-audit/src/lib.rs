@@ -0,0 +1,349 @@
+//! Portable stores for [`portals_audit`] logs.
+//!
+//! [`MemoryAuditStore`] keeps records in memory, for tests and short-lived
+//! processes; [`SqlAuditStore`] keeps them in a table over any
+//! [`portals_sql`] connection. There is no event log store, as there is no
+//! event log interface yet.
+
+use portals_audit::{AuditEvent, AuditRecord, AuditStore, Error};
+use portals_sql::{Connection, Dialect, Insert, Row, Select, Value, col};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Audit records held in memory.
+#[derive(Debug, Default)]
+pub struct MemoryAuditStore {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<AuditRecord>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl AuditStore for MemoryAuditStore {
+    async fn append(&self, record: &AuditRecord) -> Result<(), Error> {
+        let mut records = self.lock();
+        if records
+            .last()
+            .is_some_and(|last| last.sequence >= record.sequence)
+        {
+            return Err(Error::Store(format!(
+                "record {} is not after the last record",
+                record.sequence
+            )));
+        }
+        records.push(record.clone());
+        Ok(())
+    }
+
+    async fn last(&self) -> Result<Option<AuditRecord>, Error> {
+        Ok(self.lock().last().cloned())
+    }
+
+    async fn records(&self, from: u64) -> Result<Vec<AuditRecord>, Error> {
+        Ok(self
+            .lock()
+            .iter()
+            .filter(|record| record.sequence >= from)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Audit records kept in a SQL table.
+///
+/// The table (`audit_log` by default) has one row per record, keyed by
+/// sequence number, so a second record with the same number is refused.
+/// Create it with [`create_table`](Self::create_table), or to the same
+/// shape by migration.
+///
+/// ```ignore
+/// let store = SqlAuditStore::new(conn, Dialect::Sqlite);
+/// store.create_table().await?;
+/// let log: AuditLog<_, _, HmacSha256> = AuditLog::new(store, SystemClock, key);
+/// ```
+pub struct SqlAuditStore<C> {
+    conn: C,
+    dialect: Dialect,
+    table: String,
+}
+
+impl<C: Connection> SqlAuditStore<C> {
+    /// Store records over `conn`, writing SQL for `dialect`.
+    pub fn new(conn: C, dialect: Dialect) -> Self {
+        Self {
+            conn,
+            dialect,
+            table: "audit_log".to_string(),
+        }
+    }
+
+    /// Use the table `table` instead of `audit_log`.
+    pub fn with_table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Create the table if it doesn't exist.
+    pub async fn create_table(&self) -> Result<(), Error> {
+        let (integer, blob) = match self.dialect {
+            Dialect::Sqlite => ("INTEGER", "BLOB"),
+            Dialect::Postgres => ("BIGINT", "BYTEA"),
+            Dialect::MySql => ("BIGINT", "BLOB"),
+        };
+        let columns: Vec<String> = [
+            ("sequence", integer, "PRIMARY KEY"),
+            ("actor", "TEXT", "NOT NULL"),
+            ("action", "TEXT", "NOT NULL"),
+            ("resource", "TEXT", "NOT NULL"),
+            ("timestamp_secs", integer, "NOT NULL"),
+            ("timestamp_nanos", integer, "NOT NULL"),
+            ("mac", blob, "NOT NULL"),
+        ]
+        .iter()
+        .map(|(name, ty, constraint)| {
+            format!(
+                "{} {} {}",
+                self.dialect.quote_identifier(name),
+                ty,
+                constraint
+            )
+        })
+        .collect();
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            self.dialect.quote_identifier(&self.table),
+            columns.join(", ")
+        );
+        self.conn.execute(&sql, &[]).await.map_err(sql_error)?;
+        Ok(())
+    }
+
+    async fn select(&self, select: Select) -> Result<Vec<AuditRecord>, Error> {
+        let query = select.build(self.dialect);
+        let rows = self
+            .conn
+            .query(&query.sql, &query.params)
+            .await
+            .map_err(sql_error)?;
+        rows.iter().map(from_row).collect()
+    }
+}
+
+impl<C: Connection> AuditStore for SqlAuditStore<C> {
+    async fn append(&self, record: &AuditRecord) -> Result<(), Error> {
+        let event = &record.event;
+        let query = Insert::into(&self.table)
+            .value("sequence", to_integer(record.sequence)?)
+            .value("actor", event.actor.as_str())
+            .value("action", event.action.as_str())
+            .value("resource", event.resource.as_str())
+            .value("timestamp_secs", to_integer(event.timestamp.0)?)
+            .value("timestamp_nanos", event.timestamp.1 as i64)
+            .value("mac", record.mac.clone())
+            .build(self.dialect);
+        self.conn
+            .execute(&query.sql, &query.params)
+            .await
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    async fn last(&self) -> Result<Option<AuditRecord>, Error> {
+        let select = Select::from(&self.table).order_by_desc("sequence").limit(1);
+        Ok(self.select(select).await?.pop())
+    }
+
+    async fn records(&self, from: u64) -> Result<Vec<AuditRecord>, Error> {
+        let select = Select::from(&self.table)
+            .filter(col("sequence").ge(to_integer(from)?))
+            .order_by("sequence");
+        self.select(select).await
+    }
+}
+
+fn from_row(row: &Row) -> Result<AuditRecord, Error> {
+    let integer = |name: &str| match row.get_by_name(name) {
+        Some(Value::Integer(n)) if *n >= 0 => Ok(*n as u64),
+        _ => Err(malformed(name)),
+    };
+    let text = |name: &str| match row.get_by_name(name) {
+        Some(Value::Text(s)) => Ok(s.clone()),
+        _ => Err(malformed(name)),
+    };
+    let mac = match row.get_by_name("mac") {
+        Some(Value::Blob(mac)) => mac.clone(),
+        _ => return Err(malformed("mac")),
+    };
+    let nanos =
+        u32::try_from(integer("timestamp_nanos")?).map_err(|_| malformed("timestamp_nanos"))?;
+    Ok(AuditRecord {
+        sequence: integer("sequence")?,
+        event: AuditEvent {
+            actor: text("actor")?,
+            action: text("action")?,
+            resource: text("resource")?,
+            timestamp: (integer("timestamp_secs")?, nanos),
+        },
+        mac,
+    })
+}
+
+fn to_integer(n: u64) -> Result<i64, Error> {
+    i64::try_from(n).map_err(|_| Error::Store(format!("{} is too large to store", n)))
+}
+
+fn malformed(column: &str) -> Error {
+    Error::Store(format!("malformed audit record column: {}", column))
+}
+
+fn sql_error(e: portals_sql::Error) -> Error {
+    Error::Store(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_audit::AuditLog;
+    use portals_clocks_mock::MockWallClock;
+    use portals_crypto_native::HmacSha256;
+
+    const COLUMNS: [&str; 7] = [
+        "sequence",
+        "actor",
+        "action",
+        "resource",
+        "timestamp_secs",
+        "timestamp_nanos",
+        "mac",
+    ];
+
+    /// Holds inserted rows and answers the store's two kinds of query.
+    #[derive(Default)]
+    struct Table {
+        rows: Mutex<Vec<Vec<Value>>>,
+        statements: Mutex<Vec<String>>,
+    }
+
+    impl Connection for &Table {
+        async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>, portals_sql::Error> {
+            self.statements.lock().unwrap().push(sql.to_string());
+            let rows = self.rows.lock().unwrap();
+            let selected: Vec<_> = if sql.contains("DESC") {
+                rows.last().into_iter().collect()
+            } else {
+                let integer = |value: &Value| match value {
+                    Value::Integer(n) => *n,
+                    _ => panic!("sequence is not an integer"),
+                };
+                rows.iter()
+                    .filter(|row| integer(&row[0]) >= integer(&params[0]))
+                    .collect()
+            };
+            let columns: Vec<String> = COLUMNS.iter().map(|c| c.to_string()).collect();
+            Ok(selected
+                .into_iter()
+                .map(|values| Row::new(columns.clone(), values.clone()))
+                .collect())
+        }
+
+        async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64, portals_sql::Error> {
+            self.statements.lock().unwrap().push(sql.to_string());
+            if sql.starts_with("INSERT") {
+                let mut rows = self.rows.lock().unwrap();
+                if rows.iter().any(|row| row[0] == params[0]) {
+                    return Err(portals_sql::Error::ConstraintViolation(
+                        "sequence".to_string(),
+                    ));
+                }
+                rows.push(params.to_vec());
+            }
+            Ok(1)
+        }
+
+        async fn begin(&self) -> Result<(), portals_sql::Error> {
+            Ok(())
+        }
+
+        async fn commit(&self) -> Result<(), portals_sql::Error> {
+            Ok(())
+        }
+
+        async fn rollback(&self) -> Result<(), portals_sql::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_store_chain() {
+        let clock = MockWallClock::new(1_700_000_000, 5);
+        let log: AuditLog<_, _, HmacSha256> =
+            AuditLog::new(MemoryAuditStore::new(), clock, b"secret".to_vec());
+        log.record("alice", "user.create", "user/bob")
+            .await
+            .unwrap();
+        log.record("bob", "login", "session/1").await.unwrap();
+        assert_eq!(log.verify().await.unwrap(), 2);
+
+        let stale = log.store().records(1).await.unwrap().pop().unwrap();
+        assert!(log.store().append(&stale).await.is_err());
+        assert_eq!(log.store().records(0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sql_store_round_trips() {
+        let table = Table::default();
+        let store = SqlAuditStore::new(&table, Dialect::Sqlite).with_table("audit");
+        store.create_table().await.unwrap();
+        assert!(
+            table.statements.lock().unwrap()[0].starts_with(
+                "CREATE TABLE IF NOT EXISTS \"audit\" (\"sequence\" INTEGER PRIMARY KEY"
+            )
+        );
+        let evil = Table::default();
+        SqlAuditStore::new(&evil, Dialect::MySql)
+            .with_table("logs.audit`; DROP TABLE users; --")
+            .create_table()
+            .await
+            .unwrap();
+        assert!(
+            evil.statements.lock().unwrap()[0]
+                .starts_with("CREATE TABLE IF NOT EXISTS `logs`.`audit``; DROP TABLE users; --` (")
+        );
+
+        let clock = MockWallClock::new(1_700_000_000, 250);
+        let log: AuditLog<_, _, HmacSha256> = AuditLog::new(store, clock.clone(), b"k".to_vec());
+        let first = log
+            .record("alice", "invoice.approve", "invoice/42")
+            .await
+            .unwrap();
+        log.record("carol", "invoice.pay", "invoice/42")
+            .await
+            .unwrap();
+        assert_eq!(log.store().records(0).await.unwrap()[0], first);
+        assert_eq!(log.verify().await.unwrap(), 2);
+
+        // Rewriting a stored row is detected.
+        table.rows.lock().unwrap()[0][3] = Value::Text("invoice/43".to_string());
+        assert!(matches!(
+            log.verify().await,
+            Err(portals_audit::Error::Tampered(0))
+        ));
+
+        // A second writer resumes from the stored tail; replaying a number
+        // already stored is refused.
+        let other: AuditLog<_, _, HmacSha256> = AuditLog::new(
+            SqlAuditStore::new(&table, Dialect::Sqlite),
+            clock,
+            b"k".to_vec(),
+        );
+        assert_eq!(other.record("dave", "x", "y").await.unwrap().sequence, 2);
+        assert!(other.store().append(&first).await.is_err());
+    }
+}
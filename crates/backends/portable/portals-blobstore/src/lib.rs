@@ -0,0 +1,8 @@
+//! Portable utilities for [`portals_blobstore`] containers.
+//!
+//! These work with any [`Container`](portals_blobstore::Container), so they
+//! apply equally to memory, filesystem, and remote backends.
+
+mod sync;
+
+pub use sync::{SyncOptions, SyncSummary, sync};
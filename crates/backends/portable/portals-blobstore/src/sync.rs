@@ -0,0 +1,259 @@
+//! Container mirroring.
+
+use futures_util::{StreamExt, stream};
+use portals_blobstore::{Container, Error, ObjectMeta};
+use std::collections::{HashMap, HashSet};
+
+/// Options for [`sync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncOptions {
+    /// Whether to delete objects in the destination that are not in the
+    /// source.
+    pub delete: bool,
+    /// Maximum number of objects transferred at once.
+    pub concurrency: usize,
+    /// Whether to compare contents of objects whose sizes match, rather
+    /// than assuming they are unchanged.
+    pub compare_contents: bool,
+    /// Whether to only report what would change, without changing anything.
+    pub dry_run: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            delete: false,
+            concurrency: 4,
+            compare_contents: false,
+            dry_run: false,
+        }
+    }
+}
+
+impl SyncOptions {
+    /// Copy new and resized objects, four at a time, deleting nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether to delete objects missing from the source.
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Set the maximum number of concurrent transfers. Zero is treated as
+    /// one.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Set whether to compare the contents of same-sized objects.
+    pub fn compare_contents(mut self, compare_contents: bool) -> Self {
+        self.compare_contents = compare_contents;
+        self
+    }
+
+    /// Set whether to only report what would change.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// What [`sync`] did.
+///
+/// In a dry run the counts are what would have been done.
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    /// Objects copied because they were missing or differed.
+    pub copied: u64,
+    /// Bytes copied.
+    pub bytes: u64,
+    /// Objects already up to date.
+    pub unchanged: u64,
+    /// Objects deleted from the destination.
+    pub deleted: u64,
+    /// Objects that could not be copied or deleted, with the reason.
+    pub failed: Vec<(String, Error)>,
+}
+
+impl SyncSummary {
+    /// Whether every object was synced.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+enum Action<'a> {
+    Copy(&'a ObjectMeta, Option<&'a ObjectMeta>),
+    Delete(&'a str),
+}
+
+enum Outcome {
+    Copied(u64),
+    Unchanged,
+    Deleted,
+}
+
+/// Make `dst` mirror `src`.
+///
+/// Objects missing from `dst`, or present with a different size, are
+/// copied. Objects of the same size are taken to be unchanged unless
+/// [`compare_contents`](SyncOptions::compare_contents) is set, in which
+/// case both copies are fetched and compared. With
+/// [`delete`](SyncOptions::delete), objects only in `dst` are removed.
+///
+/// Failing to list either container is an error. A failure on an individual
+/// object does not stop the others; it is recorded in
+/// [`SyncSummary::failed`].
+///
+/// ```ignore
+/// let summary = sync(&local, &s3, SyncOptions::new().delete(true).concurrency(16)).await?;
+/// println!("copied {} objects ({} bytes)", summary.copied, summary.bytes);
+/// ```
+pub async fn sync(
+    src: &impl Container,
+    dst: &impl Container,
+    options: SyncOptions,
+) -> Result<SyncSummary, Error> {
+    let sources = src.list().await?;
+    let targets = dst.list().await?;
+    let existing: HashMap<&str, &ObjectMeta> = targets
+        .iter()
+        .map(|meta| (meta.name.as_str(), meta))
+        .collect();
+
+    let mut actions: Vec<Action> = sources
+        .iter()
+        .filter_map(|meta| {
+            let target = existing.get(meta.name.as_str()).copied();
+            match target {
+                Some(target) if target.size == meta.size && !options.compare_contents => None,
+                _ => Some(Action::Copy(meta, target)),
+            }
+        })
+        .collect();
+    let mut summary = SyncSummary {
+        unchanged: (sources.len() - actions.len()) as u64,
+        ..SyncSummary::default()
+    };
+    if options.delete {
+        let wanted: HashSet<&str> = sources.iter().map(|m| m.name.as_str()).collect();
+        actions.extend(
+            targets
+                .iter()
+                .filter(|meta| !wanted.contains(meta.name.as_str()))
+                .map(|meta| Action::Delete(&meta.name)),
+        );
+    }
+
+    let outcomes: Vec<_> = stream::iter(actions)
+        .map(|action| async move {
+            match action {
+                Action::Copy(meta, target) => (
+                    meta.name.as_str(),
+                    copy(src, dst, meta, target, options).await,
+                ),
+                Action::Delete(name) => {
+                    let result = match options.dry_run {
+                        true => Ok(()),
+                        false => dst.delete(name).await,
+                    };
+                    (name, result.map(|()| Outcome::Deleted))
+                }
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    for (name, outcome) in outcomes {
+        match outcome {
+            Ok(Outcome::Copied(bytes)) => {
+                summary.copied += 1;
+                summary.bytes += bytes;
+            }
+            Ok(Outcome::Unchanged) => summary.unchanged += 1,
+            Ok(Outcome::Deleted) => summary.deleted += 1,
+            Err(e) => summary.failed.push((name.to_string(), e)),
+        }
+    }
+    Ok(summary)
+}
+
+async fn copy(
+    src: &impl Container,
+    dst: &impl Container,
+    meta: &ObjectMeta,
+    target: Option<&ObjectMeta>,
+    options: SyncOptions,
+) -> Result<Outcome, Error> {
+    let same_size = target.is_some_and(|target| target.size == meta.size);
+    if options.dry_run && !same_size {
+        return Ok(Outcome::Copied(meta.size));
+    }
+    let data = src.get(&meta.name).await?;
+    if same_size && dst.get(&meta.name).await? == data {
+        return Ok(Outcome::Unchanged);
+    }
+    if !options.dry_run {
+        dst.put(&meta.name, &data).await?;
+    }
+    Ok(Outcome::Copied(data.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_blobstore_native::MemoryBlobStore;
+
+    #[tokio::test]
+    async fn mirrors_container() {
+        let store = MemoryBlobStore::new();
+        store.create_container("src").unwrap();
+        store.create_container("dst").unwrap();
+        let src = store.open_container("src").unwrap();
+        let dst = store.open_container("dst").unwrap();
+
+        src.put("new", b"new").await.unwrap();
+        src.put("resized", b"longer").await.unwrap();
+        src.put("edited", b"after").await.unwrap();
+        src.put("same", b"same").await.unwrap();
+        dst.put("resized", b"short").await.unwrap();
+        dst.put("edited", b"befor").await.unwrap();
+        dst.put("same", b"same").await.unwrap();
+        dst.put("extra", b"extra").await.unwrap();
+
+        // Sizes alone miss the same-sized edit; nothing is deleted.
+        let dry = sync(&src, &dst, SyncOptions::new().dry_run(true))
+            .await
+            .unwrap();
+        assert_eq!((dry.copied, dry.bytes, dry.unchanged), (2, 9, 2));
+        assert_eq!(dst.get("resized").await.unwrap(), b"short");
+
+        let options = SyncOptions::new()
+            .delete(true)
+            .compare_contents(true)
+            .concurrency(2);
+        let summary = sync(&src, &dst, options).await.unwrap();
+        assert!(summary.is_complete());
+        assert_eq!(summary.copied, 3);
+        assert_eq!(summary.bytes, 14);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.deleted, 1);
+        for name in ["new", "resized", "edited", "same"] {
+            assert_eq!(
+                dst.get(name).await.unwrap(),
+                src.get(name).await.unwrap(),
+                "{}",
+                name
+            );
+        }
+        assert!(!dst.exists("extra").await.unwrap());
+
+        let again = sync(&src, &dst, options).await.unwrap();
+        assert_eq!((again.copied, again.unchanged, again.deleted), (0, 4, 0));
+    }
+}
@@ -0,0 +1,41 @@
+//! Cron parse and next_after benchmarks.
+//!
+//! Run with `cargo bench -p portals-cron-portable`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use portals_cron::{CronParser, CronSchedule};
+use portals_cron_portable::CronParserImpl;
+use std::hint::black_box;
+
+fn parse(c: &mut Criterion) {
+    let parser = CronParserImpl::new();
+    c.bench_function("parse", |b| {
+        b.iter(|| parser.parse(black_box("*/15 9-17 * * 1-5")).unwrap())
+    });
+}
+
+fn next_after(c: &mut Criterion) {
+    let parser = CronParserImpl::new();
+
+    let every_minute = parser.parse("* * * * *").unwrap();
+    c.bench_function("next_after (every minute)", |b| {
+        b.iter(|| every_minute.next_after(2024, 6, 15, 12, 30, black_box(0)))
+    });
+
+    let business = parser.parse("*/15 9-17 * * 1-5").unwrap();
+    c.bench_function("next_after (friday evening)", |b| {
+        b.iter(|| business.next_after(2024, 6, 14, 18, 0, black_box(0)))
+    });
+
+    // Scans most of a year per call, so take fewer samples.
+    let yearly = parser.parse("0 0 1 1 *").unwrap();
+    let mut slow = c.benchmark_group("slow");
+    slow.sample_size(10);
+    slow.bench_function("next_after (yearly)", |b| {
+        b.iter(|| yearly.next_after(2024, 1, 2, 0, 0, black_box(0)))
+    });
+    slow.finish();
+}
+
+criterion_group!(benches, parse, next_after);
+criterion_main!(benches);
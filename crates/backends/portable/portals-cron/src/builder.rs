@@ -0,0 +1,302 @@
+//! Programmatic construction of cron schedules.
+
+use crate::{Cron, FieldMatcher};
+use portals_cron::{CronError, IntoWeekdays, Weekday};
+
+/// Builds a [`Cron`] from field values instead of an expression string.
+///
+/// Fields left unset match any value. Seconds default to `0`; setting them
+/// produces a 6-field schedule.
+///
+/// ```ignore
+/// let cron = CronBuilder::new()
+///     .minutes([0, 30])
+///     .hours(8..=17)
+///     .weekdays(Weekday::Mon..=Weekday::Fri)
+///     .build()?;
+/// assert_eq!(cron.as_str(), "0,30 8-17 * * 1-5");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CronBuilder {
+    seconds: Option<Vec<u8>>,
+    minutes: Option<Vec<u8>>,
+    hours: Option<Vec<u8>>,
+    days: Option<Vec<u8>>,
+    months: Option<Vec<u8>>,
+    weekdays: Option<Vec<u8>>,
+}
+
+impl CronBuilder {
+    /// Create a builder matching every minute.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seconds (0-59). Makes this a 6-field schedule.
+    pub fn seconds(mut self, values: impl IntoIterator<Item = u8>) -> Self {
+        self.seconds = Some(values.into_iter().collect());
+        self
+    }
+
+    /// Minutes (0-59).
+    pub fn minutes(mut self, values: impl IntoIterator<Item = u8>) -> Self {
+        self.minutes = Some(values.into_iter().collect());
+        self
+    }
+
+    /// Hours (0-23).
+    pub fn hours(mut self, values: impl IntoIterator<Item = u8>) -> Self {
+        self.hours = Some(values.into_iter().collect());
+        self
+    }
+
+    /// Days of the month (1-31).
+    pub fn days(mut self, values: impl IntoIterator<Item = u8>) -> Self {
+        self.days = Some(values.into_iter().collect());
+        self
+    }
+
+    /// Months (1-12).
+    pub fn months(mut self, values: impl IntoIterator<Item = u8>) -> Self {
+        self.months = Some(values.into_iter().collect());
+        self
+    }
+
+    /// Days of the week: a day, a collection, or a range like `Mon..=Fri`.
+    pub fn weekdays(mut self, values: impl IntoWeekdays) -> Self {
+        self.weekdays = Some(
+            values
+                .into_weekdays()
+                .into_iter()
+                .map(Weekday::number)
+                .collect(),
+        );
+        self
+    }
+
+    /// Validate the fields and build the schedule.
+    ///
+    /// The resulting expression string is canonical: full ranges become `*`,
+    /// runs of three or more values become `a-b`.
+    pub fn build(self) -> Result<Cron, CronError> {
+        let with_seconds = self.seconds.is_some();
+
+        let seconds = Field::new(self.seconds, "second", 0, 59)?;
+        let minutes = Field::new(self.minutes, "minute", 0, 59)?;
+        let hours = Field::new(self.hours, "hour", 0, 23)?;
+        let days = Field::new(self.days, "day", 1, 31)?;
+        let months = Field::new(self.months, "month", 1, 12)?;
+        let weekdays = Field::new(self.weekdays, "weekday", 0, 6)?;
+
+        let mut parts = Vec::with_capacity(6);
+        if with_seconds {
+            parts.push(seconds.text);
+        }
+        parts.extend([
+            minutes.text,
+            hours.text,
+            days.text,
+            months.text,
+            weekdays.text,
+        ]);
+
+        Ok(Cron {
+            expr: parts.join(" "),
+            seconds: if with_seconds {
+                seconds.matcher
+            } else {
+                FieldMatcher::Values(vec![0])
+            },
+            minutes: minutes.matcher,
+            hours: hours.matcher,
+            days: days.matcher,
+            months: months.matcher,
+            weekdays: weekdays.matcher,
+        })
+    }
+}
+
+/// A validated field and its canonical text.
+struct Field {
+    matcher: FieldMatcher,
+    text: String,
+}
+
+impl Field {
+    fn new(
+        values: Option<Vec<u8>>,
+        field: &'static str,
+        min: u8,
+        max: u8,
+    ) -> Result<Self, CronError> {
+        let Some(mut values) = values else {
+            return Ok(Self {
+                matcher: FieldMatcher::Any,
+                text: "*".to_string(),
+            });
+        };
+
+        if values.is_empty() {
+            return Err(CronError::InvalidField {
+                field,
+                value: String::new(),
+                reason: "no values".to_string(),
+            });
+        }
+        if let Some(&v) = values.iter().find(|&&v| v < min || v > max) {
+            return Err(CronError::OutOfRange {
+                field,
+                value: v as u32,
+                min: min as u32,
+                max: max as u32,
+            });
+        }
+
+        values.sort_unstable();
+        values.dedup();
+
+        if values.len() == (max - min + 1) as usize {
+            return Ok(Self {
+                matcher: FieldMatcher::Any,
+                text: "*".to_string(),
+            });
+        }
+
+        let text = format_values(&values);
+        Ok(Self {
+            matcher: FieldMatcher::Values(values),
+            text,
+        })
+    }
+}
+
+/// Format sorted, deduplicated values, collapsing runs of three or more.
+fn format_values(values: &[u8]) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let start = values[i];
+        let mut j = i;
+        while j + 1 < values.len() && values[j + 1] == values[j] + 1 {
+            j += 1;
+        }
+        match j - i {
+            0 => parts.push(start.to_string()),
+            1 => {
+                parts.push(start.to_string());
+                parts.push(values[j].to_string());
+            }
+            _ => parts.push(format!("{}-{}", start, values[j])),
+        }
+        i = j + 1;
+    }
+    parts.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CronParserImpl;
+    use portals_cron::{CronExpr, CronParser, CronSchedule};
+
+    #[test]
+    fn default_matches_every_minute() {
+        let cron = CronBuilder::new().build().unwrap();
+        assert_eq!(cron.as_str(), "* * * * *");
+        assert!(cron.matches(0, 17, 3, 9, 4, 2));
+        assert!(!cron.matches(1, 17, 3, 9, 4, 2));
+    }
+
+    #[test]
+    fn workday_office_hours() {
+        let cron = CronBuilder::new()
+            .minutes([0, 30])
+            .hours(8..=17)
+            .weekdays(Weekday::WORKDAYS)
+            .build()
+            .unwrap();
+        assert_eq!(cron.as_str(), "0,30 8-17 * * 1-5");
+        assert!(cron.matches(0, 30, 8, 1, 1, 1));
+        assert!(!cron.matches(0, 30, 8, 1, 1, 0));
+        assert!(!cron.matches(0, 15, 8, 1, 1, 1));
+        assert!(!cron.matches(0, 0, 18, 1, 1, 1));
+    }
+
+    #[test]
+    fn weekday_ranges() {
+        use Weekday::*;
+
+        let cron = CronBuilder::new().weekdays(Mon..=Fri).build().unwrap();
+        assert_eq!(cron.as_str(), "* * * * 1-5");
+
+        // Ranges wrap past Saturday.
+        let cron = CronBuilder::new().weekdays(Fri..=Mon).build().unwrap();
+        assert_eq!(cron.as_str(), "* * * * 0,1,5,6");
+        assert!(cron.matches(0, 0, 0, 1, 1, 0));
+        assert!(!cron.matches(0, 0, 0, 1, 1, 3));
+
+        let cron = CronBuilder::new().weekdays(Mon..Wed).build().unwrap();
+        assert_eq!(cron.as_str(), "* * * * 1,2");
+
+        let cron = CronBuilder::new().weekdays(Sun..=Sat).build().unwrap();
+        assert_eq!(cron.as_str(), "* * * * *");
+
+        let cron = CronBuilder::new().weekdays(Wed).build().unwrap();
+        assert_eq!(cron.as_str(), "* * * * 3");
+
+        // An empty half-open range is rejected like any empty field.
+        let result = CronBuilder::new().weekdays(Mon..Mon).build();
+        assert!(matches!(result, Err(CronError::InvalidField { .. })));
+    }
+
+    #[test]
+    fn seconds_make_six_fields() {
+        let cron = CronBuilder::new().seconds([15, 45]).build().unwrap();
+        assert_eq!(cron.as_str(), "15,45 * * * * *");
+        assert!(cron.matches(45, 0, 0, 1, 1, 0));
+        assert!(!cron.matches(0, 0, 0, 1, 1, 0));
+    }
+
+    #[test]
+    fn canonicalizes_values() {
+        let cron = CronBuilder::new()
+            .minutes([5, 1, 3, 2, 1])
+            .hours(0..=23)
+            .weekdays(Weekday::WEEKEND)
+            .build()
+            .unwrap();
+        assert_eq!(cron.as_str(), "1-3,5 * * * 0,6");
+    }
+
+    #[test]
+    fn matches_parsed_equivalent() {
+        let built = CronBuilder::new().minutes([0]).hours([12]).build().unwrap();
+        let parsed = CronParserImpl::new().parse("0 12 * * *").unwrap();
+        assert_eq!(built.as_str(), parsed.as_str());
+        assert_eq!(
+            built.next_after(2024, 1, 1, 8, 0, 0),
+            parsed.next_after(2024, 1, 1, 8, 0, 0)
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        let result = CronBuilder::new().hours([24]).build();
+        assert!(matches!(
+            result,
+            Err(CronError::OutOfRange {
+                field: "hour",
+                value: 24,
+                ..
+            })
+        ));
+        let result = CronBuilder::new().days([0]).build();
+        assert!(matches!(result, Err(CronError::OutOfRange { .. })));
+    }
+
+    #[test]
+    fn rejects_empty_field() {
+        let result = CronBuilder::new().months([]).build();
+        assert!(matches!(result, Err(CronError::InvalidField { .. })));
+    }
+}
@@ -0,0 +1,390 @@
+//! English descriptions of cron expressions.
+
+use crate::{Cron, FieldMatcher, Special};
+use portals_cron::CronDescribe;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const ORDINALS: [&str; 5] = ["first", "second", "third", "fourth", "fifth"];
+
+/// Most explicit times listed as `at HH:MM` before falling back to
+/// describing minutes and hours separately.
+const MAX_LISTED_TIMES: usize = 4;
+
+/// The values of a field, classified by how they read.
+enum Shape {
+    Any,
+    /// Every `n`th value starting from the minimum.
+    Step(u8),
+    Single(u8),
+    /// A contiguous run.
+    Range(u8, u8),
+    List(Vec<u8>),
+}
+
+impl Shape {
+    fn of(values: &[u8], min: u8, max: u8) -> Self {
+        let span = (max - min) as usize + 1;
+        match values {
+            [] => Self::List(Vec::new()),
+            [v] => Self::Single(*v),
+            _ if values.len() == span => Self::Any,
+            _ if values.windows(2).all(|w| w[1] == w[0] + 1) => {
+                Self::Range(values[0], values[values.len() - 1])
+            }
+            _ => {
+                let step = values[1] - values[0];
+                let stepped: Vec<u8> = (min..=max).step_by(step as usize).collect();
+                if values[0] == min && values == stepped.as_slice() {
+                    Self::Step(step)
+                } else {
+                    Self::List(values.to_vec())
+                }
+            }
+        }
+    }
+
+    fn of_field(matcher: &FieldMatcher, min: u8, max: u8) -> Self {
+        match matcher {
+            FieldMatcher::Any => Self::Any,
+            FieldMatcher::Values(values) | FieldMatcher::Calendar { values, .. } => {
+                Self::of(values, min, max)
+            }
+        }
+    }
+
+    /// Number of values, if explicitly listed.
+    fn count(&self) -> Option<usize> {
+        match self {
+            Self::Single(_) => Some(1),
+            Self::List(values) => Some(values.len()),
+            _ => None,
+        }
+    }
+
+    fn values(&self) -> Vec<u8> {
+        match self {
+            Self::Single(v) => vec![*v],
+            Self::List(values) => values.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl CronDescribe for Cron {
+    fn describe(&self) -> String {
+        let mut parts = self.describe_time();
+        parts.extend(self.describe_date());
+        parts.join(", ")
+    }
+}
+
+impl Cron {
+    fn describe_time(&self) -> Vec<String> {
+        let seconds = Shape::of_field(&self.seconds, 0, 59);
+        let minutes = Shape::of_field(&self.minutes, 0, 59);
+        let hours = Shape::of_field(&self.hours, 0, 23);
+
+        // A handful of exact times reads best as a list.
+        if let (Shape::Single(second), Some(m), Some(h)) =
+            (&seconds, minutes.count(), hours.count())
+            && m * h <= MAX_LISTED_TIMES
+        {
+            let mut times = Vec::new();
+            for hour in hours.values() {
+                for minute in minutes.values() {
+                    times.push(clock_time(hour, minute, *second));
+                }
+            }
+            return vec![format!("at {}", join(&times))];
+        }
+
+        let mut parts = Vec::new();
+        let sub_minute = matches!(seconds, Shape::Any | Shape::Step(_));
+        match &seconds {
+            Shape::Any => parts.push("every second".to_string()),
+            Shape::Step(n) => parts.push(format!("every {} seconds", n)),
+            Shape::Single(0) => {}
+            Shape::Single(s) => parts.push(format!("at second {}", s)),
+            Shape::Range(a, b) => parts.push(format!("seconds {} through {}", a, b)),
+            Shape::List(values) => parts.push(format!("at seconds {}", join_numbers(values))),
+        }
+
+        let mut hours_done = false;
+        match &minutes {
+            Shape::Any if sub_minute => {}
+            Shape::Any => parts.push("every minute".to_string()),
+            Shape::Step(n) => parts.push(format!("every {} minutes", n)),
+            Shape::Single(m) => {
+                let every = match hours {
+                    Shape::Step(n) => {
+                        hours_done = true;
+                        Some(format!("every {} hours", n))
+                    }
+                    _ => None,
+                };
+                parts.push(match (every, *m) {
+                    (Some(every), 0) => every,
+                    (Some(every), m) => format!("{} at {} minutes past the hour", every, m),
+                    (None, 0) => "every hour".to_string(),
+                    (None, m) => format!("at {} minutes past the hour", m),
+                });
+            }
+            Shape::Range(a, b) => parts.push(format!("minutes {} through {} past the hour", a, b)),
+            Shape::List(values) => {
+                parts.push(format!("at minutes {} past the hour", join_numbers(values)))
+            }
+        }
+
+        if !hours_done {
+            match &hours {
+                Shape::Any => {}
+                Shape::Step(n) => parts.push(format!("every {} hours", n)),
+                Shape::Single(h) => parts.push(hour_span(*h, *h)),
+                Shape::Range(a, b) => parts.push(hour_span(*a, *b)),
+                Shape::List(values) => {
+                    let hours: Vec<String> = values.iter().map(|&h| clock_time(h, 0, 0)).collect();
+                    parts.push(format!("during the {} hours", join(&hours)));
+                }
+            }
+        }
+        parts
+    }
+
+    fn describe_date(&self) -> Vec<String> {
+        let mut parts = Vec::new();
+
+        let days = describe_days(&self.days);
+        let restricted_days = days.is_some();
+        parts.extend(days);
+
+        if let Some(weekdays) = describe_weekdays(&self.weekdays) {
+            // Both fields must match, so the weekday qualifies the day.
+            if restricted_days {
+                parts.push(format!("if it is {}", weekdays.trim_start_matches("on ")));
+            } else {
+                parts.push(weekdays);
+            }
+        }
+
+        match Shape::of_field(&self.months, 1, 12) {
+            Shape::Any => {}
+            Shape::Step(n) => parts.push(format!("every {} months", n)),
+            Shape::Single(m) => parts.push(format!("in {}", month_name(m))),
+            Shape::Range(a, b) => {
+                parts.push(format!("{} through {}", month_name(a), month_name(b)))
+            }
+            Shape::List(values) => {
+                let names: Vec<String> =
+                    values.iter().map(|&m| month_name(m).to_string()).collect();
+                parts.push(format!("in {}", join(&names)));
+            }
+        }
+        parts
+    }
+}
+
+fn describe_days(matcher: &FieldMatcher) -> Option<String> {
+    let mut phrases = match Shape::of_field(matcher, 1, 31) {
+        Shape::Any => return None,
+        Shape::Step(n) => vec![format!("every {} days", n)],
+        Shape::Single(d) => vec![format!("on day {} of the month", d)],
+        Shape::Range(a, b) => vec![format!("on days {} through {} of the month", a, b)],
+        Shape::List(values) if values.is_empty() => Vec::new(),
+        Shape::List(values) => vec![format!("on days {} of the month", join_numbers(&values))],
+    };
+    if let FieldMatcher::Calendar { specs, .. } = matcher {
+        phrases.extend(specs.iter().map(|&spec| describe_special(spec)));
+    }
+    Some(join(&phrases))
+}
+
+fn describe_weekdays(matcher: &FieldMatcher) -> Option<String> {
+    let mut phrases = match Shape::of_field(matcher, 0, 6) {
+        Shape::Any => return None,
+        Shape::Step(_) | Shape::List(_) | Shape::Single(_) => {
+            let days = match matcher {
+                FieldMatcher::Values(values) | FieldMatcher::Calendar { values, .. } => {
+                    values.clone()
+                }
+                FieldMatcher::Any => Vec::new(),
+            };
+            if days.is_empty() {
+                Vec::new()
+            } else {
+                let names: Vec<String> =
+                    days.iter().map(|&d| weekday_name(d).to_string()).collect();
+                vec![format!("on {}", join(&names))]
+            }
+        }
+        Shape::Range(a, b) => vec![format!("{} through {}", weekday_name(a), weekday_name(b))],
+    };
+    if let FieldMatcher::Calendar { specs, .. } = matcher {
+        phrases.extend(specs.iter().map(|&spec| describe_special(spec)));
+    }
+    Some(join(&phrases))
+}
+
+fn describe_special(spec: Special) -> String {
+    match spec {
+        Special::LastDay(0) => "on the last day of the month".to_string(),
+        Special::LastDay(1) => "on the day before the last day of the month".to_string(),
+        Special::LastDay(n) => format!("{} days before the last day of the month", n),
+        Special::LastWeekday => "on the last weekday of the month".to_string(),
+        Special::NearestWeekday(d) => format!("on the weekday nearest day {} of the month", d),
+        Special::LastOf(d) => format!("on the last {} of the month", weekday_name(d)),
+        Special::Nth { weekday, n } => format!(
+            "on the {} {} of the month",
+            ORDINALS[n as usize - 1],
+            weekday_name(weekday)
+        ),
+    }
+}
+
+fn clock_time(hour: u8, minute: u8, second: u8) -> String {
+    if second == 0 {
+        format!("{:02}:{:02}", hour, minute)
+    } else {
+        format!("{:02}:{:02}:{:02}", hour, minute, second)
+    }
+}
+
+fn hour_span(first: u8, last: u8) -> String {
+    format!("between {:02}:00 and {:02}:59", first, last)
+}
+
+fn month_name(month: u8) -> &'static str {
+    MONTHS[month as usize - 1]
+}
+
+fn weekday_name(weekday: u8) -> &'static str {
+    WEEKDAYS[weekday as usize]
+}
+
+fn join_numbers(values: &[u8]) -> String {
+    let values: Vec<String> = values.iter().map(u8::to_string).collect();
+    join(&values)
+}
+
+/// Join as "a", "a and b", or "a, b and c".
+fn join(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CronParserImpl;
+    use portals_cron::CronParser;
+
+    fn describe(expr: &str) -> String {
+        CronParserImpl::new().parse(expr).unwrap().describe()
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(describe("* * * * *"), "every minute");
+        assert_eq!(describe("0 9 * * *"), "at 09:00");
+        assert_eq!(describe("30 9,17 * * *"), "at 09:30 and 17:30");
+        assert_eq!(describe("@daily"), "at 00:00");
+        assert_eq!(describe("0 * * * *"), "every hour");
+        assert_eq!(describe("5 * * * *"), "at 5 minutes past the hour");
+        assert_eq!(
+            describe("5 */6 * * *"),
+            "every 6 hours at 5 minutes past the hour"
+        );
+        assert_eq!(describe("0 */2 * * *"), "every 2 hours");
+        assert_eq!(
+            describe("0-10 * * * *"),
+            "minutes 0 through 10 past the hour"
+        );
+        assert_eq!(
+            describe("*/15 9-17 * * 1-5"),
+            "every 15 minutes, between 09:00 and 17:59, Monday through Friday"
+        );
+        assert_eq!(
+            describe("0,20,45 8,12,18 * * *"),
+            "at minutes 0, 20 and 45 past the hour, during the 08:00, 12:00 and 18:00 hours"
+        );
+
+        let cron = CronParserImpl::new()
+            .parse_with_seconds("*/10 * * * * *")
+            .unwrap();
+        assert_eq!(cron.describe(), "every 10 seconds");
+        let cron = CronParserImpl::new()
+            .parse_with_seconds("30 0 12 * * *")
+            .unwrap();
+        assert_eq!(cron.describe(), "at 12:00:30");
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(describe("0 0 1 * *"), "at 00:00, on day 1 of the month");
+        assert_eq!(
+            describe("0 0 1,15 * *"),
+            "at 00:00, on days 1 and 15 of the month"
+        );
+        assert_eq!(
+            describe("0 9 * * MON,WED,FRI"),
+            "at 09:00, on Monday, Wednesday and Friday"
+        );
+        assert_eq!(
+            describe("0 0 1 JAN *"),
+            "at 00:00, on day 1 of the month, in January"
+        );
+        assert_eq!(describe("0 0 * 6-8 *"), "at 00:00, June through August");
+        assert_eq!(
+            describe("0 0 13 * 5"),
+            "at 00:00, on day 13 of the month, if it is Friday"
+        );
+    }
+
+    #[test]
+    fn calendar_specifiers() {
+        assert_eq!(
+            describe("0 0 L * *"),
+            "at 00:00, on the last day of the month"
+        );
+        assert_eq!(
+            describe("0 0 LW * *"),
+            "at 00:00, on the last weekday of the month"
+        );
+        assert_eq!(
+            describe("0 0 15W * *"),
+            "at 00:00, on the weekday nearest day 15 of the month"
+        );
+        assert_eq!(
+            describe("0 17 * * 5L"),
+            "at 17:00, on the last Friday of the month"
+        );
+        assert_eq!(
+            describe("0 10 * * 1#2"),
+            "at 10:00, on the second Monday of the month"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Randomized offsets for schedules shared across many nodes.
+
+use crate::{from_timestamp, to_timestamp};
+use portals_cron::{CronExpr, CronSchedule};
+use portals_random::InsecureRandom;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A schedule whose occurrences are delayed by a bounded random amount.
+///
+/// When many nodes run the same expression, each wrapping it with its own
+/// random source spreads their runs over `[0, max_jitter]` seconds after the
+/// nominal time instead of all firing at once.
+///
+/// A fresh offset is drawn on every call to [`CronSchedule::next_after`].
+/// Keep `max_jitter` shorter than the interval between occurrences, or a
+/// delayed run may be reported after the next nominal one. Matching via
+/// [`CronExpr`] reflects the underlying, unjittered schedule.
+#[derive(Debug)]
+pub struct JitteredSchedule<S, R> {
+    inner: S,
+    rng: Mutex<R>,
+    max_jitter: Duration,
+}
+
+impl<S: CronSchedule, R: InsecureRandom> JitteredSchedule<S, R> {
+    /// Wrap `inner`, drawing offsets of up to `max_jitter` from `rng`.
+    ///
+    /// Offsets have whole-second resolution.
+    pub fn new(inner: S, rng: R, max_jitter: Duration) -> Self {
+        Self {
+            inner,
+            rng: Mutex::new(rng),
+            max_jitter,
+        }
+    }
+
+    /// The underlying schedule.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// The largest offset applied to an occurrence.
+    pub fn max_jitter(&self) -> Duration {
+        self.max_jitter
+    }
+
+    fn offset(&self) -> i64 {
+        let max = self.max_jitter.as_secs();
+        if max == 0 {
+            return 0;
+        }
+        let mut rng = self
+            .rng
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        (rng.u64() % (max + 1)) as i64
+    }
+}
+
+impl<S: CronSchedule, R: InsecureRandom> CronExpr for JitteredSchedule<S, R> {
+    fn matches(&self, second: u8, minute: u8, hour: u8, day: u8, month: u8, weekday: u8) -> bool {
+        self.inner
+            .matches(second, minute, hour, day, month, weekday)
+    }
+
+    fn as_str(&self) -> &str {
+        self.inner.as_str()
+    }
+}
+
+impl<S: CronSchedule, R> fmt::Display for JitteredSchedule<S, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl<S: CronSchedule, R: InsecureRandom> CronSchedule for JitteredSchedule<S, R> {
+    fn next_after(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<(i32, u8, u8, u8, u8, u8)> {
+        let (y, mo, d, h, mi, s) = self
+            .inner
+            .next_after(year, month, day, hour, minute, second)?;
+        Some(from_timestamp(
+            to_timestamp(y, mo, d, h, mi, s) + self.offset(),
+        ))
+    }
+
+    /// The previous nominal occurrence, without jitter: a past offset
+    /// cannot be recovered once drawn.
+    fn prev_before(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<(i32, u8, u8, u8, u8, u8)> {
+        self.inner
+            .prev_before(year, month, day, hour, minute, second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CronParserImpl;
+    use portals_cron::CronParser;
+    use portals_random_mock::MockInsecureRandom;
+
+    fn hourly() -> crate::Cron {
+        CronParserImpl::new().parse("0 * * * *").unwrap()
+    }
+
+    #[test]
+    fn offsets_within_bound() {
+        let schedule = JitteredSchedule::new(
+            hourly(),
+            MockInsecureRandom::new(7),
+            Duration::from_secs(300),
+        );
+        let nominal = to_timestamp(2024, 1, 1, 13, 0, 0);
+
+        let mut offsets = Vec::new();
+        for _ in 0..50 {
+            let (y, mo, d, h, mi, s) = schedule.next_after(2024, 1, 1, 12, 30, 0).unwrap();
+            let offset = to_timestamp(y, mo, d, h, mi, s) - nominal;
+            assert!((0..=300).contains(&offset), "offset {offset}");
+            offsets.push(offset);
+        }
+        offsets.dedup();
+        assert!(offsets.len() > 1);
+    }
+
+    #[test]
+    fn carries_across_day_boundary() {
+        let schedule = JitteredSchedule::new(
+            hourly(),
+            MockInsecureRandom::new(3),
+            Duration::from_secs(59 * 60),
+        );
+        let next = schedule.next_after(2024, 12, 31, 23, 30, 0).unwrap();
+        assert_eq!((next.0, next.1, next.2, next.3), (2025, 1, 1, 0));
+    }
+
+    #[test]
+    fn zero_jitter_is_identity() {
+        let schedule = JitteredSchedule::new(hourly(), MockInsecureRandom::new(1), Duration::ZERO);
+        assert_eq!(
+            schedule.next_after(2024, 1, 1, 12, 30, 0),
+            Some((2024, 1, 1, 13, 0, 0))
+        );
+        assert_eq!(schedule.as_str(), "0 * * * *");
+    }
+
+    #[test]
+    fn previous_is_nominal() {
+        let schedule = JitteredSchedule::new(
+            hourly(),
+            MockInsecureRandom::new(5),
+            Duration::from_secs(600),
+        );
+        assert_eq!(
+            schedule.prev_before(2024, 1, 1, 12, 30, 0),
+            Some((2024, 1, 1, 12, 0, 0))
+        );
+    }
+}
@@ -2,8 +2,19 @@
 //!
 //! Works on both native and WASM targets.
 
-use portals_cron::{CronError, CronExpr, CronParser, CronSchedule};
+mod builder;
+mod describe;
+mod jitter;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod zoned;
+
+pub use builder::CronBuilder;
+pub use jitter::JitteredSchedule;
+use portals_cron::{CronError, CronExpr, CronParser, CronSchedule, CronScheduleTz};
+use portals_random::InsecureRandom;
 use std::fmt;
+pub use zoned::ZonedSchedule;
 
 /// A parsed cron expression.
 #[derive(Debug, Clone)]
@@ -24,6 +35,110 @@ enum FieldMatcher {
     Any,
     /// Match specific values.
     Values(Vec<u8>),
+    /// Match specific values or calendar-dependent specifiers. Only the
+    /// day-of-month and day-of-week fields produce this.
+    Calendar {
+        values: Vec<u8>,
+        specs: Vec<Special>,
+    },
+}
+
+/// Quartz-style calendar specifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Special {
+    /// `L` or `L-n`: the last day of the month, less `n` days.
+    LastDay(u8),
+    /// `LW`: the last weekday (Monday-Friday) of the month.
+    LastWeekday,
+    /// `nW`: the weekday nearest day `n`, without leaving the month.
+    NearestWeekday(u8),
+    /// `dL`: the last given day of the week in the month.
+    LastOf(u8),
+    /// `d#n`: the `n`th given day of the week in the month.
+    Nth { weekday: u8, n: u8 },
+}
+
+impl Special {
+    /// Parse a specifier, or `None` if `part` is not one.
+    fn parse(part: &str, field: &'static str) -> Result<Option<Self>, CronError> {
+        let invalid = |reason: &str| CronError::InvalidField {
+            field,
+            value: part.to_string(),
+            reason: reason.to_string(),
+        };
+        let number = |s: &str, min: u8, max: u8| -> Result<u8, CronError> {
+            let v: u8 = s.parse().map_err(|_| invalid("invalid value"))?;
+            if v < min || v > max {
+                return Err(CronError::OutOfRange {
+                    field,
+                    value: v as u32,
+                    min: min as u32,
+                    max: max as u32,
+                });
+            }
+            Ok(v)
+        };
+
+        let special = match field {
+            "day" => {
+                if part == "L" {
+                    Self::LastDay(0)
+                } else if part == "LW" {
+                    Self::LastWeekday
+                } else if let Some(offset) = part.strip_prefix("L-") {
+                    Self::LastDay(number(offset, 0, 30)?)
+                } else if let Some(day) = part.strip_suffix('W') {
+                    Self::NearestWeekday(number(day, 1, 31)?)
+                } else {
+                    return Ok(None);
+                }
+            }
+            "weekday" => {
+                if let Some((weekday, n)) = part.split_once('#') {
+                    Self::Nth {
+                        weekday: number(weekday, 0, 6)?,
+                        n: number(n, 1, 5)?,
+                    }
+                } else if let Some(weekday) = part.strip_suffix('L').filter(|w| !w.is_empty()) {
+                    Self::LastOf(number(weekday, 0, 6)?)
+                } else {
+                    return Ok(None);
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(special))
+    }
+
+    fn matches(self, year: i32, month: u8, day: u8, weekday: u8) -> bool {
+        let last = days_in_month(year, month);
+        match self {
+            Self::LastDay(offset) => day as i32 == last as i32 - offset as i32,
+            Self::LastWeekday => {
+                let back = match day_of_week(year, month, last) {
+                    6 => 1,
+                    0 => 2,
+                    _ => 0,
+                };
+                day == last - back
+            }
+            Self::NearestWeekday(target) => {
+                if target > last {
+                    return false;
+                }
+                let nearest = match day_of_week(year, month, target) {
+                    6 if target == 1 => 3,
+                    6 => target - 1,
+                    0 if target == last => target - 2,
+                    0 => target + 1,
+                    _ => target,
+                };
+                day == nearest
+            }
+            Self::LastOf(target) => weekday == target && day + 7 > last,
+            Self::Nth { weekday: target, n } => weekday == target && (day - 1) / 7 + 1 == n,
+        }
+    }
 }
 
 impl FieldMatcher {
@@ -31,22 +146,53 @@ impl FieldMatcher {
         match self {
             Self::Any => true,
             Self::Values(values) => values.contains(&value),
+            Self::Calendar { values, .. } => values.contains(&value),
         }
     }
 
-    fn parse(s: &str, field: &'static str, min: u8, max: u8) -> Result<Self, CronError> {
+    /// Match a day-of-month or day-of-week value on a specific date.
+    fn matches_on(&self, value: u8, year: i32, month: u8, day: u8, weekday: u8) -> bool {
+        match self {
+            Self::Calendar { values, specs } => {
+                values.contains(&value)
+                    || specs
+                        .iter()
+                        .any(|spec| spec.matches(year, month, day, weekday))
+            }
+            _ => self.matches(value),
+        }
+    }
+
+    fn parse(
+        s: &str,
+        field: &'static str,
+        min: u8,
+        max: u8,
+        seed: Option<u64>,
+    ) -> Result<Self, CronError> {
         let s = s.trim();
+        let named = replace_names(s, field);
+        let s = named.as_str();
 
         if s == "*" {
             return Ok(Self::Any);
         }
 
         let mut values = Vec::new();
+        let mut specs = Vec::new();
 
         for part in s.split(',') {
             let part = part.trim();
 
-            if let Some((range, step)) = part.split_once('/') {
+            if let Some(hashed) = parse_hashed(part, field, min, max, seed)? {
+                for v in hashed {
+                    if !values.contains(&v) {
+                        values.push(v);
+                    }
+                }
+            } else if let Some(special) = Special::parse(part, field)? {
+                specs.push(special);
+            } else if let Some((range, step)) = part.split_once('/') {
                 // Step value: */2 or 1-10/2
                 let step: u8 = step.parse().map_err(|_| CronError::InvalidField {
                     field,
@@ -147,12 +293,178 @@ impl FieldMatcher {
         }
 
         values.sort();
-        Ok(Self::Values(values))
+        if specs.is_empty() {
+            Ok(Self::Values(values))
+        } else {
+            Ok(Self::Calendar { values, specs })
+        }
+    }
+}
+
+/// Expand a Jenkins-style hashed term: `H`, `H/n`, `H(a-b)`, or `H(a-b)/n`,
+/// or `None` if `part` is not one.
+///
+/// `H` stands for a value derived from `seed` and the field name, so
+/// schedules parsed with different seeds spread out while each one stays
+/// stable. A bare `H` in the day-of-month field picks from 1-28 so the
+/// schedule runs every month.
+fn parse_hashed(
+    part: &str,
+    field: &'static str,
+    min: u8,
+    max: u8,
+    seed: Option<u64>,
+) -> Result<Option<Vec<u8>>, CronError> {
+    let Some(rest) = part.strip_prefix(['H', 'h']) else {
+        return Ok(None);
+    };
+    let invalid = |reason: &str| CronError::InvalidField {
+        field,
+        value: part.to_string(),
+        reason: reason.to_string(),
+    };
+    let Some(seed) = seed else {
+        return Err(invalid("H requires a hash seed"));
+    };
+
+    let (start, end, rest) = match rest.strip_prefix('(') {
+        Some(inner) => {
+            let (range, rest) = inner
+                .split_once(')')
+                .ok_or_else(|| invalid("unclosed range"))?;
+            let (a, b) = range
+                .split_once('-')
+                .ok_or_else(|| invalid("invalid range"))?;
+            let a: u8 = a.parse().map_err(|_| invalid("invalid range start"))?;
+            let b: u8 = b.parse().map_err(|_| invalid("invalid range end"))?;
+            if let Some(v) = [a, b].into_iter().find(|&v| v < min || v > max) {
+                return Err(CronError::OutOfRange {
+                    field,
+                    value: v as u32,
+                    min: min as u32,
+                    max: max as u32,
+                });
+            }
+            if a > b {
+                return Err(invalid("range start > end"));
+            }
+            (a, b, rest)
+        }
+        None if field == "day" => (1, 28, rest),
+        None => (min, max, rest),
+    };
+
+    let hash = field_hash(seed, field);
+    if rest.is_empty() {
+        let span = (end - start) as u64 + 1;
+        return Ok(Some(vec![start + (hash % span) as u8]));
+    }
+
+    let step: u8 = rest
+        .strip_prefix('/')
+        .ok_or_else(|| invalid("invalid value"))?
+        .parse()
+        .map_err(|_| invalid("invalid step"))?;
+    if step == 0 {
+        return Err(CronError::InvalidStep { field, step: 0 });
+    }
+    let span = (end - start) as u64 + 1;
+    let offset = (hash % (step as u64).min(span)) as u8;
+    Ok(Some(
+        (start + offset..=end).step_by(step as usize).collect(),
+    ))
+}
+
+/// Mix a seed with a field name so each field gets its own hashed value.
+fn field_hash(seed: u64, field: &str) -> u64 {
+    // The splitmix64 finalizer.
+    let mut x = fnv1a(field) ^ seed;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// 64-bit FNV-1a.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Replace month (`JAN`-`DEC`) or weekday (`SUN`-`SAT`) names in a field
+/// with their numbers, case-insensitively. Other words, such as the `L` and
+/// `W` specifiers, are kept as written.
+fn replace_names(s: &str, field: &'static str) -> String {
+    let (names, first): (&[&str], u8) = match field {
+        "month" => (&MONTH_NAMES, 1),
+        "weekday" => (&WEEKDAY_NAMES, 0),
+        _ => return s.to_string(),
+    };
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic()) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        // `FRIL` is the last-Friday specifier spelled with a name.
+        let (name, suffix) = match word.strip_suffix(['L', 'l']) {
+            Some(name) if name.len() == 3 => (name, "L"),
+            _ => (word, ""),
+        };
+        match names.iter().position(|n| n.eq_ignore_ascii_case(name)) {
+            Some(i) => {
+                out.push_str(&(first + i as u8).to_string());
+                out.push_str(suffix);
+            }
+            None => out.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The 5-field expansion of an `@`-macro, or `None` if `expr` is not one.
+fn expand_macro(expr: &str) -> Result<Option<&'static str>, CronError> {
+    let expr = expr.trim();
+    if !expr.starts_with('@') {
+        return Ok(None);
     }
+    let expanded = match expr.to_ascii_lowercase().as_str() {
+        "@yearly" | "@annually" => "0 0 1 1 *",
+        "@monthly" => "0 0 1 * *",
+        "@weekly" => "0 0 * * 0",
+        "@daily" | "@midnight" => "0 0 * * *",
+        "@hourly" => "0 * * * *",
+        _ => {
+            return Err(CronError::InvalidField {
+                field: "macro",
+                value: expr.to_string(),
+                reason: "unknown macro".to_string(),
+            });
+        }
+    };
+    Ok(Some(expanded))
 }
 
 impl Cron {
-    fn parse_5_field(expr: &str) -> Result<Self, CronError> {
+    fn parse_5_field(expr: &str, seed: Option<u64>) -> Result<Self, CronError> {
+        if let Some(expanded) = expand_macro(expr)? {
+            let cron = Self::parse_5_field(expanded, seed)?;
+            return Ok(Self {
+                expr: expr.to_string(),
+                ..cron
+            });
+        }
+
         let fields: Vec<&str> = expr.split_whitespace().collect();
         if fields.len() != 5 {
             return Err(CronError::InvalidFieldCount {
@@ -164,15 +476,23 @@ impl Cron {
         Ok(Self {
             expr: expr.to_string(),
             seconds: FieldMatcher::Values(vec![0]), // Default to 0 seconds
-            minutes: FieldMatcher::parse(fields[0], "minute", 0, 59)?,
-            hours: FieldMatcher::parse(fields[1], "hour", 0, 23)?,
-            days: FieldMatcher::parse(fields[2], "day", 1, 31)?,
-            months: FieldMatcher::parse(fields[3], "month", 1, 12)?,
-            weekdays: FieldMatcher::parse(fields[4], "weekday", 0, 6)?,
+            minutes: FieldMatcher::parse(fields[0], "minute", 0, 59, seed)?,
+            hours: FieldMatcher::parse(fields[1], "hour", 0, 23, seed)?,
+            days: FieldMatcher::parse(fields[2], "day", 1, 31, seed)?,
+            months: FieldMatcher::parse(fields[3], "month", 1, 12, seed)?,
+            weekdays: FieldMatcher::parse(fields[4], "weekday", 0, 6, seed)?,
         })
     }
 
-    fn parse_6_field(expr: &str) -> Result<Self, CronError> {
+    fn parse_6_field(expr: &str, seed: Option<u64>) -> Result<Self, CronError> {
+        if let Some(expanded) = expand_macro(expr)? {
+            let cron = Self::parse_5_field(expanded, seed)?;
+            return Ok(Self {
+                expr: expr.to_string(),
+                ..cron
+            });
+        }
+
         let fields: Vec<&str> = expr.split_whitespace().collect();
         if fields.len() != 6 {
             return Err(CronError::InvalidFieldCount {
@@ -183,24 +503,55 @@ impl Cron {
 
         Ok(Self {
             expr: expr.to_string(),
-            seconds: FieldMatcher::parse(fields[0], "second", 0, 59)?,
-            minutes: FieldMatcher::parse(fields[1], "minute", 0, 59)?,
-            hours: FieldMatcher::parse(fields[2], "hour", 0, 23)?,
-            days: FieldMatcher::parse(fields[3], "day", 1, 31)?,
-            months: FieldMatcher::parse(fields[4], "month", 1, 12)?,
-            weekdays: FieldMatcher::parse(fields[5], "weekday", 0, 6)?,
+            seconds: FieldMatcher::parse(fields[0], "second", 0, 59, seed)?,
+            minutes: FieldMatcher::parse(fields[1], "minute", 0, 59, seed)?,
+            hours: FieldMatcher::parse(fields[2], "hour", 0, 23, seed)?,
+            days: FieldMatcher::parse(fields[3], "day", 1, 31, seed)?,
+            months: FieldMatcher::parse(fields[4], "month", 1, 12, seed)?,
+            weekdays: FieldMatcher::parse(fields[5], "weekday", 0, 6, seed)?,
         })
     }
+
+    /// Whether this schedule has a seconds field.
+    pub fn has_seconds(&self) -> bool {
+        self.expr.split_whitespace().count() == 6
+    }
 }
 
-impl CronExpr for Cron {
-    fn matches(&self, second: u8, minute: u8, hour: u8, day: u8, month: u8, weekday: u8) -> bool {
+impl Cron {
+    /// Match a datetime, resolving calendar specifiers (`L`, `W`, `#`)
+    /// against `year`.
+    #[allow(clippy::too_many_arguments)]
+    fn matches_on(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+        weekday: u8,
+    ) -> bool {
         self.seconds.matches(second)
             && self.minutes.matches(minute)
             && self.hours.matches(hour)
-            && self.days.matches(day)
+            && self.days.matches_on(day, year, month, day, weekday)
             && self.months.matches(month)
-            && self.weekdays.matches(weekday)
+            && self.weekdays.matches_on(weekday, year, month, day, weekday)
+    }
+}
+
+/// Year assumed by [`CronExpr::matches`], which has no year argument, when
+/// resolving calendar specifiers.
+const NON_LEAP_YEAR: i32 = 2001;
+
+impl CronExpr for Cron {
+    /// Check if this expression matches the given datetime.
+    ///
+    /// Calendar specifiers (`L`, `W`, `#`) are resolved as if in a non-leap
+    /// year; `next_after` resolves them against the actual year.
+    fn matches(&self, second: u8, minute: u8, hour: u8, day: u8, month: u8, weekday: u8) -> bool {
+        self.matches_on(NON_LEAP_YEAR, month, day, hour, minute, second, weekday)
     }
 
     fn as_str(&self) -> &str {
@@ -266,7 +617,7 @@ impl CronSchedule for Cron {
 
             let weekday = day_of_week(y, mo, d);
 
-            if self.matches(s, mi, h, d, mo, weekday) {
+            if self.matches_on(y, mo, d, h, mi, s, weekday) {
                 return Some((y, mo, d, h, mi, s));
             }
 
@@ -276,6 +627,82 @@ impl CronSchedule for Cron {
 
         None
     }
+
+    fn prev_before(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<(i32, u8, u8, u8, u8, u8)> {
+        // Mirror of `next_after`, stepping backwards
+        let mut y = year;
+        let mut mo = month;
+        let mut d = day;
+        let mut h = hour as i8;
+        let mut mi = minute as i8;
+        let mut s = second as i8 - 1;
+
+        // Search up to 4 years back
+        let min_year = year - 4;
+
+        loop {
+            // Normalize underflow
+            if s < 0 {
+                s = 59;
+                mi -= 1;
+            }
+            if mi < 0 {
+                mi = 59;
+                h -= 1;
+            }
+            if h < 0 {
+                h = 23;
+                if d > 1 {
+                    d -= 1;
+                } else {
+                    if mo > 1 {
+                        mo -= 1;
+                    } else {
+                        mo = 12;
+                        y -= 1;
+                    }
+                    d = days_in_month(y, mo);
+                }
+            }
+
+            if y < min_year {
+                return None;
+            }
+
+            let weekday = day_of_week(y, mo, d);
+
+            if self.matches_on(y, mo, d, h as u8, mi as u8, s as u8, weekday) {
+                return Some((y, mo, d, h as u8, mi as u8, s as u8));
+            }
+
+            // Decrement by one second
+            s -= 1;
+        }
+    }
+}
+
+/// A bare expression is evaluated in UTC. Wrap it in a [`ZonedSchedule`]
+/// to evaluate it in another time zone.
+impl CronScheduleTz for Cron {
+    fn next_after_timestamp(&self, timestamp: i64) -> Option<i64> {
+        let (y, mo, d, h, mi, s) = from_timestamp(timestamp);
+        let (y, mo, d, h, mi, s) = self.next_after(y, mo, d, h, mi, s)?;
+        Some(to_timestamp(y, mo, d, h, mi, s))
+    }
+
+    fn prev_before_timestamp(&self, timestamp: i64) -> Option<i64> {
+        let (y, mo, d, h, mi, s) = from_timestamp(timestamp);
+        let (y, mo, d, h, mi, s) = self.prev_before(y, mo, d, h, mi, s)?;
+        Some(to_timestamp(y, mo, d, h, mi, s))
+    }
 }
 
 /// Calculate day of week (0 = Sunday).
@@ -315,13 +742,87 @@ fn is_leap_year(year: i32) -> bool {
     (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
 }
 
+/// Seconds since the Unix epoch for a UTC datetime.
+fn to_timestamp(year: i32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> i64 {
+    // Days from civil, after Howard Hinnant's algorithm.
+    let y = if month <= 2 { year - 1 } else { year } as i64;
+    let m = month as i64;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    days * 86400 + hour as i64 * 3600 + minute as i64 * 60 + second as i64
+}
+
+/// The UTC datetime for seconds since the Unix epoch.
+fn from_timestamp(ts: i64) -> (i32, u8, u8, u8, u8, u8) {
+    let days = ts.div_euclid(86400);
+    let secs = ts.rem_euclid(86400);
+
+    // Civil from days, after Howard Hinnant's algorithm.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+
+    (
+        year,
+        month,
+        day,
+        (secs / 3600) as u8,
+        (secs / 60 % 60) as u8,
+        (secs % 60) as u8,
+    )
+}
+
 /// Default cron parser.
+///
+/// Expressions may use Jenkins-style `H` terms (`H`, `H/15`, `H(0-29)`)
+/// once the parser has a hash seed. `H` resolves to a value derived from the
+/// seed, so many workers sharing one expression but seeded differently
+/// don't all fire at the same instant:
+///
+/// ```ignore
+/// let parser = CronParserImpl::new().with_key("nightly-report");
+/// let cron = parser.parse("H H(0-5) * * *")?;
+/// ```
 #[derive(Debug, Default, Clone, Copy)]
-pub struct CronParserImpl;
+pub struct CronParserImpl {
+    seed: Option<u64>,
+}
 
 impl CronParserImpl {
+    /// A parser that rejects `H` terms.
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Resolve `H` terms from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Resolve `H` terms from a hash of `key`, such as a job or host name,
+    /// so the same key always gets the same times.
+    pub fn with_key(self, key: &str) -> Self {
+        self.with_seed(fnv1a(key))
+    }
+
+    /// Resolve `H` terms from a seed drawn from `rng`.
+    pub fn with_random<R: InsecureRandom + ?Sized>(self, rng: &mut R) -> Self {
+        self.with_seed(rng.u64())
+    }
+
+    /// The seed for `H` terms, if any.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
     }
 }
 
@@ -329,11 +830,11 @@ impl CronParser for CronParserImpl {
     type Expr = Cron;
 
     fn parse(&self, expr: &str) -> Result<Self::Expr, CronError> {
-        Cron::parse_5_field(expr)
+        Cron::parse_5_field(expr, self.seed)
     }
 
     fn parse_with_seconds(&self, expr: &str) -> Result<Self::Expr, CronError> {
-        Cron::parse_6_field(expr)
+        Cron::parse_6_field(expr, self.seed)
     }
 }
 
@@ -435,6 +936,386 @@ mod tests {
         assert_eq!(format!("{}", cron), "*/15 8-17 * * 1-5");
     }
 
+    #[test]
+    fn previous_occurrence() {
+        let parser = CronParserImpl::new();
+
+        let cron = parser.parse("30 9 * * 1-5").unwrap();
+        // Monday morning: the last run was Friday.
+        assert_eq!(
+            cron.prev_before(2024, 3, 4, 8, 0, 0),
+            Some((2024, 3, 1, 9, 30, 0))
+        );
+        // Strictly before: an exact match is not returned.
+        assert_eq!(
+            cron.prev_before(2024, 3, 4, 9, 30, 0),
+            Some((2024, 3, 1, 9, 30, 0))
+        );
+        assert_eq!(
+            cron.prev_before(2024, 3, 4, 9, 30, 1),
+            Some((2024, 3, 4, 9, 30, 0))
+        );
+
+        // Crosses a year and a leap day.
+        let cron = parser.parse("0 12 L * *").unwrap();
+        assert_eq!(
+            cron.prev_before(2024, 3, 15, 0, 0, 0),
+            Some((2024, 2, 29, 12, 0, 0))
+        );
+        assert_eq!(
+            cron.prev_before(2024, 1, 1, 0, 0, 0),
+            Some((2023, 12, 31, 12, 0, 0))
+        );
+
+        let cron = parser.parse_with_seconds("*/15 * * * * *").unwrap();
+        assert_eq!(
+            cron.prev_before(2024, 1, 1, 0, 0, 0),
+            Some((2023, 12, 31, 23, 59, 45))
+        );
+
+        // Agrees with next_after.
+        let cron = parser.parse("17 */5 * * *").unwrap();
+        let next = cron.next_after(2024, 6, 1, 0, 0, 0).unwrap();
+        let (y, mo, d, h, mi, s) = next;
+        let after = cron.next_after(y, mo, d, h, mi, s).unwrap();
+        assert_eq!(
+            cron.prev_before(after.0, after.1, after.2, after.3, after.4, after.5),
+            Some(next)
+        );
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        let cron = CronParserImpl::new().parse("30 9 * * *").unwrap();
+        // 2024-01-01T00:00:00Z
+        let midnight = 1_704_067_200;
+        assert_eq!(cron.next_after_timestamp(midnight), Some(midnight + 34_200));
+        assert_eq!(
+            cron.prev_before_timestamp(midnight),
+            Some(midnight - 86_400 + 34_200)
+        );
+    }
+
+    #[test]
+    fn iterates_occurrences() {
+        let cron = CronParserImpl::new().parse("30 9 * * 1,5").unwrap();
+        let next: Vec<_> = cron.iter_after(2024, 2, 27, 12, 0, 0).take(4).collect();
+        assert_eq!(
+            next,
+            vec![
+                (2024, 3, 1, 9, 30, 0),
+                (2024, 3, 4, 9, 30, 0),
+                (2024, 3, 8, 9, 30, 0),
+                (2024, 3, 11, 9, 30, 0),
+            ]
+        );
+
+        // A schedule that never fires ends the iterator.
+        let never = CronParserImpl::new().parse("0 0 31 2 *").unwrap();
+        let mut iter = never.iter_after(2024, 1, 1, 0, 0, 0);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn occurrences_between_is_bounded() {
+        let cron = CronParserImpl::new().parse("*/15 * * * *").unwrap();
+        let start = (2024, 1, 1, 9, 0, 0);
+        let end = (2024, 1, 1, 10, 0, 0);
+        // Exclusive of `start`, inclusive of `end`.
+        assert_eq!(
+            cron.occurrences_between(start, end, 100),
+            vec![
+                (2024, 1, 1, 9, 15, 0),
+                (2024, 1, 1, 9, 30, 0),
+                (2024, 1, 1, 9, 45, 0),
+                (2024, 1, 1, 10, 0, 0),
+            ]
+        );
+        assert_eq!(cron.occurrences_between(start, end, 2).len(), 2);
+        assert!(cron.occurrences_between(end, start, 100).is_empty());
+    }
+
+    #[test]
+    fn hashed_fields() {
+        fn values(m: &FieldMatcher) -> Vec<u8> {
+            match m {
+                FieldMatcher::Values(v) => v.clone(),
+                other => panic!("unexpected matcher {:?}", other),
+            }
+        }
+
+        let parser = CronParserImpl::new().with_seed(42);
+        let cron = parser.parse("H H(2-4) H * H/2").unwrap();
+        assert_eq!(cron.as_str(), "H H(2-4) H * H/2");
+
+        let minute = values(&cron.minutes);
+        assert_eq!(minute.len(), 1);
+        assert!(minute[0] <= 59);
+        let hour = values(&cron.hours)[0];
+        assert!((2..=4).contains(&hour));
+        // A bare `H` day stays within every month.
+        assert!((1..=28).contains(&values(&cron.days)[0]));
+        let weekdays = values(&cron.weekdays);
+        assert!(weekdays == [0, 2, 4, 6] || weekdays == [1, 3, 5]);
+
+        // Same seed, same schedule.
+        let again = parser.parse("H H(2-4) H * H/2").unwrap();
+        assert_eq!(values(&again.minutes), minute);
+
+        // Different seeds spread out.
+        let minutes: std::collections::HashSet<u8> = (0..20)
+            .map(|seed| {
+                let cron = CronParserImpl::new().with_seed(seed).parse("H * * * *");
+                values(&cron.unwrap().minutes)[0]
+            })
+            .collect();
+        assert!(minutes.len() > 5);
+
+        // Steps start at a hashed offset below the step.
+        let cron = parser.parse("H/15 * * * *").unwrap();
+        let minutes = values(&cron.minutes);
+        assert_eq!(minutes.len(), 4);
+        assert!(minutes[0] < 15);
+        assert!(minutes.windows(2).all(|w| w[1] - w[0] == 15));
+    }
+
+    #[test]
+    fn hashed_fields_from_key_and_rng() {
+        use portals_random_mock::MockInsecureRandom;
+
+        let a = CronParserImpl::new().with_key("backup");
+        assert_eq!(a.seed(), CronParserImpl::new().with_key("backup").seed());
+        assert_ne!(a.seed(), CronParserImpl::new().with_key("report").seed());
+
+        let mut rng = MockInsecureRandom::new(7);
+        let parser = CronParserImpl::new().with_random(&mut rng);
+        assert!(parser.seed().is_some());
+        assert!(parser.parse_with_seconds("H H * * * *").is_ok());
+    }
+
+    #[test]
+    fn hashed_fields_errors() {
+        // No seed.
+        let result = CronParserImpl::new().parse("H * * * *");
+        assert!(matches!(result, Err(CronError::InvalidField { .. })));
+
+        let parser = CronParserImpl::new().with_seed(1);
+        assert!(matches!(
+            parser.parse("H(0-60) * * * *"),
+            Err(CronError::OutOfRange { value: 60, .. })
+        ));
+        assert!(parser.parse("H(5-1) * * * *").is_err());
+        assert!(parser.parse("H(1-5 * * * *").is_err());
+        assert!(matches!(
+            parser.parse("H/0 * * * *"),
+            Err(CronError::InvalidStep { .. })
+        ));
+        assert!(parser.parse("Hx * * * *").is_err());
+    }
+
+    #[test]
+    fn catch_up_policies() {
+        use portals_cron::{CatchUp, missed_runs};
+
+        let parser = CronParserImpl::new();
+        let cron = parser.parse("0 * * * *").unwrap(); // Hourly
+        let last_run = (2024, 1, 1, 9, 0, 0);
+        let now = (2024, 1, 1, 12, 30, 0);
+
+        assert!(missed_runs(&cron, last_run, now, CatchUp::Skip, 100).is_empty());
+        assert_eq!(
+            missed_runs(&cron, last_run, now, CatchUp::RunOnce, 100),
+            vec![(2024, 1, 1, 12, 0, 0)]
+        );
+        assert_eq!(
+            missed_runs(&cron, last_run, now, CatchUp::RunAll, 100),
+            vec![
+                (2024, 1, 1, 10, 0, 0),
+                (2024, 1, 1, 11, 0, 0),
+                (2024, 1, 1, 12, 0, 0)
+            ]
+        );
+        assert_eq!(
+            missed_runs(&cron, last_run, now, CatchUp::RunAll, 2).len(),
+            2
+        );
+
+        // Nothing missed within the same hour.
+        let now = (2024, 1, 1, 9, 59, 59);
+        assert!(missed_runs(&cron, last_run, now, CatchUp::RunOnce, 100).is_empty());
+
+        // An occurrence exactly at `now` is due.
+        let now = (2024, 1, 1, 12, 0, 0);
+        assert_eq!(
+            missed_runs(&cron, last_run, now, CatchUp::RunOnce, 100),
+            vec![now]
+        );
+
+        // Coalescing a week of per-second occurrences stays cheap.
+        let every_second = parser.parse_with_seconds("* * * * * *").unwrap();
+        let now = (2024, 1, 8, 9, 0, 0);
+        assert_eq!(
+            missed_runs(&every_second, last_run, now, CatchUp::RunOnce, 100),
+            vec![now]
+        );
+    }
+
+    #[test]
+    fn last_day_of_month() {
+        let parser = CronParserImpl::new();
+        let cron = parser.parse("0 0 L * *").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 2, 1, 0, 0, 0),
+            Some((2024, 2, 29, 0, 0, 0))
+        );
+        assert_eq!(
+            cron.next_after(2023, 2, 1, 0, 0, 0),
+            Some((2023, 2, 28, 0, 0, 0))
+        );
+        assert_eq!(
+            cron.next_after(2024, 4, 1, 0, 0, 0),
+            Some((2024, 4, 30, 0, 0, 0))
+        );
+
+        let cron = parser.parse("0 0 L-2 * *").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 1, 1, 0, 0, 0),
+            Some((2024, 1, 29, 0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn weekday_nearest_and_last() {
+        let parser = CronParserImpl::new();
+        // 2024-06-15 is a Saturday: nearest weekday is Friday the 14th.
+        let cron = parser.parse("0 0 15W * *").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 6, 1, 0, 0, 0),
+            Some((2024, 6, 14, 0, 0, 0))
+        );
+        // 2024-06-01 is a Saturday: 1W does not leave the month, so Monday the 3rd.
+        let cron = parser.parse("0 0 1W * *").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 5, 31, 0, 0, 0),
+            Some((2024, 6, 3, 0, 0, 0))
+        );
+        // 2024-06-30 is a Sunday: last weekday is Friday the 28th.
+        let cron = parser.parse("0 0 LW * *").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 6, 1, 0, 0, 0),
+            Some((2024, 6, 28, 0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn nth_and_last_day_of_week() {
+        let parser = CronParserImpl::new();
+        // First Monday of the month.
+        let cron = parser.parse("0 9 * * 1#1").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 1, 2, 0, 0, 0),
+            Some((2024, 2, 5, 9, 0, 0))
+        );
+        // Last Friday of the month.
+        let cron = parser.parse("0 17 * * 5L").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 1, 1, 0, 0, 0),
+            Some((2024, 1, 26, 17, 0, 0))
+        );
+        // Specifiers combine with plain values.
+        let cron = parser.parse("0 0 1,L * *").unwrap();
+        assert!(cron.matches(0, 0, 0, 1, 3, 5));
+        assert!(cron.matches(0, 0, 0, 31, 3, 0));
+        assert!(!cron.matches(0, 0, 0, 30, 3, 6));
+    }
+
+    #[test]
+    fn macros() {
+        let parser = CronParserImpl::new();
+        let cases = [
+            ("@yearly", "0 0 1 1 *"),
+            ("@annually", "0 0 1 1 *"),
+            ("@monthly", "0 0 1 * *"),
+            ("@weekly", "0 0 * * 0"),
+            ("@daily", "0 0 * * *"),
+            ("@midnight", "0 0 * * *"),
+            ("@hourly", "0 * * * *"),
+        ];
+        for (name, expr) in cases {
+            let cron = parser.parse(name).unwrap();
+            let expected = parser.parse(expr).unwrap();
+            assert_eq!(cron.as_str(), name);
+            assert!(!cron.has_seconds());
+            assert_eq!(
+                cron.next_after(2024, 2, 14, 10, 30, 0),
+                expected.next_after(2024, 2, 14, 10, 30, 0),
+                "{}",
+                name
+            );
+        }
+
+        let cron = parser.parse_with_seconds("@Daily").unwrap();
+        assert_eq!(cron.as_str(), "@Daily");
+        assert_eq!(
+            cron.next_after(2024, 1, 1, 0, 0, 0),
+            Some((2024, 1, 2, 0, 0, 0))
+        );
+
+        assert!(matches!(
+            parser.parse("@fortnightly"),
+            Err(CronError::InvalidField { field: "macro", .. })
+        ));
+    }
+
+    #[test]
+    fn month_and_weekday_names() {
+        let parser = CronParserImpl::new();
+        let cron = parser.parse("0 0 * JAN MON").unwrap();
+        assert!(cron.matches(0, 0, 0, 1, 1, 1));
+        assert!(!cron.matches(0, 0, 0, 1, 2, 1));
+        assert!(!cron.matches(0, 0, 0, 1, 1, 2));
+        assert_eq!(cron.as_str(), "0 0 * JAN MON");
+
+        let cron = parser.parse("0 9 * jun-Aug,dec mon-fri").unwrap();
+        assert!(cron.matches(0, 0, 9, 1, 7, 5));
+        assert!(cron.matches(0, 0, 9, 1, 12, 1));
+        assert!(!cron.matches(0, 0, 9, 1, 9, 1));
+        assert!(!cron.matches(0, 0, 9, 1, 7, 6));
+
+        let cron = parser.parse("0 0 * * FRI#2,SunL").unwrap();
+        assert_eq!(
+            cron.next_after(2024, 3, 1, 0, 0, 0),
+            Some((2024, 3, 8, 0, 0, 0))
+        );
+        assert_eq!(
+            cron.next_after(2024, 3, 9, 0, 0, 0),
+            Some((2024, 3, 31, 0, 0, 0))
+        );
+
+        let cron = parser.parse_with_seconds("0 0 0 * */2 SAT,SUN").unwrap();
+        assert!(cron.matches(0, 0, 0, 1, 3, 6));
+
+        assert!(parser.parse("0 0 * MON * *").is_err());
+        assert!(parser.parse("0 0 * * MONDAY").is_err());
+        assert!(parser.parse("0 JAN * * *").is_err());
+    }
+
+    #[test]
+    fn invalid_specifiers() {
+        let parser = CronParserImpl::new();
+        assert!(matches!(
+            parser.parse("0 0 32W * *"),
+            Err(CronError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            parser.parse("0 0 * * 1#6"),
+            Err(CronError::OutOfRange { .. })
+        ));
+        assert!(parser.parse("0 0 * * L-1").is_err());
+        assert!(parser.parse("0 L * * *").is_err());
+    }
+
     #[test]
     fn day_of_week_calculation() {
         // Known dates
@@ -443,6 +1324,14 @@ mod tests {
         assert_eq!(day_of_week(2000, 1, 1), 6); // Saturday
     }
 
+    #[test]
+    fn timestamp_roundtrip() {
+        assert_eq!(to_timestamp(1970, 1, 1, 0, 0, 0), 0);
+        assert_eq!(to_timestamp(2024, 2, 29, 12, 30, 15), 1709209815);
+        assert_eq!(from_timestamp(1709209815), (2024, 2, 29, 12, 30, 15));
+        assert_eq!(from_timestamp(-1), (1969, 12, 31, 23, 59, 59));
+    }
+
     #[test]
     fn leap_year() {
         assert!(is_leap_year(2000));
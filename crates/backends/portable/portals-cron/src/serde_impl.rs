@@ -0,0 +1,77 @@
+//! Serde support, enabled by the `serde` feature.
+//!
+//! A [`Cron`] serializes as its expression string with fields separated by
+//! single spaces. Deserializing re-parses the string, choosing 5- or 6-field
+//! mode from the field count, so seconds-mode schedules round-trip. Macros
+//! such as `@daily` are kept as written. Expressions with `H` terms don't
+//! deserialize, since the hash seed isn't part of the string.
+
+use crate::Cron;
+use portals_cron::CronError;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+impl Serialize for Cron {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields: Vec<&str> = self.expr.split_whitespace().collect();
+        serializer.serialize_str(&fields.join(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for Cron {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let expr = String::deserialize(deserializer)?;
+        let parsed = match expr.split_whitespace().count() {
+            6 => Cron::parse_6_field(&expr, None),
+            // `@`-macros are a single field.
+            5 | 1 => Cron::parse_5_field(&expr, None),
+            got => Err(CronError::InvalidFieldCount {
+                expected: "5 or 6",
+                got,
+            }),
+        };
+        parsed.map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Cron, CronBuilder, CronParserImpl};
+    use portals_cron::{CronExpr, CronParser};
+
+    #[test]
+    fn roundtrip_five_field() {
+        let cron = CronParserImpl::new().parse("*/15  8-17 * * 1-5").unwrap();
+        let json = serde_json::to_string(&cron).unwrap();
+        assert_eq!(json, r#""*/15 8-17 * * 1-5""#);
+
+        let back: Cron = serde_json::from_str(&json).unwrap();
+        assert!(!back.has_seconds());
+        assert_eq!(back.as_str(), "*/15 8-17 * * 1-5");
+    }
+
+    #[test]
+    fn roundtrip_preserves_seconds() {
+        let cron = CronBuilder::new().seconds([30]).build().unwrap();
+        let json = serde_json::to_string(&cron).unwrap();
+        assert_eq!(json, r#""30 * * * * *""#);
+
+        let back: Cron = serde_json::from_str(&json).unwrap();
+        assert!(back.has_seconds());
+        assert!(back.matches(30, 0, 0, 1, 1, 0));
+        assert!(!back.matches(0, 0, 0, 1, 1, 0));
+    }
+
+    #[test]
+    fn roundtrip_macro() {
+        let cron: Cron = serde_json::from_str("\"@hourly\"").unwrap();
+        assert_eq!(cron.as_str(), "@hourly");
+        assert_eq!(serde_json::to_string(&cron).unwrap(), "\"@hourly\"");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(serde_json::from_str::<Cron>(r#""* * *""#).is_err());
+        assert!(serde_json::from_str::<Cron>(r#""61 * * * *""#).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Schedules evaluated in a time zone.
+
+use crate::{day_of_week, from_timestamp, to_timestamp};
+use portals_cron::{CronError, CronSchedule, CronScheduleTz};
+use portals_timezone::{AmbiguousOffset, DateTime, Offset, TimeZone, Timestamp};
+
+/// Upper bound on how far a time zone transition moves the wall clock.
+/// Real transitions are at most two hours.
+const MAX_SHIFT: i64 = 3 * 3600;
+
+/// A schedule whose fields are local times in a time zone.
+///
+/// Daylight saving transitions are resolved as follows:
+///
+/// - **Skipped times** (spring forward): an occurrence whose local time does
+///   not exist runs once, shifted forward by the length of the gap, so
+///   `30 2 * * *` runs at 03:30 on a day that jumps from 02:00 to 03:00.
+///   If that coincides with another occurrence, it runs once.
+/// - **Repeated times** (fall back): an occurrence in the repeated hour runs
+///   on the first pass only, so `30 1 * * *` runs once. Schedules that fire
+///   at that minute of every hour, like `*/15 * * * *`, run on both passes
+///   and keep their spacing.
+///
+/// ```ignore
+/// let daily = ZonedSchedule::named(parser.parse("30 2 * * *")?, "America/New_York")?;
+/// let next = daily.next_after_timestamp(now)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZonedSchedule<S> {
+    inner: S,
+    tz: TimeZone,
+}
+
+impl<S: CronSchedule> ZonedSchedule<S> {
+    /// Evaluate `inner` in `tz`.
+    pub fn new(inner: S, tz: TimeZone) -> Self {
+        Self { inner, tz }
+    }
+
+    /// Evaluate `inner` in an IANA time zone such as `"Europe/Berlin"`.
+    pub fn named(inner: S, name: &str) -> Result<Self, CronError> {
+        let tz = portals_timezone::get(name).map_err(|e| CronError::Other(e.to_string()))?;
+        Ok(Self::new(inner, tz))
+    }
+
+    /// Evaluate `inner` at a fixed offset from UTC, in seconds east.
+    pub fn with_offset(inner: S, offset_seconds: i32) -> Result<Self, CronError> {
+        let offset =
+            Offset::from_seconds(offset_seconds).map_err(|e| CronError::Other(e.to_string()))?;
+        Ok(Self::new(inner, TimeZone::fixed(offset)))
+    }
+
+    /// The underlying schedule.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// The time zone.
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.tz
+    }
+
+    /// Wall-clock time at `timestamp`, as a naive timestamp.
+    fn wall(&self, timestamp: i64) -> Option<i64> {
+        let offset = self.tz.to_offset(Timestamp::from_second(timestamp).ok()?);
+        Some(timestamp + offset.seconds() as i64)
+    }
+
+    /// The instants at which a local occurrence runs: none, one, or two.
+    fn instants(&self, local: (i32, u8, u8, u8, u8, u8)) -> [Option<i64>; 2] {
+        let (y, mo, d, h, mi, s) = local;
+        let naive = to_timestamp(y, mo, d, h, mi, s);
+        let Ok(dt) = DateTime::new(y as i16, mo as i8, d as i8, h as i8, mi as i8, s as i8, 0)
+        else {
+            return [None, None];
+        };
+        match self.tz.to_ambiguous_timestamp(dt).offset() {
+            AmbiguousOffset::Unambiguous { offset } => {
+                [Some(naive - offset.seconds() as i64), None]
+            }
+            // The pre-transition offset moves the time past the gap.
+            AmbiguousOffset::Gap { before, .. } => [Some(naive - before.seconds() as i64), None],
+            AmbiguousOffset::Fold { before, after } => {
+                let weekday = day_of_week(y, mo, d);
+                let hourly = (0..24).all(|hour| self.inner.matches(s, mi, hour, d, mo, weekday));
+                [
+                    Some(naive - before.seconds() as i64),
+                    hourly.then(|| naive - after.seconds() as i64),
+                ]
+            }
+        }
+    }
+}
+
+impl<S: CronSchedule> CronScheduleTz for ZonedSchedule<S> {
+    fn next_after_timestamp(&self, timestamp: i64) -> Option<i64> {
+        // Transitions reorder instants relative to local times by less than
+        // MAX_SHIFT, so scan local occurrences from that far back until one
+        // lies that far past the best candidate.
+        let (y, mo, d, h, mi, s) = from_timestamp(self.wall(timestamp)? - MAX_SHIFT);
+        let mut best: Option<(i64, i64)> = None;
+        for local in self.inner.iter_after(y, mo, d, h, mi, s) {
+            let (ly, lmo, ld, lh, lmi, ls) = local;
+            if let Some((_, best_wall)) = best
+                && to_timestamp(ly, lmo, ld, lh, lmi, ls) > best_wall + MAX_SHIFT
+            {
+                break;
+            }
+            for instant in self.instants(local).into_iter().flatten() {
+                if instant > timestamp && best.is_none_or(|(b, _)| instant < b) {
+                    best = Some((instant, self.wall(instant)?));
+                }
+            }
+        }
+        best.map(|(instant, _)| instant)
+    }
+
+    fn prev_before_timestamp(&self, timestamp: i64) -> Option<i64> {
+        let mut cursor = from_timestamp(self.wall(timestamp)? + MAX_SHIFT);
+        let mut best: Option<(i64, i64)> = None;
+        while let Some(local) = self
+            .inner
+            .prev_before(cursor.0, cursor.1, cursor.2, cursor.3, cursor.4, cursor.5)
+        {
+            let (ly, lmo, ld, lh, lmi, ls) = local;
+            if let Some((_, best_wall)) = best
+                && to_timestamp(ly, lmo, ld, lh, lmi, ls) < best_wall - MAX_SHIFT
+            {
+                break;
+            }
+            for instant in self.instants(local).into_iter().flatten() {
+                if instant < timestamp && best.is_none_or(|(b, _)| instant > b) {
+                    best = Some((instant, self.wall(instant)?));
+                }
+            }
+            cursor = local;
+        }
+        best.map(|(instant, _)| instant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cron, CronParserImpl};
+    use portals_cron::CronParser;
+
+    fn new_york(expr: &str) -> ZonedSchedule<Cron> {
+        let cron = CronParserImpl::new().parse(expr).unwrap();
+        ZonedSchedule::named(cron, "America/New_York").unwrap()
+    }
+
+    /// A UTC instant.
+    fn utc(y: i32, mo: u8, d: u8, h: u8, mi: u8) -> i64 {
+        to_timestamp(y, mo, d, h, mi, 0)
+    }
+
+    fn next_n(schedule: &ZonedSchedule<Cron>, mut from: i64, n: usize) -> Vec<i64> {
+        let mut out = Vec::new();
+        for _ in 0..n {
+            from = schedule.next_after_timestamp(from).unwrap();
+            out.push(from);
+        }
+        out
+    }
+
+    #[test]
+    fn plain_days_follow_offset() {
+        // 09:00 EST is 14:00 UTC.
+        let schedule = new_york("0 9 * * *");
+        assert_eq!(
+            schedule.next_after_timestamp(utc(2024, 1, 15, 0, 0)),
+            Some(utc(2024, 1, 15, 14, 0))
+        );
+        // 09:00 EDT is 13:00 UTC.
+        assert_eq!(
+            schedule.next_after_timestamp(utc(2024, 7, 15, 0, 0)),
+            Some(utc(2024, 7, 15, 13, 0))
+        );
+    }
+
+    #[test]
+    fn skipped_time_runs_after_gap() {
+        // 2024-03-10: 02:00 EST jumps to 03:00 EDT.
+        let schedule = new_york("30 2 * * *");
+        assert_eq!(
+            next_n(&schedule, utc(2024, 3, 10, 5, 0), 2),
+            // 03:30 EDT, then 02:30 EDT the next day.
+            vec![utc(2024, 3, 10, 7, 30), utc(2024, 3, 11, 6, 30)]
+        );
+
+        let schedule = new_york("*/30 * * * *");
+        assert_eq!(
+            next_n(&schedule, utc(2024, 3, 10, 6, 45), 3),
+            // 01:30 EST is already past; then 03:00, 03:30, 04:00 EDT.
+            vec![
+                utc(2024, 3, 10, 7, 0),
+                utc(2024, 3, 10, 7, 30),
+                utc(2024, 3, 10, 8, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_time_runs_once() {
+        // 2024-11-03: 02:00 EDT falls back to 01:00 EST.
+        let schedule = new_york("30 1 * * *");
+        assert_eq!(
+            next_n(&schedule, utc(2024, 11, 3, 4, 0), 2),
+            // 01:30 EDT, then 01:30 EST the next day.
+            vec![utc(2024, 11, 3, 5, 30), utc(2024, 11, 4, 6, 30)]
+        );
+        assert_eq!(
+            schedule.prev_before_timestamp(utc(2024, 11, 4, 5, 0)),
+            Some(utc(2024, 11, 3, 5, 30))
+        );
+    }
+
+    #[test]
+    fn repeated_time_keeps_intervals() {
+        let schedule = new_york("*/30 * * * *");
+        assert_eq!(
+            next_n(&schedule, utc(2024, 11, 3, 5, 15), 4),
+            // 01:30 EDT, 01:00 EST, 01:30 EST, 02:00 EST.
+            vec![
+                utc(2024, 11, 3, 5, 30),
+                utc(2024, 11, 3, 6, 0),
+                utc(2024, 11, 3, 6, 30),
+                utc(2024, 11, 3, 7, 0)
+            ]
+        );
+        assert_eq!(
+            schedule.prev_before_timestamp(utc(2024, 11, 3, 6, 15)),
+            Some(utc(2024, 11, 3, 6, 0))
+        );
+        assert_eq!(
+            schedule.prev_before_timestamp(utc(2024, 11, 3, 6, 0)),
+            Some(utc(2024, 11, 3, 5, 30))
+        );
+    }
+
+    #[test]
+    fn fixed_offset() {
+        let cron = CronParserImpl::new().parse("0 9 * * *").unwrap();
+        let schedule = ZonedSchedule::with_offset(cron, 2 * 3600).unwrap();
+        assert_eq!(
+            schedule.next_after_timestamp(utc(2024, 1, 1, 0, 0)),
+            Some(utc(2024, 1, 1, 7, 0))
+        );
+        assert_eq!(
+            schedule.prev_before_timestamp(utc(2024, 1, 1, 7, 0)),
+            Some(utc(2023, 12, 31, 7, 0))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_zone() {
+        let cron = CronParserImpl::new().parse("0 9 * * *").unwrap();
+        assert!(ZonedSchedule::named(cron, "Not/A_Zone").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Portable implementation of portals-csv.
+//!
+//! Works on both native and WASM targets, over any `portals-io` stream.
+//! Records map to and from structs with serde: columns are matched to
+//! fields by header name.
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Row { name: String, qty: u32 }
+//!
+//! let mut reader = CsvReader::new(stream);
+//! let rows: Vec<Row> = reader.deserialize_all()?;
+//! ```
+
+mod de;
+mod reader;
+mod ser;
+mod writer;
+
+pub use reader::CsvReader;
+pub use writer::CsvWriter;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_csv::{CsvError, CsvOptions, CsvRead, CsvWrite};
+    use portals_io_native::{ReaderStream, WriterStream};
+    use serde::{Deserialize, Serialize};
+    use std::io::Cursor;
+
+    fn reader(input: &str) -> CsvReader<ReaderStream<Cursor<Vec<u8>>>> {
+        CsvReader::new(ReaderStream::new(Cursor::new(input.as_bytes().to_vec())))
+    }
+
+    fn reader_with(input: &str, options: CsvOptions) -> CsvReader<ReaderStream<Cursor<Vec<u8>>>> {
+        CsvReader::with_options(
+            ReaderStream::new(Cursor::new(input.as_bytes().to_vec())),
+            options,
+        )
+    }
+
+    fn write(options: CsvOptions, f: impl FnOnce(&mut CsvWriter<WriterStream<Vec<u8>>>)) -> String {
+        let mut writer = CsvWriter::with_options(WriterStream::new(Vec::new()), options);
+        f(&mut writer);
+        String::from_utf8(writer.into_inner().unwrap().into_inner()).unwrap()
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Status {
+        Active,
+        Retired,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        name: String,
+        qty: u32,
+        price: Option<f64>,
+        status: Status,
+    }
+
+    #[test]
+    fn reads_records() {
+        let mut r = reader("a,b,c\n1,2,3\r\n\n4,5,6");
+        assert_eq!(r.headers().unwrap().unwrap(), ["a", "b", "c"]);
+        assert_eq!(r.read_record().unwrap().unwrap(), ["1", "2", "3"]);
+        assert_eq!(r.read_record().unwrap().unwrap(), ["4", "5", "6"]);
+        assert_eq!(r.line(), 4);
+        assert_eq!(r.read_record().unwrap(), None);
+        assert_eq!(r.read_record().unwrap(), None);
+    }
+
+    #[test]
+    fn reads_quoted_fields() {
+        let input = "x,y\n\"a,b\",\"say \"\"hi\"\"\"\n\"two\nlines\",\"\"\n";
+        let mut r = reader(input);
+        assert_eq!(
+            r.read_all().unwrap(),
+            vec![
+                vec!["a,b".to_string(), "say \"hi\"".to_string()],
+                vec!["two\nlines".to_string(), String::new()],
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_dialect() {
+        let options = CsvOptions::tsv().quote(b'\'').has_headers(false);
+        let mut r = reader_with("a\t'b\tc'\n", options);
+        assert_eq!(r.headers().unwrap(), None);
+        assert_eq!(r.read_record().unwrap().unwrap(), ["a", "b\tc"]);
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        let mut r = reader("a,b\n\"open,1\n");
+        assert!(matches!(r.read_record(), Err(CsvError::Parse { .. })));
+
+        let mut r = reader("a,b\n\"x\"y,1\n");
+        assert!(matches!(
+            r.read_record(),
+            Err(CsvError::Parse { line: 2, .. })
+        ));
+
+        let mut r = reader("a,b\n1,2\n3\n");
+        r.read_record().unwrap();
+        assert_eq!(
+            r.read_record(),
+            Err(CsvError::FieldCount {
+                line: 3,
+                expected: 2,
+                got: 1
+            })
+        );
+    }
+
+    #[test]
+    fn deserializes_by_header() {
+        // Columns are matched by name, not position.
+        let mut r = reader("qty,status,name,price\n3,active,bolt,0.25\n0,retired,nut,\n");
+        let items: Vec<Item> = r.deserialize_all().unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item {
+                    name: "bolt".to_string(),
+                    qty: 3,
+                    price: Some(0.25),
+                    status: Status::Active,
+                },
+                Item {
+                    name: "nut".to_string(),
+                    qty: 0,
+                    price: None,
+                    status: Status::Retired,
+                },
+            ]
+        );
+
+        let mut r = reader("name,qty,price,status\nbolt,many,,active\n");
+        let err = r.deserialize::<Item>().unwrap_err();
+        assert!(matches!(err, CsvError::Deserialize { line: 2, .. }));
+    }
+
+    #[test]
+    fn deserializes_tuples_without_headers() {
+        let mut r = reader_with("1,true,x\n", CsvOptions::new().has_headers(false));
+        let row: (i32, bool, String) = r.deserialize().unwrap().unwrap();
+        assert_eq!(row, (1, true, "x".to_string()));
+    }
+
+    #[test]
+    fn writes_and_quotes_records() {
+        let out = write(CsvOptions::new(), |w| {
+            w.write_record(&["plain", "a,b", "say \"hi\"", "two\nlines"])
+                .unwrap();
+            w.write_record(&[""]).unwrap();
+        });
+        assert_eq!(
+            out,
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\"\n\"\"\n"
+        );
+
+        let out = write(CsvOptions::tsv().crlf(true), |w| {
+            w.write_record(&["a,b", "c"]).unwrap();
+        });
+        assert_eq!(out, "a,b\tc\r\n");
+    }
+
+    #[test]
+    fn serializes_structs_with_headers() {
+        let items = [
+            Item {
+                name: "bolt".to_string(),
+                qty: 3,
+                price: Some(0.25),
+                status: Status::Active,
+            },
+            Item {
+                name: "nut, hex".to_string(),
+                qty: 0,
+                price: None,
+                status: Status::Retired,
+            },
+        ];
+        let out = write(CsvOptions::new(), |w| {
+            for item in &items {
+                w.serialize(item).unwrap();
+            }
+        });
+        assert_eq!(
+            out,
+            "name,qty,price,status\nbolt,3,0.25,active\n\"nut, hex\",0,,retired\n"
+        );
+
+        // Round trip.
+        let mut r = reader(&out);
+        assert_eq!(r.deserialize_all::<Item>().unwrap(), items);
+    }
+
+    #[test]
+    fn rejects_nested_values() {
+        #[derive(Serialize)]
+        struct Nested {
+            tags: Vec<String>,
+        }
+        let mut writer = CsvWriter::new(WriterStream::new(Vec::new()));
+        let result = writer.serialize(&Nested { tags: vec![] });
+        assert!(matches!(result, Err(CsvError::Serialize(_))));
+    }
+
+    #[test]
+    fn reads_across_buffer_boundaries() {
+        let long = "x".repeat(20_000);
+        let input = format!("h\n\"{}\"\n{}\n", long, long);
+        let mut r = reader(&input);
+        assert_eq!(r.read_record().unwrap().unwrap(), [long.as_str()]);
+        assert_eq!(r.read_record().unwrap().unwrap(), [long.as_str()]);
+    }
+}
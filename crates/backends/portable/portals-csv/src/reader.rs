@@ -0,0 +1,256 @@
+//! Streaming record reader.
+
+use crate::de::RecordDeserializer;
+use portals_csv::{CsvError, CsvOptions, CsvRead};
+use portals_io::{InputStream, StreamError};
+use serde::de::DeserializeOwned;
+
+const BUFFER_SIZE: usize = 8192;
+
+/// Reads CSV records from an [`InputStream`].
+///
+/// Input is read in chunks as records are requested, so arbitrarily large
+/// inputs can be processed in constant memory. Blank lines are skipped.
+///
+/// ```ignore
+/// let mut reader = CsvReader::new(stream);
+/// while let Some(row) = reader.deserialize::<Row>()? {
+///     // ...
+/// }
+/// ```
+pub struct CsvReader<S> {
+    stream: S,
+    options: CsvOptions,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    eof: bool,
+    /// Line the parser is on.
+    cursor_line: u64,
+    /// Line the last returned record started on.
+    record_line: u64,
+    headers: Option<Vec<String>>,
+    headers_read: bool,
+}
+
+impl<S: InputStream> CsvReader<S> {
+    /// Read comma-separated records with a header row.
+    pub fn new(stream: S) -> Self {
+        Self::with_options(stream, CsvOptions::default())
+    }
+
+    /// Read records in the given dialect.
+    pub fn with_options(stream: S, options: CsvOptions) -> Self {
+        Self {
+            stream,
+            options,
+            buf: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            eof: false,
+            cursor_line: 1,
+            record_line: 0,
+            headers: None,
+            headers_read: false,
+        }
+    }
+
+    /// The line the most recently read record started on (1-based).
+    pub fn line(&self) -> u64 {
+        self.record_line
+    }
+
+    /// Consume the reader, returning the stream.
+    ///
+    /// Input already buffered by the reader is lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Read the next record as a `T`.
+    ///
+    /// With a header row, struct fields and map keys are matched to columns
+    /// by name; without one, fields are taken in order, so `T` should be a
+    /// tuple or sequence. Empty fields deserialize to `None` for options.
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> Result<Option<T>, CsvError> {
+        self.ensure_headers()?;
+        let Some(record) = self.next_record()? else {
+            return Ok(None);
+        };
+        let de = RecordDeserializer::new(self.headers.as_deref(), &record);
+        T::deserialize(de)
+            .map(Some)
+            .map_err(|e| CsvError::Deserialize {
+                line: self.record_line,
+                message: e.to_string(),
+            })
+    }
+
+    /// Read all remaining records as `T`s.
+    pub fn deserialize_all<T: DeserializeOwned>(&mut self) -> Result<Vec<T>, CsvError> {
+        let mut values = Vec::new();
+        while let Some(value) = self.deserialize()? {
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    fn ensure_headers(&mut self) -> Result<(), CsvError> {
+        if !self.headers_read {
+            self.headers_read = true;
+            if self.options.has_headers {
+                self.headers = self.parse_record()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The next data record, checked against the header row's width.
+    fn next_record(&mut self) -> Result<Option<Vec<String>>, CsvError> {
+        let Some(record) = self.parse_record()? else {
+            return Ok(None);
+        };
+        if let Some(headers) = &self.headers
+            && headers.len() != record.len()
+        {
+            return Err(CsvError::FieldCount {
+                line: self.record_line,
+                expected: headers.len(),
+                got: record.len(),
+            });
+        }
+        Ok(Some(record))
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, CsvError> {
+        if self.pos == self.len {
+            if self.eof {
+                return Ok(None);
+            }
+            match self.stream.blocking_read_into(&mut self.buf) {
+                Ok(0) | Err(StreamError::Closed) => {
+                    self.eof = true;
+                    return Ok(None);
+                }
+                Ok(n) => {
+                    self.pos = 0;
+                    self.len = n;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Some(self.buf[self.pos]))
+    }
+
+    fn bump(&mut self) -> Result<Option<u8>, CsvError> {
+        let byte = self.peek()?;
+        if byte.is_some() {
+            self.pos += 1;
+        }
+        Ok(byte)
+    }
+
+    /// Consume a line ending whose first byte has been read.
+    fn end_line(&mut self, byte: u8) -> Result<(), CsvError> {
+        if byte == b'\r' && self.peek()? == Some(b'\n') {
+            self.pos += 1;
+        }
+        self.cursor_line += 1;
+        Ok(())
+    }
+
+    fn parse_record(&mut self) -> Result<Option<Vec<String>>, CsvError> {
+        // Skip blank lines.
+        loop {
+            match self.peek()? {
+                None => return Ok(None),
+                Some(byte @ (b'\r' | b'\n')) => {
+                    self.pos += 1;
+                    self.end_line(byte)?;
+                }
+                Some(_) => break,
+            }
+        }
+
+        self.record_line = self.cursor_line;
+        let CsvOptions {
+            delimiter, quote, ..
+        } = self.options;
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        loop {
+            if self.peek()? == Some(quote) && field.is_empty() {
+                self.pos += 1;
+                self.parse_quoted(&mut field)?;
+            }
+            match self.bump()? {
+                Some(byte) if byte == delimiter => {
+                    fields.push(self.finish_field(&mut field)?);
+                }
+                Some(byte @ (b'\r' | b'\n')) => {
+                    self.end_line(byte)?;
+                    fields.push(self.finish_field(&mut field)?);
+                    return Ok(Some(fields));
+                }
+                None => {
+                    fields.push(self.finish_field(&mut field)?);
+                    return Ok(Some(fields));
+                }
+                Some(byte) if byte == quote => {
+                    return Err(self.parse_error("unexpected quote in unquoted field"));
+                }
+                Some(byte) => field.push(byte),
+            }
+        }
+    }
+
+    /// Read a quoted field's contents up to its closing quote.
+    fn parse_quoted(&mut self, field: &mut Vec<u8>) -> Result<(), CsvError> {
+        let quote = self.options.quote;
+        loop {
+            match self.bump()? {
+                None => return Err(self.parse_error("unterminated quoted field")),
+                Some(byte) if byte == quote => {
+                    if self.peek()? == Some(quote) {
+                        self.pos += 1;
+                        field.push(quote);
+                        continue;
+                    }
+                    return match self.peek()? {
+                        None | Some(b'\r' | b'\n') => Ok(()),
+                        Some(byte) if byte == self.options.delimiter => Ok(()),
+                        Some(_) => Err(self.parse_error("unexpected data after closing quote")),
+                    };
+                }
+                Some(b'\n') => {
+                    self.cursor_line += 1;
+                    field.push(b'\n');
+                }
+                Some(byte) => field.push(byte),
+            }
+        }
+    }
+
+    fn finish_field(&self, field: &mut Vec<u8>) -> Result<String, CsvError> {
+        String::from_utf8(std::mem::take(field)).map_err(|_| self.parse_error("invalid UTF-8"))
+    }
+
+    fn parse_error(&self, message: &str) -> CsvError {
+        CsvError::Parse {
+            line: self.cursor_line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl<S: InputStream> CsvRead for CsvReader<S> {
+    fn headers(&mut self) -> Result<Option<&[String]>, CsvError> {
+        self.ensure_headers()?;
+        Ok(self.headers.as_deref())
+    }
+
+    fn read_record(&mut self) -> Result<Option<Vec<String>>, CsvError> {
+        self.ensure_headers()?;
+        self.next_record()
+    }
+}
@@ -0,0 +1,131 @@
+//! Streaming record writer.
+
+use crate::ser::RecordSerializer;
+use portals_csv::{CsvError, CsvOptions, CsvWrite};
+use portals_io::OutputStream;
+use serde::Serialize;
+
+const BUFFER_SIZE: usize = 8192;
+
+/// Writes CSV records to an [`OutputStream`].
+///
+/// Fields are quoted only when they contain the delimiter, the quote
+/// character, or a line break. Output is buffered; call
+/// [`flush`](CsvWrite::flush) when done.
+///
+/// ```ignore
+/// let mut writer = CsvWriter::new(stream);
+/// for row in &rows {
+///     writer.serialize(row)?;
+/// }
+/// writer.flush()?;
+/// ```
+pub struct CsvWriter<S> {
+    stream: S,
+    options: CsvOptions,
+    buf: Vec<u8>,
+    wrote_headers: bool,
+}
+
+impl<S: OutputStream> CsvWriter<S> {
+    /// Write comma-separated records.
+    pub fn new(stream: S) -> Self {
+        Self::with_options(stream, CsvOptions::default())
+    }
+
+    /// Write records in the given dialect.
+    pub fn with_options(stream: S, options: CsvOptions) -> Self {
+        Self {
+            stream,
+            options,
+            buf: Vec::with_capacity(BUFFER_SIZE),
+            wrote_headers: false,
+        }
+    }
+
+    /// Flush and return the stream.
+    pub fn into_inner(mut self) -> Result<S, CsvError> {
+        self.flush()?;
+        Ok(self.stream)
+    }
+
+    /// Write `value` as a record.
+    ///
+    /// Structs and maps write their field names as a header row before the
+    /// first record, if the dialect has headers. Tuples and sequences write
+    /// their elements in order. Fields must be scalars; `None` and unit are
+    /// written as empty fields, and unit enum variants by name.
+    pub fn serialize<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CsvError> {
+        let mut record = RecordSerializer::default();
+        value
+            .serialize(&mut record)
+            .map_err(|e| CsvError::Serialize(e.to_string()))?;
+
+        if !self.wrote_headers {
+            self.wrote_headers = true;
+            if self.options.has_headers && !record.headers.is_empty() {
+                self.write_record(&record.headers)?;
+            }
+        }
+        self.write_record(&record.fields)
+    }
+
+    fn write_field(&mut self, field: &str) {
+        let CsvOptions {
+            delimiter, quote, ..
+        } = self.options;
+        let needs_quotes = field
+            .bytes()
+            .any(|b| b == delimiter || b == quote || b == b'\r' || b == b'\n');
+        if !needs_quotes {
+            self.buf.extend_from_slice(field.as_bytes());
+            return;
+        }
+
+        self.buf.push(quote);
+        for &byte in field.as_bytes() {
+            if byte == quote {
+                self.buf.push(quote);
+            }
+            self.buf.push(byte);
+        }
+        self.buf.push(quote);
+    }
+}
+
+impl<S: OutputStream> CsvWrite for CsvWriter<S> {
+    fn write_record<F: AsRef<str>>(&mut self, fields: &[F]) -> Result<(), CsvError> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                self.buf.push(self.options.delimiter);
+            }
+            self.write_field(field.as_ref());
+        }
+        // A lone empty field would otherwise be written as a blank line,
+        // which readers skip.
+        if let [field] = fields
+            && field.as_ref().is_empty()
+        {
+            self.buf.extend_from_slice(&[self.options.quote; 2]);
+        }
+        if self.options.crlf {
+            self.buf.push(b'\r');
+        }
+        self.buf.push(b'\n');
+
+        if self.buf.len() >= BUFFER_SIZE {
+            self.stream.blocking_write(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), CsvError> {
+        if !self.buf.is_empty() {
+            self.stream.blocking_write(&self.buf)?;
+            self.buf.clear();
+        }
+        self.stream.blocking_flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+//! Static service discovery.
+//!
+//! For environments without multicast: services are listed up front, in
+//! code or in configuration, and browsing only ever finds those plus any
+//! announced through the same [`StaticDiscovery`].
+
+use portals_config::Config;
+use portals_discovery::{Announcer, Browser, Error, Service};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+/// Discovery over a fixed list of services.
+///
+/// Clones share the list, so a service announced through one clone is
+/// found by browsing another. Announcing a service without addresses
+/// lists it at `127.0.0.1`, since the only hosts that can find it are in
+/// the same process.
+#[derive(Debug, Clone, Default)]
+pub struct StaticDiscovery {
+    services: Arc<Mutex<Vec<Service>>>,
+}
+
+impl StaticDiscovery {
+    /// Create an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a list holding `services`.
+    pub fn from_services(services: impl IntoIterator<Item = Service>) -> Self {
+        Self {
+            services: Arc::new(Mutex::new(services.into_iter().collect())),
+        }
+    }
+
+    /// Read services from the keys under `prefix`, one group of keys per
+    /// instance:
+    ///
+    /// ```text
+    /// services.api.type = _http._tcp
+    /// services.api.port = 8080
+    /// services.api.addrs = 10.0.0.5, 10.0.0.6
+    /// services.api.txt.version = 2
+    /// ```
+    ///
+    /// `type`, `port`, and `addrs` are required; `txt.*` keys are optional.
+    /// Instance names can't contain `.`.
+    pub fn from_config(config: &impl Config, prefix: &str) -> Result<Self, Error> {
+        let mut fields: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for key in config.keys() {
+            let Some(rest) = key.strip_prefix(prefix).and_then(|k| k.strip_prefix('.')) else {
+                continue;
+            };
+            let Some((instance, field)) = rest.split_once('.') else {
+                return Err(invalid(&key, "expected <instance>.<field>"));
+            };
+            let value = config.get(&key).map_err(|e| Error::Other(e.to_string()))?;
+            fields
+                .entry(instance.to_string())
+                .or_default()
+                .insert(field.to_string(), value);
+        }
+
+        let mut services = Vec::new();
+        for (instance, mut fields) in fields {
+            let key = |field: &str| format!("{}.{}.{}", prefix, instance, field);
+            let Some(service_type) = fields.remove("type") else {
+                return Err(invalid(&key("type"), "missing"));
+            };
+            let port = fields
+                .remove("port")
+                .ok_or_else(|| invalid(&key("port"), "missing"))?
+                .trim()
+                .parse()
+                .map_err(|_| invalid(&key("port"), "not a port number"))?;
+            let addrs = fields
+                .remove("addrs")
+                .ok_or_else(|| invalid(&key("addrs"), "missing"))?
+                .split(',')
+                .map(|addr| addr.trim().parse::<IpAddr>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid(&key("addrs"), "not a list of IP addresses"))?;
+            let mut txt = BTreeMap::new();
+            for (field, value) in fields {
+                match field.strip_prefix("txt.") {
+                    Some(name) => txt.insert(name.to_string(), value),
+                    None => return Err(invalid(&key(&field), "unknown field")),
+                };
+            }
+            services.push(Service {
+                instance,
+                service_type: service_type.trim().to_string(),
+                port,
+                addrs,
+                txt,
+            });
+        }
+        Ok(Self::from_services(services))
+    }
+}
+
+fn invalid(key: &str, problem: &str) -> Error {
+    Error::Other(format!("{}: {}", key, problem))
+}
+
+impl Announcer for StaticDiscovery {
+    async fn announce(&self, service: &Service) -> Result<(), Error> {
+        let mut service = service.clone();
+        if service.addrs.is_empty() {
+            service.addrs.push(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        let mut services = self.services.lock().unwrap();
+        services
+            .retain(|s| s.instance != service.instance || s.service_type != service.service_type);
+        services.push(service);
+        Ok(())
+    }
+
+    async fn withdraw(&self, instance: &str, service_type: &str) -> Result<(), Error> {
+        let mut services = self.services.lock().unwrap();
+        services.retain(|s| s.instance != instance || s.service_type != service_type);
+        Ok(())
+    }
+}
+
+impl Browser for StaticDiscovery {
+    async fn browse(&self, service_type: &str) -> Result<Vec<Service>, Error> {
+        let services = self.services.lock().unwrap();
+        Ok(services
+            .iter()
+            .filter(|s| s.service_type == service_type)
+            .cloned()
+            .collect())
+    }
+
+    async fn resolve(&self, instance: &str, service_type: &str) -> Result<Vec<SocketAddr>, Error> {
+        let services = self.services.lock().unwrap();
+        services
+            .iter()
+            .find(|s| s.instance == instance && s.service_type == service_type)
+            .map(Service::socket_addrs)
+            .ok_or(Error::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_config_native::MemoryConfig;
+
+    fn config(pairs: &[(&str, &str)]) -> MemoryConfig {
+        MemoryConfig::from_pairs(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+    }
+
+    #[tokio::test]
+    async fn reads_services_from_config() {
+        let config = config(&[
+            ("services.api.type", "_http._tcp"),
+            ("services.api.port", "8080"),
+            ("services.api.addrs", "10.0.0.5, 10.0.0.6"),
+            ("services.api.txt.version", "2"),
+            ("services.db.type", "_postgresql._tcp"),
+            ("services.db.port", "5432"),
+            ("services.db.addrs", "::1"),
+            ("other.key", "ignored"),
+        ]);
+        let discovery = StaticDiscovery::from_config(&config, "services").unwrap();
+
+        let found = discovery.browse("_http._tcp").await.unwrap();
+        assert_eq!(
+            found,
+            [Service::new("api", "_http._tcp", 8080)
+                .with_addr("10.0.0.5".parse().unwrap())
+                .with_addr("10.0.0.6".parse().unwrap())
+                .with_txt("version", "2")]
+        );
+        assert_eq!(
+            discovery.resolve("db", "_postgresql._tcp").await.unwrap(),
+            ["[::1]:5432".parse().unwrap()]
+        );
+        assert!(matches!(
+            discovery.resolve("db", "_http._tcp").await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn rejects_incomplete_config() {
+        let missing_port = config(&[
+            ("services.api.type", "_http._tcp"),
+            ("services.api.addrs", "10.0.0.5"),
+        ]);
+        let err = StaticDiscovery::from_config(&missing_port, "services").unwrap_err();
+        assert_eq!(err.to_string(), "services.api.port: missing");
+
+        let bad_addr = config(&[
+            ("services.api.type", "_http._tcp"),
+            ("services.api.port", "80"),
+            ("services.api.addrs", "example.com"),
+        ]);
+        assert!(StaticDiscovery::from_config(&bad_addr, "services").is_err());
+    }
+
+    #[tokio::test]
+    async fn announces_and_withdraws() {
+        let discovery = StaticDiscovery::new();
+        let browser = discovery.clone();
+
+        let service = Service::new("api", "_http._tcp", 8080);
+        discovery.announce(&service).await.unwrap();
+        discovery
+            .announce(&service.clone().with_txt("v", "2"))
+            .await
+            .unwrap();
+
+        let found = browser.browse("_http._tcp").await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].txt["v"], "2");
+        assert_eq!(
+            browser.resolve("api", "_http._tcp").await.unwrap(),
+            ["127.0.0.1:8080".parse().unwrap()]
+        );
+
+        discovery.withdraw("api", "_http._tcp").await.unwrap();
+        assert!(browser.browse("_http._tcp").await.unwrap().is_empty());
+    }
+}
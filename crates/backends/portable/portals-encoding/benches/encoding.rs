@@ -0,0 +1,47 @@
+//! Encoding benchmarks.
+//!
+//! Run with `cargo bench -p portals-encoding-portable`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use portals_encoding::{Base64, Hex, UrlEncoding};
+use portals_encoding_portable::{StdBase64, StdHex, StdUrlEncoding};
+use std::hint::black_box;
+
+fn base64(c: &mut Criterion) {
+    let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    let encoded = StdBase64::encode(&data);
+
+    c.bench_function("base64 encode (4 KiB)", |b| {
+        b.iter(|| StdBase64::encode(black_box(&data)))
+    });
+    c.bench_function("base64 decode (4 KiB)", |b| {
+        b.iter(|| StdBase64::decode(black_box(&encoded)).unwrap())
+    });
+}
+
+fn hex(c: &mut Criterion) {
+    let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+    let encoded = StdHex::encode(&data);
+
+    c.bench_function("hex encode (4 KiB)", |b| {
+        b.iter(|| StdHex::encode(black_box(&data)))
+    });
+    c.bench_function("hex decode (4 KiB)", |b| {
+        b.iter(|| StdHex::decode(black_box(&encoded)).unwrap())
+    });
+}
+
+fn url(c: &mut Criterion) {
+    let text = "key=some value&other=ünïcödé/path?x=1".repeat(32);
+    let encoded = StdUrlEncoding::encode(&text);
+
+    c.bench_function("url encode", |b| {
+        b.iter(|| StdUrlEncoding::encode(black_box(&text)))
+    });
+    c.bench_function("url decode", |b| {
+        b.iter(|| StdUrlEncoding::decode(black_box(&encoded)).unwrap())
+    });
+}
+
+criterion_group!(benches, base64, hex, url);
+criterion_main!(benches);
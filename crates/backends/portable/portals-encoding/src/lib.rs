@@ -43,15 +43,23 @@ pub struct StdHex;
 
 impl Hex for StdHex {
     fn encode(data: &[u8]) -> String {
-        data.iter().map(|b| format!("{:02x}", b)).collect()
+        let mut result = String::with_capacity(data.len() * 2);
+        for &b in data {
+            push_hex(&mut result, b, HEX_LOWER);
+        }
+        result
     }
 
     fn encode_upper(data: &[u8]) -> String {
-        data.iter().map(|b| format!("{:02X}", b)).collect()
+        let mut result = String::with_capacity(data.len() * 2);
+        for &b in data {
+            push_hex(&mut result, b, HEX_UPPER);
+        }
+        result
     }
 
     fn decode(encoded: &str) -> Result<Vec<u8>, DecodeError> {
-        if encoded.len() % 2 != 0 {
+        if !encoded.len().is_multiple_of(2) {
             return Err(DecodeError::InvalidLength);
         }
 
@@ -72,6 +80,14 @@ impl Hex for StdHex {
     }
 }
 
+const HEX_LOWER: &[u8; 16] = b"0123456789abcdef";
+const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+fn push_hex(out: &mut String, b: u8, digits: &[u8; 16]) {
+    out.push(digits[(b >> 4) as usize] as char);
+    out.push(digits[(b & 0x0f) as usize] as char);
+}
+
 /// URL percent encoding.
 pub struct StdUrlEncoding;
 
@@ -84,8 +100,10 @@ impl UrlEncoding for StdUrlEncoding {
                     result.push(c);
                 }
                 _ => {
-                    for b in c.to_string().as_bytes() {
-                        result.push_str(&format!("%{:02X}", b));
+                    let mut buf = [0u8; 4];
+                    for &b in c.encode_utf8(&mut buf).as_bytes() {
+                        result.push('%');
+                        push_hex(&mut result, b, HEX_UPPER);
                     }
                 }
             }
@@ -159,5 +177,8 @@ mod tests {
         assert_eq!(encoded, "hello%20world%21");
         let decoded = StdUrlEncoding::decode(&encoded).unwrap();
         assert_eq!(decoded, input);
+
+        assert_eq!(StdUrlEncoding::encode("café"), "caf%C3%A9");
+        assert_eq!(StdUrlEncoding::decode("caf%C3%A9").unwrap(), "café");
     }
 }
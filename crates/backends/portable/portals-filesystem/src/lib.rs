@@ -0,0 +1,12 @@
+//! Portable decorators for [`portals_filesystem`] directories.
+//!
+//! These wrap any [`Directory`](portals_filesystem::Directory) and add
+//! behavior around it without depending on a particular filesystem.
+
+mod overlay;
+mod quota;
+mod read_only;
+
+pub use overlay::OverlayDir;
+pub use quota::{QuotaDir, QuotaStream};
+pub use read_only::ReadOnlyDir;
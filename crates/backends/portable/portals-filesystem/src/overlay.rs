@@ -0,0 +1,469 @@
+//! Copy-on-write overlays.
+
+use portals_filesystem::{
+    DirEntry, Directory, Error, ErrorKind, FileType, InputStream, Metadata, OutputStream,
+    PithError, Seek, SeekFrom, StreamError,
+};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// Size of each read when copying a file up from the base.
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A writable directory layered over a base that is never modified.
+///
+/// Reads see the upper layer where it has a path and the base elsewhere.
+/// Writes go to the upper layer: a base file is copied up the first time it
+/// is appended to, truncated, or has its times set, and parent directories
+/// are created in the upper layer as needed. Removing or renaming away a
+/// base path hides it and everything under it for the life of the overlay;
+/// [`removed`](Self::removed) lists what has been hidden.
+///
+/// Together, the upper layer and the removed paths describe every change,
+/// so a tool can run against an overlay as a dry run and report or discard
+/// the result, or apply it to the base afterwards.
+///
+/// ```ignore
+/// let base = ReadOnlyDir::new(NativeDir::new(project));
+/// let preview = OverlayDir::new(base, NativeDir::new(scratch));
+/// formatter.run(&preview)?;
+/// for path in preview.removed() {
+///     println!("would delete {}", path.display());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct OverlayDir<B, U> {
+    base: B,
+    upper: U,
+    removed: Mutex<BTreeSet<PathBuf>>,
+}
+
+/// Where a path was found.
+enum Layer {
+    Upper(Metadata),
+    Base(Metadata),
+}
+
+impl Layer {
+    fn metadata(self) -> Metadata {
+        match self {
+            Layer::Upper(meta) | Layer::Base(meta) => meta,
+        }
+    }
+}
+
+impl<B: Directory, U: Directory> OverlayDir<B, U> {
+    /// Layer `upper` over `base`.
+    ///
+    /// `upper` should start empty; anything already in it shadows the base.
+    pub fn new(base: B, upper: U) -> Self {
+        Self {
+            base,
+            upper,
+            removed: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// The base directory.
+    pub fn base(&self) -> &B {
+        &self.base
+    }
+
+    /// The upper directory, holding everything written through the overlay.
+    pub fn upper(&self) -> &U {
+        &self.upper
+    }
+
+    /// Base paths hidden by removals and renames, in sorted order.
+    ///
+    /// Paths under a hidden directory are hidden too but not listed.
+    pub fn removed(&self) -> Vec<PathBuf> {
+        self.removed_set().iter().cloned().collect()
+    }
+
+    fn removed_set(&self) -> std::sync::MutexGuard<'_, BTreeSet<PathBuf>> {
+        self.removed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether `path` in the base is hidden by a removal of it or a parent.
+    fn hidden(&self, path: &Path) -> bool {
+        let removed = self.removed_set();
+        path.ancestors().any(|p| removed.contains(p))
+    }
+
+    /// Hide `path` in the base, if the base has it.
+    fn hide(&self, path: &Path) -> Result<(), Error> {
+        if self.base_metadata(path)?.is_some() {
+            let mut removed = self.removed_set();
+            removed.retain(|p| !p.starts_with(path));
+            removed.insert(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn base_metadata(&self, path: &Path) -> Result<Option<Metadata>, Error> {
+        if self.hidden(path) {
+            return Ok(None);
+        }
+        found(self.base.metadata(path))
+    }
+
+    /// Find `path`, looking in the upper layer first.
+    fn lookup(&self, path: &Path) -> Result<Option<Layer>, Error> {
+        if let Some(meta) = found(self.upper.metadata(path))? {
+            return Ok(Some(Layer::Upper(meta)));
+        }
+        Ok(self.base_metadata(path)?.map(Layer::Base))
+    }
+
+    /// Create the parent directories of `path` in the upper layer.
+    fn create_parents(&self, path: &Path) -> Result<(), Error> {
+        let Some(parent) = path.parent() else {
+            return Ok(());
+        };
+        let mut ancestors: Vec<_> = parent
+            .ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        for dir in ancestors {
+            match self.lookup(dir)? {
+                Some(Layer::Upper(meta)) if meta.file_type == FileType::Directory => {}
+                Some(Layer::Base(meta)) if meta.file_type == FileType::Directory => {
+                    self.upper.create_dir(dir)?;
+                }
+                Some(_) => return Err(Error::NotDirectory),
+                None => return Err(Error::NotFound),
+            }
+        }
+        Ok(())
+    }
+
+    /// Make sure `path` is in the upper layer, copying it from the base if
+    /// needed. Directories are created empty; their contents stay below.
+    fn copy_up(&self, path: &Path) -> Result<(), Error> {
+        let meta = match self.lookup(path)? {
+            Some(Layer::Upper(_)) => return Ok(()),
+            Some(Layer::Base(meta)) => meta,
+            None => return Err(Error::NotFound),
+        };
+        self.create_parents(path)?;
+        if meta.file_type == FileType::Directory {
+            self.upper.create_dir(path)?;
+        } else {
+            copy_file(&self.base, &self.upper, path)?;
+        }
+        self.upper.set_times(path, meta.accessed, meta.modified)
+    }
+
+    /// Copy `path` and everything under it into the upper layer.
+    fn copy_up_all(&self, path: &Path) -> Result<(), Error> {
+        self.copy_up(path)?;
+        if self.upper.metadata(path)?.file_type == FileType::Directory {
+            for entry in self.read_dir(path)? {
+                self.copy_up_all(&path.join(entry?.name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B: Directory, U: Directory> Directory for OverlayDir<B, U> {
+    fn open_read(&self, path: &Path) -> Result<impl InputStream + Seek, Error> {
+        match self.lookup(path)? {
+            Some(Layer::Upper(_)) => Ok(LayerStream::Upper(self.upper.open_read(path)?)),
+            Some(Layer::Base(_)) => Ok(LayerStream::Base(self.base.open_read(path)?)),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn open_write(&self, path: &Path) -> Result<impl OutputStream + Seek, Error> {
+        if let Some(Layer::Base(meta)) = self.lookup(path)?
+            && meta.file_type == FileType::Directory
+        {
+            return Err(Error::IsDirectory);
+        }
+        self.create_parents(path)?;
+        self.upper.open_write(path)
+    }
+
+    fn open_append(&self, path: &Path) -> Result<impl OutputStream, Error> {
+        match self.copy_up(path) {
+            Err(Error::NotFound) => self.create_parents(path)?,
+            result => result?,
+        }
+        self.upper.open_append(path)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, Error> {
+        self.lookup(path)?
+            .map(Layer::metadata)
+            .ok_or(Error::NotFound)
+    }
+
+    fn read_dir(
+        &self,
+        path: &Path,
+    ) -> Result<impl Iterator<Item = Result<DirEntry, Error>>, Error> {
+        let upper = found(self.upper.metadata(path))?;
+        let base = self.base_metadata(path)?;
+        if upper.is_none() && base.is_none() {
+            return Err(Error::NotFound);
+        }
+
+        let mut entries = Vec::new();
+        let mut names = HashSet::new();
+        if upper.is_some() {
+            for entry in self.upper.read_dir(path)? {
+                let entry = entry?;
+                names.insert(entry.name.clone());
+                entries.push(Ok(entry));
+            }
+        }
+        let upper_is_dir = upper.is_none_or(|meta| meta.file_type == FileType::Directory);
+        if base.is_some() && upper_is_dir {
+            for entry in self.base.read_dir(path)? {
+                let entry = entry?;
+                if !names.contains(&entry.name) && !self.hidden(&path.join(&entry.name)) {
+                    entries.push(Ok(entry));
+                }
+            }
+        }
+        Ok(entries.into_iter())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), Error> {
+        if self.lookup(path)?.is_some() {
+            return Err(Error::Exist);
+        }
+        self.create_parents(path)?;
+        self.upper.create_dir(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Error> {
+        match self.lookup(path)? {
+            Some(Layer::Upper(_)) => self.upper.remove_file(path)?,
+            Some(Layer::Base(meta)) if meta.file_type == FileType::Directory => {
+                return Err(Error::IsDirectory);
+            }
+            Some(Layer::Base(_)) => {}
+            None => return Err(Error::NotFound),
+        }
+        self.hide(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), Error> {
+        let layer = self.lookup(path)?.ok_or(Error::NotFound)?;
+        let in_upper = matches!(layer, Layer::Upper(_));
+        if layer.metadata().file_type != FileType::Directory {
+            return Err(Error::NotDirectory);
+        }
+        if self.read_dir(path)?.next().is_some() {
+            return Err(Error::Io(std::io::ErrorKind::DirectoryNotEmpty.into()));
+        }
+        if in_upper {
+            self.upper.remove_dir(path)?;
+        }
+        self.hide(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        self.copy_up_all(from)?;
+        self.create_parents(to)?;
+        self.upper.rename(from, to)?;
+        // The destination is replaced outright, so nothing of a base entry
+        // there may show through.
+        self.hide(to)?;
+        self.hide(from)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<u64>,
+        modified: Option<u64>,
+    ) -> Result<(), Error> {
+        self.copy_up(path)?;
+        self.upper.set_times(path, accessed, modified)
+    }
+
+    fn truncate(&self, path: &Path, len: u64) -> Result<(), Error> {
+        self.copy_up(path)?;
+        self.upper.truncate(path, len)
+    }
+
+    fn allocate(&self, path: &Path, len: u64) -> Result<(), Error> {
+        self.copy_up(path)?;
+        self.upper.allocate(path, len)
+    }
+}
+
+/// `Ok(None)` for a not-found error, so lookups can fall through.
+fn found(result: Result<Metadata, Error>) -> Result<Option<Metadata>, Error> {
+    match result {
+        Ok(meta) => Ok(Some(meta)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_file(from: &impl Directory, to: &impl Directory, path: &Path) -> Result<(), Error> {
+    let stream_error = |e: StreamError| Error::Other(e.to_string());
+    let mut input = from.open_read(path)?;
+    let mut output = to.open_write(path)?;
+    let mut buf = vec![0; COPY_CHUNK_SIZE];
+    loop {
+        let n = match input.blocking_read_into(&mut buf) {
+            Ok(0) | Err(StreamError::Closed) => break,
+            Ok(n) => n,
+            Err(e) => return Err(stream_error(e)),
+        };
+        output.blocking_write(&buf[..n]).map_err(stream_error)?;
+    }
+    output.blocking_flush().map_err(stream_error)
+}
+
+/// A file opened from either layer.
+enum LayerStream<U, B> {
+    Upper(U),
+    Base(B),
+}
+
+impl<U: InputStream, B: InputStream> InputStream for LayerStream<U, B> {
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        match self {
+            Self::Upper(s) => s.read_into(buf),
+            Self::Base(s) => s.read_into(buf),
+        }
+    }
+
+    fn blocking_read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        match self {
+            Self::Upper(s) => s.blocking_read_into(buf),
+            Self::Base(s) => s.blocking_read_into(buf),
+        }
+    }
+
+    async fn subscribe(&self) {
+        match self {
+            Self::Upper(s) => s.subscribe().await,
+            Self::Base(s) => s.subscribe().await,
+        }
+    }
+}
+
+impl<U: Seek, B: Seek> Seek for LayerStream<U, B> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, StreamError> {
+        match self {
+            Self::Upper(s) => s.seek(pos),
+            Self::Base(s) => s.seek(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReadOnlyDir;
+    use portals_filesystem_native::NativeDir;
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("portals-overlay-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(dir: &impl Directory, path: &str) -> Vec<u8> {
+        let mut file = dir.open_read(Path::new(path)).unwrap();
+        file.blocking_read(1024).unwrap()
+    }
+
+    fn names(dir: &impl Directory, path: &str) -> Vec<String> {
+        let mut names: Vec<_> = dir
+            .read_dir(Path::new(path))
+            .unwrap()
+            .map(|entry| entry.unwrap().name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn leaves_base_untouched() {
+        let root = temp_dir("cow");
+        let (base, upper) = (root.join("base"), root.join("upper"));
+        fs::create_dir_all(base.join("src/nested")).unwrap();
+        fs::create_dir(&upper).unwrap();
+        fs::write(base.join("README"), "readme").unwrap();
+        fs::write(base.join("src/lib.rs"), "lib").unwrap();
+        fs::write(base.join("src/nested/mod.rs"), "mod").unwrap();
+        let dir = OverlayDir::new(
+            ReadOnlyDir::new(NativeDir::new(&base)),
+            NativeDir::new(&upper),
+        );
+
+        assert_eq!(read(&dir, "src/lib.rs"), b"lib");
+        assert_eq!(names(&dir, ""), ["README", "src"]);
+
+        // Writes and appends land in the upper layer.
+        let mut file = dir.open_append(Path::new("src/lib.rs")).unwrap();
+        file.blocking_write(b"!").unwrap();
+        drop(file);
+        let mut file = dir.open_write(Path::new("src/new.rs")).unwrap();
+        file.blocking_write(b"new").unwrap();
+        drop(file);
+        assert_eq!(read(&dir, "src/lib.rs"), b"lib!");
+        assert_eq!(names(&dir, "src"), ["lib.rs", "nested", "new.rs"]);
+        dir.truncate(Path::new("README"), 4).unwrap();
+        assert_eq!(dir.metadata(Path::new("README")).unwrap().size, 4);
+
+        // Removals and renames hide base paths.
+        dir.remove_file(Path::new("README")).unwrap();
+        assert_eq!(
+            dir.metadata(Path::new("README")).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        dir.rename(Path::new("src/nested"), Path::new("moved"))
+            .unwrap();
+        assert_eq!(read(&dir, "moved/mod.rs"), b"mod");
+        assert_eq!(names(&dir, "src"), ["lib.rs", "new.rs"]);
+        assert!(dir.remove_dir(Path::new("src")).is_err());
+
+        // A directory removed and created again starts empty.
+        dir.remove_file(Path::new("src/lib.rs")).unwrap();
+        dir.remove_file(Path::new("src/new.rs")).unwrap();
+        dir.remove_dir(Path::new("src")).unwrap();
+        dir.create_dir(Path::new("src")).unwrap();
+        assert!(names(&dir, "src").is_empty());
+        assert_eq!(
+            dir.removed(),
+            [PathBuf::from("README"), PathBuf::from("src")]
+        );
+
+        assert_eq!(fs::read(base.join("README")).unwrap(), b"readme");
+        assert_eq!(fs::read(base.join("src/lib.rs")).unwrap(), b"lib");
+        assert!(base.join("src/nested/mod.rs").exists());
+        assert!(!base.join("src/new.rs").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn conformance() {
+        let root = temp_dir("conformance");
+        let next = std::cell::Cell::new(0);
+        portals_conformance::filesystem::run(|| {
+            let dir = root.join(next.replace(next.get() + 1).to_string());
+            fs::create_dir_all(dir.join("base")).unwrap();
+            fs::create_dir_all(dir.join("upper")).unwrap();
+            OverlayDir::new(
+                ReadOnlyDir::new(NativeDir::new(dir.join("base"))),
+                NativeDir::new(dir.join("upper")),
+            )
+        });
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
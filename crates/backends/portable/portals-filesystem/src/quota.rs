@@ -0,0 +1,402 @@
+//! Byte quotas.
+
+use portals_filesystem::{
+    DirEntry, Directory, Error, FileType, InputStream, Metadata, OutputStream, Seek, SeekFrom,
+    StreamError, Usage,
+};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Bytes in use against a limit, shared by a directory and its streams.
+#[derive(Debug)]
+struct Budget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl Budget {
+    fn reserve(&self, bytes: u64) -> Result<(), Error> {
+        if bytes == 0 {
+            return Ok(());
+        }
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                used.checked_add(bytes).filter(|&total| total <= self.limit)
+            })
+            .map(|_| ())
+            .map_err(|used| Error::QuotaExceeded {
+                limit: self.limit,
+                requested: used.saturating_add(bytes),
+            })
+    }
+
+    fn release(&self, bytes: u64) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used.load(Ordering::SeqCst))
+    }
+}
+
+/// A directory that rejects writes past a byte budget.
+///
+/// Usage is measured once with [`Directory::usage`] when the wrapper is
+/// created, then kept up to date as files are written, truncated, and
+/// removed through it. Operations that would grow usage past the limit fail
+/// with [`Error::QuotaExceeded`]; stream writes fail with a
+/// [`StreamError::Other`] carrying the same message, and
+/// [`check_write`](OutputStream::check_write) reports what the quota allows.
+///
+/// Changes made to the directory by other means are not seen until
+/// [`recalculate`](Self::recalculate). Each stream tracks the length of its
+/// own file, so concurrent writers to the same file may be over-counted.
+///
+/// ```ignore
+/// let tenant = QuotaDir::new(NativeDir::new(root.join(tenant_id)), 100 << 20)?;
+/// let mut file = tenant.open_write(Path::new("upload.bin"))?;
+/// file.blocking_write(&data)?; // fails once the tenant has 100 MiB
+/// ```
+#[derive(Debug)]
+pub struct QuotaDir<D> {
+    inner: D,
+    budget: Arc<Budget>,
+}
+
+impl<D: Directory> QuotaDir<D> {
+    /// Wrap `inner`, allowing at most `limit` bytes of file contents in
+    /// total.
+    ///
+    /// Existing contents count against the limit; if they already exceed
+    /// it, nothing can grow until enough is removed.
+    pub fn new(inner: D, limit: u64) -> Result<Self, Error> {
+        let used = inner.usage(Path::new(""))?.bytes;
+        Ok(Self {
+            inner,
+            budget: Arc::new(Budget {
+                limit,
+                used: AtomicU64::new(used),
+            }),
+        })
+    }
+
+    /// The byte limit.
+    pub fn limit(&self) -> u64 {
+        self.budget.limit
+    }
+
+    /// Bytes currently counted against the limit.
+    pub fn used(&self) -> u64 {
+        self.budget.used.load(Ordering::SeqCst)
+    }
+
+    /// Bytes that can still be written.
+    pub fn remaining(&self) -> u64 {
+        self.budget.remaining()
+    }
+
+    /// Measure usage again, e.g. after the directory changed by other
+    /// means.
+    pub fn recalculate(&self) -> Result<u64, Error> {
+        let used = self.inner.usage(Path::new(""))?.bytes;
+        self.budget.used.store(used, Ordering::SeqCst);
+        Ok(used)
+    }
+
+    /// The wrapped directory.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Unwrap the directory.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Size of the file at `path`, or 0 if there is none.
+    fn file_size(&self, path: &Path) -> u64 {
+        match self.inner.metadata(path) {
+            Ok(meta) if meta.file_type == FileType::Regular => meta.size,
+            _ => 0,
+        }
+    }
+
+    /// Resize the file at `path` with `resize`, reserving any growth first.
+    fn resize(
+        &self,
+        path: &Path,
+        len: u64,
+        resize: impl FnOnce(&D) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let size = self.inner.metadata(path)?.size;
+        let growth = len.saturating_sub(size);
+        self.budget.reserve(growth)?;
+        match resize(&self.inner) {
+            Ok(()) => {
+                self.budget
+                    .release(size.saturating_sub(self.file_size(path).max(len)));
+                Ok(())
+            }
+            Err(e) => {
+                self.budget.release(growth);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<D: Directory> Directory for QuotaDir<D> {
+    fn open_read(&self, path: &Path) -> Result<impl InputStream + Seek, Error> {
+        self.inner.open_read(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<impl OutputStream + Seek, Error> {
+        let truncated = self.file_size(path);
+        let stream = self.inner.open_write(path)?;
+        self.budget.release(truncated);
+        Ok(QuotaStream::new(stream, self.budget.clone(), 0))
+    }
+
+    fn open_append(&self, path: &Path) -> Result<impl OutputStream, Error> {
+        let len = self.file_size(path);
+        let stream = self.inner.open_append(path)?;
+        Ok(QuotaStream::new(stream, self.budget.clone(), len))
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, Error> {
+        self.inner.metadata(path)
+    }
+
+    fn read_dir(
+        &self,
+        path: &Path,
+    ) -> Result<impl Iterator<Item = Result<DirEntry, Error>>, Error> {
+        self.inner.read_dir(path)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), Error> {
+        self.inner.create_dir(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Error> {
+        let size = self.file_size(path);
+        self.inner.remove_file(path)?;
+        self.budget.release(size);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), Error> {
+        self.inner.remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), Error> {
+        // Renaming over a file frees the file it replaces.
+        let replaced = self.file_size(to);
+        self.inner.rename(from, to)?;
+        self.budget.release(replaced);
+        Ok(())
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        accessed: Option<u64>,
+        modified: Option<u64>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(path, accessed, modified)
+    }
+
+    fn truncate(&self, path: &Path, len: u64) -> Result<(), Error> {
+        self.resize(path, len, |inner| inner.truncate(path, len))
+    }
+
+    fn allocate(&self, path: &Path, len: u64) -> Result<(), Error> {
+        self.resize(path, len, |inner| inner.allocate(path, len))
+    }
+
+    fn usage(&self, path: &Path) -> Result<Usage, Error> {
+        self.inner.usage(path)
+    }
+}
+
+/// An output stream from a [`QuotaDir`], counting growth of its file
+/// against the quota.
+pub struct QuotaStream<S> {
+    inner: S,
+    budget: Arc<Budget>,
+    pos: u64,
+    len: u64,
+}
+
+impl<S> QuotaStream<S> {
+    fn new(inner: S, budget: Arc<Budget>, len: u64) -> Self {
+        Self {
+            inner,
+            budget,
+            pos: len,
+            len,
+        }
+    }
+
+    /// Reserve the growth a write of `n` bytes at the current position
+    /// needs.
+    fn reserve(&self, n: usize) -> Result<u64, StreamError> {
+        let growth = (self.pos + n as u64).saturating_sub(self.len);
+        self.budget
+            .reserve(growth)
+            .map_err(|e| StreamError::Other(e.to_string()))?;
+        Ok(growth)
+    }
+
+    fn wrote(
+        &mut self,
+        n: usize,
+        growth: u64,
+        result: Result<(), StreamError>,
+    ) -> Result<(), StreamError> {
+        match result {
+            Ok(()) => {
+                self.pos += n as u64;
+                self.len = self.len.max(self.pos);
+                Ok(())
+            }
+            Err(e) => {
+                self.budget.release(growth);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<S: OutputStream> OutputStream for QuotaStream<S> {
+    fn check_write(&self) -> Result<usize, StreamError> {
+        let overwrite = self.len.saturating_sub(self.pos);
+        let allowed = self.budget.remaining().saturating_add(overwrite);
+        Ok(self
+            .inner
+            .check_write()?
+            .min(allowed.try_into().unwrap_or(usize::MAX)))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), StreamError> {
+        let growth = self.reserve(bytes.len())?;
+        let result = self.inner.write(bytes);
+        self.wrote(bytes.len(), growth, result)
+    }
+
+    fn blocking_write(&mut self, bytes: &[u8]) -> Result<(), StreamError> {
+        let growth = self.reserve(bytes.len())?;
+        let result = self.inner.blocking_write(bytes);
+        self.wrote(bytes.len(), growth, result)
+    }
+
+    fn flush(&mut self) -> Result<(), StreamError> {
+        self.inner.flush()
+    }
+
+    fn blocking_flush(&mut self) -> Result<(), StreamError> {
+        self.inner.blocking_flush()
+    }
+
+    fn subscribe(&self) -> impl Future<Output = ()> {
+        self.inner.subscribe()
+    }
+}
+
+impl<S: Seek> Seek for QuotaStream<S> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, StreamError> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_filesystem::PithError;
+    use portals_filesystem_native::NativeDir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("portals-quota-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn enforces_limit() {
+        let root = temp_dir("limit");
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/existing"), [0; 40]).unwrap();
+        let dir = QuotaDir::new(NativeDir::new(&root), 100).unwrap();
+        assert_eq!(dir.used(), 40);
+        assert_eq!(
+            dir.usage(Path::new("")).unwrap(),
+            Usage {
+                bytes: 40,
+                files: 1,
+                directories: 1
+            }
+        );
+
+        let mut file = dir.open_write(Path::new("a")).unwrap();
+        assert_eq!(file.check_write().unwrap(), 60);
+        file.blocking_write(&[1; 50]).unwrap();
+        let err = file.blocking_write(&[1; 11]).unwrap_err();
+        assert!(err.to_string().contains("quota exceeded"), "{}", err);
+        // Overwriting doesn't grow the file.
+        file.rewind().unwrap();
+        file.blocking_write(&[2; 50]).unwrap();
+        file.blocking_write(&[2; 10]).unwrap();
+        file.blocking_flush().unwrap();
+        drop(file);
+        assert_eq!(dir.used(), 100);
+
+        let err = dir.truncate(Path::new("a"), 61).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::QuotaExceeded {
+                limit: 100,
+                requested: 101
+            }
+        ));
+        assert_eq!(err.kind(), portals_filesystem::ErrorKind::Unavailable);
+        dir.truncate(Path::new("a"), 20).unwrap();
+        assert_eq!(dir.used(), 60);
+        dir.allocate(Path::new("a"), 60).unwrap();
+        assert_eq!(dir.used(), 100);
+
+        // Rewriting, renaming over, and removing files frees their bytes.
+        drop(dir.open_write(Path::new("a")).unwrap());
+        assert_eq!(dir.used(), 40);
+        let mut file = dir.open_append(Path::new("sub/existing")).unwrap();
+        file.blocking_write(&[3; 10]).unwrap();
+        drop(file);
+        assert_eq!(dir.used(), 50);
+        dir.rename(Path::new("a"), Path::new("sub/existing"))
+            .unwrap();
+        assert_eq!(dir.used(), 0);
+        assert_eq!(dir.recalculate().unwrap(), 0);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn conformance() {
+        let root = temp_dir("conformance");
+        let next = std::cell::Cell::new(0);
+        portals_conformance::filesystem::run(|| {
+            let dir = root.join(next.replace(next.get() + 1).to_string());
+            fs::create_dir_all(&dir).unwrap();
+            QuotaDir::new(NativeDir::new(&dir), u64::MAX).unwrap()
+        });
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
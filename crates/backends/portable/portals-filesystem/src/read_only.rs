@@ -0,0 +1,177 @@
+//! Read-only views.
+
+use portals_filesystem::{
+    DirEntry, Directory, Error, InputStream, Metadata, OutputStream, Seek, SeekFrom, StreamError,
+    Usage,
+};
+use std::path::Path;
+
+/// A directory that can be read but not changed.
+///
+/// Reads pass through to the wrapped directory; every operation that would
+/// modify it fails with [`Error::Access`] without reaching it. Useful for
+/// handing a tool a capability it cannot write through, or as the base of
+/// an [`OverlayDir`](crate::OverlayDir).
+#[derive(Debug, Clone)]
+pub struct ReadOnlyDir<D> {
+    inner: D,
+}
+
+impl<D: Directory> ReadOnlyDir<D> {
+    /// Wrap `inner`.
+    pub fn new(inner: D) -> Self {
+        Self { inner }
+    }
+
+    /// The wrapped directory.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// Unwrap the directory.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: Directory> Directory for ReadOnlyDir<D> {
+    fn open_read(&self, path: &Path) -> Result<impl InputStream + Seek, Error> {
+        self.inner.open_read(path)
+    }
+
+    fn open_write(&self, _path: &Path) -> Result<impl OutputStream + Seek, Error> {
+        Err::<Denied, _>(Error::Access)
+    }
+
+    fn open_append(&self, _path: &Path) -> Result<impl OutputStream, Error> {
+        Err::<Denied, _>(Error::Access)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, Error> {
+        self.inner.metadata(path)
+    }
+
+    fn read_dir(
+        &self,
+        path: &Path,
+    ) -> Result<impl Iterator<Item = Result<DirEntry, Error>>, Error> {
+        self.inner.read_dir(path)
+    }
+
+    fn create_dir(&self, _path: &Path) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn remove_file(&self, _path: &Path) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn remove_dir(&self, _path: &Path) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn set_times(
+        &self,
+        _path: &Path,
+        _accessed: Option<u64>,
+        _modified: Option<u64>,
+    ) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn truncate(&self, _path: &Path, _len: u64) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn allocate(&self, _path: &Path, _len: u64) -> Result<(), Error> {
+        Err(Error::Access)
+    }
+
+    fn usage(&self, path: &Path) -> Result<Usage, Error> {
+        self.inner.usage(path)
+    }
+}
+
+/// The stream type of writes that are always refused. It has no values.
+enum Denied {}
+
+impl OutputStream for Denied {
+    fn check_write(&self) -> Result<usize, StreamError> {
+        match *self {}
+    }
+
+    fn write(&mut self, _bytes: &[u8]) -> Result<(), StreamError> {
+        match *self {}
+    }
+
+    fn blocking_write(&mut self, _bytes: &[u8]) -> Result<(), StreamError> {
+        match *self {}
+    }
+
+    fn flush(&mut self) -> Result<(), StreamError> {
+        match *self {}
+    }
+
+    fn blocking_flush(&mut self) -> Result<(), StreamError> {
+        match *self {}
+    }
+
+    async fn subscribe(&self) {
+        match *self {}
+    }
+}
+
+impl Seek for Denied {
+    fn seek(&mut self, _pos: SeekFrom) -> Result<u64, StreamError> {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_filesystem::{ErrorKind, PithError};
+    use portals_filesystem_native::NativeDir;
+    use std::fs;
+
+    #[test]
+    fn refuses_writes() {
+        let root = std::env::temp_dir().join("portals-read-only-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "hello").unwrap();
+        let dir = ReadOnlyDir::new(NativeDir::new(&root));
+
+        assert_eq!(
+            dir.open_read(Path::new("a.txt"))
+                .unwrap()
+                .blocking_read(5)
+                .unwrap(),
+            b"hello"
+        );
+        assert_eq!(dir.metadata(Path::new("a.txt")).unwrap().size, 5);
+        assert_eq!(dir.read_dir(Path::new("")).unwrap().count(), 2);
+
+        let denied = [
+            dir.open_write(Path::new("a.txt")).err(),
+            dir.open_append(Path::new("a.txt")).err(),
+            dir.create_dir(Path::new("new")).err(),
+            dir.remove_file(Path::new("a.txt")).err(),
+            dir.remove_dir(Path::new("sub")).err(),
+            dir.rename(Path::new("a.txt"), Path::new("b.txt")).err(),
+            dir.set_times(Path::new("a.txt"), None, Some(0)).err(),
+            dir.truncate(Path::new("a.txt"), 0).err(),
+            dir.allocate(Path::new("a.txt"), 100).err(),
+        ];
+        for error in denied {
+            assert_eq!(error.map(|e| e.kind()), Some(ErrorKind::PermissionDenied));
+        }
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"hello");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+//! Human-readable formatting for byte sizes, durations, counts, and
+//! relative times.
+//!
+//! Output is stable: rounding and unit choice are fixed, so the same input
+//! always formats the same way regardless of platform. A [`Locale`] controls
+//! the digit grouping and decimal separators; unit names and relative-time
+//! phrases are English.
+//!
+//! ```ignore
+//! let fmt = Formatter::new();
+//! fmt.bytes(1536);                          // "1.5 KiB"
+//! fmt.duration(Duration::from_secs(7380));  // "2h 3m"
+//! fmt.count(1234567);                       // "1,234,567"
+//! fmt.relative(&clock, then);               // "3 minutes ago"
+//! ```
+
+use portals_clocks::WallClock;
+use std::time::Duration;
+
+/// Separators used when writing numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    /// Inserted between groups of three digits.
+    pub thousands: &'static str,
+    /// Written between the integer and fractional part.
+    pub decimal: &'static str,
+}
+
+impl Locale {
+    /// `1,234.5`
+    pub const EN: Locale = Locale {
+        thousands: ",",
+        decimal: ".",
+    };
+    /// `1.234,5`
+    pub const DE: Locale = Locale {
+        thousands: ".",
+        decimal: ",",
+    };
+    /// `1 234,5` (narrow no-break space)
+    pub const FR: Locale = Locale {
+        thousands: "\u{202f}",
+        decimal: ",",
+    };
+    /// `1234.5`, for logs and machine-adjacent output.
+    pub const PLAIN: Locale = Locale {
+        thousands: "",
+        decimal: ".",
+    };
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::EN
+    }
+}
+
+/// Unit system for byte sizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ByteUnits {
+    /// Powers of 1024: KiB, MiB, GiB, ...
+    #[default]
+    Binary,
+    /// Powers of 1000: kB, MB, GB, ...
+    Decimal,
+}
+
+impl ByteUnits {
+    fn base(self) -> u64 {
+        match self {
+            ByteUnits::Binary => 1024,
+            ByteUnits::Decimal => 1000,
+        }
+    }
+
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            ByteUnits::Binary => &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"],
+            ByteUnits::Decimal => &["B", "kB", "MB", "GB", "TB", "PB", "EB"],
+        }
+    }
+}
+
+/// Formats values for people to read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Formatter {
+    locale: Locale,
+    byte_units: ByteUnits,
+}
+
+impl Formatter {
+    /// English separators and binary byte units.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number separators.
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Set the unit system for [`bytes`](Self::bytes).
+    pub fn byte_units(mut self, units: ByteUnits) -> Self {
+        self.byte_units = units;
+        self
+    }
+
+    /// Format a byte size, e.g. `512 B`, `1.5 KiB`, `3.2 GB`.
+    ///
+    /// Sizes below one kilobyte are exact; larger sizes are rounded to one
+    /// decimal place, moving to the next unit when rounding reaches it.
+    pub fn bytes(&self, bytes: u64) -> String {
+        let base = self.byte_units.base();
+        let names = self.byte_units.names();
+        if bytes < base {
+            return format!("{} {}", bytes, names[0]);
+        }
+
+        let mut unit = 1;
+        let mut divisor = base as u128;
+        let mut tenths = round_div(bytes as u128 * 10, divisor);
+        while tenths >= base as u128 * 10 && unit + 1 < names.len() {
+            unit += 1;
+            divisor *= base as u128;
+            tenths = round_div(bytes as u128 * 10, divisor);
+        }
+        format!(
+            "{}{}{} {}",
+            self.group((tenths / 10) as u64),
+            self.locale.decimal,
+            tenths % 10,
+            names[unit]
+        )
+    }
+
+    /// Format a duration with its two largest units, e.g. `2h 3m`, `1d 4h`,
+    /// `45s`. Durations under a second are written in milliseconds.
+    ///
+    /// Smaller units are truncated, not rounded, so `59.9s` is `59s`.
+    pub fn duration(&self, duration: Duration) -> String {
+        let secs = duration.as_secs();
+        if secs == 0 {
+            return format!("{}ms", duration.subsec_millis());
+        }
+
+        let parts = [
+            (secs / 86_400, "d"),
+            (secs / 3_600 % 24, "h"),
+            (secs / 60 % 60, "m"),
+            (secs % 60, "s"),
+        ];
+        let first = parts.iter().position(|&(n, _)| n > 0).unwrap_or(3);
+        let mut out = format!("{}{}", self.group(parts[first].0), parts[first].1);
+        if let Some(&(n, unit)) = parts.get(first + 1)
+            && n > 0
+        {
+            out.push_str(&format!(" {}{}", n, unit));
+        }
+        out
+    }
+
+    /// Format a count with thousands separators, e.g. `1,234,567`.
+    pub fn count(&self, n: i64) -> String {
+        let digits = self.group(n.unsigned_abs());
+        if n < 0 {
+            format!("-{}", digits)
+        } else {
+            digits
+        }
+    }
+
+    /// Describe `then` (seconds since the Unix epoch) relative to the
+    /// clock's current time, e.g. `3 minutes ago` or `in 2 days`.
+    pub fn relative<C: WallClock + ?Sized>(&self, clock: &C, then: u64) -> String {
+        self.relative_to(then, clock.now().0)
+    }
+
+    /// Describe `then` relative to `now`, both in seconds since the Unix
+    /// epoch.
+    ///
+    /// Differences under a minute are `just now`. Larger differences use
+    /// the largest whole unit: minutes, hours, days, months (30 days), or
+    /// years (365 days).
+    pub fn relative_to(&self, then: u64, now: u64) -> String {
+        let (delta, past) = if then <= now {
+            (now - then, true)
+        } else {
+            (then - now, false)
+        };
+
+        let (n, unit) = match delta {
+            0..60 => return "just now".to_string(),
+            60..3_600 => (delta / 60, "minute"),
+            3_600..86_400 => (delta / 3_600, "hour"),
+            86_400..2_592_000 => (delta / 86_400, "day"),
+            2_592_000..31_536_000 => (delta / 2_592_000, "month"),
+            _ => (delta / 31_536_000, "year"),
+        };
+        let plural = if n == 1 { "" } else { "s" };
+        let amount = format!("{} {}{}", self.group(n), unit, plural);
+        if past {
+            format!("{} ago", amount)
+        } else {
+            format!("in {}", amount)
+        }
+    }
+
+    fn group(&self, n: u64) -> String {
+        let digits = n.to_string();
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                out.push_str(self.locale.thousands);
+            }
+            out.push(c);
+        }
+        out
+    }
+}
+
+fn round_div(n: u128, d: u128) -> u128 {
+    (n + d / 2) / d
+}
+
+/// Format a byte size with [`Formatter::new`]'s defaults.
+pub fn bytes(bytes: u64) -> String {
+    Formatter::new().bytes(bytes)
+}
+
+/// Format a duration with [`Formatter::new`]'s defaults.
+pub fn duration(duration: Duration) -> String {
+    Formatter::new().duration(duration)
+}
+
+/// Format a count with [`Formatter::new`]'s defaults.
+pub fn count(n: i64) -> String {
+    Formatter::new().count(n)
+}
+
+/// Describe a past or future time with [`Formatter::new`]'s defaults.
+pub fn relative<C: WallClock + ?Sized>(clock: &C, then: u64) -> String {
+    Formatter::new().relative(clock, then)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_clocks_mock::MockWallClock;
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(bytes(0), "0 B");
+        assert_eq!(bytes(1023), "1023 B");
+        assert_eq!(bytes(1024), "1.0 KiB");
+        assert_eq!(bytes(1536), "1.5 KiB");
+        assert_eq!(bytes(1024 * 1024 - 1), "1.0 MiB");
+        assert_eq!(bytes(u64::MAX), "16.0 EiB");
+
+        let decimal = Formatter::new().byte_units(ByteUnits::Decimal);
+        assert_eq!(decimal.bytes(999), "999 B");
+        assert_eq!(decimal.bytes(3_200_000_000), "3.2 GB");
+
+        let de = Formatter::new().locale(Locale::DE);
+        assert_eq!(de.bytes(1536), "1,5 KiB");
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(duration(Duration::ZERO), "0ms");
+        assert_eq!(duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(duration(Duration::from_millis(59_900)), "59s");
+        assert_eq!(duration(Duration::from_secs(7_380)), "2h 3m");
+        assert_eq!(duration(Duration::from_secs(7_205)), "2h");
+        assert_eq!(duration(Duration::from_secs(100_000)), "1d 3h");
+        assert_eq!(duration(Duration::from_secs(86_400 * 1_500)), "1,500d");
+    }
+
+    #[test]
+    fn formats_counts() {
+        assert_eq!(count(0), "0");
+        assert_eq!(count(999), "999");
+        assert_eq!(count(1_234_567), "1,234,567");
+        assert_eq!(count(-1_000), "-1,000");
+        assert_eq!(count(i64::MIN), "-9,223,372,036,854,775,808");
+        assert_eq!(
+            Formatter::new().locale(Locale::PLAIN).count(1_234_567),
+            "1234567"
+        );
+        assert_eq!(
+            Formatter::new().locale(Locale::FR).count(1_234_567),
+            "1\u{202f}234\u{202f}567"
+        );
+    }
+
+    #[test]
+    fn formats_relative_times() {
+        let clock = MockWallClock::new(1_000_000, 0);
+        assert_eq!(relative(&clock, 1_000_000), "just now");
+        assert_eq!(relative(&clock, 1_000_000 - 59), "just now");
+        assert_eq!(relative(&clock, 1_000_000 - 60), "1 minute ago");
+        assert_eq!(relative(&clock, 1_000_000 - 180), "3 minutes ago");
+        assert_eq!(relative(&clock, 1_000_000 + 2 * 86_400), "in 2 days");
+
+        let fmt = Formatter::new();
+        assert_eq!(fmt.relative_to(0, 7_200), "2 hours ago");
+        assert_eq!(fmt.relative_to(0, 40 * 86_400), "1 month ago");
+        assert_eq!(fmt.relative_to(0, 800 * 86_400), "2 years ago");
+    }
+}
@@ -0,0 +1,442 @@
+//! HTTP client built on portals-sockets and portals-http1.
+//!
+//! Needs nothing beyond a [`TcpConnect`] and a [`Resolver`], so it runs
+//! wherever a sockets backend does, with a much smaller dependency tree
+//! than a full HTTP stack. It speaks plain HTTP/1.1 only: there is no TLS,
+//! so `https` URLs are refused.
+
+mod url;
+
+use portals_http::{
+    Body, ChunkSource, ChunkStream, Error, HttpClient, Request, Response, StreamingHttpClient,
+    StreamingRequest, StreamingResponse,
+};
+use portals_http1::{Headers, Http1Connection, Limits, ResponseHead};
+use portals_io::InputStream;
+use portals_sockets::{Resolver, TcpConnect, TcpStream};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use url::Url;
+
+/// An HTTP/1.1 client over any sockets backend.
+///
+/// Each request is sent on a new connection, which is closed once the
+/// response is read. Host names are resolved with the given resolver and
+/// each address tried in turn until one accepts the connection. With
+/// [`StreamingHttpClient`], the response body is read off the connection
+/// as it arrives.
+///
+/// ```ignore
+/// use portals_sockets_native::{NativeResolver, NativeTcpConnect};
+///
+/// let client = Http1Client::new(NativeTcpConnect, NativeResolver);
+/// let response = client.send(request).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Http1Client<C, R> {
+    connect: C,
+    resolver: R,
+    limits: Limits,
+}
+
+impl<C: TcpConnect, R: Resolver> Http1Client<C, R> {
+    /// Create a client that connects with `connect` and looks up host
+    /// names with `resolver`.
+    pub fn new(connect: C, resolver: R) -> Self {
+        Self {
+            connect,
+            resolver,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Hold responses to `limits` instead of the defaults.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Connect to the first address for `url` that accepts.
+    async fn open(&self, url: &Url) -> Result<C::Stream, Error> {
+        let ips = match url.host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => self
+                .resolver
+                .resolve(&url.host)
+                .await
+                .map_err(|_| Error::ConnectionFailed)?,
+        };
+        let mut error = Error::ConnectionFailed;
+        for ip in ips {
+            match self.connect.connect(SocketAddr::new(ip, url.port)).await {
+                Ok(stream) => return Ok(stream),
+                Err(portals_sockets::Error::Timeout) => error = Error::Timeout,
+                Err(_) => error = Error::ConnectionFailed,
+            }
+        }
+        Err(error)
+    }
+}
+
+impl<C: TcpConnect, R: Resolver> Http1Client<C, R> {
+    /// Send `request` on a new connection and read the response head,
+    /// leaving the body on the connection.
+    async fn execute(
+        &self,
+        request: Request,
+    ) -> Result<(Http1Connection<C::Stream>, ResponseHead), Error> {
+        let url = Url::parse(&request.url)?;
+        let method = portals_http1::Method::from(request.method);
+        let mut headers = Headers::new();
+        for (name, value) in &request.headers {
+            if !valid_header(name, value) {
+                return Err(Error::Other(format!("invalid header: {}", name)));
+            }
+            headers.append(name, value);
+        }
+        if !headers.contains_key("host") {
+            headers.insert("host", url.authority.as_str());
+        }
+        headers.insert("connection", "close");
+        let http1_request = portals_http1::Request {
+            method,
+            path: url.target.clone(),
+            headers,
+            body: request.body.unwrap_or_default(),
+        };
+
+        let stream = self.open(&url).await?;
+        let mut conn = Http1Connection::client(stream).with_limits(self.limits);
+        conn.write_request(&http1_request)
+            .await
+            .map_err(from_http1)?;
+        let head = conn
+            .read_response_head_for(method)
+            .await
+            .map_err(from_http1)?;
+        Ok((conn, head))
+    }
+}
+
+impl<C: TcpConnect, R: Resolver> HttpClient for Http1Client<C, R> {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        let (mut conn, head) = self.execute(request).await?;
+        let mut body = Vec::new();
+        while let Some(chunk) = conn.read_body_chunk().await.map_err(from_http1)? {
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(Response {
+            status: head.status,
+            headers: head.headers,
+            body,
+            redirects: Vec::new(),
+        })
+    }
+}
+
+impl<C, R> StreamingHttpClient for Http1Client<C, R>
+where
+    C: TcpConnect,
+    C::Stream: 'static,
+    R: Resolver,
+{
+    type Body = ChunkStream<Http1Body<C::Stream>>;
+
+    /// Read the request body into memory, then send it and stream the
+    /// response body off the connection as it arrives.
+    async fn send_streaming<S: InputStream>(
+        &self,
+        request: StreamingRequest<S>,
+    ) -> Result<StreamingResponse<Self::Body>, Error> {
+        let (conn, head) = self.execute(request.buffer().await?).await?;
+        Ok(StreamingResponse {
+            status: head.status,
+            headers: head.headers,
+            body: Body::Stream(ChunkStream::new(Http1Body {
+                conn: Some(conn),
+                pending: None,
+            })),
+            redirects: Vec::new(),
+        })
+    }
+}
+
+/// A read of the next body chunk, holding the connection until it's done.
+type PendingChunk<S> = Pin<
+    Box<
+        dyn Future<
+            Output = (
+                Http1Connection<S>,
+                Result<Option<Vec<u8>>, portals_http1::Error>,
+            ),
+        >,
+    >,
+>;
+
+/// A response body read off its connection, through [`ChunkStream`].
+///
+/// The connection is closed once the body has been read.
+pub struct Http1Body<S> {
+    conn: Option<Http1Connection<S>>,
+    pending: Option<PendingChunk<S>>,
+}
+
+impl<S: TcpStream + 'static> ChunkSource for Http1Body<S> {
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>, Error>>> {
+        let pending = match &mut self.pending {
+            Some(pending) => pending,
+            None => {
+                let Some(mut conn) = self.conn.take() else {
+                    return Poll::Ready(None);
+                };
+                self.pending.insert(Box::pin(async move {
+                    let chunk = conn.read_body_chunk().await;
+                    (conn, chunk)
+                }))
+            }
+        };
+        let (conn, chunk) = ready!(pending.as_mut().poll(cx));
+        self.pending = None;
+        Poll::Ready(match chunk {
+            Ok(Some(chunk)) => {
+                self.conn = Some(conn);
+                Some(Ok(chunk))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(from_http1(e))),
+        })
+    }
+}
+
+/// Whether a header can be written without breaking the request: a
+/// non-empty token name and a value without line breaks.
+fn valid_header(name: &str, value: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+        && !value.bytes().any(|b| matches!(b, b'\r' | b'\n' | 0))
+}
+
+fn from_http1(e: portals_http1::Error) -> Error {
+    match e {
+        portals_http1::Error::Io(e) => Error::Io(e),
+        portals_http1::Error::Socket(portals_sockets::Error::Timeout) => Error::Timeout,
+        portals_http1::Error::Socket(portals_sockets::Error::Io(e)) => Error::Io(e),
+        portals_http1::Error::Socket(_) => Error::ConnectionFailed,
+        _ => Error::ProtocolError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_http::Method;
+    use portals_sockets::TcpListener;
+    use portals_sockets_native::{NativeResolver, NativeTcpConnect, NativeTcpListener};
+
+    fn client() -> Http1Client<NativeTcpConnect, NativeResolver> {
+        Http1Client::new(NativeTcpConnect, NativeResolver)
+    }
+
+    fn request(method: Method, url: &str) -> Request {
+        Request {
+            method,
+            url: url.to_string(),
+            headers: Headers::new(),
+            body: None,
+        }
+    }
+
+    /// A check for a request the test server receives, and its response.
+    type Exchange = (fn(&portals_http1::Request), portals_http1::Response);
+
+    /// Answer one connection per request in `exchanges`, checking each
+    /// request as it arrives.
+    async fn server(exchanges: Vec<Exchange>) -> SocketAddr {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for (check, response) in exchanges {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut conn = Http1Connection::server(stream);
+                let request = conn.read_request().await.unwrap().unwrap();
+                check(&request);
+                conn.write_response_for(&response, request.method)
+                    .await
+                    .unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Answer one connection per entry in `responses` with its raw bytes,
+    /// then close the connection.
+    async fn raw_server(responses: Vec<&'static [u8]>) -> SocketAddr {
+        use portals_sockets::TcpStream;
+
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for mut response in responses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut conn = Http1Connection::server(stream);
+                conn.read_request().await.unwrap().unwrap();
+                let stream = conn.get_mut();
+                while !response.is_empty() {
+                    let n = stream.write(response).await.unwrap();
+                    response = &response[n..];
+                }
+                stream.flush().await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn frames_response_bodies() {
+        let addr = raw_server(vec![
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+                6\r\nchunk \r\n7\r\nencoded\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nread until close",
+        ])
+        .await;
+        let url = format!("http://{}/", addr);
+
+        let chunked = client().send(request(Method::Get, &url)).await.unwrap();
+        assert_eq!(chunked.body, b"chunk encoded");
+
+        let close_delimited = client().send(request(Method::Get, &url)).await.unwrap();
+        assert_eq!(close_delimited.body, b"read until close");
+    }
+
+    #[tokio::test]
+    async fn streams_response_bodies() {
+        use portals_io::StreamError;
+
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (first_read, mut wait_for_read) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Http1Connection::server(stream);
+            conn.read_request().await.unwrap().unwrap();
+            let stream = conn.get_mut();
+            let head = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n6\r\nfirst \r\n";
+            stream.write(head).await.unwrap();
+            // Hold the rest back until the client has read the first chunk.
+            wait_for_read.recv().await;
+            stream.write(b"6\r\nsecond\r\n0\r\n\r\n").await.unwrap();
+        });
+
+        let url = format!("http://{}/", addr);
+        let request =
+            StreamingRequest::<portals_http::EmptyStream>::from(request(Method::Get, &url));
+        let response = client().send_streaming(request).await.unwrap();
+        assert_eq!(response.status, 200);
+        let Body::Stream(mut body) = response.body else {
+            panic!("expected a streamed body");
+        };
+
+        let mut chunks = Vec::new();
+        let mut buf = [0; 64];
+        loop {
+            match body.read_into(&mut buf) {
+                Ok(0) => body.subscribe().await,
+                Ok(n) => {
+                    chunks.push(buf[..n].to_vec());
+                    let _ = first_read.try_send(());
+                }
+                Err(StreamError::Closed) => break,
+                Err(e) => panic!("read failed: {}", e),
+            }
+        }
+        assert_eq!(chunks, [b"first ".to_vec(), b"second".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn sends_requests() {
+        let addr = server(vec![
+            (
+                |req| {
+                    assert_eq!(req.method, portals_http1::Method::Get);
+                    assert_eq!(req.path, "/users?id=1");
+                    assert_eq!(req.headers.get("connection"), Some("close"));
+                    assert!(req.headers.get("host").unwrap().starts_with("localhost:"));
+                    assert!(req.body.is_empty());
+                },
+                portals_http1::Response::new(200)
+                    .header("Content-Type", "text/plain")
+                    .append_header("Vary", "accept")
+                    .append_header("vary", "cookie")
+                    .body("alice"),
+            ),
+            (
+                |req| {
+                    assert_eq!(req.method, portals_http1::Method::Post);
+                    assert_eq!(req.headers.get("x-token"), Some("secret"));
+                    assert_eq!(req.body, b"{}");
+                },
+                portals_http1::Response::new(201),
+            ),
+            (
+                |req| assert_eq!(req.method, portals_http1::Method::Head),
+                portals_http1::Response::new(200).header("content-length", "5"),
+            ),
+        ])
+        .await;
+        let base = format!("http://localhost:{}", addr.port());
+
+        let response = client()
+            .send(request(Method::Get, &format!("{}/users?id=1#top", base)))
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"alice");
+        assert_eq!(&response.headers["content-type"], "text/plain");
+        assert_eq!(
+            response.headers.get_all("vary").collect::<Vec<_>>(),
+            ["accept", "cookie"]
+        );
+
+        let mut post = request(Method::Post, &format!("{}/users", base));
+        post.headers.insert("x-token", "secret");
+        post.body = Some(b"{}".to_vec());
+        assert_eq!(client().send(post).await.unwrap().status, 201);
+
+        let head = client()
+            .send(request(Method::Head, &format!("http://{}/", addr)))
+            .await
+            .unwrap();
+        assert_eq!(head.status, 200);
+        assert!(head.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_failures() {
+        // Bind and drop a listener to find a port nothing listens on.
+        let addr = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap())
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let refused = client()
+            .send(request(Method::Get, &format!("http://{}/", addr)))
+            .await;
+        assert!(matches!(refused, Err(Error::ConnectionFailed)));
+
+        let https = client()
+            .send(request(Method::Get, "https://example.com/"))
+            .await;
+        assert!(matches!(https, Err(Error::Other(_))));
+
+        let mut injected = request(Method::Get, "http://127.0.0.1/");
+        injected.headers.insert("x-a", "1\r\nx-b: 2");
+        assert!(matches!(
+            client().send(injected).await,
+            Err(Error::Other(_))
+        ));
+    }
+}
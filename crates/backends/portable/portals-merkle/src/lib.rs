@@ -0,0 +1,323 @@
+//! Merkle trees over any [`Hash`].
+//!
+//! Leaves and inner nodes are hashed with distinct prefixes (as in
+//! RFC 6962), so a leaf can never be passed off as an inner node. A node
+//! has up to `fanout` children; when a level does not divide evenly, the
+//! last node has fewer children, and a lone last node is carried up to the
+//! next level unchanged.
+//!
+//! ```ignore
+//! let tree = MerkleBuilder::<Sha256>::new().fanout(4).leaves(&records).build();
+//! let proof = tree.prove(7).unwrap();
+//! assert!(proof.verify::<Sha256>(&tree.root(), &records[7]));
+//! ```
+
+use portals_crypto::Hash;
+use std::marker::PhantomData;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Hash a leaf's data.
+pub fn leaf_hash<H: Hash>(data: &[u8]) -> Vec<u8> {
+    let mut hasher = H::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Hash an inner node from its children's hashes.
+pub fn node_hash<H: Hash>(children: &[Vec<u8>]) -> Vec<u8> {
+    let mut hasher = H::new();
+    hasher.update(&[NODE_PREFIX]);
+    for child in children {
+        hasher.update(child);
+    }
+    hasher.finalize()
+}
+
+/// Collects leaves for a [`MerkleTree`].
+#[derive(Debug, Clone)]
+pub struct MerkleBuilder<H> {
+    fanout: usize,
+    leaves: Vec<Vec<u8>>,
+    _hash: PhantomData<H>,
+}
+
+impl<H: Hash> Default for MerkleBuilder<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Hash> MerkleBuilder<H> {
+    /// Create a builder for a binary tree with no leaves.
+    pub fn new() -> Self {
+        Self {
+            fanout: 2,
+            leaves: Vec::new(),
+            _hash: PhantomData,
+        }
+    }
+
+    /// Maximum children per inner node (default 2).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fanout` is less than 2.
+    pub fn fanout(mut self, fanout: usize) -> Self {
+        assert!(fanout >= 2, "Merkle tree fanout must be at least 2");
+        self.fanout = fanout;
+        self
+    }
+
+    /// Append leaves.
+    pub fn leaves<T: AsRef<[u8]>>(mut self, leaves: impl IntoIterator<Item = T>) -> Self {
+        self.leaves
+            .extend(leaves.into_iter().map(|leaf| leaf_hash::<H>(leaf.as_ref())));
+        self
+    }
+
+    /// Append one leaf.
+    pub fn push(&mut self, leaf: &[u8]) {
+        self.leaves.push(leaf_hash::<H>(leaf));
+    }
+
+    /// Number of leaves so far.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Whether no leaves have been added.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Hash the tree.
+    pub fn build(self) -> MerkleTree<H> {
+        let mut levels = vec![self.leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(self.fanout)
+                .map(|group| match group {
+                    [lone] => lone.clone(),
+                    _ => node_hash::<H>(group),
+                })
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree {
+            fanout: self.fanout,
+            levels,
+            _hash: PhantomData,
+        }
+    }
+}
+
+/// A hashed Merkle tree.
+#[derive(Debug, Clone)]
+pub struct MerkleTree<H> {
+    fanout: usize,
+    /// Node hashes, leaves first. The last level holds the root.
+    levels: Vec<Vec<Vec<u8>>>,
+    _hash: PhantomData<H>,
+}
+
+impl<H: Hash> MerkleTree<H> {
+    /// Build a binary tree from `leaves`.
+    pub fn new<T: AsRef<[u8]>>(leaves: impl IntoIterator<Item = T>) -> Self {
+        MerkleBuilder::new().leaves(leaves).build()
+    }
+
+    /// The root hash. An empty tree's root is the hash of no data.
+    pub fn root(&self) -> Vec<u8> {
+        match self.levels.last().and_then(|level| level.first()) {
+            Some(root) => root.clone(),
+            None => H::hash(&[]),
+        }
+    }
+
+    /// Number of leaves.
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Whether the tree has no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// Maximum children per inner node.
+    pub fn fanout(&self) -> usize {
+        self.fanout
+    }
+
+    /// Prove that the leaf at `index` is in the tree, or `None` if out of
+    /// range.
+    pub fn prove(&self, index: usize) -> Option<Proof> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let start = position - position % self.fanout;
+            let end = (start + self.fanout).min(level.len());
+            siblings.push(
+                (start..end)
+                    .filter(|&i| i != position)
+                    .map(|i| level[i].clone())
+                    .collect(),
+            );
+            position /= self.fanout;
+        }
+        Some(Proof {
+            index,
+            fanout: self.fanout,
+            siblings,
+        })
+    }
+}
+
+/// Proof that a leaf is included in a tree with a given root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    index: usize,
+    fanout: usize,
+    /// For each level from the leaves up, the other children of the node
+    /// on the path, in order.
+    siblings: Vec<Vec<Vec<u8>>>,
+}
+
+impl Proof {
+    /// The proven leaf's index.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The fanout of the tree the proof was made from.
+    pub fn fanout(&self) -> usize {
+        self.fanout
+    }
+
+    /// Sibling hashes for each level, leaves first.
+    pub fn siblings(&self) -> &[Vec<Vec<u8>>] {
+        &self.siblings
+    }
+
+    /// Check that `leaf` is at this proof's index in the tree with `root`.
+    pub fn verify<H: Hash>(&self, root: &[u8], leaf: &[u8]) -> bool {
+        if self.fanout < 2 {
+            return false;
+        }
+        let mut hash = leaf_hash::<H>(leaf);
+        let mut position = self.index;
+        for siblings in &self.siblings {
+            let offset = position % self.fanout;
+            if siblings.len() >= self.fanout || offset > siblings.len() {
+                return false;
+            }
+            if !siblings.is_empty() {
+                let mut children = siblings.clone();
+                children.insert(offset, hash);
+                hash = node_hash::<H>(&children);
+            }
+            position /= self.fanout;
+        }
+        position == 0 && hash == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_crypto_native::Sha256;
+
+    fn leaves(n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|i| format!("leaf {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn small_trees() {
+        let empty = MerkleTree::<Sha256>::new(Vec::<Vec<u8>>::new());
+        assert!(empty.is_empty());
+        assert_eq!(empty.root(), Sha256::hash(b""));
+        assert!(empty.prove(0).is_none());
+
+        let one = MerkleTree::<Sha256>::new([b"a"]);
+        assert_eq!(one.root(), leaf_hash::<Sha256>(b"a"));
+
+        let three = MerkleTree::<Sha256>::new([b"a", b"b", b"c"]);
+        let ab = node_hash::<Sha256>(&[leaf_hash::<Sha256>(b"a"), leaf_hash::<Sha256>(b"b")]);
+        // The odd leaf is carried up, not duplicated.
+        let expected = node_hash::<Sha256>(&[ab, leaf_hash::<Sha256>(b"c")]);
+        assert_eq!(three.root(), expected);
+    }
+
+    #[test]
+    fn proves_every_leaf() {
+        for fanout in [2, 3, 4, 16] {
+            for n in [1, 2, 5, 16, 17, 100] {
+                let data = leaves(n);
+                let tree = MerkleBuilder::<Sha256>::new()
+                    .fanout(fanout)
+                    .leaves(&data)
+                    .build();
+                let root = tree.root();
+                for (i, leaf) in data.iter().enumerate() {
+                    let proof = tree.prove(i).unwrap();
+                    assert!(
+                        proof.verify::<Sha256>(&root, leaf),
+                        "fanout {} n {} leaf {}",
+                        fanout,
+                        n,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_inputs() {
+        let data = leaves(10);
+        let tree = MerkleBuilder::<Sha256>::new()
+            .fanout(3)
+            .leaves(&data)
+            .build();
+        let root = tree.root();
+        let proof = tree.prove(4).unwrap();
+
+        assert!(!proof.verify::<Sha256>(&root, &data[5]));
+        assert!(!proof.verify::<Sha256>(&Sha256::hash(b"other"), &data[4]));
+
+        let mut moved = proof.clone();
+        moved.index = 5;
+        assert!(!moved.verify::<Sha256>(&root, &data[4]));
+
+        let mut tampered = proof.clone();
+        tampered.siblings[0][0] = Sha256::hash(b"x");
+        assert!(!tampered.verify::<Sha256>(&root, &data[4]));
+    }
+
+    #[test]
+    fn incremental_push_matches() {
+        let data = leaves(7);
+        let mut builder = MerkleBuilder::<Sha256>::new();
+        for leaf in &data {
+            builder.push(leaf);
+        }
+        assert_eq!(builder.len(), 7);
+        assert_eq!(
+            builder.build().root(),
+            MerkleTree::<Sha256>::new(&data).root()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_unary_fanout() {
+        let _ = MerkleBuilder::<Sha256>::new().fanout(1);
+    }
+}
@@ -0,0 +1,696 @@
+//! Cron job scheduler.
+//!
+//! Runs named async jobs on cron schedules. The wall clock decides which
+//! occurrences are due and the monotonic clock sleeps until the next one;
+//! both are injected, so tests can drive the scheduler with mock clocks.
+//!
+//! ```ignore
+//! let scheduler = Scheduler::new(SystemWallClock, SystemMonotonicClock);
+//!
+//! let cron = CronParserImpl::new().parse("*/5 * * * *")?;
+//! let sweep = scheduler.add(Job::new("sweep", cron, |_scheduled| async {
+//!     sweep_expired().await;
+//! }))?;
+//!
+//! // Handles stay valid while the scheduler runs.
+//! sweep.pause()?;
+//! sweep.resume()?;
+//!
+//! scheduler.run(&shutdown).await;
+//! ```
+
+use portals_clocks::{MonotonicClock, WallClock};
+use portals_cron::{CatchUp, CronScheduleTz};
+pub use portals_error::{ErrorKind, PithError};
+use portals_signals::Shutdown;
+use std::fmt;
+use std::future::{Future, poll_fn};
+use std::pin::{Pin, pin};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// Upper bound on occurrences a single job runs in one tick under
+/// [`CatchUp::RunAll`]. Older occurrences beyond it are dropped.
+pub const RUN_ALL_LIMIT: usize = 1000;
+
+/// Scheduler errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A job with this name is already registered.
+    DuplicateName(String),
+    /// The job has been removed.
+    NotFound(String),
+    /// Other error.
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateName(name) => write!(f, "job already registered: {}", name),
+            Self::NotFound(name) => write!(f, "job not found: {}", name),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::DuplicateName(_) => ErrorKind::Conflict,
+            Self::NotFound(_) => ErrorKind::NotFound,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+type Task = Box<dyn FnMut(i64) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// A job to register with a [`Scheduler`].
+///
+/// The task is called with the occurrence it runs for, as a Unix timestamp
+/// in seconds.
+pub struct Job {
+    name: String,
+    schedule: Box<dyn CronScheduleTz + Send>,
+    task: Task,
+    catch_up: CatchUp,
+    paused: bool,
+}
+
+impl Job {
+    /// Create a job that runs `task` on `schedule`.
+    pub fn new<S, F, Fut>(name: impl Into<String>, schedule: S, mut task: F) -> Self
+    where
+        S: CronScheduleTz + Send + 'static,
+        F: FnMut(i64) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule: Box::new(schedule),
+            task: Box::new(move |scheduled| Box::pin(task(scheduled))),
+            catch_up: CatchUp::default(),
+            paused: false,
+        }
+    }
+
+    /// What to do with occurrences missed while the process was down or a
+    /// job overran (default: [`CatchUp::Skip`]).
+    pub fn catch_up(mut self, policy: CatchUp) -> Self {
+        self.catch_up = policy;
+        self
+    }
+
+    /// Register the job paused.
+    pub fn paused(mut self) -> Self {
+        self.paused = true;
+        self
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Job")
+            .field("name", &self.name)
+            .field("catch_up", &self.catch_up)
+            .field("paused", &self.paused)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs registered jobs when their schedules come due.
+///
+/// Clones share the same jobs. Jobs due at the same time run one after
+/// another, in order of occurrence; a job that overruns delays the rest,
+/// and their occurrences are then handled by their [`CatchUp`] policy.
+///
+/// An occurrence counts as missed once it is more than the grace period
+/// (default one second) overdue. [`CatchUp::Skip`] drops missed
+/// occurrences, [`CatchUp::RunOnce`] runs the latest due occurrence, and
+/// [`CatchUp::RunAll`] runs every due occurrence.
+#[derive(Debug, Clone)]
+pub struct Scheduler<W, M> {
+    wall: W,
+    clock: M,
+    grace: i64,
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        let names: Vec<&str> = state.jobs.iter().map(|e| e.name.as_str()).collect();
+        f.debug_struct("Shared").field("jobs", &names).finish()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    jobs: Vec<Entry>,
+    next_id: u64,
+    /// Bumped whenever jobs are added, removed, paused, or resumed, so a
+    /// sleeping run loop can recompute its deadline.
+    changes: u64,
+    wakers: Vec<Waker>,
+}
+
+struct Entry {
+    id: u64,
+    name: String,
+    schedule: Box<dyn CronScheduleTz + Send>,
+    /// Taken while the task runs.
+    task: Option<Task>,
+    catch_up: CatchUp,
+    paused: bool,
+    /// Set on resume; the next tick recomputes `next` from its own time.
+    resumed: bool,
+    next: Option<i64>,
+    last_run: Option<i64>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl State {
+    fn entry(&mut self, id: u64) -> Option<&mut Entry> {
+        self.jobs.iter_mut().find(|e| e.id == id)
+    }
+
+    fn changed(&mut self) {
+        self.changes += 1;
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<W: WallClock, M: MonotonicClock> Scheduler<W, M> {
+    /// Create a scheduler with no jobs.
+    pub fn new(wall: W, clock: M) -> Self {
+        Self {
+            wall,
+            clock,
+            grace: 1,
+            shared: Arc::default(),
+        }
+    }
+
+    /// How overdue an occurrence may be before it counts as missed.
+    /// Rounded down to whole seconds.
+    pub fn grace(mut self, grace: Duration) -> Self {
+        self.grace = grace.as_secs() as i64;
+        self
+    }
+
+    /// Register a job. Its first run is the first occurrence after now.
+    pub fn add(&self, job: Job) -> Result<JobHandle, Error> {
+        let now = self.now();
+        let mut state = self.shared.lock();
+        if state.jobs.iter().any(|e| e.name == job.name) {
+            return Err(Error::DuplicateName(job.name));
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        state.jobs.push(Entry {
+            id,
+            name: job.name.clone(),
+            next: job.schedule.next_after_timestamp(now),
+            schedule: job.schedule,
+            task: Some(job.task),
+            catch_up: job.catch_up,
+            paused: job.paused,
+            resumed: false,
+            last_run: None,
+        });
+        state.changed();
+        Ok(JobHandle {
+            id,
+            name: job.name,
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// A handle to the job registered under `name`.
+    pub fn handle(&self, name: &str) -> Option<JobHandle> {
+        let state = self.shared.lock();
+        let entry = state.jobs.iter().find(|e| e.name == name)?;
+        Some(JobHandle {
+            id: entry.id,
+            name: entry.name.clone(),
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Names of registered jobs, in registration order.
+    pub fn job_names(&self) -> Vec<String> {
+        let state = self.shared.lock();
+        state.jobs.iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// Run every job that is due now, and return how many runs there were.
+    ///
+    /// [`Scheduler::run`] calls this in a loop; call it directly to step
+    /// the scheduler by hand, e.g. after advancing a mock wall clock.
+    pub async fn run_pending(&self) -> usize {
+        self.tick(self.now(), None).await
+    }
+
+    /// Run jobs as they come due until `shutdown` is triggered.
+    ///
+    /// Each run is tracked by `shutdown`, so [`Shutdown::drain`] waits for
+    /// a job in progress. Returns once shutdown is triggered; runs still
+    /// due at that point are not started.
+    ///
+    /// The loop sleeps with [`MonotonicClock::subscribe_duration`] and
+    /// treats a completed timer as reaching the occurrence it waited for,
+    /// even if the wall clock lags behind. With mock clocks, whose timers
+    /// complete immediately, occurrences therefore run back to back.
+    pub async fn run(&self, shutdown: &Shutdown) {
+        let mut now = self.now();
+        while !shutdown.is_triggered() {
+            self.tick(now, Some(shutdown)).await;
+
+            let (seen, next) = {
+                let state = self.shared.lock();
+                let next = state
+                    .jobs
+                    .iter()
+                    .filter(|e| !e.paused)
+                    .filter_map(|e| e.next)
+                    .min();
+                (state.changes, next)
+            };
+
+            let timer = next.map(|next| self.clock.subscribe_duration(self.until(next)));
+            let mut timer = pin!(timer);
+            let mut stopped = pin!(shutdown.wait());
+            let mut changed = pin!(Changed {
+                shared: &self.shared,
+                seen,
+            });
+            let woke = poll_fn(|cx| {
+                if stopped.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Wake::Shutdown);
+                }
+                if changed.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Wake::Changed);
+                }
+                if let Some(timer) = timer.as_mut().as_pin_mut()
+                    && timer.poll(cx).is_ready()
+                {
+                    return Poll::Ready(Wake::Timer);
+                }
+                Poll::Pending
+            })
+            .await;
+
+            now = match woke {
+                Wake::Shutdown => return,
+                Wake::Changed => self.now(),
+                Wake::Timer => self.now().max(next.unwrap_or(i64::MIN)),
+            };
+        }
+    }
+
+    /// Wall-clock time in whole seconds.
+    fn now(&self) -> i64 {
+        self.wall.now().0 as i64
+    }
+
+    /// Time from now until `timestamp`, or zero if it has passed.
+    fn until(&self, timestamp: i64) -> Duration {
+        let (secs, nanos) = self.wall.now();
+        let now = Duration::new(secs, nanos);
+        let target = Duration::from_secs(timestamp.max(0) as u64);
+        target.saturating_sub(now)
+    }
+
+    async fn tick(&self, now: i64, shutdown: Option<&Shutdown>) -> usize {
+        let mut runs = self.due(now);
+        runs.sort_unstable();
+
+        let mut count = 0;
+        for (scheduled, id) in runs {
+            let _guard = match shutdown {
+                Some(shutdown) => match shutdown.track() {
+                    Some(guard) => Some(guard),
+                    None => break,
+                },
+                None => None,
+            };
+
+            // Earlier runs in this tick may have paused or removed the job.
+            let task = {
+                let mut state = self.shared.lock();
+                match state.entry(id) {
+                    Some(entry) if !entry.paused => {
+                        entry.last_run = Some(scheduled);
+                        entry.task.take()
+                    }
+                    _ => None,
+                }
+            };
+            let Some(mut task) = task else {
+                continue;
+            };
+
+            task(scheduled).await;
+            count += 1;
+
+            if let Some(entry) = self.shared.lock().entry(id) {
+                entry.task = Some(task);
+            }
+        }
+        count
+    }
+
+    /// Collect `(occurrence, job id)` pairs due at `now` under each job's
+    /// catch-up policy, and advance each job past `now`.
+    fn due(&self, now: i64) -> Vec<(i64, u64)> {
+        let mut state = self.shared.lock();
+        let mut runs = Vec::new();
+        for entry in state.jobs.iter_mut().filter(|e| !e.paused) {
+            if entry.resumed {
+                entry.resumed = false;
+                entry.next = entry.schedule.next_after_timestamp(now);
+            }
+
+            let Some(first) = entry.next.filter(|&next| next <= now) else {
+                continue;
+            };
+            if entry.catch_up == CatchUp::RunAll {
+                let mut due = vec![first];
+                entry.next = entry.schedule.next_after_timestamp(first);
+                while let Some(next) = entry.next.filter(|&next| next <= now) {
+                    due.push(next);
+                    entry.next = if due.len() == RUN_ALL_LIMIT {
+                        entry.schedule.next_after_timestamp(now)
+                    } else {
+                        entry.schedule.next_after_timestamp(next)
+                    };
+                }
+                runs.extend(due.into_iter().map(|at| (at, entry.id)));
+                continue;
+            }
+
+            // Only the latest due occurrence matters, so look back from
+            // `now` rather than walking a gap that may be very long.
+            let latest = entry
+                .schedule
+                .prev_before_timestamp(now + 1)
+                .filter(|&at| at >= first)
+                .unwrap_or(first);
+            entry.next = entry.schedule.next_after_timestamp(now);
+            if entry.catch_up == CatchUp::RunOnce || now - latest <= self.grace {
+                runs.push((latest, entry.id));
+            }
+        }
+        runs
+    }
+}
+
+enum Wake {
+    Timer,
+    Changed,
+    Shutdown,
+}
+
+/// Resolves once the job set changes after `seen`.
+struct Changed<'a> {
+    shared: &'a Shared,
+    seen: u64,
+}
+
+impl Future for Changed<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.shared.lock();
+        if state.changes != self.seen {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A handle to a registered job.
+///
+/// Handles are cheap to clone and stay valid for as long as the job is
+/// registered; after it is removed, operations return [`Error::NotFound`].
+#[derive(Debug, Clone)]
+pub struct JobHandle {
+    id: u64,
+    name: String,
+    shared: Arc<Shared>,
+}
+
+impl JobHandle {
+    /// The job's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Stop running the job until [`JobHandle::resume`]. A run already in
+    /// progress finishes.
+    pub fn pause(&self) -> Result<(), Error> {
+        self.update(|entry| {
+            entry.paused = true;
+            entry.resumed = false;
+        })
+    }
+
+    /// Resume a paused job. Occurrences while it was paused are not run;
+    /// it next runs at its first occurrence after resuming.
+    pub fn resume(&self) -> Result<(), Error> {
+        self.update(|entry| {
+            if entry.paused {
+                entry.paused = false;
+                entry.resumed = true;
+            }
+        })
+    }
+
+    /// Unregister the job. A run already in progress finishes.
+    pub fn remove(&self) -> Result<(), Error> {
+        let mut state = self.shared.lock();
+        let before = state.jobs.len();
+        state.jobs.retain(|e| e.id != self.id);
+        if state.jobs.len() == before {
+            return Err(self.not_found());
+        }
+        state.changed();
+        Ok(())
+    }
+
+    /// Whether the job is still registered.
+    pub fn is_registered(&self) -> bool {
+        self.shared.lock().entry(self.id).is_some()
+    }
+
+    /// Whether the job is paused.
+    pub fn is_paused(&self) -> Result<bool, Error> {
+        self.read(|entry| entry.paused)
+    }
+
+    /// The next occurrence the job will run for, as a Unix timestamp.
+    ///
+    /// `None` while paused or once the schedule has no further occurrence.
+    pub fn next_run(&self) -> Result<Option<i64>, Error> {
+        self.read(|entry| entry.next.filter(|_| !entry.paused && !entry.resumed))
+    }
+
+    /// The occurrence the job last ran for, as a Unix timestamp.
+    pub fn last_run(&self) -> Result<Option<i64>, Error> {
+        self.read(|entry| entry.last_run)
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Entry) -> T) -> Result<T, Error> {
+        let mut state = self.shared.lock();
+        match state.entry(self.id) {
+            Some(entry) => Ok(f(entry)),
+            None => Err(self.not_found()),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Entry)) -> Result<(), Error> {
+        let mut state = self.shared.lock();
+        let entry = state.entry(self.id).ok_or_else(|| self.not_found())?;
+        f(entry);
+        state.changed();
+        Ok(())
+    }
+
+    fn not_found(&self) -> Error {
+        Error::NotFound(self.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_clocks_mock::{MockMonotonicClock, MockWallClock};
+    use portals_cron::CronParser;
+    use portals_cron_portable::CronParserImpl;
+
+    /// 2024-01-01T00:00:00Z
+    const START: i64 = 1_704_067_200;
+
+    fn scheduler() -> (Scheduler<MockWallClock, MockMonotonicClock>, MockWallClock) {
+        let wall = MockWallClock::new(START as u64, 0);
+        let scheduler = Scheduler::new(wall.clone(), MockMonotonicClock::new());
+        (scheduler, wall)
+    }
+
+    /// A job on `expr` (with seconds) that records the occurrences it ran for.
+    fn recording(name: &str, expr: &str) -> (Job, Arc<Mutex<Vec<i64>>>) {
+        let cron = CronParserImpl::new().parse_with_seconds(expr).unwrap();
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let log = runs.clone();
+        let job = Job::new(name, cron, move |scheduled| {
+            let log = log.clone();
+            async move { log.lock().unwrap().push(scheduled) }
+        });
+        (job, runs)
+    }
+
+    #[tokio::test]
+    async fn runs_due_jobs() {
+        let (scheduler, wall) = scheduler();
+        let (job, runs) = recording("tick", "*/10 * * * * *");
+        let handle = scheduler.add(job).unwrap();
+        assert_eq!(handle.next_run(), Ok(Some(START + 10)));
+
+        assert_eq!(scheduler.run_pending().await, 0);
+        wall.advance(Duration::from_secs(10));
+        assert_eq!(scheduler.run_pending().await, 1);
+        wall.advance(Duration::from_secs(5));
+        assert_eq!(scheduler.run_pending().await, 0);
+
+        assert_eq!(*runs.lock().unwrap(), vec![START + 10]);
+        assert_eq!(handle.last_run(), Ok(Some(START + 10)));
+        assert_eq!(handle.next_run(), Ok(Some(START + 20)));
+    }
+
+    #[tokio::test]
+    async fn handles_pause_resume_and_remove() {
+        let (scheduler, wall) = scheduler();
+        let (job, runs) = recording("tick", "* * * * * *");
+        scheduler.add(job).unwrap();
+
+        let (duplicate, _) = recording("tick", "* * * * * *");
+        assert_eq!(
+            scheduler.add(duplicate).unwrap_err(),
+            Error::DuplicateName("tick".to_string())
+        );
+
+        let handle = scheduler.handle("tick").unwrap();
+        handle.pause().unwrap();
+        assert_eq!(handle.is_paused(), Ok(true));
+        wall.advance(Duration::from_secs(5));
+        assert_eq!(scheduler.run_pending().await, 0);
+
+        // Occurrences while paused are not caught up.
+        handle.resume().unwrap();
+        assert_eq!(scheduler.run_pending().await, 0);
+        wall.advance(Duration::from_secs(1));
+        assert_eq!(scheduler.run_pending().await, 1);
+        assert_eq!(*runs.lock().unwrap(), vec![START + 6]);
+
+        handle.remove().unwrap();
+        assert!(!handle.is_registered());
+        assert!(scheduler.job_names().is_empty());
+        assert_eq!(
+            handle.pause().unwrap_err(),
+            Error::NotFound("tick".to_string())
+        );
+        wall.advance(Duration::from_secs(1));
+        assert_eq!(scheduler.run_pending().await, 0);
+    }
+
+    #[tokio::test]
+    async fn catch_up_policies() {
+        let (scheduler, wall) = scheduler();
+        let (skip, skipped) = recording("skip", "0 * * * * *");
+        let (once, coalesced) = recording("once", "0 * * * * *");
+        let (all, every) = recording("all", "0 * * * * *");
+        scheduler.add(skip).unwrap();
+        scheduler.add(once.catch_up(CatchUp::RunOnce)).unwrap();
+        scheduler.add(all.catch_up(CatchUp::RunAll)).unwrap();
+
+        // Down for five and a half minutes.
+        wall.advance(Duration::from_secs(330));
+        scheduler.run_pending().await;
+
+        assert!(skipped.lock().unwrap().is_empty());
+        assert_eq!(*coalesced.lock().unwrap(), vec![START + 300]);
+        assert_eq!(
+            *every.lock().unwrap(),
+            (1..=5).map(|m| START + 60 * m).collect::<Vec<_>>()
+        );
+
+        // Back on time, every policy runs once.
+        wall.advance(Duration::from_secs(30));
+        assert_eq!(scheduler.run_pending().await, 3);
+        assert_eq!(skipped.lock().unwrap().last(), Some(&(START + 360)));
+    }
+
+    #[tokio::test]
+    async fn run_loop_with_mock_clocks() {
+        let (scheduler, _wall) = scheduler();
+        let shutdown = Shutdown::new();
+
+        let cron = CronParserImpl::new().parse("*/15 * * * *").unwrap();
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let (log, token) = (runs.clone(), shutdown.clone());
+        let job = Job::new("quarterly", cron, move |scheduled| {
+            let mut log = log.lock().unwrap();
+            log.push(scheduled);
+            if log.len() == 3 {
+                token.trigger();
+            }
+            async {}
+        });
+        scheduler.add(job).unwrap();
+
+        scheduler.run(&shutdown).await;
+        assert_eq!(
+            *runs.lock().unwrap(),
+            vec![START + 900, START + 1800, START + 2700]
+        );
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn run_loop_stops_when_idle() {
+        let (scheduler, _wall) = scheduler();
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+        scheduler.run(&shutdown).await;
+
+        // No jobs: the loop waits for shutdown rather than spinning.
+        let shutdown = Shutdown::new();
+        let token = shutdown.clone();
+        let stop = async move {
+            tokio::task::yield_now().await;
+            token.trigger();
+        };
+        tokio::join!(scheduler.run(&shutdown), stop);
+    }
+}
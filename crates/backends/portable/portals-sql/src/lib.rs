@@ -0,0 +1,10 @@
+//! Portable decorators for [`portals_sql`] connections.
+//!
+//! These wrap any [`Connection`](portals_sql::Connection) and add behavior
+//! around it without depending on a particular database driver.
+
+mod observed;
+mod routed;
+
+pub use observed::{NamedStatement, ObservedConnection};
+pub use routed::RoutedConnection;
@@ -0,0 +1,344 @@
+//! Query metrics.
+
+use portals_clocks::MonotonicClock;
+use portals_observe::{Counter, Histogram, Metrics};
+use portals_sql::{Connection, Error, Row, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+/// A connection that records metrics for the statements it runs.
+///
+/// Every statement is labeled with a name, and each name gets its own
+/// instruments:
+///
+/// | Metric | Kind | Records |
+/// |--------|------|---------|
+/// | `{prefix}.{name}.calls` | counter | statements run |
+/// | `{prefix}.{name}.errors` | counter | statements that failed |
+/// | `{prefix}.{name}.rows` | counter | rows returned or affected |
+/// | `{prefix}.{name}.duration` | histogram | latency in seconds |
+///
+/// [`Metrics`] has no labels, so the name becomes part of the metric name.
+/// Supply one with [`named`](Self::named); statements run through the
+/// [`Connection`] impl directly are labeled by their leading keyword
+/// (`select`, `insert`, ..., or `other`), and transaction control by
+/// `begin`, `commit`, and `rollback`. Keep names to a small fixed set,
+/// since each one creates new instruments.
+///
+/// ```ignore
+/// let db = ObservedConnection::new(conn, metrics, SystemMonotonicClock);
+/// let rows = db
+///     .named("get_user")
+///     .query("SELECT * FROM users WHERE id = ?", &[id.into()])
+///     .await?;
+/// ```
+pub struct ObservedConnection<C, M: Metrics, K> {
+    inner: C,
+    metrics: M,
+    clock: K,
+    prefix: String,
+    instruments: Mutex<HashMap<String, Instruments<M>>>,
+}
+
+struct Instruments<M: Metrics> {
+    calls: M::Counter,
+    errors: M::Counter,
+    rows: M::Counter,
+    duration: M::Histogram,
+}
+
+impl<C: Connection, M: Metrics, K: MonotonicClock> ObservedConnection<C, M, K> {
+    /// Wrap `inner`, recording to `metrics` under the `sql` prefix and
+    /// timing statements with `clock`.
+    pub fn new(inner: C, metrics: M, clock: K) -> Self {
+        Self {
+            inner,
+            metrics,
+            clock,
+            prefix: "sql".to_string(),
+            instruments: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set the prefix of metric names.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The wrapped connection.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the connection.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// A view of this connection whose queries and statements are labeled
+    /// `name`.
+    pub fn named<'a>(&'a self, name: &'a str) -> NamedStatement<'a, C, M, K> {
+        NamedStatement { conn: self, name }
+    }
+
+    async fn observe<T>(
+        &self,
+        name: &str,
+        op: impl Future<Output = Result<T, Error>>,
+        rows: impl FnOnce(&T) -> u64,
+    ) -> Result<T, Error> {
+        let start = self.clock.now();
+        let result = op.await;
+        let elapsed = self.clock.now().saturating_sub(start);
+
+        let mut instruments = self.instruments.lock().unwrap();
+        let instruments = instruments
+            .entry(name.to_string())
+            .or_insert_with(|| self.create_instruments(name));
+        instruments.calls.add(1);
+        instruments.duration.record(elapsed as f64 / 1e9);
+        match &result {
+            Ok(value) => instruments.rows.add(rows(value)),
+            Err(_) => instruments.errors.add(1),
+        }
+        result
+    }
+
+    fn create_instruments(&self, name: &str) -> Instruments<M> {
+        let metric = |suffix: &str| format!("{}.{}.{}", self.prefix, name, suffix);
+        Instruments {
+            calls: self.metrics.counter(&metric("calls"), "SQL statements run"),
+            errors: self
+                .metrics
+                .counter(&metric("errors"), "SQL statements that failed"),
+            rows: self
+                .metrics
+                .counter(&metric("rows"), "Rows returned or affected"),
+            duration: self
+                .metrics
+                .histogram(&metric("duration"), "SQL statement latency in seconds"),
+        }
+    }
+}
+
+impl<C: Connection, M: Metrics, K: MonotonicClock> Connection for ObservedConnection<C, M, K> {
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>, Error> {
+        self.named(keyword(sql)).query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64, Error> {
+        self.named(keyword(sql)).execute(sql, params).await
+    }
+
+    async fn begin(&self) -> Result<(), Error> {
+        self.observe("begin", self.inner.begin(), |_| 0).await
+    }
+
+    async fn commit(&self) -> Result<(), Error> {
+        self.observe("commit", self.inner.commit(), |_| 0).await
+    }
+
+    async fn rollback(&self) -> Result<(), Error> {
+        self.observe("rollback", self.inner.rollback(), |_| 0).await
+    }
+}
+
+/// An [`ObservedConnection`] whose queries and statements are recorded
+/// under a caller-supplied name.
+///
+/// Transaction control is recorded as on the connection itself.
+pub struct NamedStatement<'a, C, M: Metrics, K> {
+    conn: &'a ObservedConnection<C, M, K>,
+    name: &'a str,
+}
+
+impl<C: Connection, M: Metrics, K: MonotonicClock> Connection for NamedStatement<'_, C, M, K> {
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>, Error> {
+        let op = self.conn.inner.query(sql, params);
+        self.conn
+            .observe(self.name, op, |rows| rows.len() as u64)
+            .await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64, Error> {
+        let op = self.conn.inner.execute(sql, params);
+        self.conn.observe(self.name, op, |&affected| affected).await
+    }
+
+    async fn begin(&self) -> Result<(), Error> {
+        self.conn.begin().await
+    }
+
+    async fn commit(&self) -> Result<(), Error> {
+        self.conn.commit().await
+    }
+
+    async fn rollback(&self) -> Result<(), Error> {
+        self.conn.rollback().await
+    }
+}
+
+/// The leading keyword of `sql`, lowercased, as a default statement name.
+fn keyword(sql: &str) -> &'static str {
+    let word = sql
+        .trim_start()
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or("");
+    const KEYWORDS: &[&str] = &[
+        "select", "insert", "update", "delete", "with", "create", "drop", "alter", "pragma",
+    ];
+    KEYWORDS
+        .iter()
+        .find(|k| k.eq_ignore_ascii_case(word))
+        .copied()
+        .unwrap_or("other")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_clocks_mock::MockMonotonicClock;
+    use portals_observe_native::{NoopGauge, SharedCounter, SharedHistogram};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Metrics that hand out the same instrument for the same name.
+    #[derive(Default, Clone)]
+    struct Registry {
+        counters: Arc<Mutex<HashMap<String, SharedCounter>>>,
+        histograms: Arc<Mutex<HashMap<String, SharedHistogram>>>,
+    }
+
+    impl Registry {
+        fn count(&self, name: &str) -> u64 {
+            self.counters
+                .lock()
+                .unwrap()
+                .get(name)
+                .map_or(0, |c| c.value())
+        }
+
+        fn durations(&self, name: &str) -> Vec<f64> {
+            self.histograms
+                .lock()
+                .unwrap()
+                .get(name)
+                .map_or(Vec::new(), |h| h.values())
+        }
+    }
+
+    impl Metrics for Registry {
+        type Counter = SharedCounter;
+        type Gauge = NoopGauge;
+        type Histogram = SharedHistogram;
+
+        fn counter(&self, name: &str, _description: &str) -> SharedCounter {
+            let mut counters = self.counters.lock().unwrap();
+            counters.entry(name.to_string()).or_default().clone()
+        }
+
+        fn gauge(&self, _name: &str, _description: &str) -> NoopGauge {
+            NoopGauge
+        }
+
+        fn histogram(&self, name: &str, _description: &str) -> SharedHistogram {
+            let mut histograms = self.histograms.lock().unwrap();
+            histograms.entry(name.to_string()).or_default().clone()
+        }
+    }
+
+    /// A connection that takes 5ms per statement and fails on `FAIL`.
+    struct FakeDb {
+        clock: MockMonotonicClock,
+    }
+
+    impl FakeDb {
+        fn run(&self, sql: &str) -> Result<(), Error> {
+            self.clock.advance(Duration::from_millis(5));
+            if sql.contains("FAIL") {
+                return Err(Error::SyntaxError(sql.to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    impl Connection for FakeDb {
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>, Error> {
+            self.run(sql)?;
+            let row = Row::new(vec!["n".to_string()], vec![Value::Integer(1)]);
+            Ok(vec![row.clone(), row])
+        }
+
+        async fn execute(&self, sql: &str, _params: &[Value]) -> Result<u64, Error> {
+            self.run(sql)?;
+            Ok(3)
+        }
+
+        async fn begin(&self) -> Result<(), Error> {
+            self.run("BEGIN")
+        }
+
+        async fn commit(&self) -> Result<(), Error> {
+            self.run("COMMIT")
+        }
+
+        async fn rollback(&self) -> Result<(), Error> {
+            self.run("ROLLBACK")
+        }
+    }
+
+    fn observed() -> (
+        ObservedConnection<FakeDb, Registry, MockMonotonicClock>,
+        Registry,
+    ) {
+        let clock = MockMonotonicClock::new();
+        let registry = Registry::default();
+        let db = FakeDb {
+            clock: clock.clone(),
+        };
+        (
+            ObservedConnection::new(db, registry.clone(), clock),
+            registry,
+        )
+    }
+
+    #[tokio::test]
+    async fn records_named_statements() {
+        let (db, metrics) = observed();
+        let users = db.named("get_users");
+        users.query("SELECT * FROM users", &[]).await.unwrap();
+        users.query("SELECT * FROM users", &[]).await.unwrap();
+        users.query("SELECT FAIL", &[]).await.unwrap_err();
+
+        assert_eq!(metrics.count("sql.get_users.calls"), 3);
+        assert_eq!(metrics.count("sql.get_users.errors"), 1);
+        assert_eq!(metrics.count("sql.get_users.rows"), 4);
+        assert_eq!(
+            metrics.durations("sql.get_users.duration"),
+            [0.005, 0.005, 0.005]
+        );
+    }
+
+    #[tokio::test]
+    async fn labels_unnamed_statements_by_keyword() {
+        let (db, metrics) = observed();
+        let db = db.prefix("app.db");
+        db.execute("  insert INTO t VALUES (1)", &[]).await.unwrap();
+        db.query("with x AS (SELECT 1) SELECT * FROM x", &[])
+            .await
+            .unwrap();
+        db.execute("VACUUM", &[]).await.unwrap();
+        db.begin().await.unwrap();
+        db.rollback().await.unwrap();
+
+        assert_eq!(metrics.count("app.db.insert.rows"), 3);
+        assert_eq!(metrics.count("app.db.with.rows"), 2);
+        assert_eq!(metrics.count("app.db.other.calls"), 1);
+        assert_eq!(metrics.count("app.db.begin.calls"), 1);
+        assert_eq!(metrics.count("app.db.rollback.calls"), 1);
+        assert_eq!(metrics.count("app.db.commit.calls"), 0);
+    }
+}
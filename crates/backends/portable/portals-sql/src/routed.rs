@@ -0,0 +1,270 @@
+//! Read-replica routing.
+
+use portals_clocks::MonotonicClock;
+use portals_random::InsecureRandom;
+use portals_sql::{Connection, Error, Row, Value};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// A connection that sends reads to replicas and writes to the primary.
+///
+/// [`query`](Connection::query) goes to the replicas in turn, starting from
+/// a random one so that many clients don't all begin on the same replica.
+/// [`execute`](Connection::execute) and transactions go to the primary.
+///
+/// Replicas lag behind the primary, so after a write, queries stay on the
+/// primary for the sticky window (one second by default) and a client
+/// reads its own writes. Queries inside a transaction also go to the
+/// primary. With no replicas, everything goes to the primary.
+///
+/// ```ignore
+/// let db = RoutedConnection::new(primary, vec![replica_a, replica_b], SystemMonotonicClock, &mut rng)
+///     .sticky_window(Duration::from_millis(500));
+/// ```
+pub struct RoutedConnection<C, K> {
+    primary: C,
+    replicas: Vec<C>,
+    clock: K,
+    sticky_window: Duration,
+    next: AtomicUsize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// When the last write finished, in clock nanoseconds.
+    last_write: Option<u64>,
+    in_transaction: bool,
+}
+
+impl<C: Connection, K: MonotonicClock> RoutedConnection<C, K> {
+    /// Route between `primary` and `replicas`, picking the first replica
+    /// with `rng`.
+    pub fn new<R: InsecureRandom>(primary: C, replicas: Vec<C>, clock: K, rng: &mut R) -> Self {
+        let start = match replicas.len() {
+            0 => 0,
+            len => (rng.u64() % len as u64) as usize,
+        };
+        Self {
+            primary,
+            replicas,
+            clock,
+            sticky_window: Duration::from_secs(1),
+            next: AtomicUsize::new(start),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// How long after a write queries stay on the primary.
+    pub fn sticky_window(mut self, window: Duration) -> Self {
+        self.sticky_window = window;
+        self
+    }
+
+    /// The primary connection.
+    pub fn primary(&self) -> &C {
+        &self.primary
+    }
+
+    /// The replica connections.
+    pub fn replicas(&self) -> &[C] {
+        &self.replicas
+    }
+
+    /// The connection the next query will be sent to.
+    fn reader(&self) -> &C {
+        if self.replicas.is_empty() || self.pinned_to_primary() {
+            return &self.primary;
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+        &self.replicas[i]
+    }
+
+    fn pinned_to_primary(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        if state.in_transaction {
+            return true;
+        }
+        state.last_write.is_some_and(|at| {
+            self.clock.now().saturating_sub(at) < self.sticky_window.as_nanos() as u64
+        })
+    }
+
+    fn wrote(&self) {
+        self.state.lock().unwrap().last_write = Some(self.clock.now());
+    }
+}
+
+impl<C: Connection, K: MonotonicClock> Connection for RoutedConnection<C, K> {
+    async fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>, Error> {
+        self.reader().query(sql, params).await
+    }
+
+    async fn execute(&self, sql: &str, params: &[Value]) -> Result<u64, Error> {
+        let result = self.primary.execute(sql, params).await;
+        self.wrote();
+        result
+    }
+
+    async fn begin(&self) -> Result<(), Error> {
+        self.primary.begin().await?;
+        self.state.lock().unwrap().in_transaction = true;
+        Ok(())
+    }
+
+    async fn commit(&self) -> Result<(), Error> {
+        let result = self.primary.commit().await;
+        self.state.lock().unwrap().in_transaction = false;
+        self.wrote();
+        result
+    }
+
+    async fn rollback(&self) -> Result<(), Error> {
+        let result = self.primary.rollback().await;
+        self.state.lock().unwrap().in_transaction = false;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_clocks_mock::MockMonotonicClock;
+    use portals_random_mock::MockInsecureRandom;
+    use std::sync::Arc;
+
+    /// A connection that logs which statements reached it.
+    #[derive(Clone)]
+    struct Node {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Node {
+        fn log(&self, sql: &str) {
+            let entry = format!("{}: {}", self.name, sql);
+            self.log.lock().unwrap().push(entry);
+        }
+    }
+
+    impl Connection for Node {
+        async fn query(&self, sql: &str, _params: &[Value]) -> Result<Vec<Row>, Error> {
+            self.log(sql);
+            Ok(Vec::new())
+        }
+
+        async fn execute(&self, sql: &str, _params: &[Value]) -> Result<u64, Error> {
+            self.log(sql);
+            Ok(1)
+        }
+
+        async fn begin(&self) -> Result<(), Error> {
+            self.log("BEGIN");
+            Ok(())
+        }
+
+        async fn commit(&self) -> Result<(), Error> {
+            self.log("COMMIT");
+            Ok(())
+        }
+
+        async fn rollback(&self) -> Result<(), Error> {
+            self.log("ROLLBACK");
+            Ok(())
+        }
+    }
+
+    fn routed(
+        replicas: usize,
+    ) -> (
+        RoutedConnection<Node, MockMonotonicClock>,
+        MockMonotonicClock,
+        Arc<Mutex<Vec<String>>>,
+    ) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let node = |name| Node {
+            name,
+            log: log.clone(),
+        };
+        let replicas = ["r0", "r1", "r2"][..replicas]
+            .iter()
+            .map(|&name| node(name))
+            .collect();
+        let clock = MockMonotonicClock::new();
+        let mut rng = MockInsecureRandom::new(7);
+        let db = RoutedConnection::new(node("primary"), replicas, clock.clone(), &mut rng);
+        (db, clock, log)
+    }
+
+    fn take(log: &Mutex<Vec<String>>) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn rotates_reads_across_replicas() {
+        let (db, _clock, log) = routed(3);
+        for _ in 0..6 {
+            db.query("SELECT", &[]).await.unwrap();
+        }
+        let targets: Vec<_> = take(&log)
+            .into_iter()
+            .map(|entry| entry.split(':').next().unwrap().to_string())
+            .collect();
+        assert!(targets.iter().all(|t| t.starts_with('r')), "{:?}", targets);
+        // Each replica is used once per round, in order.
+        assert_eq!(targets[..3], targets[3..]);
+        let mut round = targets[..3].to_vec();
+        round.sort();
+        assert_eq!(round, ["r0", "r1", "r2"]);
+    }
+
+    #[tokio::test]
+    async fn reads_stick_to_primary_after_writes() {
+        let (db, clock, log) = routed(1);
+        db.execute("INSERT", &[]).await.unwrap();
+        clock.advance(Duration::from_millis(999));
+        db.query("SELECT 1", &[]).await.unwrap();
+        clock.advance(Duration::from_millis(1));
+        db.query("SELECT 2", &[]).await.unwrap();
+        assert_eq!(
+            take(&log),
+            ["primary: INSERT", "primary: SELECT 1", "r0: SELECT 2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn transactions_run_on_primary() {
+        let (db, clock, log) = routed(2);
+        let db = db.sticky_window(Duration::ZERO);
+        db.begin().await.unwrap();
+        clock.advance(Duration::from_secs(5));
+        db.query("SELECT", &[]).await.unwrap();
+        db.execute("UPDATE", &[]).await.unwrap();
+        clock.advance(Duration::from_secs(5));
+        db.query("SELECT", &[]).await.unwrap();
+        db.rollback().await.unwrap();
+        db.query("SELECT", &[]).await.unwrap();
+
+        let log = take(&log);
+        assert_eq!(
+            log[..5],
+            [
+                "primary: BEGIN",
+                "primary: SELECT",
+                "primary: UPDATE",
+                "primary: SELECT",
+                "primary: ROLLBACK",
+            ]
+        );
+        assert!(log[5].starts_with('r'), "{}", log[5]);
+    }
+
+    #[tokio::test]
+    async fn without_replicas_uses_primary() {
+        let (db, _clock, log) = routed(0);
+        db.query("SELECT", &[]).await.unwrap();
+        assert_eq!(take(&log), ["primary: SELECT"]);
+        assert!(db.replicas().is_empty());
+    }
+}
@@ -0,0 +1,539 @@
+//! WASM implementation of portals-blobstore.
+//!
+//! Stores containers in IndexedDB, so browser apps can keep blobs offline
+//! behind the same `Container` interface servers use. The database has
+//! three object stores:
+//!
+//! - `containers`, keyed by container name;
+//! - `objects`, keyed by `[container, name]`, holding each object's size,
+//!   creation time, and chunk count;
+//! - `chunks`, keyed by `[container, name, index]`, holding the data in
+//!   pieces of at most [`CHUNK_SIZE`] bytes.
+//!
+//! Each operation runs in a single transaction, so a failed `put` leaves
+//! the previous object intact. IndexedDB is looked up on `globalThis`, so
+//! the store works in browsers and web workers.
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use portals_blobstore::{Container, Error, ObjectMeta};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    IdbDatabase, IdbFactory, IdbKeyRange, IdbObjectStore, IdbRequest, IdbTransaction,
+    IdbTransactionMode, IdbVersionChangeEvent,
+};
+
+/// Largest piece an object's data is stored in.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+const SCHEMA_VERSION: u32 = 1;
+const CONTAINERS: &str = "containers";
+const OBJECTS: &str = "objects";
+const CHUNKS: &str = "chunks";
+
+/// Blob storage in an IndexedDB database.
+///
+/// Manages containers, like `MemoryBlobStore` does natively; container
+/// operations use the `Container` trait from the interface.
+///
+/// ```ignore
+/// let store = IdbBlobStore::open("my-app").await?;
+/// if !store.container_exists("drafts").await? {
+///     store.create_container("drafts").await?;
+/// }
+/// let drafts = store.open_container("drafts").await?;
+/// drafts.put("note.md", b"# Hello").await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct IdbBlobStore {
+    db: IdbDatabase,
+}
+
+impl IdbBlobStore {
+    /// Open the IndexedDB database `name`, creating it if needed.
+    pub async fn open(name: &str) -> Result<Self, Error> {
+        let factory = global_indexed_db()?;
+        let request = factory
+            .open_with_u32(name, SCHEMA_VERSION)
+            .map_err(js_error)?;
+        let upgrade = {
+            let request = request.clone();
+            Closure::<dyn FnMut(IdbVersionChangeEvent)>::new(move |_| {
+                if let Ok(db) = request.result() {
+                    create_schema(&db.unchecked_into());
+                }
+            })
+        };
+        request.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+        let result = wait(&request).await;
+        request.set_onupgradeneeded(None);
+        Ok(Self {
+            db: result?.unchecked_into(),
+        })
+    }
+
+    /// Wrap an already opened database.
+    ///
+    /// The database must have been opened through [`open`](Self::open) at
+    /// some point, so that its object stores exist.
+    pub fn from_database(db: IdbDatabase) -> Self {
+        Self { db }
+    }
+
+    /// Create a new container.
+    pub async fn create_container(&self, name: &str) -> Result<(), Error> {
+        let tx = transaction(&self.db, &[CONTAINERS], IdbTransactionMode::Readwrite)?;
+        let containers = object_store(&tx, CONTAINERS)?;
+        if count(&containers, &name.into()).await? > 0 {
+            return Err(Error::ContainerExists(name.to_string()));
+        }
+        containers
+            .put_with_key(&now().into(), &name.into())
+            .map_err(js_error)?;
+        commit(&tx).await
+    }
+
+    /// Delete a container and every object in it.
+    pub async fn delete_container(&self, name: &str) -> Result<(), Error> {
+        let tx = transaction(
+            &self.db,
+            &[CONTAINERS, OBJECTS, CHUNKS],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let containers = object_store(&tx, CONTAINERS)?;
+        if count(&containers, &name.into()).await? == 0 {
+            return Err(Error::ContainerNotFound(name.to_string()));
+        }
+        let range = prefix_range(&Array::of1(&name.into()))?;
+        containers.delete(&name.into()).map_err(js_error)?;
+        object_store(&tx, OBJECTS)?
+            .delete(&range)
+            .map_err(js_error)?;
+        object_store(&tx, CHUNKS)?
+            .delete(&range)
+            .map_err(js_error)?;
+        commit(&tx).await
+    }
+
+    /// Open a container by name.
+    pub async fn open_container(&self, name: &str) -> Result<IdbContainer, Error> {
+        if !self.container_exists(name).await? {
+            return Err(Error::ContainerNotFound(name.to_string()));
+        }
+        Ok(IdbContainer {
+            db: self.db.clone(),
+            name: name.to_string(),
+        })
+    }
+
+    /// Check if a container exists.
+    pub async fn container_exists(&self, name: &str) -> Result<bool, Error> {
+        let tx = transaction(&self.db, &[CONTAINERS], IdbTransactionMode::Readonly)?;
+        Ok(count(&object_store(&tx, CONTAINERS)?, &name.into()).await? > 0)
+    }
+
+    /// List all container names.
+    pub async fn list_containers(&self) -> Result<Vec<String>, Error> {
+        let tx = transaction(&self.db, &[CONTAINERS], IdbTransactionMode::Readonly)?;
+        let request = object_store(&tx, CONTAINERS)?
+            .get_all_keys()
+            .map_err(js_error)?;
+        let keys: Array = wait(&request).await?.unchecked_into();
+        Ok(keys.iter().filter_map(|key| key.as_string()).collect())
+    }
+
+    /// The underlying database.
+    pub fn database(&self) -> &IdbDatabase {
+        &self.db
+    }
+}
+
+/// A container in an [`IdbBlobStore`].
+///
+/// Operations on a container whose store entry has been deleted behave as
+/// if it were empty.
+#[derive(Debug, Clone)]
+pub struct IdbContainer {
+    db: IdbDatabase,
+    name: String,
+}
+
+impl IdbContainer {
+    /// The container's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn object_key(&self, name: &str) -> JsValue {
+        Array::of2(&self.name.as_str().into(), &name.into()).into()
+    }
+
+    /// The key range covering every chunk of `name`.
+    fn chunk_range(&self, name: &str) -> Result<JsValue, Error> {
+        prefix_range(&Array::of2(&self.name.as_str().into(), &name.into()))
+    }
+
+    fn chunk_key(&self, name: &str, index: usize) -> JsValue {
+        Array::of3(
+            &self.name.as_str().into(),
+            &name.into(),
+            &(index as f64).into(),
+        )
+        .into()
+    }
+
+    /// Look up the stored record for `name`.
+    async fn record(&self, objects: &IdbObjectStore, name: &str) -> Result<Record, Error> {
+        let request = objects.get(&self.object_key(name)).map_err(js_error)?;
+        Record::from_js(&wait(&request).await?)
+            .ok_or_else(|| Error::ObjectNotFound(name.to_string()))
+    }
+
+    /// Read every chunk of `name`, in order.
+    async fn read_chunks(&self, chunks: &IdbObjectStore, name: &str) -> Result<Vec<u8>, Error> {
+        let request = chunks
+            .get_all_with_key(&self.chunk_range(name)?)
+            .map_err(js_error)?;
+        let pieces: Array = wait(&request).await?.unchecked_into();
+        let mut data = Vec::new();
+        for piece in pieces.iter() {
+            data.extend(Uint8Array::new(&piece).to_vec());
+        }
+        Ok(data)
+    }
+
+    /// Replace `name` with `data` in the transaction's stores.
+    fn write(
+        &self,
+        objects: &IdbObjectStore,
+        chunks: &IdbObjectStore,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        chunks.delete(&self.chunk_range(name)?).map_err(js_error)?;
+        for (index, piece) in data.chunks(CHUNK_SIZE).enumerate() {
+            chunks
+                .put_with_key(&Uint8Array::from(piece), &self.chunk_key(name, index))
+                .map_err(js_error)?;
+        }
+        let record = Record {
+            size: data.len() as u64,
+            created_at: now(),
+            chunks: data.len().div_ceil(CHUNK_SIZE),
+        };
+        objects
+            .put_with_key(&record.to_js()?, &self.object_key(name))
+            .map_err(js_error)?;
+        Ok(())
+    }
+
+    fn meta(name: String, record: &Record) -> ObjectMeta {
+        ObjectMeta {
+            name,
+            size: record.size,
+            created_at: Some(record.created_at),
+            checksum: None,
+        }
+    }
+}
+
+impl Container for IdbContainer {
+    async fn get(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let tx = transaction(&self.db, &[OBJECTS, CHUNKS], IdbTransactionMode::Readonly)?;
+        self.record(&object_store(&tx, OBJECTS)?, name).await?;
+        self.read_chunks(&object_store(&tx, CHUNKS)?, name).await
+    }
+
+    async fn put(&self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let tx = transaction(&self.db, &[OBJECTS, CHUNKS], IdbTransactionMode::Readwrite)?;
+        self.write(
+            &object_store(&tx, OBJECTS)?,
+            &object_store(&tx, CHUNKS)?,
+            name,
+            data,
+        )?;
+        commit(&tx).await
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), Error> {
+        let tx = transaction(&self.db, &[OBJECTS, CHUNKS], IdbTransactionMode::Readwrite)?;
+        let objects = object_store(&tx, OBJECTS)?;
+        self.record(&objects, name).await?;
+        objects.delete(&self.object_key(name)).map_err(js_error)?;
+        object_store(&tx, CHUNKS)?
+            .delete(&self.chunk_range(name)?)
+            .map_err(js_error)?;
+        commit(&tx).await
+    }
+
+    async fn exists(&self, name: &str) -> Result<bool, Error> {
+        let tx = transaction(&self.db, &[OBJECTS], IdbTransactionMode::Readonly)?;
+        Ok(count(&object_store(&tx, OBJECTS)?, &self.object_key(name)).await? > 0)
+    }
+
+    async fn list(&self) -> Result<Vec<ObjectMeta>, Error> {
+        let tx = transaction(&self.db, &[OBJECTS], IdbTransactionMode::Readonly)?;
+        let objects = object_store(&tx, OBJECTS)?;
+        let range = prefix_range(&Array::of1(&self.name.as_str().into()))?;
+        let keys = objects.get_all_keys_with_key(&range).map_err(js_error)?;
+        let values = objects.get_all_with_key(&range).map_err(js_error)?;
+        let keys: Array = wait(&keys).await?.unchecked_into();
+        let values: Array = wait(&values).await?.unchecked_into();
+        keys.iter()
+            .zip(values.iter())
+            .map(|(key, value)| {
+                let name = Array::from(&key)
+                    .get(1)
+                    .as_string()
+                    .ok_or_else(|| corrupt(&self.name))?;
+                let record = Record::from_js(&value).ok_or_else(|| corrupt(&name))?;
+                Ok(Self::meta(name, &record))
+            })
+            .collect()
+    }
+
+    async fn metadata(&self, name: &str) -> Result<ObjectMeta, Error> {
+        let tx = transaction(&self.db, &[OBJECTS], IdbTransactionMode::Readonly)?;
+        let record = self.record(&object_store(&tx, OBJECTS)?, name).await?;
+        Ok(Self::meta(name.to_string(), &record))
+    }
+
+    async fn copy(&self, src: &str, dst: &str) -> Result<(), Error> {
+        let tx = transaction(&self.db, &[OBJECTS, CHUNKS], IdbTransactionMode::Readwrite)?;
+        let objects = object_store(&tx, OBJECTS)?;
+        let chunks = object_store(&tx, CHUNKS)?;
+        self.record(&objects, src).await?;
+        let data = self.read_chunks(&chunks, src).await?;
+        self.write(&objects, &chunks, dst, &data)?;
+        commit(&tx).await
+    }
+}
+
+/// What the `objects` store holds for each object.
+struct Record {
+    size: u64,
+    /// Unix timestamp in seconds.
+    created_at: u64,
+    chunks: usize,
+}
+
+impl Record {
+    fn to_js(&self) -> Result<JsValue, Error> {
+        let record = Object::new();
+        for (field, value) in [
+            ("size", self.size as f64),
+            ("createdAt", self.created_at as f64),
+            ("chunks", self.chunks as f64),
+        ] {
+            Reflect::set(&record, &field.into(), &value.into()).map_err(js_error)?;
+        }
+        Ok(record.into())
+    }
+
+    /// Read a record, or `None` if `value` is not one (e.g. `undefined`
+    /// for a missing key).
+    fn from_js(value: &JsValue) -> Option<Self> {
+        if !value.is_object() {
+            return None;
+        }
+        let field = |name: &str| Reflect::get(value, &name.into()).ok()?.as_f64();
+        Some(Self {
+            size: field("size")? as u64,
+            created_at: field("createdAt")? as u64,
+            chunks: field("chunks")? as usize,
+        })
+    }
+}
+
+/// Create the object stores, as the database is created or upgraded.
+fn create_schema(db: &IdbDatabase) {
+    let existing = db.object_store_names();
+    for name in [CONTAINERS, OBJECTS, CHUNKS] {
+        if !existing.contains(name) {
+            let _ = db.create_object_store(name);
+        }
+    }
+}
+
+/// Look up `globalThis.indexedDB`.
+fn global_indexed_db() -> Result<IdbFactory, Error> {
+    let factory =
+        Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB")).map_err(js_error)?;
+    if !factory.is_object() {
+        return Err(Error::Store("IndexedDB is not available".to_string()));
+    }
+    Ok(factory.unchecked_into())
+}
+
+fn transaction(
+    db: &IdbDatabase,
+    stores: &[&str],
+    mode: IdbTransactionMode,
+) -> Result<IdbTransaction, Error> {
+    let names: Array = stores.iter().map(|s| JsValue::from_str(s)).collect();
+    db.transaction_with_str_sequence_and_mode(&names, mode)
+        .map_err(js_error)
+}
+
+fn object_store(tx: &IdbTransaction, name: &str) -> Result<IdbObjectStore, Error> {
+    tx.object_store(name).map_err(js_error)
+}
+
+/// The key range covering every array key that starts with `prefix`.
+///
+/// Arrays sort after numbers and strings, so `[...prefix, []]` is greater
+/// than any key extending `prefix` with them.
+fn prefix_range(prefix: &Array) -> Result<JsValue, Error> {
+    let upper = prefix.concat(&Array::of1(&Array::new()));
+    IdbKeyRange::bound(prefix, &upper)
+        .map(Into::into)
+        .map_err(js_error)
+}
+
+async fn count(store: &IdbObjectStore, key: &JsValue) -> Result<u32, Error> {
+    let request = store.count_with_key(key).map_err(js_error)?;
+    Ok(wait(&request).await?.as_f64().unwrap_or(0.0) as u32)
+}
+
+/// Wait for `request` to finish and return its result.
+async fn wait(request: &IdbRequest) -> Result<JsValue, Error> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    let outcome = JsFuture::from(promise).await;
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+    match outcome {
+        Ok(_) => request.result().map_err(js_error),
+        Err(_) => Err(match request.error() {
+            Ok(Some(e)) => Error::Store(e.message()),
+            _ => Error::Store("IndexedDB request failed".to_string()),
+        }),
+    }
+}
+
+/// Wait for `tx` to commit.
+async fn commit(tx: &IdbTransaction) -> Result<(), Error> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        tx.set_oncomplete(Some(&resolve));
+        tx.set_onerror(Some(&reject));
+        tx.set_onabort(Some(&reject));
+    });
+    let outcome = JsFuture::from(promise).await;
+    tx.set_oncomplete(None);
+    tx.set_onerror(None);
+    tx.set_onabort(None);
+    outcome.map(|_| ()).map_err(|_| match tx.error() {
+        Some(e) => Error::Store(e.message()),
+        None => Error::Store("IndexedDB transaction aborted".to_string()),
+    })
+}
+
+/// The current Unix time in seconds.
+fn now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+fn corrupt(name: &str) -> Error {
+    Error::Store(format!("malformed record for: {}", name))
+}
+
+fn js_error(value: JsValue) -> Error {
+    let message = value
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| value.as_string())
+        .unwrap_or_else(|| format!("{:?}", value));
+    Error::Store(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    async fn store(name: &str) -> IdbBlobStore {
+        let store = IdbBlobStore::open(name).await.unwrap();
+        for container in store.list_containers().await.unwrap() {
+            store.delete_container(&container).await.unwrap();
+        }
+        store
+    }
+
+    #[wasm_bindgen_test]
+    async fn container_lifecycle() {
+        let store = store("portals-blobstore-wasm-lifecycle").await;
+
+        store.create_container("test").await.unwrap();
+        assert!(store.container_exists("test").await.unwrap());
+        assert!(matches!(
+            store.create_container("test").await,
+            Err(Error::ContainerExists(_))
+        ));
+        assert_eq!(store.list_containers().await.unwrap(), ["test"]);
+
+        let container = store.open_container("test").await.unwrap();
+        container.put("a.txt", b"aaa").await.unwrap();
+        store.delete_container("test").await.unwrap();
+        assert!(!store.container_exists("test").await.unwrap());
+        assert!(matches!(
+            store.open_container("test").await,
+            Err(Error::ContainerNotFound(_))
+        ));
+
+        // Recreating the container does not bring its objects back.
+        store.create_container("test").await.unwrap();
+        let container = store.open_container("test").await.unwrap();
+        assert!(container.list().await.unwrap().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    async fn object_operations() {
+        let store = store("portals-blobstore-wasm-objects").await;
+        store.create_container("bucket").await.unwrap();
+        store.create_container("other").await.unwrap();
+        let container = store.open_container("bucket").await.unwrap();
+        let other = store.open_container("other").await.unwrap();
+
+        let large: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        container.put("large", &large).await.unwrap();
+        container.put("empty", b"").await.unwrap();
+        other.put("large", b"elsewhere").await.unwrap();
+        assert_eq!(container.get("large").await.unwrap(), large);
+        assert_eq!(container.get("empty").await.unwrap(), b"");
+        assert_eq!(other.get("large").await.unwrap(), b"elsewhere");
+
+        // Shrinking an object drops its extra chunks.
+        container.put("large", b"small").await.unwrap();
+        assert_eq!(container.get("large").await.unwrap(), b"small");
+        assert_eq!(container.metadata("large").await.unwrap().size, 5);
+
+        container.copy("large", "copy").await.unwrap();
+        assert_eq!(container.get("copy").await.unwrap(), b"small");
+        let mut names: Vec<_> = container
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|meta| meta.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["copy", "empty", "large"]);
+
+        container.delete("large").await.unwrap();
+        assert!(!container.exists("large").await.unwrap());
+        assert!(matches!(
+            container.get("large").await,
+            Err(Error::ObjectNotFound(name)) if name == "large"
+        ));
+        assert!(matches!(
+            container.delete("large").await,
+            Err(Error::ObjectNotFound(_))
+        ));
+        assert!(other.exists("large").await.unwrap());
+    }
+}
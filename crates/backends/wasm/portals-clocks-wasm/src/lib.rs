@@ -1,12 +1,17 @@
 //! WASM implementation of portals-clocks.
 //!
-//! Uses browser APIs:
+//! Uses JavaScript APIs:
 //! - `Date.now()` for wall clock
-//! - `performance.now()` for monotonic clock
+//! - `performance.now()` for monotonic clock, falling back to `Date.now()`
 //! - `setTimeout` for timers
+//!
+//! Everything is looked up on `globalThis` rather than `window`, so the
+//! clocks work in browsers, web workers, and Node.
 
 use portals_clocks::{MonotonicClock, WallClock};
+use std::cell::Cell;
 use std::time::Duration;
+use wasm_bindgen::{JsCast, JsValue};
 
 /// Wall clock using JavaScript Date.
 #[derive(Debug, Default, Clone, Copy)]
@@ -28,12 +33,36 @@ impl WallClock for JsWallClock {
 }
 
 /// Monotonic clock using Performance.now().
+///
+/// Where `performance` is unavailable, falls back to `Date.now()`, clamped
+/// so it never goes backwards.
 #[derive(Debug, Clone)]
 pub struct PerformanceClock {
-    /// The epoch value from performance.now() when this clock was created.
+    source: Source,
+    /// The source's value when this clock was created.
     epoch_ms: f64,
 }
 
+#[derive(Debug, Clone)]
+enum Source {
+    Performance(web_sys::Performance),
+    /// The latest `Date.now()` seen, to keep readings monotonic.
+    Date(Cell<f64>),
+}
+
+impl Source {
+    fn now(&self) -> f64 {
+        match self {
+            Self::Performance(performance) => performance.now(),
+            Self::Date(last) => {
+                let now = js_sys::Date::now().max(last.get());
+                last.set(now);
+                now
+            }
+        }
+    }
+}
+
 impl Default for PerformanceClock {
     fn default() -> Self {
         Self::new()
@@ -42,16 +71,36 @@ impl Default for PerformanceClock {
 
 impl PerformanceClock {
     /// Create a new monotonic clock with epoch at creation time.
+    ///
+    /// Uses `globalThis.performance` if present, and `Date.now()` otherwise.
     pub fn new() -> Self {
-        Self {
-            epoch_ms: performance_now(),
+        match global_performance() {
+            Some(performance) => Self::with_performance(performance),
+            None => Self::from_source(Source::Date(Cell::new(f64::MIN))),
         }
     }
+
+    /// Create a clock reading the given `Performance` object, such as one
+    /// from `WorkerGlobalScope::performance()`.
+    pub fn with_performance(performance: web_sys::Performance) -> Self {
+        Self::from_source(Source::Performance(performance))
+    }
+
+    fn from_source(source: Source) -> Self {
+        let epoch_ms = source.now();
+        Self { source, epoch_ms }
+    }
+
+    /// Whether the clock reads `performance.now()` rather than falling back
+    /// to `Date.now()`.
+    pub fn is_high_resolution(&self) -> bool {
+        matches!(self.source, Source::Performance(_))
+    }
 }
 
 impl MonotonicClock for PerformanceClock {
     fn now(&self) -> u64 {
-        let elapsed_ms = performance_now() - self.epoch_ms;
+        let elapsed_ms = self.source.now() - self.epoch_ms;
         // Convert milliseconds to nanoseconds
         (elapsed_ms * 1_000_000.0) as u64
     }
@@ -79,13 +128,18 @@ impl MonotonicClock for PerformanceClock {
     }
 }
 
-/// Get the current performance.now() value.
-fn performance_now() -> f64 {
-    web_sys::window()
-        .expect("should have window")
-        .performance()
-        .expect("should have performance")
-        .now()
+/// Look up `globalThis.performance`, if it has a `now` method.
+fn global_performance() -> Option<web_sys::Performance> {
+    let performance =
+        js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("performance")).ok()?;
+    if !performance.is_object() {
+        return None;
+    }
+    let now = js_sys::Reflect::get(&performance, &JsValue::from_str("now")).ok()?;
+    if !now.is_function() {
+        return None;
+    }
+    Some(performance.unchecked_into())
 }
 
 #[cfg(test)]
@@ -116,6 +170,20 @@ mod tests {
         assert!(t2 >= t1);
     }
 
+    #[wasm_bindgen_test]
+    fn uses_performance_when_available() {
+        assert!(PerformanceClock::new().is_high_resolution());
+    }
+
+    #[wasm_bindgen_test]
+    fn date_fallback_increases() {
+        let clock = PerformanceClock::from_source(Source::Date(Cell::new(f64::MIN)));
+        assert!(!clock.is_high_resolution());
+        let t1 = clock.now();
+        let t2 = clock.now();
+        assert!(t2 >= t1);
+    }
+
     #[wasm_bindgen_test]
     async fn subscribe_duration_works() {
         let clock = PerformanceClock::new();
@@ -0,0 +1,342 @@
+//! WASM implementation of portals-config.
+//!
+//! Reads configuration from the page's query string and from
+//! localStorage, with the dotted keys `FileConfig` uses natively: the
+//! setting `database.url` is `?database.url=...` in the URL and the item
+//! `database.url` in storage. Both are looked up on `globalThis`, so they
+//! work in browsers and, where available, in web workers.
+
+use js_sys::Reflect;
+use portals_config::{Config, ConfigMut, Error};
+use std::collections::HashMap;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::Storage;
+
+/// Configuration from URL query parameters.
+///
+/// The query string is read once, when the config is created. Names and
+/// values are percent-decoded, with `+` as a space; a name given more
+/// than once takes its last value, and a name without `=` is set to the
+/// empty string.
+#[derive(Debug, Clone, Default)]
+pub struct QueryConfig {
+    values: HashMap<String, String>,
+}
+
+impl QueryConfig {
+    /// Read the current page's query string.
+    ///
+    /// Outside a page, or where there is no `location`, the config is
+    /// empty.
+    pub fn new() -> Self {
+        let search = Reflect::get(&js_sys::global(), &JsValue::from_str("location"))
+            .ok()
+            .filter(|location| location.is_object())
+            .and_then(|location| Reflect::get(&location, &JsValue::from_str("search")).ok())
+            .and_then(|search| search.as_string())
+            .unwrap_or_default();
+        Self::from_query(&search)
+    }
+
+    /// Read `query`, with or without its leading `?`.
+    pub fn from_query(query: &str) -> Self {
+        let query = query.strip_prefix('?').unwrap_or(query);
+        let values = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(name), decode(value))
+            })
+            .collect();
+        Self { values }
+    }
+}
+
+impl Config for QueryConfig {
+    fn get(&self, key: &str) -> Result<String, Error> {
+        self.values
+            .get(key)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(key.to_string()))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+}
+
+/// Configuration kept in localStorage.
+///
+/// Values persist across page loads, so a setting changed with
+/// [`ConfigMut::set`] stays changed. With a prefix, keys are stored as
+/// `{prefix}.{key}`, keeping one app's settings apart from other data in
+/// the same origin's storage.
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    storage: Storage,
+    prefix: Option<String>,
+}
+
+impl StorageConfig {
+    /// Use localStorage.
+    ///
+    /// Fails where storage is unavailable, such as in web workers or when
+    /// the user has blocked it.
+    pub fn new() -> Result<Self, Error> {
+        let storage = Reflect::get(&js_sys::global(), &JsValue::from_str("localStorage"))
+            .map_err(js_error)?;
+        if !storage.is_object() {
+            return Err(Error::Other("localStorage is not available".to_string()));
+        }
+        Ok(Self::from_storage(storage.unchecked_into()))
+    }
+
+    /// Use `storage`, such as sessionStorage.
+    pub fn from_storage(storage: Storage) -> Self {
+        Self {
+            storage,
+            prefix: None,
+        }
+    }
+
+    /// Store keys under `prefix`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    fn make_key(&self, key: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}.{}", prefix, key),
+            None => key.to_string(),
+        }
+    }
+}
+
+impl Config for StorageConfig {
+    fn get(&self, key: &str) -> Result<String, Error> {
+        self.storage
+            .get_item(&self.make_key(key))
+            .map_err(js_error)?
+            .ok_or_else(|| Error::NotFound(key.to_string()))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let len = self.storage.length().unwrap_or(0);
+        (0..len)
+            .filter_map(|i| self.storage.key(i).ok().flatten())
+            .filter_map(|key| unprefixed(self.prefix.as_deref(), key))
+            .collect()
+    }
+}
+
+impl ConfigMut for StorageConfig {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        self.storage
+            .set_item(&self.make_key(key), value)
+            .map_err(js_error)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Error> {
+        let stored = self.make_key(key);
+        if self.storage.get_item(&stored).map_err(js_error)?.is_none() {
+            return Err(Error::NotFound(key.to_string()));
+        }
+        self.storage.remove_item(&stored).map_err(js_error)
+    }
+}
+
+/// Configuration from the query string over localStorage.
+///
+/// Query parameters take precedence, so a link can override a stored
+/// setting for one visit. With a prefix, both sources use it: the key
+/// `debug` under the prefix `app` is `?app.debug=1` or the stored item
+/// `app.debug`. Storage is skipped where it is unavailable.
+///
+/// ```ignore
+/// let config = WebConfig::new().with_prefix("app");
+/// let endpoint = config.get_optional("api.url").unwrap_or(DEFAULT_API);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebConfig {
+    query: QueryConfig,
+    storage: Option<StorageConfig>,
+    prefix: Option<String>,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebConfig {
+    /// Read the current page's query string and localStorage.
+    pub fn new() -> Self {
+        Self::from_sources(QueryConfig::new(), StorageConfig::new().ok())
+    }
+
+    /// Combine `query` with `storage`, if any.
+    pub fn from_sources(query: QueryConfig, storage: Option<StorageConfig>) -> Self {
+        Self {
+            query,
+            storage,
+            prefix: None,
+        }
+    }
+
+    /// Look keys up under `prefix` in both sources.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.storage = self
+            .storage
+            .map(|storage| storage.with_prefix(prefix.clone()));
+        self.prefix = Some(prefix);
+        self
+    }
+
+    fn query_key(&self, key: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}.{}", prefix, key),
+            None => key.to_string(),
+        }
+    }
+}
+
+impl Config for WebConfig {
+    fn get(&self, key: &str) -> Result<String, Error> {
+        match self.query.get(&self.query_key(key)) {
+            Err(Error::NotFound(_)) => match &self.storage {
+                Some(storage) => storage.get(key),
+                None => Err(Error::NotFound(key.to_string())),
+            },
+            result => result,
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .query
+            .keys()
+            .into_iter()
+            .filter_map(|key| unprefixed(self.prefix.as_deref(), key))
+            .chain(self.storage.iter().flat_map(|storage| storage.keys()))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+/// `key` without `prefix` and its dot, or `None` if it lacks them.
+fn unprefixed(prefix: Option<&str>, key: String) -> Option<String> {
+    match prefix {
+        Some(prefix) => Some(key.strip_prefix(prefix)?.strip_prefix('.')?.to_string()),
+        None => Some(key),
+    }
+}
+
+/// Percent-decode a query string component, reading `+` as a space.
+///
+/// Malformed escapes are kept as they are, and invalid UTF-8 is replaced.
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let digit = |at: usize| bytes.get(at).and_then(|&b| hex(b));
+                match (digit(i + 1), digit(i + 2)) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|d| d as u8)
+}
+
+fn js_error(value: JsValue) -> Error {
+    let message = value
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| value.as_string())
+        .unwrap_or_else(|| format!("{:?}", value));
+    Error::Other(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn storage(prefix: &str) -> StorageConfig {
+        let mut config = StorageConfig::new().unwrap().with_prefix(prefix);
+        for key in config.keys() {
+            config.remove(&key).unwrap();
+        }
+        config
+    }
+
+    #[wasm_bindgen_test]
+    fn parses_query_strings() {
+        let config = QueryConfig::from_query(
+            "?database.url=postgres%3A%2F%2Fdb&name=a+b&flag&x=1&x=2&bad=%zz",
+        );
+        assert_eq!(config.get("database.url").unwrap(), "postgres://db");
+        assert_eq!(config.get("name").unwrap(), "a b");
+        assert_eq!(config.get("flag").unwrap(), "");
+        assert_eq!(config.get("x").unwrap(), "2");
+        assert_eq!(config.get("bad").unwrap(), "%zz");
+        assert!(matches!(config.get("missing"), Err(Error::NotFound(_))));
+        assert!(QueryConfig::from_query("").keys().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn stores_settings() {
+        let mut config = storage("portals-config-wasm-store");
+        config.set("theme", "dark").unwrap();
+        assert_eq!(config.get("theme").unwrap(), "dark");
+        assert_eq!(config.keys(), ["theme"]);
+        assert_eq!(
+            StorageConfig::new()
+                .unwrap()
+                .get("portals-config-wasm-store.theme")
+                .unwrap(),
+            "dark"
+        );
+        config.remove("theme").unwrap();
+        assert!(matches!(config.remove("theme"), Err(Error::NotFound(_))));
+    }
+
+    #[wasm_bindgen_test]
+    fn query_overrides_storage() {
+        let mut stored = storage("portals-config-wasm-web");
+        stored.set("api.url", "https://stored").unwrap();
+        stored.set("debug", "0").unwrap();
+
+        let config = WebConfig::from_sources(
+            QueryConfig::from_query("portals-config-wasm-web.debug=1&other=x"),
+            Some(StorageConfig::new().unwrap()),
+        )
+        .with_prefix("portals-config-wasm-web");
+        assert_eq!(config.get("debug").unwrap(), "1");
+        assert_eq!(config.get("api.url").unwrap(), "https://stored");
+        assert_eq!(config.keys(), ["api.url", "debug"]);
+        assert!(config.get("other").is_err());
+    }
+}
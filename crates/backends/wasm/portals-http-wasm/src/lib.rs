@@ -3,8 +3,7 @@
 //! Uses the Fetch API via `gloo-net`.
 
 use gloo_net::http::RequestBuilder;
-use portals_http::{Error, HttpClient, Method, Request, Response};
-use std::collections::HashMap;
+use portals_http::{Error, Headers, HttpClient, Method, Request, Response};
 
 /// HTTP client using the Fetch API.
 #[derive(Debug, Default, Clone, Copy)]
@@ -43,7 +42,7 @@ impl HttpClient for FetchClient {
 
         let status = gloo_response.status();
 
-        let headers = HashMap::new();
+        let headers = Headers::new();
         // gloo-net doesn't expose headers iterator directly
         // For full header access, we'd need to use web-sys directly
 
@@ -56,6 +55,7 @@ impl HttpClient for FetchClient {
             status,
             headers,
             body,
+            redirects: Vec::new(),
         })
     }
 }
@@ -0,0 +1,274 @@
+//! Archive interfaces.
+//!
+//! Read and write tar and zip archives as streams of entries over
+//! [`portals_io`] streams.
+
+pub use portals_error::{ErrorKind, PithError};
+use portals_filesystem::Directory;
+use portals_io::{InputStream, OutputStream, StreamError};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Kind of archive entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// Anything else (devices, FIFOs, hard links).
+    Other,
+}
+
+/// An archive entry's header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path within the archive, `/`-separated.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size of the entry's data in bytes.
+    pub size: u64,
+    /// Unix permission bits.
+    pub mode: u32,
+    /// Modification time in seconds since the Unix epoch.
+    pub modified: Option<u64>,
+    /// Target of a symlink.
+    pub link_target: Option<String>,
+}
+
+impl Entry {
+    /// A regular file of `size` bytes.
+    pub fn file(path: impl Into<String>, size: u64) -> Self {
+        Self {
+            path: path.into(),
+            kind: EntryKind::File,
+            size,
+            mode: 0o644,
+            modified: None,
+            link_target: None,
+        }
+    }
+
+    /// A directory.
+    pub fn directory(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            kind: EntryKind::Directory,
+            size: 0,
+            mode: 0o755,
+            modified: None,
+            link_target: None,
+        }
+    }
+
+    /// A symlink pointing at `target`.
+    pub fn symlink(path: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            kind: EntryKind::Symlink,
+            size: 0,
+            mode: 0o777,
+            modified: None,
+            link_target: Some(target.into()),
+        }
+    }
+
+    /// Set the permission bits.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the modification time.
+    pub fn modified(mut self, secs: u64) -> Self {
+        self.modified = Some(secs);
+        self
+    }
+}
+
+/// Reads entries from an archive, in order.
+pub trait ArchiveReader {
+    /// Advance to the next entry, skipping any unread data of the current
+    /// one. Returns `None` at the end of the archive.
+    fn next_entry(&mut self) -> Result<Option<Entry>, ArchiveError>;
+
+    /// Read the current entry's data into `buf`.
+    ///
+    /// Returns the number of bytes read, or `0` once the entry's data is
+    /// exhausted.
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, ArchiveError>;
+
+    /// Read the rest of the current entry's data.
+    fn read_to_end(&mut self) -> Result<Vec<u8>, ArchiveError> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = self.read_data(&mut buf)?;
+            if n == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    /// Extract the remaining entries into `dir`, creating parent directories
+    /// as needed. Returns the number of files written.
+    ///
+    /// Every path is checked with [`safe_path`] first, so an archive can't
+    /// write outside `dir`. Symlinks and other special entries are skipped.
+    fn extract_to<D: Directory>(&mut self, dir: &D) -> Result<usize, ArchiveError> {
+        let mut files = 0;
+        while let Some(entry) = self.next_entry()? {
+            let path = safe_path(&entry.path)?;
+            match entry.kind {
+                EntryKind::Directory => create_dirs(dir, &path)?,
+                EntryKind::File => {
+                    if let Some(parent) = path.parent() {
+                        create_dirs(dir, parent)?;
+                    }
+                    let mut out = dir.open_write(&path)?;
+                    let mut buf = [0u8; 8192];
+                    loop {
+                        let n = self.read_data(&mut buf)?;
+                        if n == 0 {
+                            break;
+                        }
+                        out.blocking_write(&buf[..n])?;
+                    }
+                    out.blocking_flush()?;
+                    files += 1;
+                }
+                EntryKind::Symlink | EntryKind::Other => {}
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Appends entries to an archive.
+///
+/// Entries are written in the order they are appended. Call
+/// [`finish`](Self::finish) to write the archive trailer.
+pub trait ArchiveWriter {
+    /// Append an entry whose data is in memory. `entry.size` is ignored in
+    /// favor of `data.len()`.
+    fn append_data(&mut self, entry: &Entry, data: &[u8]) -> Result<(), ArchiveError>;
+
+    /// Append an entry, reading exactly `entry.size` bytes of data from
+    /// `data`.
+    fn append_stream<R: InputStream>(
+        &mut self,
+        entry: &Entry,
+        data: &mut R,
+    ) -> Result<(), ArchiveError>;
+
+    /// Append a regular file.
+    fn append_file(&mut self, path: &str, data: &[u8]) -> Result<(), ArchiveError> {
+        self.append_data(&Entry::file(path, data.len() as u64), data)
+    }
+
+    /// Append a directory.
+    fn append_dir(&mut self, path: &str) -> Result<(), ArchiveError> {
+        self.append_data(&Entry::directory(path), &[])
+    }
+
+    /// Write the archive trailer and flush. No entries may be appended
+    /// afterwards.
+    fn finish(&mut self) -> Result<(), ArchiveError>;
+}
+
+/// Convert an archive path to a relative path that stays inside the
+/// extraction directory.
+///
+/// Both `/` and `\` separate components; `.` components are dropped.
+/// Absolute paths, drive prefixes, `..` components, and empty paths are
+/// rejected with [`ArchiveError::UnsafePath`].
+pub fn safe_path(path: &str) -> Result<PathBuf, ArchiveError> {
+    let unsafe_path = || ArchiveError::UnsafePath(path.to_string());
+    if path.starts_with(['/', '\\']) || path.contains('\0') {
+        return Err(unsafe_path());
+    }
+
+    let mut out = PathBuf::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return Err(unsafe_path()),
+            _ if part.contains(':') => return Err(unsafe_path()),
+            _ => out.push(part),
+        }
+    }
+    if out.as_os_str().is_empty() || !out.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(unsafe_path());
+    }
+    Ok(out)
+}
+
+/// Create `path` and its ancestors, ignoring those that already exist.
+fn create_dirs<D: Directory>(dir: &D, path: &Path) -> Result<(), ArchiveError> {
+    let mut current = PathBuf::new();
+    for component in path.components() {
+        current.push(component);
+        match dir.create_dir(&current) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::Conflict => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Archive errors.
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// The underlying stream failed.
+    Stream(StreamError),
+    /// Extraction into a directory failed.
+    Filesystem(portals_filesystem::Error),
+    /// An entry path is absolute or escapes the extraction directory.
+    UnsafePath(String),
+    /// The archive is malformed or truncated.
+    Corrupt(String),
+    /// The archive uses a feature this backend doesn't implement.
+    Unsupported(String),
+    /// Other error.
+    Other(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stream(e) => write!(f, "stream error: {}", e),
+            Self::Filesystem(e) => write!(f, "filesystem error: {}", e),
+            Self::UnsafePath(path) => write!(f, "unsafe entry path: {:?}", path),
+            Self::Corrupt(msg) => write!(f, "corrupt archive: {}", msg),
+            Self::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl PithError for ArchiveError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Stream(e) => e.kind(),
+            Self::Filesystem(e) => e.kind(),
+            Self::UnsafePath(_) | Self::Corrupt(_) => ErrorKind::InvalidInput,
+            Self::Unsupported(_) => ErrorKind::Unsupported,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<StreamError> for ArchiveError {
+    fn from(e: StreamError) -> Self {
+        Self::Stream(e)
+    }
+}
+
+impl From<portals_filesystem::Error> for ArchiveError {
+    fn from(e: portals_filesystem::Error) -> Self {
+        Self::Filesystem(e)
+    }
+}
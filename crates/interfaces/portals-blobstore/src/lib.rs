@@ -5,6 +5,7 @@
 //!
 //! See ADR-0004 for rationale.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::future::Future;
 
@@ -14,6 +15,7 @@ pub enum Error {
     ContainerNotFound(String),
     ObjectNotFound(String),
     ContainerExists(String),
+    Integrity(IntegrityError),
     Store(String),
 }
 
@@ -23,6 +25,7 @@ impl fmt::Display for Error {
             Error::ContainerNotFound(name) => write!(f, "container not found: {}", name),
             Error::ObjectNotFound(name) => write!(f, "object not found: {}", name),
             Error::ContainerExists(name) => write!(f, "container already exists: {}", name),
+            Error::Integrity(e) => write!(f, "{}", e),
             Error::Store(msg) => write!(f, "store error: {}", msg),
         }
     }
@@ -30,6 +33,41 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ContainerNotFound(_) | Self::ObjectNotFound(_) => ErrorKind::NotFound,
+            Self::ContainerExists(_) => ErrorKind::Conflict,
+            Self::Integrity(_) | Self::Store(_) => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<IntegrityError> for Error {
+    fn from(e: IntegrityError) -> Self {
+        Self::Integrity(e)
+    }
+}
+
+/// Stored data no longer matches the checksum recorded when it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityError {
+    /// Name of the object.
+    pub name: String,
+    /// Checksum recorded on `put`.
+    pub expected: Vec<u8>,
+    /// Checksum of the data read back.
+    pub actual: Vec<u8>,
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "checksum mismatch for object: {}", self.name)
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
 /// Metadata for a stored object.
 #[derive(Debug, Clone)]
 pub struct ObjectMeta {
@@ -39,6 +77,8 @@ pub struct ObjectMeta {
     pub size: u64,
     /// When the object was created (Unix timestamp).
     pub created_at: Option<u64>,
+    /// Digest of the data, if the backend records checksums.
+    pub checksum: Option<Vec<u8>>,
 }
 
 /// A blob storage container.
@@ -56,6 +96,9 @@ pub struct ObjectMeta {
 /// ```
 pub trait Container {
     /// Get object data.
+    ///
+    /// Backends that record checksums verify the data against it and fail
+    /// with [`Error::Integrity`] if they differ.
     fn get(&self, name: &str) -> impl Future<Output = Result<Vec<u8>, Error>>;
 
     /// Store object data.
@@ -2,6 +2,7 @@
 //!
 //! In-memory caching with optional TTL (time-to-live) support.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::time::Duration;
 
@@ -127,6 +128,17 @@ impl fmt::Display for CacheError {
 
 impl std::error::Error for CacheError {}
 
+impl PithError for CacheError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound => ErrorKind::NotFound,
+            Self::ValueTooLarge { .. } | Self::SerializationError(_) => ErrorKind::InvalidInput,
+            Self::CacheFull => ErrorKind::Unavailable,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// Cache statistics.
 #[derive(Debug, Clone, Default)]
 pub struct CacheStats {
@@ -2,7 +2,12 @@
 //!
 //! Based on WASI runtime-config.
 
+mod units;
+
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
+use std::time::Duration;
+pub use units::{parse_bytes, parse_duration};
 
 /// Configuration errors.
 #[derive(Debug)]
@@ -24,6 +29,65 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound(_) => ErrorKind::NotFound,
+            Self::InvalidValue(_) => ErrorKind::InvalidInput,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// A deployment profile, selecting which overrides apply on top of base
+/// configuration.
+///
+/// Branch on this rather than on profile names, so that `"prod"` and
+/// `"production"` can't be told apart by accident.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Profile {
+    Development,
+    Staging,
+    Production,
+    /// Any other profile, by name.
+    Custom(String),
+}
+
+impl Profile {
+    /// Parse a profile name. The usual short and long names of the
+    /// standard profiles are recognized, ignoring case; anything else is
+    /// [`Custom`](Self::Custom).
+    pub fn parse(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Self::Development,
+            "stage" | "staging" => Self::Staging,
+            "prod" | "production" => Self::Production,
+            _ => Self::Custom(name.to_string()),
+        }
+    }
+
+    /// The profile's canonical name.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Development => "development",
+            Self::Staging => "staging",
+            Self::Production => "production",
+            Self::Custom(name) => name,
+        }
+    }
+
+    /// Whether this is the production profile.
+    pub fn is_production(&self) -> bool {
+        *self == Self::Production
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// A configuration source.
 pub trait Config {
     /// Get a configuration value by key.
@@ -34,8 +98,35 @@ pub trait Config {
         self.get(key).ok()
     }
 
+    /// Get a duration such as `250ms` or `2h`; see [`parse_duration`].
+    ///
+    /// An unparseable value is an [`Error::InvalidValue`] naming the key.
+    fn get_duration(&self, key: &str) -> Result<Duration, Error> {
+        parse_duration(&self.get(key)?).map_err(|e| in_key(key, e))
+    }
+
+    /// Get a byte size such as `512KiB` or `2GB`; see [`parse_bytes`].
+    ///
+    /// An unparseable value is an [`Error::InvalidValue`] naming the key.
+    fn get_bytes(&self, key: &str) -> Result<u64, Error> {
+        parse_bytes(&self.get(key)?).map_err(|e| in_key(key, e))
+    }
+
     /// Get all configuration keys.
     fn keys(&self) -> Vec<String>;
+
+    /// The profile this configuration was loaded for, if any.
+    fn profile(&self) -> Option<Profile> {
+        None
+    }
+}
+
+/// Prefix an invalid value's message with the key it was read from.
+fn in_key(key: &str, error: Error) -> Error {
+    match error {
+        Error::InvalidValue(msg) => Error::InvalidValue(format!("{}: {}", key, msg)),
+        other => other,
+    }
 }
 
 /// A mutable configuration source.
@@ -0,0 +1,184 @@
+//! Parsing of human-friendly durations and byte sizes.
+
+use crate::Error;
+use std::time::Duration;
+
+/// Duration units and their length in nanoseconds.
+const DURATION_UNITS: &[(&str, u128)] = &[
+    ("ns", 1),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("m", 60 * 1_000_000_000),
+    ("h", 3_600 * 1_000_000_000),
+    ("d", 86_400 * 1_000_000_000),
+];
+
+/// Size units, lowercase, and their size in bytes.
+const BYTE_UNITS: &[(&str, u128)] = &[
+    ("b", 1),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("tb", 1_000_000_000_000),
+    ("pb", 1_000_000_000_000_000),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("tib", 1 << 40),
+    ("pib", 1 << 50),
+];
+
+/// Parse a duration such as `250ms`, `1.5s`, or `1h30m`.
+///
+/// A duration is one or more numbers, each followed by a unit: `ns`, `us`
+/// (or `µs`), `ms`, `s`, `m`, `h`, or `d`. Numbers may have a fractional
+/// part and units may be separated from their numbers by spaces. `0` needs
+/// no unit.
+pub fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let invalid = || {
+        Error::InvalidValue(format!(
+            "{:?} is not a duration (expected a number and unit such as 250ms, 30s, or 2h)",
+            value
+        ))
+    };
+    let trimmed = value.trim();
+    if trimmed == "0" {
+        return Ok(Duration::ZERO);
+    }
+    let mut rest = trimmed;
+    let mut nanos: u128 = 0;
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let (number, after) = split_number(rest).ok_or_else(invalid)?;
+        let after = after.trim_start();
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_len);
+        let (_, scale) = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .ok_or_else(invalid)?;
+        nanos = scale_number(number, *scale)
+            .and_then(|n| nanos.checked_add(n))
+            .ok_or_else(invalid)?;
+        rest = after.trim_start();
+    }
+    let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| invalid())?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Parse a byte size such as `512KiB`, `2GB`, or `1.5 MiB`.
+///
+/// The unit is `B`, a decimal multiple (`KB`, `MB`, `GB`, `TB`, `PB`, in
+/// powers of 1000) or a binary one (`KiB`, `MiB`, `GiB`, `TiB`, `PiB`, in
+/// powers of 1024), in any case. A number without a unit is in bytes.
+/// Fractional sizes are rounded down to a whole byte.
+pub fn parse_bytes(value: &str) -> Result<u64, Error> {
+    let invalid = || {
+        Error::InvalidValue(format!(
+            "{:?} is not a byte size (expected a number and unit such as 512KiB or 2GB)",
+            value
+        ))
+    };
+    let (number, unit) = split_number(value.trim()).ok_or_else(invalid)?;
+    let unit = unit.trim().to_ascii_lowercase();
+    let scale = match unit.as_str() {
+        "" => 1,
+        unit => {
+            BYTE_UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .ok_or_else(invalid)?
+                .1
+        }
+    };
+    scale_number(number, scale)
+        .and_then(|bytes| u64::try_from(bytes).ok())
+        .ok_or_else(invalid)
+}
+
+/// Split a leading decimal number, with optional fraction, from the rest.
+fn split_number(s: &str) -> Option<(&str, &str)> {
+    let end = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, rest) = s.split_at(end);
+    let digits = number.replace('.', "");
+    if digits.is_empty() || number.matches('.').count() > 1 {
+        return None;
+    }
+    Some((number, rest))
+}
+
+/// `number` times `scale`, rounded down, or `None` on overflow.
+fn scale_number(number: &str, scale: u128) -> Option<u128> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let whole: u128 = match whole {
+        "" => 0,
+        whole => whole.parse().ok()?,
+    };
+    let mut total = whole.checked_mul(scale)?;
+    // Digits past the 18th are too fine to matter at any scale here.
+    let fraction = &fraction[..fraction.len().min(18)];
+    if !fraction.is_empty() {
+        let numerator: u128 = fraction.parse().ok()?;
+        let denominator = 10u128.pow(fraction.len() as u32);
+        total = total.checked_add(numerator * scale / denominator)?;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        let cases = [
+            ("250ms", Duration::from_millis(250)),
+            ("2h", Duration::from_secs(7_200)),
+            ("1.5s", Duration::from_millis(1_500)),
+            ("1h30m", Duration::from_secs(5_400)),
+            (" 1m 30s ", Duration::from_secs(90)),
+            ("10 us", Duration::from_micros(10)),
+            ("3µs", Duration::from_micros(3)),
+            ("7ns", Duration::from_nanos(7)),
+            ("1d", Duration::from_secs(86_400)),
+            (".5m", Duration::from_secs(30)),
+            ("0", Duration::ZERO),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_duration(text).unwrap(), expected, "{}", text);
+        }
+        for text in ["", "10", "ms", "5 parsecs", "1..5s", "-1s", "1s5"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+        assert_eq!(
+            parse_duration("soon").unwrap_err().to_string(),
+            "invalid value: \"soon\" is not a duration (expected a number and unit such as 250ms, 30s, or 2h)"
+        );
+    }
+
+    #[test]
+    fn parses_byte_sizes() {
+        let cases = [
+            ("512KiB", 512 * 1024),
+            ("2GB", 2_000_000_000),
+            ("1.5 MiB", 1_572_864),
+            ("64kb", 64_000),
+            ("100", 100),
+            ("7 B", 7),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_bytes(text).unwrap(), expected, "{}", text);
+        }
+        for text in ["", "KiB", "2 GiBs", "16EiB", "-1B", "99999999PB"] {
+            assert!(parse_bytes(text).is_err(), "{}", text);
+        }
+    }
+}
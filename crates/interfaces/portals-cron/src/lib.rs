@@ -2,7 +2,9 @@
 //!
 //! Parse and evaluate cron expressions for scheduling.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
+use std::ops::{Range, RangeInclusive};
 
 /// A parsed cron expression.
 ///
@@ -24,6 +26,115 @@ pub trait CronExpr: fmt::Display {
     fn as_str(&self) -> &str;
 }
 
+/// Day of the week, numbered as in cron (Sunday = 0).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Weekday {
+    Sun = 0,
+    Mon = 1,
+    Tue = 2,
+    Wed = 3,
+    Thu = 4,
+    Fri = 5,
+    Sat = 6,
+}
+
+impl Weekday {
+    /// Every day, Sunday first.
+    pub const ALL: [Weekday; 7] = [
+        Self::Sun,
+        Self::Mon,
+        Self::Tue,
+        Self::Wed,
+        Self::Thu,
+        Self::Fri,
+        Self::Sat,
+    ];
+
+    /// Monday through Friday.
+    pub const WORKDAYS: [Weekday; 5] = [Self::Mon, Self::Tue, Self::Wed, Self::Thu, Self::Fri];
+
+    /// Saturday and Sunday.
+    pub const WEEKEND: [Weekday; 2] = [Self::Sat, Self::Sun];
+
+    /// The cron field value (0-6, Sunday = 0).
+    pub fn number(self) -> u8 {
+        self as u8
+    }
+
+    /// Convert a cron field value (0-6, Sunday = 0).
+    pub fn from_number(n: u8) -> Option<Self> {
+        Self::ALL.get(n as usize).copied()
+    }
+
+    /// The following day, wrapping from Saturday to Sunday.
+    pub fn succ(self) -> Self {
+        Self::ALL[(self as usize + 1) % 7]
+    }
+}
+
+/// One or more weekdays: a single day, a collection, or a range.
+///
+/// Ranges wrap past Saturday, so `Fri..=Mon` is Friday through Monday.
+///
+/// ```ignore
+/// builder.weekdays(Weekday::Mon..=Weekday::Fri);
+/// builder.weekdays([Weekday::Sat, Weekday::Sun]);
+/// builder.weekdays(Weekday::Wed);
+/// ```
+pub trait IntoWeekdays {
+    /// Collect the days, in order.
+    fn into_weekdays(self) -> Vec<Weekday>;
+}
+
+impl IntoWeekdays for Weekday {
+    fn into_weekdays(self) -> Vec<Weekday> {
+        vec![self]
+    }
+}
+
+impl IntoWeekdays for RangeInclusive<Weekday> {
+    fn into_weekdays(self) -> Vec<Weekday> {
+        let (start, end) = self.into_inner();
+        let mut days = vec![start];
+        let mut day = start;
+        while day != end {
+            day = day.succ();
+            days.push(day);
+        }
+        days
+    }
+}
+
+impl IntoWeekdays for Range<Weekday> {
+    fn into_weekdays(self) -> Vec<Weekday> {
+        let mut days = Vec::new();
+        let mut day = self.start;
+        while day != self.end {
+            days.push(day);
+            day = day.succ();
+        }
+        days
+    }
+}
+
+impl<const N: usize> IntoWeekdays for [Weekday; N] {
+    fn into_weekdays(self) -> Vec<Weekday> {
+        self.to_vec()
+    }
+}
+
+impl IntoWeekdays for &[Weekday] {
+    fn into_weekdays(self) -> Vec<Weekday> {
+        self.to_vec()
+    }
+}
+
+impl IntoWeekdays for Vec<Weekday> {
+    fn into_weekdays(self) -> Vec<Weekday> {
+        self
+    }
+}
+
 /// Error parsing a cron expression.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CronError {
@@ -83,6 +194,15 @@ impl fmt::Display for CronError {
 
 impl std::error::Error for CronError {}
 
+impl PithError for CronError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Other(_) => ErrorKind::Other,
+            _ => ErrorKind::InvalidInput,
+        }
+    }
+}
+
 /// Parser for cron expressions.
 pub trait CronParser {
     /// The parsed expression type.
@@ -99,6 +219,15 @@ pub trait CronParser {
     fn parse_with_seconds(&self, expr: &str) -> Result<Self::Expr, CronError>;
 }
 
+/// Human-readable descriptions of cron expressions.
+pub trait CronDescribe {
+    /// Describe when the expression fires, in English.
+    ///
+    /// For example, `*/15 9-17 * * 1-5` is described as "every 15 minutes,
+    /// between 09:00 and 17:59, Monday through Friday".
+    fn describe(&self) -> String;
+}
+
 /// Iterator over upcoming cron occurrences.
 pub trait CronSchedule: CronExpr {
     /// Find the next occurrence after the given datetime.
@@ -114,4 +243,151 @@ pub trait CronSchedule: CronExpr {
         minute: u8,
         second: u8,
     ) -> Option<(i32, u8, u8, u8, u8, u8)>;
+
+    /// Find the most recent occurrence before the given datetime.
+    ///
+    /// Returns `(year, month, day, hour, minute, second)` or `None` if no
+    /// occurrence exists within a reasonable search window.
+    fn prev_before(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Option<(i32, u8, u8, u8, u8, u8)>;
+
+    /// Lazily iterate over occurrences after the given datetime.
+    ///
+    /// ```ignore
+    /// let next_five: Vec<_> = schedule.iter_after(2024, 1, 1, 0, 0, 0).take(5).collect();
+    /// ```
+    fn iter_after(
+        &self,
+        year: i32,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Occurrences<'_, Self> {
+        Occurrences {
+            schedule: self,
+            cursor: Some((year, month, day, hour, minute, second)),
+        }
+    }
+
+    /// List occurrences after `start` and up to and including `end`,
+    /// oldest first, stopping after `limit`.
+    ///
+    /// Datetimes are `(year, month, day, hour, minute, second)`.
+    ///
+    /// ```ignore
+    /// let backfill = schedule.occurrences_between(last_run, now, 100);
+    /// ```
+    fn occurrences_between(
+        &self,
+        start: (i32, u8, u8, u8, u8, u8),
+        end: (i32, u8, u8, u8, u8, u8),
+        limit: usize,
+    ) -> Vec<(i32, u8, u8, u8, u8, u8)> {
+        let (year, month, day, hour, minute, second) = start;
+        self.iter_after(year, month, day, hour, minute, second)
+            .take_while(|&next| next <= end)
+            .take(limit)
+            .collect()
+    }
+}
+
+/// A schedule evaluated in a time zone.
+///
+/// [`CronSchedule`] works on naive wall-clock components, which cannot
+/// express daylight saving transitions: some local times never happen and
+/// others happen twice. This trait works on absolute instants instead, as
+/// Unix timestamps in seconds; implementations document how they resolve
+/// skipped and repeated local times.
+pub trait CronScheduleTz {
+    /// Find the first occurrence strictly after `timestamp`.
+    fn next_after_timestamp(&self, timestamp: i64) -> Option<i64>;
+
+    /// Find the most recent occurrence strictly before `timestamp`.
+    fn prev_before_timestamp(&self, timestamp: i64) -> Option<i64>;
+}
+
+/// Iterator over successive occurrences of a schedule, as
+/// `(year, month, day, hour, minute, second)`.
+///
+/// Created by [`CronSchedule::iter_after`]. Ends when the schedule has no
+/// further occurrence.
+#[derive(Debug, Clone)]
+pub struct Occurrences<'a, S: ?Sized> {
+    schedule: &'a S,
+    cursor: Option<(i32, u8, u8, u8, u8, u8)>,
+}
+
+impl<S: CronSchedule + ?Sized> Iterator for Occurrences<'_, S> {
+    type Item = (i32, u8, u8, u8, u8, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (year, month, day, hour, minute, second) = self.cursor?;
+        self.cursor = self
+            .schedule
+            .next_after(year, month, day, hour, minute, second);
+        self.cursor
+    }
+}
+
+impl<S: CronSchedule + ?Sized> std::iter::FusedIterator for Occurrences<'_, S> {}
+
+/// What a scheduler does with occurrences it missed.
+///
+/// Occurrences are missed when the process was down, or when a job overran
+/// past its next start time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CatchUp {
+    /// Drop missed occurrences and wait for the next one.
+    #[default]
+    Skip,
+    /// Run once for the whole gap, coalescing missed occurrences.
+    RunOnce,
+    /// Run once for every missed occurrence, oldest first.
+    RunAll,
+}
+
+/// Occurrences of `schedule` due in `(last_run, now]` under `policy`.
+///
+/// Datetimes are `(year, month, day, hour, minute, second)`. `RunOnce`
+/// yields the most recent due occurrence; `RunAll` yields every due
+/// occurrence, up to `limit`, oldest first. `Skip` yields nothing; the
+/// caller resumes from `next_after(now)`.
+pub fn missed_runs<S: CronSchedule + ?Sized>(
+    schedule: &S,
+    last_run: (i32, u8, u8, u8, u8, u8),
+    now: (i32, u8, u8, u8, u8, u8),
+    policy: CatchUp,
+    limit: usize,
+) -> Vec<(i32, u8, u8, u8, u8, u8)> {
+    if policy == CatchUp::Skip || limit == 0 {
+        return Vec::new();
+    }
+
+    if policy == CatchUp::RunAll {
+        return schedule.occurrences_between(last_run, now, limit);
+    }
+
+    // Look back from `now` rather than walking the gap, which may span
+    // millions of occurrences. `prev_before` is strict, so one step forward
+    // from it checks whether `now` itself is due.
+    let (year, month, day, hour, minute, second) = now;
+    let before = schedule
+        .prev_before(year, month, day, hour, minute, second)
+        .filter(|&prev| prev > last_run);
+    let (year, month, day, hour, minute, second) = before.unwrap_or(last_run);
+    schedule
+        .next_after(year, month, day, hour, minute, second)
+        .filter(|&next| next <= now)
+        .or(before)
+        .into_iter()
+        .collect()
 }
@@ -1,5 +1,7 @@
 //! Cryptographic interfaces.
 
+pub use portals_error::{ErrorKind, PithError};
+use portals_io::{InputStream, StreamError};
 use std::fmt;
 
 /// A cryptographic hash function.
@@ -27,6 +29,58 @@ pub trait Hash {
     }
 }
 
+/// Buffer size used by [`hash_stream`].
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Hash everything remaining in `input`.
+///
+/// Reads until the stream reports end of stream, in fixed-size chunks, so
+/// memory use does not depend on the input size.
+///
+/// ```ignore
+/// let digest = hash_stream::<Sha256>(dir.open_read(Path::new("backup.tar"))?)?;
+/// ```
+pub fn hash_stream<H: Hash>(mut input: impl InputStream) -> Result<Vec<u8>, StreamError> {
+    let mut hasher = H::new();
+    let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+    loop {
+        match input.blocking_read_into(&mut buf) {
+            Ok(0) | Err(StreamError::Closed) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// A fast non-cryptographic hash for checksums and hash tables.
+///
+/// Not collision resistant against an adversary; use [`Hash`] when inputs
+/// are untrusted.
+pub trait FastHash {
+    /// The hash value (`u64` or `u128`).
+    type Output;
+
+    /// Create a new hasher with the given seed.
+    fn with_seed(seed: u64) -> Self;
+
+    /// Update the hasher with data.
+    fn update(&mut self, data: &[u8]);
+
+    /// Return the hash of the data so far.
+    fn digest(&self) -> Self::Output;
+
+    /// Hash data in one shot with seed 0.
+    fn hash(data: &[u8]) -> Self::Output
+    where
+        Self: Sized,
+    {
+        let mut hasher = Self::with_seed(0);
+        hasher.update(data);
+        hasher.digest()
+    }
+}
+
 /// HMAC (Hash-based Message Authentication Code).
 pub trait Hmac {
     /// Create a new HMAC with the given key.
@@ -38,6 +92,16 @@ pub trait Hmac {
     /// Finalize and return the MAC.
     fn finalize(self) -> Vec<u8>;
 
+    /// Compute the MAC of `data` under `key` in one shot.
+    fn mac(key: &[u8], data: &[u8]) -> Vec<u8>
+    where
+        Self: Sized,
+    {
+        let mut mac = Self::new(key);
+        mac.update(data);
+        mac.finalize()
+    }
+
     /// Verify a MAC.
     fn verify(self, expected: &[u8]) -> bool
     where
@@ -46,6 +110,22 @@ pub trait Hmac {
         let computed = self.finalize();
         constant_time_eq(&computed, expected)
     }
+
+    /// Verify a MAC truncated to its first `len` bytes, as used by TOTP and
+    /// similar protocols.
+    ///
+    /// Fails if `expected` is not exactly `len` bytes, or if `len` is zero or
+    /// longer than the full MAC. The comparison is constant-time.
+    fn verify_truncated(self, expected: &[u8], len: usize) -> bool
+    where
+        Self: Sized,
+    {
+        let computed = self.finalize();
+        if len == 0 || len > computed.len() || expected.len() != len {
+            return false;
+        }
+        constant_time_eq(&computed[..len], expected)
+    }
 }
 
 /// Symmetric encryption.
@@ -88,6 +168,26 @@ pub trait Signature {
     fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<bool, CryptoError>;
 }
 
+/// Standard DER encodings of [`Signature`] keys, for interoperability with
+/// OpenSSL, TLS stacks, and JWKS endpoints.
+///
+/// Secret keys use PKCS#8 (RFC 5208/5958); public keys use
+/// SubjectPublicKeyInfo (RFC 5280). Keys are otherwise in the raw format
+/// used by [`Signature`].
+pub trait KeyEncoding: Signature {
+    /// Encode a secret key as PKCS#8 DER.
+    fn secret_key_to_pkcs8_der(secret_key: &[u8]) -> Result<Vec<u8>, CryptoError>;
+
+    /// Decode a secret key from PKCS#8 DER.
+    fn secret_key_from_pkcs8_der(der: &[u8]) -> Result<Vec<u8>, CryptoError>;
+
+    /// Encode a public key as SubjectPublicKeyInfo DER.
+    fn public_key_to_spki_der(public_key: &[u8]) -> Result<Vec<u8>, CryptoError>;
+
+    /// Decode a public key from SubjectPublicKeyInfo DER.
+    fn public_key_from_spki_der(der: &[u8]) -> Result<Vec<u8>, CryptoError>;
+}
+
 /// Key derivation function.
 pub trait Kdf {
     /// Derive a key from a password and salt.
@@ -105,6 +205,8 @@ pub enum CryptoError {
     AuthenticationFailed,
     /// Invalid signature.
     InvalidSignature,
+    /// Malformed or unsupported key encoding.
+    InvalidEncoding,
     /// Other error.
     Other(String),
 }
@@ -116,6 +218,7 @@ impl fmt::Display for CryptoError {
             CryptoError::InvalidNonceSize => write!(f, "invalid nonce size"),
             CryptoError::AuthenticationFailed => write!(f, "authentication failed"),
             CryptoError::InvalidSignature => write!(f, "invalid signature"),
+            CryptoError::InvalidEncoding => write!(f, "invalid key encoding"),
             CryptoError::Other(msg) => write!(f, "{}", msg),
         }
     }
@@ -123,6 +226,19 @@ impl fmt::Display for CryptoError {
 
 impl std::error::Error for CryptoError {}
 
+impl PithError for CryptoError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidKeySize
+            | Self::InvalidNonceSize
+            | Self::AuthenticationFailed
+            | Self::InvalidSignature
+            | Self::InvalidEncoding => ErrorKind::InvalidInput,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// Constant-time equality comparison.
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
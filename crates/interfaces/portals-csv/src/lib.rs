@@ -0,0 +1,165 @@
+//! CSV interfaces.
+//!
+//! Read and write delimited records over [`portals_io`] streams.
+
+pub use portals_error::{ErrorKind, PithError};
+use portals_io::StreamError;
+use std::fmt;
+
+/// Dialect settings shared by readers and writers.
+///
+/// The defaults follow RFC 4180: comma-delimited, `"`-quoted, with a header
+/// row. Writers end lines with `\n` unless [`crlf`](Self::crlf) is set;
+/// readers accept `\n`, `\r\n`, and `\r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Field separator.
+    pub delimiter: u8,
+    /// Quote character. Doubled inside a quoted field to escape itself.
+    pub quote: u8,
+    /// Whether the first record is a header row.
+    pub has_headers: bool,
+    /// Whether writers end lines with `\r\n`.
+    pub crlf: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            has_headers: true,
+            crlf: false,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Comma-delimited with a header row.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tab-delimited with a header row.
+    pub fn tsv() -> Self {
+        Self::new().delimiter(b'\t')
+    }
+
+    /// Set the field separator.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set the quote character.
+    pub fn quote(mut self, quote: u8) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// Set whether the first record is a header row.
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// Set whether writers end lines with `\r\n`.
+    pub fn crlf(mut self, crlf: bool) -> Self {
+        self.crlf = crlf;
+        self
+    }
+}
+
+/// Reads records from a stream.
+pub trait CsvRead {
+    /// The header row, if the dialect has one and the input is not empty.
+    ///
+    /// Reads the first record on first call.
+    fn headers(&mut self) -> Result<Option<&[String]>, CsvError>;
+
+    /// Read the next record, or `None` at end of input.
+    ///
+    /// The header row is never returned as a record.
+    fn read_record(&mut self) -> Result<Option<Vec<String>>, CsvError>;
+
+    /// Read all remaining records.
+    fn read_all(&mut self) -> Result<Vec<Vec<String>>, CsvError> {
+        let mut records = Vec::new();
+        while let Some(record) = self.read_record()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+/// Writes records to a stream.
+pub trait CsvWrite {
+    /// Write one record, quoting fields as needed.
+    fn write_record<S: AsRef<str>>(&mut self, fields: &[S]) -> Result<(), CsvError>;
+
+    /// Flush buffered output to the stream.
+    fn flush(&mut self) -> Result<(), CsvError>;
+}
+
+/// CSV errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvError {
+    /// The underlying stream failed.
+    Stream(StreamError),
+    /// Malformed input.
+    Parse { line: u64, message: String },
+    /// A record does not have as many fields as the header row.
+    FieldCount {
+        line: u64,
+        expected: usize,
+        got: usize,
+    },
+    /// A record could not be converted to the requested type.
+    Deserialize { line: u64, message: String },
+    /// A value could not be written as a record.
+    Serialize(String),
+    /// Other error.
+    Other(String),
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stream(e) => write!(f, "stream error: {}", e),
+            Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Self::FieldCount {
+                line,
+                expected,
+                got,
+            } => write!(
+                f,
+                "line {}: expected {} fields, got {}",
+                line, expected, got
+            ),
+            Self::Deserialize { line, message } => write!(f, "line {}: {}", line, message),
+            Self::Serialize(msg) => write!(f, "serialize error: {}", msg),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl PithError for CsvError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Stream(e) => e.kind(),
+            Self::Parse { .. }
+            | Self::FieldCount { .. }
+            | Self::Deserialize { .. }
+            | Self::Serialize(_) => ErrorKind::InvalidInput,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<StreamError> for CsvError {
+    fn from(e: StreamError) -> Self {
+        Self::Stream(e)
+    }
+}
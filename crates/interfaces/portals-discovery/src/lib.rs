@@ -0,0 +1,131 @@
+//! Service discovery interfaces.
+//!
+//! Services are named the DNS-SD way: an instance name, such as
+//! `"Office printer"`, within a service type, such as `"_ipp._tcp"`. An
+//! [`Announcer`] makes services discoverable and a [`Browser`] finds them.
+//! Backends decide where the records live: multicast DNS on the local
+//! link, or a static list where multicast is unavailable.
+
+pub use portals_error::{ErrorKind, PithError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+
+/// Discovery errors.
+#[derive(Debug)]
+pub enum Error {
+    /// An instance name or service type can't be used.
+    InvalidName(String),
+    /// No such service instance was found.
+    NotFound,
+    Io(std::io::Error),
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidName(name) => write!(f, "invalid service name: {}", name),
+            Error::NotFound => write!(f, "service not found"),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidName(_) => ErrorKind::InvalidInput,
+            Self::NotFound => ErrorKind::NotFound,
+            Self::Io(e) => ErrorKind::from_io(e.kind()),
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// A service instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    /// Human-readable name, unique within the service type.
+    pub instance: String,
+    /// Service type, such as `"_http._tcp"`.
+    pub service_type: String,
+    pub port: u16,
+    /// Addresses the service is reachable at. When announcing, leaving this
+    /// empty lets the backend use the host's own.
+    pub addrs: Vec<IpAddr>,
+    /// Key/value metadata (the DNS-SD TXT record).
+    pub txt: BTreeMap<String, String>,
+}
+
+impl Service {
+    /// Create a service with no addresses or metadata.
+    pub fn new(instance: impl Into<String>, service_type: impl Into<String>, port: u16) -> Self {
+        Self {
+            instance: instance.into(),
+            service_type: service_type.into(),
+            port,
+            addrs: Vec::new(),
+            txt: BTreeMap::new(),
+        }
+    }
+
+    /// Add an address.
+    pub fn with_addr(mut self, addr: IpAddr) -> Self {
+        self.addrs.push(addr);
+        self
+    }
+
+    /// Add a metadata entry.
+    pub fn with_txt(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.txt.insert(key.into(), value.into());
+        self
+    }
+
+    /// The service's addresses paired with its port.
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.addrs
+            .iter()
+            .map(|&addr| SocketAddr::new(addr, self.port))
+            .collect()
+    }
+}
+
+/// A capability to make services discoverable.
+pub trait Announcer {
+    /// Announce a service, replacing any earlier announcement of the same
+    /// instance and type.
+    fn announce(&self, service: &Service) -> impl Future<Output = Result<(), Error>>;
+
+    /// Stop announcing a service. Does nothing if it wasn't announced.
+    fn withdraw(
+        &self,
+        instance: &str,
+        service_type: &str,
+    ) -> impl Future<Output = Result<(), Error>>;
+}
+
+/// A capability to find services.
+pub trait Browser {
+    /// Find the instances of a service type.
+    fn browse(&self, service_type: &str) -> impl Future<Output = Result<Vec<Service>, Error>>;
+
+    /// Find where a service instance can be reached.
+    ///
+    /// Fails with [`Error::NotFound`] if the instance isn't known.
+    fn resolve(
+        &self,
+        instance: &str,
+        service_type: &str,
+    ) -> impl Future<Output = Result<Vec<SocketAddr>, Error>>;
+}
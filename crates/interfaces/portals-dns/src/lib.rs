@@ -1,5 +1,6 @@
 //! DNS interfaces.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
@@ -24,6 +25,16 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Lookup(_) => ErrorKind::Unavailable,
+            Self::NoRecords => ErrorKind::NotFound,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// A DNS resolver.
 pub trait Resolver {
     /// Lookup IPv4 addresses for a hostname.
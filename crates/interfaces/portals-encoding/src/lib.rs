@@ -1,5 +1,6 @@
 //! Encoding/decoding interfaces.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 
 /// Base64 encoding/decoding.
@@ -69,3 +70,12 @@ impl fmt::Display for DecodeError {
 }
 
 impl std::error::Error for DecodeError {}
+
+impl PithError for DecodeError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Other(_) => ErrorKind::Other,
+            _ => ErrorKind::InvalidInput,
+        }
+    }
+}
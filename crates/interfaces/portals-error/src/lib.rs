@@ -0,0 +1,90 @@
+//! Shared error classification.
+//!
+//! Each interface keeps its own error enum, but every one of them implements
+//! [`PithError`], so generic code (retry loops, circuit breakers, HTTP status
+//! mapping) can make decisions without knowing which interface failed.
+//!
+//! ```ignore
+//! fn should_retry(err: &impl PithError) -> bool {
+//!     err.is_retryable()
+//! }
+//! ```
+
+use std::fmt;
+
+/// Coarse, machine-readable classification of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The requested resource does not exist.
+    NotFound,
+    /// The resource already exists or conflicts with current state.
+    Conflict,
+    /// The caller lacks permission for the operation.
+    PermissionDenied,
+    /// The input was malformed or out of range; retrying won't help.
+    InvalidInput,
+    /// A dependency is temporarily unavailable (refused, busy, exhausted).
+    Unavailable,
+    /// The operation did not complete in time.
+    Timeout,
+    /// The operation is not supported by this backend or platform.
+    Unsupported,
+    /// Anything else.
+    Other,
+}
+
+impl ErrorKind {
+    /// Whether an operation failing with this kind may succeed if retried.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Unavailable | Self::Timeout)
+    }
+
+    /// Classify a `std::io::ErrorKind`.
+    pub fn from_io(kind: std::io::ErrorKind) -> Self {
+        use std::io::ErrorKind as Io;
+        match kind {
+            Io::NotFound => Self::NotFound,
+            Io::AlreadyExists => Self::Conflict,
+            Io::PermissionDenied => Self::PermissionDenied,
+            Io::InvalidInput | Io::InvalidData => Self::InvalidInput,
+            Io::TimedOut => Self::Timeout,
+            Io::ConnectionRefused
+            | Io::ConnectionReset
+            | Io::ConnectionAborted
+            | Io::NotConnected
+            | Io::Interrupted
+            | Io::WouldBlock => Self::Unavailable,
+            Io::Unsupported => Self::Unsupported,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found"),
+            Self::Conflict => write!(f, "conflict"),
+            Self::PermissionDenied => write!(f, "permission denied"),
+            Self::InvalidInput => write!(f, "invalid input"),
+            Self::Unavailable => write!(f, "unavailable"),
+            Self::Timeout => write!(f, "timeout"),
+            Self::Unsupported => write!(f, "unsupported"),
+            Self::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// An interface error with a portable classification.
+pub trait PithError: std::error::Error {
+    /// Classify this error.
+    fn kind(&self) -> ErrorKind;
+
+    /// Whether the failed operation may succeed if retried.
+    ///
+    /// Defaults to [`ErrorKind::is_retryable`]; override when a specific
+    /// variant knows better.
+    fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
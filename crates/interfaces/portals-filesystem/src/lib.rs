@@ -2,6 +2,8 @@
 //!
 //! Based on WASI filesystem.
 
+pub use portals_crypto::Hash;
+pub use portals_error::{ErrorKind, PithError};
 use std::path::Path;
 
 pub use portals_io::{InputStream, OutputStream, Seek, SeekFrom, StreamError};
@@ -15,6 +17,9 @@ pub enum Error {
     NotDirectory,
     IsDirectory,
     Invalid,
+    /// A write would take usage past a quota. `requested` is the total the
+    /// write needed.
+    QuotaExceeded { limit: u64, requested: u64 },
     Io(std::io::Error),
     Other(String),
 }
@@ -28,6 +33,11 @@ impl std::fmt::Display for Error {
             Self::NotDirectory => write!(f, "not a directory"),
             Self::IsDirectory => write!(f, "is a directory"),
             Self::Invalid => write!(f, "invalid argument"),
+            Self::QuotaExceeded { limit, requested } => write!(
+                f,
+                "quota exceeded: {} bytes requested, limit is {}",
+                requested, limit
+            ),
             Self::Io(e) => write!(f, "I/O error: {}", e),
             Self::Other(s) => write!(f, "{}", s),
         }
@@ -36,6 +46,20 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Access => ErrorKind::PermissionDenied,
+            Self::Exist => ErrorKind::Conflict,
+            Self::NotFound => ErrorKind::NotFound,
+            Self::NotDirectory | Self::IsDirectory | Self::Invalid => ErrorKind::InvalidInput,
+            Self::QuotaExceeded { .. } => ErrorKind::Unavailable,
+            Self::Io(e) => ErrorKind::from_io(e.kind()),
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
@@ -61,6 +85,14 @@ pub struct Metadata {
     pub created: Option<u64>,
 }
 
+/// Disk usage of a directory tree, from [`Directory::usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub bytes: u64,
+    pub files: u64,
+    pub directories: u64,
+}
+
 /// A capability to access a directory and its contents.
 pub trait Directory {
     /// Open a file for reading.
@@ -89,6 +121,54 @@ pub trait Directory {
 
     /// Rename a file or directory.
     fn rename(&self, from: &Path, to: &Path) -> Result<(), Error>;
+
+    /// Set access and modification times, in seconds since the Unix epoch
+    /// as in [`Metadata`]. `None` leaves that time unchanged.
+    fn set_times(&self, path: &Path, accessed: Option<u64>, modified: Option<u64>) -> Result<(), Error>;
+
+    /// Set a file's length, cutting it short or extending it with zeros.
+    fn truncate(&self, path: &Path, len: u64) -> Result<(), Error>;
+
+    /// Ensure a file is at least `len` bytes, with the space reserved on
+    /// disk so later writes within it cannot run out of room. Never
+    /// shrinks the file.
+    fn allocate(&self, path: &Path, len: u64) -> Result<(), Error>;
+
+    /// Total size and count of everything under `path`, recursively.
+    ///
+    /// `directories` counts subdirectories, not `path` itself. Symlinks
+    /// count as files but are not followed and add no bytes.
+    fn usage(&self, path: &Path) -> Result<Usage, Error> {
+        let mut usage = Usage::default();
+        for entry in self.read_dir(path)? {
+            let entry = entry?;
+            let child = path.join(&entry.name);
+            match entry.file_type {
+                FileType::Directory => {
+                    let sub = self.usage(&child)?;
+                    usage.bytes += sub.bytes;
+                    usage.files += sub.files;
+                    usage.directories += sub.directories + 1;
+                }
+                FileType::Regular => {
+                    usage.bytes += self.metadata(&child)?.size;
+                    usage.files += 1;
+                }
+                FileType::Symlink | FileType::Unknown => usage.files += 1,
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Hash a file's contents with `H`, reading it in chunks.
+    ///
+    /// ```ignore
+    /// let digest = dir.hash_file::<Sha256>(Path::new("release.tar.gz"))?;
+    /// ```
+    fn hash_file<H: Hash>(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let file = self.open_read(path)?;
+        portals_crypto::hash_stream::<H>(file).map_err(|e| Error::Other(e.to_string()))
+    }
 }
 
 /// A directory entry.
@@ -0,0 +1,367 @@
+//! Streamed request and response bodies.
+
+use crate::{Error, Headers, Method, Request, Response};
+use portals_io::{InputStream, StreamError};
+use std::cell::RefCell;
+use std::future::{Future, poll_fn};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+/// A message body, either in memory or read incrementally from a stream.
+#[derive(Debug, Clone)]
+pub enum Body<S> {
+    Bytes(Vec<u8>),
+    Stream(S),
+}
+
+impl<S> From<Vec<u8>> for Body<S> {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl<S: InputStream> Body<S> {
+    /// Read the whole body into memory, waiting on the stream as needed.
+    pub async fn collect(self) -> Result<Vec<u8>, Error> {
+        let mut stream = match self {
+            Self::Bytes(bytes) => return Ok(bytes),
+            Self::Stream(stream) => stream,
+        };
+        let mut bytes = Vec::new();
+        let mut buf = [0u8; 8192];
+        loop {
+            match stream.read_into(&mut buf) {
+                Ok(0) => stream.subscribe().await,
+                Ok(n) => bytes.extend_from_slice(&buf[..n]),
+                Err(StreamError::Closed) => return Ok(bytes),
+                Err(e) => return Err(Error::Io(std::io::Error::other(e))),
+            }
+        }
+    }
+}
+
+/// A stream with nothing in it, for requests that never stream a body.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmptyStream;
+
+impl InputStream for EmptyStream {
+    fn read_into(&mut self, _buf: &mut [u8]) -> Result<usize, StreamError> {
+        Err(StreamError::Closed)
+    }
+
+    fn blocking_read_into(&mut self, _buf: &mut [u8]) -> Result<usize, StreamError> {
+        Err(StreamError::Closed)
+    }
+
+    fn subscribe(&self) -> impl Future<Output = ()> {
+        std::future::ready(())
+    }
+}
+
+/// A body that arrives asynchronously, a chunk at a time.
+///
+/// Backends implement this for their connections and hand it to
+/// [`ChunkStream`] to read it as an [`InputStream`].
+pub trait ChunkSource {
+    /// Poll for the next chunk, or `None` once the body is complete.
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>, Error>>>;
+}
+
+/// An [`InputStream`] over a [`ChunkSource`].
+///
+/// [`read_into`](InputStream::read_into) polls the source once without
+/// waiting and returns 0 if nothing has arrived; [`subscribe`] waits for
+/// the next chunk. [`blocking_read_into`] parks the thread until one
+/// arrives, so it must not be called on a thread the connection itself
+/// depends on, such as a single-threaded runtime's.
+///
+/// [`subscribe`]: InputStream::subscribe
+/// [`blocking_read_into`]: InputStream::blocking_read_into
+#[derive(Debug)]
+pub struct ChunkStream<C> {
+    inner: RefCell<Chunks<C>>,
+}
+
+#[derive(Debug)]
+struct Chunks<C> {
+    source: C,
+    chunk: Vec<u8>,
+    pos: usize,
+    /// Why the body ended, once it has.
+    end: Option<StreamError>,
+}
+
+impl<C: ChunkSource> ChunkStream<C> {
+    /// Read the body `source` produces.
+    pub fn new(source: C) -> Self {
+        Self {
+            inner: RefCell::new(Chunks {
+                source,
+                chunk: Vec::new(),
+                pos: 0,
+                end: None,
+            }),
+        }
+    }
+}
+
+impl<C: ChunkSource> Chunks<C> {
+    /// Poll until there are bytes to read or the body has ended.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while self.pos == self.chunk.len() && self.end.is_none() {
+            match self.source.poll_chunk(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Poll::Ready(Some(Err(e))) => self.end = Some(StreamError::Other(e.to_string())),
+                Poll::Ready(None) => self.end = Some(StreamError::Closed),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Copy out buffered bytes; an error is reported once, then `Closed`.
+    fn take(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        let rest = &self.chunk[self.pos..];
+        if rest.is_empty() {
+            return match &mut self.end {
+                Some(end) => Err(std::mem::replace(end, StreamError::Closed)),
+                None => Ok(0),
+            };
+        }
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<C: ChunkSource> InputStream for ChunkStream<C> {
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        let chunks = self.inner.get_mut();
+        let _ = chunks.poll_fill(&mut Context::from_waker(Waker::noop()));
+        chunks.take(buf)
+    }
+
+    fn blocking_read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let chunks = self.inner.get_mut();
+        while chunks
+            .poll_fill(&mut Context::from_waker(&waker))
+            .is_pending()
+        {
+            std::thread::park();
+        }
+        chunks.take(buf)
+    }
+
+    fn subscribe(&self) -> impl Future<Output = ()> {
+        poll_fn(|cx| self.inner.borrow_mut().poll_fill(cx))
+    }
+}
+
+/// Wakes a thread parked in a blocking read.
+struct Unpark(std::thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// An HTTP request whose body may be streamed.
+#[derive(Debug, Clone)]
+pub struct StreamingRequest<S> {
+    pub method: Method,
+    pub url: String,
+    pub headers: Headers,
+    pub body: Option<Body<S>>,
+}
+
+impl<S> From<Request> for StreamingRequest<S> {
+    fn from(request: Request) -> Self {
+        Self {
+            method: request.method,
+            url: request.url,
+            headers: request.headers,
+            body: request.body.map(Body::Bytes),
+        }
+    }
+}
+
+impl<S: InputStream> StreamingRequest<S> {
+    /// Read the body into memory, giving an ordinary [`Request`].
+    pub async fn buffer(self) -> Result<Request, Error> {
+        let body = match self.body {
+            Some(body) => Some(body.collect().await?),
+            None => None,
+        };
+        Ok(Request {
+            method: self.method,
+            url: self.url,
+            headers: self.headers,
+            body,
+        })
+    }
+}
+
+/// An HTTP response whose body may be streamed.
+#[derive(Debug, Clone)]
+pub struct StreamingResponse<S> {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Body<S>,
+    /// As for [`Response::redirects`].
+    pub redirects: Vec<String>,
+}
+
+impl<S> From<Response> for StreamingResponse<S> {
+    fn from(response: Response) -> Self {
+        Self {
+            status: response.status,
+            headers: response.headers,
+            body: Body::Bytes(response.body),
+            redirects: response.redirects,
+        }
+    }
+}
+
+impl<S: InputStream> StreamingResponse<S> {
+    /// Read the body into memory, giving an ordinary [`Response`].
+    pub async fn buffer(self) -> Result<Response, Error> {
+        Ok(Response {
+            status: self.status,
+            headers: self.headers,
+            body: self.body.collect().await?,
+            redirects: self.redirects,
+        })
+    }
+}
+
+/// HTTP client that can upload and download bodies incrementally.
+///
+/// The response arrives once its head has been read, with a body that
+/// reads the rest as it comes in, so large downloads needn't be held in
+/// memory. A backend that can't stream in one direction may buffer that
+/// side and answer with [`Body::Bytes`]. Backends whose bodies arrive
+/// asynchronously can read them through [`ChunkStream`].
+///
+/// ```ignore
+/// let mut response = client.send_streaming(StreamingRequest::<EmptyStream>::from(request)).await?;
+/// if let Body::Stream(stream) = &mut response.body {
+///     while let Ok(chunk) = stream.blocking_read(64 * 1024) {
+///         file.write_all(&chunk)?;
+///     }
+/// }
+/// ```
+pub trait StreamingHttpClient {
+    /// The stream response bodies are read from.
+    type Body: InputStream;
+
+    /// Send an HTTP request, streaming its body from `S`.
+    fn send_streaming<S: InputStream>(
+        &self,
+        request: StreamingRequest<S>,
+    ) -> impl Future<Output = Result<StreamingResponse<Self::Body>, Error>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Yields its chunks one read at a time, with an empty read between
+    /// each to exercise waiting.
+    struct Chunks(Vec<&'static [u8]>, bool);
+
+    impl InputStream for Chunks {
+        fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+            self.1 = !self.1;
+            if self.1 {
+                return Ok(0);
+            }
+            if self.0.is_empty() {
+                return Err(StreamError::Closed);
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+
+        fn blocking_read_into(&mut self, buf: &mut [u8]) -> Result<usize, StreamError> {
+            self.read_into(buf)
+        }
+
+        async fn subscribe(&self) {}
+    }
+
+    /// Yields its chunks one per poll, pending before each.
+    struct Source(Vec<Result<&'static [u8], Error>>, bool);
+
+    impl ChunkSource for Source {
+        fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>, Error>>> {
+            self.1 = !self.1;
+            if self.1 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            if self.0.is_empty() {
+                return Poll::Ready(None);
+            }
+            Poll::Ready(Some(self.0.remove(0).map(<[u8]>::to_vec)))
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_chunk_sources() {
+        let mut stream = ChunkStream::new(Source(vec![Ok(b"hello, "), Ok(b"world")], false));
+        let mut buf = [0; 4];
+        assert_eq!(stream.read_into(&mut buf), Ok(0));
+        stream.subscribe().await;
+        assert_eq!(stream.read_into(&mut buf), Ok(4));
+        assert_eq!(stream.read_into(&mut buf), Ok(3));
+        assert_eq!(stream.blocking_read(16).unwrap(), b"world");
+        assert_eq!(
+            stream.blocking_read_into(&mut buf),
+            Err(StreamError::Closed)
+        );
+
+        let failed = Source(vec![Ok(b"partial"), Err(Error::ProtocolError)], false);
+        assert!(
+            Body::Stream(ChunkStream::new(failed))
+                .collect()
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn collects_streamed_bodies() {
+        let body = Body::Stream(Chunks(vec![b"hello, ", b"world"], false));
+        assert_eq!(body.collect().await.unwrap(), b"hello, world");
+
+        let request = StreamingRequest {
+            method: Method::Put,
+            url: "http://example.com/".to_string(),
+            headers: Headers::new(),
+            body: Some(Body::Stream(Chunks(vec![b"abc"], false))),
+        };
+        assert_eq!(request.buffer().await.unwrap().body.unwrap(), b"abc");
+
+        let response = StreamingResponse::<EmptyStream>::from(Response {
+            status: 204,
+            headers: Headers::new(),
+            body: b"kept".to_vec(),
+            redirects: Vec::new(),
+        });
+        assert_eq!(response.buffer().await.unwrap().body, b"kept");
+        assert!(
+            Body::Stream(EmptyStream)
+                .collect()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Header maps.
+
+use std::ops::Index;
+
+/// HTTP headers.
+///
+/// Names are matched case-insensitively but keep the casing they were
+/// added or parsed with, which is how they are written out. A name may
+/// appear more than once, as `set-cookie` does; entries keep the order they
+/// were added in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Create an empty header map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of entries, counting each value of a repeated header.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no headers.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the first value for `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.position(name).map(|i| self.entries[i].1.as_str())
+    }
+
+    /// Get every value for `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether `name` is present.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    /// Set `name` to `value`, replacing every existing value and returning
+    /// the first.
+    ///
+    /// A replaced header keeps the position of its first entry and takes
+    /// the casing of `name`; a new one is added at the end.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        let name = name.into();
+        let value = value.into();
+        let Some(first) = self.position(&name) else {
+            self.entries.push((name, value));
+            return None;
+        };
+        let mut i = first + 1;
+        while i < self.entries.len() {
+            if self.entries[i].0.eq_ignore_ascii_case(&name) {
+                self.entries.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        let old = std::mem::replace(&mut self.entries[first], (name, value));
+        Some(old.1)
+    }
+
+    /// Add a value for `name`, keeping any existing ones.
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Remove every value for `name`, returning the first.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self.position(name)?;
+        let (_, value) = self.entries.remove(first);
+        self.entries.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        Some(value)
+    }
+
+    /// Iterate over `(name, value)` pairs in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(n, _)| n.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Index<&str> for Headers {
+    type Output = str;
+
+    /// The first value for `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the header is not present.
+    fn index(&self, name: &str) -> &str {
+        self.get(name)
+            .unwrap_or_else(|| panic!("no header named {:?}", name))
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for Headers {
+    /// Appends each pair, keeping repeated names.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+impl<K: Into<String>, V: Into<String>, const N: usize> From<[(K, V); N]> for Headers {
+    fn from(pairs: [(K, V); N]) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+impl IntoIterator for Headers {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Headers {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_and_case_insensitive() {
+        let mut headers = Headers::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Content-Type", "text/plain");
+        headers.append("set-cookie", "b=2");
+
+        assert_eq!(headers.get("SET-COOKIE"), Some("a=1"));
+        assert_eq!(
+            headers.get_all("set-cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+        assert_eq!(&headers["content-type"], "text/plain");
+        assert_eq!(headers.len(), 3);
+
+        assert_eq!(headers.insert("SET-COOKIE", "c=3"), Some("a=1".to_string()));
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [("SET-COOKIE", "c=3"), ("Content-Type", "text/plain")]
+        );
+
+        assert_eq!(
+            headers.remove("content-TYPE"),
+            Some("text/plain".to_string())
+        );
+        assert_eq!(headers.remove("content-type"), None);
+        assert!(!headers.contains_key("Content-Type"));
+        assert_eq!(headers.len(), 1);
+    }
+}
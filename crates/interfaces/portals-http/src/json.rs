@@ -0,0 +1,130 @@
+//! JSON request and response bodies.
+
+use crate::{Error, Headers, Method, Request, Response};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+impl Request {
+    /// Create a request with `value` as its JSON body.
+    ///
+    /// Sets `content-type` to `application/json`, and `accept` too, since
+    /// an API sent JSON usually answers with it.
+    ///
+    /// ```ignore
+    /// let request = Request::json(Method::Post, "https://api.example.com/users", &user)?;
+    /// let created: User = client.send(request).await?.json()?;
+    /// ```
+    pub fn json<T: Serialize + ?Sized>(
+        method: Method,
+        url: impl Into<String>,
+        value: &T,
+    ) -> Result<Self, Error> {
+        let body = serde_json::to_vec(value)
+            .map_err(|e| Error::Other(format!("failed to encode JSON body: {}", e)))?;
+        let headers = Headers::from([
+            ("content-type", "application/json"),
+            ("accept", "application/json"),
+        ]);
+        Ok(Self {
+            method,
+            url: url.into(),
+            headers,
+            body: Some(body),
+        })
+    }
+}
+
+impl Response {
+    /// Parse the body as JSON.
+    ///
+    /// Fails if the body isn't valid JSON for `T`, or if the response has a
+    /// `content-type` that isn't JSON (`application/json` or a `+json`
+    /// type). A response without one is parsed anyway. The status isn't
+    /// checked, so error bodies can be parsed too.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let content_type = self.headers.get("content-type");
+        if let Some(content_type) = content_type
+            && !is_json(content_type)
+        {
+            return Err(Error::Other(format!(
+                "expected a JSON response, got {}",
+                content_type
+            )));
+        }
+        serde_json::from_slice(&self.body)
+            .map_err(|e| Error::Other(format!("invalid JSON body: {}", e)))
+    }
+}
+
+/// Whether `content_type` names a JSON media type, ignoring parameters.
+fn is_json(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    media_type == "application/json" || media_type.ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        admin: bool,
+    }
+
+    fn response(content_type: Option<&str>, body: &str) -> Response {
+        Response {
+            status: 200,
+            headers: content_type
+                .map(|value| ("Content-Type".to_string(), value.to_string()))
+                .into_iter()
+                .collect(),
+            body: body.as_bytes().to_vec(),
+            redirects: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let user = User {
+            name: "ada".to_string(),
+            admin: true,
+        };
+        let request = Request::json(Method::Post, "http://api.test/users", &user).unwrap();
+        assert_eq!(&request.headers["content-type"], "application/json");
+        assert_eq!(
+            request.body.as_deref(),
+            Some(&br#"{"name":"ada","admin":true}"#[..])
+        );
+
+        let body = r#"{"name":"ada","admin":true}"#;
+        for content_type in [
+            None,
+            Some("application/json"),
+            Some("Application/JSON; charset=utf-8"),
+            Some("application/problem+json"),
+        ] {
+            assert_eq!(
+                response(content_type, body).json::<User>().unwrap(),
+                user,
+                "{:?}",
+                content_type
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_non_json() {
+        let err = response(Some("text/html"), "<p>hi</p>")
+            .json::<User>()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "expected a JSON response, got text/html");
+        assert!(response(None, r#"{"name":"ada"}"#).json::<User>().is_err());
+    }
+}
@@ -1,8 +1,34 @@
 //! HTTP interfaces.
 //!
-//! Based on WASI HTTP.
+//! Based on WASI HTTP. Clients send requests with [`HttpClient`]; servers
+//! answer them with an [`HttpHandler`] through [`HttpServer`].
+//!
+//! Cross-cutting client behavior can be layered over any backend with
+//! [`Middleware`], failed requests retried with [`RetryingClient`], and
+//! redirects followed with [`RedirectingClient`].
+//! Backends that implement [`StreamingHttpClient`] can upload and download
+//! bodies incrementally.
+//!
+//! With the `serde` feature, `Request::json` and `Response::json`
+//! send and parse JSON bodies.
+
+mod body;
+mod headers;
+#[cfg(feature = "serde")]
+mod json;
+mod middleware;
+mod redirect;
+mod retry;
 
-use std::collections::HashMap;
+pub use body::{
+    Body, ChunkSource, ChunkStream, EmptyStream, StreamingHttpClient, StreamingRequest,
+    StreamingResponse,
+};
+pub use headers::Headers;
+pub use middleware::{Chain, ClientBuilder, Identity, Layered, MapRequest, Middleware, SetHeader};
+pub use portals_error::{ErrorKind, PithError};
+pub use redirect::{RedirectPolicy, RedirectingClient};
+pub use retry::{IDEMPOTENCY_KEY_HEADER, RetryPolicy, RetryingClient};
 use std::future::Future;
 
 /// HTTP errors.
@@ -12,6 +38,7 @@ pub enum Error {
     ConnectionFailed,
     Timeout,
     ProtocolError,
+    TooManyRedirects,
     Io(std::io::Error),
     Other(String),
 }
@@ -23,6 +50,7 @@ impl std::fmt::Display for Error {
             Self::ConnectionFailed => write!(f, "connection failed"),
             Self::Timeout => write!(f, "timeout"),
             Self::ProtocolError => write!(f, "protocol error"),
+            Self::TooManyRedirects => write!(f, "too many redirects"),
             Self::Io(e) => write!(f, "I/O error: {}", e),
             Self::Other(s) => write!(f, "{}", s),
         }
@@ -31,6 +59,20 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidUrl => ErrorKind::InvalidInput,
+            Self::ConnectionFailed => ErrorKind::Unavailable,
+            Self::Timeout => ErrorKind::Timeout,
+            Self::ProtocolError => ErrorKind::Other,
+            Self::TooManyRedirects => ErrorKind::Other,
+            Self::Io(e) => ErrorKind::from_io(e.kind()),
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
@@ -54,7 +96,7 @@ pub enum Method {
 pub struct Request {
     pub method: Method,
     pub url: String,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers,
     pub body: Option<Vec<u8>>,
 }
 
@@ -62,8 +104,12 @@ pub struct Request {
 #[derive(Debug, Clone)]
 pub struct Response {
     pub status: u16,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers,
     pub body: Vec<u8>,
+    /// The URLs the request was redirected to, in order, ending with the
+    /// one that answered. Empty unless a client followed redirects, as
+    /// [`RedirectingClient`] does.
+    pub redirects: Vec<String>,
 }
 
 /// HTTP client for making outgoing requests.
@@ -77,3 +123,21 @@ pub trait HttpHandler {
     /// Handle an incoming HTTP request.
     fn handle(&self, request: Request) -> impl Future<Output = Response>;
 }
+
+/// HTTP server answering incoming requests.
+///
+/// Servers are handed a listener that is already bound, so binding (and
+/// the permission to do so) stays with the caller. Request URLs are the
+/// target as sent, usually a path and query such as `/users?id=1`.
+///
+/// ```ignore
+/// let server = NativeHttpServer::new(NativeTcpListener::bind(addr)?);
+/// server.serve(&app).await?;
+/// ```
+pub trait HttpServer {
+    /// Answer requests with `handler` until the server can't go on.
+    ///
+    /// Errors accepting one connection shouldn't end it; which errors do
+    /// is up to the backend.
+    fn serve<H: HttpHandler>(&self, handler: &H) -> impl Future<Output = Result<(), Error>>;
+}
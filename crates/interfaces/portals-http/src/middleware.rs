@@ -0,0 +1,269 @@
+//! Client middleware: cross-cutting behavior layered over any [`HttpClient`].
+
+use crate::{Error, HttpClient, Request, Response};
+use std::future::Future;
+
+/// Behavior wrapped around sending a request, such as adding headers,
+/// logging, or tracing.
+///
+/// Middleware may change the request before passing it to `next`, answer
+/// without calling `next` at all, or inspect and change the result on the
+/// way out. `next` is the rest of the chain, ending in the client the
+/// middleware was layered over.
+///
+/// ```ignore
+/// struct Log<L>(L);
+///
+/// impl<L: Logger> Middleware for Log<L> {
+///     async fn send<C: HttpClient>(&self, request: Request, next: &C) -> Result<Response, Error> {
+///         let url = request.url.clone();
+///         let result = next.send(request).await;
+///         self.0.info(&format!("{} -> {:?}", url, result.as_ref().map(|r| r.status)));
+///         result
+///     }
+/// }
+/// ```
+pub trait Middleware {
+    /// Send `request` on through `next`.
+    fn send<C: HttpClient>(
+        &self,
+        request: Request,
+        next: &C,
+    ) -> impl Future<Output = Result<Response, Error>>;
+}
+
+/// Builds a client from a backend and the middleware to layer over it.
+///
+/// Middleware runs in the order it is added: the first sees each request
+/// first and its result last.
+///
+/// ```ignore
+/// let client = ClientBuilder::new(ReqwestClient::new())
+///     .with(SetHeader::new("authorization", format!("Bearer {}", token)))
+///     .with(Log(logger))
+///     .build();
+/// ```
+pub struct ClientBuilder<C, M> {
+    client: C,
+    middleware: M,
+}
+
+impl<C: HttpClient> ClientBuilder<C, Identity> {
+    /// Start from `client`, with no middleware.
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            middleware: Identity,
+        }
+    }
+}
+
+impl<C: HttpClient, M: Middleware> ClientBuilder<C, M> {
+    /// Add `middleware`, running after any added before it.
+    pub fn with<N: Middleware>(self, middleware: N) -> ClientBuilder<C, Chain<M, N>> {
+        ClientBuilder {
+            client: self.client,
+            middleware: Chain(self.middleware, middleware),
+        }
+    }
+
+    /// Build the client.
+    pub fn build(self) -> Layered<M, C> {
+        Layered::new(self.middleware, self.client)
+    }
+}
+
+/// A client with middleware layered over it.
+pub struct Layered<M, C> {
+    middleware: M,
+    inner: C,
+}
+
+impl<M: Middleware, C: HttpClient> Layered<M, C> {
+    /// Layer `middleware` over `inner`.
+    pub fn new(middleware: M, inner: C) -> Self {
+        Self { middleware, inner }
+    }
+
+    /// Get the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<M: Middleware, C: HttpClient> HttpClient for Layered<M, C> {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        self.middleware.send(request, &self.inner).await
+    }
+}
+
+/// Middleware that does nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity;
+
+impl Middleware for Identity {
+    async fn send<C: HttpClient>(&self, request: Request, next: &C) -> Result<Response, Error> {
+        next.send(request).await
+    }
+}
+
+/// Two middleware run in order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Chain<A, B>(pub A, pub B);
+
+impl<A: Middleware, B: Middleware> Middleware for Chain<A, B> {
+    async fn send<C: HttpClient>(&self, request: Request, next: &C) -> Result<Response, Error> {
+        let next = Next {
+            middleware: &self.1,
+            client: next,
+        };
+        self.0.send(request, &next).await
+    }
+}
+
+/// The rest of a chain, as seen by the middleware before it.
+struct Next<'a, M, C> {
+    middleware: &'a M,
+    client: &'a C,
+}
+
+impl<M: Middleware, C: HttpClient> HttpClient for Next<'_, M, C> {
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        self.middleware.send(request, self.client).await
+    }
+}
+
+/// Middleware that sets a header on every request, replacing any value
+/// already set under any casing of the name.
+#[derive(Debug, Clone)]
+pub struct SetHeader {
+    name: String,
+    value: String,
+}
+
+impl SetHeader {
+    /// Set `name` to `value`.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl Middleware for SetHeader {
+    async fn send<C: HttpClient>(&self, mut request: Request, next: &C) -> Result<Response, Error> {
+        request
+            .headers
+            .insert(self.name.clone(), self.value.clone());
+        next.send(request).await
+    }
+}
+
+/// Middleware that passes each request through a function.
+#[derive(Debug, Clone)]
+pub struct MapRequest<F>(pub F);
+
+impl<F: Fn(Request) -> Request> Middleware for MapRequest<F> {
+    async fn send<C: HttpClient>(&self, request: Request, next: &C) -> Result<Response, Error> {
+        next.send((self.0)(request)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Method;
+    use std::cell::RefCell;
+
+    /// Answers with the request's headers, sorted, as the body.
+    struct Echo;
+
+    impl HttpClient for Echo {
+        async fn send(&self, request: Request) -> Result<Response, Error> {
+            let mut headers: Vec<_> = request
+                .headers
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            headers.sort();
+            Ok(Response {
+                status: 200,
+                headers: Default::default(),
+                body: headers.join(",").into_bytes(),
+                redirects: Vec::new(),
+            })
+        }
+    }
+
+    /// Records when it sees the request and the response.
+    struct Trace<'a>(&'static str, &'a RefCell<Vec<String>>);
+
+    impl Middleware for Trace<'_> {
+        async fn send<C: HttpClient>(&self, request: Request, next: &C) -> Result<Response, Error> {
+            self.1.borrow_mut().push(format!("{} request", self.0));
+            let response = next.send(request).await;
+            self.1.borrow_mut().push(format!("{} response", self.0));
+            response
+        }
+    }
+
+    /// Answers every request itself.
+    struct Refuse;
+
+    impl Middleware for Refuse {
+        async fn send<C: HttpClient>(&self, _: Request, _: &C) -> Result<Response, Error> {
+            Err(Error::Other("refused".to_string()))
+        }
+    }
+
+    fn request() -> Request {
+        Request {
+            method: Method::Get,
+            url: "http://example.com/".to_string(),
+            headers: [("Authorization".to_string(), "old".to_string())].into(),
+            body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_middleware_in_order() {
+        let log = RefCell::new(Vec::new());
+        let client = ClientBuilder::new(Echo)
+            .with(Trace("outer", &log))
+            .with(SetHeader::new("authorization", "Bearer token"))
+            .with(MapRequest(|mut request: Request| {
+                request.headers.insert("x-a".to_string(), "1".to_string());
+                request
+            }))
+            .with(Trace("inner", &log))
+            .build();
+
+        let response = client.send(request()).await.unwrap();
+        assert_eq!(response.body, b"authorization=Bearer token,x-a=1");
+        assert_eq!(
+            log.into_inner(),
+            [
+                "outer request",
+                "inner request",
+                "inner response",
+                "outer response"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn middleware_can_answer_early() {
+        let log = RefCell::new(Vec::new());
+        let client = ClientBuilder::new(Echo)
+            .with(Refuse)
+            .with(Trace("after", &log))
+            .build();
+        assert!(matches!(client.send(request()).await, Err(Error::Other(_))));
+        assert!(log.into_inner().is_empty());
+
+        let unwrapped = Layered::new(Identity, Echo);
+        let response = unwrapped.send(request()).await.unwrap();
+        assert_eq!(response.body, b"Authorization=old");
+    }
+}
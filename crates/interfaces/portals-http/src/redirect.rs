@@ -0,0 +1,308 @@
+//! Following redirects.
+
+use crate::{Error, HttpClient, Method, Request, Response};
+use url::Url;
+
+/// Headers not sent on to another origin, since they may carry credentials
+/// meant for the first.
+const CREDENTIAL_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization"];
+
+/// Which redirects [`RedirectingClient`] follows.
+///
+/// By default up to 10 redirects are followed to any origin, and a `303
+/// See Other` is followed with a `GET`.
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+    max_redirects: usize,
+    same_origin: bool,
+    rewrite_see_other: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: 10,
+            same_origin: false,
+            rewrite_see_other: true,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// Create the default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow at most `n` redirects per request; 0 follows none.
+    ///
+    /// A request redirected more often fails with
+    /// [`Error::TooManyRedirects`].
+    pub fn max_redirects(mut self, n: usize) -> Self {
+        self.max_redirects = n;
+        self
+    }
+
+    /// Only follow redirects within the origin (scheme, host, and port) of
+    /// the original request. A redirect elsewhere is returned as the
+    /// response, unfollowed.
+    pub fn same_origin(mut self, same_origin: bool) -> Self {
+        self.same_origin = same_origin;
+        self
+    }
+
+    /// Whether to follow a `303 See Other` with a bodiless `GET`, as
+    /// browsers do. Otherwise it is followed like a `307`, keeping the
+    /// method and body.
+    pub fn rewrite_see_other(mut self, rewrite: bool) -> Self {
+        self.rewrite_see_other = rewrite;
+        self
+    }
+}
+
+/// Client wrapper that follows redirects.
+///
+/// Responses with status 301, 302, 303, 307, or 308 and a `location`
+/// header are followed, resolving relative locations against the URL that
+/// was redirected. The method and body are kept except as
+/// [`RedirectPolicy::rewrite_see_other`] says. Credential headers
+/// (`authorization`, `cookie`, and `proxy-authorization`) are dropped when
+/// a redirect leaves the origin. Each URL followed is recorded in the final
+/// response's [`redirects`](Response::redirects).
+///
+/// Wrap a backend that leaves redirects to its caller; one that follows
+/// them itself never shows this client a redirect.
+///
+/// ```ignore
+/// let client = RedirectingClient::new(ReqwestClient::new())
+///     .with_policy(RedirectPolicy::new().max_redirects(5).same_origin(true));
+/// ```
+pub struct RedirectingClient<C> {
+    inner: C,
+    policy: RedirectPolicy,
+}
+
+impl<C: HttpClient> RedirectingClient<C> {
+    /// Wrap a client with the default policy.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            policy: RedirectPolicy::default(),
+        }
+    }
+
+    /// Follow redirects according to `policy`.
+    pub fn with_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Get the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: HttpClient> HttpClient for RedirectingClient<C> {
+    async fn send(&self, mut request: Request) -> Result<Response, Error> {
+        let first_origin = Url::parse(&request.url).map(|url| url.origin());
+        let mut redirects = Vec::new();
+        loop {
+            let mut response = self.inner.send(request.clone()).await?;
+            let location = match response.status {
+                301 | 302 | 303 | 307 | 308 => response
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("location"))
+                    .and_then(|(_, location)| resolve(&request.url, location.trim())),
+                _ => None,
+            };
+            let Some(location) = location else {
+                response.redirects = redirects;
+                return Ok(response);
+            };
+            let next_origin = location.origin();
+            if self.policy.same_origin && first_origin.as_ref().ok() != Some(&next_origin) {
+                response.redirects = redirects;
+                return Ok(response);
+            }
+            if redirects.len() >= self.policy.max_redirects {
+                return Err(Error::TooManyRedirects);
+            }
+
+            if response.status == 303
+                && self.policy.rewrite_see_other
+                && request.method != Method::Head
+            {
+                request.method = Method::Get;
+                request.body = None;
+                request.headers.remove("content-type");
+                request.headers.remove("content-length");
+            }
+            if Url::parse(&request.url).map(|url| url.origin()).ok() != Some(next_origin) {
+                for name in CREDENTIAL_HEADERS {
+                    request.headers.remove(name);
+                }
+            }
+            request.url = location.into();
+            redirects.push(request.url.clone());
+        }
+    }
+}
+
+/// Resolve `location` against the URL `base`, if both are valid.
+fn resolve(base: &str, location: &str) -> Option<Url> {
+    if location.is_empty() {
+        return None;
+    }
+    Url::parse(base).ok()?.join(location).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Headers;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    /// Answers each URL with its route, recording what it was sent.
+    struct Site {
+        routes: HashMap<&'static str, (u16, &'static str)>,
+        seen: RefCell<Vec<Request>>,
+    }
+
+    impl Site {
+        fn new(routes: &[(&'static str, u16, &'static str)]) -> Self {
+            Self {
+                routes: routes
+                    .iter()
+                    .map(|&(url, status, location)| (url, (status, location)))
+                    .collect(),
+                seen: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl HttpClient for Site {
+        async fn send(&self, request: Request) -> Result<Response, Error> {
+            let (status, location) = self
+                .routes
+                .get(request.url.as_str())
+                .copied()
+                .unwrap_or((200, ""));
+            self.seen.borrow_mut().push(request);
+            let mut headers = Headers::new();
+            if !location.is_empty() {
+                headers.insert("Location", location);
+            }
+            Ok(Response {
+                status,
+                headers,
+                body: Vec::new(),
+                redirects: Vec::new(),
+            })
+        }
+    }
+
+    fn post(url: &str) -> Request {
+        Request {
+            method: Method::Post,
+            url: url.to_string(),
+            headers: [
+                ("Authorization".to_string(), "secret".to_string()),
+                ("Content-Type".to_string(), "text/plain".to_string()),
+            ]
+            .into(),
+            body: Some(b"data".to_vec()),
+        }
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let base = "http://example.com/a/b/c?q=1#frag";
+        let cases = [
+            ("https://other.org/x", "https://other.org/x"),
+            ("//cdn.example.com/y", "http://cdn.example.com/y"),
+            ("/root", "http://example.com/root"),
+            ("d", "http://example.com/a/b/d"),
+            ("../d?x=2", "http://example.com/a/d?x=2"),
+            ("./", "http://example.com/a/b/"),
+            ("../../../../up", "http://example.com/up"),
+            ("?page=2", "http://example.com/a/b/c?page=2"),
+        ];
+        for (location, expected) in cases {
+            assert_eq!(
+                resolve(base, location).unwrap().as_str(),
+                expected,
+                "{}",
+                location
+            );
+        }
+        assert_eq!(
+            resolve("http://example.com", "x").unwrap().as_str(),
+            "http://example.com/x"
+        );
+        assert_eq!(resolve("http://example.com", ""), None);
+        assert_eq!(
+            resolve("HTTP://Example.com:8080/p?q", "/")
+                .unwrap()
+                .origin(),
+            Url::parse("http://example.com:8080").unwrap().origin()
+        );
+    }
+
+    #[tokio::test]
+    async fn follows_redirects() {
+        let site = Site::new(&[
+            ("http://a.test/form", 303, "/done"),
+            ("http://a.test/done", 307, "http://b.test/final"),
+        ]);
+        let client = RedirectingClient::new(site);
+        let response = client.send(post("http://a.test/form")).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.redirects,
+            ["http://a.test/done", "http://b.test/final"]
+        );
+
+        let seen = client.inner().seen.borrow();
+        assert_eq!(seen[1].method, Method::Get);
+        assert_eq!(seen[1].body, None);
+        assert!(seen[1].headers.contains_key("Authorization"));
+        assert!(!seen[1].headers.contains_key("Content-Type"));
+        // Crossing to b.test drops credentials.
+        assert!(!seen[2].headers.contains_key("Authorization"));
+    }
+
+    #[tokio::test]
+    async fn keeps_method_when_asked() {
+        let site = Site::new(&[("http://a.test/form", 303, "/done")]);
+        let client = RedirectingClient::new(site)
+            .with_policy(RedirectPolicy::new().rewrite_see_other(false));
+        client.send(post("http://a.test/form")).await.unwrap();
+        let seen = client.inner().seen.borrow();
+        assert_eq!(seen[1].method, Method::Post);
+        assert_eq!(seen[1].body.as_deref(), Some(&b"data"[..]));
+    }
+
+    #[tokio::test]
+    async fn enforces_limits() {
+        let site = Site::new(&[
+            ("http://a.test/1", 302, "/2"),
+            ("http://a.test/2", 301, "http://b.test/3"),
+        ]);
+        let client =
+            RedirectingClient::new(site).with_policy(RedirectPolicy::new().same_origin(true));
+        let response = client.send(post("http://a.test/1")).await.unwrap();
+        assert_eq!(response.status, 301);
+        assert_eq!(response.redirects, ["http://a.test/2"]);
+
+        let client = RedirectingClient::new(Site::new(&[("http://a.test/loop", 302, "loop")]))
+            .with_policy(RedirectPolicy::new().max_redirects(3));
+        assert!(matches!(
+            client.send(post("http://a.test/loop")).await,
+            Err(Error::TooManyRedirects)
+        ));
+        assert_eq!(client.inner().seen.borrow().len(), 4);
+    }
+}
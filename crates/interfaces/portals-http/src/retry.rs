@@ -0,0 +1,371 @@
+//! Retrying failed requests with exponential backoff.
+
+use crate::{Error, HttpClient, Method, Request, Response};
+use portals_clocks::MonotonicClock;
+use portals_random::InsecureRandom;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Header that marks a request as safe to repeat whatever its method.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// When and how often [`RetryingClient`] retries.
+///
+/// By default a request is tried up to three times, waiting 100ms and then
+/// 200ms between tries, less up to half of each wait at random. Retries
+/// follow connection failures, timeouts, I/O errors, and the statuses
+/// 408, 425, 429, 502, 503, and 504.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    statuses: Vec<u16>,
+    retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+            statuses: vec![408, 425, 429, 502, 503, 504],
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create the default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Try each request at most `n` times, including the first; 1 turns
+    /// retrying off.
+    pub fn max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = n.max(1);
+        self
+    }
+
+    /// Wait `delay` before the first retry, doubling the wait before each
+    /// retry after that, up to `max`.
+    pub fn backoff(mut self, delay: Duration, max: Duration) -> Self {
+        self.base_delay = delay;
+        self.max_delay = max;
+        self
+    }
+
+    /// Shorten each wait by up to `fraction` of it, at random, so clients
+    /// that failed together don't retry together. 0 waits exactly; 1 waits
+    /// anywhere from no time to the full delay.
+    pub fn jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Retry responses with these statuses, instead of the defaults.
+    pub fn statuses(mut self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Retry requests whatever their method.
+    ///
+    /// By default `POST` and `PATCH` requests are only retried if they carry
+    /// an [`idempotency-key`](IDEMPOTENCY_KEY_HEADER) header, since
+    /// repeating them may repeat their effects.
+    pub fn retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.retry_non_idempotent = retry;
+        self
+    }
+
+    /// Whether `request` may be sent more than once.
+    fn may_retry(&self, request: &Request) -> bool {
+        self.retry_non_idempotent
+            || !matches!(request.method, Method::Post | Method::Patch)
+            || request.headers.contains_key(IDEMPOTENCY_KEY_HEADER)
+    }
+
+    /// Whether the outcome of a try is worth retrying.
+    fn should_retry(&self, result: &Result<Response, Error>) -> bool {
+        match result {
+            Ok(response) => self.statuses.contains(&response.status),
+            Err(Error::ConnectionFailed | Error::Timeout | Error::Io(_)) => true,
+            Err(_) => false,
+        }
+    }
+
+    /// The wait before retry number `retry` (counting from 1), before
+    /// jitter.
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry - 1);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Client wrapper that retries failed requests.
+///
+/// Waits between tries on `clock`, with jitter drawn from `random`. A
+/// `retry-after` header on a retried response, in seconds, replaces the
+/// computed wait, up to the policy's maximum delay. When every try fails,
+/// the last try's result is returned.
+///
+/// ```ignore
+/// let client = RetryingClient::new(ReqwestClient::new(), StdMonotonicClock::new(), rng)
+///     .with_policy(RetryPolicy::new().max_attempts(5));
+/// ```
+pub struct RetryingClient<C, K, R> {
+    inner: C,
+    clock: K,
+    random: Mutex<R>,
+    policy: RetryPolicy,
+}
+
+impl<C, K, R> RetryingClient<C, K, R>
+where
+    C: HttpClient,
+    K: MonotonicClock,
+    R: InsecureRandom,
+{
+    /// Wrap a client with the default policy.
+    pub fn new(inner: C, clock: K, random: R) -> Self {
+        Self {
+            inner,
+            clock,
+            random: Mutex::new(random),
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Retry according to `policy`.
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Get the wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// The wait before retry number `retry`, after `result`.
+    fn wait(&self, retry: u32, result: &Result<Response, Error>) -> Duration {
+        let retry_after = result
+            .as_ref()
+            .ok()
+            .and_then(|response| {
+                response
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+            })
+            .and_then(|(_, value)| value.trim().parse::<u64>().ok());
+        if let Some(secs) = retry_after {
+            return Duration::from_secs(secs).min(self.policy.max_delay);
+        }
+        let delay = self.policy.delay(retry);
+        // The top 53 bits, as a fraction in [0, 1).
+        let draw = (self.random.lock().unwrap().u64() >> 11) as f64 / (1u64 << 53) as f64;
+        delay.mul_f64(1.0 - self.policy.jitter * draw)
+    }
+}
+
+impl<C, K, R> HttpClient for RetryingClient<C, K, R>
+where
+    C: HttpClient,
+    K: MonotonicClock,
+    R: InsecureRandom,
+{
+    async fn send(&self, request: Request) -> Result<Response, Error> {
+        if !self.policy.may_retry(&request) {
+            return self.inner.send(request).await;
+        }
+        let mut retry = 0;
+        loop {
+            let result = self.inner.send(request.clone()).await;
+            retry += 1;
+            if retry >= self.policy.max_attempts || !self.policy.should_retry(&result) {
+                return result;
+            }
+            self.clock
+                .subscribe_duration(self.wait(retry, &result))
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_random_mock::MockInsecureRandom;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Answers with queued results, counting requests.
+    #[derive(Default)]
+    struct Queue {
+        results: RefCell<VecDeque<Result<Response, Error>>>,
+        sent: RefCell<usize>,
+    }
+
+    impl Queue {
+        fn new(results: impl IntoIterator<Item = Result<Response, Error>>) -> Self {
+            Self {
+                results: RefCell::new(results.into_iter().collect()),
+                sent: RefCell::new(0),
+            }
+        }
+    }
+
+    impl HttpClient for Queue {
+        async fn send(&self, _request: Request) -> Result<Response, Error> {
+            *self.sent.borrow_mut() += 1;
+            self.results.borrow_mut().pop_front().unwrap()
+        }
+    }
+
+    /// Records each wait and returns at once.
+    #[derive(Default)]
+    struct Waits(RefCell<Vec<Duration>>);
+
+    impl MonotonicClock for &Waits {
+        fn now(&self) -> u64 {
+            0
+        }
+
+        fn resolution(&self) -> u64 {
+            1
+        }
+
+        async fn subscribe_duration(&self, duration: Duration) {
+            self.0.borrow_mut().push(duration);
+        }
+
+        async fn subscribe_instant(&self, _instant: u64) {}
+    }
+
+    fn status(status: u16) -> Result<Response, Error> {
+        Ok(Response {
+            status,
+            headers: Default::default(),
+            body: Vec::new(),
+            redirects: Vec::new(),
+        })
+    }
+
+    fn request(method: Method) -> Request {
+        Request {
+            method,
+            url: "http://example.com/".to_string(),
+            headers: Default::default(),
+            body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_with_backoff() {
+        let waits = Waits::default();
+        let client = RetryingClient::new(
+            Queue::new([
+                Err(Error::ConnectionFailed),
+                status(503),
+                Err(Error::Timeout),
+                status(200),
+            ]),
+            &waits,
+            MockInsecureRandom::new(1),
+        )
+        .with_policy(
+            RetryPolicy::new()
+                .max_attempts(5)
+                .backoff(Duration::from_secs(1), Duration::from_secs(3))
+                .jitter(0.0),
+        );
+
+        let response = client.send(request(Method::Get)).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(*client.inner().sent.borrow(), 4);
+        assert_eq!(
+            *waits.0.borrow(),
+            [1, 2, 3].map(Duration::from_secs).as_slice()
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_with_last_result() {
+        let waits = Waits::default();
+        let client = RetryingClient::new(
+            Queue::new([status(502), status(429), status(504)]),
+            &waits,
+            MockInsecureRandom::new(7),
+        );
+        assert_eq!(client.send(request(Method::Get)).await.unwrap().status, 504);
+        let waits = waits.0.borrow();
+        assert_eq!(waits.len(), 2);
+        assert!(waits[0] > Duration::from_millis(50) && waits[0] <= Duration::from_millis(100));
+        assert!(waits[1] > Duration::from_millis(100) && waits[1] <= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn leaves_other_failures_alone() {
+        let waits = Waits::default();
+        let random = MockInsecureRandom::new(1);
+        let client = RetryingClient::new(Queue::new([status(500)]), &waits, random);
+        assert_eq!(client.send(request(Method::Get)).await.unwrap().status, 500);
+
+        let client = RetryingClient::new(
+            Queue::new([Err(Error::InvalidUrl)]),
+            &waits,
+            MockInsecureRandom::new(1),
+        );
+        assert!(client.send(request(Method::Get)).await.is_err());
+        assert!(waits.0.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn respects_idempotency() {
+        let waits = Waits::default();
+        let client = RetryingClient::new(
+            Queue::new([status(503), status(503), status(201)]),
+            &waits,
+            MockInsecureRandom::new(1),
+        );
+        assert_eq!(
+            client.send(request(Method::Post)).await.unwrap().status,
+            503
+        );
+
+        let mut keyed = request(Method::Post);
+        keyed
+            .headers
+            .insert("Idempotency-Key".to_string(), "abc".to_string());
+        assert_eq!(client.send(keyed).await.unwrap().status, 201);
+        assert_eq!(*client.inner().sent.borrow(), 3);
+    }
+
+    #[tokio::test]
+    async fn honors_retry_after() {
+        let waits = Waits::default();
+        let mut limited = status(429).unwrap();
+        limited
+            .headers
+            .insert("Retry-After".to_string(), "7".to_string());
+        let client = RetryingClient::new(
+            Queue::new([Ok(limited.clone()), Ok(limited), status(200)]),
+            &waits,
+            MockInsecureRandom::new(1),
+        )
+        .with_policy(
+            RetryPolicy::new().backoff(Duration::from_millis(100), Duration::from_secs(5)),
+        );
+        assert_eq!(client.send(request(Method::Get)).await.unwrap().status, 200);
+        assert_eq!(
+            *waits.0.borrow(),
+            [Duration::from_secs(5), Duration::from_secs(5)]
+        );
+    }
+}
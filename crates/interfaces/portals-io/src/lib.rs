@@ -2,6 +2,7 @@
 //!
 //! Based on WASI I/O.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::future::Future;
 
 /// Error type for stream operations.
@@ -27,6 +28,14 @@ impl std::fmt::Display for StreamError {
 
 impl std::error::Error for StreamError {}
 
+impl PithError for StreamError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Closed | Self::LastOperationFailed | Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// An input stream.
 pub trait InputStream {
     /// Read bytes from the stream into a buffer.
@@ -2,6 +2,7 @@
 //!
 //! Based on WASI key-value.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::future::Future;
 
@@ -23,6 +24,15 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound => ErrorKind::NotFound,
+            Self::Store(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// A key-value store.
 pub trait KeyValue {
     /// Get a value by key.
@@ -0,0 +1,292 @@
+//! Per-module log levels that can change at runtime.
+
+use crate::{ErrorKind, Level, Logger, PithError, Record};
+use std::sync::{Arc, RwLock};
+
+/// Log levels by module, shared between the loggers that consult them and
+/// whatever changes them.
+///
+/// A record passes if its level is at least the level set for the longest
+/// module path its target falls under, or else the default level. Targets
+/// fall under a module if they are its path or start with its path and
+/// `::`, so `app::db` covers `app::db::pool` but not `app::dbx`.
+///
+/// Clones share their levels, so a handle kept by an admin endpoint or a
+/// config watcher changes what every [`FilteredLogger`] built from it
+/// lets through, without a restart:
+///
+/// ```ignore
+/// let filter = DynamicFilter::parse("info,portals_http=warn")?;
+/// let logger = FilteredLogger::new(TracingLogger::new(), filter.clone());
+/// // Later, while chasing a problem:
+/// filter.set_level("app::db", Level::Trace);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DynamicFilter {
+    state: Arc<RwLock<Directives>>,
+}
+
+#[derive(Debug, Default)]
+struct Directives {
+    default: Level,
+    /// Module paths and their levels, longest path first.
+    modules: Vec<(String, Level)>,
+}
+
+impl DynamicFilter {
+    /// Let through records at `default` or above, from every module.
+    pub fn new(default: Level) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(Directives {
+                default,
+                modules: Vec::new(),
+            })),
+        }
+    }
+
+    /// Build a filter from a spec such as `info,portals_http=debug`.
+    ///
+    /// A spec is a comma-separated list of directives, each a level, which
+    /// sets the default, or `module=level`. Levels are `trace`, `debug`,
+    /// `info`, `warn`, or `error`, in any case. The default is `info`
+    /// unless the spec sets one.
+    pub fn parse(spec: &str) -> Result<Self, FilterParseError> {
+        let filter = Self::default();
+        filter.update(spec)?;
+        Ok(filter)
+    }
+
+    /// Replace every level with those in `spec`, as for
+    /// [`parse`](Self::parse). Nothing changes if the spec is invalid.
+    pub fn update(&self, spec: &str) -> Result<(), FilterParseError> {
+        let mut directives = Directives::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let invalid = || FilterParseError {
+                directive: directive.to_string(),
+            };
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim();
+                    if module.is_empty() {
+                        return Err(invalid());
+                    }
+                    let level = parse_level(level.trim()).ok_or_else(invalid)?;
+                    directives.set(module, level);
+                }
+                None => directives.default = parse_level(directive).ok_or_else(invalid)?,
+            }
+        }
+        *self.state.write().unwrap() = directives;
+        Ok(())
+    }
+
+    /// Set the level for modules no directive covers.
+    pub fn set_default(&self, level: Level) {
+        self.state.write().unwrap().default = level;
+    }
+
+    /// Set the level for `module` and the modules under it.
+    pub fn set_level(&self, module: &str, level: Level) {
+        self.state.write().unwrap().set(module, level);
+    }
+
+    /// Remove the level set for `module`, so it falls back to the level of
+    /// the module above it, or the default.
+    pub fn clear_level(&self, module: &str) {
+        self.state
+            .write()
+            .unwrap()
+            .modules
+            .retain(|(path, _)| path != module);
+    }
+
+    /// The least severe level records from `target` may have.
+    pub fn level_for(&self, target: &str) -> Level {
+        let state = self.state.read().unwrap();
+        state
+            .modules
+            .iter()
+            .find(|(module, _)| covers(module, target))
+            .map_or(state.default, |(_, level)| *level)
+    }
+
+    /// Whether a record at `level` from `target` passes.
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        level >= self.level_for(target)
+    }
+
+    /// The least severe level any module lets through.
+    pub fn min_level(&self) -> Level {
+        let state = self.state.read().unwrap();
+        state
+            .modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(state.default, Ord::min)
+    }
+}
+
+impl Directives {
+    fn set(&mut self, module: &str, level: Level) {
+        match self.modules.iter_mut().find(|(path, _)| path == module) {
+            Some((_, existing)) => *existing = level,
+            None => {
+                self.modules.push((module.to_string(), level));
+                self.modules
+                    .sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+            }
+        }
+    }
+}
+
+fn covers(module: &str, target: &str) -> bool {
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+fn parse_level(level: &str) -> Option<Level> {
+    match level.to_ascii_lowercase().as_str() {
+        "trace" => Some(Level::Trace),
+        "debug" => Some(Level::Debug),
+        "info" => Some(Level::Info),
+        "warn" => Some(Level::Warn),
+        "error" => Some(Level::Error),
+        _ => None,
+    }
+}
+
+/// A filter spec directive that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterParseError {
+    directive: String,
+}
+
+impl std::fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid log filter directive {:?} (expected a level or module=level)",
+            self.directive
+        )
+    }
+}
+
+impl std::error::Error for FilterParseError {}
+
+impl PithError for FilterParseError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::InvalidInput
+    }
+}
+
+/// A logger that drops records a [`DynamicFilter`] doesn't let through.
+///
+/// The filter is consulted on every record, so changes to it apply at
+/// once. The wrapped logger should let every level through, leaving the
+/// filtering to this one.
+#[derive(Debug)]
+pub struct FilteredLogger<L> {
+    inner: L,
+    filter: DynamicFilter,
+}
+
+impl<L: Logger> FilteredLogger<L> {
+    /// Filter the records sent to `inner`.
+    pub fn new(inner: L, filter: DynamicFilter) -> Self {
+        Self { inner, filter }
+    }
+
+    /// Get the filter.
+    pub fn filter(&self) -> &DynamicFilter {
+        &self.filter
+    }
+}
+
+impl<L: Logger> Logger for FilteredLogger<L> {
+    fn log(&self, record: &Record) {
+        if self.filter.enabled(record.level, &record.target) {
+            self.inner.log(record);
+        }
+    }
+
+    /// Whether any module lets `level` through; records are checked
+    /// against their own module when logged.
+    fn enabled(&self, level: Level) -> bool {
+        level >= self.filter.min_level() && self.inner.enabled(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Capture(Mutex<Vec<String>>);
+
+    impl Logger for Capture {
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(record.message.clone());
+        }
+
+        fn enabled(&self, _level: Level) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn longest_module_wins() {
+        let filter = DynamicFilter::parse("warn, app=info ,app::db=TRACE").unwrap();
+        assert_eq!(filter.level_for("other"), Level::Warn);
+        assert_eq!(filter.level_for("app"), Level::Info);
+        assert_eq!(filter.level_for("app::http"), Level::Info);
+        assert_eq!(filter.level_for("app::db::pool"), Level::Trace);
+        assert_eq!(filter.level_for("app::dbx"), Level::Info);
+        assert_eq!(filter.level_for("application"), Level::Warn);
+        assert_eq!(filter.min_level(), Level::Trace);
+
+        assert_eq!(
+            DynamicFilter::parse("").unwrap().level_for("x"),
+            Level::Info
+        );
+        for spec in ["loud", "app=", "=info", "app=verbose"] {
+            let err = DynamicFilter::parse(spec).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", spec);
+        }
+        assert_eq!(
+            DynamicFilter::parse("info,app=loud")
+                .unwrap_err()
+                .to_string(),
+            "invalid log filter directive \"app=loud\" (expected a level or module=level)"
+        );
+    }
+
+    #[test]
+    fn changes_apply_to_running_loggers() {
+        let capture = Capture::default();
+        let filter = DynamicFilter::new(Level::Info);
+        let logger = FilteredLogger::new(&capture, filter.clone());
+
+        logger.debug("app::db", "hidden");
+        logger.info("app::db", "shown");
+        assert!(!logger.enabled(Level::Debug));
+
+        filter.set_level("app::db", Level::Debug);
+        assert!(logger.enabled(Level::Debug));
+        logger.debug("app::db::pool", "now shown");
+        logger.debug("app::http", "still hidden");
+
+        filter.clear_level("app::db");
+        logger.debug("app::db", "hidden again");
+
+        assert!(filter.update("error,app=nonsense").is_err());
+        assert_eq!(filter.level_for("app"), Level::Info);
+        filter.update("error").unwrap();
+        logger.warn("app", "dropped");
+        filter.set_default(Level::Warn);
+        logger.warn("app", "kept");
+
+        assert_eq!(*capture.0.lock().unwrap(), ["shown", "now shown", "kept"]);
+    }
+}
@@ -1,6 +1,12 @@
 //! Structured logging interfaces.
 //!
-//! Based on WASI logging.
+//! Based on WASI logging. Levels can be set per module, and changed while
+//! running, with a [`DynamicFilter`].
+
+mod filter;
+
+pub use filter::{DynamicFilter, FilterParseError, FilteredLogger};
+pub use portals_error::{ErrorKind, PithError};
 
 /// Log levels.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -88,3 +94,13 @@ pub trait Logger {
         }
     }
 }
+
+impl<L: Logger + ?Sized> Logger for &L {
+    fn log(&self, record: &Record) {
+        (**self).log(record)
+    }
+
+    fn enabled(&self, level: Level) -> bool {
+        (**self).enabled(level)
+    }
+}
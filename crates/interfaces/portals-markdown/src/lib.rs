@@ -2,7 +2,11 @@
 //!
 //! Parse and render Markdown text.
 
+pub use portals_error::{ErrorKind, PithError};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
 
 /// Markdown renderer options.
 #[derive(Debug, Clone, Default)]
@@ -19,10 +23,27 @@ pub struct MarkdownOptions {
     pub autolinks: bool,
     /// Enable smart punctuation (quotes, dashes).
     pub smart_punctuation: bool,
-    /// Enable heading IDs.
+    /// Enable heading IDs: explicit `{#id}` attributes, and GitHub-style
+    /// slugs for headings without one. See [`MarkdownDocument::toc`].
     pub heading_ids: bool,
     /// Enable footnotes.
     pub footnotes: bool,
+    /// Enable admonitions: GitHub alerts (`> [!NOTE]`) and fenced
+    /// `:::warning` blocks. See [`AdmonitionKind`] for the supported kinds.
+    pub admonitions: bool,
+    /// Enable math: `$...$` and `$$...$$` are passed through as
+    /// `<span class="math">` and `<div class="math">` for a client-side
+    /// renderer.
+    pub math: bool,
+    /// Enable emoji shortcodes: `:rocket:` renders as the Unicode emoji,
+    /// following GitHub's shortcode names. Code is left untouched.
+    pub emoji: bool,
+    /// How raw HTML in the source is rendered. Use anything but
+    /// [`HtmlPolicy::Allow`] for untrusted input.
+    pub html: HtmlPolicy,
+    /// Rewrite link destinations and image URLs in rendered HTML, such as
+    /// to make relative links absolute. Raw HTML is not rewritten.
+    pub rewrite_urls: Option<UrlRewriter>,
 }
 
 impl MarkdownOptions {
@@ -37,6 +58,11 @@ impl MarkdownOptions {
             smart_punctuation: false,
             heading_ids: true,
             footnotes: false,
+            admonitions: false,
+            math: false,
+            emoji: false,
+            html: HtmlPolicy::Allow,
+            rewrite_urls: None,
         }
     }
 
@@ -56,10 +82,503 @@ impl MarkdownOptions {
             smart_punctuation: true,
             heading_ids: true,
             footnotes: true,
+            admonitions: true,
+            math: true,
+            emoji: true,
+            html: HtmlPolicy::Allow,
+            rewrite_urls: None,
         }
     }
 }
 
+/// What a URL passed to a [`UrlRewriter`] belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UrlKind {
+    /// A link destination.
+    Link,
+    /// An image source.
+    Image,
+}
+
+/// A callback that rewrites URLs during rendering.
+///
+/// ```ignore
+/// let options = MarkdownOptions {
+///     rewrite_urls: Some(UrlRewriter::new(|kind, url| match kind {
+///         UrlKind::Image => Some(format!("https://cdn.example.com/?src={}", url)),
+///         UrlKind::Link if url.starts_with('/') => Some(format!("https://example.com{}", url)),
+///         UrlKind::Link => None,
+///     })),
+///     ..MarkdownOptions::gfm()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct UrlRewriter(Arc<RewriteFn>);
+
+type RewriteFn = dyn Fn(UrlKind, &str) -> Option<String> + Send + Sync;
+
+impl UrlRewriter {
+    /// Wrap a function returning the new URL, or `None` to keep the
+    /// original.
+    pub fn new(f: impl Fn(UrlKind, &str) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// The rewritten URL, or `None` to keep `url`.
+    pub fn rewrite(&self, kind: UrlKind, url: &str) -> Option<String> {
+        (self.0)(kind, url)
+    }
+}
+
+impl fmt::Debug for UrlRewriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UrlRewriter(..)")
+    }
+}
+
+/// How raw HTML in Markdown source is rendered.
+///
+/// Every policy but [`Allow`](Self::Allow) also replaces link and image
+/// destinations with schemes other than `http`, `https`, `mailto`, and
+/// `tel` (such as `javascript:`) with `#`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HtmlPolicy {
+    /// Pass raw HTML through unchanged. Only safe for trusted input.
+    #[default]
+    Allow,
+    /// Drop raw HTML.
+    Strip,
+    /// Escape raw HTML so it shows as text.
+    Escape,
+    /// Keep the allowed tags and attributes and drop everything else,
+    /// including comments. Text between tags is kept, except in `script`
+    /// and `style` elements that aren't allowed.
+    Allowlist(HtmlAllowlist),
+}
+
+/// Tags and attributes kept by [`HtmlPolicy::Allowlist`].
+///
+/// Event handler (`on*`) attributes are never kept, and URL attributes
+/// such as `href` and `src` are subject to the same scheme check as
+/// Markdown links. `style` is kept as written if allowed, so don't allow it
+/// for untrusted input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlAllowlist {
+    /// Allowed tags and the attributes allowed on each.
+    tags: BTreeMap<String, BTreeSet<String>>,
+    /// Attributes allowed on every allowed tag.
+    attributes: BTreeSet<String>,
+}
+
+impl HtmlAllowlist {
+    /// An allowlist that allows nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Common formatting and structural tags, links with `href` and
+    /// `title`, and images with `src`, `alt`, `title`, `width`, and
+    /// `height`.
+    pub fn basic() -> Self {
+        let mut allowlist = Self::new()
+            .tag("a", &["href", "title"])
+            .tag("img", &["src", "alt", "title", "width", "height"])
+            .tag("abbr", &["title"])
+            .tag("td", &["align"])
+            .tag("th", &["align"]);
+        for tag in [
+            "b",
+            "blockquote",
+            "br",
+            "code",
+            "dd",
+            "del",
+            "details",
+            "div",
+            "dl",
+            "dt",
+            "em",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "hr",
+            "i",
+            "ins",
+            "kbd",
+            "li",
+            "mark",
+            "ol",
+            "p",
+            "pre",
+            "s",
+            "span",
+            "strong",
+            "sub",
+            "summary",
+            "sup",
+            "table",
+            "tbody",
+            "thead",
+            "tr",
+            "ul",
+        ] {
+            allowlist = allowlist.tag(tag, &[]);
+        }
+        allowlist
+    }
+
+    /// Allow `tag`, with `attributes` on it.
+    pub fn tag(mut self, tag: &str, attributes: &[&str]) -> Self {
+        self.tags
+            .entry(tag.to_ascii_lowercase())
+            .or_default()
+            .extend(attributes.iter().map(|a| a.to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow `attribute` on every allowed tag.
+    pub fn attribute(mut self, attribute: &str) -> Self {
+        self.attributes.insert(attribute.to_ascii_lowercase());
+        self
+    }
+
+    /// The allowed tags, each with the attributes allowed on it.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &str>)> {
+        self.tags
+            .iter()
+            .map(|(tag, attributes)| (tag.as_str(), attributes.iter().map(String::as_str)))
+    }
+
+    /// The attributes allowed on every allowed tag.
+    pub fn attributes(&self) -> impl Iterator<Item = &str> {
+        self.attributes.iter().map(String::as_str)
+    }
+
+    /// Whether `tag` is allowed. Names are compared ignoring case.
+    pub fn allows_tag(&self, tag: &str) -> bool {
+        self.tags.contains_key(&tag.to_ascii_lowercase())
+    }
+
+    /// Whether `attribute` is allowed on `tag`. Names are compared ignoring
+    /// case.
+    pub fn allows_attribute(&self, tag: &str, attribute: &str) -> bool {
+        let attribute = attribute.to_ascii_lowercase();
+        if attribute.starts_with("on") {
+            return false;
+        }
+        match self.tags.get(&tag.to_ascii_lowercase()) {
+            Some(allowed) => allowed.contains(&attribute) || self.attributes.contains(&attribute),
+            None => false,
+        }
+    }
+}
+
+/// Kind of an admonition (callout) block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdmonitionKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AdmonitionKind {
+    /// Lowercase name, as used in `:::name` and CSS classes.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Tip => "tip",
+            Self::Important => "important",
+            Self::Warning => "warning",
+            Self::Caution => "caution",
+        }
+    }
+
+    /// Parse a kind name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Note,
+            Self::Tip,
+            Self::Important,
+            Self::Warning,
+            Self::Caution,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+/// An admonition block and its plain-text content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Admonition {
+    pub kind: AdmonitionKind,
+    pub text: String,
+}
+
+/// A heading in a table of contents, with the headings nested under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    /// Anchor ID: the heading's `{#id}` attribute, or a slug of its text
+    /// made unique within the document.
+    pub id: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// Bullet character for unordered lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ListMarker {
+    /// `- item`
+    #[default]
+    Dash,
+    /// `* item`
+    Star,
+    /// `+ item`
+    Plus,
+}
+
+impl ListMarker {
+    /// The marker character.
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Dash => '-',
+            Self::Star => '*',
+            Self::Plus => '+',
+        }
+    }
+}
+
+/// Heading syntax.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HeadingStyle {
+    /// `# Heading`
+    #[default]
+    Atx,
+    /// Underlined with `===` or `---`. Only levels 1 and 2 have a setext
+    /// form; deeper headings fall back to ATX.
+    Setext,
+}
+
+/// Options for re-emitting Markdown.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownFormatOptions {
+    /// Marker for unordered list items.
+    pub list_marker: ListMarker,
+    /// Heading syntax.
+    pub heading_style: HeadingStyle,
+    /// Rewrap paragraphs to this many columns. `None` keeps the original
+    /// line breaks.
+    pub wrap_width: Option<usize>,
+}
+
+/// Column alignment in a table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// What a [`Node`] is.
+///
+/// Containers hold their content as the node's children; leaves carry
+/// their text inline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The root of the tree.
+    Document,
+    Paragraph,
+    Heading {
+        level: u8,
+        /// Explicit `{#id}` attribute.
+        id: Option<String>,
+    },
+    BlockQuote,
+    /// An admonition block; see [`MarkdownOptions::admonitions`].
+    Admonition(AdmonitionKind),
+    /// A code block. The code is held in [`Text`](Self::Text) children.
+    CodeBlock {
+        lang: Option<String>,
+    },
+    /// A raw HTML block. The markup is held in [`Html`](Self::Html)
+    /// children.
+    HtmlBlock,
+    /// A list of [`Item`](Self::Item)s. `start` is the first number of an
+    /// ordered list, or `None` for a bullet list.
+    List {
+        start: Option<u64>,
+    },
+    Item {
+        /// Task list state, if the item has a checkbox.
+        checked: Option<bool>,
+    },
+    FootnoteDefinition {
+        label: String,
+    },
+    /// A table. The first child is the [`TableHead`](Self::TableHead).
+    Table {
+        alignments: Vec<Alignment>,
+    },
+    /// The header row of a table.
+    TableHead,
+    TableRow,
+    TableCell,
+    ThematicBreak,
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link {
+        url: String,
+        title: String,
+    },
+    /// An image. Its children are the alt text.
+    Image {
+        url: String,
+        title: String,
+    },
+    Text(String),
+    /// An inline code span.
+    Code(String),
+    /// Raw HTML, inline or in an [`HtmlBlock`](Self::HtmlBlock).
+    Html(String),
+    /// A math span; see [`MarkdownOptions::math`].
+    Math {
+        display: bool,
+        tex: String,
+    },
+    FootnoteReference(String),
+    SoftBreak,
+    HardBreak,
+}
+
+/// A node in a document's syntax tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// Byte range of the node in [`MarkdownDocument::source`]. Nodes built
+    /// by hand may use an empty range.
+    pub range: Range<usize>,
+    pub children: Vec<Node>,
+}
+
+/// What to do after visiting a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Walk {
+    /// Visit the node's children, then carry on.
+    Continue,
+    /// Carry on without visiting the node's children.
+    SkipChildren,
+    /// Stop the walk.
+    Stop,
+}
+
+/// Callbacks for a depth-first traversal with [`Node::accept`].
+pub trait Visitor {
+    /// Called before a node's children are visited.
+    fn enter(&mut self, _node: &Node) -> Walk {
+        Walk::Continue
+    }
+
+    /// Called after a node's children are visited. Not called for nodes
+    /// whose children were skipped, or once the walk has stopped.
+    fn leave(&mut self, _node: &Node) {}
+}
+
+impl Node {
+    /// A node with no children.
+    pub fn new(kind: NodeKind, range: Range<usize>) -> Self {
+        Self {
+            kind,
+            range,
+            children: Vec::new(),
+        }
+    }
+
+    /// Set the children.
+    pub fn with_children(mut self, children: Vec<Node>) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Plain text content of this node and its descendants, as
+    /// [`MarkdownDocument::to_text`] would extract it.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.walk(|node| {
+            if let NodeKind::Text(t) | NodeKind::Code(t) | NodeKind::Math { tex: t, .. } =
+                &node.kind
+            {
+                text.push_str(t);
+            }
+            Walk::Continue
+        });
+        text
+    }
+
+    /// Call `f` on this node and its descendants, depth-first in document
+    /// order.
+    pub fn walk(&self, mut f: impl FnMut(&Node) -> Walk) {
+        self.walk_inner(&mut f);
+    }
+
+    fn walk_inner(&self, f: &mut impl FnMut(&Node) -> Walk) -> bool {
+        match f(self) {
+            Walk::Stop => false,
+            Walk::SkipChildren => true,
+            Walk::Continue => self.children.iter().all(|child| child.walk_inner(f)),
+        }
+    }
+
+    /// Call `f` on this node and its descendants, depth-first in document
+    /// order, allowing each to be modified.
+    ///
+    /// A node is visited before its children, so `f` may replace, insert,
+    /// or remove children and the walk continues over the new ones.
+    pub fn walk_mut(&mut self, mut f: impl FnMut(&mut Node) -> Walk) {
+        self.walk_mut_inner(&mut f);
+    }
+
+    fn walk_mut_inner(&mut self, f: &mut impl FnMut(&mut Node) -> Walk) -> bool {
+        match f(self) {
+            Walk::Stop => false,
+            Walk::SkipChildren => true,
+            Walk::Continue => self
+                .children
+                .iter_mut()
+                .all(|child| child.walk_mut_inner(f)),
+        }
+    }
+
+    /// Traverse this node and its descendants with `visitor`.
+    pub fn accept<V: Visitor>(&self, visitor: &mut V) {
+        self.accept_inner(visitor);
+    }
+
+    fn accept_inner<V: Visitor>(&self, visitor: &mut V) -> bool {
+        match visitor.enter(self) {
+            Walk::Stop => return false,
+            Walk::SkipChildren => return true,
+            Walk::Continue => {}
+        }
+        if !self
+            .children
+            .iter()
+            .all(|child| child.accept_inner(visitor))
+        {
+            return false;
+        }
+        visitor.leave(self);
+        true
+    }
+}
+
 /// Render Markdown to HTML.
 pub trait MarkdownRenderer {
     /// Render Markdown text to HTML.
@@ -83,11 +602,46 @@ pub trait MarkdownDocument {
     /// Get all headings with their levels and text.
     fn headings(&self) -> Vec<(u8, String)>;
 
+    /// The headings as a nested table of contents.
+    ///
+    /// Each heading is nested under the closest preceding heading of a
+    /// lower level. IDs match those rendered into HTML when
+    /// [`MarkdownOptions::heading_ids`] is enabled.
+    fn toc(&self) -> Vec<TocEntry>;
+
     /// Get all links (text, url).
     fn links(&self) -> Vec<(String, String)>;
 
+    /// Get all images (alt text, url).
+    fn images(&self) -> Vec<(String, String)>;
+
+    /// Get all task list items (checked, text), in document order.
+    ///
+    /// The text excludes nested list items. Empty unless parsed with
+    /// [`MarkdownOptions::task_lists`] or [`MarkdownOptions::gfm`].
+    fn tasks(&self) -> Vec<(bool, String)>;
+
     /// Get all code blocks (language, code).
     fn code_blocks(&self) -> Vec<(Option<String>, String)>;
+
+    /// Get all admonition blocks, in document order.
+    ///
+    /// Empty unless parsed with [`MarkdownOptions::admonitions`].
+    fn admonitions(&self) -> Vec<Admonition>;
+
+    /// The document's syntax tree, rooted at a [`NodeKind::Document`] node.
+    fn ast(&self) -> Node;
+
+    /// Re-emit as normalized CommonMark with default formatting.
+    fn to_markdown(&self) -> String {
+        self.to_markdown_with_options(&MarkdownFormatOptions::default())
+    }
+
+    /// Re-emit as normalized CommonMark.
+    ///
+    /// The output parses to the same document as the source. Emphasis is
+    /// written with `*`, code blocks are fenced, and links are inlined.
+    fn to_markdown_with_options(&self, options: &MarkdownFormatOptions) -> String;
 }
 
 /// Parse Markdown into a document.
@@ -102,6 +656,21 @@ pub trait MarkdownParser {
     fn parse_with_options(&self, markdown: &str, options: &MarkdownOptions) -> Self::Document;
 }
 
+/// Reformat Markdown source as normalized Markdown, for building
+/// `mdfmt`-style formatters.
+///
+/// The output renders to the same HTML as the input, and formatting it
+/// again leaves it unchanged.
+pub trait MarkdownFormatter {
+    /// Reformat with default options.
+    fn format(&self, markdown: &str) -> String {
+        self.format_with_options(markdown, &MarkdownFormatOptions::default())
+    }
+
+    /// Reformat with the given heading style, list marker, and wrap width.
+    fn format_with_options(&self, markdown: &str, options: &MarkdownFormatOptions) -> String;
+}
+
 /// Error type for Markdown operations.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MarkdownError {
@@ -121,3 +690,12 @@ impl fmt::Display for MarkdownError {
 }
 
 impl std::error::Error for MarkdownError {}
+
+impl PithError for MarkdownError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidInput(_) => ErrorKind::InvalidInput,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
@@ -5,6 +5,10 @@
 //!
 //! See ADR-0004 for rationale.
 
+mod metadata;
+
+pub use metadata::MetadataMap;
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::future::Future;
 use std::time::Duration;
@@ -29,13 +33,23 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Closed => ErrorKind::Other,
+            Self::Timeout => ErrorKind::Timeout,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// A message with payload and metadata.
 #[derive(Debug, Clone)]
 pub struct Message {
     /// Message payload.
     pub data: Vec<u8>,
     /// Optional metadata/headers.
-    pub metadata: Vec<(String, String)>,
+    pub metadata: MetadataMap,
 }
 
 impl Message {
@@ -43,13 +57,13 @@ impl Message {
     pub fn new(data: impl Into<Vec<u8>>) -> Self {
         Self {
             data: data.into(),
-            metadata: Vec::new(),
+            metadata: MetadataMap::new(),
         }
     }
 
-    /// Add metadata to the message.
+    /// Add metadata to the message, replacing any value for `key`.
     pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
-        self.metadata.push((key.into(), value.into()));
+        self.metadata.insert(key, value);
         self
     }
 }
@@ -61,6 +75,19 @@ impl Message {
 pub trait Sender {
     /// Send a message.
     fn send(&self, message: Message) -> impl Future<Output = Result<(), Error>>;
+
+    /// Send messages in order.
+    ///
+    /// Stops at the first error; messages before it may have been sent.
+    /// The default sends one at a time.
+    fn send_batch(&self, messages: &[Message]) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            for message in messages {
+                self.send(message.clone()).await?;
+            }
+            Ok(())
+        }
+    }
 }
 
 /// A message receiver.
@@ -76,6 +103,39 @@ pub trait Receiver {
 
     /// Try to receive a message without blocking.
     fn try_receive(&self) -> impl Future<Output = Result<Option<Message>, Error>>;
+
+    /// Receive up to `max` messages.
+    ///
+    /// Waits up to `max_wait` for the first message, then takes whatever
+    /// else is already queued without waiting further. Returns an empty
+    /// batch if nothing arrives in time, and `Error::Closed` only when the
+    /// receiver is closed with nothing left to take.
+    fn receive_batch(
+        &self,
+        max: usize,
+        max_wait: Duration,
+    ) -> impl Future<Output = Result<Vec<Message>, Error>> {
+        async move {
+            let mut batch = Vec::new();
+            if max == 0 {
+                return Ok(batch);
+            }
+            match self.receive_timeout(max_wait).await {
+                Ok(message) => batch.push(message),
+                Err(Error::Timeout) => return Ok(batch),
+                Err(e) => return Err(e),
+            }
+            // Errors after the first message surface on the next call, so
+            // the messages already taken are not lost.
+            while batch.len() < max {
+                match self.try_receive().await {
+                    Ok(Some(message)) => batch.push(message),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            Ok(batch)
+        }
+    }
 }
 
 /// A channel for point-to-point messaging.
@@ -118,6 +178,19 @@ pub trait Topic {
     /// Publish a message to all subscribers.
     fn publish(&self, message: Message) -> impl Future<Output = Result<(), Error>>;
 
+    /// Publish messages in order.
+    ///
+    /// Stops at the first error; messages before it may have been
+    /// published. The default publishes one at a time.
+    fn publish_batch(&self, messages: &[Message]) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            for message in messages {
+                self.publish(message.clone()).await?;
+            }
+            Ok(())
+        }
+    }
+
     /// Subscribe to receive messages.
     fn subscribe(&self) -> impl Future<Output = Result<Self::Subscriber, Error>>;
 }
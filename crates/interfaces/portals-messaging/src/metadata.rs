@@ -0,0 +1,145 @@
+//! Message metadata.
+
+use std::collections::HashMap;
+
+/// Message metadata: string keys mapped to string values.
+///
+/// Keys are case-sensitive and unique; inserting an existing key replaces
+/// its value in place. Iteration follows insertion order, so metadata
+/// reaches backends in the order it was added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataMap {
+    entries: Vec<(String, String)>,
+    index: HashMap<String, usize>,
+}
+
+impl MetadataMap {
+    /// Create an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the value for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.index.get(key).map(|&i| self.entries[i].1.as_str())
+    }
+
+    /// Whether `key` is present.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Set `key` to `value`, returning the previous value.
+    ///
+    /// A new key is added at the end; an existing key keeps its position.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        let key = key.into();
+        let value = value.into();
+        match self.index.get(&key) {
+            Some(&i) => Some(std::mem::replace(&mut self.entries[i].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Remove `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for (key, _) in &self.entries[i..] {
+            *self.index.get_mut(key).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    /// Remove every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    /// Iterate over `(key, value)` pairs in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Iterate over keys in insertion order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(k, _)| k.as_str())
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for MetadataMap {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for MetadataMap {
+    /// Later duplicates of a key replace earlier ones.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl IntoIterator for MetadataMap {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MetadataMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get_remove() {
+        let mut map = MetadataMap::new();
+        assert_eq!(map.insert("b", "1"), None);
+        assert_eq!(map.insert("a", "2"), None);
+        assert_eq!(map.insert("c", "3"), None);
+        assert_eq!(map.insert("b", "4"), Some("1".to_string()));
+        assert_eq!(map.get("b"), Some("4"));
+        assert_eq!(map.get("B"), None);
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["b", "a", "c"]);
+
+        assert_eq!(map.remove("b"), Some("4".to_string()));
+        assert_eq!(map.remove("b"), None);
+        assert_eq!(map.get("a"), Some("2"));
+        assert_eq!(map.get("c"), Some("3"));
+        assert_eq!(map.iter().collect::<Vec<_>>(), [("a", "2"), ("c", "3")]);
+        assert_eq!(map.len(), 2);
+
+        let from: MetadataMap = [("x", "1"), ("y", "2"), ("x", "3")].into_iter().collect();
+        assert_eq!(from.iter().collect::<Vec<_>>(), [("x", "3"), ("y", "2")]);
+    }
+}
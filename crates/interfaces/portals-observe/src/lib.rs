@@ -2,6 +2,101 @@
 //!
 //! Based on WASI observe.
 
+use std::fmt::{Display, Write};
+use std::ops::Deref;
+
+/// Header and metadata key carrying a [`SpanContext`].
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Header and metadata key carrying a request ID, which correlates the
+/// logs, spans, and downstream calls made on behalf of one request.
+pub const REQUEST_ID: &str = "x-request-id";
+
+/// Span attribute a request ID is recorded under.
+pub const REQUEST_ID_ATTRIBUTE: &str = "request.id";
+
+/// Identifies a span across process boundaries.
+///
+/// Carried in the W3C Trace Context `traceparent` format, e.g.
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanContext {
+    /// Trace ID shared by every span in the trace.
+    pub trace_id: [u8; 16],
+    /// ID of this span.
+    pub span_id: [u8; 8],
+    /// Whether the trace is being recorded upstream.
+    pub sampled: bool,
+}
+
+impl SpanContext {
+    /// Whether both IDs are non-zero, as the format requires.
+    pub fn is_valid(&self) -> bool {
+        self.trace_id != [0; 16] && self.span_id != [0; 8]
+    }
+
+    /// Format as a version 00 `traceparent` value.
+    pub fn to_traceparent(&self) -> String {
+        let mut out = String::with_capacity(55);
+        out.push_str("00-");
+        for byte in self.trace_id {
+            let _ = write!(out, "{:02x}", byte);
+        }
+        out.push('-');
+        for byte in self.span_id {
+            let _ = write!(out, "{:02x}", byte);
+        }
+        out.push_str(if self.sampled { "-01" } else { "-00" });
+        out
+    }
+
+    /// Parse a `traceparent` value.
+    ///
+    /// Returns `None` if the value is malformed or either ID is zero.
+    /// Versions after 00 are accepted if they start with the 00 fields.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let version = parse_hex::<1>(value.get(..2)?)?[0];
+        let fields = match version {
+            0xff => return None,
+            0 if value.len() != 55 => return None,
+            0 => value,
+            _ if value.len() > 55 && value.as_bytes()[55] != b'-' => return None,
+            _ => value.get(..55)?,
+        };
+        if [2, 35, 52].iter().any(|&i| fields.as_bytes()[i] != b'-') {
+            return None;
+        }
+        let trace_id = parse_hex::<16>(fields.get(3..35)?)?;
+        let span_id = parse_hex::<8>(fields.get(36..52)?)?;
+        let flags = parse_hex::<1>(fields.get(53..55)?)?[0];
+        let context = Self {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+        };
+        context.is_valid().then_some(context)
+    }
+}
+
+/// Parse exactly `N` bytes of lowercase hex.
+fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.as_bytes();
+    if hex.len() != N * 2 {
+        return None;
+    }
+    let digit = |c: u8| match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    };
+    let mut out = [0; N];
+    for (byte, pair) in out.iter_mut().zip(hex.chunks(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(out)
+}
+
 /// A span for distributed tracing.
 pub trait Span {
     /// Set an attribute on this span.
@@ -10,10 +105,183 @@ pub trait Span {
     /// Add an event to this span.
     fn add_event(&self, name: &str);
 
+    /// The context to propagate to remote children of this span, if the
+    /// tracer records one.
+    fn context(&self) -> Option<SpanContext> {
+        None
+    }
+
     /// End the span.
     fn end(self);
 }
 
+/// Ends a span when dropped, so early returns, `?`, and panics can't leave
+/// it open.
+///
+/// Dereferences to the span, so attributes and events can be added
+/// through the guard. A span ended by a panic gets an `error` attribute.
+///
+/// ```ignore
+/// let span = SpanGuard::new(tracer.start_span("load config"));
+/// let text = std::fs::read_to_string(path)?;
+/// span.set_attribute("config.bytes", &text.len().to_string());
+/// ```
+#[derive(Debug)]
+pub struct SpanGuard<S: Span> {
+    span: Option<S>,
+}
+
+impl<S: Span> SpanGuard<S> {
+    /// Guard a started span.
+    pub fn new(span: S) -> Self {
+        Self { span: Some(span) }
+    }
+
+    /// End the span now.
+    pub fn end(self) {}
+}
+
+impl<S: Span> Deref for SpanGuard<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        self.span.as_ref().expect("span already ended")
+    }
+}
+
+impl<S: Span> Drop for SpanGuard<S> {
+    fn drop(&mut self) {
+        if let Some(span) = self.span.take() {
+            if std::thread::panicking() {
+                span.set_attribute("error", "panicked");
+            }
+            span.end();
+        }
+    }
+}
+
+/// The result of code run by [`Tracer::in_span`], which may be a failure
+/// to record on the span.
+pub trait Outcome {
+    /// A description of the failure, if this is one.
+    fn error(&self) -> Option<String>;
+}
+
+impl Outcome for () {
+    fn error(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<T, E: Display> Outcome for Result<T, E> {
+    fn error(&self) -> Option<String> {
+        self.as_ref().err().map(ToString::to_string)
+    }
+}
+
+/// The role a span plays in a trace, as in OpenTelemetry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SpanKind {
+    /// An operation inside the process.
+    #[default]
+    Internal,
+    /// Handling a request from a remote client.
+    Server,
+    /// A request to a remote server.
+    Client,
+    /// Sending a message to a broker, without waiting for it to be handled.
+    Producer,
+    /// Handling a message from a broker.
+    Consumer,
+}
+
+/// The parent of a span being built.
+#[derive(Debug)]
+pub enum Parent<'a, S> {
+    /// A span in this process.
+    Local(&'a S),
+    /// A span in another process.
+    Remote(SpanContext),
+}
+
+/// Describes a span before it starts, created by [`Tracer::span_builder`].
+///
+/// Some exporters only accept a span's kind and attributes at start time,
+/// so set them here rather than on the started span.
+///
+/// ```ignore
+/// let span = tracer
+///     .span_builder("GET /users")
+///     .with_kind(SpanKind::Server)
+///     .with_attributes([("http.method", "GET"), ("http.route", "/users")])
+///     .with_remote_parent(context)
+///     .start();
+/// ```
+pub struct SpanBuilder<'a, T: Tracer + ?Sized> {
+    tracer: &'a T,
+    /// Span name.
+    pub name: String,
+    /// Span kind.
+    pub kind: SpanKind,
+    /// Attributes in the order they were added.
+    pub attributes: Vec<(String, String)>,
+    /// Parent span, or `None` for a root span.
+    pub parent: Option<Parent<'a, T::Span>>,
+}
+
+impl<'a, T: Tracer + ?Sized> SpanBuilder<'a, T> {
+    /// Describe an internal root span with no attributes.
+    pub fn new(tracer: &'a T, name: &str) -> Self {
+        Self {
+            tracer,
+            name: name.to_string(),
+            kind: SpanKind::Internal,
+            attributes: Vec::new(),
+            parent: None,
+        }
+    }
+
+    /// Set the span kind.
+    pub fn with_kind(mut self, kind: SpanKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Add an attribute.
+    pub fn with_attribute(mut self, key: &str, value: &str) -> Self {
+        self.attributes.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add several attributes.
+    pub fn with_attributes<K, V>(mut self, attributes: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.attributes
+            .extend(attributes.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Make the span a child of a span in this process.
+    pub fn with_parent(mut self, parent: &'a T::Span) -> Self {
+        self.parent = Some(Parent::Local(parent));
+        self
+    }
+
+    /// Make the span a child of a span in another process.
+    pub fn with_remote_parent(mut self, parent: SpanContext) -> Self {
+        self.parent = Some(Parent::Remote(parent));
+        self
+    }
+
+    /// Start the span.
+    pub fn start(self) -> T::Span {
+        self.tracer.start_span_from(self)
+    }
+}
+
 /// A tracer that creates spans.
 pub trait Tracer {
     /// The span type.
@@ -24,6 +292,79 @@ pub trait Tracer {
 
     /// Start a span as a child of another span.
     fn start_span_with_parent(&self, name: &str, parent: &Self::Span) -> Self::Span;
+
+    /// Start a span as a child of a span in another process, e.g. one
+    /// whose context arrived in a `traceparent` header.
+    ///
+    /// Tracers that don't propagate context start a new root span.
+    fn start_span_with_remote_parent(&self, name: &str, parent: &SpanContext) -> Self::Span {
+        let _ = parent;
+        self.start_span(name)
+    }
+
+    /// Describe a span to start, with a kind, attributes, and parent.
+    fn span_builder(&self, name: &str) -> SpanBuilder<'_, Self> {
+        SpanBuilder::new(self, name)
+    }
+
+    /// Start a span described by a builder.
+    ///
+    /// The default starts the span with the builder's parent, then sets its
+    /// attributes; the kind is dropped. Tracers that record kinds, or need
+    /// attributes at start time, override this.
+    fn start_span_from(&self, builder: SpanBuilder<'_, Self>) -> Self::Span {
+        let span = match builder.parent {
+            None => self.start_span(&builder.name),
+            Some(Parent::Local(parent)) => self.start_span_with_parent(&builder.name, parent),
+            Some(Parent::Remote(parent)) => {
+                self.start_span_with_remote_parent(&builder.name, &parent)
+            }
+        };
+        for (key, value) in &builder.attributes {
+            span.set_attribute(key, value);
+        }
+        span
+    }
+
+    /// Run `f` in a new span, ending it afterwards.
+    ///
+    /// If `f` returns an error, its message is recorded as the span's
+    /// `error` attribute. The span also ends if `f` panics.
+    ///
+    /// ```ignore
+    /// let user = tracer.in_span("load user", |span| {
+    ///     span.set_attribute("user.id", id);
+    ///     db.load_user(id)
+    /// })?;
+    /// ```
+    fn in_span<R: Outcome>(&self, name: &str, f: impl FnOnce(&Self::Span) -> R) -> R {
+        let span = SpanGuard::new(self.start_span(name));
+        let outcome = f(&span);
+        if let Some(error) = outcome.error() {
+            span.set_attribute("error", &error);
+        }
+        outcome
+    }
+
+    /// Await `future` in a new span, ending it afterwards, as
+    /// [`in_span`](Self::in_span) does.
+    ///
+    /// The span also ends if the returned future is dropped before it
+    /// completes.
+    fn in_span_async<F>(&self, name: &str, future: F) -> impl Future<Output = F::Output>
+    where
+        F: Future,
+        F::Output: Outcome,
+    {
+        let span = SpanGuard::new(self.start_span(name));
+        async move {
+            let outcome = future.await;
+            if let Some(error) = outcome.error() {
+                span.set_attribute("error", &error);
+            }
+            outcome
+        }
+    }
 }
 
 /// A counter metric (monotonically increasing).
@@ -33,9 +374,22 @@ pub trait Counter {
 }
 
 /// A gauge metric (can go up or down).
+///
+/// Either set outright, for values sampled from elsewhere (queue depth,
+/// temperature), or adjusted with [`add`](Self::add) and
+/// [`sub`](Self::sub), for values tracked as things come and go (active
+/// connections, in-flight requests).
 pub trait Gauge {
     /// Set the gauge value.
     fn set(&self, value: f64);
+
+    /// Add to the gauge value. Concurrent adjustments are not lost.
+    fn add(&self, delta: f64);
+
+    /// Subtract from the gauge value.
+    fn sub(&self, delta: f64) {
+        self.add(-delta);
+    }
 }
 
 /// A histogram metric (records distributions).
@@ -62,3 +416,34 @@ pub trait Metrics {
     /// Create or get a histogram.
     fn histogram(&self, name: &str, description: &str) -> Self::Histogram;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traceparent_round_trip() {
+        let value = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = SpanContext::from_traceparent(value).unwrap();
+        assert_eq!(context.trace_id[0], 0x4b);
+        assert_eq!(context.span_id[7], 0xb7);
+        assert!(context.sampled);
+        assert_eq!(context.to_traceparent(), value);
+
+        let future = "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra";
+        let context = SpanContext::from_traceparent(future).unwrap();
+        assert!(!context.sampled);
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00_4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(SpanContext::from_traceparent(invalid), None, "{}", invalid);
+        }
+    }
+}
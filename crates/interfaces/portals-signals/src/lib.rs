@@ -0,0 +1,247 @@
+//! Process signal and graceful shutdown interfaces.
+//!
+//! `Signals` delivers termination requests from the host (SIGINT, SIGTERM,
+//! etc.). `Shutdown` is a cloneable token that servers and consumers watch:
+//! once triggered they stop accepting new work, drain in-flight work, and
+//! close.
+//!
+//! ```ignore
+//! let shutdown = Shutdown::new();
+//!
+//! // Trigger shutdown on the first termination signal.
+//! let token = shutdown.clone();
+//! tokio::spawn(async move { token.trigger_on(&signals).await });
+//!
+//! // Servers and consumers watch the token.
+//! server.serve_with_shutdown(&app, &shutdown, Duration::from_secs(10)).await?;
+//! ```
+
+pub use portals_error::{ErrorKind, PithError};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Signal errors.
+#[derive(Debug)]
+pub enum Error {
+    /// Signal handling is not available on this platform.
+    Unsupported,
+    /// Other error.
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => write!(f, "signals not supported on this platform"),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Unsupported => ErrorKind::Unsupported,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// A termination request from the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// Interactive interrupt (SIGINT, Ctrl-C).
+    Interrupt,
+    /// Termination request (SIGTERM).
+    Terminate,
+    /// Terminal hangup (SIGHUP).
+    Hangup,
+}
+
+/// A source of termination signals.
+///
+/// This trait operates on an already-registered signal listener.
+/// The listener is obtained from a backend constructor.
+pub trait Signals {
+    /// Wait for the next signal.
+    fn recv(&self) -> impl Future<Output = Result<Signal, Error>>;
+}
+
+/// A graceful shutdown token.
+///
+/// Clones share state. Work is tracked with [`Shutdown::track`], which
+/// refuses new work once shutdown has been triggered; [`Shutdown::drain`]
+/// (or [`Shutdown::drained`]) then waits for tracked work to finish.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    triggered: bool,
+    in_flight: usize,
+    wakers: Vec<Waker>,
+}
+
+impl Shutdown {
+    /// Create a token that has not been triggered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trigger shutdown. Idempotent.
+    pub fn trigger(&self) {
+        let mut state = self.lock();
+        if !state.triggered {
+            state.triggered = true;
+            self.notify(&mut state);
+        }
+    }
+
+    /// Whether shutdown has been triggered.
+    pub fn is_triggered(&self) -> bool {
+        self.lock().triggered
+    }
+
+    /// Number of tracked units of work still in flight.
+    pub fn in_flight(&self) -> usize {
+        self.lock().in_flight
+    }
+
+    /// Start tracking a unit of work.
+    ///
+    /// Returns `None` if shutdown has been triggered, meaning the work should
+    /// not be started. The work counts as in flight until the guard drops.
+    pub fn track(&self) -> Option<WorkGuard> {
+        let mut state = self.lock();
+        if state.triggered {
+            return None;
+        }
+        state.in_flight += 1;
+        Some(WorkGuard {
+            shutdown: self.clone(),
+        })
+    }
+
+    /// Wait until shutdown is triggered.
+    pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+        WaitFor {
+            shutdown: self,
+            done: |s: &State| s.triggered,
+        }
+    }
+
+    /// Wait until shutdown is triggered and all tracked work has finished.
+    pub fn drained(&self) -> impl Future<Output = ()> + '_ {
+        WaitFor {
+            shutdown: self,
+            done: |s: &State| s.triggered && s.in_flight == 0,
+        }
+    }
+
+    /// Block until shutdown is triggered or `timeout` elapses.
+    ///
+    /// Returns whether shutdown was triggered.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.block_until(timeout, |s| s.triggered)
+    }
+
+    /// Block until all tracked work has finished or `timeout` elapses.
+    ///
+    /// Intended to be called after [`Shutdown::trigger`]. Returns whether
+    /// everything drained in time.
+    pub fn drain(&self, timeout: Duration) -> bool {
+        self.block_until(timeout, |s| s.in_flight == 0)
+    }
+
+    /// Wait for the next signal from `signals`, then trigger shutdown.
+    pub async fn trigger_on(&self, signals: &impl Signals) -> Result<Signal, Error> {
+        let signal = signals.recv().await?;
+        self.trigger();
+        Ok(signal)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn notify(&self, state: &mut State) {
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        self.inner.changed.notify_all();
+    }
+
+    fn block_until(&self, timeout: Duration, done: impl Fn(&State) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        while !done(&state) {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .inner
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+        true
+    }
+}
+
+/// Marks a unit of work as in flight until dropped.
+#[derive(Debug)]
+pub struct WorkGuard {
+    shutdown: Shutdown,
+}
+
+impl Drop for WorkGuard {
+    fn drop(&mut self) {
+        let mut state = self.shutdown.lock();
+        state.in_flight -= 1;
+        if state.in_flight == 0 {
+            self.shutdown.notify(&mut state);
+        }
+    }
+}
+
+struct WaitFor<'a, F> {
+    shutdown: &'a Shutdown,
+    done: F,
+}
+
+impl<F> Future for WaitFor<'_, F>
+where
+    F: Fn(&State) -> bool + Unpin,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.shutdown.lock();
+        if (self.done)(&state) {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
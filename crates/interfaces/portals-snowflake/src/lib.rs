@@ -2,7 +2,11 @@
 //!
 //! Twitter-style snowflake IDs: 64-bit unique identifiers that encode
 //! timestamp, machine ID, and sequence number.
+//!
+//! Snowflake IDs can also be laid out as UUIDv7 values (see
+//! [`SnowflakeId::to_uuid_v7`]) for storage in UUID columns.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 
 /// A snowflake ID (64-bit).
@@ -37,8 +41,52 @@ impl SnowflakeId {
     pub fn sequence(&self) -> u16 {
         (self.0 & 0xFFF) as u16
     }
+
+    /// Lay this ID out as a UUIDv7 (RFC 9562), given the epoch of the
+    /// generator that made it.
+    ///
+    /// The UUID's 48-bit timestamp is the ID's absolute time in
+    /// milliseconds. The machine ID and sequence fill the top 22 of the
+    /// remaining 74 bits, and the rest are zero. Use `to_be_bytes` for the
+    /// UUID's byte form.
+    ///
+    /// The layout is lossless, so:
+    ///
+    /// - IDs that differ give UUIDs that differ. IDs from generators with
+    ///   different epochs give the same UUID only if they were made in the
+    ///   same millisecond with the same machine ID and sequence, which is
+    ///   the same collision snowflakes themselves are subject to.
+    /// - The UUIDs sort like the IDs, both as integers and byte-wise, as
+    ///   databases compare UUIDs. Against UUIDv7s from other sources they
+    ///   sort by millisecond only.
+    pub fn to_uuid_v7(&self, epoch: u64) -> u128 {
+        let millis = (self.timestamp_bits() + epoch) as u128 & UUID_TIMESTAMP_MASK;
+        let rest = (self.0 & 0x3F_FFFF) as u128;
+        (millis << 80) | (0x7 << 76) | ((rest >> 10) << 64) | (0b10 << 62) | ((rest & 0x3FF) << 52)
+    }
+
+    /// Recover an ID laid out with [`to_uuid_v7`](Self::to_uuid_v7) and
+    /// the same epoch.
+    ///
+    /// Returns `None` for any other UUID: one that isn't a version 7 UUID,
+    /// has random bits where the layout has zeros, or has a timestamp that
+    /// doesn't fit a snowflake with this epoch.
+    pub fn from_uuid_v7(uuid: u128, epoch: u64) -> Option<Self> {
+        let version = (uuid >> 76) & 0xF;
+        let variant = (uuid >> 62) & 0b11;
+        if version != 7 || variant != 0b10 || uuid & ((1 << 52) - 1) != 0 {
+            return None;
+        }
+        let millis = (uuid >> 80) as u64;
+        let timestamp = millis.checked_sub(epoch).filter(|t| *t >> 41 == 0)?;
+        let rest = (((uuid >> 64) & 0xFFF) << 10) as u64 | ((uuid >> 52) & 0x3FF) as u64;
+        Some(Self((timestamp << 22) | rest))
+    }
 }
 
+/// The 48 bits of a UUIDv7's timestamp.
+const UUID_TIMESTAMP_MASK: u128 = (1 << 48) - 1;
+
 impl fmt::Display for SnowflakeId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -95,6 +143,16 @@ impl fmt::Display for SnowflakeError {
 
 impl std::error::Error for SnowflakeError {}
 
+impl PithError for SnowflakeError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ClockMovedBackwards { .. } | Self::SequenceExhausted => ErrorKind::Unavailable,
+            Self::InvalidMachineId(_) => ErrorKind::InvalidInput,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// Generator for snowflake IDs.
 pub trait Snowflake {
     /// Generate the next snowflake ID.
@@ -112,4 +170,57 @@ pub trait Snowflake {
     fn extract_timestamp(&self, id: SnowflakeId) -> u64 {
         id.timestamp_bits() + self.epoch()
     }
+
+    /// Generate the next ID as a UUIDv7; see [`SnowflakeId::to_uuid_v7`].
+    fn next_uuid_v7(&self) -> Result<u128, SnowflakeError> {
+        Ok(self.next_id()?.to_uuid_v7(self.epoch()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: u64 = 1_288_834_974_657;
+
+    fn id(timestamp: u64, machine: u64, sequence: u64) -> SnowflakeId {
+        SnowflakeId((timestamp << 22) | (machine << 12) | sequence)
+    }
+
+    #[test]
+    fn uuid_v7_layout() {
+        let id = id(1_000, 0x3FF, 0xABC);
+        let uuid = id.to_uuid_v7(EPOCH);
+        assert_eq!((uuid >> 80) as u64, EPOCH + 1_000);
+        assert_eq!((uuid >> 76) & 0xF, 7);
+        assert_eq!((uuid >> 62) & 0b11, 0b10);
+        assert_eq!(SnowflakeId::from_uuid_v7(uuid, EPOCH), Some(id));
+        assert_eq!(SnowflakeId::from_uuid_v7(uuid, EPOCH + 1_001), None);
+
+        // A random UUIDv7 from elsewhere isn't mistaken for a snowflake.
+        assert_eq!(SnowflakeId::from_uuid_v7(uuid | 1, EPOCH), None);
+        let v4 = (uuid & !(0xF << 76)) | (0x4 << 76);
+        assert_eq!(SnowflakeId::from_uuid_v7(v4, EPOCH), None);
+    }
+
+    #[test]
+    fn uuid_v7_preserves_order() {
+        let ids = [
+            id(5, 0, 0),
+            id(5, 0, 1),
+            id(5, 0, 0xFFF),
+            id(5, 1, 0),
+            id(5, 0x3FF, 0xFFF),
+            id(6, 0, 0),
+            id((1 << 41) - 1, 0x3FF, 0xFFF),
+        ];
+        let uuids: Vec<u128> = ids.iter().map(|id| id.to_uuid_v7(EPOCH)).collect();
+        for pair in uuids.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].to_be_bytes() < pair[1].to_be_bytes());
+        }
+        for (id, uuid) in ids.iter().zip(uuids) {
+            assert_eq!(SnowflakeId::from_uuid_v7(uuid, EPOCH), Some(*id));
+        }
+    }
 }
@@ -5,6 +5,7 @@
 //!
 //! See ADR-0004 for rationale.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
 
@@ -42,6 +43,23 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::AddressInUse => ErrorKind::Conflict,
+            Self::AddressNotAvailable => ErrorKind::InvalidInput,
+            Self::ConnectionRefused
+            | Self::ConnectionReset
+            | Self::ConnectionAborted
+            | Self::NotConnected => ErrorKind::Unavailable,
+            Self::Timeout => ErrorKind::Timeout,
+            Self::Access => ErrorKind::PermissionDenied,
+            Self::Io(e) => ErrorKind::from_io(e.kind()),
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
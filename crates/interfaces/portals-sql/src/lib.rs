@@ -1,8 +1,13 @@
 //! SQL database interfaces.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::future::Future;
 
+mod query;
+
+pub use query::{Column, Condition, Delete, Dialect, Insert, Query, Select, Update, col};
+
 /// SQL value types.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
@@ -92,8 +97,8 @@ impl Row {
 /// Database errors.
 #[derive(Debug)]
 pub enum Error {
-    /// Connection failed.
-    ConnectionFailed,
+    /// The connection failed or was lost.
+    ConnectionFailed(String),
     /// Query syntax error.
     SyntaxError(String),
     /// Constraint violation.
@@ -109,7 +114,7 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::ConnectionFailed => write!(f, "connection failed"),
+            Error::ConnectionFailed(msg) => write!(f, "connection failed: {}", msg),
             Error::SyntaxError(msg) => write!(f, "syntax error: {}", msg),
             Error::ConstraintViolation(msg) => write!(f, "constraint violation: {}", msg),
             Error::TypeMismatch => write!(f, "type mismatch"),
@@ -121,6 +126,17 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ConnectionFailed(_) | Self::Busy => ErrorKind::Unavailable,
+            Self::SyntaxError(_) | Self::TypeMismatch => ErrorKind::InvalidInput,
+            Self::ConstraintViolation(_) => ErrorKind::Conflict,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// A database connection.
 ///
 /// This trait defines operations on an already-opened connection.
@@ -0,0 +1,819 @@
+//! Query builders.
+//!
+//! Build `SELECT`, `INSERT`, `UPDATE`, and `DELETE` statements with every
+//! value bound as a parameter and every identifier quoted, instead of
+//! splicing strings into SQL.
+//!
+//! ```ignore
+//! let query = Select::from("users")
+//!     .columns(&["id", "name"])
+//!     .filter(col("age").ge(18).and(col("role").in_list(["admin", "staff"])))
+//!     .order_by_desc("created_at")
+//!     .limit(20)
+//!     .build(Dialect::Postgres);
+//! // SELECT "id", "name" FROM "users" WHERE ("age" >= $1 AND "role" IN ($2, $3))
+//! //   ORDER BY "created_at" DESC LIMIT 20
+//! let rows = query.query(&conn).await?;
+//! ```
+
+use crate::{Connection, Error, Row, Value};
+
+/// SQL dialect, which decides placeholder syntax, identifier quoting, and
+/// upsert syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    /// `?` placeholders, `"identifiers"`, `ON CONFLICT`.
+    Sqlite,
+    /// `$1` placeholders, `"identifiers"`, `ON CONFLICT`.
+    Postgres,
+    /// `?` placeholders, `` `identifiers` ``, `ON DUPLICATE KEY UPDATE`.
+    MySql,
+}
+
+impl Dialect {
+    /// Quote a possibly qualified identifier, such as `schema.table`,
+    /// doubling any quote characters in it, for statements the builders
+    /// don't cover, like DDL. A `*` part is left bare.
+    pub fn quote_identifier(self, name: &str) -> String {
+        let quote = match self {
+            Dialect::MySql => '`',
+            Dialect::Sqlite | Dialect::Postgres => '"',
+        };
+        let mut quoted = String::new();
+        for (i, part) in name.split('.').enumerate() {
+            if i > 0 {
+                quoted.push('.');
+            }
+            if part == "*" {
+                quoted.push('*');
+                continue;
+            }
+            quoted.push(quote);
+            for c in part.chars() {
+                if c == quote {
+                    quoted.push(quote);
+                }
+                quoted.push(c);
+            }
+            quoted.push(quote);
+        }
+        quoted
+    }
+}
+
+/// A statement and its bound parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+impl Query {
+    /// Run the query on `conn`, returning rows.
+    pub async fn query<C: Connection>(&self, conn: &C) -> Result<Vec<Row>, Error> {
+        conn.query(&self.sql, &self.params).await
+    }
+
+    /// Run the statement on `conn`, returning the number of rows affected.
+    pub async fn execute<C: Connection>(&self, conn: &C) -> Result<u64, Error> {
+        conn.execute(&self.sql, &self.params).await
+    }
+}
+
+/// Start a condition on a column. The name may be qualified, as in
+/// `users.id`.
+pub fn col(name: &str) -> Column {
+    Column(name.to_string())
+}
+
+/// A column, to compare in a [`Condition`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column(String);
+
+impl Column {
+    fn compare(self, op: &'static str, value: impl Into<Value>) -> Condition {
+        Condition(Expr::Compare(self.0, op, value.into()))
+    }
+
+    /// `column = value`
+    pub fn eq(self, value: impl Into<Value>) -> Condition {
+        self.compare("=", value)
+    }
+
+    /// `column <> value`
+    pub fn ne(self, value: impl Into<Value>) -> Condition {
+        self.compare("<>", value)
+    }
+
+    /// `column < value`
+    pub fn lt(self, value: impl Into<Value>) -> Condition {
+        self.compare("<", value)
+    }
+
+    /// `column <= value`
+    pub fn le(self, value: impl Into<Value>) -> Condition {
+        self.compare("<=", value)
+    }
+
+    /// `column > value`
+    pub fn gt(self, value: impl Into<Value>) -> Condition {
+        self.compare(">", value)
+    }
+
+    /// `column >= value`
+    pub fn ge(self, value: impl Into<Value>) -> Condition {
+        self.compare(">=", value)
+    }
+
+    /// `column LIKE pattern`
+    pub fn like(self, pattern: impl Into<Value>) -> Condition {
+        self.compare("LIKE", pattern)
+    }
+
+    /// `column IS NULL`
+    pub fn is_null(self) -> Condition {
+        Condition(Expr::Null(self.0, true))
+    }
+
+    /// `column IS NOT NULL`
+    pub fn is_not_null(self) -> Condition {
+        Condition(Expr::Null(self.0, false))
+    }
+
+    /// `column IN (...)`, with one parameter per value. An empty list
+    /// matches nothing.
+    pub fn in_list<V: Into<Value>>(self, values: impl IntoIterator<Item = V>) -> Condition {
+        let values = values.into_iter().map(Into::into).collect();
+        Condition(Expr::In(self.0, values, true))
+    }
+
+    /// `column NOT IN (...)`, with one parameter per value. An empty list
+    /// matches everything.
+    pub fn not_in_list<V: Into<Value>>(self, values: impl IntoIterator<Item = V>) -> Condition {
+        let values = values.into_iter().map(Into::into).collect();
+        Condition(Expr::In(self.0, values, false))
+    }
+}
+
+/// A `WHERE` condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition(Expr);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Compare(String, &'static str, Value),
+    Null(String, bool),
+    In(String, Vec<Value>, bool),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// SQL split at its placeholders, with one parameter per placeholder.
+    Raw(Vec<String>, Vec<Value>),
+}
+
+impl Condition {
+    /// A condition written in SQL, for what the builders don't cover. Each
+    /// `?` in `sql` is replaced with a placeholder for the next of `params`.
+    /// A `?` inside a quoted string or identifier is left alone, and `??`
+    /// stands for a literal `?`, as in Postgres' JSON operators.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of placeholders in `sql` differs from
+    /// `params.len()`.
+    pub fn raw(sql: &str, params: Vec<Value>) -> Self {
+        let parts = split_placeholders(sql);
+        assert_eq!(
+            parts.len() - 1,
+            params.len(),
+            "raw condition {:?} has {} placeholders but {} parameters",
+            sql,
+            parts.len() - 1,
+            params.len()
+        );
+        Self(Expr::Raw(parts, params))
+    }
+
+    /// Both conditions.
+    pub fn and(self, other: Condition) -> Self {
+        Self(Expr::And(Box::new(self.0), Box::new(other.0)))
+    }
+
+    /// Either condition.
+    pub fn or(self, other: Condition) -> Self {
+        Self(Expr::Or(Box::new(self.0), Box::new(other.0)))
+    }
+
+    /// The negated condition.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Self(Expr::Not(Box::new(self.0)))
+    }
+}
+
+/// Split `sql` at its `?` placeholders, skipping quoted text and unescaping
+/// `??` to `?`.
+fn split_placeholders(sql: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+        match (c, quote) {
+            ('?', None) if chars.next_if_eq(&'?').is_some() => part.push('?'),
+            ('?', None) => parts.push(String::new()),
+            ('\'' | '"' | '`', None) => {
+                quote = Some(c);
+                part.push(c);
+            }
+            // A doubled quote closes and reopens, so it needs no special case.
+            (c, Some(q)) if c == q => {
+                quote = None;
+                part.push(c);
+            }
+            (c, _) => part.push(c),
+        }
+    }
+    parts
+}
+
+/// Combine an optional existing condition with a new one.
+fn and_filter(existing: Option<Condition>, condition: Condition) -> Option<Condition> {
+    Some(match existing {
+        Some(existing) => existing.and(condition),
+        None => condition,
+    })
+}
+
+/// A `SELECT` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    table: String,
+    columns: Vec<String>,
+    filter: Option<Condition>,
+    order_by: Vec<(String, bool)>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+impl Select {
+    /// Select all columns from `table`.
+    pub fn from(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            columns: Vec::new(),
+            filter: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    /// Select only `columns`.
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Add a `WHERE` condition. Repeated calls are combined with `AND`.
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = and_filter(self.filter.take(), condition);
+        self
+    }
+
+    /// Order by `column`, ascending.
+    pub fn order_by(mut self, column: &str) -> Self {
+        self.order_by.push((column.to_string(), false));
+        self
+    }
+
+    /// Order by `column`, descending.
+    pub fn order_by_desc(mut self, column: &str) -> Self {
+        self.order_by.push((column.to_string(), true));
+        self
+    }
+
+    /// Return at most `limit` rows.
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip the first `offset` rows.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Build the statement.
+    pub fn build(&self, dialect: Dialect) -> Query {
+        let mut w = Writer::new(dialect);
+        w.push("SELECT ");
+        if self.columns.is_empty() {
+            w.push("*");
+        } else {
+            w.identifiers(&self.columns);
+        }
+        w.push(" FROM ");
+        w.identifier(&self.table);
+        w.filter(self.filter.as_ref());
+        for (i, (column, desc)) in self.order_by.iter().enumerate() {
+            w.push(if i == 0 { " ORDER BY " } else { ", " });
+            w.identifier(column);
+            if *desc {
+                w.push(" DESC");
+            }
+        }
+        match (self.limit, self.offset) {
+            (Some(limit), _) => w.push(&format!(" LIMIT {}", limit)),
+            // MySQL and SQLite need a LIMIT before OFFSET.
+            (None, Some(_)) if dialect != Dialect::Postgres => {
+                w.push(&format!(" LIMIT {}", i64::MAX))
+            }
+            (None, _) => {}
+        }
+        if let Some(offset) = self.offset {
+            w.push(&format!(" OFFSET {}", offset));
+        }
+        w.finish()
+    }
+}
+
+/// What an [`Insert`] does when a row conflicts with an existing one.
+#[derive(Debug, Clone, PartialEq)]
+enum OnConflict {
+    Fail,
+    Ignore(Vec<String>),
+    Update {
+        target: Vec<String>,
+        columns: Vec<String>,
+    },
+}
+
+/// An `INSERT` statement for a single row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    table: String,
+    values: Vec<(String, Value)>,
+    on_conflict: OnConflict,
+}
+
+impl Insert {
+    /// Insert a row into `table`.
+    pub fn into(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            values: Vec::new(),
+            on_conflict: OnConflict::Fail,
+        }
+    }
+
+    /// Set `column` to `value`.
+    pub fn value(mut self, column: &str, value: impl Into<Value>) -> Self {
+        self.values.push((column.to_string(), value.into()));
+        self
+    }
+
+    /// Skip the row if it conflicts with an existing one on the unique
+    /// `target` columns.
+    ///
+    /// MySQL has no conflict target and uses `INSERT IGNORE`, which also
+    /// ignores other errors such as invalid values.
+    pub fn on_conflict_do_nothing(mut self, target: &[&str]) -> Self {
+        self.on_conflict = OnConflict::Ignore(strings(target));
+        self
+    }
+
+    /// Upsert: if the row conflicts with an existing one on the unique
+    /// `target` columns, update the existing row's `columns` to the new
+    /// values instead.
+    ///
+    /// MySQL ignores `target` and applies to any unique key.
+    pub fn on_conflict_update(mut self, target: &[&str], columns: &[&str]) -> Self {
+        self.on_conflict = OnConflict::Update {
+            target: strings(target),
+            columns: strings(columns),
+        };
+        self
+    }
+
+    /// Build the statement.
+    pub fn build(&self, dialect: Dialect) -> Query {
+        let mut w = Writer::new(dialect);
+        let ignore = matches!(self.on_conflict, OnConflict::Ignore(_));
+        w.push(if ignore && dialect == Dialect::MySql {
+            "INSERT IGNORE INTO "
+        } else {
+            "INSERT INTO "
+        });
+        w.identifier(&self.table);
+
+        if self.values.is_empty() {
+            w.push(if dialect == Dialect::MySql {
+                " () VALUES ()"
+            } else {
+                " DEFAULT VALUES"
+            });
+        } else {
+            w.push(" (");
+            let columns: Vec<_> = self.values.iter().map(|(c, _)| c.clone()).collect();
+            w.identifiers(&columns);
+            w.push(") VALUES (");
+            for (i, (_, value)) in self.values.iter().enumerate() {
+                if i > 0 {
+                    w.push(", ");
+                }
+                w.param(value.clone());
+            }
+            w.push(")");
+        }
+
+        match (&self.on_conflict, dialect) {
+            (OnConflict::Fail, _) | (OnConflict::Ignore(_), Dialect::MySql) => {}
+            (OnConflict::Ignore(target), _) => {
+                w.conflict_target(target);
+                w.push(" DO NOTHING");
+            }
+            (OnConflict::Update { columns, .. }, Dialect::MySql) => {
+                w.push(" ON DUPLICATE KEY UPDATE ");
+                for (i, column) in columns.iter().enumerate() {
+                    if i > 0 {
+                        w.push(", ");
+                    }
+                    w.identifier(column);
+                    w.push(" = VALUES(");
+                    w.identifier(column);
+                    w.push(")");
+                }
+            }
+            (OnConflict::Update { target, columns }, _) => {
+                w.conflict_target(target);
+                w.push(" DO UPDATE SET ");
+                for (i, column) in columns.iter().enumerate() {
+                    if i > 0 {
+                        w.push(", ");
+                    }
+                    w.identifier(column);
+                    w.push(" = excluded.");
+                    w.identifier(column);
+                }
+            }
+        }
+        w.finish()
+    }
+}
+
+/// An `UPDATE` statement.
+///
+/// Without a [`filter`](Self::filter), every row is updated. An update
+/// with no [`set`](Self::set) calls is not valid SQL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    table: String,
+    values: Vec<(String, Value)>,
+    filter: Option<Condition>,
+}
+
+impl Update {
+    /// Update rows of `table`.
+    pub fn table(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            values: Vec::new(),
+            filter: None,
+        }
+    }
+
+    /// Set `column` to `value`.
+    pub fn set(mut self, column: &str, value: impl Into<Value>) -> Self {
+        self.values.push((column.to_string(), value.into()));
+        self
+    }
+
+    /// Add a `WHERE` condition. Repeated calls are combined with `AND`.
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = and_filter(self.filter.take(), condition);
+        self
+    }
+
+    /// Build the statement.
+    pub fn build(&self, dialect: Dialect) -> Query {
+        let mut w = Writer::new(dialect);
+        w.push("UPDATE ");
+        w.identifier(&self.table);
+        w.push(" SET ");
+        for (i, (column, value)) in self.values.iter().enumerate() {
+            if i > 0 {
+                w.push(", ");
+            }
+            w.identifier(column);
+            w.push(" = ");
+            w.param(value.clone());
+        }
+        w.filter(self.filter.as_ref());
+        w.finish()
+    }
+}
+
+/// A `DELETE` statement.
+///
+/// Without a [`filter`](Self::filter), every row is deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    table: String,
+    filter: Option<Condition>,
+}
+
+impl Delete {
+    /// Delete rows of `table`.
+    pub fn from(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            filter: None,
+        }
+    }
+
+    /// Add a `WHERE` condition. Repeated calls are combined with `AND`.
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = and_filter(self.filter.take(), condition);
+        self
+    }
+
+    /// Build the statement.
+    pub fn build(&self, dialect: Dialect) -> Query {
+        let mut w = Writer::new(dialect);
+        w.push("DELETE FROM ");
+        w.identifier(&self.table);
+        w.filter(self.filter.as_ref());
+        w.finish()
+    }
+}
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+/// Accumulates SQL text and parameters.
+struct Writer {
+    dialect: Dialect,
+    sql: String,
+    params: Vec<Value>,
+}
+
+impl Writer {
+    fn new(dialect: Dialect) -> Self {
+        Self {
+            dialect,
+            sql: String::new(),
+            params: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> Query {
+        Query {
+            sql: self.sql,
+            params: self.params,
+        }
+    }
+
+    fn push(&mut self, sql: &str) {
+        self.sql.push_str(sql);
+    }
+
+    fn param(&mut self, value: Value) {
+        self.params.push(value);
+        match self.dialect {
+            Dialect::Postgres => self.sql.push_str(&format!("${}", self.params.len())),
+            Dialect::Sqlite | Dialect::MySql => self.sql.push('?'),
+        }
+    }
+
+    fn identifier(&mut self, name: &str) {
+        self.sql.push_str(&self.dialect.quote_identifier(name));
+    }
+
+    fn identifiers(&mut self, names: &[String]) {
+        for (i, name) in names.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            self.identifier(name);
+        }
+    }
+
+    fn conflict_target(&mut self, target: &[String]) {
+        self.push(" ON CONFLICT");
+        if !target.is_empty() {
+            self.push(" (");
+            self.identifiers(target);
+            self.push(")");
+        }
+    }
+
+    fn filter(&mut self, condition: Option<&Condition>) {
+        if let Some(condition) = condition {
+            self.push(" WHERE ");
+            self.expr(&condition.0);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Compare(column, op, value) => {
+                self.identifier(column);
+                self.push(&format!(" {} ", op));
+                self.param(value.clone());
+            }
+            Expr::Null(column, is_null) => {
+                self.identifier(column);
+                self.push(if *is_null { " IS NULL" } else { " IS NOT NULL" });
+            }
+            Expr::In(_, values, true) if values.is_empty() => self.push("1 = 0"),
+            Expr::In(_, values, false) if values.is_empty() => self.push("1 = 1"),
+            Expr::In(column, values, is_in) => {
+                self.identifier(column);
+                self.push(if *is_in { " IN (" } else { " NOT IN (" });
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    self.param(value.clone());
+                }
+                self.push(")");
+            }
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                self.push("(");
+                self.expr(a);
+                self.push(if matches!(expr, Expr::And(..)) {
+                    " AND "
+                } else {
+                    " OR "
+                });
+                self.expr(b);
+                self.push(")");
+            }
+            Expr::Not(inner) => {
+                self.push("NOT (");
+                self.expr(inner);
+                self.push(")");
+            }
+            Expr::Raw(parts, params) => {
+                self.push("(");
+                self.push(&parts[0]);
+                for (part, value) in parts[1..].iter().zip(params) {
+                    self.param(value.clone());
+                    self.push(part);
+                }
+                self.push(")");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_with_conditions() {
+        let select = Select::from("users")
+            .columns(&["id", "name"])
+            .filter(col("age").ge(18))
+            .filter(
+                col("role")
+                    .in_list(["admin", "staff"])
+                    .or(col("banned").is_null()),
+            )
+            .order_by_desc("created_at")
+            .order_by("id")
+            .limit(20)
+            .offset(40);
+
+        let query = select.build(Dialect::Postgres);
+        assert_eq!(
+            query.sql,
+            "SELECT \"id\", \"name\" FROM \"users\" WHERE (\"age\" >= $1 AND (\"role\" IN ($2, $3) OR \"banned\" IS NULL)) ORDER BY \"created_at\" DESC, \"id\" LIMIT 20 OFFSET 40"
+        );
+        assert_eq!(
+            query.params,
+            [
+                Value::Integer(18),
+                Value::Text("admin".into()),
+                Value::Text("staff".into())
+            ]
+        );
+
+        assert_eq!(
+            Select::from("t").offset(5).build(Dialect::Sqlite).sql,
+            format!("SELECT * FROM \"t\" LIMIT {} OFFSET 5", i64::MAX)
+        );
+    }
+
+    #[test]
+    fn quotes_identifiers() {
+        let query = Select::from("odd\"table")
+            .columns(&["t.*", "t.`x`"])
+            .build(Dialect::MySql);
+        assert_eq!(query.sql, "SELECT `t`.*, `t`.```x``` FROM `odd\"table`");
+        let query = Select::from("odd\"table").build(Dialect::Sqlite);
+        assert_eq!(query.sql, "SELECT * FROM \"odd\"\"table\"");
+        assert_eq!(
+            Dialect::Postgres.quote_identifier("audit.log\"; --"),
+            "\"audit\".\"log\"\"; --\""
+        );
+    }
+
+    #[test]
+    fn values_are_never_inlined() {
+        let evil = "'; DROP TABLE users; --";
+        let query = Update::table("users")
+            .set("name", evil)
+            .filter(col("id").eq(1))
+            .build(Dialect::Sqlite);
+        assert_eq!(
+            query.sql,
+            "UPDATE \"users\" SET \"name\" = ? WHERE \"id\" = ?"
+        );
+        assert_eq!(query.params, [Value::from(evil), Value::Integer(1)]);
+    }
+
+    #[test]
+    fn empty_in_lists() {
+        let query = Delete::from("t")
+            .filter(col("id").in_list(Vec::<i64>::new()))
+            .build(Dialect::Sqlite);
+        assert_eq!(query.sql, "DELETE FROM \"t\" WHERE 1 = 0");
+        let query = Delete::from("t")
+            .filter(col("id").not_in_list(Vec::<i64>::new()).not())
+            .build(Dialect::Sqlite);
+        assert_eq!(query.sql, "DELETE FROM \"t\" WHERE NOT (1 = 1)");
+    }
+
+    #[test]
+    fn raw_conditions_renumber_placeholders() {
+        let query = Select::from("t")
+            .filter(col("a").eq(1))
+            .filter(Condition::raw(
+                "lower(b) = lower(?) OR c > ?",
+                vec!["X".into(), 2i64.into()],
+            ))
+            .build(Dialect::Postgres);
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM \"t\" WHERE (\"a\" = $1 AND (lower(b) = lower($2) OR c > $3))"
+        );
+        assert_eq!(query.params.len(), 3);
+    }
+
+    #[test]
+    fn raw_conditions_skip_quoted_and_escaped_question_marks() {
+        let query = Select::from("t")
+            .filter(Condition::raw(
+                "b <> 'why?' AND \"odd?\" = ? AND doc ?? 'k' AND 'it''s?' <> ?",
+                vec![1i64.into(), 2i64.into()],
+            ))
+            .build(Dialect::Postgres);
+        assert_eq!(
+            query.sql,
+            "SELECT * FROM \"t\" WHERE (b <> 'why?' AND \"odd?\" = $1 AND doc ? 'k' AND 'it''s?' <> $2)"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "2 placeholders but 1 parameters")]
+    fn raw_conditions_check_parameter_count() {
+        Condition::raw("a = ? AND b = ?", vec![1i64.into()]);
+    }
+
+    #[test]
+    fn upserts_per_dialect() {
+        let insert = Insert::into("users")
+            .value("id", 1)
+            .value("name", "Ada")
+            .on_conflict_update(&["id"], &["name"]);
+        assert_eq!(
+            insert.build(Dialect::Postgres).sql,
+            "INSERT INTO \"users\" (\"id\", \"name\") VALUES ($1, $2) ON CONFLICT (\"id\") DO UPDATE SET \"name\" = excluded.\"name\""
+        );
+        assert_eq!(
+            insert.build(Dialect::MySql).sql,
+            "INSERT INTO `users` (`id`, `name`) VALUES (?, ?) ON DUPLICATE KEY UPDATE `name` = VALUES(`name`)"
+        );
+
+        let insert = Insert::into("tags")
+            .value("name", "x")
+            .on_conflict_do_nothing(&["name"]);
+        assert_eq!(
+            insert.build(Dialect::Sqlite).sql,
+            "INSERT INTO \"tags\" (\"name\") VALUES (?) ON CONFLICT (\"name\") DO NOTHING"
+        );
+        assert_eq!(
+            insert.build(Dialect::MySql).sql,
+            "INSERT IGNORE INTO `tags` (`name`) VALUES (?)"
+        );
+        assert_eq!(
+            Insert::into("t").build(Dialect::Postgres).sql,
+            "INSERT INTO \"t\" DEFAULT VALUES"
+        );
+    }
+}
@@ -2,9 +2,11 @@
 //!
 //! Wraps the `jiff` crate for timezone operations.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 
-pub use jiff::tz::{TimeZone, TimeZoneDatabase};
+pub use jiff::civil::DateTime;
+pub use jiff::tz::{AmbiguousOffset, Offset, TimeZone, TimeZoneDatabase};
 pub use jiff::{Timestamp, Zoned};
 
 /// Timezone errors.
@@ -27,6 +29,15 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidTimezone(_) | Self::Conversion(_) => ErrorKind::InvalidInput,
+            Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// Get the system's local timezone.
 pub fn local() -> TimeZone {
     TimeZone::system()
@@ -5,6 +5,7 @@
 //!
 //! See ADR-0004 for rationale.
 
+pub use portals_error::{ErrorKind, PithError};
 use std::fmt;
 use std::future::Future;
 
@@ -32,6 +33,15 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ConnectionFailed(_) => ErrorKind::Unavailable,
+            Self::SendFailed | Self::Closed | Self::Protocol(_) | Self::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// A WebSocket message.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Message {
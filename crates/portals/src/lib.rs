@@ -24,6 +24,7 @@
 //! | [`portals-filesystem`](https://docs.rs/portals-filesystem) | File I/O |
 //! | [`portals-io`](https://docs.rs/portals-io) | Streams and polling |
 //! | [`portals-random`](https://docs.rs/portals-random) | Randomness |
+//! | [`portals-signals`](https://docs.rs/portals-signals) | Termination signals and graceful shutdown |
 //! | [`portals-sockets`](https://docs.rs/portals-sockets) | Raw networking |
 //!
 //! ### Contested Domains (Medium Value)
@@ -36,6 +37,9 @@
 //! | [`portals-sql`](https://docs.rs/portals-sql) | SQL databases | rusqlite, sqlx, diesel |
 //! | [`portals-cache`](https://docs.rs/portals-cache) | Caching with TTL | moka, cached, etc. |
 //! | [`portals-crypto`](https://docs.rs/portals-crypto) | Cryptography | ring, rustcrypto |
+//! | [`portals-csv`](https://docs.rs/portals-csv) | CSV | csv |
+//! | [`portals-discovery`](https://docs.rs/portals-discovery) | Service discovery | mdns-sd, zeroconf |
+//! | [`portals-archive`](https://docs.rs/portals-archive) | Tar and zip archives | tar, zip |
 //! | [`portals-logging`](https://docs.rs/portals-logging) | Logging | log, tracing |
 //! | [`portals-markdown`](https://docs.rs/portals-markdown) | Markdown | pulldown-cmark, comrak |
 //! | [`portals-config`](https://docs.rs/portals-config) | Configuration | figment, config |
@@ -69,6 +73,8 @@
 //! - **Abstract stylistic choices** - error handling, parser combinators
 //! - **Replace the ecosystem** - we complement it, not compete
 //! - **Be a framework** - portals is à la carte
+//! - **Wrap application protocols** - use tonic for gRPC; `portals-rpc` is
+//!   only length-prefixed framing for services that both use it
 //!
 //! ## Usage
 //!
@@ -0,0 +1,55 @@
+//! HTTP/1.1 parse and serialize benchmarks.
+//!
+//! Run with `cargo bench -p portals-http1`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use portals_http1::{Response, parse_request, parse_response, write_response};
+use std::hint::black_box;
+use std::io::Cursor;
+
+const REQUEST: &[u8] = b"POST /api/v1/items?limit=10 HTTP/1.1\r\n\
+Host: example.com\r\n\
+User-Agent: bench/1.0\r\n\
+Accept: application/json\r\n\
+Accept-Encoding: gzip, deflate\r\n\
+Content-Type: application/json\r\n\
+X-Request-Id: 0123456789\r\n\
+Content-Length: 13\r\n\
+\r\n\
+{\"id\": 12345}";
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("parse_request", |b| {
+        b.iter(|| {
+            let mut cursor = Cursor::new(black_box(REQUEST));
+            parse_request(&mut cursor).unwrap()
+        })
+    });
+}
+
+fn serialize(c: &mut Criterion) {
+    let response = Response::new(200)
+        .header("content-type", "application/json")
+        .header("cache-control", "no-cache")
+        .body(vec![b'x'; 1024]);
+    let mut encoded = Vec::new();
+    write_response(&mut encoded, &response).unwrap();
+
+    c.bench_function("write_response (1 KiB)", |b| {
+        b.iter(|| {
+            let mut buf = Vec::with_capacity(2048);
+            write_response(&mut buf, black_box(&response)).unwrap();
+            buf
+        })
+    });
+
+    c.bench_function("parse_response (1 KiB)", |b| {
+        b.iter(|| {
+            let mut cursor = Cursor::new(black_box(encoded.as_slice()));
+            parse_response(&mut cursor).unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, parse, serialize);
+criterion_main!(benches);
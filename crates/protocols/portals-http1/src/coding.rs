@@ -0,0 +1,410 @@
+//! Content codings.
+//!
+//! Compresses response bodies according to the request's
+//! `accept-encoding`, and decompresses bodies sent with a
+//! `content-encoding`. Codings themselves plug in through [`Codec`]; this
+//! crate has none built in, so backends supply them (`portals-http-native`
+//! has `gzip` and `deflate`).
+//!
+//! ```ignore
+//! let router = Router::new()
+//!     .get("/report", report)
+//!     .middleware(Compression::new().codec(Gzip).min_size(1024));
+//! ```
+
+use crate::{Error, Headers, Limits, Request, Response, status_allows_body};
+use portals_http::{HttpClient, Middleware};
+
+/// A content coding, such as `gzip`.
+pub trait Codec {
+    /// The coding's name as it appears in `content-encoding`.
+    fn name(&self) -> &str;
+
+    /// Encode a body.
+    fn encode(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Decode a body into at most `limit` bytes.
+    ///
+    /// Fails with [`Error::BodyTooLarge`] as soon as the output would
+    /// exceed `limit`, so a small compressed body can't expand without
+    /// bound, and with [`Error::InvalidEncoding`] if it is not valid for
+    /// this coding.
+    fn decode(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, Error>;
+}
+
+/// Compresses and decompresses message bodies.
+///
+/// As [`Middleware`], it decodes request bodies before the handler sees
+/// them, answering `415 Unsupported Media Type` for unknown codings,
+/// `413 Content Too Large` for bodies decoding past
+/// [`max_decoded_size`](Self::max_decoded_size), and `400 Bad Request` for
+/// corrupt bodies, and encodes responses with the best coding the client
+/// accepts. Clients can use
+/// [`accept_encoding`](Self::accept_encoding) and
+/// [`decode_response`](Self::decode_response) directly.
+pub struct Compression {
+    codecs: Vec<Box<dyn Codec + Send + Sync>>,
+    min_size: usize,
+    max_decoded_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Support no codings yet, compress bodies of 256 bytes or more, and
+    /// decode bodies up to the default [`Limits::max_body`].
+    pub fn new() -> Self {
+        Self {
+            codecs: Vec::new(),
+            min_size: 256,
+            max_decoded_size: Limits::default().max_body,
+        }
+    }
+
+    /// Add a codec, replacing any with the same name.
+    ///
+    /// When a client accepts several codings equally, the earliest added
+    /// is used; a replacement keeps the place of the codec it replaces.
+    pub fn codec(mut self, codec: impl Codec + Send + Sync + 'static) -> Self {
+        let codec: Box<dyn Codec + Send + Sync> = Box::new(codec);
+        match self.find(codec.name()) {
+            Some(i) => self.codecs[i] = codec,
+            None => self.codecs.push(codec),
+        }
+        self
+    }
+
+    /// Set the smallest body worth compressing. Smaller bodies are sent
+    /// as they are, since compression would barely shrink them, if at all.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Set the largest body decoding may produce. Larger bodies fail with
+    /// [`Error::BodyTooLarge`].
+    pub fn max_decoded_size(mut self, max_decoded_size: usize) -> Self {
+        self.max_decoded_size = max_decoded_size;
+        self
+    }
+
+    /// An `accept-encoding` value listing the supported codings.
+    pub fn accept_encoding(&self) -> String {
+        let names: Vec<&str> = self.codecs.iter().map(|c| c.name()).collect();
+        names.join(", ")
+    }
+
+    /// Decode a request body according to its `content-encoding`.
+    ///
+    /// Afterwards the body is plain and the `content-encoding` and
+    /// `content-length` headers are gone. Fails with
+    /// [`Error::UnsupportedEncoding`] for a coding with no codec, and with
+    /// [`Error::BodyTooLarge`] if the body decodes past
+    /// [`max_decoded_size`](Self::max_decoded_size).
+    pub fn decode_request(&self, request: &mut Request) -> Result<(), Error> {
+        self.decode(&mut request.headers, &mut request.body)
+    }
+
+    /// Decode a response body according to its `content-encoding`, as
+    /// [`decode_request`](Self::decode_request) does.
+    pub fn decode_response(&self, response: &mut Response) -> Result<(), Error> {
+        self.decode(&mut response.headers, &mut response.body)
+    }
+
+    /// Encode a response body with the coding `request` prefers.
+    ///
+    /// Responses that are already encoded, smaller than
+    /// [`min_size`](Self::min_size), or of a status without a body are
+    /// returned unchanged. Others gain `vary: accept-encoding`, since
+    /// another client may get them encoded differently, and are encoded
+    /// unless the client accepts none of the codings.
+    pub fn encode_response(&self, request: &Request, mut response: Response) -> Response {
+        self.encode(
+            &request.headers,
+            response.status,
+            &mut response.headers,
+            &mut response.body,
+        );
+        response
+    }
+
+    fn encode(&self, accept: &Headers, status: u16, headers: &mut Headers, body: &mut Vec<u8>) {
+        if body.len() < self.min_size
+            || !status_allows_body(status)
+            || headers.contains_key("content-encoding")
+        {
+            return;
+        }
+        if let Some(codec) = self.negotiate(accept) {
+            *body = codec.encode(body);
+            headers.remove("content-length");
+            headers.insert("content-encoding", codec.name().to_string());
+        }
+        let varies = headers.get_all("vary").any(|value| {
+            value
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding") || v.trim() == "*")
+        });
+        if !varies {
+            headers.append("vary", "accept-encoding");
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.codecs
+            .iter()
+            .position(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    fn decode(&self, headers: &mut Headers, body: &mut Vec<u8>) -> Result<(), Error> {
+        let codings: Vec<String> = headers
+            .get_all("content-encoding")
+            .flat_map(|value| value.split(','))
+            .map(|coding| coding.trim().to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty() && coding != "identity")
+            .collect();
+        // Codings are listed in the order they were applied.
+        for coding in codings.iter().rev() {
+            let name = if coding == "x-gzip" { "gzip" } else { coding };
+            let i = self
+                .find(name)
+                .ok_or_else(|| Error::UnsupportedEncoding(coding.clone()))?;
+            *body = self.codecs[i].decode(body, self.max_decoded_size)?;
+        }
+        headers.remove("content-encoding");
+        if !codings.is_empty() {
+            headers.remove("content-length");
+        }
+        Ok(())
+    }
+
+    /// The codec with the highest `q` in `accept-encoding`, if any.
+    fn negotiate(&self, headers: &Headers) -> Option<&(dyn Codec + Send + Sync)> {
+        let mut weights: Vec<Option<f32>> = vec![None; self.codecs.len()];
+        let mut wildcard = None;
+        for item in headers
+            .get_all("accept-encoding")
+            .flat_map(|value| value.split(','))
+        {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or("").trim();
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if name == "*" {
+                wildcard = Some(q);
+            } else if let Some(i) = self.find(name) {
+                weights[i] = Some(q);
+            }
+        }
+        let mut best: Option<(usize, f32)> = None;
+        for (i, weight) in weights.into_iter().enumerate() {
+            let q = weight.or(wildcard).unwrap_or(0.0);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((i, q));
+            }
+        }
+        best.map(|(i, _)| &*self.codecs[i])
+    }
+}
+
+impl Middleware for Compression {
+    async fn send<C: HttpClient>(
+        &self,
+        mut request: portals_http::Request,
+        next: &C,
+    ) -> Result<portals_http::Response, portals_http::Error> {
+        let accept = request.headers.clone();
+        let mut response = if request.headers.contains_key("content-encoding") {
+            let mut body = request.body.take().unwrap_or_default();
+            match self.decode(&mut request.headers, &mut body) {
+                Ok(()) => {
+                    request.body = Some(body);
+                    next.send(request).await?
+                }
+                Err(Error::UnsupportedEncoding(_)) => Response::new(415)
+                    .header("accept-encoding", self.accept_encoding())
+                    .into(),
+                Err(Error::BodyTooLarge) => Response::new(413).into(),
+                Err(_) => Response::new(400).into(),
+            }
+        } else {
+            next.send(request).await?
+        };
+        self.encode(
+            &accept,
+            response.status,
+            &mut response.headers,
+            &mut response.body,
+        );
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Method, Params, Router};
+    use portals_http::HttpHandler;
+
+    /// Run-length coding under a given name: each run is a count byte
+    /// followed by the repeated byte.
+    struct Rle(&'static str);
+
+    impl Codec for Rle {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn encode(&self, data: &[u8]) -> Vec<u8> {
+            let mut out = Vec::new();
+            for run in data.chunk_by(|a, b| a == b) {
+                for piece in run.chunks(255) {
+                    out.extend([piece.len() as u8, piece[0]]);
+                }
+            }
+            out
+        }
+
+        fn decode(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+            if !data.len().is_multiple_of(2) {
+                return Err(Error::InvalidEncoding);
+            }
+            let mut out = Vec::new();
+            for pair in data.chunks(2) {
+                if out.len() + pair[0] as usize > limit {
+                    return Err(Error::BodyTooLarge);
+                }
+                out.extend(std::iter::repeat_n(pair[1], pair[0] as usize));
+            }
+            Ok(out)
+        }
+    }
+
+    fn compression() -> Compression {
+        Compression::new().codec(Rle("gzip")).codec(Rle("deflate"))
+    }
+
+    fn request(headers: Headers, body: Vec<u8>) -> Request {
+        Request {
+            method: Method::Post,
+            path: "/".to_string(),
+            headers,
+            body,
+        }
+    }
+
+    fn post(headers: Headers, body: Vec<u8>) -> portals_http::Request {
+        portals_http::Request {
+            method: portals_http::Method::Post,
+            url: "/".to_string(),
+            headers,
+            body: Some(body),
+        }
+    }
+
+    fn router() -> Router<impl Middleware> {
+        Router::new()
+            .post("/", |req: &Request, _: &Params| {
+                Response::new(200).body(req.body.repeat(100))
+            })
+            .middleware(compression().max_decoded_size(1000))
+    }
+
+    #[test]
+    fn negotiates_coding() {
+        let compression = compression();
+        let body = b"z".repeat(1000);
+        let encode = |accept: &str| {
+            let req = request(Headers::from([("Accept-Encoding", accept)]), Vec::new());
+            let res = compression.encode_response(&req, Response::new(200).body(body.clone()));
+            res.headers.get("content-encoding").map(str::to_string)
+        };
+
+        assert_eq!(encode("gzip, deflate").as_deref(), Some("gzip"));
+        assert_eq!(
+            encode("deflate;q=1, gzip;q=0.5").as_deref(),
+            Some("deflate")
+        );
+        assert_eq!(encode("br, *;q=0.1, gzip;q=0").as_deref(), Some("deflate"));
+        assert_eq!(encode("identity").as_deref(), None);
+        assert_eq!(encode("br").as_deref(), None);
+
+        // Small bodies are left alone.
+        let req = request(Headers::from([("Accept-Encoding", "gzip")]), Vec::new());
+        let res = compression.encode_response(&req, Response::new(200).body("tiny"));
+        assert_eq!(res.body, b"tiny");
+        assert!(!res.headers.contains_key("content-encoding"));
+    }
+
+    #[tokio::test]
+    async fn middleware_decodes_and_encodes() {
+        let router = router();
+        let headers = Headers::from([
+            ("Content-Encoding", "gzip"),
+            ("Content-Length", "999"),
+            ("Accept-Encoding", "gzip"),
+        ]);
+        let res = router
+            .handle(post(headers, Rle("gzip").encode(b"aaa")))
+            .await;
+        let mut res = Response::from(res);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.headers.get("content-encoding"), Some("gzip"));
+        assert_eq!(res.headers.get("vary"), Some("accept-encoding"));
+        assert!(res.body.len() < 10);
+
+        compression().decode_response(&mut res).unwrap();
+        assert_eq!(res.body, b"a".repeat(300));
+        assert!(!res.headers.contains_key("content-encoding"));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_bodies() {
+        let router = router();
+        let headers = Headers::from([("Content-Encoding", "br")]);
+        let res = router.handle(post(headers, b"whatever".to_vec())).await;
+        assert_eq!(res.status, 415);
+        assert_eq!(res.headers.get("accept-encoding"), Some("gzip, deflate"));
+
+        let headers = Headers::from([("Content-Encoding", "gzip")]);
+        let res = router.handle(post(headers, b"odd".to_vec())).await;
+        assert_eq!(res.status, 400);
+
+        // 1001 bytes decoded from 8 sent.
+        let headers = Headers::from([("Content-Encoding", "gzip")]);
+        let res = router
+            .handle(post(headers, Rle("gzip").encode(&[0; 1001])))
+            .await;
+        assert_eq!(res.status, 413);
+    }
+
+    #[test]
+    fn stacked_codings_and_custom_codecs() {
+        struct Reverse;
+        impl Codec for Reverse {
+            fn name(&self) -> &str {
+                "reverse"
+            }
+            fn encode(&self, data: &[u8]) -> Vec<u8> {
+                data.iter().rev().copied().collect()
+            }
+            fn decode(&self, data: &[u8], _limit: usize) -> Result<Vec<u8>, Error> {
+                Ok(self.encode(data))
+            }
+        }
+
+        let compression = compression().codec(Reverse);
+        assert_eq!(compression.accept_encoding(), "gzip, deflate, reverse");
+        let mut res = Response::new(200)
+            .header("Content-Encoding", "reverse, x-gzip")
+            .body(Rle("gzip").encode(b"olleh"));
+        compression.decode_response(&mut res).unwrap();
+        assert_eq!(res.body, b"hello");
+    }
+}
@@ -0,0 +1,830 @@
+//! HTTP/1.1 over an async [`TcpStream`].
+//!
+//! Drives [`Http1Parser`] from socket reads, so requests and responses can
+//! be exchanged over any `portals-sockets` backend without a blocking
+//! reader.
+
+use crate::{
+    CONTINUE, Error, Event, Handler, Http1Parser, Limits, Method, Request, RequestHead, Response,
+    ResponseHead, Version, content_length, expects_continue, keep_alive, unexpected_eof,
+    write_request, write_response_for,
+};
+use portals_http::HttpHandler;
+use portals_sockets::TcpStream;
+use std::future::poll_fn;
+use std::pin::pin;
+use std::task::Poll;
+
+/// Size of each socket read.
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// An HTTP/1.1 connection over a TCP stream.
+///
+/// A server connection reads requests and writes responses; a client
+/// connection writes requests and reads responses. Input read past the end
+/// of one message is kept for the next, so several messages can be
+/// exchanged on one connection.
+///
+/// The connection tracks the HTTP version and `connection` header of each
+/// message it reads, and whether the peer expects it to stay open
+/// afterwards (see [`keep_alive`](Self::keep_alive)). Servers can leave
+/// the loop to [`serve`](Self::serve).
+///
+/// ```ignore
+/// let (stream, _) = listener.accept().await?;
+/// let mut conn = Http1Connection::server(stream);
+/// while let Some(request) = conn.read_request().await? {
+///     let response = handler.handle(&request);
+///     conn.write_response_for(&response, request.method).await?;
+/// }
+/// ```
+pub struct Http1Connection<S> {
+    stream: S,
+    parser: Http1Parser,
+    read_buf: Box<[u8]>,
+    write_buf: Vec<u8>,
+    version: Option<Version>,
+    keep_alive: bool,
+    /// A request head has been read but not its body.
+    body_pending: bool,
+    /// The pending body is held back until `100 Continue` is sent.
+    continue_pending: bool,
+}
+
+impl<S: TcpStream> Http1Connection<S> {
+    /// Wrap the server side of a connection.
+    pub fn server(stream: S) -> Self {
+        Self::new(stream, Http1Parser::request())
+    }
+
+    /// Wrap the client side of a connection.
+    pub fn client(stream: S) -> Self {
+        Self::new(stream, Http1Parser::response())
+    }
+
+    fn new(stream: S, parser: Http1Parser) -> Self {
+        Self {
+            stream,
+            parser,
+            read_buf: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+            write_buf: Vec::new(),
+            version: None,
+            keep_alive: true,
+            body_pending: false,
+            continue_pending: false,
+        }
+    }
+
+    /// Hold incoming messages to `limits` instead of the defaults.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.parser.set_limits(limits);
+        self
+    }
+
+    /// Read the next request.
+    ///
+    /// Returns `None` if the peer closed the connection between requests,
+    /// or if the last exchange asked for the connection to be closed, and
+    /// an `UnexpectedEof` I/O error if the peer closed in the middle of a
+    /// request.
+    ///
+    /// A client that sent `expect: 100-continue` is told to continue before
+    /// the body is read.
+    pub async fn read_request(&mut self) -> Result<Option<Request>, Error> {
+        let Some(head) = self.read_request_head().await? else {
+            return Ok(None);
+        };
+        let body = self.read_request_body().await?;
+        Ok(Some(Request {
+            method: head.method,
+            path: head.path,
+            headers: head.headers,
+            body,
+        }))
+    }
+
+    /// Read the next request's line and headers, leaving its body for
+    /// [`read_request_body`](Self::read_request_body).
+    ///
+    /// This lets a server decide on a request before a client that sent
+    /// `expect: 100-continue` uploads the body: reading the body sends
+    /// `100 Continue` first, while writing a response without reading it
+    /// turns the upload down. Since the body may arrive anyway, the
+    /// connection then closes after the response.
+    ///
+    /// Returns `None` as [`read_request`](Self::read_request) does.
+    pub async fn read_request_head(&mut self) -> Result<Option<RequestHead>, Error> {
+        if !self.keep_alive {
+            return Ok(None);
+        }
+        let Some(Event::Request(head)) = self.next_event().await? else {
+            return Ok(None);
+        };
+        self.version = Some(head.version);
+        self.keep_alive = keep_alive(head.version, &head.headers);
+        if content_length(&head.headers)? == 0 {
+            self.read_body().await?;
+        } else {
+            self.body_pending = true;
+            self.continue_pending = expects_continue(head.version, &head.headers);
+        }
+        Ok(Some(head))
+    }
+
+    /// Read the body of the request whose head was just read, sending
+    /// `100 Continue` first if the client is waiting for it.
+    ///
+    /// Returns an empty body if there is none, or if it was already read.
+    pub async fn read_request_body(&mut self) -> Result<Vec<u8>, Error> {
+        if !self.body_pending {
+            return Ok(Vec::new());
+        }
+        self.write_continue().await?;
+        let body = self.read_body().await?;
+        self.body_pending = false;
+        Ok(body)
+    }
+
+    /// Send `100 Continue` if the request just read is waiting for it and
+    /// has not been sent one yet.
+    ///
+    /// [`read_request_body`](Self::read_request_body) does this itself;
+    /// call it directly to let the client start uploading before the
+    /// server is ready to read.
+    pub async fn write_continue(&mut self) -> Result<(), Error> {
+        if !std::mem::take(&mut self.continue_pending) {
+            return Ok(());
+        }
+        self.write_buf.clear();
+        self.write_buf.extend_from_slice(CONTINUE);
+        self.send().await
+    }
+
+    /// Read the next response.
+    ///
+    /// Assumes the response answers a request that may carry a body; use
+    /// [`read_response_for`](Self::read_response_for) when the request was
+    /// `HEAD`.
+    pub async fn read_response(&mut self) -> Result<Response, Error> {
+        self.read_response_for(Method::Get).await
+    }
+
+    /// Read the next response to a request made with `method`.
+    ///
+    /// Interim responses (1xx other than `101 Switching Protocols`) are
+    /// skipped. Unlike [`read_request`](Self::read_request), a connection
+    /// closed before the response is an `UnexpectedEof` error.
+    pub async fn read_response_for(&mut self, method: Method) -> Result<Response, Error> {
+        let head = self.read_response_head_for(method).await?;
+        let body = self.read_body().await?;
+        Ok(Response {
+            status: head.status,
+            reason: head.reason,
+            headers: head.headers,
+            body,
+        })
+    }
+
+    /// Read the status line and headers of the next response to a request
+    /// made with `method`, leaving its body for
+    /// [`read_body_chunk`](Self::read_body_chunk).
+    ///
+    /// Interim responses are skipped as in
+    /// [`read_response_for`](Self::read_response_for). The body must be
+    /// read to its end before the next response.
+    pub async fn read_response_head_for(&mut self, method: Method) -> Result<ResponseHead, Error> {
+        self.parser.set_request_method(method);
+        let head = loop {
+            match self.next_event().await? {
+                Some(Event::Response(head)) if is_interim(head.status) => {
+                    self.read_body().await?;
+                }
+                Some(Event::Response(head)) => break head,
+                _ => return Err(unexpected_eof()),
+            }
+        };
+        self.version = Some(head.version);
+        self.keep_alive = keep_alive(head.version, &head.headers);
+        Ok(head)
+    }
+
+    /// Read the next piece of the current message's body as it arrives,
+    /// or `None` once the body is complete.
+    pub async fn read_body_chunk(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match self.next_event().await? {
+            Some(Event::Body(chunk)) => Ok(Some(chunk)),
+            Some(Event::End) => Ok(None),
+            _ => Err(unexpected_eof()),
+        }
+    }
+
+    /// Write a request and flush it.
+    ///
+    /// A request with `connection: close` marks the connection as closing.
+    pub async fn write_request(&mut self, request: &Request) -> Result<(), Error> {
+        if !keep_alive(Version::Http11, &request.headers) {
+            self.keep_alive = false;
+        }
+        self.write_buf.clear();
+        write_request(&mut self.write_buf, request)?;
+        self.send().await
+    }
+
+    /// Write a request with a body, holding the body back until the server
+    /// answers `100 Continue`, so a server that would refuse the request
+    /// can do so before the upload.
+    ///
+    /// Adds `expect: 100-continue` unless the request has it. The body is
+    /// sent anyway if `timeout` completes first, since servers that don't
+    /// support the expectation never answer it. If the server sends a
+    /// final response instead, the body is not sent, the response is
+    /// returned, and the connection is marked as closing; otherwise the
+    /// response is read as usual with
+    /// [`read_response_for`](Self::read_response_for).
+    ///
+    /// Requests without a body are written as with
+    /// [`write_request`](Self::write_request).
+    ///
+    /// ```ignore
+    /// let refused = conn
+    ///     .write_request_with_continue(&upload, tokio::time::sleep(Duration::from_secs(1)))
+    ///     .await?;
+    /// let response = match refused {
+    ///     Some(response) => response,
+    ///     None => conn.read_response_for(upload.method).await?,
+    /// };
+    /// ```
+    pub async fn write_request_with_continue(
+        &mut self,
+        request: &Request,
+        timeout: impl Future<Output = ()>,
+    ) -> Result<Option<Response>, Error> {
+        if request.body.is_empty() {
+            self.write_request(request).await?;
+            return Ok(None);
+        }
+        let with_expect;
+        let request = if expects_continue(Version::Http11, &request.headers) {
+            request
+        } else {
+            let mut copy = request.clone();
+            copy.headers.insert("expect", "100-continue");
+            with_expect = copy;
+            &with_expect
+        };
+        if !keep_alive(Version::Http11, &request.headers) {
+            self.keep_alive = false;
+        }
+        self.write_buf.clear();
+        write_request(&mut self.write_buf, request)?;
+        let body = self
+            .write_buf
+            .split_off(self.write_buf.len() - request.body.len());
+        self.send().await?;
+
+        self.parser.set_request_method(request.method);
+        let mut timeout = pin!(timeout);
+        loop {
+            let event = {
+                let mut next = pin!(self.next_event());
+                poll_fn(|cx| match next.as_mut().poll(cx) {
+                    Poll::Ready(event) => Poll::Ready(Some(event)),
+                    Poll::Pending => timeout.as_mut().poll(cx).map(|()| None),
+                })
+                .await
+            };
+            let head = match event {
+                // Timed out.
+                None => break,
+                Some(Ok(Some(Event::Response(head)))) => head,
+                Some(Ok(_)) => return Err(unexpected_eof()),
+                Some(Err(e)) => return Err(e),
+            };
+            if is_interim(head.status) {
+                self.read_body().await?;
+                if head.status == 100 {
+                    break;
+                }
+                continue;
+            }
+            self.version = Some(head.version);
+            self.keep_alive = false;
+            let body = self.read_body().await?;
+            return Ok(Some(Response {
+                status: head.status,
+                reason: head.reason,
+                headers: head.headers,
+                body,
+            }));
+        }
+        self.write_buf = body;
+        self.send().await?;
+        Ok(None)
+    }
+
+    /// Write a response and flush it.
+    ///
+    /// Assumes the response answers a request that may carry a body; use
+    /// [`write_response_for`](Self::write_response_for) when the request was
+    /// `HEAD`.
+    pub async fn write_response(&mut self, response: &Response) -> Result<(), Error> {
+        self.write_response_for(response, Method::Get).await
+    }
+
+    /// Write a response to a request made with `method` and flush it.
+    ///
+    /// Bodies are suppressed and headers validated as in
+    /// [`write_response_for`](crate::write_response_for).
+    ///
+    /// Unless the response sets its own `connection` header, one is added
+    /// when the connection will close, or when an HTTP/1.0 peer asked for
+    /// it to stay open. A response with `connection: close`, or one written
+    /// before the request body was read, marks the connection as closing.
+    pub async fn write_response_for(
+        &mut self,
+        response: &Response,
+        method: Method,
+    ) -> Result<(), Error> {
+        if !keep_alive(Version::Http11, &response.headers) || self.body_pending {
+            self.keep_alive = false;
+            self.body_pending = false;
+            self.continue_pending = false;
+        }
+        let connection = match (self.keep_alive, self.version) {
+            _ if response.headers.contains_key("connection") => None,
+            (false, _) => Some("close"),
+            (true, Some(Version::Http10)) => Some("keep-alive"),
+            (true, _) => None,
+        };
+        self.write_buf.clear();
+        match connection {
+            Some(value) => {
+                let response = response.clone().header("connection", value);
+                write_response_for(&mut self.write_buf, &response, method)?;
+            }
+            None => write_response_for(&mut self.write_buf, response, method)?,
+        }
+        self.send().await
+    }
+
+    /// Answer requests with `handler` until the peer closes the connection
+    /// or either side asks for it to be closed.
+    ///
+    /// Malformed requests are answered with `400 Bad Request`, and requests
+    /// over the connection's [`Limits`] with `414`, `431`, or `413`; either
+    /// ends the connection. I/O errors are returned. The stream is not shut down.
+    ///
+    /// ```ignore
+    /// let (stream, _) = listener.accept().await?;
+    /// Http1Connection::server(stream).serve(&handler).await?;
+    /// ```
+    pub async fn serve<H: Handler + ?Sized>(&mut self, handler: &H) -> Result<(), Error> {
+        loop {
+            let (response, method) = match self.read_request().await {
+                Ok(Some(request)) => (handler.handle(&request), request.method),
+                Ok(None) => return Ok(()),
+                Err(e @ (Error::Io(_) | Error::Socket(_))) => return Err(e),
+                Err(e) => {
+                    self.keep_alive = false;
+                    (Response::new(e.status()), Method::Get)
+                }
+            };
+            self.write_response_for(&response, method).await?;
+        }
+    }
+
+    /// Answer requests with an async `handler` until the peer closes the
+    /// connection, either side asks for it to be closed, or `stop`
+    /// completes.
+    ///
+    /// Errors are handled as in [`serve`](Self::serve), and requests with
+    /// methods [`portals_http::Method`] can't express are answered with
+    /// `501 Not Implemented`. Once `stop` completes, an idle connection
+    /// returns at once, and a request being answered still gets its
+    /// response, sent with `connection: close`.
+    ///
+    /// ```ignore
+    /// let (stream, _) = listener.accept().await?;
+    /// let mut conn = Http1Connection::server(stream);
+    /// conn.serve_http(&router, shutdown.wait()).await?;
+    /// ```
+    pub async fn serve_http<H: HttpHandler + ?Sized>(
+        &mut self,
+        handler: &H,
+        stop: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        let mut stop = pin!(stop);
+        loop {
+            let next = {
+                let mut read = pin!(self.read_request());
+                poll_fn(|cx| match read.as_mut().poll(cx) {
+                    Poll::Ready(next) => Poll::Ready(Some(next)),
+                    Poll::Pending => stop.as_mut().poll(cx).map(|()| None),
+                })
+                .await
+            };
+            let (mut response, method) = match next {
+                // Stopped while idle.
+                None => return Ok(()),
+                Some(Ok(Some(request))) => {
+                    let method = request.method;
+                    let response = match to_http(request) {
+                        Some(request) => handler.handle(request).await.into(),
+                        None => Response::new(501),
+                    };
+                    (response, method)
+                }
+                Some(Ok(None)) => return Ok(()),
+                Some(Err(e @ (Error::Io(_) | Error::Socket(_)))) => return Err(e),
+                Some(Err(e)) => {
+                    self.keep_alive = false;
+                    (Response::new(e.status()), Method::Get)
+                }
+            };
+            let stopped = poll_fn(|cx| Poll::Ready(stop.as_mut().poll(cx).is_ready())).await;
+            if stopped {
+                response = response.header("connection", "close");
+            }
+            self.write_response_for(&response, method).await?;
+            if stopped {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether the connection can carry another message after the current
+    /// exchange.
+    ///
+    /// HTTP/1.1 connections stay open unless a message says
+    /// `connection: close`; HTTP/1.0 connections close unless the peer
+    /// sends `connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// The HTTP version of the last message read, if any.
+    pub fn version(&self) -> Option<Version> {
+        self.version
+    }
+
+    /// Input read from the stream but not yet parsed into a message.
+    pub fn buffered(&self) -> &[u8] {
+        self.parser.buffered()
+    }
+
+    /// Get a reference to the stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Unwrap the stream, discarding any buffered input.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Return the next event, reading from the stream as needed.
+    ///
+    /// Returns `None` if the stream ends between messages. A body that
+    /// runs until the stream ends leaves the connection closing.
+    async fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        let mut event = self.parser.next_event()?;
+        while event.is_none() {
+            let n = self.stream.read(&mut self.read_buf).await?;
+            if n == 0 {
+                self.keep_alive = false;
+                return self.parser.finish();
+            }
+            event = self.parser.feed(&self.read_buf[..n])?;
+        }
+        Ok(event)
+    }
+
+    /// Collect body chunks up to the end of the current message.
+    async fn read_body(&mut self) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.read_body_chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Write out `write_buf` in full and flush.
+    async fn send(&mut self) -> Result<(), Error> {
+        let mut written = 0;
+        while written < self.write_buf.len() {
+            let n = self.stream.write(&self.write_buf[written..]).await?;
+            if n == 0 {
+                return Err(Error::Io(std::io::ErrorKind::WriteZero.into()));
+            }
+            written += n;
+        }
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+/// Convert a request for an [`HttpHandler`], if its method is one
+/// [`portals_http::Method`] has.
+fn to_http(request: Request) -> Option<portals_http::Request> {
+    let method = match request.method {
+        Method::Get => portals_http::Method::Get,
+        Method::Head => portals_http::Method::Head,
+        Method::Post => portals_http::Method::Post,
+        Method::Put => portals_http::Method::Put,
+        Method::Delete => portals_http::Method::Delete,
+        Method::Patch => portals_http::Method::Patch,
+        Method::Options => portals_http::Method::Options,
+        Method::Connect | Method::Trace => return None,
+    };
+    Some(portals_http::Request {
+        method,
+        url: request.path,
+        headers: request.headers,
+        body: (!request.body.is_empty()).then_some(request.body),
+    })
+}
+
+/// Whether a response is interim, to be followed by the final one.
+fn is_interim(status: u16) -> bool {
+    (100..200).contains(&status) && status != 101
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Headers;
+    use portals_sockets::{TcpConnect, TcpListener};
+    use portals_sockets_native::{NativeTcpConnect, NativeTcpListener};
+
+    #[tokio::test]
+    async fn exchanges_messages() {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Http1Connection::server(stream);
+            while let Some(request) = conn.read_request().await.unwrap() {
+                let response =
+                    Response::new(200).body([request.path.as_bytes(), &request.body].concat());
+                conn.write_response_for(&response, request.method)
+                    .await
+                    .unwrap();
+            }
+        };
+
+        let client = async {
+            let stream = NativeTcpConnect.connect(addr).await.unwrap();
+            let mut conn = Http1Connection::client(stream);
+            let request = Request {
+                method: Method::Post,
+                path: "/echo".to_string(),
+                headers: Headers::new(),
+                body: b"-body".to_vec(),
+            };
+            conn.write_request(&request).await.unwrap();
+            let response = conn.read_response().await.unwrap();
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, b"/echo-body");
+
+            let request = Request {
+                method: Method::Head,
+                path: "/".to_string(),
+                headers: Headers::new(),
+                body: Vec::new(),
+            };
+            conn.write_request(&request).await.unwrap();
+            let response = conn.read_response_for(Method::Head).await.unwrap();
+            assert_eq!(response.headers.get("content-length"), Some("1"));
+            assert!(response.body.is_empty());
+            conn.get_mut().shutdown().unwrap();
+        };
+
+        tokio::join!(server, client);
+    }
+
+    #[tokio::test]
+    async fn truncated_request() {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = async {
+            let mut stream = NativeTcpConnect.connect(addr).await.unwrap();
+            stream
+                .write(b"PUT / HTTP/1.1\r\ncontent-length: 10\r\n\r\nshort")
+                .await
+                .unwrap();
+            stream
+        };
+        let (stream, (accepted, _)) =
+            tokio::join!(client, async { listener.accept().await.unwrap() });
+        let mut conn = Http1Connection::server(accepted);
+        drop(stream);
+
+        assert!(matches!(
+            conn.read_request().await,
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[tokio::test]
+    async fn expect_continue() {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Http1Connection::server(stream);
+            while let Some(head) = conn.read_request_head().await.unwrap() {
+                assert_eq!(head.headers.get("expect"), Some("100-continue"));
+                let response = if head.path == "/refuse" {
+                    Response::new(413)
+                } else {
+                    Response::new(200).body(conn.read_request_body().await.unwrap())
+                };
+                conn.write_response(&response).await.unwrap();
+            }
+            conn.get_mut().shutdown().unwrap();
+        };
+
+        let client = async {
+            let stream = NativeTcpConnect.connect(addr).await.unwrap();
+            let mut conn = Http1Connection::client(stream);
+            let upload = |path: &str| Request {
+                method: Method::Put,
+                path: path.to_string(),
+                headers: Headers::new(),
+                body: b"upload".to_vec(),
+            };
+
+            let sent = conn
+                .write_request_with_continue(&upload("/accept"), std::future::pending())
+                .await
+                .unwrap();
+            assert!(sent.is_none());
+            let response = conn.read_response_for(Method::Put).await.unwrap();
+            assert_eq!(response.body, b"upload");
+
+            // Timing out sends the body anyway; the late `100 Continue` is
+            // skipped when reading the response.
+            let sent = conn
+                .write_request_with_continue(&upload("/late"), std::future::ready(()))
+                .await
+                .unwrap();
+            assert!(sent.is_none());
+            let response = conn.read_response_for(Method::Put).await.unwrap();
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, b"upload");
+            assert!(conn.keep_alive());
+
+            let refused = conn
+                .write_request_with_continue(&upload("/refuse"), std::future::pending())
+                .await
+                .unwrap();
+            assert_eq!(refused.unwrap().status, 413);
+            assert!(!conn.keep_alive());
+        };
+
+        tokio::join!(server, client);
+    }
+
+    /// Write `input` to a connection served by an echo handler and return
+    /// everything the server sends back before it stops.
+    async fn serve_raw(input: &[u8]) -> String {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Http1Connection::server(stream);
+            let handler = |request: &Request| Response::new(200).body(request.path.clone());
+            conn.serve(&handler).await.unwrap();
+            conn.get_mut().shutdown().unwrap();
+        };
+
+        let client = async {
+            let mut stream = NativeTcpConnect.connect(addr).await.unwrap();
+            stream.write(input).await.unwrap();
+            let mut output = Vec::new();
+            let mut buf = [0; 1024];
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buf[..n]);
+            }
+            String::from_utf8(output).unwrap()
+        };
+
+        tokio::join!(server, client).1
+    }
+
+    #[tokio::test]
+    async fn keeps_connection_alive() {
+        let output = serve_raw(
+            b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\nConnection: close\r\n\r\nGET /c HTTP/1.1\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            output,
+            "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n/a\
+             HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 2\r\n\r\n/b"
+        );
+    }
+
+    #[tokio::test]
+    async fn http10_closes_by_default() {
+        let output = serve_raw(b"GET /a HTTP/1.0\r\n\r\nGET /b HTTP/1.0\r\n\r\n").await;
+        assert_eq!(
+            output,
+            "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 2\r\n\r\n/a"
+        );
+
+        let output =
+            serve_raw(b"GET /a HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.0\r\n\r\n")
+                .await;
+        assert_eq!(
+            output,
+            "HTTP/1.1 200 OK\r\nconnection: keep-alive\r\ncontent-length: 2\r\n\r\n/a\
+             HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 2\r\n\r\n/b"
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_request_closes() {
+        let output = serve_raw(b"BREW /pot HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").await;
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\nconnection: close\r\n"));
+        assert!(!output.contains("200"));
+    }
+
+    /// Answers with the URL it was sent.
+    struct Path;
+
+    impl HttpHandler for Path {
+        async fn handle(&self, request: portals_http::Request) -> portals_http::Response {
+            Response::new(200).body(request.url).into()
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_http_handlers_until_stopped() {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+        let server = async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Http1Connection::server(stream);
+            conn.serve_http(&Path, async {
+                let _ = stopped.await;
+            })
+            .await
+            .unwrap();
+            conn.get_mut().shutdown().unwrap();
+        };
+
+        let client = async {
+            let mut stream = NativeTcpConnect.connect(addr).await.unwrap();
+            stream
+                .write(b"GET /a HTTP/1.1\r\n\r\nTRACE / HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap();
+            let expected = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n/a\
+                            HTTP/1.1 501 Not Implemented\r\ncontent-length: 0\r\n\r\n";
+            let mut output = Vec::new();
+            let mut buf = [0; 1024];
+            while output.len() < expected.len() {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert_ne!(n, 0);
+                output.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+            // The connection is idle, so stopping closes it at once.
+            stop.send(()).unwrap();
+            assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+        };
+
+        tokio::join!(server, client);
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_rejected() {
+        let output = serve_raw(b"POST / HTTP/1.1\r\ncontent-length: 999999999\r\n\r\n").await;
+        assert!(output.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+
+        let mut input = b"GET / HTTP/1.1\r\n".to_vec();
+        for i in 0..101 {
+            input.extend_from_slice(format!("x-{}: {}\r\n", i, i).as_bytes());
+        }
+        input.extend_from_slice(b"\r\n");
+        let output = serve_raw(&input).await;
+        assert!(output.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+}
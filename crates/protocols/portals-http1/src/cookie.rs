@@ -0,0 +1,459 @@
+//! Cookies (RFC 6265).
+//!
+//! Servers read the request's cookies into a [`CookieJar`], change them
+//! with [`add`](CookieJar::add) and [`remove`](CookieJar::remove), and
+//! write the changes back as `set-cookie` headers:
+//!
+//! ```ignore
+//! let mut jar = CookieJar::from_headers(&request.headers);
+//! if jar.get("session").is_none() {
+//!     jar.add(Cookie::new("session", new_session_id()).http_only(true).secure(true));
+//! }
+//! let mut response = Response::new(200);
+//! jar.write_changes(&mut response.headers)?;
+//! ```
+//!
+//! Clients do the reverse with [`store`](CookieJar::store) and
+//! [`cookie_header`](CookieJar::cookie_header).
+
+use crate::{Error, Headers, format_http_date, parse_http_date};
+use std::fmt;
+
+/// The `SameSite` cookie attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+/// A cookie, with the attributes a `set-cookie` header can carry.
+///
+/// Names must be HTTP tokens; values may be wrapped in double quotes but
+/// must not otherwise contain whitespace, `"`, `,`, `;`, `\`, or control
+/// characters. Encode anything else (e.g. as base64) before storing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Expiry as a Unix timestamp.
+    pub expires: Option<u64>,
+    /// Lifetime in seconds; zero or less expires the cookie at once.
+    pub max_age: Option<i64>,
+    pub domain: Option<String>,
+    pub path: Option<String>,
+    pub same_site: Option<SameSite>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+impl Cookie {
+    /// Create a session cookie with no attributes.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            same_site: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// Set when the cookie expires, as a Unix timestamp.
+    pub fn expires(mut self, expires: u64) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Set how many seconds the cookie lasts.
+    pub fn max_age(mut self, max_age: i64) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Set the domain the cookie is sent to.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Set the path prefix the cookie is sent for.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Set whether the cookie is only sent over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set whether the cookie is hidden from scripts.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Whether this cookie tells the client to delete it.
+    pub fn is_removal(&self) -> bool {
+        self.max_age.is_some_and(|age| age <= 0)
+    }
+
+    /// Render the cookie as a `set-cookie` header value.
+    ///
+    /// Fails with [`Error::InvalidCookie`] if the name, value, domain, or
+    /// path contains characters that would break the header.
+    pub fn to_set_cookie(&self) -> Result<String, Error> {
+        self.validate()?;
+        Ok(self.to_string())
+    }
+
+    /// Parse a `set-cookie` header value.
+    ///
+    /// Unknown attributes and attributes with unparseable values are
+    /// ignored, as browsers do. Fails with [`Error::InvalidCookie`] only if
+    /// there is no `name=value` pair.
+    pub fn parse_set_cookie(value: &str) -> Result<Self, Error> {
+        let mut parts = value.split(';');
+        let (name, value) = parts
+            .next()
+            .and_then(|pair| pair.split_once('='))
+            .ok_or(Error::InvalidCookie)?;
+        let name = name.trim();
+        if !is_token(name) {
+            return Err(Error::InvalidCookie);
+        }
+        let mut cookie = Self::new(name, value.trim());
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "expires" => cookie.expires = parse_http_date(value).or(cookie.expires),
+                "max-age" => cookie.max_age = value.parse().ok().or(cookie.max_age),
+                "domain" if !value.is_empty() => {
+                    cookie.domain = Some(value.trim_start_matches('.').to_string())
+                }
+                "path" if value.starts_with('/') => cookie.path = Some(value.to_string()),
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "strict" => Some(SameSite::Strict),
+                        "lax" => Some(SameSite::Lax),
+                        "none" => Some(SameSite::None),
+                        _ => cookie.same_site,
+                    }
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        Ok(cookie)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let value = self
+            .value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(&self.value);
+        let attribute_ok = |a: &Option<String>| {
+            a.as_deref()
+                .is_none_or(|a| a.bytes().all(|b| b.is_ascii_graphic() && b != b';'))
+        };
+        if is_token(&self.name)
+            && value.bytes().all(is_cookie_octet)
+            && attribute_ok(&self.domain)
+            && attribute_ok(&self.path)
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidCookie)
+        }
+    }
+}
+
+impl fmt::Display for Cookie {
+    /// Writes the `set-cookie` form without validating it; see
+    /// [`to_set_cookie`](Cookie::to_set_cookie).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", format_http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        Ok(())
+    }
+}
+
+/// A set of cookies, and the changes made to it.
+///
+/// Cookies are identified by name. The jar does not match cookies against
+/// request URLs or track expiry; it is the per-request view a server or a
+/// simple client needs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    changes: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Create an empty jar.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the cookies from every `cookie` header.
+    ///
+    /// Malformed pairs are skipped. If a name appears more than once, the
+    /// first is kept, since clients send the most specific cookie first.
+    pub fn from_headers(headers: &Headers) -> Self {
+        let mut jar = Self::new();
+        let pairs = headers
+            .get_all("cookie")
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.split_once('='));
+        for (name, value) in pairs {
+            let name = name.trim();
+            if is_token(name) && jar.get(name).is_none() {
+                jar.cookies.push(Cookie::new(name, value.trim()));
+            }
+        }
+        jar
+    }
+
+    /// Get a cookie's value.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookie(name).map(|c| c.value.as_str())
+    }
+
+    /// Get a cookie.
+    pub fn cookie(&self, name: &str) -> Option<&Cookie> {
+        self.cookies.iter().find(|c| c.name == name)
+    }
+
+    /// Iterate over the cookies, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.iter()
+    }
+
+    /// Add or replace a cookie, recording the change.
+    pub fn add(&mut self, cookie: Cookie) {
+        self.record(cookie.clone());
+        match self.cookies.iter_mut().find(|c| c.name == cookie.name) {
+            Some(existing) => *existing = cookie,
+            None => self.cookies.push(cookie),
+        }
+    }
+
+    /// Remove a cookie, recording a change that tells the client to delete
+    /// it.
+    ///
+    /// Clients only delete a cookie whose domain and path match, so pass
+    /// the ones it was set with.
+    pub fn remove(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| c.name != cookie.name);
+        let mut removal = Cookie::new(cookie.name, "").max_age(0).expires(0);
+        removal.domain = cookie.domain;
+        removal.path = cookie.path;
+        self.record(removal);
+    }
+
+    /// The changes made with [`add`](Self::add) and
+    /// [`remove`](Self::remove), one per name.
+    pub fn changes(&self) -> impl Iterator<Item = &Cookie> {
+        self.changes.iter()
+    }
+
+    /// Append a `set-cookie` header for each change.
+    pub fn write_changes(&self, headers: &mut Headers) -> Result<(), Error> {
+        for cookie in &self.changes {
+            headers.append("set-cookie", cookie.to_set_cookie()?);
+        }
+        Ok(())
+    }
+
+    /// Apply the `set-cookie` headers of a response, as a client.
+    ///
+    /// Expired cookies are removed; malformed headers are skipped. Returns
+    /// how many headers were applied. Nothing is recorded as a change.
+    pub fn store(&mut self, headers: &Headers) -> usize {
+        let mut applied = 0;
+        for value in headers.get_all("set-cookie") {
+            let Ok(cookie) = Cookie::parse_set_cookie(value) else {
+                continue;
+            };
+            self.cookies.retain(|c| c.name != cookie.name);
+            if !cookie.is_removal() {
+                self.cookies.push(cookie);
+            }
+            applied += 1;
+        }
+        applied
+    }
+
+    /// The `cookie` header value sending every cookie, or `None` if the jar
+    /// is empty.
+    pub fn cookie_header(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        let pairs: Vec<String> = self
+            .cookies
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    fn record(&mut self, cookie: Cookie) {
+        self.changes.retain(|c| c.name != cookie.name);
+        self.changes.push(cookie);
+    }
+}
+
+/// Whether `s` is a non-empty HTTP token (RFC 9110 §5.6.2).
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Whether `b` may appear in a cookie value (RFC 6265 §4.1.1).
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_cookie_round_trip() {
+        let cookie = Cookie::new("session", "abc123")
+            .expires(784_111_777)
+            .max_age(3600)
+            .domain("example.com")
+            .path("/app")
+            .same_site(SameSite::Lax)
+            .secure(true)
+            .http_only(true);
+        let header = cookie.to_set_cookie().unwrap();
+        assert_eq!(
+            header,
+            "session=abc123; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=3600; \
+             Domain=example.com; Path=/app; SameSite=Lax; Secure; HttpOnly"
+        );
+        assert_eq!(Cookie::parse_set_cookie(&header).unwrap(), cookie);
+
+        let parsed = Cookie::parse_set_cookie(
+            "id = \"quoted\" ; max-age=oops; DOMAIN=.Example.com; path=relative; secure; Foo=bar",
+        )
+        .unwrap();
+        assert_eq!(parsed.value, "\"quoted\"");
+        assert_eq!(parsed.max_age, None);
+        assert_eq!(parsed.domain.as_deref(), Some("Example.com"));
+        assert_eq!(parsed.path, None);
+        assert!(parsed.secure && !parsed.http_only);
+
+        assert!(Cookie::parse_set_cookie("no pair here").is_err());
+        for invalid in [
+            Cookie::new("bad name", "v"),
+            Cookie::new("a", "semi;colon"),
+            Cookie::new("a", "white space"),
+            Cookie::new("a", "v").path("/x;Secure"),
+        ] {
+            assert!(
+                matches!(invalid.to_set_cookie(), Err(Error::InvalidCookie)),
+                "{}",
+                invalid
+            );
+        }
+        assert!(Cookie::new("a", "\"quoted\"").to_set_cookie().is_ok());
+    }
+
+    #[test]
+    fn server_jar() {
+        let headers = Headers::from([
+            ("Cookie", "session=abc; theme=dark"),
+            ("Cookie", "bad pair; theme=light; lang=en"),
+        ]);
+        let mut jar = CookieJar::from_headers(&headers);
+        assert_eq!(jar.get("session"), Some("abc"));
+        assert_eq!(jar.get("theme"), Some("dark"));
+        assert_eq!(jar.iter().count(), 3);
+        assert_eq!(jar.changes().count(), 0);
+
+        jar.add(Cookie::new("theme", "light").path("/"));
+        jar.add(Cookie::new("theme", "solarized").path("/"));
+        jar.remove(Cookie::new("session", "").path("/"));
+        assert_eq!(jar.get("theme"), Some("solarized"));
+        assert_eq!(jar.get("session"), None);
+
+        let mut headers = Headers::new();
+        jar.write_changes(&mut headers).unwrap();
+        assert_eq!(
+            headers.get_all("set-cookie").collect::<Vec<_>>(),
+            [
+                "theme=solarized; Path=/",
+                "session=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0; Path=/",
+            ]
+        );
+    }
+
+    #[test]
+    fn client_jar() {
+        let mut jar = CookieJar::new();
+        assert_eq!(jar.cookie_header(), None);
+
+        let headers = Headers::from([
+            ("Set-Cookie", "a=1; Path=/"),
+            ("Set-Cookie", "b=2; HttpOnly"),
+            ("Set-Cookie", "=nameless"),
+        ]);
+        assert_eq!(jar.store(&headers), 2);
+        assert_eq!(jar.cookie_header().as_deref(), Some("a=1; b=2"));
+
+        let headers = Headers::from([("Set-Cookie", "a=; Max-Age=0"), ("Set-Cookie", "b=3")]);
+        jar.store(&headers);
+        assert_eq!(jar.cookie_header().as_deref(), Some("b=3"));
+        assert_eq!(jar.changes().count(), 0);
+    }
+}
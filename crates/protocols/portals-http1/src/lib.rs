@@ -2,41 +2,114 @@
 //!
 //! Provides parsing and serialization of HTTP/1.1 requests and responses.
 
-use std::collections::HashMap;
+mod coding;
+mod connection;
+mod cookie;
+mod date;
+mod parser;
+mod request_id;
+mod router;
+mod server;
+mod target;
+
+pub use coding::{Codec, Compression};
+pub use connection::Http1Connection;
+pub use cookie::{Cookie, CookieJar, SameSite};
+pub use date::{format_http_date, parse_http_date};
+pub use parser::{Event, Http1Parser, RequestHead, ResponseHead};
+pub use portals_http::Headers;
+pub use portals_observe::REQUEST_ID;
+pub use request_id::RequestId;
+pub use router::{Handler, Params, RouteHandler, Router};
+pub use server::serve_connection;
+pub use target::Target;
+
 use std::io::{BufRead, Write};
 
 /// HTTP/1.1 errors.
 #[derive(Debug)]
 pub enum Error {
     InvalidRequestLine,
+    InvalidTarget,
     InvalidStatusLine,
     InvalidHeader,
     InvalidMethod,
     InvalidContentLength,
+    InvalidStatus(u16),
+    InvalidReason,
+    UnsupportedEncoding(String),
+    UnsupportedTransferEncoding(String),
+    InvalidEncoding,
+    InvalidChunk,
+    InvalidCookie,
+    LineTooLong,
+    TooManyHeaders,
+    HeadersTooLarge,
+    BodyTooLarge,
     Io(std::io::Error),
+    Socket(portals_sockets::Error),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::InvalidRequestLine => write!(f, "invalid request line"),
+            Self::InvalidTarget => write!(f, "invalid request target"),
             Self::InvalidStatusLine => write!(f, "invalid status line"),
             Self::InvalidHeader => write!(f, "invalid header"),
             Self::InvalidMethod => write!(f, "invalid method"),
             Self::InvalidContentLength => write!(f, "invalid content length"),
+            Self::InvalidStatus(status) => write!(f, "invalid status code: {}", status),
+            Self::InvalidReason => write!(f, "invalid reason phrase"),
+            Self::UnsupportedEncoding(coding) => {
+                write!(f, "unsupported content encoding: {}", coding)
+            }
+            Self::UnsupportedTransferEncoding(coding) => {
+                write!(f, "unsupported transfer encoding: {}", coding)
+            }
+            Self::InvalidEncoding => write!(f, "body does not match its content encoding"),
+            Self::InvalidChunk => write!(f, "invalid chunked encoding"),
+            Self::InvalidCookie => write!(f, "invalid cookie"),
+            Self::LineTooLong => write!(f, "start line too long"),
+            Self::TooManyHeaders => write!(f, "too many header fields"),
+            Self::HeadersTooLarge => write!(f, "header section too large"),
+            Self::BodyTooLarge => write!(f, "body too large"),
             Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Socket(e) => write!(f, "socket error: {}", e),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /// The status a server answers a request that failed with this error.
+    ///
+    /// Oversized requests get `414`, `431`, or `413`, a `transfer-encoding`
+    /// on a request `501`, and anything else `400 Bad Request`.
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::LineTooLong => 414,
+            Self::TooManyHeaders | Self::HeadersTooLarge => 431,
+            Self::BodyTooLarge => 413,
+            Self::UnsupportedTransferEncoding(_) => 501,
+            _ => 400,
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
     }
 }
 
+impl From<portals_sockets::Error> for Error {
+    fn from(e: portals_sockets::Error) -> Self {
+        Self::Socket(e)
+    }
+}
+
 /// HTTP method.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Method {
@@ -66,6 +139,7 @@ impl Method {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, Error> {
         match s {
             "GET" => Ok(Self::Get),
@@ -82,21 +156,91 @@ impl Method {
     }
 }
 
+/// HTTP protocol version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http10 => "HTTP/1.0",
+            Self::Http11 => "HTTP/1.1",
+        }
+    }
+
+    /// Parse a version token. A missing version is taken as HTTP/1.1.
+    fn parse(token: Option<&str>) -> Option<Self> {
+        match token {
+            Some("HTTP/1.0") => Some(Self::Http10),
+            Some("HTTP/1.1") | None => Some(Self::Http11),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Whether a connection stays open after a message with this version and
+/// headers.
+///
+/// HTTP/1.1 connections persist unless the `connection` header lists
+/// `close`; HTTP/1.0 connections close unless it lists `keep-alive`.
+pub fn keep_alive(version: Version, headers: &Headers) -> bool {
+    let has = |token: &str| {
+        headers.get_all("connection").any(|value| {
+            value
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        })
+    };
+    match version {
+        Version::Http11 => !has("close"),
+        Version::Http10 => has("keep-alive"),
+    }
+}
+
+/// The interim response telling a client to send the body it is holding
+/// back.
+pub(crate) const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+/// Whether a request with this version and headers waits for a
+/// `100 Continue` interim response before sending its body.
+///
+/// Only HTTP/1.1 clients send `expect: 100-continue`; servers must not
+/// send interim responses to HTTP/1.0 clients.
+pub fn expects_continue(version: Version, headers: &Headers) -> bool {
+    version == Version::Http11
+        && headers
+            .get_all("expect")
+            .any(|value| value.trim().eq_ignore_ascii_case("100-continue"))
+}
+
 /// HTTP request.
 #[derive(Debug, Clone)]
 pub struct Request {
     pub method: Method,
     pub path: String,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers,
     pub body: Vec<u8>,
 }
 
+impl Request {
+    /// Parse the request target.
+    ///
+    /// `path` holds the target exactly as it was sent; this splits it into
+    /// its form, path, and query. See [`Target`].
+    pub fn target(&self) -> Result<Target, Error> {
+        Target::parse(&self.path)
+    }
+}
+
 /// HTTP response.
 #[derive(Debug, Clone)]
 pub struct Response {
     pub status: u16,
     pub reason: String,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers,
     pub body: Vec<u8>,
 }
 
@@ -106,14 +250,20 @@ impl Response {
         Self {
             status,
             reason: reason_phrase(status).to_string(),
-            headers: HashMap::new(),
+            headers: Headers::new(),
             body: Vec::new(),
         }
     }
 
-    /// Set a header.
+    /// Set a header, replacing any values it already has.
     pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
-        self.headers.insert(name.into(), value.into());
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Add a header value, keeping any it already has (e.g. `set-cookie`).
+    pub fn append_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.append(name, value);
         self
     }
 
@@ -122,103 +272,360 @@ impl Response {
         self.body = body.into();
         self
     }
+
+    /// The class of the status code, or `None` if it is outside 100–599.
+    pub fn class(&self) -> Option<StatusClass> {
+        StatusClass::of(self.status)
+    }
+
+    /// Whether the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        self.class() == Some(StatusClass::Success)
+    }
+
+    /// Whether the request may succeed if sent again; see
+    /// [`status_is_retryable`].
+    pub fn is_retryable(&self) -> bool {
+        status_is_retryable(self.status)
+    }
 }
 
-/// Parse an HTTP request from a buffered reader.
-pub fn parse_request<R: BufRead>(reader: &mut R) -> Result<Request, Error> {
-    let mut line = String::new();
+impl From<portals_http::Method> for Method {
+    fn from(method: portals_http::Method) -> Self {
+        match method {
+            portals_http::Method::Get => Self::Get,
+            portals_http::Method::Head => Self::Head,
+            portals_http::Method::Post => Self::Post,
+            portals_http::Method::Put => Self::Put,
+            portals_http::Method::Delete => Self::Delete,
+            portals_http::Method::Patch => Self::Patch,
+            portals_http::Method::Options => Self::Options,
+        }
+    }
+}
 
-    // Request line
-    reader.read_line(&mut line)?;
-    let parts: Vec<&str> = line.trim_end().split(' ').collect();
-    if parts.len() < 2 {
-        return Err(Error::InvalidRequestLine);
+impl From<portals_http::Request> for Request {
+    /// The URL becomes the target as it is; a missing body, an empty one.
+    fn from(request: portals_http::Request) -> Self {
+        Self {
+            method: request.method.into(),
+            path: request.url,
+            headers: request.headers,
+            body: request.body.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<portals_http::Response> for Response {
+    fn from(response: portals_http::Response) -> Self {
+        let mut converted = Self::new(response.status);
+        converted.headers = response.headers;
+        converted.body = response.body;
+        converted
+    }
+}
+
+impl From<Response> for portals_http::Response {
+    /// The reason phrase is dropped.
+    fn from(response: Response) -> Self {
+        Self {
+            status: response.status,
+            headers: response.headers,
+            body: response.body,
+            redirects: Vec::new(),
+        }
     }
+}
 
-    let method = Method::from_str(parts[0])?;
-    let path = parts[1].to_string();
+/// Bounds on the size of a message, so a peer can't exhaust memory by
+/// sending an endless line, header section, or body.
+///
+/// Exceeding a limit fails parsing with a dedicated error before the
+/// oversized part is buffered: [`Error::LineTooLong`],
+/// [`Error::TooManyHeaders`], [`Error::HeadersTooLarge`], or
+/// [`Error::BodyTooLarge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Longest request or status line, in bytes, including its line ending.
+    pub max_line: usize,
+    /// Most header fields.
+    pub max_headers: usize,
+    /// Largest header section, in bytes, including line endings and the
+    /// blank line that ends it.
+    pub max_header_bytes: usize,
+    /// Largest `content-length`.
+    pub max_body: usize,
+}
 
-    // Headers
-    let mut headers = HashMap::new();
-    loop {
-        line.clear();
-        reader.read_line(&mut line)?;
-        let trimmed = line.trim_end();
-        if trimmed.is_empty() {
-            break;
+impl Default for Limits {
+    /// 8 KiB lines, 100 headers in at most 64 KiB, and 16 MiB bodies.
+    fn default() -> Self {
+        Self {
+            max_line: 8 * 1024,
+            max_headers: 100,
+            max_header_bytes: 64 * 1024,
+            max_body: 16 * 1024 * 1024,
         }
-        if let Some((name, value)) = trimmed.split_once(':') {
-            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+}
+
+impl Limits {
+    /// No limits, for trusted peers.
+    pub fn unlimited() -> Self {
+        Self {
+            max_line: usize::MAX,
+            max_headers: usize::MAX,
+            max_header_bytes: usize::MAX,
+            max_body: usize::MAX,
         }
     }
 
-    // Body
-    let body = if let Some(len) = headers.get("content-length") {
-        let len: usize = len.parse().map_err(|_| Error::InvalidContentLength)?;
-        let mut body = vec![0u8; len];
-        reader.read_exact(&mut body)?;
-        body
-    } else {
-        Vec::new()
-    };
+    /// Set the longest request or status line.
+    pub fn max_line(mut self, max_line: usize) -> Self {
+        self.max_line = max_line;
+        self
+    }
+
+    /// Set the most header fields.
+    pub fn max_headers(mut self, max_headers: usize) -> Self {
+        self.max_headers = max_headers;
+        self
+    }
+
+    /// Set the largest header section.
+    pub fn max_header_bytes(mut self, max_header_bytes: usize) -> Self {
+        self.max_header_bytes = max_header_bytes;
+        self
+    }
+
+    /// Set the largest body.
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    /// The body length a request's `headers` declare, if within the limit.
+    ///
+    /// Request bodies are only framed by `content-length`. One sent with a
+    /// `transfer-encoding` is refused rather than read as empty, which
+    /// would leave its body to be parsed as the next request.
+    fn request_body_length(&self, headers: &Headers) -> Result<usize, Error> {
+        if let Some(coding) = headers.get("transfer-encoding") {
+            return Err(Error::UnsupportedTransferEncoding(coding.to_string()));
+        }
+        self.body_length(headers)
+    }
 
+    /// The body length `headers` declare, if within the limit.
+    fn body_length(&self, headers: &Headers) -> Result<usize, Error> {
+        let len = content_length(headers)?;
+        if len > self.max_body {
+            return Err(Error::BodyTooLarge);
+        }
+        Ok(len)
+    }
+}
+
+/// Parse an HTTP request from a buffered reader, with the default
+/// [`Limits`].
+pub fn parse_request<R: BufRead>(reader: &mut R) -> Result<Request, Error> {
+    parse_request_with_limits(reader, &Limits::default())
+}
+
+/// Parse an HTTP request from a buffered reader, within `limits`.
+pub fn parse_request_with_limits<R: BufRead>(
+    reader: &mut R,
+    limits: &Limits,
+) -> Result<Request, Error> {
+    let mut message = MessageReader::new(reader, Http1Parser::request().with_limits(*limits));
+    let head = message.request_head()?;
     Ok(Request {
-        method,
-        path,
-        headers,
-        body,
+        method: head.method,
+        path: head.path,
+        headers: head.headers,
+        body: message.read_body()?,
     })
 }
 
-/// Parse an HTTP response from a buffered reader.
+/// Parse an HTTP response from a buffered reader, with the default
+/// [`Limits`].
+///
+/// Assumes the response answers a request that may carry a body; use
+/// [`parse_response_for`] when the request was `HEAD`.
 pub fn parse_response<R: BufRead>(reader: &mut R) -> Result<Response, Error> {
-    let mut line = String::new();
+    parse_response_for(reader, Method::Get)
+}
 
-    // Status line
-    reader.read_line(&mut line)?;
-    let parts: Vec<&str> = line.trim_end().splitn(3, ' ').collect();
-    if parts.len() < 2 {
-        return Err(Error::InvalidStatusLine);
-    }
+/// Parse an HTTP response to a request made with `method`, with the
+/// default [`Limits`].
+///
+/// Responses to `HEAD` and responses with 1xx, 204, or 304 status never
+/// have a body, even if they carry a `content-length` header.
+pub fn parse_response_for<R: BufRead>(reader: &mut R, method: Method) -> Result<Response, Error> {
+    parse_response_with_limits(reader, method, &Limits::default())
+}
+
+/// Parse an HTTP response to a request made with `method`, within
+/// `limits`.
+pub fn parse_response_with_limits<R: BufRead>(
+    reader: &mut R,
+    method: Method,
+    limits: &Limits,
+) -> Result<Response, Error> {
+    let parser = Http1Parser::response_for(method).with_limits(*limits);
+    let mut message = MessageReader::new(reader, parser);
+    let Event::Response(head) = message.next_event()? else {
+        unreachable!("a response parser starts with a response head");
+    };
+    Ok(Response {
+        status: head.status,
+        reason: head.reason,
+        headers: head.headers,
+        body: message.read_body()?,
+    })
+}
 
-    let status: u16 = parts[1].parse().map_err(|_| Error::InvalidStatusLine)?;
-    let reason = parts.get(2).unwrap_or(&"").to_string();
+/// Runs an [`Http1Parser`] over a blocking reader for one message.
+///
+/// Input is consumed from the reader only up to the end of the message, so
+/// whatever follows it, such as a pipelined request, stays in the reader.
+pub(crate) struct MessageReader<'a, R> {
+    reader: &'a mut R,
+    parser: Http1Parser,
+    /// Bytes fed to the parser but not yet consumed from the reader.
+    fed: usize,
+}
 
-    // Headers
-    let mut headers = HashMap::new();
-    loop {
-        line.clear();
-        reader.read_line(&mut line)?;
-        let trimmed = line.trim_end();
-        if trimmed.is_empty() {
-            break;
+impl<'a, R: BufRead> MessageReader<'a, R> {
+    pub(crate) fn new(reader: &'a mut R, parser: Http1Parser) -> Self {
+        Self {
+            reader,
+            parser,
+            fed: 0,
         }
-        if let Some((name, value)) = trimmed.split_once(':') {
-            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+
+    /// Return the next event, reading as needed.
+    pub(crate) fn next_event(&mut self) -> Result<Event, Error> {
+        let mut event = self.parser.next_event()?;
+        loop {
+            if let Some(event) = event {
+                if event == Event::End {
+                    let unused = self.parser.buffered().len().min(self.fed);
+                    self.reader.consume(self.fed - unused);
+                    self.fed = 0;
+                }
+                return Ok(event);
+            }
+            self.reader.consume(std::mem::take(&mut self.fed));
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                event = Some(self.parser.finish()?.ok_or_else(unexpected_eof)?);
+                continue;
+            }
+            self.fed = buf.len();
+            event = self.parser.feed(buf)?;
         }
     }
 
-    // Body
-    let body = if let Some(len) = headers.get("content-length") {
-        let len: usize = len.parse().map_err(|_| Error::InvalidContentLength)?;
-        let mut body = vec![0u8; len];
-        reader.read_exact(&mut body)?;
-        body
-    } else {
-        Vec::new()
+    /// Read the head of a request.
+    pub(crate) fn request_head(&mut self) -> Result<RequestHead, Error> {
+        let Event::Request(head) = self.next_event()? else {
+            unreachable!("a request parser starts with a request head");
+        };
+        Ok(head)
+    }
+
+    /// Collect body chunks up to the end of the message.
+    pub(crate) fn read_body(&mut self) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        while let Event::Body(chunk) = self.next_event()? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Get a mutable reference to the reader.
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        self.reader
+    }
+}
+
+fn unexpected_eof() -> Error {
+    Error::Io(std::io::ErrorKind::UnexpectedEof.into())
+}
+
+/// Parse a request line into its method, target, and version.
+fn parse_request_line(line: &[u8]) -> Result<(Method, String, Version), Error> {
+    let request_line =
+        std::str::from_utf8(line.trim_ascii_end()).map_err(|_| Error::InvalidRequestLine)?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(Error::InvalidRequestLine);
     };
+    let version = Version::parse(parts.next()).ok_or(Error::InvalidRequestLine)?;
+    Ok((Method::from_str(method)?, path.to_string(), version))
+}
 
-    Ok(Response {
-        status,
-        reason,
-        headers,
-        body,
-    })
+/// Parse a status line into its version, status code, and reason phrase.
+fn parse_status_line(line: &[u8]) -> Result<(Version, u16, String), Error> {
+    let status_line =
+        std::str::from_utf8(line.trim_ascii_end()).map_err(|_| Error::InvalidStatusLine)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(Error::InvalidStatusLine);
+    };
+    let version = Version::parse(Some(version)).ok_or(Error::InvalidStatusLine)?;
+    let status: u16 = status.parse().map_err(|_| Error::InvalidStatusLine)?;
+    Ok((version, status, parts.next().unwrap_or("").to_string()))
+}
+
+/// Parse a header line into its name and trimmed value.
+///
+/// Lines without a colon are skipped.
+fn parse_header_line(line: &[u8]) -> Result<Option<(String, String)>, Error> {
+    let trimmed = line.trim_ascii_end();
+    let Some(colon) = trimmed.iter().position(|&b| b == b':') else {
+        return Ok(None);
+    };
+    let name =
+        std::str::from_utf8(trimmed[..colon].trim_ascii()).map_err(|_| Error::InvalidHeader)?;
+    let value =
+        std::str::from_utf8(trimmed[colon + 1..].trim_ascii()).map_err(|_| Error::InvalidHeader)?;
+    Ok(Some((name.to_string(), value.to_string())))
+}
+
+/// The `content-length` of a message, or 0 if it has none.
+///
+/// Repeated values, in separate fields or a comma-separated list, are
+/// accepted only if they all agree: peers that picked different ones would
+/// disagree on where the message ends.
+fn content_length(headers: &Headers) -> Result<usize, Error> {
+    let mut len = None;
+    for value in headers
+        .get_all("content-length")
+        .flat_map(|value| value.split(','))
+    {
+        let value = value
+            .trim_ascii()
+            .parse()
+            .map_err(|_| Error::InvalidContentLength)?;
+        if len.is_some_and(|len| len != value) {
+            return Err(Error::InvalidContentLength);
+        }
+        len = Some(value);
+    }
+    Ok(len.unwrap_or(0))
 }
 
 /// Write an HTTP request to a writer.
 pub fn write_request<W: Write>(writer: &mut W, request: &Request) -> Result<(), Error> {
-    write!(writer, "{} {} HTTP/1.1\r\n", request.method.as_str(), request.path)?;
+    write!(
+        writer,
+        "{} {} HTTP/1.1\r\n",
+        request.method.as_str(),
+        request.path
+    )?;
 
     for (name, value) in &request.headers {
         write!(writer, "{}: {}\r\n", name, value)?;
@@ -236,48 +643,199 @@ pub fn write_request<W: Write>(writer: &mut W, request: &Request) -> Result<(),
 }
 
 /// Write an HTTP response to a writer.
+///
+/// Assumes the response answers a request that may carry a body; use
+/// [`write_response_for`] when the request was `HEAD`.
 pub fn write_response<W: Write>(writer: &mut W, response: &Response) -> Result<(), Error> {
-    write!(writer, "HTTP/1.1 {} {}\r\n", response.status, response.reason)?;
+    write_response_for(writer, response, Method::Get)
+}
+
+/// Write an HTTP response to a request made with `method`.
+///
+/// The body is suppressed for `HEAD` requests and for 1xx, 204, and 304
+/// statuses. For `HEAD` and 304 the `content-length` still reflects the
+/// body that would have been sent; 1xx and 204 responses never carry
+/// `content-length` or `transfer-encoding`. Other empty bodies are framed
+/// with `content-length: 0`, so the connection can carry another response.
+///
+/// Returns an error instead of writing anything if the status code is not
+/// three digits, or if the reason phrase or a header contains characters
+/// that would break message framing.
+pub fn write_response_for<W: Write>(
+    writer: &mut W,
+    response: &Response,
+    method: Method,
+) -> Result<(), Error> {
+    let status = response.status;
+    if !(100..=999).contains(&status) {
+        return Err(Error::InvalidStatus(status));
+    }
+    if response.reason.bytes().any(|b| b == b'\r' || b == b'\n') {
+        return Err(Error::InvalidReason);
+    }
+    for (name, value) in &response.headers {
+        if !is_valid_header(name, value) {
+            return Err(Error::InvalidHeader);
+        }
+    }
+
+    let forbids_length = (100..200).contains(&status) || status == 204;
+    let send_body = method != Method::Head && status_allows_body(status);
+
+    write!(writer, "HTTP/1.1 {} {}\r\n", status, response.reason)?;
 
     for (name, value) in &response.headers {
+        if forbids_length
+            && (name.eq_ignore_ascii_case("content-length")
+                || name.eq_ignore_ascii_case("transfer-encoding"))
+        {
+            continue;
+        }
         write!(writer, "{}: {}\r\n", name, value)?;
     }
 
-    if !response.body.is_empty() && !response.headers.contains_key("content-length") {
+    if !forbids_length
+        && (send_body || !response.body.is_empty())
+        && !response.headers.contains_key("content-length")
+    {
         write!(writer, "content-length: {}\r\n", response.body.len())?;
     }
 
     write!(writer, "\r\n")?;
-    writer.write_all(&response.body)?;
+    if send_body {
+        writer.write_all(&response.body)?;
+    }
     writer.flush()?;
 
     Ok(())
 }
 
+/// Whether a response with this status may carry a body.
+///
+/// Informational (1xx), 204 No Content, and 304 Not Modified responses
+/// never have a body.
+pub fn status_allows_body(status: u16) -> bool {
+    !((100..200).contains(&status) || status == 204 || status == 304)
+}
+
+fn is_valid_header(name: &str, value: &str) -> bool {
+    !name.is_empty()
+        && name.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+        && !value.bytes().any(|b| b == b'\r' || b == b'\n' || b == 0)
+}
+
 /// Get the standard reason phrase for a status code.
+///
+/// Covers every code in the IANA HTTP Status Code Registry, using the
+/// phrases from RFC 9110 where it renamed them. Unregistered codes get
+/// `"Unknown"`.
 pub fn reason_phrase(status: u16) -> &'static str {
     match status {
         100 => "Continue",
         101 => "Switching Protocols",
+        102 => "Processing",
+        103 => "Early Hints",
         200 => "OK",
         201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
         204 => "No Content",
+        205 => "Reset Content",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        208 => "Already Reported",
+        226 => "IM Used",
+        300 => "Multiple Choices",
         301 => "Moved Permanently",
         302 => "Found",
+        303 => "See Other",
         304 => "Not Modified",
+        305 => "Use Proxy",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
         400 => "Bad Request",
         401 => "Unauthorized",
+        402 => "Payment Required",
         403 => "Forbidden",
         404 => "Not Found",
         405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Content",
+        423 => "Locked",
+        424 => "Failed Dependency",
+        425 => "Too Early",
+        426 => "Upgrade Required",
+        428 => "Precondition Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        451 => "Unavailable For Legal Reasons",
         500 => "Internal Server Error",
         501 => "Not Implemented",
         502 => "Bad Gateway",
         503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        506 => "Variant Also Negotiates",
+        507 => "Insufficient Storage",
+        508 => "Loop Detected",
+        510 => "Not Extended",
+        511 => "Network Authentication Required",
         _ => "Unknown",
     }
 }
 
+/// The class of a status code, given by its first digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusClass {
+    /// 1xx: the request was received and processing continues.
+    Informational,
+    /// 2xx: the request was accepted.
+    Success,
+    /// 3xx: further action is needed to complete the request.
+    Redirection,
+    /// 4xx: the request was at fault.
+    ClientError,
+    /// 5xx: the server failed to fulfil a valid request.
+    ServerError,
+}
+
+impl StatusClass {
+    /// Classify a status code, or `None` if it is outside 100–599.
+    pub fn of(status: u16) -> Option<Self> {
+        match status {
+            100..=199 => Some(Self::Informational),
+            200..=299 => Some(Self::Success),
+            300..=399 => Some(Self::Redirection),
+            400..=499 => Some(Self::ClientError),
+            500..=599 => Some(Self::ServerError),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a request that got this status may succeed if sent again
+/// unchanged.
+///
+/// True for 408 Request Timeout, 425 Too Early, 429 Too Many Requests,
+/// 502 Bad Gateway, 503 Service Unavailable, and 504 Gateway Timeout.
+/// Whether retrying is *safe* also depends on the method; that is the
+/// caller's concern.
+pub fn status_is_retryable(status: u16) -> bool {
+    matches!(status, 408 | 425 | 429 | 502 | 503 | 504)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -291,7 +849,58 @@ mod tests {
 
         assert_eq!(req.method, Method::Get);
         assert_eq!(req.path, "/path");
-        assert_eq!(req.headers.get("host"), Some(&"example.com".to_string()));
+        assert_eq!(req.headers.get("host"), Some("example.com"));
+    }
+
+    #[test]
+    fn request_framing() {
+        let parse = |data: &[u8]| parse_request(&mut Cursor::new(data));
+
+        let req = parse(b"POST / HTTP/1.1\r\nContent-Length: 2\r\ncontent-length: 2, 2\r\n\r\nhi");
+        assert_eq!(req.unwrap().body, b"hi");
+        for data in [
+            &b"POST / HTTP/1.1\r\nContent-Length: 2\r\ncontent-length: 3\r\n\r\nhi!"[..],
+            b"POST / HTTP/1.1\r\nContent-Length: 2, 3\r\n\r\nhi!",
+            b"POST / HTTP/1.1\r\nContent-Length: 2,\r\n\r\nhi",
+        ] {
+            assert!(matches!(parse(data), Err(Error::InvalidContentLength)));
+        }
+
+        let chunked = parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n");
+        assert!(matches!(
+            chunked,
+            Err(Error::UnsupportedTransferEncoding(coding)) if coding == "chunked"
+        ));
+        let err = Error::UnsupportedTransferEncoding("chunked".to_string());
+        assert_eq!(err.status(), 501);
+    }
+
+    #[test]
+    fn parse_within_limits() {
+        let limits = Limits::default()
+            .max_line(20)
+            .max_headers(1)
+            .max_header_bytes(32)
+            .max_body(3);
+        let parse = |data: &[u8]| parse_request_with_limits(&mut Cursor::new(data), &limits);
+
+        let req = parse(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabc");
+        assert!(matches!(req, Err(Error::TooManyHeaders)));
+        let req = parse(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc").unwrap();
+        assert_eq!(req.body, b"abc");
+
+        let long_line = parse(b"GET /aaaaaaaaaaaaaaaaaaaa HTTP/1.1\r\n\r\n");
+        assert!(matches!(long_line, Err(Error::LineTooLong)));
+        let long_header = parse(b"GET / HTTP/1.1\r\nX-Long: aaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n");
+        assert!(matches!(long_header, Err(Error::HeadersTooLarge)));
+        let big_body = parse(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nabcd");
+        assert!(matches!(big_body, Err(Error::BodyTooLarge)));
+
+        let data = b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nabcd";
+        let res = parse_response_with_limits(&mut Cursor::new(data), Method::Get, &limits);
+        assert!(matches!(res, Err(Error::BodyTooLarge)));
+        let res = parse_response_with_limits(&mut Cursor::new(data), Method::Head, &limits);
+        assert!(res.unwrap().body.is_empty());
     }
 
     #[test]
@@ -304,6 +913,19 @@ mod tests {
         assert_eq!(req.body, b"hello");
     }
 
+    #[test]
+    fn pipelined_requests_stay_in_reader() {
+        let data = b"POST /a HTTP/1.1\r\ncontent-length: 2\r\n\r\nhiGET /b HTTP/1.1\r\n\r\n";
+        let mut reader = std::io::BufReader::with_capacity(7, data.as_slice());
+
+        let first = parse_request(&mut reader).unwrap();
+        assert_eq!(first.path, "/a");
+        assert_eq!(first.body, b"hi");
+        let second = parse_request(&mut reader).unwrap();
+        assert_eq!(second.path, "/b");
+        assert!(matches!(parse_request(&mut reader), Err(Error::Io(_))));
+    }
+
     #[test]
     fn parse_simple_response() {
         let data = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
@@ -314,12 +936,24 @@ mod tests {
         assert_eq!(res.body, b"hi");
     }
 
+    #[test]
+    fn response_framing() {
+        let data = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\nrest";
+        let mut cursor = Cursor::new(data.as_slice());
+        assert_eq!(parse_response(&mut cursor).unwrap().body, b"hi");
+        assert_eq!(&data[cursor.position() as usize..], b"rest");
+
+        let data = b"HTTP/1.0 200 OK\r\n\r\nuntil close";
+        let res = parse_response(&mut Cursor::new(data.as_slice())).unwrap();
+        assert_eq!(res.body, b"until close");
+    }
+
     #[test]
     fn roundtrip_request() {
         let req = Request {
             method: Method::Post,
             path: "/api".to_string(),
-            headers: HashMap::from([("host".to_string(), "localhost".to_string())]),
+            headers: Headers::from([("Host", "localhost")]),
             body: b"data".to_vec(),
         };
 
@@ -349,4 +983,111 @@ mod tests {
         assert_eq!(parsed.status, 201);
         assert_eq!(parsed.body, b"created");
     }
+
+    #[test]
+    fn repeated_headers_keep_case_and_order() {
+        let res = Response::new(200)
+            .header("X-Trace", "1")
+            .append_header("Set-Cookie", "a=1")
+            .append_header("Set-Cookie", "b=2");
+
+        let mut buf = Vec::new();
+        write_response(&mut buf, &res).unwrap();
+        assert_eq!(
+            buf,
+            b"HTTP/1.1 200 OK\r\nX-Trace: 1\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\ncontent-length: 0\r\n\r\n"
+        );
+
+        let mut cursor = Cursor::new(buf.as_slice());
+        let parsed = parse_response(&mut cursor).unwrap();
+        let mut expected = res.headers.clone();
+        expected.append("content-length", "0");
+        assert_eq!(parsed.headers, expected);
+        assert_eq!(
+            parsed.headers.get_all("set-cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+    }
+
+    #[test]
+    fn head_response_keeps_length_without_body() {
+        let res = Response::new(200).body(b"hello".to_vec());
+
+        let mut buf = Vec::new();
+        write_response_for(&mut buf, &res, Method::Head).unwrap();
+        assert_eq!(buf, b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n");
+
+        let mut cursor = Cursor::new(buf.as_slice());
+        let parsed = parse_response_for(&mut cursor, Method::Head).unwrap();
+        assert_eq!(parsed.headers.get("content-length"), Some("5"));
+        assert!(parsed.body.is_empty());
+    }
+
+    #[test]
+    fn bodiless_statuses() {
+        let res = Response::new(204)
+            .header("content-length", "3")
+            .body(b"abc".to_vec());
+        let mut buf = Vec::new();
+        write_response(&mut buf, &res).unwrap();
+        assert_eq!(buf, b"HTTP/1.1 204 No Content\r\n\r\n");
+
+        let res = Response::new(304).body(b"abc".to_vec());
+        let mut buf = Vec::new();
+        write_response(&mut buf, &res).unwrap();
+        assert_eq!(
+            buf,
+            b"HTTP/1.1 304 Not Modified\r\ncontent-length: 3\r\n\r\n"
+        );
+
+        let mut cursor = Cursor::new(buf.as_slice());
+        let parsed = parse_response(&mut cursor).unwrap();
+        assert!(parsed.body.is_empty());
+    }
+
+    #[test]
+    fn status_classes() {
+        assert_eq!(reason_phrase(422), "Unprocessable Content");
+        assert_eq!(reason_phrase(511), "Network Authentication Required");
+        assert_eq!(reason_phrase(418), "Unknown");
+
+        assert_eq!(StatusClass::of(103), Some(StatusClass::Informational));
+        assert_eq!(StatusClass::of(308), Some(StatusClass::Redirection));
+        assert_eq!(StatusClass::of(599), Some(StatusClass::ServerError));
+        assert_eq!(StatusClass::of(99), None);
+        assert_eq!(StatusClass::of(600), None);
+
+        assert!(Response::new(204).is_success());
+        assert!(!Response::new(301).is_success());
+        assert!(Response::new(429).is_retryable());
+        assert!(Response::new(503).is_retryable());
+        assert!(!Response::new(500).is_retryable());
+        assert!(!Response::new(404).is_retryable());
+    }
+
+    #[test]
+    fn rejects_invalid_responses() {
+        let mut buf = Vec::new();
+
+        let res = Response::new(42);
+        assert!(matches!(
+            write_response(&mut buf, &res),
+            Err(Error::InvalidStatus(42))
+        ));
+
+        let mut res = Response::new(200);
+        res.reason = "OK\r\nx-injected: 1".to_string();
+        assert!(matches!(
+            write_response(&mut buf, &res),
+            Err(Error::InvalidReason)
+        ));
+
+        let res = Response::new(200).header("x-split", "a\r\nb");
+        assert!(matches!(
+            write_response(&mut buf, &res),
+            Err(Error::InvalidHeader)
+        ));
+
+        assert!(buf.is_empty());
+    }
 }
@@ -0,0 +1,688 @@
+//! Incremental HTTP/1.1 parser.
+//!
+//! [`Http1Parser`] does no I/O of its own: the caller pushes bytes as they
+//! arrive from wherever they come from (a blocking socket, an async socket,
+//! a completion queue, a WASM host) and pulls out [`Event`]s as soon as
+//! there is enough input for one.
+
+use crate::{
+    Error, Headers, Limits, Method, Version, parse_header_line, parse_request_line,
+    parse_status_line, status_allows_body, unexpected_eof,
+};
+
+/// A parsed request line and headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHead {
+    pub method: Method,
+    pub path: String,
+    pub version: Version,
+    pub headers: Headers,
+}
+
+/// A parsed status line and headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseHead {
+    pub version: Version,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+}
+
+/// Something the parser found in its input.
+///
+/// Each message produces a head, zero or more body chunks, and `End`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A request head (request parsers only).
+    Request(RequestHead),
+    /// A response head (response parsers only).
+    Response(ResponseHead),
+    /// Part of the message body, in order.
+    Body(Vec<u8>),
+    /// The message is complete; the next event starts a new message.
+    End,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Request,
+    Response(Method),
+}
+
+#[derive(Debug)]
+enum StartLine {
+    Request(Method, String, Version),
+    Response(Version, u16, String),
+}
+
+#[derive(Debug)]
+enum State {
+    StartLine,
+    Headers(StartLine),
+    /// A body of known length, with this many bytes left.
+    Body(usize),
+    /// A chunked body, before the next chunk-size line.
+    ChunkSize,
+    /// Inside a chunk, with this many bytes left.
+    Chunk(usize),
+    /// The line break that ends a chunk's data.
+    ChunkEnd,
+    /// Trailer fields after the last chunk.
+    Trailers,
+    /// A body that runs until the input ends.
+    UntilClose,
+    Failed,
+}
+
+/// A push-based HTTP/1.1 parser.
+///
+/// Feed it bytes with [`feed`](Self::feed) and it returns the next event
+/// once one is complete, or `None` if it needs more input. One call yields
+/// at most one event, so keep calling [`next_event`](Self::next_event)
+/// until it returns `None` before reading more. Bytes past the end of a
+/// message are kept for the next one, so pipelined messages parse in turn.
+///
+/// Request bodies are framed by `content-length`. Response bodies are
+/// framed as RFC 9112 §6.3 describes: responses to `HEAD` and with 1xx,
+/// 204, or 304 status have none, a `transfer-encoding` ending in `chunked`
+/// is decoded, and otherwise `content-length` gives the length or, without
+/// one, the body runs until the input ends, which the caller signals with
+/// [`finish`](Self::finish). Messages are held to the default [`Limits`]
+/// unless [`with_limits`](Self::with_limits) says otherwise. A line that
+/// runs past its limit fails as soon as enough of it is buffered, without
+/// waiting for its end. After an error the input can no longer be framed;
+/// the parser returns no further events and the connection should be
+/// closed. The blocking [`parse_request`] and [`parse_response`] run this
+/// parser over a `BufRead`.
+///
+/// ```ignore
+/// let mut parser = Http1Parser::request();
+/// let mut buf = [0; 4096];
+/// loop {
+///     let n = socket.read(&mut buf).await?;
+///     let mut event = parser.feed(&buf[..n])?;
+///     while let Some(e) = event {
+///         match e {
+///             Event::Request(head) => start(head),
+///             Event::Body(chunk) => body.extend(chunk),
+///             Event::End => respond().await?,
+///             Event::Response(_) => unreachable!(),
+///         }
+///         event = parser.next_event()?;
+///     }
+/// }
+/// ```
+///
+/// [`parse_request`]: crate::parse_request
+/// [`parse_response`]: crate::parse_response
+#[derive(Debug)]
+pub struct Http1Parser {
+    kind: Kind,
+    state: State,
+    buf: Vec<u8>,
+    pos: usize,
+    headers: Headers,
+    header_bytes: usize,
+    /// Body bytes of a chunked or close-delimited body so far.
+    body_bytes: usize,
+    limits: Limits,
+}
+
+impl Http1Parser {
+    /// Create a parser for requests, as read by a server.
+    pub fn request() -> Self {
+        Self::new(Kind::Request)
+    }
+
+    /// Create a parser for responses, as read by a client.
+    ///
+    /// Assumes the responses answer requests that may carry a body; use
+    /// [`response_for`](Self::response_for) when the request was `HEAD`.
+    pub fn response() -> Self {
+        Self::response_for(Method::Get)
+    }
+
+    /// Create a parser for responses to requests made with `method`.
+    ///
+    /// Responses to `HEAD` and responses with 1xx, 204, or 304 status never
+    /// have a body, even if they carry a `content-length` header.
+    pub fn response_for(method: Method) -> Self {
+        Self::new(Kind::Response(method))
+    }
+
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            state: State::StartLine,
+            buf: Vec::new(),
+            pos: 0,
+            headers: Headers::new(),
+            header_bytes: 0,
+            body_bytes: 0,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Hold messages to `limits` instead of the defaults.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Change the limits for messages not yet started.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Set the method of the request the next response answers.
+    ///
+    /// Has no effect on request parsers.
+    pub fn set_request_method(&mut self, method: Method) {
+        if let Kind::Response(current) = &mut self.kind {
+            *current = method;
+        }
+    }
+
+    /// Add input and return the next event, if there is one yet.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Option<Event>, Error> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        self.buf.extend_from_slice(data);
+        self.next_event()
+    }
+
+    /// Return the next event from input already fed, if there is one.
+    pub fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        let result = self.parse();
+        if result.is_err() {
+            self.state = State::Failed;
+        }
+        result
+    }
+
+    /// Input fed but not yet returned in an event.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Signal that the input has ended, once [`next_event`](Self::next_event)
+    /// returns `None`.
+    ///
+    /// Returns `End` for a body that runs until the input ends, and `None`
+    /// if the parser is between messages. Input ending anywhere else cuts
+    /// the message short, which is an `UnexpectedEof` error.
+    pub fn finish(&mut self) -> Result<Option<Event>, Error> {
+        match self.state {
+            State::UntilClose => {
+                self.state = State::StartLine;
+                Ok(Some(Event::End))
+            }
+            _ if self.is_idle() => Ok(None),
+            _ => {
+                self.state = State::Failed;
+                Err(unexpected_eof())
+            }
+        }
+    }
+
+    /// Whether the parser is between messages with no partial input.
+    ///
+    /// If the connection closes while this is false, the last message was
+    /// cut short.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, State::StartLine) && self.buffered().is_empty()
+    }
+
+    fn parse(&mut self) -> Result<Option<Event>, Error> {
+        loop {
+            match std::mem::replace(&mut self.state, State::Failed) {
+                State::StartLine => {
+                    let max = self.limits.max_line;
+                    let Some(line) = self.take_line(max, Error::LineTooLong)? else {
+                        self.state = State::StartLine;
+                        return Ok(None);
+                    };
+                    let line = &self.buf[line];
+                    // Stray blank lines between messages are ignored.
+                    if line.trim_ascii_end().is_empty() {
+                        self.state = State::StartLine;
+                        continue;
+                    }
+                    let start = match self.kind {
+                        Kind::Request => {
+                            let (method, path, version) = parse_request_line(line)?;
+                            StartLine::Request(method, path, version)
+                        }
+                        Kind::Response(_) => {
+                            let (version, status, reason) = parse_status_line(line)?;
+                            StartLine::Response(version, status, reason)
+                        }
+                    };
+                    self.state = State::Headers(start);
+                }
+                State::Headers(start) => {
+                    let max = self.limits.max_header_bytes - self.header_bytes;
+                    let Some(line) = self.take_line(max, Error::HeadersTooLarge)? else {
+                        self.state = State::Headers(start);
+                        return Ok(None);
+                    };
+                    self.header_bytes += line.len();
+                    let line = &self.buf[line];
+                    if !line.trim_ascii_end().is_empty() {
+                        if let Some((name, value)) = parse_header_line(line)? {
+                            if self.headers.len() == self.limits.max_headers {
+                                return Err(Error::TooManyHeaders);
+                            }
+                            self.headers.append(name, value);
+                        }
+                        self.state = State::Headers(start);
+                        continue;
+                    }
+                    return self.finish_head(start).map(Some);
+                }
+                State::Body(0) => {
+                    self.state = State::StartLine;
+                    return Ok(Some(Event::End));
+                }
+                State::Body(remaining) => {
+                    let Some(chunk) = self.take_body(remaining) else {
+                        self.state = State::Body(remaining);
+                        return Ok(None);
+                    };
+                    self.state = State::Body(remaining - chunk.len());
+                    return Ok(Some(Event::Body(chunk)));
+                }
+                State::ChunkSize => {
+                    let max = self.limits.max_line;
+                    let Some(line) = self.take_line(max, Error::LineTooLong)? else {
+                        self.state = State::ChunkSize;
+                        return Ok(None);
+                    };
+                    let size = parse_chunk_size(&self.buf[line])?;
+                    if size == 0 {
+                        self.state = State::Trailers;
+                        continue;
+                    }
+                    self.body_bytes = self
+                        .body_bytes
+                        .checked_add(size)
+                        .filter(|&len| len <= self.limits.max_body)
+                        .ok_or(Error::BodyTooLarge)?;
+                    self.state = State::Chunk(size);
+                }
+                State::Chunk(0) => self.state = State::ChunkEnd,
+                State::Chunk(remaining) => {
+                    let Some(chunk) = self.take_body(remaining) else {
+                        self.state = State::Chunk(remaining);
+                        return Ok(None);
+                    };
+                    self.state = State::Chunk(remaining - chunk.len());
+                    return Ok(Some(Event::Body(chunk)));
+                }
+                State::ChunkEnd => {
+                    let Some(line) = self.take_line(2, Error::InvalidChunk)? else {
+                        self.state = State::ChunkEnd;
+                        return Ok(None);
+                    };
+                    if !self.buf[line].trim_ascii().is_empty() {
+                        return Err(Error::InvalidChunk);
+                    }
+                    self.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    // Trailer fields are read against the header limits
+                    // and dropped.
+                    let max = self.limits.max_header_bytes - self.header_bytes;
+                    let Some(line) = self.take_line(max, Error::HeadersTooLarge)? else {
+                        self.state = State::Trailers;
+                        return Ok(None);
+                    };
+                    self.header_bytes += line.len();
+                    if !self.buf[line].trim_ascii_end().is_empty() {
+                        self.state = State::Trailers;
+                        continue;
+                    }
+                    self.header_bytes = 0;
+                    self.body_bytes = 0;
+                    self.state = State::StartLine;
+                    return Ok(Some(Event::End));
+                }
+                State::UntilClose => {
+                    let Some(chunk) = self.take_body(usize::MAX) else {
+                        self.state = State::UntilClose;
+                        return Ok(None);
+                    };
+                    self.body_bytes = self
+                        .body_bytes
+                        .checked_add(chunk.len())
+                        .filter(|&len| len <= self.limits.max_body)
+                        .ok_or(Error::BodyTooLarge)?;
+                    self.state = State::UntilClose;
+                    return Ok(Some(Event::Body(chunk)));
+                }
+                State::Failed => return Ok(None),
+            }
+        }
+    }
+
+    fn finish_head(&mut self, start: StartLine) -> Result<Event, Error> {
+        let headers = std::mem::take(&mut self.headers);
+        self.header_bytes = 0;
+        let (body, event) = match start {
+            StartLine::Request(method, path, version) => (
+                State::Body(self.limits.request_body_length(&headers)?),
+                Event::Request(RequestHead {
+                    method,
+                    path,
+                    version,
+                    headers,
+                }),
+            ),
+            StartLine::Response(version, status, reason) => {
+                let has_body = !matches!(self.kind, Kind::Response(Method::Head))
+                    && status_allows_body(status);
+                let body = if has_body {
+                    self.response_body(&headers)?
+                } else {
+                    State::Body(0)
+                };
+                let head = ResponseHead {
+                    version,
+                    status,
+                    reason,
+                    headers,
+                };
+                (body, Event::Response(head))
+            }
+        };
+        self.body_bytes = 0;
+        self.state = body;
+        Ok(event)
+    }
+
+    /// How the body of a response with `headers` is framed.
+    ///
+    /// A `transfer-encoding` overrides any `content-length`; if its last
+    /// coding is not `chunked`, only the end of the input ends the body.
+    /// Codings before `chunked` are left in the body for the caller.
+    fn response_body(&self, headers: &Headers) -> Result<State, Error> {
+        if headers.contains_key("transfer-encoding") {
+            let last = headers
+                .get_all("transfer-encoding")
+                .flat_map(|value| value.split(','))
+                .map(str::trim_ascii)
+                .filter(|coding| !coding.is_empty())
+                .last();
+            return Ok(match last {
+                Some(coding) if coding.eq_ignore_ascii_case("chunked") => State::ChunkSize,
+                _ => State::UntilClose,
+            });
+        }
+        if headers.contains_key("content-length") {
+            return Ok(State::Body(self.limits.body_length(headers)?));
+        }
+        Ok(State::UntilClose)
+    }
+
+    /// Take up to `max` buffered body bytes, or `None` if there are none.
+    fn take_body(&mut self, max: usize) -> Option<Vec<u8>> {
+        let available = self.buf.len() - self.pos;
+        if available == 0 {
+            return None;
+        }
+        let n = max.min(available);
+        let chunk = self.buf[self.pos..self.pos + n].to_vec();
+        self.pos += n;
+        Some(chunk)
+    }
+
+    /// Take the next complete line from the buffer, including its `\n`,
+    /// failing with `too_long` once the line is known to exceed `max`
+    /// bytes.
+    fn take_line(
+        &mut self,
+        max: usize,
+        too_long: Error,
+    ) -> Result<Option<std::ops::Range<usize>>, Error> {
+        let start = self.pos;
+        let window = &self.buf[start..];
+        let window = &window[..window.len().min(max.saturating_add(1))];
+        match window.iter().position(|&b| b == b'\n') {
+            Some(i) if i < max => {
+                self.pos = start + i + 1;
+                Ok(Some(start..self.pos))
+            }
+            None if window.len() < max => Ok(None),
+            _ => Err(too_long),
+        }
+    }
+}
+
+/// Parse a chunk-size line, ignoring any chunk extensions.
+fn parse_chunk_size(line: &[u8]) -> Result<usize, Error> {
+    let line = line.trim_ascii_end();
+    let size = match line.iter().position(|&b| b == b';') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let size = std::str::from_utf8(size.trim_ascii()).map_err(|_| Error::InvalidChunk)?;
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::InvalidChunk);
+    }
+    usize::from_str_radix(size, 16).map_err(|_| Error::BodyTooLarge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `data` in pieces of `step` bytes and collect every event.
+    fn collect_events(parser: &mut Http1Parser, data: &[u8], step: usize) -> Vec<Event> {
+        let mut events = Vec::new();
+        for piece in data.chunks(step) {
+            let mut event = parser.feed(piece).unwrap();
+            while let Some(e) = event {
+                events.push(e);
+                event = parser.next_event().unwrap();
+            }
+        }
+        events
+    }
+
+    fn body(events: &[Event]) -> Vec<u8> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Body(chunk) => Some(chunk.as_slice()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .concat()
+    }
+
+    #[test]
+    fn parses_request_in_any_split() {
+        let data = b"POST /submit HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello";
+        for step in [1, 2, 7, data.len()] {
+            let mut parser = Http1Parser::request();
+            let events = collect_events(&mut parser, data, step);
+            let Event::Request(head) = &events[0] else {
+                panic!("expected request head, got {:?}", events[0]);
+            };
+            assert_eq!(head.method, Method::Post);
+            assert_eq!(head.path, "/submit");
+            assert_eq!(head.version, Version::Http11);
+            assert_eq!(head.headers.get("host"), Some("example.com"));
+            assert_eq!(body(&events), b"hello");
+            assert_eq!(events.last(), Some(&Event::End));
+            assert!(parser.is_idle());
+        }
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let data =
+            b"GET /a HTTP/1.1\r\n\r\n\r\nPUT /b HTTP/1.1\r\ncontent-length: 2\r\n\r\nhiGET /c";
+        let mut parser = Http1Parser::request();
+        let events = collect_events(&mut parser, data, data.len());
+        let paths: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Request(head) => Some(head.path.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(paths, ["/a", "/b"]);
+        assert_eq!(events.iter().filter(|e| **e == Event::End).count(), 2);
+        assert_eq!(body(&events), b"hi");
+        assert_eq!(parser.buffered(), b"GET /c");
+        assert!(!parser.is_idle());
+    }
+
+    #[test]
+    fn responses_without_bodies() {
+        let data = b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n";
+        let mut parser = Http1Parser::response_for(Method::Head);
+        let events = collect_events(&mut parser, data, 3);
+        let Event::Response(head) = &events[0] else {
+            panic!("expected response head, got {:?}", events[0]);
+        };
+        assert_eq!(head.version, Version::Http11);
+        assert_eq!(head.status, 200);
+        assert_eq!(head.reason, "OK");
+        assert_eq!(events[1], Event::End);
+
+        let data =
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\ncontent-length: 2\r\n\r\nok";
+        let mut parser = Http1Parser::response();
+        let events = collect_events(&mut parser, data, 5);
+        let statuses: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Response(head) => Some(head.status),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(statuses, [100, 201]);
+        assert_eq!(body(&events), b"ok");
+    }
+
+    #[test]
+    fn chunked_responses() {
+        let data = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\ncontent-length: 1\r\n\r\n\
+            5;name=value\r\nhello\r\n7\r\n, world\r\n0\r\nx-checksum: 1\r\n\r\n\
+            HTTP/1.1 204 No Content\r\n\r\n";
+        for step in [1, 3, data.len()] {
+            let mut parser = Http1Parser::response();
+            let events = collect_events(&mut parser, data, step);
+            assert!(matches!(&events[0], Event::Response(head) if head.status == 200));
+            assert_eq!(body(&events), b"hello, world");
+            assert_eq!(events.iter().filter(|e| **e == Event::End).count(), 2);
+            assert!(parser.is_idle());
+        }
+
+        let mut parser = Http1Parser::response();
+        assert!(matches!(
+            parser.feed(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\nzz\r\n"),
+            Ok(Some(Event::Response(_)))
+        ));
+        assert!(matches!(parser.next_event(), Err(Error::InvalidChunk)));
+
+        let mut parser = Http1Parser::response().with_limits(Limits::default().max_body(8));
+        parser
+            .feed(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n")
+            .unwrap();
+        assert!(matches!(
+            parser.feed(b"5\r\nhello\r\n5\r\n"),
+            Ok(Some(Event::Body(_)))
+        ));
+        assert!(matches!(parser.next_event(), Err(Error::BodyTooLarge)));
+    }
+
+    #[test]
+    fn close_delimited_responses() {
+        for data in [
+            &b"HTTP/1.1 200 OK\r\n\r\nhello"[..],
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: gzip\r\ncontent-length: 1\r\n\r\nhello",
+        ] {
+            let mut parser = Http1Parser::response();
+            let events = collect_events(&mut parser, data, 4);
+            assert_eq!(body(&events), b"hello");
+            assert_ne!(events.last(), Some(&Event::End));
+            assert_eq!(parser.finish().unwrap(), Some(Event::End));
+            assert!(parser.is_idle());
+        }
+
+        // Input ending inside any other body cuts the message short.
+        let mut parser = Http1Parser::response();
+        parser
+            .feed(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhel")
+            .unwrap();
+        while parser.next_event().unwrap().is_some() {}
+        assert!(matches!(parser.finish(), Err(Error::Io(_))));
+        assert_eq!(Http1Parser::response().finish().unwrap(), None);
+    }
+
+    #[test]
+    fn errors_stop_parsing() {
+        let mut parser = Http1Parser::request();
+        assert!(matches!(
+            parser.feed(b"BREW /pot HTTP/1.1\r\n"),
+            Err(Error::InvalidMethod)
+        ));
+        assert!(parser.feed(b"GET / HTTP/1.1\r\n\r\n").unwrap().is_none());
+
+        let mut parser = Http1Parser::request();
+        assert!(matches!(
+            parser.feed(b"GET / HTTP/1.1\r\ncontent-length: lots\r\n\r\n"),
+            Err(Error::InvalidContentLength)
+        ));
+
+        let mut parser = Http1Parser::request();
+        assert!(matches!(
+            parser.feed(b"GET / HTTP/2.0\r\n\r\n"),
+            Err(Error::InvalidRequestLine)
+        ));
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let limits = Limits::default()
+            .max_line(32)
+            .max_headers(2)
+            .max_header_bytes(64)
+            .max_body(4);
+        let parser = || Http1Parser::request().with_limits(limits);
+
+        // An overlong line fails before its end arrives.
+        let mut long = parser();
+        assert!(long.feed(b"GET /aaaaaaaaaaaaaaaa").unwrap().is_none());
+        assert!(matches!(
+            long.feed(b"aaaaaaaaaaaa"),
+            Err(Error::LineTooLong)
+        ));
+
+        let mut many = parser();
+        assert!(matches!(
+            many.feed(b"GET / HTTP/1.1\r\na: 1\r\nb: 2\r\nc: 3\r\n\r\n"),
+            Err(Error::TooManyHeaders)
+        ));
+
+        let mut large = parser();
+        let mut input = b"GET / HTTP/1.1\r\n".to_vec();
+        input.extend_from_slice(&[b'x'; 70]);
+        assert!(matches!(large.feed(&input), Err(Error::HeadersTooLarge)));
+
+        let mut big = parser();
+        assert!(matches!(
+            big.feed(b"POST / HTTP/1.1\r\ncontent-length: 5\r\n\r\n"),
+            Err(Error::BodyTooLarge)
+        ));
+
+        // Header bytes are counted per message.
+        let mut ok = parser();
+        let message = b"POST / HTTP/1.1\r\nhost: a\r\ncontent-length: 4\r\n\r\nbody";
+        let events = collect_events(&mut ok, &message.repeat(3), 7);
+        assert_eq!(events.iter().filter(|e| **e == Event::End).count(), 3);
+    }
+}
@@ -0,0 +1,125 @@
+//! Request ID middleware.
+//!
+//! Tags every request with a correlation ID so logs, spans, and downstream
+//! calls for one request can be tied together.
+
+use portals_http::{Error, HttpClient, Middleware, Request, Response};
+use portals_observe::REQUEST_ID;
+
+/// Middleware that assigns each request an ID.
+///
+/// If the incoming request already carries the header (e.g. set by a proxy
+/// or an upstream service), that ID is kept; otherwise one is generated.
+/// The ID is visible to downstream handlers in the request headers and is
+/// echoed back on the response.
+///
+/// On a client, it tags outgoing requests the same way, so a service can
+/// pass its own request's ID on to the services it calls.
+///
+/// The generator is any `Fn() -> String`, so IDs can come from a snowflake
+/// generator, a UUID source, or anything else:
+///
+/// ```ignore
+/// let ids = SnowflakeGenerator::new(1)?;
+/// let router = Router::new()
+///     .get("/", |req: &Request, _: &Params| {
+///         let id = req.headers.get(REQUEST_ID).unwrap();
+///         let logger = ContextLogger::new(&logger).with_field("request_id", id);
+///         span.set_attribute(REQUEST_ID_ATTRIBUTE, id);
+///         // ...
+///     })
+///     .middleware(RequestId::new(move || ids.next_id().unwrap().to_string()));
+/// ```
+pub struct RequestId<G> {
+    generate: G,
+    header: String,
+}
+
+impl<G> RequestId<G>
+where
+    G: Fn() -> String,
+{
+    /// Create the middleware with an ID generator.
+    pub fn new(generate: G) -> Self {
+        Self {
+            generate,
+            header: REQUEST_ID.to_string(),
+        }
+    }
+
+    /// Use a different header name. Names match case-insensitively.
+    pub fn header(mut self, name: impl Into<String>) -> Self {
+        self.header = name.into();
+        self
+    }
+}
+
+impl<G> Middleware for RequestId<G>
+where
+    G: Fn() -> String,
+{
+    async fn send<C: HttpClient>(&self, mut request: Request, next: &C) -> Result<Response, Error> {
+        let id = match request.headers.get(&self.header) {
+            Some(id) => id.to_string(),
+            None => {
+                let id = (self.generate)();
+                request.headers.insert(self.header.clone(), id.clone());
+                id
+            }
+        };
+        let mut response = next.send(request).await?;
+        response.headers.insert(self.header.clone(), id);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Headers, Params, Router};
+    use portals_http::{HttpHandler, Method};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn router() -> Router<impl Middleware> {
+        let counter = AtomicU64::new(0);
+        Router::new()
+            .get("/", |req: &crate::Request, _params: &Params| {
+                let seen = req.headers.get(REQUEST_ID).map(str::to_string);
+                crate::Response::new(200).body(seen.unwrap_or_default())
+            })
+            .middleware(RequestId::new(move || {
+                format!("req-{}", counter.fetch_add(1, Ordering::Relaxed))
+            }))
+    }
+
+    fn get(headers: Headers) -> Request {
+        Request {
+            method: Method::Get,
+            url: "/".to_string(),
+            headers,
+            body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn generates_ids() {
+        let router = router();
+
+        let res = router.handle(get(Headers::new())).await;
+        assert_eq!(res.body, b"req-0");
+        assert_eq!(res.headers.get(REQUEST_ID), Some("req-0"));
+
+        let res = router.handle(get(Headers::new())).await;
+        assert_eq!(res.body, b"req-1");
+    }
+
+    #[tokio::test]
+    async fn keeps_incoming_id() {
+        let router = router();
+        let headers = Headers::from([("X-Request-Id", "upstream")]);
+
+        let res = router.handle(get(headers)).await;
+        assert_eq!(res.body, b"upstream");
+        assert_eq!(res.headers.get(REQUEST_ID), Some("upstream"));
+    }
+}
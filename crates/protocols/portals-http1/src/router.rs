@@ -0,0 +1,513 @@
+//! Request routing.
+//!
+//! Matches requests by method and path pattern, extracts path parameters,
+//! and runs a middleware chain around the matched handler. A [`Router`] is
+//! an [`HttpHandler`], so any server can answer requests with it, and its
+//! middleware is the same [`Middleware`] that wraps HTTP clients.
+//!
+//! ```ignore
+//! let router = Router::new()
+//!     .get("/users/:id", |_req: &Request, params: &Params| {
+//!         Response::new(200).body(params.get("id").unwrap_or_default())
+//!     })
+//!     .get("/static/*path", serve_static)
+//!     .middleware(RequestId::new(next_id));
+//!
+//! server.serve(&router).await?;
+//! ```
+
+use crate::{Method, Request, Response};
+use portals_http::{Chain, HttpClient, HttpHandler, Identity, Middleware};
+
+/// Something that turns a request into a response.
+pub trait Handler {
+    /// Handle a request.
+    fn handle(&self, request: &Request) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request) -> Response,
+{
+    fn handle(&self, request: &Request) -> Response {
+        self(request)
+    }
+}
+
+/// A handler for a matched route.
+///
+/// Receives the request and the parameters captured from the path.
+pub trait RouteHandler {
+    /// Handle a request that matched this route.
+    fn handle(&self, request: &Request, params: &Params) -> Response;
+}
+
+impl<F> RouteHandler for F
+where
+    F: Fn(&Request, &Params) -> Response,
+{
+    fn handle(&self, request: &Request, params: &Params) -> Response {
+        self(request, params)
+    }
+}
+
+/// Parameters captured from a matched path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    /// Get a parameter by name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterate over all parameters in path order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Number of captured parameters.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether no parameters were captured.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// A segment of a route pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// Must match exactly.
+    Literal(String),
+    /// `:name` - matches one segment.
+    Param(String),
+    /// `*` or `*name` - matches the rest of the path.
+    Wildcard(String),
+}
+
+/// A parsed route pattern such as `/users/:id/files/*path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// Parse a route pattern.
+    ///
+    /// Panics if a wildcard is not the final segment, since that is a
+    /// programmer error in the route table.
+    fn parse(pattern: &str) -> Self {
+        let parts: Vec<&str> = split_path(pattern).collect();
+        let mut segments = Vec::with_capacity(parts.len());
+
+        for (i, part) in parts.iter().enumerate() {
+            let segment = if let Some(name) = part.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                assert!(
+                    i == parts.len() - 1,
+                    "wildcard must be the last segment in route pattern '{}'",
+                    pattern
+                );
+                let name = if name.is_empty() { "*" } else { name };
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            };
+            segments.push(segment);
+        }
+
+        Self { segments }
+    }
+
+    /// Match decoded path segments, returning captured parameters.
+    fn matches(&self, path: &[String]) -> Option<Params> {
+        let mut parts = path.iter();
+        let mut params = Params::default();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let value = parts.next()?;
+                    params.values.push((name.clone(), value.clone()));
+                }
+                Segment::Wildcard(name) => {
+                    let rest: Vec<&str> = parts.by_ref().map(String::as_str).collect();
+                    params.values.push((name.clone(), rest.join("/")));
+                }
+            }
+        }
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(params)
+    }
+}
+
+/// Split a route pattern into non-empty segments.
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+struct Route {
+    method: Method,
+    pattern: Pattern,
+    handler: Box<dyn RouteHandler + Send + Sync>,
+}
+
+/// A request router.
+///
+/// Routes are matched in registration order; the first route whose method
+/// and pattern both match handles the request. If the path matches but no
+/// route accepts the method, the router responds `405 Method Not Allowed`
+/// with an `allow` header. If nothing matches, it responds `404 Not Found`
+/// (or calls the handler set with [`Router::not_found`]).
+///
+/// Paths are matched segment by segment after percent-decoding, so
+/// `/users/j%C3%B6rg` matches `/users/:id` with `id` set to `jörg`, and an
+/// encoded `%2F` is captured as a `/` within one segment. Requests whose
+/// target doesn't parse (see [`Target`](crate::Target)) are answered with
+/// `400 Bad Request`.
+///
+/// `HEAD` requests fall back to the `GET` route for the same path.
+pub struct Router<M = Identity> {
+    routes: Vec<Route>,
+    middleware: M,
+    not_found: Option<Box<dyn Handler + Send + Sync>>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Router {
+    /// Create an empty router.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            middleware: Identity,
+            not_found: None,
+        }
+    }
+}
+
+impl<M> Router<M> {
+    /// Add a route for the given method and path pattern.
+    ///
+    /// Patterns are `/`-separated segments. A segment starting with `:`
+    /// captures one path segment, and a final segment starting with `*`
+    /// captures the remainder of the path (`*` alone is captured as `"*"`).
+    pub fn route(
+        mut self,
+        method: Method,
+        pattern: &str,
+        handler: impl RouteHandler + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.push(Route {
+            method,
+            pattern: Pattern::parse(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Add a `GET` route.
+    pub fn get(self, pattern: &str, handler: impl RouteHandler + Send + Sync + 'static) -> Self {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    /// Add a `POST` route.
+    pub fn post(self, pattern: &str, handler: impl RouteHandler + Send + Sync + 'static) -> Self {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// Add a `PUT` route.
+    pub fn put(self, pattern: &str, handler: impl RouteHandler + Send + Sync + 'static) -> Self {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    /// Add a `DELETE` route.
+    pub fn delete(self, pattern: &str, handler: impl RouteHandler + Send + Sync + 'static) -> Self {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    /// Add a `PATCH` route.
+    pub fn patch(self, pattern: &str, handler: impl RouteHandler + Send + Sync + 'static) -> Self {
+        self.route(Method::Patch, pattern, handler)
+    }
+
+    /// Add middleware.
+    ///
+    /// Middleware runs in registration order around every request,
+    /// including requests that end in 400, 404, or 405: the first added
+    /// sees each request first and its response last. Middleware that
+    /// fails is answered with `500 Internal Server Error`.
+    pub fn middleware<N: Middleware>(self, middleware: N) -> Router<Chain<M, N>> {
+        Router {
+            routes: self.routes,
+            middleware: Chain(self.middleware, middleware),
+            not_found: self.not_found,
+        }
+    }
+
+    /// Set the handler used when no route matches the path.
+    pub fn not_found(mut self, handler: impl Handler + Send + Sync + 'static) -> Self {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// Dispatch a request to the matching route, without middleware.
+    fn dispatch(&self, request: &Request) -> Response {
+        let Ok(path) = request.target().and_then(|target| target.segments()) else {
+            return Response::new(400);
+        };
+        let mut allowed: Vec<Method> = Vec::new();
+        let mut head_fallback = None;
+
+        for route in &self.routes {
+            let Some(params) = route.pattern.matches(&path) else {
+                continue;
+            };
+            if route.method == request.method {
+                return route.handler.handle(request, &params);
+            }
+            if request.method == Method::Head && route.method == Method::Get {
+                head_fallback.get_or_insert((route, params));
+            } else if !allowed.contains(&route.method) {
+                allowed.push(route.method);
+            }
+        }
+
+        if let Some((route, params)) = head_fallback {
+            return route.handler.handle(request, &params);
+        }
+
+        if !allowed.is_empty() {
+            let allow = allowed
+                .iter()
+                .map(|m| m.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Response::new(405).header("allow", allow);
+        }
+
+        match &self.not_found {
+            Some(handler) => handler.handle(request),
+            None => Response::new(404),
+        }
+    }
+}
+
+impl<M: Middleware> HttpHandler for Router<M> {
+    async fn handle(&self, request: portals_http::Request) -> portals_http::Response {
+        match self.middleware.send(request, &Dispatch(self)).await {
+            Ok(response) => response,
+            Err(_) => Response::new(500).into(),
+        }
+    }
+}
+
+/// The end of the middleware chain: route dispatch, as a client the last
+/// middleware sends to.
+struct Dispatch<'a, M>(&'a Router<M>);
+
+impl<M> HttpClient for Dispatch<'_, M> {
+    async fn send(
+        &self,
+        request: portals_http::Request,
+    ) -> Result<portals_http::Response, portals_http::Error> {
+        Ok(self.0.dispatch(&request.into()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portals_http::Error;
+
+    fn request(method: portals_http::Method, url: &str) -> portals_http::Request {
+        portals_http::Request {
+            method,
+            url: url.to_string(),
+            headers: Default::default(),
+            body: None,
+        }
+    }
+
+    fn get(url: &str) -> portals_http::Request {
+        request(portals_http::Method::Get, url)
+    }
+
+    fn echo_params(_req: &Request, params: &Params) -> Response {
+        let body = params
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        Response::new(200).body(body)
+    }
+
+    /// Tags responses with a header.
+    struct Tag(&'static str);
+
+    impl Middleware for Tag {
+        async fn send<C: HttpClient>(
+            &self,
+            request: portals_http::Request,
+            next: &C,
+        ) -> Result<portals_http::Response, Error> {
+            let mut response = next.send(request).await?;
+            response.headers.insert(self.0, "1");
+            Ok(response)
+        }
+    }
+
+    /// Answers `403` to requests with an `x-block` header, and fails those
+    /// with `x-fail`.
+    struct Block;
+
+    impl Middleware for Block {
+        async fn send<C: HttpClient>(
+            &self,
+            request: portals_http::Request,
+            next: &C,
+        ) -> Result<portals_http::Response, Error> {
+            if request.headers.contains_key("x-fail") {
+                return Err(Error::Other("failed".to_string()));
+            }
+            if request.headers.contains_key("x-block") {
+                return Ok(Response::new(403).into());
+            }
+            next.send(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn matches_literal_and_params() {
+        let router = Router::new().get("/users/:id/posts/:post", echo_params);
+
+        let res = router.handle(get("/users/42/posts/7")).await;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.body, b"id=42&post=7");
+
+        let res = router.handle(get("/users/42")).await;
+        assert_eq!(res.status, 404);
+    }
+
+    #[tokio::test]
+    async fn decodes_params() {
+        let router = Router::new()
+            .get("/users/:id", echo_params)
+            .get("/files/*path", echo_params);
+
+        let res = router.handle(get("/users/j%C3%B6rg")).await;
+        assert_eq!(res.body, "id=jörg".as_bytes());
+
+        let res = router.handle(get("/users/a%2Fb")).await;
+        assert_eq!(res.body, b"id=a/b");
+
+        let res = router.handle(get("/files/a%20b/c+d")).await;
+        assert_eq!(res.body, b"path=a b/c+d");
+
+        let res = router.handle(get("/users/%zz")).await;
+        assert_eq!(res.status, 400);
+    }
+
+    #[tokio::test]
+    async fn wildcard_captures_rest() {
+        let router = Router::new()
+            .get("/static/*path", echo_params)
+            .get("/any/*", echo_params);
+
+        let res = router.handle(get("/static/css/site.css")).await;
+        assert_eq!(res.body, b"path=css/site.css");
+
+        let res = router.handle(get("/any")).await;
+        assert_eq!(res.body, b"*=");
+    }
+
+    #[tokio::test]
+    async fn ignores_query_string() {
+        let router = Router::new().get("/search", echo_params);
+        let res = router.handle(get("/search?q=rust")).await;
+        assert_eq!(res.status, 200);
+    }
+
+    #[tokio::test]
+    async fn method_not_allowed() {
+        let router = Router::new()
+            .get("/items", echo_params)
+            .post("/items", echo_params);
+
+        let res = router
+            .handle(request(portals_http::Method::Delete, "/items"))
+            .await;
+        assert_eq!(res.status, 405);
+        assert_eq!(res.headers.get("allow"), Some("GET, POST"));
+    }
+
+    #[tokio::test]
+    async fn head_falls_back_to_get() {
+        let router = Router::new().get("/items", echo_params);
+        let res = router
+            .handle(request(portals_http::Method::Head, "/items"))
+            .await;
+        assert_eq!(res.status, 200);
+    }
+
+    #[tokio::test]
+    async fn custom_not_found() {
+        let router = Router::new().not_found(|_req: &Request| Response::new(404).body("nope"));
+        let res = router.handle(get("/missing")).await;
+        assert_eq!(res.body, b"nope");
+    }
+
+    #[tokio::test]
+    async fn middleware_runs_in_order() {
+        let router = Router::new()
+            .get("/", |_req: &Request, _params: &Params| Response::new(200))
+            .middleware(Tag("x-first"))
+            .middleware(Block)
+            .middleware(Tag("x-second"));
+
+        let res = router.handle(get("/")).await;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.headers.get("x-first"), Some("1"));
+        assert_eq!(res.headers.get("x-second"), Some("1"));
+
+        let mut blocked = get("/");
+        blocked.headers.insert("x-block", "1");
+        let res = router.handle(blocked).await;
+        assert_eq!(res.status, 403);
+        assert_eq!(res.headers.get("x-first"), Some("1"));
+        assert!(!res.headers.contains_key("x-second"));
+
+        let mut failed = get("/");
+        failed.headers.insert("x-fail", "1");
+        let res = router.handle(failed).await;
+        assert_eq!(res.status, 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard must be the last segment")]
+    fn wildcard_must_be_last() {
+        let _ = Router::new().get("/a/*rest/b", echo_params);
+    }
+}
@@ -0,0 +1,148 @@
+//! Blocking HTTP/1.1 connection handling.
+//!
+//! Serves one request over any `Read + Write` stream; accepting connections
+//! is a backend's concern.
+
+use crate::{
+    CONTINUE, Error, Handler, Http1Parser, Limits, MessageReader, Method, Request, Response,
+    expects_continue, write_response_for,
+};
+use std::io::{BufReader, Read, Write};
+
+/// Serve a single request on a connection.
+///
+/// Reads one request, passes it to `handler`, and writes the response with
+/// method-aware body handling (see [`write_response_for`]). Malformed
+/// requests are answered with `400 Bad Request`, requests over the default
+/// [`Limits`] with `414`, `431`, or `413`, and requests with a
+/// `transfer-encoding` with `501 Not Implemented`. A client that sent
+/// `expect: 100-continue` is told to continue before its body is read. The
+/// connection is closed after the response.
+pub fn serve_connection<S, H>(stream: S, handler: &H) -> Result<(), Error>
+where
+    S: Read + Write,
+    H: Handler + ?Sized,
+{
+    let mut reader = BufReader::new(stream);
+
+    let (response, method) = match read_request(&mut reader) {
+        Ok(request) => (handler.handle(&request), request.method),
+        Err(Error::Io(e)) => return Err(Error::Io(e)),
+        Err(e) => (Response::new(e.status()), Method::Get),
+    };
+    let response = response.header("connection", "close");
+
+    write_response_for(reader.get_mut(), &response, method)
+}
+
+/// Read a request, sending `100 Continue` first if the client waits for it.
+fn read_request<S: Read + Write>(reader: &mut BufReader<S>) -> Result<Request, Error> {
+    let limits = Limits::default();
+    let mut message = MessageReader::new(reader, Http1Parser::request().with_limits(limits));
+    let head = message.request_head()?;
+    if limits.request_body_length(&head.headers)? > 0
+        && expects_continue(head.version, &head.headers)
+    {
+        let stream = message.get_mut().get_mut();
+        stream.write_all(CONTINUE)?;
+        stream.flush()?;
+    }
+    Ok(Request {
+        method: head.method,
+        path: head.path,
+        headers: head.headers,
+        body: message.read_body()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Request;
+    use std::io::Cursor;
+
+    /// In-memory duplex stream: reads from `input`, writes to `output`.
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Duplex {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Answers `GET` with `hello` and anything else with `405`.
+    fn handler() -> impl Handler {
+        |req: &Request| match req.method {
+            Method::Get | Method::Head => Response::new(200).body("hello"),
+            _ => Response::new(405),
+        }
+    }
+
+    #[test]
+    fn serves_get() {
+        let mut stream = Duplex::new(b"GET / HTTP/1.1\r\n\r\n");
+        serve_connection(&mut stream, &handler()).unwrap();
+
+        let output = String::from_utf8(stream.output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.contains("content-length: 5\r\n"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn suppresses_body_for_head() {
+        let mut stream = Duplex::new(b"HEAD / HTTP/1.1\r\n\r\n");
+        serve_connection(&mut stream, &handler()).unwrap();
+
+        let output = String::from_utf8(stream.output).unwrap();
+        assert!(output.contains("content-length: 5\r\n"));
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn bad_request() {
+        let mut stream = Duplex::new(b"BREW /pot HTTP/1.1\r\n\r\n");
+        serve_connection(&mut stream, &handler()).unwrap();
+
+        let output = String::from_utf8(stream.output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn sends_continue_before_body() {
+        let mut stream =
+            Duplex::new(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\ncontent-length: 2\r\n\r\nhi");
+        serve_connection(&mut stream, &handler()).unwrap();
+        let output = String::from_utf8(stream.output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 405 "));
+
+        // HTTP/1.0 clients never get interim responses.
+        let mut stream =
+            Duplex::new(b"POST / HTTP/1.0\r\nExpect: 100-continue\r\ncontent-length: 2\r\n\r\nhi");
+        serve_connection(&mut stream, &handler()).unwrap();
+        let output = String::from_utf8(stream.output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 405 "));
+    }
+}
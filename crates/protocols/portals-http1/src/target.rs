@@ -0,0 +1,222 @@
+//! Request targets.
+//!
+//! A request line's target takes one of four forms (RFC 9112 §3.2):
+//! `/path?query` for ordinary requests, `http://host/path?query` for
+//! requests to a proxy, `host:port` for `CONNECT`, and `*` for server-wide
+//! `OPTIONS`. [`Target`] tells them apart and decodes the path and query.
+
+use crate::Error;
+use portals_encoding::UrlEncoding;
+use portals_encoding_portable::StdUrlEncoding;
+use url::{Position, Url};
+
+/// A parsed request target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// `/path?query`.
+    Origin { path: String, query: Option<String> },
+    /// `scheme://authority/path?query`.
+    Absolute {
+        scheme: String,
+        authority: String,
+        path: String,
+        query: Option<String>,
+    },
+    /// `host:port`, as sent with `CONNECT`.
+    Authority(String),
+    /// `*`, as sent with `OPTIONS`.
+    Asterisk,
+}
+
+impl Target {
+    /// Parse a raw request target.
+    ///
+    /// Any fragment is dropped. An absolute-form target with no path has
+    /// the path `/`. Targets containing spaces, control characters, or
+    /// non-ASCII bytes are rejected.
+    pub fn parse(target: &str) -> Result<Self, Error> {
+        if target.is_empty() || !target.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(Error::InvalidTarget);
+        }
+        let target = target.split('#').next().unwrap_or("");
+        if target == "*" {
+            return Ok(Self::Asterisk);
+        }
+        if target.starts_with('/') {
+            let (path, query) = split_query(target);
+            return Ok(Self::Origin { path, query });
+        }
+        if let Some((_, rest)) = target.split_once("://") {
+            // The url crate reads `http:///a` as host `a`; a request target
+            // must spell its authority out.
+            if rest.is_empty() || rest.starts_with(['/', '?']) {
+                return Err(Error::InvalidTarget);
+            }
+            let url = Url::parse(target).map_err(|_| Error::InvalidTarget)?;
+            if !url.has_host() {
+                return Err(Error::InvalidTarget);
+            }
+            return Ok(Self::Absolute {
+                scheme: url.scheme().to_string(),
+                authority: url[Position::BeforeUsername..Position::AfterPort].to_string(),
+                path: match url.path() {
+                    "" => "/".to_string(),
+                    path => path.to_string(),
+                },
+                query: url.query().map(str::to_string),
+            });
+        }
+        if target.contains(['/', '?']) || !target.contains(':') {
+            return Err(Error::InvalidTarget);
+        }
+        Ok(Self::Authority(target.to_string()))
+    }
+
+    /// The path, still percent-encoded.
+    ///
+    /// Authority-form targets have no path and return `""`; the asterisk
+    /// form returns `"*"`.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Origin { path, .. } | Self::Absolute { path, .. } => path,
+            Self::Authority(_) => "",
+            Self::Asterisk => "*",
+        }
+    }
+
+    /// The query string without its `?`, still percent-encoded.
+    pub fn query(&self) -> Option<&str> {
+        match self {
+            Self::Origin { query, .. } | Self::Absolute { query, .. } => query.as_deref(),
+            Self::Authority(_) | Self::Asterisk => None,
+        }
+    }
+
+    /// The host and port, for absolute- and authority-form targets.
+    pub fn authority(&self) -> Option<&str> {
+        match self {
+            Self::Absolute { authority, .. } | Self::Authority(authority) => Some(authority),
+            Self::Origin { .. } | Self::Asterisk => None,
+        }
+    }
+
+    /// The decoded path segments, skipping empty ones.
+    ///
+    /// `/files/a%2Fb/` yields `["files", "a/b"]`. A `+` in the path is a
+    /// literal plus sign.
+    pub fn segments(&self) -> Result<Vec<String>, Error> {
+        if matches!(self, Self::Asterisk) {
+            return Ok(Vec::new());
+        }
+        self.path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| decode(&s.replace('+', "%2B")))
+            .collect()
+    }
+
+    /// The decoded query parameters, in order.
+    ///
+    /// Repeated names are kept. A parameter without `=` has an empty value,
+    /// and `+` decodes to a space.
+    pub fn query_params(&self) -> Result<Vec<(String, String)>, Error> {
+        let Some(query) = self.query() else {
+            return Ok(Vec::new());
+        };
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((decode(name)?, decode(value)?))
+            })
+            .collect()
+    }
+
+    /// The first decoded value of the query parameter `name`.
+    pub fn query_param(&self, name: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .query_params()?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v))
+    }
+}
+
+/// Split `path?query` into its parts.
+fn split_query(target: &str) -> (String, Option<String>) {
+    match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    }
+}
+
+fn decode(s: &str) -> Result<String, Error> {
+    StdUrlEncoding::decode(s).map_err(|_| Error::InvalidTarget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_forms() {
+        let target = Target::parse("/search?q=a+b&tag=x%26y&tag=z&flag#top").unwrap();
+        assert_eq!(target.path(), "/search");
+        assert_eq!(target.authority(), None);
+        assert_eq!(
+            target.query_params().unwrap(),
+            [
+                ("q".to_string(), "a b".to_string()),
+                ("tag".to_string(), "x&y".to_string()),
+                ("tag".to_string(), "z".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(target.query_param("tag").unwrap().as_deref(), Some("x&y"));
+        assert_eq!(target.query_param("missing").unwrap(), None);
+
+        let target = Target::parse("/files/a%2Fb//c+d/").unwrap();
+        assert_eq!(target.segments().unwrap(), ["files", "a/b", "c+d"]);
+        assert_eq!(target.query(), None);
+
+        let target = Target::parse("HTTP://example.com:8080?x=1").unwrap();
+        assert_eq!(
+            target,
+            Target::Absolute {
+                scheme: "http".to_string(),
+                authority: "example.com:8080".to_string(),
+                path: "/".to_string(),
+                query: Some("x=1".to_string()),
+            }
+        );
+
+        assert_eq!(
+            Target::parse("example.com:443").unwrap(),
+            Target::Authority("example.com:443".to_string())
+        );
+        assert_eq!(Target::parse("*").unwrap(), Target::Asterisk);
+        assert_eq!(Target::Asterisk.path(), "*");
+    }
+
+    #[test]
+    fn rejects_invalid_targets() {
+        for target in [
+            "",
+            "/a b",
+            "/caf\u{e9}",
+            "example.com",
+            "1http://x/",
+            "http:///a",
+        ] {
+            assert!(
+                matches!(Target::parse(target), Err(Error::InvalidTarget)),
+                "{:?}",
+                target
+            );
+        }
+        let target = Target::parse("/a%zz?b=%4").unwrap();
+        assert!(matches!(target.segments(), Err(Error::InvalidTarget)));
+        assert!(matches!(target.query_params(), Err(Error::InvalidTarget)));
+    }
+}
@@ -0,0 +1,232 @@
+//! Client side of an RPC connection.
+
+use crate::{Code, Error, Frame, FrameDecoder, Status, Transport};
+use portals_clocks::MonotonicClock;
+use portals_sockets::TcpStream;
+use std::collections::{HashSet, VecDeque};
+use std::future::{Future, poll_fn};
+use std::pin::pin;
+use std::task::Poll;
+use std::time::Duration;
+
+/// A call's reply: its payload, or the status the server failed it with.
+type Reply = Result<Vec<u8>, Status>;
+
+/// Identifies a call started on an [`RpcClient`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallId(u64);
+
+impl CallId {
+    /// The id sent on the wire.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for CallId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The client side of an RPC connection.
+///
+/// [`call`](Self::call) sends a request and waits for its reply. To have
+/// several calls outstanding at once, [`start`](Self::start) each and then
+/// [`wait`](Self::wait) for them in any order, or take replies as they
+/// arrive with [`next_reply`](Self::next_reply); replies read while waiting
+/// for a different call are kept until asked for.
+///
+/// ```ignore
+/// let stream = connect.connect(addr).await?;
+/// let mut client = RpcClient::new(stream);
+/// let user = client.start("users.get", b"42".to_vec(), None).await?;
+/// let posts = client.start("posts.list", b"42".to_vec(), None).await?;
+/// let posts = client.wait(posts).await?;
+/// let user = client.wait(user).await?;
+/// ```
+pub struct RpcClient<S> {
+    transport: Transport<S>,
+    next_id: u64,
+    /// Calls sent and not yet answered or cancelled.
+    pending: HashSet<u64>,
+    /// Replies read while waiting for another call, in arrival order.
+    replies: VecDeque<(u64, Reply)>,
+}
+
+impl<S: TcpStream> RpcClient<S> {
+    /// Wrap a connected stream.
+    pub fn new(stream: S) -> Self {
+        Self {
+            transport: Transport::new(stream),
+            next_id: 0,
+            pending: HashSet::new(),
+            replies: VecDeque::new(),
+        }
+    }
+
+    /// Accept replies up to `max_frame` bytes instead of
+    /// [`DEFAULT_MAX_FRAME`](crate::DEFAULT_MAX_FRAME).
+    pub fn with_max_frame(mut self, max_frame: usize) -> Self {
+        self.transport.decoder = FrameDecoder::new().with_max_frame(max_frame);
+        self
+    }
+
+    /// Call `method` and wait for its reply.
+    ///
+    /// A failure reported by the server is [`Error::Status`].
+    pub async fn call(
+        &mut self,
+        method: &str,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<Vec<u8>, Error> {
+        let id = self.start(method, payload, None).await?;
+        self.wait(id).await
+    }
+
+    /// Call `method`, giving up after `deadline` as measured by `clock`.
+    ///
+    /// The deadline is sent along, so the server can abandon the call too.
+    /// Either way the result is [`Error::DeadlineExceeded`], and a call
+    /// abandoned here is cancelled.
+    pub async fn call_with_deadline(
+        &mut self,
+        method: &str,
+        payload: impl Into<Vec<u8>>,
+        deadline: Duration,
+        clock: &impl MonotonicClock,
+    ) -> Result<Vec<u8>, Error> {
+        let id = self.start(method, payload, Some(deadline)).await?;
+        self.wait_until(id, clock.subscribe_duration(deadline))
+            .await
+    }
+
+    /// Send a request for `method` without waiting for the reply.
+    pub async fn start(
+        &mut self,
+        method: &str,
+        payload: impl Into<Vec<u8>>,
+        deadline: Option<Duration>,
+    ) -> Result<CallId, Error> {
+        let id = self.next_id;
+        self.transport
+            .write_frame(&Frame::Request {
+                id,
+                method: method.to_string(),
+                deadline,
+                payload: payload.into(),
+            })
+            .await?;
+        self.next_id += 1;
+        self.pending.insert(id);
+        Ok(CallId(id))
+    }
+
+    /// Wait for the reply to a started call.
+    ///
+    /// Fails with [`Error::UnknownCall`] if the call's reply was already
+    /// taken or it was cancelled.
+    pub async fn wait(&mut self, id: CallId) -> Result<Vec<u8>, Error> {
+        self.wait_until(id, std::future::pending()).await
+    }
+
+    /// Like [`wait`](Self::wait), but gives up when `timeout` completes,
+    /// cancelling the call and failing with [`Error::DeadlineExceeded`].
+    pub async fn wait_until(
+        &mut self,
+        id: CallId,
+        timeout: impl Future<Output = ()>,
+    ) -> Result<Vec<u8>, Error> {
+        if let Some(i) = self
+            .replies
+            .iter()
+            .position(|(reply_id, _)| *reply_id == id.0)
+        {
+            let (_, reply) = self.replies.remove(i).unwrap();
+            return into_result(reply);
+        }
+        if !self.pending.contains(&id.0) {
+            return Err(Error::UnknownCall(id));
+        }
+
+        let mut timeout = pin!(timeout);
+        loop {
+            let reply = {
+                let mut next = pin!(self.read_reply());
+                poll_fn(|cx| match next.as_mut().poll(cx) {
+                    Poll::Ready(reply) => Poll::Ready(Some(reply)),
+                    Poll::Pending => timeout.as_mut().poll(cx).map(|()| None),
+                })
+                .await
+            };
+            match reply {
+                None => {
+                    self.cancel(id).await?;
+                    return Err(Error::DeadlineExceeded);
+                }
+                Some(reply) => match reply? {
+                    (reply_id, reply) if reply_id == id.0 => return into_result(reply),
+                    other => self.replies.push_back(other),
+                },
+            }
+        }
+    }
+
+    /// Take the next reply to any started call, in the order replies
+    /// arrive, or `None` if no calls are outstanding.
+    pub async fn next_reply(&mut self) -> Result<Option<(CallId, Result<Vec<u8>, Error>)>, Error> {
+        let (id, reply) = match self.replies.pop_front() {
+            Some(reply) => reply,
+            None if self.pending.is_empty() => return Ok(None),
+            None => self.read_reply().await?,
+        };
+        Ok(Some((CallId(id), into_result(reply))))
+    }
+
+    /// Tell the server the reply to a call is no longer wanted.
+    ///
+    /// Any reply already received is dropped. Cancelling a call that is not
+    /// outstanding does nothing.
+    pub async fn cancel(&mut self, id: CallId) -> Result<(), Error> {
+        self.replies.retain(|(reply_id, _)| *reply_id != id.0);
+        if self.pending.remove(&id.0) {
+            self.transport
+                .write_frame(&Frame::Cancel { id: id.0 })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// How many started calls have not had their replies taken.
+    pub fn outstanding(&self) -> usize {
+        self.pending.len() + self.replies.len()
+    }
+
+    /// Read frames until one answers a pending call.
+    ///
+    /// Cancel-safe: a reply is only taken off the wire together with its
+    /// call.
+    async fn read_reply(&mut self) -> Result<(u64, Reply), Error> {
+        loop {
+            let (id, reply) = match self.transport.read_frame().await? {
+                Some(Frame::Response { id, payload }) => (id, Ok(payload)),
+                Some(Frame::Error { id, status }) => (id, Err(status)),
+                Some(Frame::Request { .. } | Frame::Cancel { .. }) => {
+                    return Err(Error::InvalidFrame);
+                }
+                None => return Err(Error::Closed),
+            };
+            // Replies to cancelled calls may still arrive; drop them.
+            if self.pending.remove(&id) {
+                return Ok((id, reply));
+            }
+        }
+    }
+}
+
+fn into_result(reply: Reply) -> Result<Vec<u8>, Error> {
+    reply.map_err(|status| match status.code {
+        Code::DeadlineExceeded => Error::DeadlineExceeded,
+        _ => Error::Status(status),
+    })
+}
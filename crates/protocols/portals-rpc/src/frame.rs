@@ -0,0 +1,312 @@
+//! Frame encoding and incremental decoding.
+//!
+//! Every frame is a big-endian `u32` length, counting the bytes after it,
+//! followed by a one-byte kind and the `u64` id of the call it belongs to:
+//!
+//! | Kind | Byte | Rest of the frame |
+//! |------|------|-------------------|
+//! | request | 0 | deadline in ms (`u32`, 0 for none), method length (`u16`), method, payload |
+//! | response | 1 | payload |
+//! | error | 2 | status code (`u8`), UTF-8 message |
+//! | cancel | 3 | nothing |
+
+use crate::{Code, Error, Status};
+use std::time::Duration;
+
+/// Default largest frame, not counting its length prefix: 16 MiB.
+pub const DEFAULT_MAX_FRAME: usize = 16 * 1024 * 1024;
+
+const REQUEST: u8 = 0;
+const RESPONSE: u8 = 1;
+const ERROR: u8 = 2;
+const CANCEL: u8 = 3;
+
+/// Bytes of kind and id at the start of every frame.
+const HEADER_LEN: usize = 1 + 8;
+
+/// One message on an RPC connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// A call from client to server.
+    Request {
+        id: u64,
+        method: String,
+        /// How long the client will wait, measured from when the server
+        /// reads the frame. Sent in whole milliseconds.
+        deadline: Option<Duration>,
+        payload: Vec<u8>,
+    },
+    /// A successful reply to the request with the same id.
+    Response { id: u64, payload: Vec<u8> },
+    /// A failed reply to the request with the same id.
+    Error { id: u64, status: Status },
+    /// The client no longer wants the reply to the request with this id.
+    Cancel { id: u64 },
+}
+
+impl Frame {
+    /// The id of the call this frame belongs to.
+    pub fn id(&self) -> u64 {
+        match self {
+            Self::Request { id, .. }
+            | Self::Response { id, .. }
+            | Self::Error { id, .. }
+            | Self::Cancel { id } => *id,
+        }
+    }
+
+    /// Append the encoded frame, length prefix included, to `out`.
+    ///
+    /// Fails with [`Error::InvalidFrame`] if the method name is longer
+    /// than 65535 bytes, and [`Error::FrameTooLarge`] if the frame would
+    /// not fit its length prefix.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        if let Self::Request { method, .. } = self
+            && method.len() > u16::MAX as usize
+        {
+            return Err(Error::InvalidFrame);
+        }
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]);
+        match self {
+            Self::Request {
+                id,
+                method,
+                deadline,
+                payload,
+            } => {
+                let method_len = method.len() as u16;
+                let deadline = deadline.map_or(0, |d| d.as_millis().clamp(1, u32::MAX as u128));
+                out.push(REQUEST);
+                out.extend_from_slice(&id.to_be_bytes());
+                out.extend_from_slice(&(deadline as u32).to_be_bytes());
+                out.extend_from_slice(&method_len.to_be_bytes());
+                out.extend_from_slice(method.as_bytes());
+                out.extend_from_slice(payload);
+            }
+            Self::Response { id, payload } => {
+                out.push(RESPONSE);
+                out.extend_from_slice(&id.to_be_bytes());
+                out.extend_from_slice(payload);
+            }
+            Self::Error { id, status } => {
+                out.push(ERROR);
+                out.extend_from_slice(&id.to_be_bytes());
+                out.push(status.code as u8);
+                out.extend_from_slice(status.message.as_bytes());
+            }
+            Self::Cancel { id } => {
+                out.push(CANCEL);
+                out.extend_from_slice(&id.to_be_bytes());
+            }
+        }
+        let Ok(len) = u32::try_from(out.len() - start - 4) else {
+            out.truncate(start);
+            return Err(Error::FrameTooLarge);
+        };
+        out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+        Ok(())
+    }
+
+    fn decode(frame: &[u8]) -> Result<Self, Error> {
+        if frame.len() < HEADER_LEN {
+            return Err(Error::InvalidFrame);
+        }
+        let id = u64::from_be_bytes(frame[1..HEADER_LEN].try_into().unwrap());
+        let body = &frame[HEADER_LEN..];
+        match frame[0] {
+            REQUEST => {
+                if body.len() < 6 {
+                    return Err(Error::InvalidFrame);
+                }
+                let deadline = u32::from_be_bytes(body[..4].try_into().unwrap());
+                let method_len = u16::from_be_bytes(body[4..6].try_into().unwrap()) as usize;
+                let rest = &body[6..];
+                if rest.len() < method_len {
+                    return Err(Error::InvalidFrame);
+                }
+                let (method, payload) = rest.split_at(method_len);
+                let method = std::str::from_utf8(method).map_err(|_| Error::InvalidFrame)?;
+                Ok(Self::Request {
+                    id,
+                    method: method.to_string(),
+                    deadline: (deadline > 0).then(|| Duration::from_millis(deadline.into())),
+                    payload: payload.to_vec(),
+                })
+            }
+            RESPONSE => Ok(Self::Response {
+                id,
+                payload: body.to_vec(),
+            }),
+            ERROR => {
+                let (&code, message) = body.split_first().ok_or(Error::InvalidFrame)?;
+                let message = std::str::from_utf8(message).map_err(|_| Error::InvalidFrame)?;
+                Ok(Self::Error {
+                    id,
+                    status: Status::new(Code::from_u8(code), message),
+                })
+            }
+            CANCEL if body.is_empty() => Ok(Self::Cancel { id }),
+            _ => Err(Error::InvalidFrame),
+        }
+    }
+}
+
+/// Splits a byte stream into [`Frame`]s.
+///
+/// Push bytes as they arrive and take frames out once they are complete.
+/// A frame longer than the limit fails with [`Error::FrameTooLarge`] as soon
+/// as its length prefix is seen, before its body is buffered.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    max_frame: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    /// Create a decoder that accepts frames up to [`DEFAULT_MAX_FRAME`].
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            max_frame: DEFAULT_MAX_FRAME,
+        }
+    }
+
+    /// Accept frames up to `max_frame` bytes, not counting the length
+    /// prefix.
+    pub fn with_max_frame(mut self, max_frame: usize) -> Self {
+        self.max_frame = max_frame;
+        self
+    }
+
+    /// Buffer bytes read from the connection.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Whether bytes of an incomplete frame are buffered.
+    pub fn is_partial(&self) -> bool {
+        !self.buf.is_empty()
+    }
+
+    /// Take the next complete frame, or `None` if more bytes are needed.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let Some(prefix) = self.buf.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(*prefix) as usize;
+        if len > self.max_frame {
+            return Err(Error::FrameTooLarge);
+        }
+        if self.buf.len() < 4 + len {
+            return Ok(None);
+        }
+        let frame = Frame::decode(&self.buf[4..4 + len]);
+        self.buf.drain(..4 + len);
+        frame.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames() -> Vec<Frame> {
+        vec![
+            Frame::Request {
+                id: 1,
+                method: "users.get".to_string(),
+                deadline: Some(Duration::from_millis(250)),
+                payload: b"42".to_vec(),
+            },
+            Frame::Request {
+                id: 2,
+                method: String::new(),
+                deadline: None,
+                payload: Vec::new(),
+            },
+            Frame::Response {
+                id: 1,
+                payload: b"alice".to_vec(),
+            },
+            Frame::Error {
+                id: 2,
+                status: Status::new(Code::NotFound, "no such user"),
+            },
+            Frame::Cancel { id: u64::MAX },
+        ]
+    }
+
+    #[test]
+    fn roundtrip_byte_by_byte() {
+        let mut encoded = Vec::new();
+        for frame in frames() {
+            frame.encode(&mut encoded).unwrap();
+        }
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::new();
+        for byte in encoded {
+            decoder.push(&[byte]);
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                decoded.push(frame);
+            }
+        }
+        assert_eq!(decoded, frames());
+        assert!(!decoder.is_partial());
+    }
+
+    #[test]
+    fn rounds_deadlines_up_to_a_millisecond() {
+        let mut encoded = Vec::new();
+        Frame::Request {
+            id: 1,
+            method: "m".to_string(),
+            deadline: Some(Duration::from_micros(10)),
+            payload: Vec::new(),
+        }
+        .encode(&mut encoded)
+        .unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&encoded);
+        let Some(Frame::Request { deadline, .. }) = decoder.next_frame().unwrap() else {
+            panic!("expected a request");
+        };
+        assert_eq!(deadline, Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let mut decoder = FrameDecoder::new().with_max_frame(16);
+        decoder.push(&17u32.to_be_bytes());
+        assert!(matches!(decoder.next_frame(), Err(Error::FrameTooLarge)));
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&[0, 0, 0, 9, 7, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(matches!(decoder.next_frame(), Err(Error::InvalidFrame)));
+
+        // A request whose method runs past the end of the frame.
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&[
+            0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 9, b'x',
+        ]);
+        assert!(matches!(decoder.next_frame(), Err(Error::InvalidFrame)));
+
+        let mut out = Vec::new();
+        let method = "x".repeat(70_000);
+        let frame = Frame::Request {
+            id: 1,
+            method,
+            deadline: None,
+            payload: Vec::new(),
+        };
+        assert!(matches!(frame.encode(&mut out), Err(Error::InvalidFrame)));
+    }
+}
@@ -0,0 +1,243 @@
+//! Length-prefixed RPC protocol.
+//!
+//! A small request/response protocol for internal services: each call
+//! names a method and carries an opaque byte payload, and its reply is
+//! either a payload or a [`Status`]. Calls carry ids, so a client can have
+//! several outstanding on one connection and replies may come back in any
+//! order. Requests may carry a deadline, and a client can cancel a call it
+//! no longer needs; the server then drops the handler's future.
+//!
+//! [`RpcClient`] and [`RpcServer`] run over any `portals-sockets`
+//! [`TcpStream`](portals_sockets::TcpStream); the wire format itself is in
+//! [`Frame`] and [`FrameDecoder`].
+//!
+//! This is framing, not gRPC: payloads are opaque, there is no IDL or
+//! codegen, and only peers using this crate understand it. To talk to gRPC
+//! services, use tonic.
+
+mod client;
+mod frame;
+mod server;
+
+pub use client::{CallId, RpcClient};
+pub use frame::{DEFAULT_MAX_FRAME, Frame, FrameDecoder};
+pub use server::{Request, RpcServer, Service};
+
+use portals_error::{ErrorKind, PithError};
+use portals_sockets::TcpStream;
+
+/// Size of each socket read.
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// RPC errors.
+#[derive(Debug)]
+pub enum Error {
+    /// The peer sent bytes that are not a valid frame, or a frame that
+    /// makes no sense on its side of the connection.
+    InvalidFrame,
+    /// A frame exceeded the size limit.
+    FrameTooLarge,
+    /// The connection closed with calls outstanding.
+    Closed,
+    /// The call's id is not outstanding on this client.
+    UnknownCall(CallId),
+    /// The call's deadline passed before it was answered.
+    DeadlineExceeded,
+    /// The server answered the call with an error.
+    Status(Status),
+    /// Reading from or writing to the connection failed.
+    Socket(portals_sockets::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFrame => write!(f, "invalid frame"),
+            Self::FrameTooLarge => write!(f, "frame too large"),
+            Self::Closed => write!(f, "connection closed"),
+            Self::UnknownCall(id) => write!(f, "unknown call: {}", id),
+            Self::DeadlineExceeded => write!(f, "deadline exceeded"),
+            Self::Status(status) => write!(f, "{}", status),
+            Self::Socket(e) => write!(f, "socket error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl PithError for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidFrame | Self::FrameTooLarge => ErrorKind::InvalidInput,
+            Self::Closed => ErrorKind::Unavailable,
+            Self::UnknownCall(_) => ErrorKind::NotFound,
+            Self::DeadlineExceeded => ErrorKind::Timeout,
+            Self::Status(status) => status.kind(),
+            Self::Socket(e) => e.kind(),
+        }
+    }
+}
+
+impl From<portals_sockets::Error> for Error {
+    fn from(e: portals_sockets::Error) -> Self {
+        Self::Socket(e)
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        Self::Status(status)
+    }
+}
+
+/// Why a call failed, as reported by the server.
+///
+/// The numbering follows gRPC's, so codes map across directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Code {
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    PermissionDenied = 7,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+}
+
+impl Code {
+    /// The code for a byte read off the wire; unassigned bytes are
+    /// [`Unknown`](Self::Unknown).
+    pub fn from_u8(code: u8) -> Self {
+        match code {
+            1 => Self::Cancelled,
+            3 => Self::InvalidArgument,
+            4 => Self::DeadlineExceeded,
+            5 => Self::NotFound,
+            7 => Self::PermissionDenied,
+            12 => Self::Unimplemented,
+            13 => Self::Internal,
+            14 => Self::Unavailable,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Cancelled => "cancelled",
+            Self::Unknown => "unknown",
+            Self::InvalidArgument => "invalid argument",
+            Self::DeadlineExceeded => "deadline exceeded",
+            Self::NotFound => "not found",
+            Self::PermissionDenied => "permission denied",
+            Self::Unimplemented => "unimplemented",
+            Self::Internal => "internal",
+            Self::Unavailable => "unavailable",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A failed call's code and message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub code: Code,
+    pub message: String,
+}
+
+impl Status {
+    /// Create a status.
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{}", self.code)
+        } else {
+            write!(f, "{}: {}", self.code, self.message)
+        }
+    }
+}
+
+impl std::error::Error for Status {}
+
+impl PithError for Status {
+    fn kind(&self) -> ErrorKind {
+        match self.code {
+            Code::InvalidArgument => ErrorKind::InvalidInput,
+            Code::DeadlineExceeded => ErrorKind::Timeout,
+            Code::NotFound => ErrorKind::NotFound,
+            Code::PermissionDenied => ErrorKind::PermissionDenied,
+            Code::Unimplemented => ErrorKind::Unsupported,
+            Code::Unavailable => ErrorKind::Unavailable,
+            Code::Cancelled | Code::Unknown | Code::Internal => ErrorKind::Other,
+        }
+    }
+}
+
+/// Frames over a stream, shared by client and server.
+struct Transport<S> {
+    stream: S,
+    decoder: FrameDecoder,
+    read_buf: Box<[u8]>,
+    write_buf: Vec<u8>,
+}
+
+impl<S: TcpStream> Transport<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            decoder: FrameDecoder::new(),
+            read_buf: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+            write_buf: Vec::new(),
+        }
+    }
+
+    /// Read the next frame, or `None` if the peer closed the connection
+    /// between frames.
+    ///
+    /// Cancel-safe as long as the stream's reads are: bytes are handed to
+    /// the decoder as soon as a read completes.
+    async fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        loop {
+            if let Some(frame) = self.decoder.next_frame()? {
+                return Ok(Some(frame));
+            }
+            let n = self.stream.read(&mut self.read_buf).await?;
+            if n == 0 {
+                return if self.decoder.is_partial() {
+                    Err(Error::Closed)
+                } else {
+                    Ok(None)
+                };
+            }
+            self.decoder.push(&self.read_buf[..n]);
+        }
+    }
+
+    /// Write a frame and flush it.
+    async fn write_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        self.write_buf.clear();
+        frame.encode(&mut self.write_buf)?;
+        let mut written = 0;
+        while written < self.write_buf.len() {
+            let n = self.stream.write(&self.write_buf[written..]).await?;
+            if n == 0 {
+                return Err(Error::Closed);
+            }
+            written += n;
+        }
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
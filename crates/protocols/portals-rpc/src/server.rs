@@ -0,0 +1,289 @@
+//! Server side of an RPC connection.
+
+use crate::{Code, Error, Frame, FrameDecoder, Status, Transport};
+use portals_clocks::MonotonicClock;
+use portals_sockets::TcpStream;
+use std::future::{Future, poll_fn};
+use std::pin::{Pin, pin};
+use std::task::Poll;
+use std::time::Duration;
+
+/// A call as a [`Service`] receives it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub payload: Vec<u8>,
+    /// How long the client was willing to wait, from when the request
+    /// was read. The server fails the call once it passes.
+    pub deadline: Option<Duration>,
+}
+
+/// Handles calls arriving on an [`RpcServer`].
+///
+/// Implemented for closures taking a [`Request`] and returning a future.
+pub trait Service {
+    /// Answer a call with a payload, or fail it with a status.
+    ///
+    /// An unrecognised method should fail with [`Code::Unimplemented`].
+    fn call(&self, request: Request) -> impl Future<Output = Result<Vec<u8>, Status>>;
+}
+
+impl<F, Fut> Service for F
+where
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>, Status>>,
+{
+    fn call(&self, request: Request) -> impl Future<Output = Result<Vec<u8>, Status>> {
+        self(request)
+    }
+}
+
+/// The server side of an RPC connection.
+///
+/// Serving runs every call's handler concurrently on the current task, so
+/// a slow call doesn't hold up the others. A handler is dropped without
+/// answering when the client cancels its call, and answered with
+/// [`Code::DeadlineExceeded`] if its deadline passes first.
+///
+/// ```ignore
+/// let (stream, _) = listener.accept().await?;
+/// RpcServer::new(stream).serve(&service, &clock).await?;
+/// ```
+pub struct RpcServer<S> {
+    transport: Transport<S>,
+}
+
+/// A call being handled.
+struct InFlight<'a> {
+    id: u64,
+    handler: Pin<Box<dyn Future<Output = Result<Vec<u8>, Status>> + 'a>>,
+    deadline: Option<Pin<Box<dyn Future<Output = ()> + 'a>>>,
+}
+
+/// What woke the serving loop.
+enum Step {
+    Frame(Result<Option<Frame>, Error>),
+    Answered(usize, Result<Vec<u8>, Status>),
+    Expired(usize),
+}
+
+impl<S: TcpStream> RpcServer<S> {
+    /// Wrap an accepted stream.
+    pub fn new(stream: S) -> Self {
+        Self {
+            transport: Transport::new(stream),
+        }
+    }
+
+    /// Accept requests up to `max_frame` bytes instead of
+    /// [`DEFAULT_MAX_FRAME`](crate::DEFAULT_MAX_FRAME).
+    pub fn with_max_frame(mut self, max_frame: usize) -> Self {
+        self.transport.decoder = FrameDecoder::new().with_max_frame(max_frame);
+        self
+    }
+
+    /// Serve calls until the client closes the connection.
+    ///
+    /// Calls still in flight when it closes are dropped. `clock` times the
+    /// requests' deadlines.
+    pub async fn serve<V, C>(mut self, service: &V, clock: &C) -> Result<(), Error>
+    where
+        V: Service + ?Sized,
+        C: MonotonicClock,
+    {
+        let mut calls: Vec<InFlight<'_>> = Vec::new();
+        loop {
+            let step = {
+                let mut next = pin!(self.transport.read_frame());
+                poll_fn(|cx| {
+                    for (i, call) in calls.iter_mut().enumerate() {
+                        if let Poll::Ready(result) = call.handler.as_mut().poll(cx) {
+                            return Poll::Ready(Step::Answered(i, result));
+                        }
+                        if let Some(deadline) = &mut call.deadline
+                            && deadline.as_mut().poll(cx).is_ready()
+                        {
+                            return Poll::Ready(Step::Expired(i));
+                        }
+                    }
+                    next.as_mut().poll(cx).map(Step::Frame)
+                })
+                .await
+            };
+
+            let reply = match step {
+                Step::Answered(i, result) => {
+                    let id = calls.swap_remove(i).id;
+                    match result {
+                        Ok(payload) => Frame::Response { id, payload },
+                        Err(status) => Frame::Error { id, status },
+                    }
+                }
+                Step::Expired(i) => Frame::Error {
+                    id: calls.swap_remove(i).id,
+                    status: Status::new(Code::DeadlineExceeded, ""),
+                },
+                Step::Frame(frame) => match frame? {
+                    Some(Frame::Request {
+                        id,
+                        method,
+                        deadline,
+                        payload,
+                    }) => {
+                        if calls.iter().any(|call| call.id == id) {
+                            return Err(Error::InvalidFrame);
+                        }
+                        let expires = deadline.map(|deadline| {
+                            let nanos = u64::try_from(deadline.as_nanos()).unwrap_or(u64::MAX);
+                            let at = clock.now().saturating_add(nanos);
+                            Box::pin(clock.subscribe_instant(at))
+                                as Pin<Box<dyn Future<Output = ()>>>
+                        });
+                        let request = Request {
+                            method,
+                            payload,
+                            deadline,
+                        };
+                        calls.push(InFlight {
+                            id,
+                            handler: Box::pin(service.call(request)),
+                            deadline: expires,
+                        });
+                        continue;
+                    }
+                    Some(Frame::Cancel { id }) => {
+                        calls.retain(|call| call.id != id);
+                        continue;
+                    }
+                    Some(Frame::Response { .. } | Frame::Error { .. }) => {
+                        return Err(Error::InvalidFrame);
+                    }
+                    None => return Ok(()),
+                },
+            };
+            self.transport.write_frame(&reply).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RpcClient;
+    use portals_clocks_native::StdMonotonicClock;
+    use portals_sockets::{TcpConnect, TcpListener};
+    use portals_sockets_native::{NativeTcpConnect, NativeTcpListener, NativeTcpStream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    async fn connect() -> (NativeTcpStream, NativeTcpStream) {
+        let listener = NativeTcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(NativeTcpConnect.connect(addr), listener.accept());
+        (client.unwrap(), accepted.unwrap().0)
+    }
+
+    /// Sets its flag when dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    async fn service(request: Request, dropped: Arc<AtomicBool>) -> Result<Vec<u8>, Status> {
+        match request.method.as_str() {
+            "echo" => Ok(request.payload),
+            "slow" => {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(request.payload)
+            }
+            "hang" => {
+                let _flag = DropFlag(dropped);
+                std::future::pending().await
+            }
+            method => Err(Status::new(Code::Unimplemented, method)),
+        }
+    }
+
+    #[tokio::test]
+    async fn multiplexes_calls() {
+        let (client, server) = connect().await;
+        let dropped = Arc::new(AtomicBool::new(false));
+        let handler = |request| service(request, dropped.clone());
+        let clock = StdMonotonicClock::new();
+        let server = RpcServer::new(server).serve(&handler, &clock);
+
+        let client = async {
+            let mut client = RpcClient::new(client);
+            let slow = client.start("slow", b"first".to_vec(), None).await.unwrap();
+            let echo = client
+                .start("echo", b"second".to_vec(), None)
+                .await
+                .unwrap();
+            assert_eq!(client.outstanding(), 2);
+
+            // The fast call overtakes the slow one.
+            let (id, reply) = client.next_reply().await.unwrap().unwrap();
+            assert_eq!(id, echo);
+            assert_eq!(reply.unwrap(), b"second");
+            assert_eq!(client.wait(slow).await.unwrap(), b"first");
+            assert!(client.next_reply().await.unwrap().is_none());
+            assert!(matches!(client.wait(slow).await, Err(Error::UnknownCall(id)) if id == slow));
+
+            match client.call("missing", Vec::new()).await {
+                Err(Error::Status(status)) => {
+                    assert_eq!(status, Status::new(Code::Unimplemented, "missing"));
+                }
+                other => panic!("expected a status, got {:?}", other),
+            }
+        };
+
+        let (served, ()) = tokio::join!(server, client);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deadlines_and_cancellation() {
+        let (client, server) = connect().await;
+        let dropped = Arc::new(AtomicBool::new(false));
+        let handler = |request| service(request, dropped.clone());
+        let clock = StdMonotonicClock::new();
+        let server = RpcServer::new(server).serve(&handler, &clock);
+
+        let client = async {
+            let mut client = RpcClient::new(client);
+            let deadline = Duration::from_millis(20);
+
+            // Abandoned by the client.
+            let result = client
+                .call_with_deadline("hang", Vec::new(), deadline, &clock)
+                .await;
+            assert!(matches!(result, Err(Error::DeadlineExceeded)));
+            assert_eq!(client.outstanding(), 0);
+
+            // Abandoned by the server, with the client waiting forever.
+            let id = client
+                .start("hang", Vec::new(), Some(deadline))
+                .await
+                .unwrap();
+            assert!(matches!(
+                client.wait(id).await,
+                Err(Error::DeadlineExceeded)
+            ));
+
+            dropped.store(false, Ordering::SeqCst);
+            let id = client.start("hang", Vec::new(), None).await.unwrap();
+            client.cancel(id).await.unwrap();
+            assert_eq!(
+                client.call("echo", b"after".to_vec()).await.unwrap(),
+                b"after"
+            );
+            assert!(dropped.load(Ordering::SeqCst));
+        };
+
+        let (served, ()) = tokio::join!(server, client);
+        served.unwrap();
+    }
+}
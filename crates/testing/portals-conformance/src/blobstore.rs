@@ -0,0 +1,161 @@
+//! Conformance suite for [`Container`].
+
+use crate::{CASES, Gen, STEPS};
+use portals_blobstore::{Container, Error};
+use std::collections::HashMap;
+
+/// Check a [`Container`] backend. `make` must return a new, empty
+/// container.
+pub async fn run<C: Container>(make: impl Fn() -> C) {
+    basics(&make()).await;
+    model(&make).await;
+}
+
+fn is_not_found(result: Result<impl std::fmt::Debug, Error>, name: &str) -> bool {
+    matches!(result, Err(Error::ObjectNotFound(n)) if n == name)
+}
+
+async fn basics(container: &impl Container) {
+    assert!(
+        is_not_found(container.get("missing").await, "missing"),
+        "get: missing object is ObjectNotFound with its name"
+    );
+    assert!(
+        is_not_found(container.delete("missing").await, "missing"),
+        "delete: missing object is ObjectNotFound"
+    );
+    assert!(
+        is_not_found(container.metadata("missing").await, "missing"),
+        "metadata: missing object is ObjectNotFound"
+    );
+    assert!(
+        is_not_found(container.copy("missing", "dst").await, "missing"),
+        "copy: missing source is ObjectNotFound"
+    );
+    assert!(
+        !container.exists("dst").await.unwrap(),
+        "copy: failed copy creates nothing"
+    );
+    assert!(
+        container.list().await.unwrap().is_empty(),
+        "list: new container"
+    );
+
+    container.put("a", b"hello").await.unwrap();
+    assert_eq!(container.get("a").await.unwrap(), b"hello", "get after put");
+    assert!(container.exists("a").await.unwrap(), "exists after put");
+    let meta = container.metadata("a").await.unwrap();
+    assert_eq!(meta.name, "a", "metadata: name");
+    assert_eq!(meta.size, 5, "metadata: size");
+
+    container.put("a", b"hi").await.unwrap();
+    assert_eq!(container.get("a").await.unwrap(), b"hi", "put overwrites");
+    assert_eq!(
+        container.metadata("a").await.unwrap().size,
+        2,
+        "metadata: size after overwrite"
+    );
+
+    container.copy("a", "b").await.unwrap();
+    container.put("a", b"changed").await.unwrap();
+    assert_eq!(
+        container.get("b").await.unwrap(),
+        b"hi",
+        "copy: destination is independent of the source"
+    );
+
+    let mut listed: Vec<_> = container
+        .list()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|meta| (meta.name, meta.size))
+        .collect();
+    listed.sort();
+    assert_eq!(
+        listed,
+        [("a".to_string(), 7), ("b".to_string(), 2)],
+        "list: every object once, with sizes"
+    );
+
+    container.delete("a").await.unwrap();
+    assert!(!container.exists("a").await.unwrap(), "exists after delete");
+}
+
+async fn model<C: Container>(make: &impl Fn() -> C) {
+    for seed in 0..CASES {
+        let container = make();
+        let mut model: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut g = Gen::new(seed);
+        for step in 0..STEPS {
+            let name = g.key();
+            let context = format!("model (seed {}, step {})", seed, step);
+            match g.below(6) {
+                0 => {
+                    let data = g.value();
+                    container.put(&name, &data).await.unwrap();
+                    model.insert(name, data);
+                }
+                1 => {
+                    let result = container.delete(&name).await;
+                    match model.remove(&name) {
+                        Some(_) => assert!(result.is_ok(), "{}: delete {:?}", context, name),
+                        None => assert!(
+                            is_not_found(result, &name),
+                            "{}: delete {:?}",
+                            context,
+                            name
+                        ),
+                    }
+                }
+                2 => {
+                    let dst = g.key();
+                    let result = container.copy(&name, &dst).await;
+                    match model.get(&name).cloned() {
+                        Some(data) => {
+                            assert!(result.is_ok(), "{}: copy {:?}", context, name);
+                            model.insert(dst, data);
+                        }
+                        None => {
+                            assert!(is_not_found(result, &name), "{}: copy {:?}", context, name)
+                        }
+                    }
+                }
+                3 => {
+                    let mut listed: Vec<_> = container
+                        .list()
+                        .await
+                        .unwrap()
+                        .into_iter()
+                        .map(|meta| (meta.name, meta.size))
+                        .collect();
+                    listed.sort();
+                    let mut expected: Vec<_> = model
+                        .iter()
+                        .map(|(name, data)| (name.clone(), data.len() as u64))
+                        .collect();
+                    expected.sort();
+                    assert_eq!(listed, expected, "{}: list", context);
+                }
+                4 => assert_eq!(
+                    container.exists(&name).await.unwrap(),
+                    model.contains_key(&name),
+                    "{}: exists {:?}",
+                    context,
+                    name
+                ),
+                _ => {
+                    let result = container.get(&name).await;
+                    match model.get(&name) {
+                        Some(data) => {
+                            assert_eq!(&result.unwrap(), data, "{}: get {:?}", context, name)
+                        }
+                        None => {
+                            assert!(is_not_found(result, &name), "{}: get {:?}", context, name)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
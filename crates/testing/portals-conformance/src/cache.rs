@@ -0,0 +1,112 @@
+//! Conformance suite for [`Cache`].
+
+use crate::{CASES, Gen, STEPS};
+use portals_cache::Cache;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+/// Check a [`Cache`] backend. `make` must return a new, empty cache.
+///
+/// Expiry is checked against real time, so this sleeps for a fraction of a
+/// second.
+pub fn run<C: Cache>(make: impl Fn() -> C) {
+    basics(&make());
+    ttl(&make());
+    model(&make);
+}
+
+fn basics(cache: &impl Cache) {
+    assert_eq!(cache.get("missing"), None, "get: missing key");
+    assert!(!cache.exists("missing"), "exists: missing key");
+    assert!(
+        !cache.delete("missing"),
+        "delete: missing key returns false"
+    );
+
+    cache.set("a", b"1".to_vec());
+    assert_eq!(cache.get("a"), Some(b"1".to_vec()), "get after set");
+    assert!(cache.exists("a"), "exists after set");
+    cache.set("a", b"2".to_vec());
+    assert_eq!(cache.get("a"), Some(b"2".to_vec()), "set overwrites");
+
+    cache.set("empty", Vec::new());
+    assert_eq!(
+        cache.get("empty"),
+        Some(Vec::new()),
+        "empty values are kept"
+    );
+
+    assert!(cache.delete("a"), "delete: existing key returns true");
+    assert_eq!(cache.get("a"), None, "get after delete");
+
+    cache.set("b", b"x".to_vec());
+    cache.clear();
+    assert_eq!(cache.get("b"), None, "get after clear");
+    assert_eq!(cache.get("empty"), None, "get after clear");
+}
+
+fn ttl(cache: &impl Cache) {
+    let ttl = Duration::from_millis(50);
+    cache.set_with_ttl("short", b"x".to_vec(), ttl);
+    cache.set_with_ttl("long", b"x".to_vec(), Duration::from_secs(3600));
+    cache.set_with_ttl("persisted", b"x".to_vec(), ttl);
+    cache.set("persisted", b"y".to_vec());
+    assert_eq!(
+        cache.get("short"),
+        Some(b"x".to_vec()),
+        "ttl: live before expiry"
+    );
+
+    thread::sleep(ttl * 3);
+    assert_eq!(cache.get("short"), None, "ttl: get after expiry");
+    assert!(!cache.exists("short"), "ttl: exists after expiry");
+    assert!(cache.exists("long"), "ttl: unexpired entry kept");
+    assert_eq!(
+        cache.get("persisted"),
+        Some(b"y".to_vec()),
+        "ttl: set without ttl replaces the expiry"
+    );
+}
+
+fn model<C: Cache>(make: &impl Fn() -> C) {
+    for seed in 0..CASES {
+        let cache = make();
+        let mut model: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut g = Gen::new(seed);
+        for step in 0..STEPS {
+            let key = g.key();
+            match g.below(4) {
+                0 => {
+                    let value = g.value();
+                    cache.set(&key, value.clone());
+                    model.insert(key, value);
+                }
+                1 => assert_eq!(
+                    cache.delete(&key),
+                    model.remove(&key).is_some(),
+                    "model (seed {}, step {}): delete {:?}",
+                    seed,
+                    step,
+                    key
+                ),
+                2 => assert_eq!(
+                    cache.exists(&key),
+                    model.contains_key(&key),
+                    "model (seed {}, step {}): exists {:?}",
+                    seed,
+                    step,
+                    key
+                ),
+                _ => assert_eq!(
+                    cache.get(&key).as_ref(),
+                    model.get(&key),
+                    "model (seed {}, step {}): get {:?}",
+                    seed,
+                    step,
+                    key
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Conformance suite for [`Directory`].
+
+use crate::{CASES, Gen, STEPS};
+use portals_filesystem::{
+    Directory, ErrorKind, FileType, InputStream, OutputStream, PithError, Seek, SeekFrom,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Check a [`Directory`] backend. `make` must return a capability to a
+/// new, empty directory.
+///
+/// Failures are checked by [`ErrorKind`] rather than by variant, since
+/// backends may report them as [`Error::Io`](portals_filesystem::Error::Io).
+pub fn run<D: Directory>(make: impl Fn() -> D) {
+    files(&make());
+    lengths_and_times(&make());
+    directories(&make());
+    model(&make);
+}
+
+fn write(dir: &impl Directory, path: &str, data: &[u8]) {
+    let mut file = dir.open_write(Path::new(path)).unwrap();
+    file.blocking_write(data).unwrap();
+    file.blocking_flush().unwrap();
+}
+
+fn read(dir: &impl Directory, path: &str) -> Vec<u8> {
+    let mut file = dir.open_read(Path::new(path)).unwrap();
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match file.blocking_read_into(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(portals_filesystem::StreamError::Closed) => break,
+            Err(e) => panic!("read {:?}: {}", path, e),
+        }
+    }
+    data
+}
+
+fn kind<T>(result: Result<T, portals_filesystem::Error>) -> Option<ErrorKind> {
+    result.err().map(|e| e.kind())
+}
+
+fn files(dir: &impl Directory) {
+    let missing = Path::new("missing");
+    assert_eq!(
+        kind(dir.open_read(missing)),
+        Some(ErrorKind::NotFound),
+        "open_read: missing file is NotFound"
+    );
+    assert_eq!(
+        kind(dir.metadata(missing)),
+        Some(ErrorKind::NotFound),
+        "metadata: missing file is NotFound"
+    );
+    assert_eq!(
+        kind(dir.remove_file(missing)),
+        Some(ErrorKind::NotFound),
+        "remove_file: missing file is NotFound"
+    );
+
+    write(dir, "a.txt", b"hello world");
+    assert_eq!(read(dir, "a.txt"), b"hello world", "read after write");
+    let meta = dir.metadata(Path::new("a.txt")).unwrap();
+    assert_eq!(meta.file_type, FileType::Regular, "metadata: file type");
+    assert_eq!(meta.size, 11, "metadata: size");
+
+    write(dir, "a.txt", b"bye");
+    assert_eq!(read(dir, "a.txt"), b"bye", "open_write truncates");
+
+    let mut file = dir.open_append(Path::new("a.txt")).unwrap();
+    file.blocking_write(b"!").unwrap();
+    file.blocking_flush().unwrap();
+    drop(file);
+    assert_eq!(read(dir, "a.txt"), b"bye!", "open_append appends");
+
+    let mut file = dir.open_read(Path::new("a.txt")).unwrap();
+    assert_eq!(file.seek(SeekFrom::Start(1)).unwrap(), 1, "seek: position");
+    assert_eq!(file.blocking_read(2).unwrap(), b"ye", "read after seek");
+    drop(file);
+
+    dir.rename(Path::new("a.txt"), Path::new("b.txt")).unwrap();
+    assert_eq!(
+        kind(dir.metadata(Path::new("a.txt"))),
+        Some(ErrorKind::NotFound),
+        "rename: source is gone"
+    );
+    assert_eq!(read(dir, "b.txt"), b"bye!", "rename: destination has data");
+
+    dir.remove_file(Path::new("b.txt")).unwrap();
+    assert_eq!(
+        kind(dir.open_read(Path::new("b.txt"))),
+        Some(ErrorKind::NotFound),
+        "open_read after remove_file"
+    );
+}
+
+fn lengths_and_times(dir: &impl Directory) {
+    let missing = Path::new("missing");
+    assert_eq!(
+        kind(dir.truncate(missing, 0)),
+        Some(ErrorKind::NotFound),
+        "truncate: missing file is NotFound"
+    );
+    assert_eq!(
+        kind(dir.set_times(missing, None, Some(0))),
+        Some(ErrorKind::NotFound),
+        "set_times: missing file is NotFound"
+    );
+
+    let path = Path::new("f");
+    write(dir, "f", b"hello world");
+    dir.truncate(path, 5).unwrap();
+    assert_eq!(read(dir, "f"), b"hello", "truncate: shortens");
+    dir.truncate(path, 7).unwrap();
+    assert_eq!(read(dir, "f"), b"hello\0\0", "truncate: extends with zeros");
+
+    dir.allocate(path, 10).unwrap();
+    assert_eq!(
+        read(dir, "f"),
+        b"hello\0\0\0\0\0",
+        "allocate: extends with zeros"
+    );
+    dir.allocate(path, 2).unwrap();
+    assert_eq!(
+        dir.metadata(path).unwrap().size,
+        10,
+        "allocate: never shrinks"
+    );
+
+    dir.set_times(path, Some(1_000_000_000), Some(1_500_000_000))
+        .unwrap();
+    let meta = dir.metadata(path).unwrap();
+    assert_eq!(meta.accessed, Some(1_000_000_000), "set_times: accessed");
+    assert_eq!(meta.modified, Some(1_500_000_000), "set_times: modified");
+    dir.set_times(path, None, Some(1_600_000_000)).unwrap();
+    let meta = dir.metadata(path).unwrap();
+    assert_eq!(
+        meta.accessed,
+        Some(1_000_000_000),
+        "set_times: None leaves the time unchanged"
+    );
+    assert_eq!(meta.modified, Some(1_600_000_000), "set_times: modified");
+}
+
+fn directories(dir: &impl Directory) {
+    dir.create_dir(Path::new("sub")).unwrap();
+    assert_eq!(
+        kind(dir.create_dir(Path::new("sub"))),
+        Some(ErrorKind::Conflict),
+        "create_dir: existing directory is Conflict"
+    );
+    assert_eq!(
+        dir.metadata(Path::new("sub")).unwrap().file_type,
+        FileType::Directory,
+        "metadata: directory type"
+    );
+
+    write(dir, "sub/inner.txt", b"x");
+    write(dir, "top.txt", b"y");
+    let mut entries: Vec<_> = dir
+        .read_dir(Path::new(""))
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.name, entry.file_type)
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        entries,
+        [
+            ("sub".to_string(), FileType::Directory),
+            ("top.txt".to_string(), FileType::Regular)
+        ],
+        "read_dir: lists files and directories"
+    );
+
+    assert!(
+        dir.remove_dir(Path::new("sub")).is_err(),
+        "remove_dir: non-empty directory fails"
+    );
+    dir.remove_file(Path::new("sub/inner.txt")).unwrap();
+    dir.remove_dir(Path::new("sub")).unwrap();
+    assert_eq!(
+        kind(dir.metadata(Path::new("sub"))),
+        Some(ErrorKind::NotFound),
+        "metadata after remove_dir"
+    );
+}
+
+fn model<D: Directory>(make: &impl Fn() -> D) {
+    for seed in 0..CASES / 4 {
+        let dir = make();
+        let mut model: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut g = Gen::new(seed);
+        for step in 0..STEPS {
+            let name = g.key();
+            let path = Path::new(&name);
+            let context = format!("model (seed {}, step {})", seed, step);
+            match g.below(5) {
+                0 => {
+                    let data = g.value();
+                    write(&dir, &name, &data);
+                    model.insert(name, data);
+                }
+                1 => {
+                    let data = g.value();
+                    let mut file = dir.open_append(path).unwrap();
+                    file.blocking_write(&data).unwrap();
+                    file.blocking_flush().unwrap();
+                    model.entry(name.clone()).or_default().extend(data);
+                }
+                2 => {
+                    let result = dir.remove_file(path);
+                    match model.remove(&name) {
+                        Some(_) => assert!(result.is_ok(), "{}: remove {:?}", context, name),
+                        None => assert_eq!(
+                            kind(result),
+                            Some(ErrorKind::NotFound),
+                            "{}: remove {:?}",
+                            context,
+                            name
+                        ),
+                    }
+                }
+                3 => {
+                    let to = g.key();
+                    let result = dir.rename(path, Path::new(&to));
+                    match model.remove(&name) {
+                        Some(data) => {
+                            assert!(result.is_ok(), "{}: rename {:?}", context, name);
+                            model.insert(to, data);
+                        }
+                        None => assert_eq!(
+                            kind(result),
+                            Some(ErrorKind::NotFound),
+                            "{}: rename {:?}",
+                            context,
+                            name
+                        ),
+                    }
+                }
+                _ => match model.get(&name) {
+                    Some(data) => {
+                        assert_eq!(&read(&dir, &name), data, "{}: read {:?}", context, name);
+                        assert_eq!(
+                            dir.metadata(path).unwrap().size,
+                            data.len() as u64,
+                            "{}: size {:?}",
+                            context,
+                            name
+                        );
+                    }
+                    None => assert_eq!(
+                        kind(dir.open_read(path)),
+                        Some(ErrorKind::NotFound),
+                        "{}: read {:?}",
+                        context,
+                        name
+                    ),
+                },
+            }
+        }
+    }
+}
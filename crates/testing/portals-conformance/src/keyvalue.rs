@@ -0,0 +1,147 @@
+//! Conformance suite for [`KeyValue`] and [`AtomicKeyValue`].
+
+use crate::{CASES, Gen, STEPS};
+use portals_keyvalue::{AtomicKeyValue, Error, KeyValue};
+use std::collections::HashMap;
+
+/// Check a [`KeyValue`] backend. `make` must return a new, empty store.
+pub async fn run<K: KeyValue>(make: impl Fn() -> K) {
+    basics(&make()).await;
+    model(&make).await;
+}
+
+/// Check an [`AtomicKeyValue`] backend, including everything [`run`]
+/// checks. `make` must return a new, empty store.
+pub async fn run_atomic<K: AtomicKeyValue>(make: impl Fn() -> K) {
+    run(&make).await;
+    compare_and_swap(&make()).await;
+    increment(&make()).await;
+}
+
+async fn basics(store: &impl KeyValue) {
+    assert!(
+        matches!(store.get("missing").await, Err(Error::NotFound)),
+        "get: missing key is NotFound"
+    );
+    assert!(
+        matches!(store.delete("missing").await, Err(Error::NotFound)),
+        "delete: missing key is NotFound"
+    );
+    assert!(
+        !store.exists("missing").await.unwrap(),
+        "exists: missing key"
+    );
+    assert!(store.keys().await.unwrap().is_empty(), "keys: new store");
+
+    store.set("a", b"1").await.unwrap();
+    store.set("b", b"").await.unwrap();
+    assert_eq!(store.get("a").await.unwrap(), b"1", "get after set");
+    assert_eq!(store.get("b").await.unwrap(), b"", "empty values are kept");
+    store.set("a", b"2").await.unwrap();
+    assert_eq!(store.get("a").await.unwrap(), b"2", "set overwrites");
+
+    let mut keys = store.keys().await.unwrap();
+    keys.sort();
+    assert_eq!(keys, ["a", "b"], "keys lists every key once");
+
+    store.delete("a").await.unwrap();
+    assert!(!store.exists("a").await.unwrap(), "exists after delete");
+    assert_eq!(store.keys().await.unwrap(), ["b"], "keys after delete");
+}
+
+async fn model<K: KeyValue>(make: &impl Fn() -> K) {
+    for seed in 0..CASES {
+        let store = make();
+        let mut model: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut g = Gen::new(seed);
+        for step in 0..STEPS {
+            let key = g.key();
+            let context = format!("model (seed {}, step {})", seed, step);
+            match g.below(5) {
+                0 => {
+                    let value = g.value();
+                    store.set(&key, &value).await.unwrap();
+                    model.insert(key, value);
+                }
+                1 => match (store.delete(&key).await, model.remove(&key)) {
+                    (Ok(()), Some(_)) | (Err(Error::NotFound), None) => {}
+                    (result, _) => panic!("{}: delete {:?} returned {:?}", context, key, result),
+                },
+                2 => assert_eq!(
+                    store.exists(&key).await.unwrap(),
+                    model.contains_key(&key),
+                    "{}: exists {:?}",
+                    context,
+                    key
+                ),
+                3 => {
+                    let mut keys = store.keys().await.unwrap();
+                    keys.sort();
+                    let mut expected: Vec<_> = model.keys().cloned().collect();
+                    expected.sort();
+                    assert_eq!(keys, expected, "{}: keys", context);
+                }
+                _ => match (store.get(&key).await, model.get(&key)) {
+                    (Ok(value), Some(expected)) if &value == expected => {}
+                    (Err(Error::NotFound), None) => {}
+                    (result, _) => panic!("{}: get {:?} returned {:?}", context, key, result),
+                },
+            }
+        }
+    }
+}
+
+async fn compare_and_swap(store: &impl AtomicKeyValue) {
+    assert!(
+        store.compare_and_swap("k", None, b"1").await.unwrap(),
+        "cas: expecting absent on a missing key succeeds"
+    );
+    assert_eq!(store.get("k").await.unwrap(), b"1", "cas: value written");
+    assert!(
+        !store.compare_and_swap("k", None, b"2").await.unwrap(),
+        "cas: expecting absent on a present key fails"
+    );
+    assert!(
+        !store.compare_and_swap("k", Some(b"0"), b"2").await.unwrap(),
+        "cas: mismatched value fails"
+    );
+    assert_eq!(
+        store.get("k").await.unwrap(),
+        b"1",
+        "cas: failed swap leaves the value"
+    );
+    assert!(
+        store.compare_and_swap("k", Some(b"1"), b"2").await.unwrap(),
+        "cas: matching value succeeds"
+    );
+    assert_eq!(store.get("k").await.unwrap(), b"2", "cas: value swapped");
+    assert!(
+        !store
+            .compare_and_swap("other", Some(b"1"), b"2")
+            .await
+            .unwrap(),
+        "cas: expecting a value on a missing key fails"
+    );
+    assert!(
+        !store.exists("other").await.unwrap(),
+        "cas: failed swap creates nothing"
+    );
+}
+
+async fn increment(store: &impl AtomicKeyValue) {
+    assert_eq!(
+        store.increment("n", 5).await.unwrap(),
+        5,
+        "increment: missing key starts at 0"
+    );
+    assert_eq!(
+        store.increment("n", -7).await.unwrap(),
+        -2,
+        "increment: adds delta"
+    );
+    assert_eq!(
+        store.increment("n", 0).await.unwrap(),
+        -2,
+        "increment: by zero"
+    );
+}
@@ -0,0 +1,60 @@
+//! Conformance test suites for backends of portals interfaces.
+//!
+//! Each module checks the behavior every backend of one interface must
+//! share: round trips, overwrite and delete semantics, which error each
+//! failure reports, and, where it applies, expiry and transactions. Suites
+//! panic with a message naming the failed check, so they are called from a
+//! backend's own tests:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn conformance() {
+//!     portals_conformance::keyvalue::run(MemoryStore::new).await;
+//! }
+//! ```
+//!
+//! Alongside fixed cases, suites replay seeded random operation sequences
+//! against a simple model of the interface and compare every result, so
+//! a failure names the seed that reproduces it.
+
+pub mod blobstore;
+pub mod cache;
+pub mod filesystem;
+pub mod keyvalue;
+pub mod messaging;
+pub mod sql;
+
+use portals_random::InsecureRandom;
+use portals_random_mock::MockInsecureRandom;
+
+/// Random operation sequences run per property.
+const CASES: u64 = 32;
+
+/// Operations per sequence.
+const STEPS: usize = 64;
+
+/// A seeded source of random operations.
+struct Gen(MockInsecureRandom);
+
+impl Gen {
+    fn new(seed: u64) -> Self {
+        Self(MockInsecureRandom::new(seed.wrapping_add(1)))
+    }
+
+    /// A number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.0.u64() % n as u64) as usize
+    }
+
+    /// One of a few keys, so operations often hit the same entry.
+    fn key(&mut self) -> String {
+        format!("key-{}", self.below(6))
+    }
+
+    /// Up to 32 arbitrary bytes, sometimes none.
+    fn value(&mut self) -> Vec<u8> {
+        let mut value = vec![0; self.below(33)];
+        self.0.fill(&mut value);
+        value
+    }
+}
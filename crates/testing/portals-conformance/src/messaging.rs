@@ -0,0 +1,180 @@
+//! Conformance suites for [`Channel`] and [`Topic`].
+
+use crate::{CASES, Gen};
+use portals_messaging::{Channel, Error, Message, Receiver, Sender, Topic};
+use std::time::Duration;
+
+/// How long timeout checks wait, in the backend's notion of time.
+const TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Check a [`Channel`] backend. Each pair from `channel.create()` must be
+/// independent, and hold at least 16 unreceived messages without blocking.
+pub async fn run_channel<Ch: Channel>(channel: &Ch) {
+    channel_basics(channel).await;
+    channel_close(channel).await;
+    channel_order(channel).await;
+    channel_batch(channel).await;
+}
+
+/// Check a [`Topic`] backend. `make` must return a new topic with no
+/// subscribers.
+pub async fn run_topic<T: Topic>(make: impl Fn() -> T) {
+    let topic = make();
+    topic
+        .publish(Message::new("unheard"))
+        .await
+        .expect("publish: no subscribers is not an error");
+
+    let first = topic.subscribe().await.unwrap();
+    let second = topic.subscribe().await.unwrap();
+    let message = Message::new("news").with_metadata("k", "v");
+    topic.publish(message).await.unwrap();
+    for subscriber in [&first, &second] {
+        let received = subscriber.receive_timeout(TIMEOUT).await;
+        let received = received.expect("every subscriber receives a publish");
+        assert_eq!(received.data, b"news", "topic: data");
+        assert_eq!(
+            received.metadata.iter().collect::<Vec<_>>(),
+            [("k", "v")],
+            "topic: metadata"
+        );
+    }
+    assert!(
+        matches!(first.try_receive().await, Ok(None)),
+        "topic: messages published before subscribing are not delivered"
+    );
+
+    let late = topic.subscribe().await.unwrap();
+    topic.publish(Message::new("1")).await.unwrap();
+    topic.publish(Message::new("2")).await.unwrap();
+    for expected in [b"1", b"2"] {
+        let received = late.receive_timeout(TIMEOUT).await.unwrap();
+        assert_eq!(received.data, expected, "topic: publish order");
+    }
+
+    let batch = [Message::new("3"), Message::new("4"), Message::new("5")];
+    topic.publish_batch(&batch).await.unwrap();
+    let received = late.receive_batch(8, TIMEOUT).await.unwrap();
+    let data: Vec<_> = received.into_iter().map(|m| m.data).collect();
+    assert_eq!(data, [b"3", b"4", b"5"], "topic: publish_batch order");
+}
+
+async fn channel_basics<Ch: Channel>(channel: &Ch) {
+    let (sender, receiver) = channel.create();
+    assert!(
+        matches!(receiver.try_receive().await, Ok(None)),
+        "try_receive: empty channel is None"
+    );
+    assert!(
+        matches!(receiver.receive_timeout(TIMEOUT).await, Err(Error::Timeout)),
+        "receive_timeout: empty channel times out"
+    );
+
+    let message = Message::new(vec![0, 255, 7]).with_metadata("content-type", "bytes");
+    sender.send(message).await.unwrap();
+    let received = receiver.receive().await.unwrap();
+    assert_eq!(received.data, [0, 255, 7], "receive: data");
+    assert_eq!(
+        received.metadata.iter().collect::<Vec<_>>(),
+        [("content-type", "bytes")],
+        "receive: metadata"
+    );
+
+    sender.send(Message::new("x")).await.unwrap();
+    let received = receiver.try_receive().await.unwrap();
+    assert_eq!(
+        received.map(|m| m.data),
+        Some(b"x".to_vec()),
+        "try_receive: queued message"
+    );
+
+    let (other_sender, other_receiver) = channel.create();
+    other_sender.send(Message::new("other")).await.unwrap();
+    assert!(
+        matches!(receiver.try_receive().await, Ok(None)),
+        "channels from separate create() calls are independent"
+    );
+    drop(other_receiver);
+}
+
+async fn channel_close<Ch: Channel>(channel: &Ch) {
+    let (sender, receiver) = channel.create();
+    sender.send(Message::new("last")).await.unwrap();
+    drop(sender);
+    assert_eq!(
+        receiver.receive().await.unwrap().data,
+        b"last",
+        "close: queued messages are delivered after senders are dropped"
+    );
+    assert!(
+        matches!(receiver.receive().await, Err(Error::Closed)),
+        "close: receive after senders are dropped is Closed"
+    );
+    assert!(
+        matches!(receiver.try_receive().await, Err(Error::Closed)),
+        "close: try_receive after senders are dropped is Closed"
+    );
+
+    let (sender, receiver) = channel.create();
+    drop(receiver);
+    assert!(
+        matches!(sender.send(Message::new("x")).await, Err(Error::Closed)),
+        "close: send after the receiver is dropped is Closed"
+    );
+}
+
+async fn channel_order<Ch: Channel>(channel: &Ch) {
+    for seed in 0..CASES {
+        let (sender, receiver) = channel.create();
+        let mut g = Gen::new(seed);
+        let messages: Vec<Vec<u8>> = (0..1 + g.below(16)).map(|_| g.value()).collect();
+        for data in &messages {
+            sender.send(Message::new(data.clone())).await.unwrap();
+        }
+        for (i, data) in messages.iter().enumerate() {
+            let received = receiver.receive().await.unwrap();
+            assert_eq!(
+                &received.data, data,
+                "order (seed {}): message {} out of order",
+                seed, i
+            );
+        }
+    }
+}
+
+async fn channel_batch<Ch: Channel>(channel: &Ch) {
+    let (sender, receiver) = channel.create();
+    assert!(
+        receiver.receive_batch(8, TIMEOUT).await.unwrap().is_empty(),
+        "receive_batch: empty channel times out with an empty batch"
+    );
+    assert!(
+        receiver.receive_batch(0, TIMEOUT).await.unwrap().is_empty(),
+        "receive_batch: max of 0"
+    );
+
+    let messages: Vec<_> = (0..5u8).map(|i| Message::new(vec![i])).collect();
+    sender.send_batch(&messages).await.unwrap();
+    let first = receiver.receive_batch(3, TIMEOUT).await.unwrap();
+    let rest = receiver.receive_batch(8, TIMEOUT).await.unwrap();
+    let first: Vec<_> = first.into_iter().map(|m| m.data[0]).collect();
+    let rest: Vec<_> = rest.into_iter().map(|m| m.data[0]).collect();
+    assert_eq!(
+        first,
+        [0, 1, 2],
+        "receive_batch: takes at most max, in order"
+    );
+    assert_eq!(rest, [3, 4], "receive_batch: takes what is queued");
+
+    sender.send(Message::new("last")).await.unwrap();
+    drop(sender);
+    assert_eq!(
+        receiver.receive_batch(8, TIMEOUT).await.unwrap().len(),
+        1,
+        "receive_batch: queued messages are delivered after senders are dropped"
+    );
+    assert!(
+        matches!(receiver.receive_batch(8, TIMEOUT).await, Err(Error::Closed)),
+        "receive_batch: closed and drained is Closed"
+    );
+}
@@ -0,0 +1,197 @@
+//! Conformance suite for [`Connection`].
+
+use crate::{CASES, Gen, STEPS};
+use portals_sql::{Connection, Error, Value};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+
+/// Check a [`Connection`] backend. `make` must return a connection to a
+/// new, empty database.
+///
+/// Statements use the common subset of SQL: `CREATE TABLE` with `INTEGER
+/// PRIMARY KEY`, `INSERT`, `UPDATE`, `DELETE`, and `SELECT ... ORDER BY`,
+/// with `?` placeholders.
+pub async fn run<C: Connection>(make: impl AsyncFn() -> C) {
+    values(&make().await).await;
+    errors(&make().await).await;
+    transactions(&make().await).await;
+    model(&make().await).await;
+}
+
+async fn values(conn: &impl Connection) {
+    conn.execute(
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, i INTEGER, r REAL, s TEXT, b BLOB)",
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let row = [
+        Value::Integer(1),
+        Value::Integer(i64::MIN),
+        Value::Real(0.5),
+        Value::Text("héllo ' \" ?".to_string()),
+        Value::Blob(vec![0, 1, 255]),
+    ];
+    let inserted = conn
+        .execute(
+            "INSERT INTO t (id, i, r, s, b) VALUES (?, ?, ?, ?, ?)",
+            &row,
+        )
+        .await
+        .unwrap();
+    assert_eq!(inserted, 1, "execute: rows affected by insert");
+    let nulls = [
+        Value::Integer(2),
+        Value::Null,
+        Value::Null,
+        Value::Null,
+        Value::Null,
+    ];
+    conn.execute(
+        "INSERT INTO t (id, i, r, s, b) VALUES (?, ?, ?, ?, ?)",
+        &nulls,
+    )
+    .await
+    .unwrap();
+
+    let rows = conn
+        .query("SELECT id, i, r, s, b FROM t ORDER BY id", &[])
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2, "query: row count");
+    assert_eq!(rows[0].values(), row, "query: values round-trip");
+    assert_eq!(rows[1].values(), nulls, "query: nulls round-trip");
+    assert_eq!(
+        rows[0].columns(),
+        ["id", "i", "r", "s", "b"],
+        "query: columns"
+    );
+    assert_eq!(
+        rows[0].get_by_name("s"),
+        Some(&row[3]),
+        "query: get_by_name"
+    );
+
+    let empty = conn
+        .query("SELECT id FROM t WHERE id = ?", &[Value::Integer(3)])
+        .await
+        .unwrap();
+    assert!(empty.is_empty(), "query: no matching rows");
+}
+
+async fn errors(conn: &impl Connection) {
+    assert!(
+        matches!(
+            conn.execute("CREATE TABEL t (x)", &[]).await,
+            Err(Error::SyntaxError(_))
+        ),
+        "malformed SQL is SyntaxError"
+    );
+
+    conn.execute("CREATE TABLE u (id INTEGER PRIMARY KEY)", &[])
+        .await
+        .unwrap();
+    conn.execute("INSERT INTO u (id) VALUES (1)", &[])
+        .await
+        .unwrap();
+    assert!(
+        matches!(
+            conn.execute("INSERT INTO u (id) VALUES (1)", &[]).await,
+            Err(Error::ConstraintViolation(_))
+        ),
+        "duplicate primary key is ConstraintViolation"
+    );
+}
+
+async fn transactions(conn: &impl Connection) {
+    conn.execute("CREATE TABLE t (x INTEGER)", &[])
+        .await
+        .unwrap();
+
+    conn.begin().await.unwrap();
+    conn.execute("INSERT INTO t (x) VALUES (1)", &[])
+        .await
+        .unwrap();
+    let inside = conn.query("SELECT x FROM t", &[]).await.unwrap();
+    assert_eq!(inside.len(), 1, "transaction: writes visible inside");
+    conn.rollback().await.unwrap();
+    let after = conn.query("SELECT x FROM t", &[]).await.unwrap();
+    assert!(after.is_empty(), "rollback discards writes");
+
+    conn.begin().await.unwrap();
+    conn.execute("INSERT INTO t (x) VALUES (2)", &[])
+        .await
+        .unwrap();
+    conn.commit().await.unwrap();
+    let after = conn.query("SELECT x FROM t", &[]).await.unwrap();
+    assert_eq!(after.len(), 1, "commit keeps writes");
+}
+
+async fn model(conn: &impl Connection) {
+    conn.execute("CREATE TABLE kv (k INTEGER PRIMARY KEY, v BLOB)", &[])
+        .await
+        .unwrap();
+    let mut model: BTreeMap<i64, Vec<u8>> = BTreeMap::new();
+    let mut g = Gen::new(0);
+    for step in 0..CASES as usize * STEPS / 8 {
+        let key = g.below(6) as i64;
+        let params = [Value::Integer(key)];
+        let context = format!("model (step {})", step);
+        match g.below(4) {
+            0 => {
+                let value = g.value();
+                let result = conn
+                    .execute(
+                        "INSERT INTO kv (k, v) VALUES (?, ?)",
+                        &[Value::Integer(key), Value::Blob(value.clone())],
+                    )
+                    .await;
+                match model.entry(key) {
+                    Entry::Occupied(_) => assert!(
+                        matches!(result, Err(Error::ConstraintViolation(_))),
+                        "{}: insert duplicate {}",
+                        context,
+                        key
+                    ),
+                    Entry::Vacant(entry) => {
+                        assert_eq!(result.unwrap(), 1, "{}: insert {}", context, key);
+                        entry.insert(value);
+                    }
+                }
+            }
+            1 => {
+                let value = g.value();
+                let changed = conn
+                    .execute(
+                        "UPDATE kv SET v = ? WHERE k = ?",
+                        &[Value::Blob(value.clone()), Value::Integer(key)],
+                    )
+                    .await
+                    .unwrap();
+                let expected = model.get_mut(&key).map(|v| *v = value).is_some();
+                assert_eq!(changed, expected as u64, "{}: update {}", context, key);
+            }
+            2 => {
+                let deleted = conn
+                    .execute("DELETE FROM kv WHERE k = ?", &params)
+                    .await
+                    .unwrap();
+                let expected = model.remove(&key).is_some();
+                assert_eq!(deleted, expected as u64, "{}: delete {}", context, key);
+            }
+            _ => {
+                let rows = conn
+                    .query("SELECT k, v FROM kv ORDER BY k", &[])
+                    .await
+                    .unwrap();
+                let actual: Vec<_> = rows.iter().map(|row| row.values().to_vec()).collect();
+                let expected: Vec<_> = model
+                    .iter()
+                    .map(|(k, v)| vec![Value::Integer(*k), Value::Blob(v.clone())])
+                    .collect();
+                assert_eq!(actual, expected, "{}: select", context);
+            }
+        }
+    }
+}